@@ -0,0 +1,34 @@
+//! Speech over game audio: a drone plays on one Sink as the music, and a
+//! line of dialogue on another, the music ducked while it's spoken.
+//!
+//!     cargo run -p speakturbo-client --features rodio --example game_audio -- "Halt! Who goes there?"
+//!
+//! The daemon is the one at SPEAKTURBO_URL, or the default.
+
+use rodio::source::{SineWave, Source};
+use rodio::{OutputStream, Sink};
+use speakturbo_client::source::SourceOptions;
+use speakturbo_client::{SpeakClient, SpeakError, SpeakRequest, DEFAULT_URL};
+use std::time::Duration;
+
+fn main() -> Result<(), SpeakError> {
+    let line = std::env::args().skip(1).collect::<Vec<_>>().join(" ");
+    let line = if line.trim().is_empty() { "Halt! Who goes there?".to_string() } else { line };
+    let url = std::env::var("SPEAKTURBO_URL").unwrap_or_else(|_| DEFAULT_URL.to_string());
+
+    let (_stream, output) = OutputStream::try_default().map_err(SpeakError::output_device)?;
+    let music = Sink::try_new(&output).map_err(SpeakError::output_device)?;
+    music.append(SineWave::new(110.0).mix(SineWave::new(165.0)).amplify(0.1));
+    std::thread::sleep(Duration::from_secs(1));
+
+    let client = SpeakClient::new(&url);
+    let request = SpeakRequest::new(line).voice("javert").build()?;
+    let speech = client.synthesize(&request)?.into_rodio_source_with(SourceOptions { volume: 0.9, ..SourceOptions::default() })?;
+    let voice = Sink::try_new(&output).map_err(SpeakError::output_device)?;
+    music.set_volume(0.3);
+    voice.append(speech);
+    voice.sleep_until_end();
+    music.set_volume(1.0);
+    std::thread::sleep(Duration::from_secs(1));
+    Ok(())
+}
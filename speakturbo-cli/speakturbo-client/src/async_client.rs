@@ -0,0 +1,258 @@
+//! With the `async` feature: the client over reqwest, for tokio services.
+//! A synthesis is a [`PcmStream`] of sample chunks, read from the socket
+//! only as it's polled, so a slow consumer slows the download rather than
+//! piling up audio; dropping the stream drops the connection, as does
+//! cancelling the token given to [`AsyncSpeakClient::synthesize_cancellable`].
+//! As with the blocking client, a daemon whose [`Capabilities`] say it
+//! takes POST is sent its requests that way.
+//!
+//! ```no_run
+//! # async fn speak() -> Result<(), speakturbo_client::SpeakError> {
+//! use speakturbo_client::{AsyncSpeakClient, SpeakRequest};
+//! use std::future::poll_fn;
+//! use std::pin::Pin;
+//!
+//! let client = AsyncSpeakClient::new("http://127.0.0.1:7125");
+//! let mut pcm = client.synthesize(&SpeakRequest::new("Hello there").build()?).await?;
+//! while let Some(samples) = poll_fn(|cx| futures_core::Stream::poll_next(Pin::new(&mut pcm), cx)).await {
+//!     let samples: Vec<i16> = samples?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::cancel::CancellationToken;
+use crate::error::{Phase, Result, SpeakError};
+use crate::stats::{Meter, SynthesisStats};
+use crate::transport::{self, Capabilities, Endpoint, Health, ResponseHead};
+use crate::wav::{self, WavFormat};
+use crate::SpeakRequest;
+use bytes::Bytes;
+use futures_core::Stream;
+use std::future::Future;
+use std::pin::{pin, Pin};
+use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+/// A daemon at a base URL, reached through a `reqwest` client.
+#[derive(Clone)]
+pub struct AsyncSpeakClient {
+    endpoint: Endpoint,
+    http: reqwest::Client,
+    /// Once the daemon has said, shared by the client's clones
+    capabilities: Arc<OnceLock<Capabilities>>,
+}
+
+impl AsyncSpeakClient {
+    /// The daemon at `base_url`, with reqwest's default client.
+    pub fn new(base_url: &str) -> AsyncSpeakClient {
+        AsyncSpeakClient::with_client(base_url, reqwest::Client::new())
+    }
+
+    /// The daemon at `base_url`, through `http` and its timeouts.
+    pub fn with_client(base_url: &str, http: reqwest::Client) -> AsyncSpeakClient {
+        AsyncSpeakClient { endpoint: Endpoint::new(base_url), http, capabilities: Arc::default() }
+    }
+
+    pub fn url(&self) -> &str {
+        self.endpoint.base()
+    }
+
+    /// The synthesis URL for `request`.
+    pub fn request_url(&self, request: &SpeakRequest) -> String {
+        self.endpoint.tts(request)
+    }
+
+    /// Start `request` and read as far as the end of the WAV header. An
+    /// error status from the daemon is a [`SpeakError::DaemonError`].
+    pub async fn synthesize(&self, request: &SpeakRequest) -> Result<PcmStream> {
+        self.synthesize_cancellable(request, &CancellationToken::new()).await
+    }
+
+    /// As [`AsyncSpeakClient::synthesize`], until `cancel` is cancelled:
+    /// that ends the wait for the header with [`SpeakError::Cancelled`],
+    /// and after it, ends the stream with one.
+    pub async fn synthesize_cancellable(&self, request: &SpeakRequest, cancel: &CancellationToken) -> Result<PcmStream> {
+        let mut meter = Meter::new(Instant::now());
+        let (format, head, pending, body) = until_cancelled(cancel, self.start(request, &mut meter)).await?;
+        let endpoint = self.url().to_string();
+        Ok(PcmStream { format, head, pending, body: Some(body), cancel: cancel.clone(), meter, endpoint })
+    }
+
+    /// Send `request` and read its WAV header: the format, the head, what
+    /// followed the header, and the rest of the body. What's read goes
+    /// through `meter`.
+    async fn start(&self, request: &SpeakRequest, meter: &mut Meter) -> Result<(WavFormat, ResponseHead, Vec<u8>, Body)> {
+        let post = self.capabilities().await.is_ok_and(|c| c.supports(Capabilities::POST));
+        let mut response = match post {
+            true => {
+                let body = request.to_json().to_string();
+                self.answer(self.http.post(self.endpoint.tts_post()).header("Content-Type", "application/json").body(body)).await?
+            }
+            false => self.call(&self.request_url(request)).await?,
+        };
+        let head = ResponseHead::new(response.status().as_u16(), |name| response.headers().get(name)?.to_str().ok());
+        let mut start = Vec::new();
+        let (format, consumed) = loop {
+            if let Some(header) = wav::parse_header(&start)? {
+                break header;
+            }
+            match response.chunk().await.map_err(reading)? {
+                Some(chunk) => {
+                    meter.read(&chunk);
+                    start.extend_from_slice(&chunk);
+                }
+                None => return Err(SpeakError::bad_audio("Truncated WAV header")),
+            }
+        };
+        if format.bits_per_sample != 16 {
+            return Err(SpeakError::BadAudio { reason: format!("{}-bit audio; only 16-bit PCM streams", format.bits_per_sample) });
+        }
+        Ok((format, head, start.split_off(consumed as usize), Box::pin(response.bytes_stream())))
+    }
+
+    pub async fn health(&self) -> Result<Health> {
+        let url = self.endpoint.health();
+        let body = self.call(&url).await?.text().await.map_err(reading)?;
+        transport::health(&url, &body)
+    }
+
+    /// The voices the daemon has, from /health.
+    pub async fn list_voices(&self) -> Result<Vec<String>> {
+        Ok(self.health().await?.voices)
+    }
+
+    /// What the daemon says it has, as
+    /// [`SpeakClient::capabilities`](crate::SpeakClient::capabilities) asks.
+    pub async fn capabilities(&self) -> Result<Capabilities> {
+        if let Some(capabilities) = self.capabilities.get() {
+            return Ok(capabilities.clone());
+        }
+        let url = self.endpoint.capabilities();
+        let body = match self.call(&url).await {
+            Ok(response) => Some(response.text().await.map_err(reading)?),
+            Err(SpeakError::DaemonError { status, .. }) if transport::without_endpoint(status) => None,
+            Err(e) => return Err(e),
+        };
+        let capabilities = transport::capabilities(&url, body.as_deref())?;
+        Ok(self.capabilities.get_or_init(|| capabilities).clone())
+    }
+
+    /// GET `url`, an error status read into a [`SpeakError::DaemonError`].
+    async fn call(&self, url: &str) -> Result<reqwest::Response> {
+        self.answer(self.http.get(url)).await
+    }
+
+    /// Send `request`, an error status read into a [`SpeakError::DaemonError`].
+    async fn answer(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let response = request.send().await.map_err(|e| SpeakError::of_reqwest(self.url(), e))?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let retry_after = response.headers().get("Retry-After").and_then(|v| v.to_str().ok()).map(str::to_string);
+        let body = response.text().await.unwrap_or_default();
+        Err(SpeakError::daemon(status.as_u16(), &body, retry_after.as_deref()))
+    }
+}
+
+/// `future`'s output, or [`SpeakError::Cancelled`] as soon as `cancel` is.
+async fn until_cancelled<T>(cancel: &CancellationToken, future: impl Future<Output = Result<T>>) -> Result<T> {
+    let mut future = pin!(future);
+    std::future::poll_fn(|cx| {
+        cancel.wake_on_cancel(cx.waker());
+        match cancel.is_cancelled() {
+            true => Poll::Ready(Err(SpeakError::Cancelled)),
+            false => future.as_mut().poll(cx),
+        }
+    })
+    .await
+}
+
+/// `error` from reading a response's body.
+fn reading(error: reqwest::Error) -> SpeakError {
+    match error.is_timeout() {
+        true => SpeakError::Timeout { phase: Phase::Stream },
+        false => SpeakError::Io(std::io::Error::other(error)),
+    }
+}
+
+/// A synthesis as chunks of interleaved 16-bit samples, in the order the
+/// daemon sends them
+pub struct PcmStream {
+    format: WavFormat,
+    head: ResponseHead,
+    /// Bytes read but not yet given out: what followed the header, and
+    /// the first byte of a sample split between chunks
+    pending: Vec<u8>,
+    /// None once cancelled, the connection dropped
+    body: Option<Body>,
+    cancel: CancellationToken,
+    meter: Meter,
+    endpoint: String,
+}
+
+type Body = Pin<Box<dyn Stream<Item = reqwest::Result<Bytes>> + Send>>;
+
+impl PcmStream {
+    /// The format from the stream's header
+    pub fn format(&self) -> WavFormat {
+        self.format
+    }
+
+    pub fn head(&self) -> ResponseHead {
+        self.head
+    }
+
+    /// What cancels the stream: the token it was started with, or one of
+    /// its own that nothing else holds
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.cancel
+    }
+
+    /// How the synthesis went, once the stream has ended; None before,
+    /// or after an error or cancelling.
+    pub fn stats(&self) -> Option<SynthesisStats> {
+        Some(SynthesisStats { endpoint: Some(self.endpoint.clone()), ..self.meter.stats()? })
+    }
+
+    /// The whole samples in `pending`, leaving an odd byte there.
+    fn samples(&mut self) -> Vec<i16> {
+        let whole = self.pending.len() & !1;
+        let samples = self.pending[..whole].chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect();
+        self.pending.drain(..whole);
+        samples
+    }
+}
+
+impl Stream for PcmStream {
+    type Item = Result<Vec<i16>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            self.cancel.wake_on_cancel(cx.waker());
+            if self.cancel.is_cancelled() {
+                // Cancelled is said once, as the connection is dropped
+                return Poll::Ready(self.body.take().map(|_| Err(SpeakError::Cancelled)));
+            }
+            if self.pending.len() >= 2 {
+                return Poll::Ready(Some(Ok(self.samples())));
+            }
+            let Some(body) = self.body.as_mut() else { return Poll::Ready(None) };
+            match body.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    self.meter.read(&chunk);
+                    self.pending.extend_from_slice(&chunk);
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(reading(e)))),
+                Poll::Ready(None) => {
+                    self.meter.end();
+                    self.body = None;
+                    return Poll::Ready(None);
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
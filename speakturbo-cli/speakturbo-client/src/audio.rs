@@ -0,0 +1,458 @@
+//! Converting audio between formats as it streams: a [`Resampler`] from
+//! one rate to another, a [`Remix`] from one channel count to another,
+//! and the sample conversions between 16-bit, 24-bit and floats, with a
+//! [`Quantizer`] that dithers on the way down.
+//!
+//! Stages work on interleaved f32 frames in -1.0..1.0 and implement
+//! [`ChainedProcessor`], so a pipeline is built by chaining them. Each
+//! takes a block at a time and appends to an output `Vec` the caller
+//! reuses; once the buffers have grown to the block size, nothing is
+//! allocated. A stage may hold frames back (the resampler needs a few
+//! ahead of each output), which [`ChainedProcessor::flush`] gives out at
+//! the end of the stream.
+//!
+//! ```
+//! use speakturbo_client::audio::{self, ChainedProcessor, Quality, Quantizer, Remix, Resampler};
+//!
+//! // The daemon's 24 kHz mono, for a 48 kHz stereo device
+//! let mut pipeline = Remix::new(1, 2).then(Resampler::new(2, 24000, 48000, Quality::Sinc));
+//! let (mut floats, mut out, mut pcm) = (Vec::new(), Vec::new(), Vec::new());
+//! audio::i16_to_f32(&[0, 1000, 2000, 1000], &mut floats);
+//! pipeline.process(&floats, &mut out);
+//! pipeline.flush(&mut out);
+//! Quantizer::new().to_i16(&out, &mut pcm);
+//! assert_eq!(pcm.len(), 16);
+//! ```
+
+use std::f64::consts::PI;
+
+/// A stage of a pipeline over interleaved f32 frames
+pub trait ChainedProcessor: Send {
+    /// Take in `input`, whole frames, appending to `output` what can be
+    /// made of it yet.
+    fn process(&mut self, input: &[f32], output: &mut Vec<f32>);
+
+    /// The stream has ended: append what's been held back, and start
+    /// afresh for the next.
+    fn flush(&mut self, output: &mut Vec<f32>);
+
+    /// This stage, its output fed to `next`.
+    fn then<P: ChainedProcessor>(self, next: P) -> Chain<Self, P>
+    where
+        Self: Sized,
+    {
+        Chain { first: self, second: next, between: Vec::new() }
+    }
+}
+
+impl<P: ChainedProcessor + ?Sized> ChainedProcessor for Box<P> {
+    fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        (**self).process(input, output);
+    }
+
+    fn flush(&mut self, output: &mut Vec<f32>) {
+        (**self).flush(output);
+    }
+}
+
+/// Two stages one after the other; see [`ChainedProcessor::then`].
+pub struct Chain<A, B> {
+    first: A,
+    second: B,
+    /// The first stage's output, kept for its capacity
+    between: Vec<f32>,
+}
+
+impl<A: ChainedProcessor, B: ChainedProcessor> ChainedProcessor for Chain<A, B> {
+    fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        self.between.clear();
+        self.first.process(input, &mut self.between);
+        self.second.process(&self.between, output);
+    }
+
+    fn flush(&mut self, output: &mut Vec<f32>) {
+        self.between.clear();
+        self.first.flush(&mut self.between);
+        self.second.process(&self.between, output);
+        self.second.flush(output);
+    }
+}
+
+/// How a [`Resampler`] interpolates
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Quality {
+    /// Between the two nearest frames: cheap, and dull above a quarter of
+    /// the rate; plenty for earcons
+    Linear,
+    /// A Blackman-windowed sinc over 16 zero crossings each side, which
+    /// keeps the band below 94% of the lower rate's Nyquist frequency
+    Sinc,
+}
+
+/// Zero crossings of the sinc on each side of a frame
+const ZERO_CROSSINGS: f64 = 16.0;
+
+/// The cutoff, as a share of the lower rate's Nyquist frequency
+const ROLLOFF: f64 = 0.94;
+
+/// From one sample rate to another, a block at a time. Output frame `n`
+/// is the input at `n * from / to` frames, with no delay: frames are held
+/// back until those the kernel reaches past it have arrived, and a stream
+/// of `n` frames comes out `ceil(n * to / from)` frames long.
+pub struct Resampler {
+    channels: usize,
+    /// The ratio, in lowest terms
+    from: u64,
+    to: u64,
+    quality: Quality,
+    /// Frames the kernel reaches on each side: at `index - half + 1` to
+    /// `index + half`
+    half: usize,
+    /// The sinc's cutoff, as a share of the input's Nyquist frequency
+    cutoff: f64,
+    /// Input frames, interleaved, from `half - 1` before the next
+    /// output's on
+    history: Vec<f32>,
+    /// The next output's frame in `history`, and how far past it, in
+    /// `to`ths of a frame
+    index: usize,
+    phase: u64,
+    /// Frames taken in, and given out, since the stream started
+    received: u64,
+    produced: u64,
+    /// One output's kernel, one weight a frame
+    weights: Vec<f64>,
+}
+
+impl Resampler {
+    /// `channels`-channel frames from `from` Hz to `to` Hz.
+    pub fn new(channels: usize, from: u32, to: u32, quality: Quality) -> Resampler {
+        assert!(channels > 0 && from > 0 && to > 0, "a resampler needs channels and rates");
+        let gcd = gcd(from as u64, to as u64);
+        let (from, to) = (from as u64 / gcd, to as u64 / gcd);
+        let cutoff = ROLLOFF * (to as f64 / from as f64).min(1.0);
+        let half = match quality {
+            Quality::Linear => 1,
+            Quality::Sinc => (ZERO_CROSSINGS / cutoff).ceil() as usize,
+        };
+        let mut resampler = Resampler {
+            channels,
+            from,
+            to,
+            quality,
+            half,
+            cutoff,
+            history: Vec::new(),
+            index: 0,
+            phase: 0,
+            received: 0,
+            produced: 0,
+            weights: vec![0.0; 2 * half],
+        };
+        resampler.reset();
+        resampler
+    }
+
+    /// Frames a stream of `frames` comes out as.
+    pub fn output_frames(&self, frames: u64) -> u64 {
+        (frames * self.to).div_ceil(self.from)
+    }
+
+    /// Forget the stream so far.
+    fn reset(&mut self) {
+        // Silence before the stream, for the kernel to reach back into
+        self.history.clear();
+        self.history.resize((self.half - 1) * self.channels, 0.0);
+        (self.index, self.phase, self.received, self.produced) = (self.half - 1, 0, 0, 0);
+    }
+
+    /// Give out every frame the history reaches far enough for.
+    fn produce(&mut self, output: &mut Vec<f32>) {
+        let frames = self.history.len() / self.channels;
+        while self.produced < self.output_frames(self.received) && self.index + self.half < frames {
+            self.kernel(self.phase as f64 / self.to as f64);
+            let start = (self.index + 1 - self.half) * self.channels;
+            for channel in 0..self.channels {
+                let taps = self.history[start + channel..].iter().step_by(self.channels);
+                output.push(self.weights.iter().zip(taps).map(|(w, s)| w * *s as f64).sum::<f64>() as f32);
+            }
+            self.produced += 1;
+            self.phase += self.from;
+            self.index += (self.phase / self.to) as usize;
+            self.phase %= self.to;
+        }
+        // Let go of the frames no output will reach back to, but for the
+        // last, which a linear flush repeats
+        let done = (self.index + 1).saturating_sub(self.half).min(frames.saturating_sub(1));
+        self.history.drain(..done * self.channels);
+        self.index -= done;
+    }
+
+    /// Fill `weights` for an output `frac` of a frame past `index`.
+    fn kernel(&mut self, frac: f64) {
+        if self.quality == Quality::Linear {
+            self.weights[..2].copy_from_slice(&[1.0 - frac, frac]);
+            return;
+        }
+        let half = self.half as f64;
+        for (k, weight) in self.weights.iter_mut().enumerate() {
+            let t = k as f64 - (half - 1.0) - frac;
+            let x = t * self.cutoff;
+            let sinc = if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+            let blackman = 0.42 + 0.5 * (PI * t / half).cos() + 0.08 * (2.0 * PI * t / half).cos();
+            *weight = sinc * blackman;
+        }
+        // Unity gain at DC, whatever the phase
+        let sum: f64 = self.weights.iter().sum();
+        self.weights.iter_mut().for_each(|w| *w /= sum);
+    }
+}
+
+impl ChainedProcessor for Resampler {
+    fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        let whole = input.len() - input.len() % self.channels;
+        self.history.extend_from_slice(&input[..whole]);
+        self.received += (whole / self.channels) as u64;
+        self.produce(output);
+    }
+
+    fn flush(&mut self, output: &mut Vec<f32>) {
+        let last = self.history.len().saturating_sub(self.channels);
+        for _ in 0..self.half {
+            match self.quality {
+                // Held at the last frame, as the old linear resampler did
+                Quality::Linear if self.received > 0 => self.history.extend_from_within(last..last + self.channels),
+                _ => self.history.extend(std::iter::repeat_n(0.0, self.channels)),
+            }
+        }
+        self.produce(output);
+        self.reset();
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    match b {
+        0 => a,
+        b => gcd(b, a % b),
+    }
+}
+
+/// From one channel count to another. Going up, each output channel
+/// takes the input channel its number wraps round to (mono goes to every
+/// channel); going down, each takes the mean of the input channels that
+/// wrap round to it (everything goes to mono).
+#[derive(Clone, Copy, Debug)]
+pub struct Remix {
+    from: usize,
+    to: usize,
+}
+
+impl Remix {
+    pub fn new(from: usize, to: usize) -> Remix {
+        assert!(from > 0 && to > 0, "a remix needs channels");
+        Remix { from, to }
+    }
+}
+
+impl ChainedProcessor for Remix {
+    fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        for frame in input.chunks_exact(self.from) {
+            match self.from <= self.to {
+                true => output.extend((0..self.to).map(|c| frame[c % self.from])),
+                false => output.extend((0..self.to).map(|c| {
+                    let shared = frame.iter().skip(c).step_by(self.to);
+                    shared.clone().sum::<f32>() / shared.count() as f32
+                })),
+            }
+        }
+    }
+
+    fn flush(&mut self, _: &mut Vec<f32>) {}
+}
+
+/// Full scale of 16-bit and 24-bit samples
+const I16_SCALE: f32 = 32768.0;
+const I24_SCALE: f32 = 8388608.0;
+
+/// 16-bit samples as floats, appended to `output`.
+pub fn i16_to_f32(input: &[i16], output: &mut Vec<f32>) {
+    output.extend(input.iter().map(|&s| s as f32 / I16_SCALE));
+}
+
+/// Packed little-endian 24-bit samples as floats, appended to `output`;
+/// a partial sample at the end is left.
+pub fn i24_to_f32(input: &[u8], output: &mut Vec<f32>) {
+    output.extend(input.chunks_exact(3).map(|b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / I24_SCALE));
+}
+
+/// Floats down to 16-bit or 24-bit samples, with triangular dither of a
+/// step's width so that quiet passages fade into noise rather than
+/// distortion
+pub struct Quantizer {
+    /// None for plain rounding
+    noise: Option<u32>,
+}
+
+impl Default for Quantizer {
+    fn default() -> Quantizer {
+        Quantizer::new()
+    }
+}
+
+impl Quantizer {
+    /// A dithering quantizer, its noise the same from run to run.
+    pub fn new() -> Quantizer {
+        Quantizer { noise: Some(0x9e37_79b9) }
+    }
+
+    /// Rounding to the nearest step, as for audio that came as samples of
+    /// the same depth and must come back unchanged.
+    pub fn undithered() -> Quantizer {
+        Quantizer { noise: None }
+    }
+
+    /// `input` as 16-bit samples, appended to `output`.
+    pub fn to_i16(&mut self, input: &[f32], output: &mut Vec<i16>) {
+        output.extend(input.iter().map(|&s| self.step(s, I16_SCALE) as i16));
+    }
+
+    /// `input` as packed little-endian 24-bit samples, appended to
+    /// `output`.
+    pub fn to_i24(&mut self, input: &[f32], output: &mut Vec<u8>) {
+        for &s in input {
+            output.extend_from_slice(&self.step(s, I24_SCALE).to_le_bytes()[..3]);
+        }
+    }
+
+    /// `sample` to the nearest of the steps of `scale`, clipped.
+    fn step(&mut self, sample: f32, scale: f32) -> i32 {
+        let dither = match &mut self.noise {
+            Some(state) => uniform(state) + uniform(state),
+            None => 0.0,
+        };
+        (sample as f64 * scale as f64 + dither).round().clamp(-(scale as f64), scale as f64 - 1.0) as i32
+    }
+}
+
+/// The next of an xorshift sequence, in -0.5..0.5.
+fn uniform(state: &mut u32) -> f64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 17;
+    *state ^= *state << 5;
+    *state as f64 / (u32::MAX as f64 + 1.0) - 0.5
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AMPLITUDE: f64 = 0.5;
+
+    /// A linear sweep from 100 Hz to `top` Hz over a second, sampled at `rate`.
+    fn sweep(rate: u32, top: f64, channels: usize) -> Vec<f32> {
+        let frames = rate as usize;
+        let at = |n: usize| {
+            let t = n as f64 / rate as f64;
+            (AMPLITUDE * (2.0 * PI * (100.0 * t + (top - 100.0) * t * t / 2.0)).sin()) as f32
+        };
+        (0..frames).flat_map(|n| std::iter::repeat_n(at(n), channels)).collect()
+    }
+
+    /// The ratio of `reference` to the difference from it, in dB, the
+    /// first and last twentieth left out for the edges.
+    fn snr(output: &[f32], reference: &[f32]) -> f64 {
+        assert_eq!(output.len(), reference.len());
+        let edge = reference.len() / 20;
+        let (mut signal, mut noise) = (0.0, 0.0);
+        for (o, r) in output[edge..output.len() - edge].iter().zip(&reference[edge..reference.len() - edge]) {
+            signal += (*r as f64).powi(2);
+            noise += (*o as f64 - *r as f64).powi(2);
+        }
+        10.0 * (signal / noise).log10()
+    }
+
+    /// `input` through `stage` in blocks of uneven sizes, then flushed.
+    fn run(stage: &mut impl ChainedProcessor, input: &[f32], channels: usize) -> Vec<f32> {
+        let mut output = Vec::new();
+        let mut rest = input;
+        for size in [1usize, 37, 480, 4096].iter().cycle() {
+            if rest.is_empty() {
+                break;
+            }
+            let (block, after) = rest.split_at((size * channels).min(rest.len()));
+            stage.process(block, &mut output);
+            rest = after;
+        }
+        stage.flush(&mut output);
+        output
+    }
+
+    #[test]
+    fn resamples_a_sweep_close_to_the_reference() {
+        for (from, to, quality, top, floor) in [
+            (24000, 48000, Quality::Sinc, 8400.0, 85.0),
+            (24000, 44100, Quality::Sinc, 8400.0, 85.0),
+            (48000, 24000, Quality::Sinc, 8400.0, 85.0),
+            (24000, 16000, Quality::Sinc, 5600.0, 85.0),
+            (24000, 48000, Quality::Linear, 2400.0, 30.0),
+            (24000, 16000, Quality::Linear, 2400.0, 30.0),
+        ] {
+            let mut resampler = Resampler::new(2, from, to, quality);
+            let output = run(&mut resampler, &sweep(from, top, 2), 2);
+            let reference = sweep(to, top, 2);
+            let snr = snr(&output, &reference);
+            assert!(snr > floor, "{} Hz to {} Hz, {:?}: {:.1} dB", from, to, quality, snr);
+        }
+        // Once flushed, ready for another stream
+        let mut resampler = Resampler::new(1, 24000, 44100, Quality::Sinc);
+        for frames in [100, 7, 0] {
+            let output = run(&mut resampler, &vec![0.25; frames], 1);
+            assert_eq!(output.len() as u64, resampler.output_frames(frames as u64));
+        }
+        assert_eq!(run(&mut Resampler::new(1, 24000, 44100, Quality::Linear), &[0.25; 7], 1), [0.25; 13]);
+    }
+
+    #[test]
+    fn quantizes_to_near_the_depths_limit() {
+        let wave = sweep(48000, 8000.0, 1);
+        let (mut narrow, mut wide, mut back) = (Vec::new(), Vec::new(), Vec::new());
+        Quantizer::new().to_i16(&wave, &mut narrow);
+        i16_to_f32(&narrow, &mut back);
+        // 6 dB a bit, less the sweep's 6 dB below full scale and the dither's 4.8
+        assert!(snr(&back, &wave) > 84.0, "16-bit: {:.1} dB", snr(&back, &wave));
+        Quantizer::new().to_i24(&wave, &mut wide);
+        back.clear();
+        i24_to_f32(&wide, &mut back);
+        assert!(snr(&back, &wave) > 120.0, "24-bit: {:.1} dB", snr(&back, &wave));
+
+        let (mut samples, mut floats) = (Vec::new(), Vec::new());
+        i16_to_f32(&[i16::MIN, -1, 0, 1, i16::MAX], &mut floats);
+        Quantizer::undithered().to_i16(&[floats.as_slice(), &[1.5, -1.5]].concat(), &mut samples);
+        assert_eq!(samples, [i16::MIN, -1, 0, 1, i16::MAX, i16::MAX, i16::MIN]);
+        let mut packed = Vec::new();
+        Quantizer::undithered().to_i24(&[-1.0, 0.5], &mut packed);
+        assert_eq!(packed, [0, 0, 0x80, 0, 0, 0x40]);
+    }
+
+    #[test]
+    fn remixes_channels_up_and_down() {
+        let mut out = Vec::new();
+        Remix::new(1, 2).process(&[0.5, -0.25], &mut out);
+        assert_eq!(out, [0.5, 0.5, -0.25, -0.25]);
+        out.clear();
+        Remix::new(2, 1).process(&[0.5, 0.25, -1.0, 1.0], &mut out);
+        assert_eq!(out, [0.375, 0.0]);
+        out.clear();
+        Remix::new(4, 2).process(&[0.5, 0.25, 0.0, 0.75], &mut out);
+        assert_eq!(out, [0.25, 0.5]);
+    }
+
+    #[test]
+    fn chains_stages_and_flushes_what_they_hold() {
+        let mut chain: Box<dyn ChainedProcessor> = Box::new(Remix::new(1, 2).then(Resampler::new(2, 24000, 48000, Quality::Sinc)));
+        let wave = sweep(24000, 8400.0, 1);
+        let output = run(&mut chain, &wave, 1);
+        assert_eq!(output, run(&mut Resampler::new(2, 24000, 48000, Quality::Sinc), &sweep(24000, 8400.0, 2), 2));
+        assert_eq!(output.len(), 2 * 48000);
+    }
+}
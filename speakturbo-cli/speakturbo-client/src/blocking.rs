@@ -0,0 +1,308 @@
+//! The blocking client: each call waits for the daemon, and a synthesis
+//! reads as a [`Read`] on the calling thread. HTTP is over ureq unless
+//! the client is given another [`Transport`]; with a cache, syntheses
+//! read through it first. The daemon's [`Capabilities`] are asked for
+//! before the first synthesis it's sent, and kept: one that takes POST is
+//! sent its requests that way.
+
+use crate::cache::{self, Cache, CacheStats};
+use crate::cancel::CancellationToken;
+use crate::transport::{self, Capabilities, Endpoint, Fetched, Health, ResponseHead, Transport};
+use crate::error::{Result, SpeakError};
+use crate::stats::{Meter, SynthesisStats};
+use crate::SpeakRequest;
+use std::io::{self, Read};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+/// How often a cancellable request that's waiting on the daemon looks at its token
+const CANCEL_POLL: Duration = Duration::from_millis(10);
+
+/// A daemon at a base URL, reached through a [`Transport`].
+#[derive(Clone)]
+pub struct SpeakClient {
+    endpoint: Endpoint,
+    transport: Arc<dyn Transport>,
+    cache: Option<Cache>,
+    /// Once the daemon has said, shared by the client's clones
+    capabilities: Arc<OnceLock<Capabilities>>,
+}
+
+impl SpeakClient {
+    /// The daemon at `base_url`, with ureq's default agent.
+    pub fn new(base_url: &str) -> SpeakClient {
+        SpeakClient::with_agent(base_url, ureq::Agent::new())
+    }
+
+    /// The daemon at `base_url`, through `agent` and its timeouts.
+    pub fn with_agent(base_url: &str, agent: ureq::Agent) -> SpeakClient {
+        SpeakClient::with_transport(base_url, UreqTransport::new(base_url, agent))
+    }
+
+    /// The daemon reached through `transport`; `base_url` is what
+    /// [`SpeakClient::url`] and [`SpeakClient::request_url`] say.
+    pub fn with_transport(base_url: &str, transport: impl Transport + 'static) -> SpeakClient {
+        SpeakClient { endpoint: Endpoint::new(base_url), transport: Arc::new(transport), cache: None, capabilities: Arc::default() }
+    }
+
+    /// This client, its syntheses read from `cache` when they're there,
+    /// and stored there when they're read in full. Given a
+    /// [`CacheConfig`](cache::CacheConfig), the cache is the client's
+    /// own; given a [`Cache`], it's shared with whatever else holds it.
+    pub fn with_cache(self, cache: impl Into<Cache>) -> SpeakClient {
+        SpeakClient { cache: Some(cache.into()), ..self }
+    }
+
+    pub fn cache(&self) -> Option<&Cache> {
+        self.cache.as_ref()
+    }
+
+    /// Lookups and stores since the cache was given; None without one.
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        Some(self.cache.as_ref()?.stats())
+    }
+
+    pub fn url(&self) -> &str {
+        self.endpoint.base()
+    }
+
+    /// The synthesis URL for `request`.
+    pub fn request_url(&self, request: &SpeakRequest) -> String {
+        self.endpoint.tts(request)
+    }
+
+    /// Start `request`. The stream reads as the daemon sends it, WAV
+    /// header first. A daemon that answers with an error status gives
+    /// [`SpeakError::DaemonError`], its body the message; one that can't be
+    /// reached gives [`SpeakError::DaemonUnreachable`].
+    pub fn synthesize(&self, request: &SpeakRequest) -> Result<AudioStream> {
+        if let Some(audio) = self.cached(request) {
+            return Ok(audio);
+        }
+        let started = Instant::now();
+        let Fetched { head, body } = self.send(request)?;
+        Ok(self.stream(AudioStream::new(self.stored(request, body), head, started), false))
+    }
+
+    /// Send `request` to the daemon, as a POST if it takes one.
+    fn send(&self, request: &SpeakRequest) -> Result<Fetched> {
+        // A daemon that can't say now is tried the old way, and fails
+        // there if it fails at all
+        match self.capabilities().is_ok_and(|c| c.supports(Capabilities::POST)) {
+            true => self.transport.post(request),
+            false => self.transport.fetch(request),
+        }
+    }
+
+    /// `request`'s audio from the cache, without asking the daemon; None
+    /// when it isn't there, or there's no cache.
+    pub fn cached(&self, request: &SpeakRequest) -> Option<AudioStream> {
+        let started = Instant::now();
+        let audio = self.cache.as_ref()?.get(&cache::key(self.url(), request))?;
+        let head = ResponseHead { status: 200, content_length: Some(audio.len()), duration: None };
+        Some(self.stream(AudioStream::new(Box::new(audio), head, started), true))
+    }
+
+    /// `audio` as this client's: from its daemon, and with a cache, a hit
+    /// or a miss.
+    fn stream(&self, audio: AudioStream, hit: bool) -> AudioStream {
+        AudioStream { cache_hit: self.cache.as_ref().map(|_| hit), endpoint: Some(self.url().to_string()), ..audio }
+    }
+
+    /// `body`, stored in the cache as it's read if there is one.
+    fn stored(&self, request: &SpeakRequest, body: Box<dyn Read + Send>) -> Box<dyn Read + Send> {
+        match &self.cache {
+            Some(cache) => Box::new(cache.tee(&cache::key(self.url(), request), body)),
+            None => body,
+        }
+    }
+
+    /// As [`SpeakClient::synthesize`], until `cancel` is cancelled. While
+    /// the daemon has yet to answer, that returns [`SpeakError::Cancelled`]
+    /// at once, the request left to finish on a thread of its own and its
+    /// answer dropped; after, the stream's reads fail with it.
+    pub fn synthesize_cancellable(&self, request: &SpeakRequest, cancel: &CancellationToken) -> Result<AudioStream> {
+        cancel.check()?;
+        if let Some(audio) = self.cached(request) {
+            let (head, started) = (audio.head, audio.started);
+            return Ok(self.stream(AudioStream::cancelled_by(audio.into_reader(), head, started, cancel.clone()), true));
+        }
+        let started = Instant::now();
+        let (sender, receiver) = mpsc::channel();
+        let (client, fetching) = (self.clone(), request.clone());
+        std::thread::Builder::new().name("speakturbo-fetch".into()).spawn(move || {
+            let _ = sender.send(client.send(&fetching));
+        })?;
+        let Fetched { head, body } = loop {
+            match receiver.recv_timeout(CANCEL_POLL) {
+                Ok(fetched) => break fetched?,
+                Err(RecvTimeoutError::Timeout) => cancel.check()?,
+                Err(RecvTimeoutError::Disconnected) => return Err(io::Error::other("The request's thread panicked").into()),
+            }
+        };
+        cancel.check()?;
+        Ok(self.stream(AudioStream::cancelled_by(self.stored(request, body), head, started, cancel.clone()), false))
+    }
+
+    pub fn health(&self) -> Result<Health> {
+        transport::health(&self.endpoint.health(), &self.transport.health()?)
+    }
+
+    /// The voices the daemon has, from /health.
+    pub fn list_voices(&self) -> Result<Vec<String>> {
+        Ok(self.health()?.voices)
+    }
+
+    /// What the daemon says it has, from /capabilities the first time and
+    /// kept after; [`Capabilities::legacy`] if it hasn't the endpoint. A
+    /// daemon that can't be asked isn't taken for legacy: it's asked again
+    /// next time.
+    pub fn capabilities(&self) -> Result<Capabilities> {
+        if let Some(capabilities) = self.capabilities.get() {
+            return Ok(capabilities.clone());
+        }
+        let capabilities = transport::capabilities(&self.endpoint.capabilities(), self.transport.capabilities()?.as_deref())?;
+        Ok(self.capabilities.get_or_init(|| capabilities).clone())
+    }
+}
+
+/// HTTP to the daemon through a `ureq` agent: what [`SpeakClient::new`]
+/// and [`SpeakClient::with_agent`] use
+#[derive(Clone)]
+pub struct UreqTransport {
+    endpoint: Endpoint,
+    agent: ureq::Agent,
+}
+
+impl UreqTransport {
+    /// The daemon at `base_url`, through `agent`.
+    pub fn new(base_url: &str, agent: ureq::Agent) -> UreqTransport {
+        UreqTransport { endpoint: Endpoint::new(base_url), agent }
+    }
+
+    fn get(&self, url: &str) -> Result<ureq::Response> {
+        self.agent.get(url).call().map_err(|e| SpeakError::of_ureq(self.endpoint.base(), e))
+    }
+
+    fn fetched(response: ureq::Response) -> Fetched {
+        let head = ResponseHead::new(response.status(), |name| response.header(name));
+        Fetched { head, body: response.into_reader() }
+    }
+}
+
+impl Transport for UreqTransport {
+    fn fetch(&self, request: &SpeakRequest) -> Result<Fetched> {
+        Ok(UreqTransport::fetched(self.get(&self.endpoint.tts(request))?))
+    }
+
+    fn health(&self) -> Result<String> {
+        self.get(&self.endpoint.health())?.into_string().map_err(SpeakError::reading)
+    }
+
+    fn capabilities(&self) -> Result<Option<String>> {
+        match self.get(&self.endpoint.capabilities()) {
+            Ok(response) => response.into_string().map(Some).map_err(SpeakError::reading),
+            Err(SpeakError::DaemonError { status, .. }) if transport::without_endpoint(status) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn post(&self, request: &SpeakRequest) -> Result<Fetched> {
+        let response = self
+            .agent
+            .post(&self.endpoint.tts_post())
+            .set("Content-Type", "application/json")
+            .send_string(&request.to_json().to_string())
+            .map_err(|e| SpeakError::of_ureq(self.endpoint.base(), e))?;
+        Ok(UreqTransport::fetched(response))
+    }
+}
+
+/// The daemon's answer to a synthesis, read as it arrives
+pub struct AudioStream {
+    reader: Box<dyn Read + Send>,
+    head: ResponseHead,
+    started: Instant,
+    cancel: CancellationToken,
+    /// None without a cache
+    cache_hit: Option<bool>,
+    endpoint: Option<String>,
+    meter: Meter,
+}
+
+impl AudioStream {
+    pub(crate) fn new(reader: Box<dyn Read + Send>, head: ResponseHead, started: Instant) -> AudioStream {
+        AudioStream::cancelled_by(reader, head, started, CancellationToken::new())
+    }
+
+    fn cancelled_by(reader: Box<dyn Read + Send>, head: ResponseHead, started: Instant, cancel: CancellationToken) -> AudioStream {
+        let meter = Meter::new(started);
+        AudioStream { reader: Box::new(cancel.reader(reader)), head, started, cancel, cache_hit: None, endpoint: None, meter }
+    }
+
+    /// Whether the audio comes from the client's cache, not the daemon
+    pub fn is_cached(&self) -> bool {
+        self.cache_hit == Some(true)
+    }
+
+    /// How the synthesis went, once the stream has been read to its end
+    /// (as bytes, or as samples); None before, or after an error.
+    pub fn stats(&self) -> Option<SynthesisStats> {
+        Some(SynthesisStats { cache_hit: self.cache_hit, endpoint: self.endpoint.clone(), ..self.meter.stats()? })
+    }
+
+    /// The client's cache hit or miss and daemon, for stats kept elsewhere
+    #[cfg(feature = "rodio")]
+    pub(crate) fn origin(&self) -> (Option<bool>, Option<String>) {
+        (self.cache_hit, self.endpoint.clone())
+    }
+
+    /// What cancels the stream: the token it was started with, or one of
+    /// its own that nothing else holds
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.cancel
+    }
+
+    /// When the request was sent, which event times count from
+    pub fn started(&self) -> Instant {
+        self.started
+    }
+
+    pub fn head(&self) -> ResponseHead {
+        self.head
+    }
+
+    pub fn status(&self) -> u16 {
+        self.head.status
+    }
+
+    pub fn content_length(&self) -> Option<u64> {
+        self.head.content_length
+    }
+
+    /// The daemon's X-Audio-Duration hint, in seconds
+    pub fn duration_hint(&self) -> Option<f64> {
+        self.head.duration
+    }
+
+    /// Total response size, from Content-Length or the daemon's X-Audio-Duration hint.
+    pub fn expected_bytes(&self) -> Option<u64> {
+        self.head.expected_bytes()
+    }
+
+    pub fn into_reader(self) -> Box<dyn Read + Send> {
+        self.reader
+    }
+}
+
+impl Read for AudioStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        match n {
+            0 if !buf.is_empty() => self.meter.end(),
+            n => self.meter.read(&buf[..n]),
+        }
+        Ok(n)
+    }
+}
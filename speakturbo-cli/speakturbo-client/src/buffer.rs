@@ -0,0 +1,270 @@
+//! The samples between a stream's reader and its playback: the reader
+//! pushes as bytes arrive, playback pops, and each side can see how far
+//! the other has got. Playback notes when it runs dry and when audio comes
+//! again, and whoever drives it samples how far ahead the stream is, so
+//! that the stats can say whether synthesis kept up.
+
+use crate::SAMPLE_RATE;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Simple lock-free-ish ring buffer using atomic operations
+pub struct LockFreeBuffer {
+    data: Mutex<VecDeque<i16>>,
+    len: AtomicUsize,
+    /// The first byte of a sample whose second hasn't been pushed yet
+    odd: Mutex<Option<u8>>,
+    done: AtomicBool,
+    /// Why the stream ended early, if it did
+    error: Mutex<Option<String>>,
+    /// Samples pushed in all, when the first came and the last played, and
+    /// how often playback found the buffer empty before then
+    pushed: AtomicUsize,
+    /// Bytes pushed, an odd one included
+    received: AtomicU64,
+    first: OnceLock<Instant>,
+    /// When playback took its first sample
+    playing: OnceLock<Instant>,
+    drained: OnceLock<Instant>,
+    /// When the stream ended, read in full or not
+    ended: OnceLock<Instant>,
+    underruns: AtomicUsize,
+    /// Since when playback has been waiting for audio, while it is
+    stalled_at: Mutex<Option<Instant>>,
+    /// Time spent waiting, the current wait aside
+    stalled: Mutex<Duration>,
+    /// Samples buffered ahead of playback, at each [`sample_lead`](Self::sample_lead)
+    leads: Mutex<Vec<usize>>,
+}
+
+impl Default for LockFreeBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LockFreeBuffer {
+    pub fn new() -> Self {
+        Self {
+            data: Mutex::new(VecDeque::with_capacity(SAMPLE_RATE as usize)),
+            len: AtomicUsize::new(0),
+            odd: Mutex::new(None),
+            done: AtomicBool::new(false),
+            error: Mutex::new(None),
+            pushed: AtomicUsize::new(0),
+            received: AtomicU64::new(0),
+            first: OnceLock::new(),
+            playing: OnceLock::new(),
+            drained: OnceLock::new(),
+            ended: OnceLock::new(),
+            underruns: AtomicUsize::new(0),
+            stalled_at: Mutex::new(None),
+            stalled: Mutex::new(Duration::ZERO),
+            leads: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn push(&self, sample: i16) {
+        self.data.lock().unwrap().push_back(sample);
+        self.len.fetch_add(1, Ordering::Release);
+        self.pushed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Each little-endian sample in `bytes`. A read can end between a
+    /// sample's two bytes; the first is kept for the next push.
+    pub fn push_bytes(&self, mut bytes: &[u8]) {
+        self.received.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+        let mut odd = self.odd.lock().unwrap();
+        if let (Some(low), [high, rest @ ..]) = (*odd, bytes) {
+            self.push(i16::from_le_bytes([low, *high]));
+            *odd = None;
+            bytes = rest;
+        }
+        let mut samples = bytes.chunks_exact(2);
+        for chunk in &mut samples {
+            self.push(i16::from_le_bytes([chunk[0], chunk[1]]));
+        }
+        if let [low] = samples.remainder() {
+            *odd = Some(*low);
+        }
+    }
+
+    pub fn pop(&self) -> Option<i16> {
+        let mut data = self.data.lock().unwrap();
+        if let Some(s) = data.pop_front() {
+            self.len.fetch_sub(1, Ordering::Release);
+            Some(s)
+        } else {
+            None
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Samples pushed since the start
+    pub fn pushed(&self) -> u64 {
+        self.pushed.load(Ordering::Relaxed) as u64
+    }
+
+    /// Bytes given to [`push_bytes`](Self::push_bytes) since the start
+    pub fn received(&self) -> u64 {
+        self.received.load(Ordering::Relaxed)
+    }
+
+    /// Samples taken by playback so far
+    pub fn played(&self) -> u64 {
+        self.pushed().saturating_sub(self.len() as u64)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done.load(Ordering::Acquire)
+    }
+
+    pub fn set_done(&self) {
+        let _ = self.ended.set(Instant::now());
+        self.done.store(true, Ordering::Release);
+    }
+
+    pub fn fail(&self, error: String) {
+        *self.error.lock().unwrap() = Some(error);
+        self.set_done();
+    }
+
+    pub fn error(&self) -> Option<String> {
+        self.error.lock().unwrap().clone()
+    }
+
+    /// Note that the first audio has arrived; later calls change nothing.
+    pub fn mark_first(&self) {
+        let _ = self.first.set(Instant::now());
+    }
+
+    /// When the first audio arrived, if it has
+    pub fn first(&self) -> Option<Instant> {
+        self.first.get().copied()
+    }
+
+    /// Note that playback has taken its first sample; later calls change nothing.
+    pub fn mark_playing(&self) {
+        let _ = self.playing.set(Instant::now());
+    }
+
+    /// When playback took its first sample, if it has
+    pub fn playing(&self) -> Option<Instant> {
+        self.playing.get().copied()
+    }
+
+    /// Note that playback has reached the end.
+    pub fn mark_drained(&self) {
+        let _ = self.drained.set(Instant::now());
+    }
+
+    /// When playback got to the end, or now if it hasn't
+    pub fn drained(&self) -> Instant {
+        self.drained.get().copied().unwrap_or_else(Instant::now)
+    }
+
+    /// When the stream ended, if it has
+    pub fn ended(&self) -> Option<Instant> {
+        self.ended.get().copied()
+    }
+
+    /// Note that playback found the buffer empty before the end, and is
+    /// waiting from now until [`note_refilled`](Self::note_refilled).
+    pub fn note_underrun(&self) {
+        self.underruns.fetch_add(1, Ordering::Relaxed);
+        self.stalled_at.lock().unwrap().get_or_insert_with(Instant::now);
+    }
+
+    /// Note that playback has audio again after an underrun.
+    pub fn note_refilled(&self) {
+        if let Some(at) = self.stalled_at.lock().unwrap().take() {
+            *self.stalled.lock().unwrap() += at.elapsed();
+        }
+    }
+
+    pub fn underruns(&self) -> u64 {
+        self.underruns.load(Ordering::Relaxed) as u64
+    }
+
+    /// Time playback has spent waiting for audio, a wait still going on
+    /// included
+    pub fn stalled(&self) -> Duration {
+        let waiting = self.stalled_at.lock().unwrap().map_or(Duration::ZERO, |at| at.elapsed());
+        *self.stalled.lock().unwrap() + waiting
+    }
+
+    /// Note how far the stream is ahead of playback now. Only the time
+    /// both are going counts: before playback starts and after the stream
+    /// ends, what's buffered says nothing about synthesis keeping up.
+    /// Called every few milliseconds by whatever waits on playback.
+    pub fn sample_lead(&self) {
+        if self.playing().is_some() && !self.is_done() {
+            self.leads.lock().unwrap().push(self.len());
+        }
+    }
+
+    /// The least, median and greatest lead sampled, if any was
+    pub fn leads(&self) -> Option<(Duration, Duration, Duration)> {
+        let mut leads = self.leads.lock().unwrap().clone();
+        leads.sort_unstable();
+        let time = |samples: usize| Duration::from_micros(samples as u64 * 1_000_000 / SAMPLE_RATE as u64);
+        Some((time(*leads.first()?), time(leads[leads.len() / 2]), time(*leads.last()?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_what_went_through() {
+        let buffer = LockFreeBuffer::new();
+        buffer.push_bytes(&[1, 0, 2, 0, 3]);
+        assert_eq!((buffer.len(), buffer.pop(), buffer.played(), buffer.pushed()), (2, Some(1), 1, 2));
+        assert_eq!(buffer.received(), 5);
+        buffer.push_bytes(&[1]);
+        assert_eq!((buffer.pop(), buffer.pop()), (Some(2), Some(259)));
+        buffer.fail("reset by peer".to_string());
+        assert!(buffer.is_done());
+        assert_eq!(buffer.error().as_deref(), Some("reset by peer"));
+    }
+
+    #[test]
+    fn times_stalls_and_samples_leads_while_both_sides_go() {
+        let buffer = LockFreeBuffer::new();
+        buffer.push_bytes(&[0; 4800]);
+        buffer.sample_lead();
+        assert_eq!(buffer.leads(), None, "not playing yet");
+        buffer.mark_playing();
+        buffer.sample_lead();
+        for _ in 0..1200 {
+            buffer.pop();
+        }
+        buffer.sample_lead();
+        buffer.push_bytes(&[0; 9600]);
+        buffer.sample_lead();
+        let ms = Duration::from_millis;
+        assert_eq!(buffer.leads(), Some((ms(50), ms(100), ms(250))));
+
+        assert_eq!(buffer.stalled(), Duration::ZERO);
+        buffer.note_underrun();
+        std::thread::sleep(ms(20));
+        buffer.note_refilled();
+        let stalled = buffer.stalled();
+        assert!(stalled >= ms(20));
+        buffer.note_refilled();
+        assert_eq!(buffer.stalled(), stalled, "one wait counted once");
+        buffer.set_done();
+        buffer.sample_lead();
+        assert_eq!(buffer.leads().map(|(_, _, max)| max), Some(ms(250)));
+    }
+}
@@ -0,0 +1,590 @@
+//! On-disk cache of syntheses, so repeated phrases skip the daemon. A
+//! [`SpeakClient`](crate::SpeakClient) given a [`CacheConfig`] reads
+//! through it and writes each complete stream back; the `speakturbo` CLI
+//! and a long-running service can share one directory.
+//!
+//! Entries are `<sha256>.entry` files named by [`key`], a hash of the
+//! daemon and everything in the request. Each starts with a header (magic,
+//! when it was written, and the audio's length), is written to a temp file
+//! and renamed into place, and is checked on every read: one that's cut
+//! short, unrecognised or past its TTL is deleted and counts as a miss.
+//! Least-recently-used entries are evicted once the directory grows past
+//! its cap; a hit refreshes the entry's mtime. Recently used entries that
+//! are small enough are also kept in memory, for hits without the disk.
+//!
+//! ```no_run
+//! # #[cfg(feature = "blocking")] {
+//! use speakturbo_client::cache::CacheConfig;
+//! use speakturbo_client::{SpeakClient, SpeakRequest};
+//!
+//! let config = CacheConfig { max_bytes: 200 << 20, ..CacheConfig::new("/var/cache/speakturbo") };
+//! let client = SpeakClient::new(speakturbo_client::DEFAULT_URL).with_cache(config);
+//! let hello = SpeakRequest::new("Hello there").build()?;
+//! std::io::copy(&mut client.synthesize(&hello)?, &mut std::io::sink())?;
+//! // The second time, from the cache
+//! assert!(client.synthesize(&hello)?.is_cached());
+//! # }
+//! # Ok::<(), speakturbo_client::SpeakError>(())
+//! ```
+
+use crate::error::Result;
+use crate::SpeakRequest;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Bump when the key inputs or entry format change
+const KEY_VERSION: &str = "2";
+
+/// The start of every entry, before when it was written and its length
+const MAGIC: &[u8; 4] = b"STC2";
+const HEADER_LEN: u64 = 20;
+
+const EXTENSION: &str = "entry";
+
+/// What entries were called before they had a header; still counted and
+/// evicted, never read
+const LEGACY_EXTENSION: &str = "wav";
+
+// Hit/miss counters for `cache stats`
+const COUNTERS_FILE: &str = ".counters";
+
+/// Where a cache lives and how much it may hold
+#[derive(Clone, Debug)]
+pub struct CacheConfig {
+    pub dir: PathBuf,
+    /// Least-recently-used entries go once the directory holds more
+    pub max_bytes: u64,
+    /// How long an entry is good for after it's written; None for ever
+    pub ttl: Option<Duration>,
+    /// What recently used entries may take up in memory; 0 for none.
+    /// Entries over a quarter of it are always read from the disk.
+    pub memory_bytes: u64,
+    /// Whether lookups are counted in the directory, for hit rates across
+    /// processes ([`Cache::counters`]) as well as in [`Cache::stats`]
+    pub lifetime_counters: bool,
+}
+
+impl CacheConfig {
+    /// A cache in `dir` of up to 100 MB, 8 MB of it in memory.
+    pub fn new(dir: impl Into<PathBuf>) -> CacheConfig {
+        CacheConfig { dir: dir.into(), max_bytes: 100 << 20, ttl: None, memory_bytes: 8 << 20, lifetime_counters: false }
+    }
+}
+
+/// `$XDG_CACHE_HOME/speakturbo`, falling back to `~/.cache/speakturbo`
+/// (Local AppData on Windows; see [`crate::dirs`]).
+pub fn default_dir() -> Option<PathBuf> {
+    crate::dirs::cache().map(|base| base.join("speakturbo"))
+}
+
+/// The entry for `request` to the daemon at `daemon`: everything that
+/// changes the audio, hashed.
+pub fn key(daemon: &str, request: &SpeakRequest) -> String {
+    let mut digest = ring::digest::Context::new(&ring::digest::SHA256);
+    for field in [KEY_VERSION, daemon, &request.query()] {
+        // Length-prefixed so ("ab", "c") and ("a", "bc") differ
+        digest.update(&(field.len() as u64).to_le_bytes());
+        digest.update(field.as_bytes());
+    }
+    digest.finish().as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Lookups and stores since the [`Cache`] was made
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub memory_hits: u64,
+    pub disk_hits: u64,
+    pub misses: u64,
+    /// Complete streams written to the directory
+    pub stored: u64,
+    /// Entries found cut short, unrecognised or expired, and deleted
+    pub evicted: u64,
+}
+
+/// A cache directory, and the recent entries held in memory. Clones share
+/// both.
+#[derive(Clone)]
+pub struct Cache {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    config: CacheConfig,
+    memory: Mutex<Memory>,
+    stats: Mutex<CacheStats>,
+}
+
+/// Recently used entries, least recently used first
+#[derive(Default)]
+struct Memory {
+    entries: HashMap<String, Held>,
+    order: VecDeque<String>,
+    bytes: u64,
+}
+
+#[derive(Clone)]
+struct Held {
+    audio: Arc<[u8]>,
+    written: SystemTime,
+}
+
+impl Cache {
+    pub fn new(config: CacheConfig) -> Cache {
+        let shared = Shared { config, memory: Mutex::default(), stats: Mutex::default() };
+        Cache { shared: Arc::new(shared) }
+    }
+
+    pub fn config(&self) -> &CacheConfig {
+        &self.shared.config
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.shared.config.dir
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        *self.shared.stats.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn count(&self, update: impl FnOnce(&mut CacheStats)) {
+        update(&mut self.shared.stats.lock().unwrap_or_else(|e| e.into_inner()));
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir().join(format!("{}.{}", key, EXTENSION))
+    }
+
+    /// The audio stored under `key`, if it's there, whole and in date;
+    /// counted as a hit or a miss.
+    pub fn get(&self, key: &str) -> Option<CachedAudio> {
+        let found = self.find(key);
+        self.count(|stats| match &found {
+            Some(CachedAudio { from_memory: true, .. }) => stats.memory_hits += 1,
+            Some(_) => stats.disk_hits += 1,
+            None => stats.misses += 1,
+        });
+        if self.config().lifetime_counters {
+            self.record(found.is_some());
+        }
+        found
+    }
+
+    /// Whether `key` is stored, without counting a lookup.
+    pub fn contains(&self, key: &str) -> bool {
+        self.find(key).is_some()
+    }
+
+    fn find(&self, key: &str) -> Option<CachedAudio> {
+        if let Some(held) = self.remembered(key) {
+            return Some(CachedAudio { len: held.len() as u64, reader: Stored::Memory(Cursor::new(held)), from_memory: true });
+        }
+        let path = self.entry_path(key);
+        let mut file = File::open(&path).ok()?;
+        let Some((written, len)) = self.check(&mut file) else {
+            // Not played: cut short, from something else, or stale
+            if fs::remove_file(&path).is_ok() {
+                self.count(|stats| stats.evicted += 1);
+            }
+            return None;
+        };
+        let _ = file.set_modified(SystemTime::now());
+        if len <= self.config().memory_bytes / 4 {
+            let mut audio = Vec::with_capacity(len as usize);
+            Read::by_ref(&mut file).take(len).read_to_end(&mut audio).ok()?;
+            let audio: Arc<[u8]> = audio.into();
+            self.remember(key, Held { audio: Arc::clone(&audio), written });
+            return Some(CachedAudio { len, reader: Stored::Memory(Cursor::new(audio)), from_memory: false });
+        }
+        Some(CachedAudio { len, reader: Stored::File(file.take(len)), from_memory: false })
+    }
+
+    /// When `file`'s entry was written and how long its audio is, if its
+    /// header is one of ours, its length agrees and it's in date.
+    fn check(&self, file: &mut File) -> Option<(SystemTime, u64)> {
+        let mut header = [0u8; HEADER_LEN as usize];
+        file.read_exact(&mut header).ok()?;
+        let field = |at: usize| u64::from_le_bytes(header[at..at + 8].try_into().unwrap());
+        let (written, len) = (UNIX_EPOCH + Duration::from_secs(field(4)), field(12));
+        let whole = file.metadata().ok()?.len() == HEADER_LEN + len;
+        (&header[..4] == MAGIC && whole && self.fresh(written)).then_some((written, len))
+    }
+
+    fn fresh(&self, written: SystemTime) -> bool {
+        self.config().ttl.is_none_or(|ttl| written.elapsed().unwrap_or_default() < ttl)
+    }
+
+    /// `key`'s audio from memory, marked as just used.
+    fn remembered(&self, key: &str) -> Option<Arc<[u8]>> {
+        let mut memory = self.shared.memory.lock().unwrap_or_else(|e| e.into_inner());
+        let held = memory.entries.get(key)?.clone();
+        if !self.fresh(held.written) {
+            memory.forget(key);
+            return None;
+        }
+        if let Some(at) = memory.order.iter().position(|k| k == key) {
+            let key = memory.order.remove(at).expect("at is in the queue");
+            memory.order.push_back(key);
+        }
+        Some(held.audio)
+    }
+
+    fn remember(&self, key: &str, held: Held) {
+        let mut memory = self.shared.memory.lock().unwrap_or_else(|e| e.into_inner());
+        memory.forget(key);
+        memory.bytes += held.audio.len() as u64;
+        memory.entries.insert(key.to_string(), held);
+        memory.order.push_back(key.to_string());
+        while memory.bytes > self.config().memory_bytes {
+            let Some(oldest) = memory.order.front().cloned() else { break };
+            memory.forget(&oldest);
+        }
+    }
+
+    /// Wrap a daemon stream so it is stored as it is read. The entry only
+    /// appears once the stream reaches EOF; a partial read leaves nothing.
+    pub fn tee<R: Read>(&self, key: &str, inner: R) -> Tee<R> {
+        static TEMPS: AtomicU64 = AtomicU64::new(0);
+        let n = TEMPS.fetch_add(1, Ordering::Relaxed);
+        let tmp = self.dir().join(format!(".{}.{}.{}.tmp", key, std::process::id(), n));
+        let file = fs::create_dir_all(self.dir())
+            .and_then(|()| File::create(&tmp))
+            .and_then(|mut file| file.write_all(&[0; HEADER_LEN as usize]).map(|()| file))
+            .ok();
+        Tee { inner, file, tmp, len: 0, cache: self.clone(), key: key.to_string() }
+    }
+
+    /// Put a finished temp file's header in, move it into place, then
+    /// trim the cache to size.
+    fn insert(&self, mut file: File, tmp: &Path, key: &str, len: u64) -> Result<()> {
+        let written = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let mut header = MAGIC.to_vec();
+        header.extend(written.to_le_bytes());
+        header.extend(len.to_le_bytes());
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&header)?;
+        file.sync_data()?;
+        drop(file);
+        let _lock = Lock::acquire(self.dir())?;
+        fs::rename(tmp, self.entry_path(key))?;
+        // A stale copy in memory would outlive its replacement
+        self.shared.memory.lock().unwrap_or_else(|e| e.into_inner()).forget(key);
+        self.count(|stats| stats.stored += 1);
+        self.prune_locked(Some(self.config().max_bytes), None)?;
+        Ok(())
+    }
+
+    /// Count a lookup towards the lifetime hit rate. Best effort.
+    fn record(&self, hit: bool) {
+        let update = || -> Result<()> {
+            fs::create_dir_all(self.dir())?;
+            let _lock = Lock::acquire(self.dir())?;
+            let (hits, misses) = self.counters();
+            let (hits, misses) = if hit { (hits + 1, misses) } else { (hits, misses + 1) };
+            fs::write(self.dir().join(COUNTERS_FILE), format!("hits {}\nmisses {}\n", hits, misses))?;
+            Ok(())
+        };
+        let _ = update();
+    }
+
+    /// Lifetime (hits, misses), as counted with
+    /// [`CacheConfig::lifetime_counters`]; zero if never recorded.
+    pub fn counters(&self) -> (u64, u64) {
+        let text = fs::read_to_string(self.dir().join(COUNTERS_FILE)).unwrap_or_default();
+        let field = |name: &str| {
+            text.lines()
+                .filter_map(|l| l.strip_prefix(name)?.trim().parse().ok())
+                .next()
+                .unwrap_or(0)
+        };
+        (field("hits "), field("misses "))
+    }
+
+    /// Cached entries, oldest use first.
+    pub fn entries(&self) -> Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        let dir = match fs::read_dir(self.dir()) {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(entries),
+            Err(e) => return Err(e.into()),
+        };
+        for entry in dir {
+            let entry = entry?;
+            let path = entry.path();
+            if !is_entry(&path) {
+                continue;
+            }
+            let meta = entry.metadata()?;
+            let used = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            entries.push(Entry { used, len: meta.len(), path });
+        }
+        entries.sort_by(|a, b| a.used.cmp(&b.used).then_with(|| a.path.cmp(&b.path)));
+        Ok(entries)
+    }
+
+    /// Drop entries unused for `older_than`, then least-recently-used ones
+    /// until the cache fits in `max_bytes`.
+    pub fn prune(&self, max_bytes: Option<u64>, older_than: Option<Duration>) -> Result<Removed> {
+        fs::create_dir_all(self.dir())?;
+        let _lock = Lock::acquire(self.dir())?;
+        self.prune_locked(max_bytes, older_than)
+    }
+
+    fn prune_locked(&self, max_bytes: Option<u64>, older_than: Option<Duration>) -> Result<Removed> {
+        let entries = self.entries()?;
+        let mut total: u64 = entries.iter().map(|e| e.len).sum();
+        let cutoff = older_than.and_then(|age| SystemTime::now().checked_sub(age));
+        let mut removed = Removed::default();
+        for entry in entries {
+            let stale = cutoff.is_some_and(|c| entry.used < c);
+            let over = max_bytes.is_some_and(|m| total > m);
+            if !stale && !over {
+                continue;
+            }
+            // Unlinking is safe while another run is reading the entry: its
+            // open handle keeps the data until it's done. Where the OS
+            // refuses (Windows), the entry just stays for next time.
+            if fs::remove_file(&entry.path).is_ok() {
+                total -= entry.len;
+                removed.entries += 1;
+                removed.bytes += entry.len;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Remove every entry, leftover temp file and the counters, and
+    /// whatever is held in memory.
+    pub fn clear(&self) -> Result<Removed> {
+        *self.shared.memory.lock().unwrap_or_else(|e| e.into_inner()) = Memory::default();
+        let mut removed = Removed::default();
+        if !self.dir().exists() {
+            return Ok(removed);
+        }
+        let _lock = Lock::acquire(self.dir())?;
+        for entry in fs::read_dir(self.dir())? {
+            let path = entry?.path();
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if !is_entry(&path) && !name.ends_with(".tmp") && name != COUNTERS_FILE {
+                continue;
+            }
+            let len = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            if fs::remove_file(&path).is_ok() && is_entry(&path) {
+                removed.entries += 1;
+                removed.bytes += len;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+impl From<CacheConfig> for Cache {
+    fn from(config: CacheConfig) -> Cache {
+        Cache::new(config)
+    }
+}
+
+impl Memory {
+    fn forget(&mut self, key: &str) {
+        if let Some(held) = self.entries.remove(key) {
+            self.bytes -= held.audio.len() as u64;
+            self.order.retain(|k| k != key);
+        }
+    }
+}
+
+fn is_entry(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == EXTENSION || e == LEGACY_EXTENSION)
+}
+
+/// A stored synthesis, read from memory or from its file
+pub struct CachedAudio {
+    len: u64,
+    reader: Stored,
+    /// Found in memory, not read from the disk just now
+    from_memory: bool,
+}
+
+enum Stored {
+    Memory(Cursor<Arc<[u8]>>),
+    File(io::Take<File>),
+}
+
+impl CachedAudio {
+    /// The audio's length in bytes, its WAV header included
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Read for CachedAudio {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.reader {
+            Stored::Memory(cursor) => cursor.read(buf),
+            Stored::File(file) => file.read(buf),
+        }
+    }
+}
+
+/// A reader that stores what passes through it under a key once it ends;
+/// see [`Cache::tee`]. Failing to store only disables storing, never the
+/// read.
+pub struct Tee<R> {
+    inner: R,
+    /// None once storing has finished or been given up
+    file: Option<File>,
+    tmp: PathBuf,
+    len: u64,
+    cache: Cache,
+    key: String,
+}
+
+impl<R: Read> Read for Tee<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n == 0 {
+            if let Some(file) = self.file.take() {
+                if self.cache.insert(file, &self.tmp, &self.key, self.len).is_err() {
+                    let _ = fs::remove_file(&self.tmp);
+                }
+            }
+        } else if let Some(file) = &mut self.file {
+            self.len += n as u64;
+            if file.write_all(&buf[..n]).is_err() {
+                self.file = None;
+                let _ = fs::remove_file(&self.tmp);
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl<R> Drop for Tee<R> {
+    fn drop(&mut self) {
+        // Stream never finished: discard the partial copy
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.tmp);
+        }
+    }
+}
+
+pub struct Entry {
+    pub used: SystemTime,
+    pub len: u64,
+    pub path: PathBuf,
+}
+
+#[derive(Default)]
+pub struct Removed {
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+/// Exclusive lock on the cache directory for inserts and eviction,
+/// released when the file is closed
+struct Lock {
+    _file: File,
+}
+
+impl Lock {
+    fn acquire(dir: &Path) -> Result<Lock> {
+        let file = fs::OpenOptions::new().create(true).truncate(false).write(true).open(dir.join(".lock"))?;
+        file.lock()?;
+        Ok(Lock { _file: file })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_cache(name: &str, max_bytes: u64) -> Cache {
+        let dir = std::env::temp_dir().join(format!("speakturbo-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        Cache::new(CacheConfig { max_bytes, ..CacheConfig::new(dir) })
+    }
+
+    fn store(cache: &Cache, key: &str, audio: &[u8]) {
+        io::copy(&mut cache.tee(key, audio), &mut io::sink()).unwrap();
+    }
+
+    fn read(audio: Option<CachedAudio>) -> Option<Vec<u8>> {
+        let mut bytes = Vec::new();
+        audio?.read_to_end(&mut bytes).unwrap();
+        Some(bytes)
+    }
+
+    #[test]
+    fn keys_cover_the_daemon_and_every_field() {
+        let hi = SpeakRequest::new("Hi").voice("alba").build().unwrap();
+        let a = key("http://a", &hi);
+        assert_eq!(a, key("http://a", &hi.clone()));
+        assert_ne!(a, key("http://b", &hi));
+        assert_ne!(a, key("http://a", &hi.to_builder().param("speed", "1.2").build().unwrap()));
+        assert_ne!(a, key("http://a", &hi.to_builder().seed(1).build().unwrap()));
+    }
+
+    #[test]
+    fn stores_only_complete_streams() {
+        let cache = temp_cache("partial", 1 << 20);
+        let mut tee = cache.tee("k", &b"0123456789"[..]);
+        let mut buf = [0u8; 4];
+        tee.read_exact(&mut buf).unwrap();
+        drop(tee);
+        assert!(cache.get("k").is_none());
+
+        store(&cache, "k", b"0123456789");
+        let from_disk = Cache::new(cache.config().clone());
+        assert_eq!(read(from_disk.get("k")).unwrap(), b"0123456789");
+        // Read again, from memory this time
+        assert_eq!(read(from_disk.get("k")).unwrap(), b"0123456789");
+        assert_eq!(from_disk.stats(), CacheStats { memory_hits: 1, disk_hits: 1, ..CacheStats::default() });
+        assert_eq!(cache.stats(), CacheStats { misses: 1, stored: 1, ..CacheStats::default() });
+        fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[test]
+    fn evicts_corrupt_and_expired_entries() {
+        let cache = temp_cache("corrupt", 1 << 20);
+        store(&cache, "cut", b"0123456789");
+        let path = cache.entry_path("cut");
+        let stored = fs::read(&path).unwrap();
+        fs::write(&path, &stored[..stored.len() - 1]).unwrap();
+        assert!(cache.get("cut").is_none());
+        assert!(!path.exists());
+        fs::write(&path, [b"RIFF".as_slice(), &stored[4..]].concat()).unwrap();
+        assert!(cache.get("cut").is_none());
+        assert_eq!(cache.stats().evicted, 2);
+
+        let config = CacheConfig { ttl: Some(Duration::ZERO), ..cache.config().clone() };
+        let expiring = Cache::new(config);
+        store(&expiring, "old", b"0123456789");
+        assert!(expiring.get("old").is_none());
+        fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let cache = temp_cache("lru", 2 * HEADER_LEN + 25);
+        for key in ["a", "b"] {
+            store(&cache, key, &[0u8; 10]);
+        }
+        // Touch "a" so "b" is the older entry
+        let past = SystemTime::now() - Duration::from_secs(60);
+        File::options().write(true).open(cache.entry_path("b")).unwrap().set_modified(past).unwrap();
+        assert!(cache.get("a").is_some());
+
+        store(&cache, "c", &[0u8; 10]);
+        let on_disk = |key| cache.entry_path(key).exists();
+        assert_eq!((on_disk("a"), on_disk("b"), on_disk("c")), (true, false, true));
+        fs::remove_dir_all(cache.dir()).unwrap();
+    }
+}
@@ -0,0 +1,146 @@
+//! Stopping a synthesis from elsewhere, as when the user moves on. A
+//! [`CancellationToken`] is handed to `synthesize_cancellable`; cancelling
+//! it, from any thread, drops the connection at the next read, ends the
+//! stream's samples or source, and makes whatever was waiting on the
+//! synthesis fail with [`SpeakError::Cancelled`].
+//!
+//! ```
+//! use speakturbo_client::CancellationToken;
+//! use std::time::Duration;
+//!
+//! let token = CancellationToken::new();
+//! let cancel = token.clone();
+//! std::thread::spawn(move || cancel.cancel());
+//! assert!(token.wait_timeout(Duration::from_secs(5)));
+//! ```
+
+use crate::error::{Result, SpeakError};
+use std::fmt;
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::Waker;
+use std::time::{Duration, Instant};
+
+/// Cancels once, for good; clones cancel together
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    /// Async tasks to wake on cancelling, under the lock the condvar uses
+    wakers: Mutex<Vec<Waker>>,
+    changed: Condvar,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Cancel, waking whatever is waiting on the token. Takes a lock, so
+    /// not for a signal handler.
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        let wakers = std::mem::take(&mut *self.inner.wakers.lock().unwrap_or_else(|e| e.into_inner()));
+        self.inner.changed.notify_all();
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// [`SpeakError::Cancelled`] once cancelled.
+    pub fn check(&self) -> Result<()> {
+        match self.is_cancelled() {
+            true => Err(SpeakError::Cancelled),
+            false => Ok(()),
+        }
+    }
+
+    /// Wait up to `timeout` for the token to be cancelled; whether it was.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut wakers = self.inner.wakers.lock().unwrap_or_else(|e| e.into_inner());
+        while !self.is_cancelled() {
+            let Some(left) = deadline.checked_duration_since(Instant::now()) else { break };
+            wakers = self.inner.changed.wait_timeout(wakers, left).unwrap_or_else(|e| e.into_inner()).0;
+        }
+        self.is_cancelled()
+    }
+
+    /// `reader`, until the token is cancelled: then it's dropped, and
+    /// reads fail with [`SpeakError::Cancelled`] inside the `io::Error`.
+    pub fn reader<R: Read>(&self, reader: R) -> CancellableReader<R> {
+        CancellableReader { reader: Some(reader), token: self.clone() }
+    }
+
+    /// Wake `waker` when the token is cancelled, or now if it has been.
+    #[cfg(feature = "async")]
+    pub(crate) fn wake_on_cancel(&self, waker: &Waker) {
+        let mut wakers = self.inner.wakers.lock().unwrap_or_else(|e| e.into_inner());
+        match self.is_cancelled() {
+            true => waker.wake_by_ref(),
+            false if wakers.iter().any(|w| w.will_wake(waker)) => {}
+            false => wakers.push(waker.clone()),
+        }
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancellationToken").field("cancelled", &self.is_cancelled()).finish()
+    }
+}
+
+/// A reader that gives up at its token's cancelling; see
+/// [`CancellationToken::reader`].
+pub struct CancellableReader<R> {
+    /// None once cancelled
+    reader: Option<R>,
+    token: CancellationToken,
+}
+
+impl<R: Read> Read for CancellableReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.reader {
+            Some(reader) if !self.token.is_cancelled() => reader.read(buf),
+            _ => {
+                self.reader = None;
+                Err(cancelled())
+            }
+        }
+    }
+}
+
+/// The io::Error of a read cut short by cancelling, which
+/// [`SpeakError::reading`] turns back into [`SpeakError::Cancelled`]
+pub(crate) fn cancelled() -> io::Error {
+    io::Error::other(SpeakError::Cancelled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn drops_the_reader_once_cancelled() {
+        let token = CancellationToken::new();
+        let mut reader = token.reader(Cursor::new(vec![1, 2, 3, 4]));
+        let mut buf = [0; 2];
+        assert_eq!(reader.read(&mut buf).unwrap(), 2);
+        assert!(!token.wait_timeout(Duration::from_millis(1)));
+        token.clone().cancel();
+        let error = reader.read(&mut buf).unwrap_err();
+        assert!(matches!(SpeakError::reading(error), SpeakError::Cancelled));
+        assert!(reader.reader.is_none());
+        assert!(token.wait_timeout(Duration::ZERO) && token.check().is_err());
+    }
+}
@@ -0,0 +1,96 @@
+//! The per-user directories speakturbo keeps things in. An `XDG_*_HOME`
+//! variable wins everywhere; past that, Unix-likes use the XDG defaults
+//! under `$HOME`, and Windows the known folders the shell gives (Local
+//! AppData for the cache and state, Roaming AppData for the config), not
+//! whatever HOME a Unix-style shell happens to set.
+
+use std::path::PathBuf;
+
+/// The cache's base: `$XDG_CACHE_HOME`, `~/.cache`, or Local AppData.
+pub fn cache() -> Option<PathBuf> {
+    base("XDG_CACHE_HOME", ".cache", Folder::Local)
+}
+
+/// The config's base: `$XDG_CONFIG_HOME`, `~/.config`, or Roaming AppData.
+pub fn config() -> Option<PathBuf> {
+    base("XDG_CONFIG_HOME", ".config", Folder::Roaming)
+}
+
+/// The base for history and the like: `$XDG_STATE_HOME`, `~/.local/state`,
+/// or Local AppData.
+pub fn state() -> Option<PathBuf> {
+    base("XDG_STATE_HOME", ".local/state", Folder::Local)
+}
+
+#[cfg_attr(not(windows), allow(dead_code))]
+enum Folder {
+    Local,
+    Roaming,
+}
+
+fn base(xdg: &str, under_home: &str, folder: Folder) -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os(xdg).filter(|v| !v.is_empty()) {
+        return Some(PathBuf::from(dir));
+    }
+    #[cfg(windows)]
+    {
+        let _ = under_home;
+        known_folder(folder)
+    }
+    #[cfg(not(windows))]
+    {
+        let _ = folder;
+        std::env::var_os("HOME").filter(|v| !v.is_empty()).map(|home| PathBuf::from(home).join(under_home))
+    }
+}
+
+/// The shell's answer for `folder`; its variable when the shell won't say.
+#[cfg(windows)]
+fn known_folder(folder: Folder) -> Option<PathBuf> {
+    use std::ffi::{c_void, OsString};
+    use std::os::windows::ffi::OsStringExt;
+
+    #[repr(C)]
+    struct Guid(u32, u16, u16, [u8; 8]);
+
+    #[link(name = "shell32")]
+    extern "system" {
+        fn SHGetKnownFolderPath(id: *const Guid, flags: u32, token: *mut c_void, path: *mut *mut u16) -> i32;
+    }
+    #[link(name = "ole32")]
+    extern "system" {
+        fn CoTaskMemFree(memory: *mut c_void);
+    }
+
+    // FOLDERID_LocalAppData and FOLDERID_RoamingAppData
+    let (id, variable) = match folder {
+        Folder::Local => (Guid(0xF1B32785, 0x6FBA, 0x4FCF, [0x9D, 0x55, 0x7B, 0x8E, 0x7F, 0x15, 0x70, 0x91]), "LOCALAPPDATA"),
+        Folder::Roaming => (Guid(0x3EB685DB, 0x65F9, 0x4CF6, [0xA0, 0x3A, 0xE3, 0xEF, 0x65, 0x72, 0x9F, 0x3D]), "APPDATA"),
+    };
+    let mut path = std::ptr::null_mut();
+    let found = unsafe { SHGetKnownFolderPath(&id, 0, std::ptr::null_mut(), &mut path) } == 0;
+    let dir = match found && !path.is_null() {
+        true => {
+            let len = (0..).take_while(|&i| unsafe { *path.add(i) } != 0).count();
+            let wide = unsafe { std::slice::from_raw_parts(path, len) };
+            Some(PathBuf::from(OsString::from_wide(wide)))
+        }
+        false => None,
+    };
+    // Freed whether or not the call succeeded, as the shell asks
+    unsafe { CoTaskMemFree(path.cast()) };
+    dir.or_else(|| std::env::var_os(variable).filter(|v| !v.is_empty()).map(PathBuf::from))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn an_xdg_variable_wins_unless_empty() {
+        std::env::set_var("SPEAKTURBO_TEST_DIRS", "/srv/speakturbo");
+        assert_eq!(base("SPEAKTURBO_TEST_DIRS", ".cache", Folder::Local), Some(PathBuf::from("/srv/speakturbo")));
+        std::env::set_var("SPEAKTURBO_TEST_DIRS", "");
+        assert_ne!(base("SPEAKTURBO_TEST_DIRS", ".cache", Folder::Local), Some(PathBuf::new()));
+    }
+}
@@ -0,0 +1,160 @@
+//! What can go wrong, as one enum the library's calls all return, so that
+//! a caller can tell a daemon that's down from one that refused, or from
+//! audio that didn't parse, without reading messages. Each variant's
+//! message is one a person can act on; the cause, where there is one, is
+//! its [`source`](std::error::Error::source).
+//!
+//! ```no_run
+//! # #[cfg(feature = "blocking")] {
+//! use speakturbo_client::{SpeakClient, SpeakError, SpeakRequest};
+//!
+//! let client = SpeakClient::new(speakturbo_client::DEFAULT_URL);
+//! match client.synthesize(&SpeakRequest::new("Hi").voice("nobody").build()?) {
+//!     Err(SpeakError::DaemonError { status: 400, message, .. }) => eprintln!("Refused: {}", message),
+//!     Err(e) => return Err(e),
+//!     Ok(audio) => drop(audio),
+//! }
+//! # }
+//! # Ok::<(), speakturbo_client::SpeakError>(())
+//! ```
+
+use crate::RequestError;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+/// Most of a daemon's error body kept in [`SpeakError::DaemonError`]
+#[cfg(any(feature = "blocking", feature = "async"))]
+const MAX_MESSAGE: usize = 200;
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum SpeakError {
+    /// Nothing answered at `url`; the source says how reaching it failed
+    #[error("Daemon not running?")]
+    DaemonUnreachable { url: String, source: Box<dyn Error + Send + Sync> },
+    /// The daemon answered with an error status, and `message`, its body
+    #[error("The daemon answered {status}{}", colon(.message))]
+    DaemonError {
+        status: u16,
+        message: String,
+        /// How long a 429 asks to be left alone for
+        retry_after: Option<Duration>,
+    },
+    /// What should be WAV isn't, or ends too soon
+    #[error("{reason}")]
+    BadAudio { reason: String },
+    /// /health's answer doesn't say what it should
+    #[error("{reason}")]
+    BadResponse { reason: String },
+    /// The daemon stopped answering
+    #[error("Timed out {phase}")]
+    Timeout { phase: Phase },
+    /// The synthesis was stopped before its end
+    #[error("Cancelled")]
+    Cancelled,
+    /// There's no audio device to play on
+    #[error("No audio output")]
+    OutputDevice { source: Box<dyn Error + Send + Sync> },
+    /// The request didn't pass `build()`
+    #[error(transparent)]
+    Request(#[from] RequestError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Where in a synthesis the daemon went quiet
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Connect,
+    /// Connected, before the response's head
+    Response,
+    /// Reading the audio
+    Stream,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Phase::Connect => "connecting to the daemon",
+            Phase::Response => "waiting for the daemon to answer",
+            Phase::Stream => "reading the audio",
+        })
+    }
+}
+
+fn colon(message: &str) -> String {
+    match message {
+        "" => String::new(),
+        message => format!(": {}", message),
+    }
+}
+
+/// The `Result` of the library's calls
+pub type Result<T, E = SpeakError> = std::result::Result<T, E>;
+
+impl SpeakError {
+    pub(crate) fn bad_audio(reason: impl Into<String>) -> SpeakError {
+        SpeakError::BadAudio { reason: reason.into() }
+    }
+
+    /// No audio device, for `source`.
+    pub fn output_device(source: impl Into<Box<dyn Error + Send + Sync>>) -> SpeakError {
+        SpeakError::OutputDevice { source: source.into() }
+    }
+
+    /// `error` from reading the audio, a timeout told apart.
+    pub(crate) fn reading(error: io::Error) -> SpeakError {
+        if let Some(SpeakError::Cancelled) = error.get_ref().and_then(|e| e.downcast_ref::<SpeakError>()) {
+            return SpeakError::Cancelled;
+        }
+        match error.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => SpeakError::Timeout { phase: Phase::Stream },
+            _ => SpeakError::Io(error),
+        }
+    }
+
+    /// The daemon's error `status`, with its `body` and Retry-After header.
+    #[cfg(any(feature = "blocking", feature = "async"))]
+    pub(crate) fn daemon(status: u16, body: &str, retry_after: Option<&str>) -> SpeakError {
+        SpeakError::DaemonError {
+            status,
+            message: body.trim().chars().take(MAX_MESSAGE).collect(),
+            retry_after: retry_after.and_then(|s| s.trim().parse::<f64>().ok()).map(|secs| Duration::from_secs_f64(secs.clamp(0.0, 60.0))),
+        }
+    }
+
+    /// `error` from ureq's call to the daemon at `url`.
+    #[cfg(feature = "blocking")]
+    pub(crate) fn of_ureq(url: &str, error: ureq::Error) -> SpeakError {
+        let transport = match error {
+            ureq::Error::Status(status, response) => {
+                let retry_after = response.header("Retry-After").map(str::to_string);
+                let body = response.into_string().unwrap_or_default();
+                return SpeakError::daemon(status, &body, retry_after.as_deref());
+            }
+            ureq::Error::Transport(transport) => transport,
+        };
+        let timed_out = transport
+            .source()
+            .and_then(|e| e.downcast_ref::<io::Error>())
+            .is_some_and(|e| matches!(e.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock));
+        match (timed_out, transport.kind()) {
+            (true, ureq::ErrorKind::ConnectionFailed) => SpeakError::Timeout { phase: Phase::Connect },
+            (true, _) => SpeakError::Timeout { phase: Phase::Response },
+            _ => SpeakError::DaemonUnreachable { url: url.to_string(), source: Box::new(transport) },
+        }
+    }
+
+    /// `error` from reqwest's call to the daemon at `url`.
+    #[cfg(feature = "async")]
+    pub(crate) fn of_reqwest(url: &str, error: reqwest::Error) -> SpeakError {
+        match (error.is_timeout(), error.is_connect()) {
+            (true, true) => SpeakError::Timeout { phase: Phase::Connect },
+            (true, false) => SpeakError::Timeout { phase: Phase::Response },
+            _ => SpeakError::DaemonUnreachable { url: url.to_string(), source: Box::new(error) },
+        }
+    }
+}
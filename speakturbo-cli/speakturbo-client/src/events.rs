@@ -0,0 +1,145 @@
+//! What happens to a synthesis as it's read and played, for whoever is
+//! watching: the first bytes and samples, progress, underruns, the end.
+//! Sending an event never waits, so playback sends its own from the audio
+//! thread; a callback is run on a thread of its own, one event at a time.
+//!
+//! ```
+//! use speakturbo_client::events::{Events, SpeakEvent};
+//!
+//! let (events, received) = Events::channel();
+//! events.emit(SpeakEvent::FirstByte { ms: 95 });
+//! assert_eq!(received.recv().unwrap(), SpeakEvent::FirstByte { ms: 95 });
+//! ```
+
+use crate::stats::SynthesisStats;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// Times are in milliseconds from the request's start
+#[non_exhaustive]
+#[derive(Clone, Debug, PartialEq)]
+pub enum SpeakEvent {
+    /// The daemon's first audio bytes arrived
+    FirstByte { ms: u64 },
+    /// Playback took its first sample
+    FirstSample { ms: u64 },
+    /// More audio arrived
+    Progress { samples_emitted: u64, bytes_received: u64 },
+    /// Playback found nothing waiting before the end
+    Underrun,
+    /// Part `index` (from 0) of a text read in parts started streaming
+    ChunkStarted { index: usize },
+    /// Playback reached the end, the synthesis's stats complete
+    Finished { ms: u64, stats: SynthesisStats },
+    Error { kind: ErrorKind, message: String },
+}
+
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Reading the stream failed partway
+    Stream,
+    /// Playback was stopped before the end
+    Interrupted,
+}
+
+/// Where events go: nowhere, a channel, or a callback. Clones send to the
+/// same place.
+#[derive(Clone, Default)]
+pub struct Events {
+    target: Option<Target>,
+}
+
+#[derive(Clone)]
+enum Target {
+    Channel(Sender<SpeakEvent>),
+    Callback(Sender<Message>),
+}
+
+enum Message {
+    Event(SpeakEvent),
+    /// Answered once every event before it has been handled
+    Flush(Sender<()>),
+}
+
+impl Events {
+    /// Events that go nowhere
+    pub fn none() -> Events {
+        Events::default()
+    }
+
+    /// Events sent down a channel, to receive as one likes.
+    pub fn channel() -> (Events, Receiver<SpeakEvent>) {
+        let (sender, receiver) = mpsc::channel();
+        (Events { target: Some(Target::Channel(sender)) }, receiver)
+    }
+
+    /// Events handed to `callback` in order, on a thread that lasts as
+    /// long as the handle or a clone of it.
+    pub fn callback(mut callback: impl FnMut(SpeakEvent) + Send + 'static) -> Events {
+        let (sender, receiver) = mpsc::channel();
+        let spawned = std::thread::Builder::new().name("speakturbo-events".into()).spawn(move || {
+            for message in receiver {
+                match message {
+                    Message::Event(event) => callback(event),
+                    Message::Flush(done) => {
+                        let _ = done.send(());
+                    }
+                }
+            }
+        });
+        match spawned {
+            Ok(_) => Events { target: Some(Target::Callback(sender)) },
+            Err(_) => Events::none(),
+        }
+    }
+
+    /// Whether events go anywhere
+    pub fn is_watched(&self) -> bool {
+        self.target.is_some()
+    }
+
+    /// Send `event` on; this never waits.
+    pub fn emit(&self, event: SpeakEvent) {
+        // A receiver that has gone away wants no more
+        match &self.target {
+            None => {}
+            Some(Target::Channel(sender)) => {
+                let _ = sender.send(event);
+            }
+            Some(Target::Callback(sender)) => {
+                let _ = sender.send(Message::Event(event));
+            }
+        }
+    }
+
+    /// Wait until the callback has handled every event sent so far, so
+    /// that what it prints comes before what follows. Without a callback
+    /// this returns at once.
+    pub fn flush(&self) {
+        if let Some(Target::Callback(sender)) = &self.target {
+            let (done, handled) = mpsc::channel();
+            if sender.send(Message::Flush(done)).is_ok() {
+                let _ = handled.recv();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn callbacks_see_every_event_by_the_flush() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&seen);
+        let events = Events::callback(move |event| log.lock().unwrap().push(event));
+        events.emit(SpeakEvent::ChunkStarted { index: 0 });
+        events.clone().emit(SpeakEvent::Underrun);
+        events.flush();
+        assert_eq!(*seen.lock().unwrap(), [SpeakEvent::ChunkStarted { index: 0 }, SpeakEvent::Underrun]);
+        Events::none().emit(SpeakEvent::Underrun);
+        Events::none().flush();
+    }
+}
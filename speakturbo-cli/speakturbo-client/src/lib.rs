@@ -0,0 +1,71 @@
+//! A client for the speakturbo daemon: synthesis as the daemon's WAV
+//! stream, its voices and its health, and the pieces a player is built
+//! from (the WAV header, the sample buffer, the events of playback). Nothing here opens an audio
+//! device or reads a command line; the `speakturbo` CLI is one consumer.
+//! Every call that can fail says why with a [`SpeakError`].
+//!
+//! Features:
+//! - `blocking` (default): [`SpeakClient`], over ureq or another
+//!   [`Transport`]; a synthesis reads
+//!   as bytes, or as [`Samples`] for a plain iterator
+//! - `rodio`: a synthesis appended to a rodio Sink of one's own, as a
+//!   `source::SpeechSource`
+//! - `async`: `AsyncSpeakClient`, over reqwest, whose syntheses are
+//!   streams of samples; it builds without `blocking`
+//! - `serde`: [`SynthesisStats`] as `serde::Serialize` and `Deserialize`,
+//!   and [`Health`] and [`Capabilities`] as `serde::Deserialize`; both
+//!   clients need it
+//!
+//! ```no_run
+//! # #[cfg(feature = "blocking")] {
+//! use speakturbo_client::{wav, SpeakClient, SpeakRequest};
+//!
+//! let client = SpeakClient::new("http://127.0.0.1:7125");
+//! let request = SpeakRequest::new("Hello there").voice("alba").build()?;
+//! let mut audio = client.synthesize(&request)?;
+//! let (format, _) = wav::read_header(&mut audio)?;
+//! // audio now reads 16-bit PCM in `format`
+//! # }
+//! # Ok::<(), speakturbo_client::SpeakError>(())
+//! ```
+
+#[cfg(feature = "async")]
+pub mod async_client;
+pub mod audio;
+#[cfg(feature = "blocking")]
+mod blocking;
+pub mod buffer;
+pub mod cache;
+pub mod cancel;
+pub mod dirs;
+pub mod error;
+pub mod events;
+pub mod mock_daemon;
+pub mod request;
+#[cfg(feature = "blocking")]
+pub mod samples;
+#[cfg(feature = "rodio")]
+pub mod source;
+pub mod stats;
+mod transport;
+pub mod wav;
+
+#[cfg(feature = "async")]
+pub use async_client::{AsyncSpeakClient, PcmStream};
+#[cfg(feature = "blocking")]
+pub use blocking::{AudioStream, SpeakClient, UreqTransport};
+#[cfg(feature = "blocking")]
+pub use samples::{FramesF32, Samples};
+pub use cancel::CancellationToken;
+pub use error::SpeakError;
+pub use events::{Events, SpeakEvent};
+pub use request::{RequestError, SpeakRequest, SpeakRequestBuilder};
+pub use stats::SynthesisStats;
+pub use transport::{Capabilities, Fetched, Health, ResponseHead, Transport};
+
+/// Where the daemon listens unless told otherwise
+pub const DEFAULT_URL: &str = "http://127.0.0.1:7125";
+/// The daemon's voices
+pub const VOICES: &[&str] = &["alba", "marius", "javert", "jean", "fantine", "cosette", "eponine", "azelma"];
+/// The daemon's streams are 16-bit mono at this rate
+pub const SAMPLE_RATE: u32 = 24000;
@@ -0,0 +1,288 @@
+//! A stand-in for the synthesis daemon on an ephemeral local port, for
+//! tests that need the whole client path and for `speakturbo self-test`.
+//! /health and /voices list [`crate::VOICES`]; /tts streams a sine sweep as
+//! a chunked WAV the way the daemon does, as long as [`samples_for`] says.
+//!
+//! [`MockTransport`] says the same without a socket, or answers every
+//! request with a crafted stream, read as the test says.
+
+use crate::error::{Result, SpeakError};
+use crate::transport::{Fetched, ResponseHead, Transport};
+use crate::wav::{self, WavFormat};
+use crate::{SpeakRequest, SAMPLE_RATE};
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// PCM bytes per chunk of the /tts stream
+const CHUNK_BYTES: usize = 9600;
+
+pub struct MockDaemon {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MockDaemon {
+    /// Listen on 127.0.0.1 on a port the system picks, until dropped.
+    pub fn start() -> io::Result<MockDaemon> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        // Polled, so that dropping it stops the thread
+        listener.set_nonblocking(true)?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::clone(&stop);
+        let thread = std::thread::Builder::new().name("mock-daemon".into()).spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        std::thread::spawn(move || answer(stream));
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => std::thread::sleep(Duration::from_millis(5)),
+                    Err(_) => break,
+                }
+            }
+        })?;
+        Ok(MockDaemon { addr, stop, thread: Some(thread) })
+    }
+
+    /// "http://127.0.0.1:PORT", as --daemon-url takes it
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+}
+
+impl Drop for MockDaemon {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// How many samples /tts gives `text`: 60ms a character and 200ms more.
+pub fn samples_for(text: &str) -> usize {
+    SAMPLE_RATE as usize * (text.chars().count() * 60 + 200) / 1000
+}
+
+/// `n` samples sweeping from 220 to 880 Hz.
+pub fn sweep(n: usize) -> Vec<i16> {
+    let rate = SAMPLE_RATE as f64;
+    let span = (n.max(1) as f64) / rate;
+    (0..n)
+        .map(|i| {
+            let t = i as f64 / rate;
+            // The phase of a linear chirp: f(t) = 220 + 660 t / span
+            let phase = 2.0 * std::f64::consts::PI * (220.0 * t + 330.0 * t * t / span);
+            (phase.sin() * 8000.0) as i16
+        })
+        .collect()
+}
+
+/// What /health answers
+fn health() -> String {
+    let voices: Vec<String> = crate::VOICES.iter().map(|v| format!("{:?}", v)).collect();
+    format!("{{\"status\":\"ready\",\"voices\":[{}]}}", voices.join(","))
+}
+
+/// A streaming WAV header and `samples` of the sweep
+fn wav_bytes(samples: usize) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let format = WavFormat { channels: 1, sample_rate: SAMPLE_RATE, bits_per_sample: 16 };
+    // Writing to a Vec doesn't fail
+    let _ = wav::write_header(&mut bytes, format, wav::STREAMING_DATA_LEN);
+    bytes.extend(sweep(samples).iter().flat_map(|s| s.to_le_bytes()));
+    bytes
+}
+
+fn answer(mut stream: TcpStream) {
+    let _ = stream.set_nonblocking(false);
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+    let Some(target) = request_target(&mut stream) else { return };
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    let param = |name: &str| {
+        query.split('&').find_map(|pair| pair.split_once('=').filter(|(key, _)| *key == name).map(|(_, value)| decode(value)))
+    };
+    let _ = match path {
+        "/health" | "/voices" => respond(&mut stream, "200 OK", "application/json", health().as_bytes()),
+        "/tts" => match (param("text"), param("voice")) {
+            (Some(text), Some(voice)) if crate::VOICES.contains(&voice.as_str()) => stream_wav(&mut stream, samples_for(&text)),
+            (Some(_), Some(voice)) => respond(&mut stream, "400 Bad Request", "text/plain", format!("unknown voice {}\n", voice).as_bytes()),
+            _ => respond(&mut stream, "400 Bad Request", "text/plain", b"text and voice are required\n"),
+        },
+        _ => respond(&mut stream, "404 Not Found", "text/plain", b"not found\n"),
+    };
+}
+
+/// The target of the request's GET line, once its head has been read.
+fn request_target(stream: &mut TcpStream) -> Option<String> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf).ok().filter(|n| *n > 0)?;
+        head.extend_from_slice(&buf[..n]);
+    }
+    let head = String::from_utf8_lossy(&head);
+    let mut parts = head.lines().next()?.split_whitespace();
+    (parts.next()? == "GET").then_some(())?;
+    parts.next().map(str::to_string)
+}
+
+/// A query value with its %XX escapes and '+' undone
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok()).and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)
+}
+
+/// A streaming WAV header, then the sweep, chunked as the daemon sends it.
+fn stream_wav(stream: &mut TcpStream, samples: usize) -> io::Result<()> {
+    write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: audio/wav\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n")?;
+    let bytes = wav_bytes(samples);
+    let (header, pcm) = bytes.split_at(wav::HEADER_LEN as usize);
+    for chunk in std::iter::once(header).chain(pcm.chunks(CHUNK_BYTES)) {
+        write!(stream, "{:x}\r\n", chunk.len())?;
+        stream.write_all(chunk)?;
+        stream.write_all(b"\r\n")?;
+    }
+    stream.write_all(b"0\r\n\r\n")
+}
+
+/// The daemon's answers with no socket: what [`MockDaemon`] would say, or
+/// with [`MockTransport::answering`], the same crafted stream to every
+/// request. Reads can be made short, and the stream made to fail at its
+/// end, to try what reads it against a network's worst.
+#[derive(Clone, Debug, Default)]
+pub struct MockTransport {
+    /// None to answer as the daemon does
+    body: Option<Vec<u8>>,
+    /// The most a read gives, 0 for no limit
+    read_size: usize,
+    /// Given by the read after the last byte, instead of the end
+    error: Option<io::ErrorKind>,
+}
+
+impl MockTransport {
+    /// Answers as [`MockDaemon`] does.
+    pub fn new() -> MockTransport {
+        MockTransport::default()
+    }
+
+    /// Answers every synthesis with `body`, a voice the daemon lacks included.
+    pub fn answering(body: impl Into<Vec<u8>>) -> MockTransport {
+        MockTransport { body: Some(body.into()), ..MockTransport::default() }
+    }
+
+    /// No read gives more than `bytes` (at least 1).
+    pub fn in_reads_of(self, bytes: usize) -> MockTransport {
+        MockTransport { read_size: bytes.max(1), ..self }
+    }
+
+    /// Once the body has been read, reads fail with `kind` instead of ending.
+    pub fn then_failing(self, kind: io::ErrorKind) -> MockTransport {
+        MockTransport { error: Some(kind), ..self }
+    }
+}
+
+impl Transport for MockTransport {
+    fn fetch(&self, request: &SpeakRequest) -> Result<Fetched> {
+        let body = match &self.body {
+            Some(body) => body.clone(),
+            None if !crate::VOICES.contains(&request.voice()) => {
+                let message = format!("unknown voice {}", request.voice());
+                return Err(SpeakError::DaemonError { status: 400, message, retry_after: None });
+            }
+            None => wav_bytes(samples_for(request.text())),
+        };
+        let reads = Scripted { body, at: 0, read_size: self.read_size, error: self.error };
+        Ok(Fetched { head: ResponseHead { status: 200, ..ResponseHead::default() }, body: Box::new(reads) })
+    }
+
+    fn health(&self) -> Result<String> {
+        Ok(health())
+    }
+}
+
+/// A [`MockTransport`]'s body, read as it says
+struct Scripted {
+    body: Vec<u8>,
+    at: usize,
+    read_size: usize,
+    error: Option<io::ErrorKind>,
+}
+
+impl Read for Scripted {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = &self.body[self.at..];
+        if left.is_empty() {
+            return match self.error {
+                Some(kind) => Err(io::Error::new(kind, "the mock transport failed, as asked")),
+                None => Ok(0),
+            };
+        }
+        let limit = if self.read_size == 0 { buf.len() } else { self.read_size.min(buf.len()) };
+        let n = left.len().min(limit);
+        buf[..n].copy_from_slice(&left[..n]);
+        self.at += n;
+        Ok(n)
+    }
+}
+
+#[cfg(all(test, feature = "blocking"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_like_the_daemon() {
+        let daemon = MockDaemon::start().unwrap();
+        let health = ureq::get(&format!("{}/health", daemon.url())).call().unwrap().into_string().unwrap();
+        assert!(health.starts_with("{\"status\":\"ready\",\"voices\":[\"alba\","), "{}", health);
+
+        let response = ureq::get(&format!("{}/tts?text=Hi%20there&voice=alba", daemon.url())).call().unwrap();
+        let mut reader = response.into_reader();
+        let (format, _) = wav::read_header(&mut reader).unwrap();
+        assert_eq!(format, WavFormat { channels: 1, sample_rate: SAMPLE_RATE, bits_per_sample: 16 });
+        let mut pcm = Vec::new();
+        reader.read_to_end(&mut pcm).unwrap();
+        assert_eq!(pcm.len(), 2 * samples_for("Hi there"));
+
+        match ureq::get(&format!("{}/tts?text=Hi&voice=nobody", daemon.url())).call() {
+            Err(ureq::Error::Status(400, _)) => {}
+            other => panic!("{:?}", other.map(|r| r.status())),
+        }
+        assert_eq!(decode("a%20b+c%zz"), "a b c%zz");
+    }
+}
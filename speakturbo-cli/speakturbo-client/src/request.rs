@@ -0,0 +1,257 @@
+//! What a synthesis asks the daemon for, built up option by option and
+//! checked once, at `build()`:
+//!
+//! ```
+//! use speakturbo_client::SpeakRequest;
+//!
+//! let request = SpeakRequest::new("Hello there").voice("alba").style("calm").seed(42).param("x", "y").build()?;
+//! assert_eq!(request.query(), "text=Hello%20there&voice=alba&style=calm&seed=42&x=y");
+//! # Ok::<(), speakturbo_client::RequestError>(())
+//! ```
+//!
+//! The GET query and the POST JSON body both come from [`SpeakRequest::fields`],
+//! so the two forms say the same thing.
+
+use serde_json::Value;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// The voice a request gets unless it names one
+pub const DEFAULT_VOICE: &str = "alba";
+/// Speaking rates the daemon is asked for, 1.0 being its natural pace
+pub const RATES: RangeInclusive<f64> = 0.25..=4.0;
+/// The largest seed a JSON number carries exactly
+pub const MAX_SEED: u64 = (1 << 53) - 1;
+/// Names the request's own fields take, which a param can't
+pub(crate) const FIELDS: [&str; 6] = ["text", "voice", "style", "rate", "seed", "ssml"];
+
+/// A checked synthesis request; see [`SpeakRequest::new`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpeakRequest {
+    text: String,
+    voice: String,
+    style: Option<String>,
+    rate: Option<f64>,
+    seed: Option<u64>,
+    ssml: bool,
+    params: Vec<(String, String)>,
+}
+
+/// A request being put together; `build()` checks it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpeakRequestBuilder {
+    request: SpeakRequest,
+}
+
+/// Why `build()` refused a request
+#[derive(Clone, Debug, PartialEq)]
+pub enum RequestError {
+    /// No text, or only whitespace
+    EmptyText,
+    EmptyVoice,
+    /// Outside [`RATES`]
+    Rate(f64),
+    /// Above [`MAX_SEED`]
+    Seed(u64),
+    /// A param named like one of the request's own fields, or not named
+    Param(String),
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::EmptyText => f.write_str("Nothing to say: the text is empty"),
+            RequestError::EmptyVoice => f.write_str("The voice is empty"),
+            RequestError::Rate(rate) => write!(f, "Rate {} is outside {}-{}", rate, RATES.start(), RATES.end()),
+            RequestError::Seed(seed) => write!(f, "Seed {} is above {}", seed, MAX_SEED),
+            RequestError::Param(name) if name.is_empty() => f.write_str("A param needs a name"),
+            RequestError::Param(name) => write!(f, "\"{}\" is set by the request itself, not as a param", name),
+        }
+    }
+}
+
+impl std::error::Error for RequestError {}
+
+impl SpeakRequest {
+    /// Start a request for `text`, in [`DEFAULT_VOICE`] until `.voice()` says otherwise.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(text: impl Into<String>) -> SpeakRequestBuilder {
+        SpeakRequestBuilder {
+            request: SpeakRequest {
+                text: text.into(),
+                voice: DEFAULT_VOICE.to_string(),
+                style: None,
+                rate: None,
+                seed: None,
+                ssml: false,
+                params: Vec::new(),
+            },
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn voice(&self) -> &str {
+        &self.voice
+    }
+
+    pub fn ssml(&self) -> bool {
+        self.ssml
+    }
+
+    pub fn params(&self) -> &[(String, String)] {
+        &self.params
+    }
+
+    /// A builder with this request's options, to change some of them.
+    pub fn to_builder(&self) -> SpeakRequestBuilder {
+        SpeakRequestBuilder { request: self.clone() }
+    }
+
+    /// Each field the daemon is sent, in order; unset options are left out.
+    pub fn fields(&self) -> Vec<(&str, Value)> {
+        let mut fields = vec![("text", Value::from(self.text.as_str())), ("voice", Value::from(self.voice.as_str()))];
+        if let Some(style) = &self.style {
+            fields.push(("style", Value::from(style.as_str())));
+        }
+        if let Some(rate) = self.rate {
+            fields.push(("rate", Value::from(rate)));
+        }
+        if let Some(seed) = self.seed {
+            fields.push(("seed", Value::from(seed)));
+        }
+        if self.ssml {
+            fields.push(("ssml", Value::from(true)));
+        }
+        fields.extend(self.params.iter().map(|(name, value)| (name.as_str(), Value::from(value.as_str()))));
+        fields
+    }
+
+    /// The GET form: "text=...&voice=...", percent-encoded.
+    pub fn query(&self) -> String {
+        let fields: Vec<String> = self
+            .fields()
+            .into_iter()
+            .map(|(name, value)| match value {
+                Value::String(s) => format!("{}={}", encode(name), encode(&s)),
+                value => format!("{}={}", encode(name), value),
+            })
+            .collect();
+        fields.join("&")
+    }
+
+    /// The POST form: a JSON object of the same fields.
+    pub fn to_json(&self) -> Value {
+        Value::Object(self.fields().into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+}
+
+impl SpeakRequestBuilder {
+    pub fn text(mut self, text: impl Into<String>) -> Self {
+        self.request.text = text.into();
+        self
+    }
+
+    pub fn voice(mut self, voice: impl Into<String>) -> Self {
+        self.request.voice = voice.into();
+        self
+    }
+
+    pub fn style(mut self, style: impl Into<String>) -> Self {
+        self.request.style = Some(style.into());
+        self
+    }
+
+    pub fn rate(mut self, rate: f64) -> Self {
+        self.request.rate = Some(rate);
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.request.seed = Some(seed);
+        self
+    }
+
+    /// The text is SSML markup
+    pub fn ssml(mut self, ssml: bool) -> Self {
+        self.request.ssml = ssml;
+        self
+    }
+
+    /// Pass `name=value` on to the daemon as it is.
+    pub fn param(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.request.params.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn params<'a>(mut self, params: impl IntoIterator<Item = &'a (String, String)>) -> Self {
+        self.request.params.extend(params.into_iter().cloned());
+        self
+    }
+
+    pub fn build(self) -> Result<SpeakRequest, RequestError> {
+        let request = self.request;
+        if request.text.trim().is_empty() {
+            return Err(RequestError::EmptyText);
+        }
+        if request.voice.is_empty() {
+            return Err(RequestError::EmptyVoice);
+        }
+        if let Some(rate) = request.rate.filter(|rate| !RATES.contains(rate)) {
+            return Err(RequestError::Rate(rate));
+        }
+        if let Some(seed) = request.seed.filter(|seed| *seed > MAX_SEED) {
+            return Err(RequestError::Seed(seed));
+        }
+        if let Some((name, _)) = request.params.iter().find(|(name, _)| name.is_empty() || FIELDS.contains(&name.as_str())) {
+            return Err(RequestError::Param(name.clone()));
+        }
+        Ok(request)
+    }
+}
+
+fn encode(s: &str) -> String {
+    let mut r = String::with_capacity(s.len() * 2);
+    for c in s.chars() {
+        match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' | '~' => r.push(c),
+            ' ' => r.push_str("%20"),
+            _ => {
+                for b in c.to_string().as_bytes() {
+                    r.push_str(&format!("%{:02X}", b));
+                }
+            }
+        }
+    }
+    r
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_both_forms_alike() {
+        let request = SpeakRequest::new("Café, 2 €").voice("javert").rate(1.5).ssml(true).param("lang", "fr").build().unwrap();
+        assert_eq!(request.query(), "text=Caf%C3%A9%2C%202%20%E2%82%AC&voice=javert&rate=1.5&ssml=true&lang=fr");
+        assert_eq!(request.to_json().to_string(), r#"{"text":"Café, 2 €","voice":"javert","rate":1.5,"ssml":true,"lang":"fr"}"#);
+        assert_eq!(request.to_builder().text("Again").build().unwrap().query(), "text=Again&voice=javert&rate=1.5&ssml=true&lang=fr");
+    }
+
+    #[test]
+    fn refuses_bad_requests_at_build() {
+        let cases = [
+            (SpeakRequest::new("  \n"), RequestError::EmptyText),
+            (SpeakRequest::new("Hi").voice(""), RequestError::EmptyVoice),
+            (SpeakRequest::new("Hi").rate(9.0), RequestError::Rate(9.0)),
+            (SpeakRequest::new("Hi").seed(u64::MAX), RequestError::Seed(u64::MAX)),
+            (SpeakRequest::new("Hi").param("voice", "alba"), RequestError::Param("voice".to_string())),
+        ];
+        for (builder, error) in cases {
+            assert_eq!(builder.build(), Err(error));
+        }
+        assert_eq!(SpeakRequest::new("Hi").rate(f64::NAN).build().unwrap_err().to_string(), "Rate NaN is outside 0.25-4");
+    }
+}
@@ -0,0 +1,262 @@
+//! A synthesis as a plain iterator of samples, for code that wants to
+//! collect, window or resample them itself. Nothing is read until it's
+//! asked for: each step takes from one read of at most [`READ_BYTES`], so
+//! the network keeps pace with the consumer.
+//!
+//! An iterator can't hand out a `Result` per sample without making every
+//! caller unwrap one, so an error ends the iteration instead, and
+//! [`Samples::take_error`] says why.
+//!
+//! ```no_run
+//! # use speakturbo_client::{SpeakClient, SpeakRequest};
+//! # let client = SpeakClient::new(speakturbo_client::DEFAULT_URL);
+//! let mut samples = client.synthesize(&SpeakRequest::new("Hello there").build()?)?.samples();
+//! let loudest = samples.by_ref().map(i16::unsigned_abs).max();
+//! if let Some(error) = samples.take_error() {
+//!     return Err(error);
+//! }
+//! # Ok::<(), speakturbo_client::SpeakError>(())
+//! ```
+
+use crate::error::{Result, SpeakError};
+use crate::stats::SynthesisStats;
+use crate::wav::{self, WavFormat};
+use crate::AudioStream;
+use std::io::Read;
+use std::iter::FusedIterator;
+
+/// The most read from the stream at a time
+pub const READ_BYTES: usize = 4096;
+
+/// The stream's 16-bit samples, interleaved if it has more than one channel
+pub struct Samples {
+    audio: AudioStream,
+    /// None until the header has been read
+    format: Option<WavFormat>,
+    read: Box<[u8; READ_BYTES]>,
+    /// `read[at..end]` is yet to be given out
+    at: usize,
+    end: usize,
+    /// The first byte of a sample split between reads
+    odd: Option<u8>,
+    error: Option<SpeakError>,
+    finished: bool,
+}
+
+// Both may be handed to another thread to consume
+const _: fn() = || {
+    fn send<T: Send>() {}
+    send::<Samples>();
+    send::<FramesF32>();
+};
+
+impl AudioStream {
+    /// This stream's samples, read as they're taken.
+    pub fn samples(self) -> Samples {
+        Samples { audio: self, format: None, read: Box::new([0; READ_BYTES]), at: 0, end: 0, odd: None, error: None, finished: false }
+    }
+}
+
+impl Samples {
+    /// The stream's format, once the header has been read (by the first
+    /// step or by [`Samples::read_format`]).
+    pub fn format(&self) -> Option<WavFormat> {
+        self.format
+    }
+
+    /// Read the header if it hasn't been, for the format.
+    pub fn read_format(&mut self) -> Result<WavFormat> {
+        if let Some(format) = self.format {
+            return Ok(format);
+        }
+        if self.finished {
+            return Err(SpeakError::bad_audio("The stream has already ended"));
+        }
+        match self.header() {
+            Ok(format) => Ok(format),
+            Err(e) => {
+                self.finished = true;
+                Err(e)
+            }
+        }
+    }
+
+    fn header(&mut self) -> Result<WavFormat> {
+        let (format, _) = wav::read_header(&mut self.audio)?;
+        if format.bits_per_sample != 16 || format.channels == 0 {
+            let reason = format!("{}-bit audio in {} channels; only 16-bit PCM reads as samples", format.bits_per_sample, format.channels);
+            return Err(SpeakError::BadAudio { reason });
+        }
+        self.format = Some(format);
+        Ok(format)
+    }
+
+    /// How the synthesis went, once the iteration has reached the end.
+    pub fn stats(&self) -> Option<SynthesisStats> {
+        self.audio.stats()
+    }
+
+    /// Why iteration ended early, if it did. The error is handed out once.
+    pub fn take_error(&mut self) -> Option<SpeakError> {
+        self.error.take()
+    }
+
+    /// Each frame (a sample per channel) as floats in -1.0..1.0.
+    pub fn frames_f32(self) -> FramesF32 {
+        FramesF32 { samples: self }
+    }
+
+    /// End the iteration, keeping `error` for take_error.
+    fn fail(&mut self, error: SpeakError) -> Option<i16> {
+        self.finished = true;
+        self.error = Some(error);
+        None
+    }
+}
+
+impl Iterator for Samples {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if self.finished {
+            return None;
+        }
+        if self.format.is_none() {
+            if let Err(e) = self.header() {
+                return self.fail(e);
+            }
+        }
+        loop {
+            match (self.odd, self.end - self.at) {
+                (Some(low), 1..) => {
+                    self.odd = None;
+                    self.at += 1;
+                    return Some(i16::from_le_bytes([low, self.read[self.at - 1]]));
+                }
+                (None, 2..) => {
+                    self.at += 2;
+                    return Some(i16::from_le_bytes([self.read[self.at - 2], self.read[self.at - 1]]));
+                }
+                (None, 1) => {
+                    self.odd = Some(self.read[self.at]);
+                    self.at += 1;
+                }
+                _ => {}
+            }
+            match self.audio.read(&mut self.read[..]) {
+                Ok(0) if self.odd.is_some() => return self.fail(SpeakError::bad_audio("The stream ended in the middle of a sample")),
+                Ok(0) => {
+                    self.finished = true;
+                    return None;
+                }
+                Ok(n) => (self.at, self.end) = (0, n),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return self.fail(SpeakError::reading(e)),
+            }
+        }
+    }
+}
+
+impl FusedIterator for Samples {}
+
+/// A stream's frames as floats, one `Vec` of a sample per channel each
+pub struct FramesF32 {
+    samples: Samples,
+}
+
+impl FramesF32 {
+    /// See [`Samples::format`].
+    pub fn format(&self) -> Option<WavFormat> {
+        self.samples.format()
+    }
+
+    /// See [`Samples::stats`].
+    pub fn stats(&self) -> Option<SynthesisStats> {
+        self.samples.stats()
+    }
+
+    /// See [`Samples::take_error`].
+    pub fn take_error(&mut self) -> Option<SpeakError> {
+        self.samples.take_error()
+    }
+}
+
+impl Iterator for FramesF32 {
+    type Item = Vec<f32>;
+
+    fn next(&mut self) -> Option<Vec<f32>> {
+        // The first sample brings the header, and so the channel count
+        let first = self.samples.next()?;
+        let channels = self.samples.format.map_or(1, |format| format.channels as usize);
+        let mut frame = Vec::with_capacity(channels);
+        frame.push(first as f32 / 32768.0);
+        frame.extend(self.samples.by_ref().take(channels - 1).map(|s| s as f32 / 32768.0));
+        if frame.len() < channels {
+            if self.samples.error.is_none() {
+                self.samples.fail(SpeakError::bad_audio("The stream ended in the middle of a frame"));
+            }
+            return None;
+        }
+        Some(frame)
+    }
+}
+
+impl FusedIterator for FramesF32 {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ResponseHead;
+    use std::io::{self, Cursor};
+    use std::time::Instant;
+
+    fn stream(reader: impl Read + Send + 'static) -> AudioStream {
+        AudioStream::new(Box::new(reader), ResponseHead { status: 200, ..Default::default() }, Instant::now())
+    }
+
+    fn wav(format: WavFormat, samples: &[i16]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        wav::write_header(&mut bytes, format, samples.len() as u32 * 2).unwrap();
+        bytes.extend(samples.iter().flat_map(|s| s.to_le_bytes()));
+        bytes
+    }
+
+    /// Hands out a byte per read, then fails
+    struct Trickle(Vec<u8>);
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::ConnectionReset, "reset by peer"));
+            }
+            buf[0] = self.0.remove(0);
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn frames_normalize_each_channel() {
+        let stereo = WavFormat { channels: 2, sample_rate: 8000, bits_per_sample: 16 };
+        let mut frames = stream(Cursor::new(wav(stereo, &[16384, -32768, 0, 8192, 1]))).samples().frames_f32();
+        assert_eq!(frames.next(), Some(vec![0.5, -1.0]));
+        assert_eq!(frames.format(), Some(stereo));
+        assert_eq!(frames.next(), Some(vec![0.0, 0.25]));
+        assert_eq!(frames.next(), None);
+        assert_eq!(frames.take_error().unwrap().to_string(), "The stream ended in the middle of a frame");
+    }
+
+    #[test]
+    fn ends_at_an_error_and_keeps_it() {
+        let mono = WavFormat { channels: 1, sample_rate: 8000, bits_per_sample: 16 };
+        // Split reads, a sample straddling two of them
+        let mut samples = stream(Trickle(wav(mono, &[1, -2, 300]))).samples();
+        assert_eq!(samples.by_ref().collect::<Vec<_>>(), [1, -2, 300]);
+        assert_eq!(samples.next(), None);
+        assert_eq!(samples.take_error().unwrap().to_string(), "reset by peer");
+        assert!(samples.take_error().is_none());
+
+        let mut samples = stream(Cursor::new(b"RIFF".to_vec())).samples();
+        assert_eq!(samples.next(), None);
+        assert!(matches!(samples.take_error(), Some(SpeakError::BadAudio { reason }) if reason == "Truncated WAV header"));
+    }
+}
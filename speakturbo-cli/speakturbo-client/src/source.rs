@@ -0,0 +1,303 @@
+//! With the `rodio` feature: a synthesis as a [`rodio::Source`], to append
+//! to a Sink of one's own. A thread of the library's reads the stream into
+//! a [`LockFreeBuffer`] while the source plays from it, and dropping the
+//! source stops that read, closing the connection. Cancelling the stream's
+//! token does too, and ends the source at its next sample.
+//!
+//! ```no_run
+//! # use speakturbo_client::{SpeakClient, SpeakRequest};
+//! # let (client, sink): (SpeakClient, rodio::Sink) = todo!();
+//! let request = SpeakRequest::new("Halt! Who goes there?").voice("javert").build()?;
+//! sink.append(client.synthesize(&request)?.into_rodio_source()?);
+//! # Ok::<(), speakturbo_client::SpeakError>(())
+//! ```
+
+use crate::buffer::LockFreeBuffer;
+use crate::cancel::CancellationToken;
+use crate::error::{Result, SpeakError};
+use crate::wav::{self, WavFormat};
+use crate::events::{ErrorKind, Events, SpeakEvent};
+use crate::stats::SynthesisStats;
+use crate::AudioStream;
+use rodio::Source;
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Audio read ahead of playback before reading waits for it
+const MAX_AHEAD: Duration = Duration::from_secs(30);
+
+/// How the speech is shaped on its way out, and who hears how it went
+#[derive(Clone)]
+pub struct SourceOptions {
+    /// Ramp up from silence over this long, against the click of a cold start
+    pub fade_in: Duration,
+    /// Gain on every sample, 1.0 leaving them as they are
+    pub volume: f32,
+    /// Where the stream's [`SpeakEvent`]s go; the source sends its own
+    /// from the mixer's thread, which sending never holds up
+    pub events: Events,
+}
+
+impl Default for SourceOptions {
+    fn default() -> Self {
+        SourceOptions { fade_in: Duration::from_millis(10), volume: 1.0, events: Events::none() }
+    }
+}
+
+/// The speech as it arrives. When the network falls behind, the source
+/// plays silence rather than hold up the mixer; it ends with the stream.
+pub struct SpeechSource {
+    buffer: Arc<LockFreeBuffer>,
+    format: WavFormat,
+    /// Samples over which the fade-in runs, all channels counted
+    fade_in: usize,
+    volume: f32,
+    emitted: usize,
+    /// Waiting on an empty buffer, counted once as an underrun
+    starved: bool,
+    /// Past the end, its Finished (or cancelled Error) event sent
+    finished: bool,
+    /// Set on drop, to stop the reader
+    dropped: Arc<AtomicBool>,
+    cancel: CancellationToken,
+    events: Events,
+    started: Instant,
+    /// The stream's cache hit and daemon, for its stats
+    cache_hit: Option<bool>,
+    endpoint: Option<String>,
+}
+
+impl AudioStream {
+    /// This stream as a [`rodio::Source`], with the default [`SourceOptions`].
+    pub fn into_rodio_source(self) -> Result<SpeechSource> {
+        self.into_rodio_source_with(SourceOptions::default())
+    }
+
+    /// This stream as a [`rodio::Source`]: the WAV header is read here, for
+    /// the format, and the rest on a thread of its own.
+    pub fn into_rodio_source_with(mut self, options: SourceOptions) -> Result<SpeechSource> {
+        let (format, _) = wav::read_header(&mut self)?;
+        if format.bits_per_sample != 16 {
+            return Err(SpeakError::BadAudio { reason: format!("{}-bit audio; only 16-bit PCM plays", format.bits_per_sample) });
+        }
+        let buffer = Arc::new(LockFreeBuffer::new());
+        let (dropped, cancel) = (Arc::new(AtomicBool::new(false)), self.cancellation().clone());
+        let (cache_hit, endpoint) = self.origin();
+        let (feed, stopped, events, started) = (Arc::clone(&buffer), Arc::clone(&dropped), options.events.clone(), self.started());
+        let limit = (MAX_AHEAD.as_secs_f64() * format.sample_rate as f64) as usize * format.channels as usize;
+        std::thread::Builder::new().name("speakturbo-reader".into()).spawn(move || {
+            let mut chunk = [0u8; 4096];
+            while !stopped.load(Ordering::Relaxed) {
+                if self.cancellation().is_cancelled() {
+                    return feed.fail(SpeakError::Cancelled.to_string());
+                }
+                if feed.len() >= limit {
+                    std::thread::sleep(Duration::from_millis(10));
+                    continue;
+                }
+                match self.read(&mut chunk) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        if feed.first().is_none() {
+                            feed.mark_first();
+                            events.emit(SpeakEvent::FirstByte { ms: started.elapsed().as_millis() as u64 });
+                        }
+                        feed.push_bytes(&chunk[..n]);
+                        events.emit(SpeakEvent::Progress { samples_emitted: feed.played(), bytes_received: feed.received() });
+                    }
+                    // The source says it was cancelled, as it stops
+                    Err(e) => match SpeakError::reading(e) {
+                        SpeakError::Cancelled => return feed.fail(SpeakError::Cancelled.to_string()),
+                        e => {
+                            events.emit(SpeakEvent::Error { kind: ErrorKind::Stream, message: e.to_string() });
+                            return feed.fail(e.to_string());
+                        }
+                    },
+                }
+            }
+            feed.set_done();
+        })?;
+        let fade_in = (options.fade_in.as_secs_f64() * format.sample_rate as f64) as usize * format.channels as usize;
+        Ok(SpeechSource {
+            buffer,
+            format,
+            fade_in,
+            volume: options.volume,
+            emitted: 0,
+            starved: false,
+            finished: false,
+            dropped,
+            cancel,
+            events: options.events,
+            started,
+            cache_hit,
+            endpoint,
+        })
+    }
+}
+
+impl SpeechSource {
+    /// Where reading and playback have got to, and why the stream ended
+    /// early if it did
+    pub fn buffer(&self) -> &LockFreeBuffer {
+        &self.buffer
+    }
+
+    pub fn format(&self) -> WavFormat {
+        self.format
+    }
+}
+
+impl Iterator for SpeechSource {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if self.cancel.is_cancelled() {
+            if !self.finished {
+                self.finished = true;
+                self.events.emit(SpeakEvent::Error { kind: ErrorKind::Interrupted, message: SpeakError::Cancelled.to_string() });
+            }
+            return None;
+        }
+        let Some(sample) = self.buffer.pop() else {
+            // Done is set after the last push, so an empty buffer then is the end
+            if self.buffer.is_done() && self.buffer.is_empty() {
+                if !self.finished {
+                    self.finished = true;
+                    self.buffer.mark_drained();
+                    let stats = SynthesisStats {
+                        cache_hit: self.cache_hit,
+                        endpoint: self.endpoint.clone(),
+                        ..SynthesisStats::of(&self.buffer, self.format, self.started)
+                    };
+                    self.events.emit(SpeakEvent::Finished { ms: self.started.elapsed().as_millis() as u64, stats });
+                }
+                return None;
+            }
+            if !self.starved {
+                self.starved = true;
+                self.buffer.note_underrun();
+                self.events.emit(SpeakEvent::Underrun);
+            }
+            return Some(0);
+        };
+        if self.emitted == 0 {
+            self.buffer.mark_playing();
+            self.events.emit(SpeakEvent::FirstSample { ms: self.started.elapsed().as_millis() as u64 });
+        }
+        let mut gain = self.volume;
+        if self.emitted < self.fade_in {
+            gain *= self.emitted as f32 / self.fade_in as f32;
+        }
+        self.emitted += 1;
+        self.starved = false;
+        Some((sample as f32 * gain).clamp(i16::MIN as f32, i16::MAX as f32) as i16)
+    }
+}
+
+impl Source for SpeechSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.format.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.format.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl Drop for SpeechSource {
+    fn drop(&mut self) {
+        self.dropped.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn stream(reader: impl Read + Send + 'static) -> AudioStream {
+        AudioStream::new(Box::new(reader), crate::ResponseHead { status: 200, ..Default::default() }, Instant::now())
+    }
+
+    /// A reader that says when it's dropped
+    struct Watched<R>(R, Arc<AtomicBool>);
+
+    impl<R: Read> Read for Watched<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl<R> Drop for Watched<R> {
+        fn drop(&mut self) {
+            self.1.store(true, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn plays_the_stream_in_its_format() {
+        let mut bytes = Vec::new();
+        wav::write_header(&mut bytes, WavFormat { channels: 1, sample_rate: 8000, bits_per_sample: 16 }, 8).unwrap();
+        bytes.extend([1000i16, 1000, 1000, 1000].iter().flat_map(|s| s.to_le_bytes()));
+        let (events, received) = Events::channel();
+        let options = SourceOptions { fade_in: Duration::from_micros(250), volume: 0.5, events };
+        let source = stream(Cursor::new(bytes)).into_rodio_source_with(options).unwrap();
+        assert_eq!((source.channels(), source.sample_rate()), (1, 8000));
+        // Read in full first, so that no underrun's silence gets in
+        while !source.buffer().is_done() {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(source.collect::<Vec<_>>(), [0, 250, 500, 500]);
+        let events: Vec<SpeakEvent> = received.try_iter().collect();
+        assert!(matches!(events[..], [SpeakEvent::FirstByte { .. }, SpeakEvent::Progress { samples_emitted: 0, bytes_received: 8 }, SpeakEvent::FirstSample { .. }, SpeakEvent::Finished { .. }]));
+        let Some(SpeakEvent::Finished { stats, .. }) = events.last() else { unreachable!() };
+        assert_eq!((stats.bytes, stats.underruns, stats.chunks), (8, Some(0), 1));
+    }
+
+    #[test]
+    fn ends_once_cancelled() {
+        let mut header = Vec::new();
+        wav::write_header(&mut header, WavFormat { channels: 1, sample_rate: 16000, bits_per_sample: 16 }, wav::STREAMING_DATA_LEN).unwrap();
+        let (events, received) = Events::channel();
+        let audio = stream(Cursor::new(header).chain(std::io::repeat(0)));
+        let cancel = audio.cancellation().clone();
+        let mut source = audio.into_rodio_source_with(SourceOptions { events, ..SourceOptions::default() }).unwrap();
+        assert!(source.next().is_some());
+        cancel.cancel();
+        assert_eq!(source.next(), None);
+        assert!(received.try_iter().any(|e| matches!(e, SpeakEvent::Error { kind: ErrorKind::Interrupted, .. })));
+        let start = Instant::now();
+        while !source.buffer().is_done() {
+            assert!(start.elapsed() < Duration::from_secs(2), "the reader outlived its cancelling");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn stops_reading_when_dropped() {
+        let dropped = Arc::new(AtomicBool::new(false));
+        // Stereo silence at 16 kHz, without end
+        let mut header = Vec::new();
+        wav::write_header(&mut header, WavFormat { channels: 2, sample_rate: 16000, bits_per_sample: 16 }, wav::STREAMING_DATA_LEN).unwrap();
+        let endless = Watched(Cursor::new(header).chain(std::io::repeat(0)), Arc::clone(&dropped));
+        let source = stream(endless).into_rodio_source().unwrap();
+        assert_eq!((source.channels(), source.sample_rate()), (2, 16000));
+        drop(source);
+        let start = Instant::now();
+        while !dropped.load(Ordering::Relaxed) {
+            assert!(start.elapsed() < Duration::from_secs(2), "the reader outlived its source");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+}
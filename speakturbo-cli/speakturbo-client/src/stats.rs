@@ -0,0 +1,299 @@
+//! How a synthesis went, as numbers: when its audio came and started, how
+//! much there was and how fast it arrived, and where from. A stream has
+//! its [`SynthesisStats`] once read to the end, and playback sends them
+//! in its Finished event. The `speakturbo` CLI prints --stats and --json
+//! from them, so what a program reads is what the command line shows.
+//!
+//! ```no_run
+//! # #[cfg(feature = "blocking")] {
+//! # use speakturbo_client::{SpeakClient, SpeakRequest};
+//! # let client = SpeakClient::new(speakturbo_client::DEFAULT_URL);
+//! let mut audio = client.synthesize(&SpeakRequest::new("Hello there").build()?)?;
+//! std::io::copy(&mut audio, &mut std::io::sink())?;
+//! if let Some(stats) = audio.stats() {
+//!     eprintln!("{}", stats);
+//! }
+//! # }
+//! # Ok::<(), speakturbo_client::SpeakError>(())
+//! ```
+//!
+//! With the `serde` feature they serialize, with the same fields as
+//! [`SynthesisStats::to_json`] gives, and deserialize from those.
+
+use crate::buffer::LockFreeBuffer;
+use crate::wav::WavFormat;
+use serde_json::Value;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Times are in milliseconds from the request's start
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SynthesisStats {
+    /// The first audio bytes arrived, the daemon's or the cache's
+    pub first_byte_ms: Option<u64>,
+    /// Playback took its first sample; without playback, the first
+    /// sample past the header was read
+    pub first_sample_ms: Option<u64>,
+    /// The audio's bytes, the WAV header aside
+    pub bytes: u64,
+    /// How long the audio lasts, to the millisecond
+    pub audio_secs: Option<f64>,
+    /// Time from the request to the last byte over `audio_secs`: under 1,
+    /// faster than real time
+    pub realtime_factor: Option<f64>,
+    /// None where there was no cache to ask
+    pub cache_hit: Option<bool>,
+    /// Times playback ran dry before the end; None where nothing played
+    pub underruns: Option<u64>,
+    /// Seconds of audio that came per second from the first byte to the
+    /// last: over 1, synthesis was ahead of playback
+    pub synthesis_speed: Option<f64>,
+    /// Time playback spent waiting for audio; None where nothing played
+    pub stall_ms: Option<u64>,
+    /// How far the stream was ahead of playback while both went, at
+    /// least, at the median and at most; None where that wasn't sampled
+    pub lead_min_ms: Option<u64>,
+    pub lead_median_ms: Option<u64>,
+    pub lead_max_ms: Option<u64>,
+    /// Parts the text was read in
+    pub chunks: u64,
+    /// The daemon's base URL; None for audio from elsewhere, a file say
+    pub endpoint: Option<String>,
+}
+
+impl SynthesisStats {
+    /// `bytes` of audio in `format`, the first arriving at `first` and the
+    /// last at `last`, timed from `start`: one part, from nowhere in
+    /// particular, with nothing played.
+    pub fn of_audio(format: WavFormat, bytes: u64, start: Instant, first: Option<Instant>, last: Option<Instant>) -> SynthesisStats {
+        let ms = |at: Instant| at.saturating_duration_since(start).as_millis() as u64;
+        let per_sec = format.sample_rate as u64 * format.channels as u64 * format.bits_per_sample.div_ceil(8) as u64;
+        let audio_secs = first.and((per_sec > 0).then(|| thousandths(bytes as f64 / per_sec as f64)));
+        let realtime_factor = match (last, audio_secs) {
+            (Some(last), Some(secs)) if secs > 0.0 => Some(thousandths(last.saturating_duration_since(start).as_secs_f64() / secs)),
+            _ => None,
+        };
+        let synthesis_speed = match (first, last, audio_secs) {
+            (Some(first), Some(last), Some(secs)) if last > first => Some(thousandths(secs / last.duration_since(first).as_secs_f64())),
+            _ => None,
+        };
+        SynthesisStats {
+            first_byte_ms: first.map(ms),
+            first_sample_ms: first.map(ms),
+            bytes,
+            audio_secs,
+            realtime_factor,
+            synthesis_speed,
+            chunks: 1,
+            ..SynthesisStats::default()
+        }
+    }
+
+    /// What `buffer` saw of audio in `format`, timed from `start`.
+    pub fn of(buffer: &LockFreeBuffer, format: WavFormat, start: Instant) -> SynthesisStats {
+        let leads = buffer.leads();
+        let lead = |pick: fn((Duration, Duration, Duration)) -> Duration| leads.map(|l| pick(l).as_millis() as u64);
+        SynthesisStats {
+            first_sample_ms: buffer.playing().map(|at| at.saturating_duration_since(start).as_millis() as u64),
+            underruns: Some(buffer.underruns()),
+            stall_ms: buffer.playing().map(|_| buffer.stalled().as_millis() as u64),
+            lead_min_ms: lead(|(min, _, _)| min),
+            lead_median_ms: lead(|(_, median, _)| median),
+            lead_max_ms: lead(|(_, _, max)| max),
+            ..SynthesisStats::of_audio(format, buffer.received(), start, buffer.first(), buffer.ended())
+        }
+    }
+
+    /// Each field with its value, in order: what [`to_json`](Self::to_json)
+    /// makes an object of, for a caller to put among fields of its own.
+    pub fn fields(&self) -> Vec<(&'static str, Value)> {
+        let optional = |v: Option<Value>| v.unwrap_or(Value::Null);
+        vec![
+            ("first_byte_ms", optional(self.first_byte_ms.map(Value::from))),
+            ("first_sample_ms", optional(self.first_sample_ms.map(Value::from))),
+            ("bytes", self.bytes.into()),
+            ("audio_secs", optional(self.audio_secs.map(Value::from))),
+            ("realtime_factor", optional(self.realtime_factor.map(Value::from))),
+            ("cache_hit", optional(self.cache_hit.map(Value::from))),
+            ("underruns", optional(self.underruns.map(Value::from))),
+            ("synthesis_speed", optional(self.synthesis_speed.map(Value::from))),
+            ("stall_ms", optional(self.stall_ms.map(Value::from))),
+            ("lead_min_ms", optional(self.lead_min_ms.map(Value::from))),
+            ("lead_median_ms", optional(self.lead_median_ms.map(Value::from))),
+            ("lead_max_ms", optional(self.lead_max_ms.map(Value::from))),
+            ("chunks", self.chunks.into()),
+            ("endpoint", optional(self.endpoint.as_deref().map(Value::from))),
+        ]
+    }
+
+    pub fn to_json(&self) -> Value {
+        Value::Object(self.fields().into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+
+    /// Whether synthesis kept ahead of playback, in a line: "synthesis
+    /// 3.2x realtime, never stalled, min lead 140ms". None if there's
+    /// nothing to say.
+    pub fn pace(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if let Some(speed) = self.synthesis_speed {
+            parts.push(format!("synthesis {:.1}x realtime", speed));
+        }
+        match self.stall_ms {
+            Some(0) => parts.push("never stalled".to_string()),
+            Some(ms) => parts.push(format!("stalled {}ms", ms)),
+            None => {}
+        }
+        if let Some(ms) = self.lead_min_ms {
+            parts.push(format!("min lead {}ms", ms));
+        }
+        (!parts.is_empty()).then(|| parts.join(", "))
+    }
+}
+
+/// "first byte 95ms, first sample 180ms, 60000 bytes, 1.250s of audio,
+/// RTF 0.412, 0 underruns, 1 chunk, cache miss, from http://127.0.0.1:7125"
+impl fmt::Display for SynthesisStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |ms: Option<u64>| ms.map_or("-".to_string(), |ms| format!("{}ms", ms));
+        write!(f, "first byte {}, first sample {}, {} bytes", ms(self.first_byte_ms), ms(self.first_sample_ms), self.bytes)?;
+        if let Some(secs) = self.audio_secs {
+            write!(f, ", {:.3}s of audio", secs)?;
+        }
+        if let Some(rtf) = self.realtime_factor {
+            write!(f, ", RTF {:.3}", rtf)?;
+        }
+        if let Some(underruns) = self.underruns {
+            write!(f, ", {} underruns", underruns)?;
+        }
+        write!(f, ", {} chunk{}", self.chunks, if self.chunks == 1 { "" } else { "s" })?;
+        if let Some(hit) = self.cache_hit {
+            write!(f, ", cache {}", if hit { "hit" } else { "miss" })?;
+        }
+        if let Some(endpoint) = &self.endpoint {
+            write!(f, ", from {}", endpoint)?;
+        }
+        Ok(())
+    }
+}
+
+fn thousandths(n: f64) -> f64 {
+    (n * 1000.0).round() / 1000.0
+}
+
+/// Counts a stream's bytes as they're read, for its [`SynthesisStats`]
+/// once it has ended
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub(crate) struct Meter {
+    started: Instant,
+    /// The stream so far, until its header has been read or shown not to be one
+    header: Option<Vec<u8>>,
+    format: Option<WavFormat>,
+    bytes: u64,
+    first_byte: Option<Instant>,
+    first_sample: Option<Instant>,
+    ended: Option<Instant>,
+}
+
+#[cfg(any(feature = "blocking", feature = "async"))]
+impl Meter {
+    /// A stream requested at `started`.
+    pub(crate) fn new(started: Instant) -> Meter {
+        Meter {
+            started,
+            header: Some(Vec::new()),
+            format: None,
+            bytes: 0,
+            first_byte: None,
+            first_sample: None,
+            ended: None,
+        }
+    }
+
+    /// `read`, just read from the stream.
+    pub(crate) fn read(&mut self, read: &[u8]) {
+        if read.is_empty() {
+            return;
+        }
+        let now = Instant::now();
+        self.first_byte.get_or_insert(now);
+        let audio = match &mut self.header {
+            None => read.len(),
+            Some(header) => {
+                header.extend_from_slice(read);
+                match crate::wav::parse_header(header) {
+                    Ok(None) => 0,
+                    Ok(Some((format, consumed))) => {
+                        self.format = Some(format);
+                        header.len() - consumed as usize
+                    }
+                    // Not WAV: the bytes are counted, with no duration
+                    Err(_) => header.len(),
+                }
+            }
+        };
+        if audio > 0 {
+            self.header = None;
+            self.first_sample.get_or_insert(now);
+            self.bytes += audio as u64;
+        }
+    }
+
+    /// Note that the stream has ended, all of it read.
+    pub(crate) fn end(&mut self) {
+        self.ended.get_or_insert_with(Instant::now);
+    }
+
+    /// The stream's stats, once it has ended, without a cache or daemon
+    /// to speak of.
+    pub(crate) fn stats(&self) -> Option<SynthesisStats> {
+        let ended = self.ended?;
+        let stats = match self.format {
+            Some(format) => SynthesisStats::of_audio(format, self.bytes, self.started, self.first_byte, Some(ended)),
+            None => SynthesisStats { bytes: self.bytes, chunks: 1, ..SynthesisStats::default() },
+        };
+        Some(SynthesisStats {
+            first_byte_ms: self.first_byte.map(|at| at.saturating_duration_since(self.started).as_millis() as u64),
+            first_sample_ms: self.first_sample.map(|at| at.saturating_duration_since(self.started).as_millis() as u64),
+            ..stats
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times_and_sizes_the_audio() {
+        let start = Instant::now();
+        let mono = WavFormat { channels: 1, sample_rate: 24000, bits_per_sample: 16 };
+        let (first, last) = (start + Duration::from_millis(95), start + Duration::from_millis(500));
+        let stats = SynthesisStats::of_audio(mono, 60000, start, Some(first), Some(last));
+        assert_eq!((stats.first_byte_ms, stats.audio_secs, stats.realtime_factor, stats.chunks), (Some(95), Some(1.25), Some(0.4), 1));
+        assert_eq!(stats.to_string(), "first byte 95ms, first sample 95ms, 60000 bytes, 1.250s of audio, RTF 0.400, 1 chunk");
+        assert_eq!(SynthesisStats::of_audio(mono, 0, start, None, None).audio_secs, None);
+        assert_eq!(stats.synthesis_speed, Some(3.086));
+        let played = SynthesisStats { stall_ms: Some(0), lead_min_ms: Some(140), ..stats };
+        assert_eq!(played.pace().as_deref(), Some("synthesis 3.1x realtime, never stalled, min lead 140ms"));
+        assert_eq!(SynthesisStats::default().pace(), None);
+    }
+
+    #[cfg(any(feature = "blocking", feature = "async"))]
+    #[test]
+    fn meters_a_stream_once_it_has_ended() {
+        let mono = WavFormat { channels: 1, sample_rate: 24000, bits_per_sample: 16 };
+        let mut meter = Meter::new(Instant::now());
+        let mut stream = Vec::new();
+        crate::wav::write_header(&mut stream, mono, 4).unwrap();
+        stream.extend([0; 4]);
+        for part in stream.chunks(40) {
+            meter.read(part);
+        }
+        assert_eq!(meter.stats(), None);
+        meter.end();
+        let stats = meter.stats().unwrap();
+        assert_eq!((stats.bytes, stats.audio_secs, stats.endpoint.as_deref()), (4, Some(0.0), None));
+        assert_eq!(stats.to_json().get("chunks"), Some(&Value::from(1u64)));
+    }
+}
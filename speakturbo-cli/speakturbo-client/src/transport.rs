@@ -0,0 +1,216 @@
+//! What the blocking and async clients share, whatever carries their
+//! requests: the daemon's URLs, what a response's headers say about it,
+//! and what /health's and /capabilities' bodies mean. Each client is HTTP
+//! around these.
+//!
+//! The blocking client reaches the daemon through a [`Transport`]: ureq's
+//! by default, or one of the caller's, such as
+//! [`MockTransport`](crate::mock_daemon::MockTransport), which answers
+//! with crafted streams and no socket at all.
+
+// With neither client built, only the public types are used
+#![cfg_attr(not(any(feature = "blocking", feature = "async")), allow(dead_code))]
+
+use crate::{wav, SpeakRequest, SAMPLE_RATE};
+use crate::error::Result;
+use serde_json::{json, Value};
+use std::io::Read;
+
+/// What /health says
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+pub struct Health {
+    /// "ready" once the daemon will synthesize
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub status: String,
+    pub voices: Vec<String>,
+}
+
+/// What /capabilities says: the daemon's version, and what it takes
+/// beyond a GET /tts of a text and a voice. A daemon without the endpoint
+/// is a legacy one, with no version or features, and is sent requests as
+/// daemons always were.
+///
+/// The endpoint answers `{"version": "0.4.1", "features": ["post", "rate"]}`;
+/// a feature named like a request's field (style, rate, seed, ssml) says
+/// the daemon acts on it.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize), serde(default))]
+pub struct Capabilities {
+    /// None for a legacy daemon
+    pub version: Option<String>,
+    pub features: Vec<String>,
+}
+
+impl Capabilities {
+    /// Synthesis requests are taken as a JSON body, POSTed to /tts
+    pub const POST: &'static str = "post";
+
+    /// What a daemon without /capabilities is taken to have: nothing more.
+    pub fn legacy() -> Capabilities {
+        Capabilities::default()
+    }
+
+    pub fn is_legacy(&self) -> bool {
+        *self == Capabilities::legacy()
+    }
+
+    pub fn supports(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+
+    /// The fields `request` sets that the daemon doesn't list among its
+    /// features, and so would ignore; none for a legacy daemon, which
+    /// can't say.
+    pub fn unsupported<'a>(&self, request: &'a SpeakRequest) -> Vec<&'a str> {
+        if self.is_legacy() {
+            return Vec::new();
+        }
+        // Every field but the text and the voice
+        let optional = &crate::request::FIELDS[2..];
+        request.fields().into_iter().map(|(name, _)| name).filter(|name| optional.contains(name) && !self.supports(name)).collect()
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({"version": self.version, "features": self.features, "legacy": self.is_legacy()})
+    }
+}
+
+/// How a blocking client reaches the daemon. What's read from a
+/// [`Fetched`] body is all the WAV parsing and buffering ever see.
+pub trait Transport: Send + Sync {
+    /// Start synthesizing `request`. A daemon that refuses gives
+    /// [`SpeakError::DaemonError`]; one that can't be reached gives
+    /// [`SpeakError::DaemonUnreachable`].
+    fn fetch(&self, request: &SpeakRequest) -> Result<Fetched>;
+
+    /// /health's body.
+    fn health(&self) -> Result<String>;
+
+    /// /capabilities' body, or None from a daemon without the endpoint.
+    /// By default None: the daemon is a legacy one.
+    fn capabilities(&self) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// As [`fetch`](Transport::fetch), `request` POSTed as a JSON body, for
+    /// a daemon with [`Capabilities::POST`]. By default, as `fetch` sends it.
+    fn post(&self, request: &SpeakRequest) -> Result<Fetched> {
+        self.fetch(request)
+    }
+}
+
+/// A synthesis the daemon took: the response's head, and its body to
+/// read as it arrives, WAV header first
+pub struct Fetched {
+    pub head: ResponseHead,
+    pub body: Box<dyn Read + Send>,
+}
+
+/// The daemon's URLs, from its base
+#[derive(Clone, Debug)]
+pub(crate) struct Endpoint {
+    base: String,
+}
+
+impl Endpoint {
+    pub(crate) fn new(base_url: &str) -> Endpoint {
+        Endpoint { base: base_url.trim_end_matches('/').to_string() }
+    }
+
+    pub(crate) fn base(&self) -> &str {
+        &self.base
+    }
+
+    pub(crate) fn tts(&self, request: &SpeakRequest) -> String {
+        format!("{}/tts?{}", self.base, request.query())
+    }
+
+    /// Where a POSTed synthesis goes
+    pub(crate) fn tts_post(&self) -> String {
+        format!("{}/tts", self.base)
+    }
+
+    pub(crate) fn health(&self) -> String {
+        format!("{}/health", self.base)
+    }
+
+    pub(crate) fn capabilities(&self) -> String {
+        format!("{}/capabilities", self.base)
+    }
+}
+
+/// A synthesis response's status and what its headers say of its size
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ResponseHead {
+    pub status: u16,
+    pub content_length: Option<u64>,
+    /// The daemon's X-Audio-Duration hint, in seconds
+    pub duration: Option<f64>,
+}
+
+impl ResponseHead {
+    /// The head of a response with `status`, its headers looked up by `header`.
+    pub(crate) fn new<'a>(status: u16, header: impl Fn(&str) -> Option<&'a str>) -> ResponseHead {
+        ResponseHead {
+            status,
+            content_length: header("Content-Length").and_then(|v| v.trim().parse().ok()),
+            duration: header("X-Audio-Duration").and_then(|v| v.trim().parse().ok()),
+        }
+    }
+
+    /// Total response size, from Content-Length or the daemon's X-Audio-Duration hint.
+    pub fn expected_bytes(&self) -> Option<u64> {
+        self.content_length.or_else(|| Some(wav::HEADER_LEN + (self.duration? * SAMPLE_RATE as f64) as u64 * 2))
+    }
+}
+
+/// /health's `body`, fetched from `url`.
+#[cfg(feature = "serde")]
+pub(crate) fn health(url: &str, body: &str) -> Result<Health> {
+    serde_json::from_str(body).map_err(|e| crate::SpeakError::BadResponse { reason: format!("Unexpected /health from {}: {}", url, e) })
+}
+
+/// /capabilities' `body` from `url`; None, where the daemon hasn't the
+/// endpoint, for a legacy daemon.
+#[cfg(feature = "serde")]
+pub(crate) fn capabilities(url: &str, body: Option<&str>) -> Result<Capabilities> {
+    let Some(body) = body else {
+        return Ok(Capabilities::legacy());
+    };
+    serde_json::from_str(body).map_err(|e| crate::SpeakError::BadResponse { reason: format!("Unexpected /capabilities from {}: {}", url, e) })
+}
+
+/// Whether a daemon's error `status` for /capabilities means it hasn't
+/// the endpoint, rather than that it failed.
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub(crate) fn without_endpoint(status: u16) -> bool {
+    matches!(status, 404 | 405 | 501)
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_heads_and_health() {
+        let head = ResponseHead::new(200, |name| (name == "X-Audio-Duration").then_some(" 1.5"));
+        assert_eq!((head.content_length, head.expected_bytes()), (None, Some(44 + 72000)));
+        let body = r#"{"status":"ready","voices":["alba","jean"],"idle_timeout_mins":60}"#;
+        assert_eq!(health("u", body).unwrap(), Health { status: "ready".to_string(), voices: vec!["alba".to_string(), "jean".to_string()] });
+        assert_eq!(health("u", r#"{"status":"ready"}"#).unwrap_err().to_string(), "Unexpected /health from u: missing field `voices` at line 1 column 18");
+    }
+
+    #[test]
+    fn reads_capabilities_and_what_they_lack() {
+        let modern = capabilities("u", Some(r#"{"version":"0.4.1","features":["post","style"],"build":"x"}"#)).unwrap();
+        assert_eq!((modern.version.as_deref(), modern.supports(Capabilities::POST), modern.is_legacy()), (Some("0.4.1"), true, false));
+        let request = SpeakRequest::new("Hi").style("calm").rate(1.5).seed(3).param("rate_hint", "x").build().unwrap();
+        assert_eq!(modern.unsupported(&request), ["rate", "seed"]);
+        assert_eq!(modern.to_json().to_string(), r#"{"version":"0.4.1","features":["post","style"],"legacy":false}"#);
+
+        let legacy = capabilities("u", None).unwrap();
+        assert!(legacy.is_legacy() && legacy.unsupported(&request).is_empty());
+        assert_eq!(capabilities("u", Some(r#"{"features":"post"}"#)).unwrap_err().to_string(), "Unexpected /capabilities from u: invalid type: string \"post\", expected a sequence at line 1 column 18");
+    }
+}
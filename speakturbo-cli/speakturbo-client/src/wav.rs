@@ -0,0 +1,196 @@
+//! Minimal RIFF/WAVE handling for the daemon's 16-bit PCM streams.
+
+use crate::audio::{self, ChainedProcessor, Quality, Quantizer, Remix, Resampler};
+use crate::error::{Result, SpeakError};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// Size of the canonical header written by `write_header`
+pub const HEADER_LEN: u64 = 44;
+
+/// Placeholder data size for streams whose length isn't known up front
+pub const STREAMING_DATA_LEN: u32 = 0x7FFF_FFFF;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WavFormat {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub bits_per_sample: u16,
+}
+
+/// Parse a WAV header, leaving `reader` positioned at the first PCM byte.
+/// Returns the format and the number of header bytes consumed.
+pub fn read_header<R: Read>(reader: &mut R) -> Result<(WavFormat, u64)> {
+    read_header_or_end(reader)?.ok_or_else(|| SpeakError::bad_audio("Truncated WAV header"))
+}
+
+/// As `read_header`, but None if `reader` ends before the header does.
+fn read_header_or_end<R: Read>(reader: &mut R) -> Result<Option<(WavFormat, u64)>> {
+    let mut riff = [0u8; 12];
+    if !filled(reader, &mut riff)? {
+        return Ok(None);
+    }
+    if &riff[0..4] != b"RIFF" || &riff[8..12] != b"WAVE" {
+        return Err(SpeakError::bad_audio("Not a WAV stream"));
+    }
+
+    let mut consumed = 12u64;
+    let mut format = None;
+    loop {
+        let mut chunk = [0u8; 8];
+        if !filled(reader, &mut chunk)? {
+            return Ok(None);
+        }
+        consumed += 8;
+        let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as u64;
+
+        match &chunk[0..4] {
+            b"fmt " => {
+                // Padded to even length, like any chunk
+                let mut fmt = vec![0u8; (size + (size & 1)) as usize];
+                if !filled(reader, &mut fmt)? {
+                    return Ok(None);
+                }
+                consumed += fmt.len() as u64;
+                if fmt.len() < 16 {
+                    return Err(SpeakError::bad_audio("Malformed fmt chunk"));
+                }
+                format = Some(WavFormat {
+                    channels: u16::from_le_bytes([fmt[2], fmt[3]]),
+                    sample_rate: u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]),
+                    bits_per_sample: u16::from_le_bytes([fmt[14], fmt[15]]),
+                });
+            }
+            b"data" => {
+                let format = format.ok_or_else(|| SpeakError::bad_audio("WAV data chunk before fmt chunk"))?;
+                return Ok(Some((format, consumed)));
+            }
+            _ => {
+                // Skip unknown chunks (LIST, fact, ...), padded to even length
+                let skip = size + (size & 1);
+                io::copy(&mut reader.by_ref().take(skip), &mut io::sink()).map_err(SpeakError::reading)?;
+                consumed += skip;
+            }
+        }
+    }
+}
+
+/// The header at the start of `bytes`, as `read_header` reads it, or None
+/// if `bytes` ends before the header does.
+pub fn parse_header(bytes: &[u8]) -> Result<Option<(WavFormat, u64)>> {
+    Ok(read_header_or_end(&mut &bytes[..])?.filter(|&(_, consumed)| consumed <= bytes.len() as u64))
+}
+
+/// Fill `buf` from `reader`: false if it ends first.
+fn filled<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<bool> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(SpeakError::reading(e)),
+    }
+}
+
+/// Write a canonical 44-byte PCM header for `data_len` bytes of audio.
+pub fn write_header<W: Write>(writer: &mut W, format: WavFormat, data_len: u32) -> Result<()> {
+    let block_align = format.channels * format.bits_per_sample / 8;
+    let mut h = Vec::with_capacity(HEADER_LEN as usize);
+    h.extend_from_slice(b"RIFF");
+    h.extend_from_slice(&data_len.saturating_add(36).to_le_bytes());
+    h.extend_from_slice(b"WAVEfmt ");
+    h.extend_from_slice(&16u32.to_le_bytes());
+    h.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    h.extend_from_slice(&format.channels.to_le_bytes());
+    h.extend_from_slice(&format.sample_rate.to_le_bytes());
+    h.extend_from_slice(&(format.sample_rate * block_align as u32).to_le_bytes());
+    h.extend_from_slice(&block_align.to_le_bytes());
+    h.extend_from_slice(&format.bits_per_sample.to_le_bytes());
+    h.extend_from_slice(b"data");
+    h.extend_from_slice(&data_len.to_le_bytes());
+    writer.write_all(&h)?;
+    Ok(())
+}
+
+/// Rewrite the RIFF and data chunk sizes in place once the file is complete.
+/// The daemon streams placeholder sizes, so saved files need this to be seekable.
+pub fn patch_sizes<F: Write + Seek>(file: &mut F, data_offset: u64, data_len: u64) -> Result<()> {
+    let riff_len = u32::try_from(data_offset + data_len - 8).unwrap_or(u32::MAX);
+    let data_len = u32::try_from(data_len).unwrap_or(u32::MAX);
+    file.seek(SeekFrom::Start(4))?;
+    file.write_all(&riff_len.to_le_bytes())?;
+    file.seek(SeekFrom::Start(data_offset - 4))?;
+    file.write_all(&data_len.to_le_bytes())?;
+    file.seek(SeekFrom::End(0))?;
+    Ok(())
+}
+
+/// Decode a 16-bit PCM WAV, downmixed to mono and resampled to `rate`.
+pub fn decode<R: Read>(reader: &mut R, rate: u32) -> Result<Vec<i16>> {
+    let (format, _) = read_header(reader)?;
+    if format.bits_per_sample != 16 || format.channels == 0 || format.sample_rate == 0 {
+        let reason = format!("must be 16-bit PCM, got {}-bit/{}ch", format.bits_per_sample, format.channels);
+        return Err(SpeakError::BadAudio { reason });
+    }
+
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let channels = format.channels as usize;
+    let whole = bytes.len() - bytes.len() % (2 * channels);
+    let samples: Vec<i16> = bytes[..whole].chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect();
+    if samples.is_empty() {
+        return Err(SpeakError::bad_audio("no audio"));
+    }
+    // Linear interpolation; plenty for earcons and speech
+    let mut pipeline = Remix::new(channels, 1).then(Resampler::new(1, format.sample_rate, rate, Quality::Linear));
+    let (mut floats, mut mono, mut out) = (Vec::new(), Vec::new(), Vec::new());
+    audio::i16_to_f32(&samples, &mut floats);
+    pipeline.process(&floats, &mut mono);
+    pipeline.flush(&mut mono);
+    Quantizer::undithered().to_i16(&mono, &mut out);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_headers_as_they_arrive() {
+        let mut bytes = Vec::new();
+        write_header(&mut bytes, WavFormat { channels: 1, sample_rate: 24000, bits_per_sample: 16 }, STREAMING_DATA_LEN).unwrap();
+        bytes.extend([7, 0]);
+        assert_eq!(parse_header(&bytes[..30]).unwrap(), None);
+        assert_eq!(parse_header(&bytes).unwrap(), Some((WavFormat { channels: 1, sample_rate: 24000, bits_per_sample: 16 }, HEADER_LEN)));
+        assert_eq!(parse_header(b"<html><body>Not found").unwrap_err().to_string(), "Not a WAV stream");
+    }
+
+    #[test]
+    fn patches_streaming_sizes_to_the_data_written() {
+        let format = WavFormat { channels: 1, sample_rate: 24000, bits_per_sample: 16 };
+        let mut file = io::Cursor::new(Vec::new());
+        write_header(&mut file, format, STREAMING_DATA_LEN).unwrap();
+        file.write_all(&[1, 0, 2, 0, 3, 0]).unwrap();
+        patch_sizes(&mut file, HEADER_LEN, 6).unwrap();
+        let bytes = file.into_inner();
+        assert_eq!(bytes.len(), 50);
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()), 42, "RIFF size: the file less 8");
+        assert_eq!(u32::from_le_bytes(bytes[40..44].try_into().unwrap()), 6);
+        let mut reader = &bytes[..];
+        assert_eq!(read_header(&mut reader).unwrap(), (format, HEADER_LEN));
+        assert_eq!(reader, [1, 0, 2, 0, 3, 0]);
+    }
+
+    #[test]
+    fn skips_the_pad_byte_after_odd_sized_chunks() {
+        let mut bytes = b"RIFF\0\0\0\0WAVE".to_vec();
+        // A 19-byte fmt chunk, with a made-up odd extension, then a 3-byte LIST
+        bytes.extend(b"fmt \x13\0\0\0");
+        bytes.extend([1, 0, 2, 0, 0x80, 0xBB, 0, 0, 0, 0xEE, 2, 0, 4, 0, 16, 0, 1, 0, 9]);
+        bytes.push(0);
+        bytes.extend(b"LIST\x03\0\0\0abc\0");
+        bytes.extend(b"data\x02\0\0\0");
+        bytes.extend([5, 0]);
+        let mut reader = &bytes[..];
+        let format = WavFormat { channels: 2, sample_rate: 48000, bits_per_sample: 16 };
+        assert_eq!(read_header(&mut reader).unwrap(), (format, bytes.len() as u64 - 2));
+        assert_eq!(reader, [5, 0]);
+    }
+}
@@ -0,0 +1,58 @@
+//! The async client against the mock daemon.
+
+#![cfg(feature = "async")]
+
+use futures_core::Stream;
+use speakturbo_client::mock_daemon::{self, MockDaemon};
+use speakturbo_client::wav::WavFormat;
+use speakturbo_client::{AsyncSpeakClient, CancellationToken, PcmStream, SpeakError, SpeakRequest, SAMPLE_RATE, VOICES};
+use std::future::poll_fn;
+use std::pin::Pin;
+
+async fn next(pcm: &mut PcmStream) -> Option<Result<Vec<i16>, SpeakError>> {
+    poll_fn(|cx| Pin::new(&mut *pcm).poll_next(cx)).await
+}
+
+#[tokio::test]
+async fn streams_samples_and_lists_voices() {
+    let daemon = MockDaemon::start().unwrap();
+    let client = AsyncSpeakClient::new(&daemon.url());
+    assert_eq!(client.list_voices().await.unwrap(), VOICES);
+
+    let mut pcm = client.synthesize(&SpeakRequest::new("Hello there").build().unwrap()).await.unwrap();
+    assert_eq!(pcm.format(), WavFormat { channels: 1, sample_rate: SAMPLE_RATE, bits_per_sample: 16 });
+    let mut samples = Vec::new();
+    while let Some(chunk) = next(&mut pcm).await {
+        assert!(pcm.stats().is_none());
+        samples.extend(chunk.unwrap());
+    }
+    assert_eq!(samples, mock_daemon::sweep(mock_daemon::samples_for("Hello there")));
+    let stats = pcm.stats().unwrap();
+    assert_eq!((stats.bytes, stats.endpoint.as_deref()), (samples.len() as u64 * 2, Some(client.url())));
+}
+
+#[tokio::test]
+async fn surfaces_the_daemons_refusal() {
+    let daemon = MockDaemon::start().unwrap();
+    let client = AsyncSpeakClient::new(&daemon.url());
+    match client.synthesize(&SpeakRequest::new("Hi").voice("nobody").build().unwrap()).await {
+        Err(SpeakError::DaemonError { status: 400, message, .. }) => assert_eq!(message, "unknown voice nobody"),
+        other => panic!("{:?}", other.err()),
+    }
+    let unreachable = AsyncSpeakClient::new("http://127.0.0.1:9");
+    assert!(matches!(unreachable.list_voices().await, Err(SpeakError::DaemonUnreachable { .. })));
+}
+
+#[tokio::test]
+async fn ends_the_stream_once_cancelled() {
+    let daemon = MockDaemon::start().unwrap();
+    let client = AsyncSpeakClient::new(&daemon.url());
+    let request = SpeakRequest::new("Hello there").build().unwrap();
+    let cancel = CancellationToken::new();
+    let mut pcm = client.synthesize_cancellable(&request, &cancel).await.unwrap();
+    assert!(next(&mut pcm).await.unwrap().is_ok());
+    cancel.cancel();
+    assert!(matches!(next(&mut pcm).await, Some(Err(SpeakError::Cancelled))));
+    assert!(next(&mut pcm).await.is_none());
+    assert!(matches!(client.synthesize_cancellable(&request, &cancel).await, Err(SpeakError::Cancelled)));
+}
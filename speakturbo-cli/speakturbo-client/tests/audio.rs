@@ -0,0 +1,55 @@
+//! The audio stages, in steady state, allocate nothing.
+
+use speakturbo_client::audio::{self, ChainedProcessor, Quality, Quantizer, Remix, Resampler};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+/// The system allocator, counting this thread's allocations
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|n| n.set(n.get() + 1));
+        System.realloc(ptr, layout, size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static COUNTING: Counting = Counting;
+
+#[test]
+fn allocates_nothing_once_warmed_up() {
+    let mut pipeline = Remix::new(1, 2).then(Resampler::new(2, 24000, 44100, Quality::Sinc)).then(Remix::new(2, 1));
+    let mut quantizer = Quantizer::new();
+    let block: Vec<i16> = (0..480).map(|n| (n * 64) as i16).collect();
+    let (mut floats, mut out, mut pcm) = (Vec::new(), Vec::new(), Vec::new());
+    let mut step = || {
+        floats.clear();
+        out.clear();
+        pcm.clear();
+        audio::i16_to_f32(&block, &mut floats);
+        pipeline.process(&floats, &mut out);
+        quantizer.to_i16(&out, &mut pcm);
+    };
+    for _ in 0..4 {
+        step();
+    }
+    let before = ALLOCATIONS.with(Cell::get);
+    for _ in 0..100 {
+        step();
+    }
+    assert_eq!(ALLOCATIONS.with(Cell::get), before);
+}
@@ -0,0 +1,259 @@
+//! The client against the mock daemon, through its public API only.
+
+#![cfg(feature = "blocking")]
+
+use speakturbo_client::buffer::LockFreeBuffer;
+use speakturbo_client::cache::CacheConfig;
+use speakturbo_client::mock_daemon::{self, MockDaemon, MockTransport};
+use speakturbo_client::wav::{self, WavFormat};
+use speakturbo_client::{Capabilities, CancellationToken, Fetched, SpeakClient, SpeakError, SpeakRequest, Transport, SAMPLE_RATE, VOICES};
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[test]
+fn reports_health_and_voices() {
+    let daemon = MockDaemon::start().unwrap();
+    let client = SpeakClient::new(&format!("{}/", daemon.url()));
+    assert_eq!(client.url(), daemon.url());
+    let health = client.health().unwrap();
+    assert_eq!(health.status, "ready");
+    assert_eq!(client.list_voices().unwrap(), VOICES);
+}
+
+#[test]
+fn streams_a_synthesis_into_the_buffer() {
+    let daemon = MockDaemon::start().unwrap();
+    let client = SpeakClient::new(&daemon.url());
+    let mut audio = client.synthesize(&SpeakRequest::new("Hello there").build().unwrap()).unwrap();
+    assert_eq!((audio.status(), audio.expected_bytes()), (200, None));
+    let (format, consumed) = wav::read_header(&mut audio).unwrap();
+    assert_eq!((format, consumed), (WavFormat { channels: 1, sample_rate: SAMPLE_RATE, bits_per_sample: 16 }, wav::HEADER_LEN));
+
+    let buffer = LockFreeBuffer::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        match audio.read(&mut chunk).unwrap() {
+            0 => break,
+            n => buffer.push_bytes(&chunk[..n]),
+        }
+    }
+    buffer.set_done();
+    assert_eq!(buffer.pushed() as usize, mock_daemon::samples_for("Hello there"));
+    assert_eq!(buffer.pop(), Some(mock_daemon::sweep(1)[0]));
+}
+
+#[test]
+fn builds_requests_and_surfaces_errors() {
+    let daemon = MockDaemon::start().unwrap();
+    let client = SpeakClient::new(&daemon.url());
+    let request = SpeakRequest::new("<speak>Hi</speak>").ssml(true).param("speed", "1.2").build().unwrap();
+    assert_eq!(
+        client.request_url(&request),
+        format!("{}/tts?text=%3Cspeak%3EHi%3C%2Fspeak%3E&voice=alba&ssml=true&speed=1.2", daemon.url())
+    );
+
+    match client.synthesize(&SpeakRequest::new("Hi").voice("nobody").build().unwrap()) {
+        Err(error @ SpeakError::DaemonError { status: 400, .. }) => assert_eq!(error.to_string(), "The daemon answered 400: unknown voice nobody"),
+        other => panic!("{:?}", other.map(|audio| audio.status())),
+    }
+}
+
+#[test]
+fn tells_an_absent_daemon_from_bad_audio() {
+    let agent = ureq::AgentBuilder::new().timeout(Duration::from_millis(500)).build();
+    let unreachable = SpeakClient::with_agent("http://127.0.0.1:9", agent);
+    match unreachable.synthesize(&SpeakRequest::new("Hi").build().unwrap()) {
+        Err(SpeakError::DaemonUnreachable { url, source }) => {
+            assert_eq!(url, "http://127.0.0.1:9");
+            assert!(source.to_string().contains("Connection Failed"), "{}", source);
+        }
+        other => panic!("{:?}", other.map(|audio| audio.status())),
+    }
+    assert!(matches!(unreachable.list_voices(), Err(SpeakError::DaemonUnreachable { .. })));
+
+    let mut hi = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden/hi.wav")).unwrap();
+    hi.truncate(30);
+    match wav::read_header(&mut &hi[..]) {
+        Err(SpeakError::BadAudio { reason }) => assert_eq!(reason, "Truncated WAV header"),
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(wav::parse_header(&hi).unwrap(), None);
+}
+
+/// tests/golden/hi.wav is what the mock daemon says for "Hi"
+fn golden() -> (WavFormat, Vec<i16>) {
+    let mut file = std::fs::File::open(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden/hi.wav")).unwrap();
+    let (format, _) = wav::read_header(&mut file).unwrap();
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).unwrap();
+    (format, bytes.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect())
+}
+
+#[test]
+fn iterates_the_golden_samples() {
+    let daemon = MockDaemon::start().unwrap();
+    let client = SpeakClient::new(&daemon.url());
+    let request = SpeakRequest::new("Hi").build().unwrap();
+    let (format, golden) = golden();
+
+    let mut samples = client.synthesize(&request).unwrap().samples();
+    assert_eq!(samples.by_ref().collect::<Vec<_>>(), golden);
+    assert_eq!(samples.format(), Some(format));
+    assert!(samples.take_error().is_none());
+    let stats = samples.stats().unwrap();
+    assert_eq!((stats.bytes, stats.chunks, stats.cache_hit, stats.endpoint.as_deref()), (golden.len() as u64 * 2, 1, None, Some(client.url())));
+    assert!(stats.first_byte_ms <= stats.first_sample_ms && stats.audio_secs.is_some());
+
+    let frames: Vec<Vec<f32>> = client.synthesize(&request).unwrap().samples().frames_f32().collect();
+    let expected: Vec<Vec<f32>> = golden.iter().map(|&s| vec![s as f32 / 32768.0]).collect();
+    assert_eq!(frames, expected);
+}
+
+#[test]
+fn reads_crafted_streams_without_a_socket() {
+    let client = SpeakClient::with_transport("mock:", MockTransport::new());
+    let hi = SpeakRequest::new("Hi").build().unwrap();
+    assert_eq!(client.synthesize(&hi).unwrap().samples().collect::<Vec<_>>(), golden().1);
+    assert_eq!(client.list_voices().unwrap(), VOICES);
+    assert!(matches!(
+        client.synthesize(&SpeakRequest::new("Hi").voice("nobody").build().unwrap()),
+        Err(SpeakError::DaemonError { status: 400, .. })
+    ));
+
+    let mono = WavFormat { channels: 1, sample_rate: SAMPLE_RATE, bits_per_sample: 16 };
+    let mut three = Vec::new();
+    wav::write_header(&mut three, mono, 6).unwrap();
+    three.extend([1i16, -2, 300].iter().flat_map(|s| s.to_le_bytes()));
+    let read = |transport: MockTransport| {
+        let mut samples = SpeakClient::with_transport("mock:", transport).synthesize(&hi).unwrap().samples();
+        (samples.by_ref().collect::<Vec<_>>(), samples.take_error())
+    };
+
+    // Reads of three bytes split every other sample
+    assert!(matches!(read(MockTransport::answering(three.clone()).in_reads_of(3)), (s, None) if s == [1, -2, 300]));
+    assert!(matches!(read(MockTransport::answering(&three[..20])), (s, Some(SpeakError::BadAudio { .. })) if s.is_empty()));
+    assert!(matches!(read(MockTransport::answering(&three[..49])), (s, Some(SpeakError::BadAudio { .. })) if s == [1, -2]));
+    let reset = read(MockTransport::answering(three.clone()).then_failing(io::ErrorKind::ConnectionReset));
+    assert!(matches!(reset, (s, Some(SpeakError::Io(e))) if s == [1, -2, 300] && e.kind() == io::ErrorKind::ConnectionReset));
+    let stalled = read(MockTransport::answering(three).in_reads_of(1).then_failing(io::ErrorKind::TimedOut));
+    assert!(matches!(stalled, (_, Some(SpeakError::Timeout { .. }))));
+}
+
+/// A daemon that takes its time to answer
+struct Slow(MockTransport);
+
+impl Transport for Slow {
+    fn fetch(&self, request: &SpeakRequest) -> Result<Fetched, SpeakError> {
+        std::thread::sleep(Duration::from_secs(5));
+        self.0.fetch(request)
+    }
+
+    fn health(&self) -> Result<String, SpeakError> {
+        self.0.health()
+    }
+}
+
+#[test]
+fn cancels_before_during_and_after_a_synthesis() {
+    let hi = SpeakRequest::new("Hi").build().unwrap();
+    let client = SpeakClient::with_transport("mock:", MockTransport::new().in_reads_of(64));
+    let cancel = CancellationToken::new();
+    cancel.cancel();
+    assert!(matches!(client.synthesize_cancellable(&hi, &cancel), Err(SpeakError::Cancelled)));
+
+    // Before the first byte: the wait for the daemon ends at once
+    let (slow, cancel) = (SpeakClient::with_transport("mock:", Slow(MockTransport::new())), CancellationToken::new());
+    let canceller = cancel.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        canceller.cancel();
+    });
+    let start = Instant::now();
+    assert!(matches!(slow.synthesize_cancellable(&hi, &cancel), Err(SpeakError::Cancelled)));
+    assert!(start.elapsed() < Duration::from_secs(2));
+
+    // Mid-stream: what's already read is given out, and no more
+    let cancel = CancellationToken::new();
+    let mut samples = client.synthesize_cancellable(&hi, &cancel).unwrap().samples();
+    assert_eq!(samples.by_ref().take(10).count(), 10);
+    cancel.cancel();
+    assert!(samples.by_ref().count() < 32);
+    assert!(matches!(samples.take_error(), Some(SpeakError::Cancelled)));
+
+    // After the end, cancelling changes nothing
+    let cancel = CancellationToken::new();
+    let mut samples = client.synthesize_cancellable(&hi, &cancel).unwrap().samples();
+    assert_eq!(samples.by_ref().collect::<Vec<_>>(), golden().1);
+    cancel.cancel();
+    assert_eq!(samples.next(), None);
+    assert!(samples.take_error().is_none());
+}
+
+#[test]
+fn reads_through_and_writes_back_to_a_cache() {
+    let dir = std::env::temp_dir().join(format!("speakturbo-client-cache-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let client = SpeakClient::with_transport("mock:", MockTransport::new()).with_cache(CacheConfig::new(&dir));
+    let hi = SpeakRequest::new("Hi").build().unwrap();
+    let read = |audio: speakturbo_client::AudioStream| {
+        let (cached, mut samples) = (audio.is_cached(), audio.samples());
+        let read = samples.by_ref().collect::<Vec<_>>();
+        assert_eq!(samples.stats().unwrap().cache_hit, Some(cached));
+        (cached, read)
+    };
+    assert!(client.cached(&hi).is_none());
+    assert_eq!(read(client.synthesize(&hi).unwrap()), (false, golden().1));
+    assert_eq!(read(client.synthesize(&hi).unwrap()), (true, golden().1));
+    assert_eq!(read(client.synthesize_cancellable(&hi, &CancellationToken::new()).unwrap()), (true, golden().1));
+    // Another voice is another entry
+    assert!(!client.synthesize(&SpeakRequest::new("Hi").voice("jean").build().unwrap()).unwrap().is_cached());
+    let stats = client.cache_stats().unwrap();
+    assert_eq!((stats.memory_hits + stats.disk_hits, stats.misses, stats.stored), (2, 3, 1));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// A daemon with /capabilities that takes POST, counting what it's asked
+#[derive(Clone, Default)]
+struct Modern {
+    asked: Arc<AtomicUsize>,
+    posted: Arc<AtomicUsize>,
+}
+
+impl Transport for Modern {
+    fn fetch(&self, request: &SpeakRequest) -> Result<Fetched, SpeakError> {
+        MockTransport::new().fetch(request)
+    }
+
+    fn health(&self) -> Result<String, SpeakError> {
+        MockTransport::new().health()
+    }
+
+    fn capabilities(&self) -> Result<Option<String>, SpeakError> {
+        self.asked.fetch_add(1, Ordering::Relaxed);
+        Ok(Some(r#"{"version":"0.4.1","features":["post","style"]}"#.to_string()))
+    }
+
+    fn post(&self, request: &SpeakRequest) -> Result<Fetched, SpeakError> {
+        self.posted.fetch_add(1, Ordering::Relaxed);
+        self.fetch(request)
+    }
+}
+
+#[test]
+fn asks_for_capabilities_once_and_posts_when_it_can() {
+    let modern = Modern::default();
+    let client = SpeakClient::with_transport("mock:", modern.clone());
+    let hi = SpeakRequest::new("Hi").build().unwrap();
+    assert_eq!(client.synthesize(&hi).unwrap().samples().count(), golden().1.len());
+    assert!(client.clone().synthesize_cancellable(&hi, &CancellationToken::new()).is_ok());
+    assert_eq!((modern.asked.load(Ordering::Relaxed), modern.posted.load(Ordering::Relaxed)), (1, 2));
+    assert_eq!(client.capabilities().unwrap().version.as_deref(), Some("0.4.1"));
+
+    // Without the endpoint: legacy, and asked with GET as ever
+    let daemon = MockDaemon::start().unwrap();
+    let legacy = SpeakClient::new(&daemon.url());
+    assert_eq!(legacy.capabilities().unwrap(), Capabilities::legacy());
+    assert!(legacy.synthesize(&hi).is_ok());
+}
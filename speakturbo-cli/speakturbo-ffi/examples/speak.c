@@ -0,0 +1,56 @@
+/* Speaks a line through the C API and counts the samples that come back.
+ *
+ *   speak [URL [TEXT]]
+ *
+ * Build against the library from `cargo build -p speakturbo-ffi`:
+ *   cc examples/speak.c -Iinclude -L../target/debug -lspeakturbo_ffi -o speak
+ * and run with ../target/debug on the library path (LD_LIBRARY_PATH, or
+ * DYLD_LIBRARY_PATH on macOS).
+ */
+
+#include <stdio.h>
+#include <unistd.h>
+
+#include "speakturbo.h"
+
+int main(int argc, char **argv) {
+    const char *url = argc > 1 ? argv[1] : NULL;
+    const char *text = argc > 2 ? argv[2] : "Hello from C";
+
+    StClient *client = st_client_new(url);
+    if (client == NULL) {
+        fprintf(stderr, "error: %s\n", st_last_error());
+        return 1;
+    }
+    StOptions options = {.style = NULL, .rate = 1.0, .has_seed = true, .seed = 7};
+    StSpeech *speech = st_speak(client, text, "alba", &options);
+    if (speech == NULL) {
+        fprintf(stderr, "error: %s\n", st_last_error());
+        st_client_free(client);
+        return 1;
+    }
+
+    int16_t buf[1024];
+    long total = 0;
+    int status = 0;
+    for (;;) {
+        int64_t n = st_poll_samples(speech, buf, sizeof buf / sizeof buf[0]);
+        if (n == ST_END) {
+            break;
+        }
+        if (n < 0) {
+            fprintf(stderr, "error: %s\n", st_last_error());
+            status = 1;
+            break;
+        }
+        if (n == 0) {
+            usleep(1000); /* nothing has arrived yet */
+        }
+        total += (long)n;
+    }
+    printf("%ld samples at %d Hz\n", total, ST_SAMPLE_RATE);
+
+    st_speech_free(speech);
+    st_client_free(client);
+    return status;
+}
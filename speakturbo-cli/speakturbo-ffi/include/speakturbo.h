@@ -0,0 +1,126 @@
+/* Generated by cbindgen from speakturbo-ffi/src/lib.rs; don't edit. Regenerate with: UPDATE_HEADER=1 cargo test -p speakturbo-ffi --test header */
+
+#ifndef SPEAKTURBO_H
+#define SPEAKTURBO_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Samples per second. Samples are mono and signed 16-bit.
+#define ST_SAMPLE_RATE 24000
+
+#define ST_OK 0
+
+// From st_poll_samples: every sample has been taken
+#define ST_END -1
+
+// A NULL handle or string, or a string that isn't UTF-8
+#define ST_ERR_ARGUMENT -2
+
+// A request the daemon wouldn't take: empty text, a rate out of range, ...
+#define ST_ERR_REQUEST -3
+
+// No daemon at the client's URL
+#define ST_ERR_UNREACHABLE -4
+
+// The daemon answered with an error status
+#define ST_ERR_DAEMON -5
+
+// The stream wasn't 16-bit audio, or broke off
+#define ST_ERR_STREAM -6
+
+// The handle is in use on another thread
+#define ST_ERR_BUSY -7
+
+// A bug in the library; the message says where
+#define ST_ERR_PANIC -8
+
+// A daemon to synthesize with
+typedef struct StClient StClient;
+
+// A synthesis being read, its samples waiting for st_poll_samples
+typedef struct StSpeech StSpeech;
+
+// What st_speak may be told besides the text and voice. Pass NULL for
+// the daemon's defaults.
+typedef struct StOptions {
+  // A style, or NULL for none
+  const char *style;
+  // Speaking rate, 1.0 as the voice speaks; 0 for the daemon's
+  double rate;
+  // Whether `seed` is set
+  bool has_seed;
+  // The same seed says the same text the same way
+  uint64_t seed;
+} StOptions;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// A client for the daemon at `url`, or at the default URL if `url` is
+// NULL. Nothing is sent until st_speak. Free it with st_client_free.
+//
+// # Safety
+// `url` is NULL or a NUL-terminated string.
+struct StClient *st_client_new(const char *url);
+
+// Start synthesizing `text` in `voice` (NULL for the default voice).
+// This waits for the daemon to answer and the audio to begin; the rest
+// is read on a thread of the library's. Returns NULL on failure. Free
+// the synthesis with st_speech_free.
+//
+// # Safety
+// `client` is NULL or from st_client_new and not yet freed; `text` and
+// `voice` are NULL or NUL-terminated strings; `options` is NULL or
+// points to an StOptions.
+struct StSpeech *st_speak(const struct StClient *client,
+                          const char *text,
+                          const char *voice,
+                          const struct StOptions *options);
+
+// Copy up to `len` of the samples that have arrived into `buf`, without
+// waiting. Returns how many were copied, which is 0 when none have
+// arrived yet; ST_END once all have been taken (or after st_cancel); or
+// a negative ST_ERR_*. A stream that breaks off gives its samples up to
+// the break, then ST_ERR_STREAM.
+//
+// # Safety
+// `speech` is NULL or from st_speak and not yet freed; `buf` has room
+// for `len` samples.
+int64_t st_poll_samples(struct StSpeech *speech, int16_t *buf, size_t len);
+
+// Stop reading `speech`, closing its connection. Samples not yet taken
+// are dropped; st_poll_samples returns ST_END from here on.
+//
+// # Safety
+// `speech` is NULL or from st_speak and not yet freed.
+int32_t st_cancel(struct StSpeech *speech);
+
+// Cancel `speech` and free it. NULL is ignored.
+//
+// # Safety
+// `speech` is NULL or from st_speak, not yet freed, and in use on no
+// other thread.
+void st_speech_free(struct StSpeech *speech);
+
+// Free `client`. Syntheses started from it carry on. NULL is ignored.
+//
+// # Safety
+// `client` is NULL or from st_client_new, not yet freed, and in use on
+// no other thread.
+void st_client_free(struct StClient *client);
+
+// What the last failed call on this thread said, or NULL if none has
+// failed. The string is the library's, good until the next failure on
+// this thread; don't free it.
+const char *st_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SPEAKTURBO_H */
@@ -0,0 +1,415 @@
+//! A C ABI over the client library, for players that aren't Rust: a
+//! client, a synthesis started from it, and the synthesis's samples
+//! polled into a buffer of the caller's. `include/speakturbo.h` is
+//! generated from this file by cbindgen; tests/header.rs keeps it current.
+//!
+//! Ownership: what `st_client_new` and `st_speak` return belongs to the
+//! caller until it's given, once, to the matching free. Strings and
+//! options passed in are only borrowed for the call.
+//!
+//! Threads: a handle may move to another thread, but only one thread may
+//! use it at a time. A call that finds its handle in use elsewhere fails
+//! with `ST_ERR_BUSY` rather than race. (In Rust terms the handles are
+//! Send but not Sync.)
+//!
+//! Failures: a function that returns a handle returns NULL, and one that
+//! returns a number returns a negative `ST_ERR_*`. Either way
+//! `st_last_error` says what happened. Panics are caught at the boundary
+//! and come back as `ST_ERR_PANIC`, never unwinding into C.
+
+use speakturbo_client::buffer::LockFreeBuffer;
+use speakturbo_client::{wav, CancellationToken, SpeakClient, SpeakError, SpeakRequest, DEFAULT_URL, SAMPLE_RATE};
+use std::cell::{Cell, RefCell};
+use std::ffi::{c_char, CStr, CString};
+use std::io::Read;
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Samples per second. Samples are mono and signed 16-bit.
+pub const ST_SAMPLE_RATE: u32 = 24000;
+// Spelled out above for cbindgen, which can't see the library's
+const _: () = assert!(ST_SAMPLE_RATE == SAMPLE_RATE);
+
+pub const ST_OK: i32 = 0;
+/// From st_poll_samples: every sample has been taken
+pub const ST_END: i32 = -1;
+/// A NULL handle or string, or a string that isn't UTF-8
+pub const ST_ERR_ARGUMENT: i32 = -2;
+/// A request the daemon wouldn't take: empty text, a rate out of range, ...
+pub const ST_ERR_REQUEST: i32 = -3;
+/// No daemon at the client's URL
+pub const ST_ERR_UNREACHABLE: i32 = -4;
+/// The daemon answered with an error status
+pub const ST_ERR_DAEMON: i32 = -5;
+/// The stream wasn't 16-bit audio, or broke off
+pub const ST_ERR_STREAM: i32 = -6;
+/// The handle is in use on another thread
+pub const ST_ERR_BUSY: i32 = -7;
+/// A bug in the library; the message says where
+pub const ST_ERR_PANIC: i32 = -8;
+
+/// What st_speak may be told besides the text and voice. Pass NULL for
+/// the daemon's defaults.
+#[repr(C)]
+pub struct StOptions {
+    /// A style, or NULL for none
+    pub style: *const c_char,
+    /// Speaking rate, 1.0 as the voice speaks; 0 for the daemon's
+    pub rate: f64,
+    /// Whether `seed` is set
+    pub has_seed: bool,
+    /// The same seed says the same text the same way
+    pub seed: u64,
+}
+
+/// A daemon to synthesize with
+pub struct StClient {
+    client: SpeakClient,
+    in_use: InUse,
+}
+
+/// A synthesis being read, its samples waiting for st_poll_samples
+pub struct StSpeech {
+    buffer: Arc<LockFreeBuffer>,
+    cancel: CancellationToken,
+    in_use: InUse,
+}
+
+/// Set while a call has the handle. The Cell keeps handles from being
+/// Sync; the flag catches C sharing one anyway.
+#[derive(Default)]
+struct InUse {
+    flag: AtomicBool,
+    _unsync: PhantomData<Cell<()>>,
+}
+
+/// A call's hold on its handle, let go when dropped
+struct Entered<'a>(&'a AtomicBool);
+
+impl InUse {
+    fn enter(&self) -> Result<Entered<'_>, Failure> {
+        match self.flag.swap(true, Ordering::Acquire) {
+            true => Err(Failure::new(ST_ERR_BUSY, "The handle is in use on another thread")),
+            false => Ok(Entered(&self.flag)),
+        }
+    }
+}
+
+impl Drop for Entered<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+/// Why a call failed
+struct Failure {
+    code: i32,
+    message: String,
+}
+
+impl Failure {
+    fn new(code: i32, message: impl Into<String>) -> Failure {
+        Failure { code, message: message.into() }
+    }
+}
+
+impl From<SpeakError> for Failure {
+    fn from(error: SpeakError) -> Failure {
+        match error {
+            SpeakError::DaemonUnreachable { url, source } => Failure::new(ST_ERR_UNREACHABLE, format!("No daemon at {}: {}", url, source)),
+            SpeakError::Timeout { .. } => Failure::new(ST_ERR_UNREACHABLE, error.to_string()),
+            SpeakError::DaemonError { .. } => Failure::new(ST_ERR_DAEMON, error.to_string()),
+            SpeakError::Request(_) => Failure::new(ST_ERR_REQUEST, error.to_string()),
+            _ => Failure::new(ST_ERR_STREAM, error.to_string()),
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Run `f`, catching a panic. On failure the message is kept for
+/// st_last_error and `failed` turns the code into the return value.
+fn call<T>(failed: impl FnOnce(i32) -> T, f: impl FnOnce() -> Result<T, Failure>) -> T {
+    let failure = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => return value,
+        Ok(Err(failure)) => failure,
+        Err(payload) => {
+            let what = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Failure::new(ST_ERR_PANIC, format!("Panicked: {}", what))
+        }
+    };
+    // A message with a NUL in it is cut there
+    let message = failure.message.split('\0').next().unwrap_or_default().to_string();
+    LAST_ERROR.with(|last| *last.borrow_mut() = CString::new(message).ok());
+    failed(failure.code)
+}
+
+/// The UTF-8 string at `s`, or None for NULL.
+///
+/// # Safety
+/// `s` is NULL or a NUL-terminated string that outlives `'a`.
+unsafe fn string<'a>(s: *const c_char, name: &str) -> Result<Option<&'a str>, Failure> {
+    if s.is_null() {
+        return Ok(None);
+    }
+    CStr::from_ptr(s).to_str().map(Some).map_err(|_| Failure::new(ST_ERR_ARGUMENT, format!("The {} isn't UTF-8", name)))
+}
+
+/// # Safety
+/// `handle` is NULL or a live handle of this library's.
+unsafe fn handle<'a, T>(handle: *const T, name: &str) -> Result<&'a T, Failure> {
+    handle.as_ref().ok_or_else(|| Failure::new(ST_ERR_ARGUMENT, format!("The {} is NULL", name)))
+}
+
+/// A client for the daemon at `url`, or at the default URL if `url` is
+/// NULL. Nothing is sent until st_speak. Free it with st_client_free.
+///
+/// # Safety
+/// `url` is NULL or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn st_client_new(url: *const c_char) -> *mut StClient {
+    call(
+        |_| std::ptr::null_mut(),
+        || {
+            let url = string(url, "URL")?.unwrap_or(DEFAULT_URL);
+            Ok(Box::into_raw(Box::new(StClient { client: SpeakClient::new(url), in_use: InUse::default() })))
+        },
+    )
+}
+
+/// Start synthesizing `text` in `voice` (NULL for the default voice).
+/// This waits for the daemon to answer and the audio to begin; the rest
+/// is read on a thread of the library's. Returns NULL on failure. Free
+/// the synthesis with st_speech_free.
+///
+/// # Safety
+/// `client` is NULL or from st_client_new and not yet freed; `text` and
+/// `voice` are NULL or NUL-terminated strings; `options` is NULL or
+/// points to an StOptions.
+#[no_mangle]
+pub unsafe extern "C" fn st_speak(
+    client: *const StClient,
+    text: *const c_char,
+    voice: *const c_char,
+    options: *const StOptions,
+) -> *mut StSpeech {
+    call(
+        |_| std::ptr::null_mut(),
+        || {
+            let client = handle(client, "client")?;
+            let _entered = client.in_use.enter()?;
+            let text = string(text, "text")?.ok_or_else(|| Failure::new(ST_ERR_ARGUMENT, "The text is NULL"))?;
+            let mut request = SpeakRequest::new(text);
+            if let Some(voice) = string(voice, "voice")? {
+                request = request.voice(voice);
+            }
+            if let Some(options) = options.as_ref() {
+                if let Some(style) = string(options.style, "style")? {
+                    request = request.style(style);
+                }
+                if options.rate != 0.0 {
+                    request = request.rate(options.rate);
+                }
+                if options.has_seed {
+                    request = request.seed(options.seed);
+                }
+            }
+            let request = request.build().map_err(SpeakError::from)?;
+            let mut audio = client.client.synthesize(&request)?;
+            let (format, _) = wav::read_header(&mut audio)?;
+            if format.bits_per_sample != 16 || format.channels != 1 {
+                return Err(Failure::new(ST_ERR_STREAM, format!("Unexpected audio format from the daemon: {:?}", format)));
+            }
+
+            let buffer = Arc::new(LockFreeBuffer::new());
+            let (feed, cancel) = (Arc::clone(&buffer), audio.cancellation().clone());
+            std::thread::Builder::new()
+                .name("speakturbo-reader".into())
+                .spawn(move || {
+                    let mut chunk = [0u8; 4096];
+                    while !audio.cancellation().is_cancelled() {
+                        match audio.read(&mut chunk) {
+                            Ok(0) => break,
+                            Ok(n) => feed.push_bytes(&chunk[..n]),
+                            Err(e) => return feed.fail(e.to_string()),
+                        }
+                    }
+                    feed.set_done();
+                })
+                .map_err(|e| Failure::new(ST_ERR_STREAM, e.to_string()))?;
+            Ok(Box::into_raw(Box::new(StSpeech { buffer, cancel, in_use: InUse::default() })))
+        },
+    )
+}
+
+/// Copy up to `len` of the samples that have arrived into `buf`, without
+/// waiting. Returns how many were copied, which is 0 when none have
+/// arrived yet; ST_END once all have been taken (or after st_cancel); or
+/// a negative ST_ERR_*. A stream that breaks off gives its samples up to
+/// the break, then ST_ERR_STREAM.
+///
+/// # Safety
+/// `speech` is NULL or from st_speak and not yet freed; `buf` has room
+/// for `len` samples.
+#[no_mangle]
+pub unsafe extern "C" fn st_poll_samples(speech: *mut StSpeech, buf: *mut i16, len: usize) -> i64 {
+    call(
+        |code| code as i64,
+        || {
+            let speech = handle(speech, "speech")?;
+            let _entered = speech.in_use.enter()?;
+            if buf.is_null() && len > 0 {
+                return Err(Failure::new(ST_ERR_ARGUMENT, "The buffer is NULL"));
+            }
+            if speech.cancel.is_cancelled() {
+                return Ok(ST_END as i64);
+            }
+            let mut copied = 0;
+            while copied < len {
+                let Some(sample) = speech.buffer.pop() else { break };
+                buf.add(copied).write(sample);
+                copied += 1;
+            }
+            // Done is set after the last push, so an empty buffer then is the end
+            if copied == 0 && len > 0 && speech.buffer.is_done() && speech.buffer.is_empty() {
+                return match speech.buffer.error() {
+                    Some(error) => Err(Failure::new(ST_ERR_STREAM, error)),
+                    None => Ok(ST_END as i64),
+                };
+            }
+            Ok(copied as i64)
+        },
+    )
+}
+
+/// Stop reading `speech`, closing its connection. Samples not yet taken
+/// are dropped; st_poll_samples returns ST_END from here on.
+///
+/// # Safety
+/// `speech` is NULL or from st_speak and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn st_cancel(speech: *mut StSpeech) -> i32 {
+    call(
+        |code| code,
+        || {
+            let speech = handle(speech, "speech")?;
+            let _entered = speech.in_use.enter()?;
+            speech.cancel.cancel();
+            Ok(ST_OK)
+        },
+    )
+}
+
+/// Cancel `speech` and free it. NULL is ignored.
+///
+/// # Safety
+/// `speech` is NULL or from st_speak, not yet freed, and in use on no
+/// other thread.
+#[no_mangle]
+pub unsafe extern "C" fn st_speech_free(speech: *mut StSpeech) {
+    call(
+        |_| (),
+        || {
+            if !speech.is_null() {
+                let speech = Box::from_raw(speech);
+                speech.cancel.cancel();
+            }
+            Ok(())
+        },
+    )
+}
+
+/// Free `client`. Syntheses started from it carry on. NULL is ignored.
+///
+/// # Safety
+/// `client` is NULL or from st_client_new, not yet freed, and in use on
+/// no other thread.
+#[no_mangle]
+pub unsafe extern "C" fn st_client_free(client: *mut StClient) {
+    call(
+        |_| (),
+        || {
+            if !client.is_null() {
+                drop(Box::from_raw(client));
+            }
+            Ok(())
+        },
+    )
+}
+
+/// What the last failed call on this thread said, or NULL if none has
+/// failed. The string is the library's, good until the next failure on
+/// this thread; don't free it.
+#[no_mangle]
+pub extern "C" fn st_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(std::ptr::null(), |message| message.as_ptr()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use speakturbo_client::mock_daemon::{self, MockDaemon};
+    use std::time::{Duration, Instant};
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(st_last_error()) }.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn speaks_and_polls_to_the_end() {
+        let daemon = MockDaemon::start().unwrap();
+        let url = CString::new(daemon.url()).unwrap();
+        unsafe {
+            let client = st_client_new(url.as_ptr());
+            let speech = st_speak(client, c"Hello there".as_ptr(), c"jean".as_ptr(), std::ptr::null());
+            assert!(!speech.is_null(), "{}", last_error());
+            let (mut samples, mut buf) = (Vec::new(), [0i16; 1000]);
+            let start = Instant::now();
+            loop {
+                match st_poll_samples(speech, buf.as_mut_ptr(), buf.len()) {
+                    n if n == ST_END as i64 => break,
+                    n if n < 0 => panic!("{}", last_error()),
+                    n => samples.extend_from_slice(&buf[..n as usize]),
+                }
+                assert!(start.elapsed() < Duration::from_secs(5));
+            }
+            assert_eq!(samples, mock_daemon::sweep(mock_daemon::samples_for("Hello there")));
+            assert_eq!(st_cancel(speech), ST_OK);
+            st_speech_free(speech);
+            st_client_free(client);
+        }
+    }
+
+    #[test]
+    fn failures_are_codes_and_messages() {
+        let daemon = MockDaemon::start().unwrap();
+        let url = CString::new(daemon.url()).unwrap();
+        unsafe {
+            assert_eq!(st_poll_samples(std::ptr::null_mut(), std::ptr::null_mut(), 0), ST_ERR_ARGUMENT as i64);
+            assert_eq!(last_error(), "The speech is NULL");
+            let client = st_client_new(url.as_ptr());
+            let options = StOptions { style: std::ptr::null(), rate: 9.0, has_seed: false, seed: 0 };
+            assert!(st_speak(client, c"Hi".as_ptr(), std::ptr::null(), &options).is_null());
+            assert_eq!(last_error(), "Rate 9 is outside 0.25-4");
+            assert!(st_speak(client, c"Hi".as_ptr(), c"nobody".as_ptr(), std::ptr::null()).is_null());
+            assert!(last_error().starts_with("The daemon answered 400"), "{}", last_error());
+
+            // A second thread's call while the first's is running
+            let _held = (*client).in_use.enter().ok();
+            assert!(st_speak(client, c"Hi".as_ptr(), std::ptr::null(), std::ptr::null()).is_null());
+            assert_eq!(last_error(), "The handle is in use on another thread");
+            drop(_held);
+            st_client_free(client);
+        }
+        assert_eq!(call(|code| code, || -> Result<i32, Failure> { panic!("boom") }), ST_ERR_PANIC);
+        assert_eq!(last_error(), "Panicked: boom");
+    }
+}
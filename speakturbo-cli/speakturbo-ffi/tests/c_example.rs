@@ -0,0 +1,40 @@
+//! examples/speak.c, compiled with the system's C compiler against the
+//! cdylib and run against the mock daemon.
+
+use speakturbo_client::mock_daemon::{self, MockDaemon};
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn the_c_example_speaks() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    // target/debug/deps, where cargo built the cdylib alongside this test
+    let lib_dir = std::env::current_exe().unwrap().parent().unwrap().to_path_buf();
+    let exe = Path::new(env!("CARGO_TARGET_TMPDIR")).join("speak");
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let compiled = Command::new(cc)
+        .arg(dir.join("examples/speak.c"))
+        .arg("-I")
+        .arg(dir.join("include"))
+        .arg("-L")
+        .arg(&lib_dir)
+        .arg("-lspeakturbo_ffi")
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg("-o")
+        .arg(&exe)
+        .status()
+        .unwrap();
+    assert!(compiled.success());
+
+    let daemon = MockDaemon::start().unwrap();
+    let output = Command::new(&exe).arg(daemon.url()).arg("Hello from C").output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let expected = format!("{} samples at 24000 Hz\n", mock_daemon::samples_for("Hello from C"));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+
+    let output = Command::new(&exe).arg("http://127.0.0.1:9").output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("error: No daemon at http://127.0.0.1:9"));
+}
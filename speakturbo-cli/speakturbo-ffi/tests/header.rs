@@ -0,0 +1,17 @@
+//! include/speakturbo.h is what cbindgen makes of the crate now.
+
+use std::path::Path;
+
+#[test]
+fn header_is_current() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let config = cbindgen::Config::from_file(dir.join("cbindgen.toml")).unwrap();
+    let mut generated = Vec::new();
+    cbindgen::generate_with_config(dir, config).unwrap().write(&mut generated);
+    let path = dir.join("include/speakturbo.h");
+    if std::env::var_os("UPDATE_HEADER").is_some() {
+        std::fs::write(&path, &generated).unwrap();
+    }
+    let committed = std::fs::read(&path).unwrap_or_default();
+    assert!(committed == generated, "{} is stale; regenerate it with UPDATE_HEADER=1", path.display());
+}
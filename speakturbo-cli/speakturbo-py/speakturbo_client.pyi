@@ -0,0 +1,43 @@
+"""A client for the speakturbo daemon: streaming synthesis, voices and playback."""
+
+import array
+from collections.abc import Iterator
+from typing import Literal, overload
+
+DEFAULT_URL: str
+SAMPLE_RATE: int
+VOICES: list[str]
+
+class SpeakError(Exception): ...
+class DaemonUnreachable(SpeakError): ...
+class DaemonError(SpeakError):
+    """args are (message, status)."""
+
+class Chunks(Iterator[array.array]):
+    def __iter__(self) -> Chunks: ...
+    def __next__(self) -> array.array: ...
+    def cancel(self) -> None: ...
+
+class Speak:
+    def __init__(self, url: str = ..., connect_timeout: float | None = None) -> None: ...
+    @property
+    def url(self) -> str: ...
+    @overload
+    def synthesize(self, text: str, voice: str | None = None, *, output: Literal["wav"] = "wav", **params: object) -> bytes: ...
+    @overload
+    def synthesize(self, text: str, voice: str | None = None, *, output: Literal["samples"], **params: object) -> array.array: ...
+    @overload
+    def synthesize(self, text: str, voice: str | None = None, *, output: Literal["chunks"], **params: object) -> Chunks: ...
+    def play(self, text: str, voice: str | None = None, **params: object) -> None: ...
+    def list_voices(self) -> list[str]: ...
+    def health(self) -> dict[str, object]: ...
+
+class MockDaemon:
+    def __init__(self) -> None: ...
+    @property
+    def url(self) -> str: ...
+    @staticmethod
+    def samples_for(text: str) -> int: ...
+    def close(self) -> None: ...
+    def __enter__(self) -> MockDaemon: ...
+    def __exit__(self, *exc: object) -> None: ...
@@ -0,0 +1,395 @@
+//! Python bindings for the client library, built into the
+//! `speakturbo_client` module by maturin (`maturin build` here):
+//!
+//! ```python
+//! from speakturbo_client import Speak
+//!
+//! speak = Speak()
+//! for chunk in speak.synthesize("Hello there", voice="alba", output="chunks"):
+//!     ...  # an array('h') of samples at SAMPLE_RATE
+//! ```
+//!
+//! The network is read on threads of the library's. Python waits for
+//! them with the GIL released, a slice at a time, and checks for Ctrl-C
+//! between slices; a KeyboardInterrupt cancels the synthesis, closing its
+//! connection.
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyTuple};
+use rodio::{OutputStream, Sink};
+use speakturbo_client::events::{Events, SpeakEvent};
+use speakturbo_client::source::{SourceOptions, SpeechSource};
+use speakturbo_client::{mock_daemon, wav, SpeakClient, SpeakRequest, DEFAULT_URL, SAMPLE_RATE, VOICES};
+use std::io::Read;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Mutex;
+use std::time::Duration;
+
+create_exception!(speakturbo_client, SpeakError, PyException, "A synthesis failed.");
+create_exception!(speakturbo_client, DaemonUnreachable, SpeakError, "No daemon answered at the client's URL.");
+create_exception!(
+    speakturbo_client,
+    DaemonError,
+    SpeakError,
+    "The daemon answered with an error status; args are (message, status)."
+);
+
+/// How long Python waits without the GIL before checking for Ctrl-C
+const SLICE: Duration = Duration::from_millis(50);
+/// Reads held for Python before the reader waits
+const AHEAD: usize = 16;
+
+/// Why a call failed, on its way to becoming an exception
+enum Failure {
+    Unreachable(String),
+    Daemon { status: u16, message: String },
+    Stream(String),
+}
+
+impl From<speakturbo_client::SpeakError> for Failure {
+    fn from(error: speakturbo_client::SpeakError) -> Failure {
+        use speakturbo_client::SpeakError as Error;
+        match error {
+            Error::DaemonUnreachable { url, source } => Failure::Unreachable(format!("No daemon at {}: {}", url, source)),
+            Error::Timeout { .. } => Failure::Unreachable(error.to_string()),
+            Error::DaemonError { status, .. } => Failure::Daemon { status, message: error.to_string() },
+            _ => Failure::Stream(error.to_string()),
+        }
+    }
+}
+
+impl From<Failure> for PyErr {
+    fn from(failure: Failure) -> PyErr {
+        match failure {
+            Failure::Unreachable(message) => DaemonUnreachable::new_err(message),
+            Failure::Daemon { status, message } => DaemonError::new_err((message, status)),
+            Failure::Stream(message) => SpeakError::new_err(message),
+        }
+    }
+}
+
+/// The next of `receiver`'s messages, None once its sender has gone.
+/// Ctrl-C during the wait raises KeyboardInterrupt.
+fn receive<T: Send>(py: Python<'_>, receiver: &Mutex<Receiver<T>>) -> PyResult<Option<T>> {
+    loop {
+        match py.allow_threads(|| receiver.lock().unwrap().recv_timeout(SLICE)) {
+            Ok(message) => return Ok(Some(message)),
+            Err(RecvTimeoutError::Timeout) => py.check_signals()?,
+            Err(RecvTimeoutError::Disconnected) => return Ok(None),
+        }
+    }
+}
+
+/// What `f` returns, run on a thread of its own; Ctrl-C abandons it.
+fn interruptible<T: Send + 'static>(py: Python<'_>, f: impl FnOnce() -> T + Send + 'static) -> PyResult<T> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::Builder::new().name("speakturbo-call".into()).spawn(move || {
+        let _ = sender.send(f());
+    })?;
+    receive(py, &Mutex::new(receiver))?.ok_or_else(|| SpeakError::new_err("The call panicked"))
+}
+
+/// The stream's bytes as they're read, or why reading stopped
+type Message = Result<Vec<u8>, Failure>;
+
+/// Start `request` on a thread that sends its bytes back as they come.
+/// Dropping the receiver stops the thread, closing the connection.
+fn start(client: &SpeakClient, request: SpeakRequest) -> PyResult<Receiver<Message>> {
+    let (sender, receiver) = mpsc::sync_channel(AHEAD);
+    let client = client.clone();
+    std::thread::Builder::new().name("speakturbo-reader".into()).spawn(move || {
+        let mut audio = match client.synthesize(&request) {
+            Ok(audio) => audio,
+            Err(e) => {
+                let _ = sender.send(Err(e.into()));
+                return;
+            }
+        };
+        let mut chunk = [0u8; 4096];
+        loop {
+            let message = match audio.read(&mut chunk) {
+                Ok(0) => return,
+                Ok(n) => Ok(chunk[..n].to_vec()),
+                Err(e) => Err(Failure::Stream(e.to_string())),
+            };
+            let failed = message.is_err();
+            if sender.send(message).is_err() || failed {
+                return;
+            }
+        }
+    })?;
+    Ok(receiver)
+}
+
+/// `text` in `voice`, with the daemon's other parameters from `params`.
+fn request(text: &str, voice: Option<&str>, params: Option<&Bound<'_, PyDict>>) -> PyResult<SpeakRequest> {
+    let mut request = SpeakRequest::new(text);
+    if let Some(voice) = voice {
+        request = request.voice(voice);
+    }
+    for (name, value) in params.into_iter().flat_map(|params| params.iter()) {
+        let name: String = name.extract()?;
+        request = match name.as_str() {
+            "style" => request.style(value.extract::<String>()?),
+            "rate" => request.rate(value.extract()?),
+            "seed" => request.seed(value.extract()?),
+            _ => request.param(name, value.str()?.to_string()),
+        };
+    }
+    request.build().map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+/// `samples` as an `array('h')`, which numpy takes without a copy
+fn samples_array<'py>(py: Python<'py>, samples: &[i16]) -> PyResult<Bound<'py, PyAny>> {
+    let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_ne_bytes()).collect();
+    py.import("array")?.getattr("array")?.call1(("h", PyBytes::new(py, &bytes)))
+}
+
+/// A client for the speakturbo daemon.
+#[pyclass(module = "speakturbo_client", frozen)]
+struct Speak {
+    client: SpeakClient,
+}
+
+#[pymethods]
+impl Speak {
+    #[new]
+    #[pyo3(signature = (url = DEFAULT_URL, connect_timeout = None))]
+    fn new(url: &str, connect_timeout: Option<f64>) -> Speak {
+        let mut agent = ureq::AgentBuilder::new();
+        if let Some(seconds) = connect_timeout {
+            agent = agent.timeout_connect(Duration::from_secs_f64(seconds));
+        }
+        Speak { client: SpeakClient::with_agent(url, agent.build()) }
+    }
+
+    #[getter]
+    fn url(&self) -> &str {
+        self.client.url()
+    }
+
+    /// Synthesize `text`. `output` says how it comes back: "wav" for the
+    /// daemon's bytes, header and all; "samples" for an array('h') of the
+    /// whole; "chunks" for an iterator of arrays as they arrive. Other
+    /// keywords (style, rate, seed, ...) go to the daemon.
+    #[pyo3(signature = (text, voice = None, *, output = "wav", **params))]
+    fn synthesize(
+        &self,
+        py: Python<'_>,
+        text: &str,
+        voice: Option<&str>,
+        output: &str,
+        params: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Py<PyAny>> {
+        let request = request(text, voice, params)?;
+        match output {
+            "wav" => {
+                let receiver = Mutex::new(start(&self.client, request)?);
+                let mut bytes = Vec::new();
+                while let Some(read) = receive(py, &receiver)? {
+                    bytes.extend(read?);
+                }
+                Ok(PyBytes::new(py, &bytes).into_any().unbind())
+            }
+            "samples" => {
+                let mut chunks = Chunks::open(py, start(&self.client, request)?)?;
+                let mut samples = Vec::new();
+                while let Some(chunk) = chunks.next_samples(py)? {
+                    samples.extend(chunk);
+                }
+                Ok(samples_array(py, &samples)?.unbind())
+            }
+            "chunks" => Ok(Py::new(py, Chunks::open(py, start(&self.client, request)?)?)?.into_any()),
+            _ => Err(PyValueError::new_err(format!("output is \"wav\", \"samples\" or \"chunks\", not {:?}", output))),
+        }
+    }
+
+    /// Play `text` on the default audio output, returning once it has
+    /// been heard. Ctrl-C stops it.
+    #[pyo3(signature = (text, voice = None, **params))]
+    fn play(&self, py: Python<'_>, text: &str, voice: Option<&str>, params: Option<&Bound<'_, PyDict>>) -> PyResult<()> {
+        let request = request(text, voice, params)?;
+        let client = self.client.clone();
+        let (events, received) = Events::channel();
+        let options = SourceOptions { events, ..SourceOptions::default() };
+        let source = interruptible(py, move || -> Result<SpeechSource, Failure> {
+            let audio = client.synthesize(&request)?;
+            Ok(audio.into_rodio_source_with(options)?)
+        })??;
+        let (_stream, handle) = OutputStream::try_default().map_err(|e| SpeakError::new_err(format!("No audio output: {}", e)))?;
+        let sink = Sink::try_new(&handle).map_err(|e| SpeakError::new_err(format!("No audio output: {}", e)))?;
+        sink.append(source);
+        while !sink.empty() {
+            py.allow_threads(|| std::thread::sleep(SLICE));
+            if let Err(interrupted) = py.check_signals() {
+                sink.stop();
+                return Err(interrupted);
+            }
+        }
+        // A stream that broke off has played what it had
+        for event in received.try_iter() {
+            if let SpeakEvent::Error { message, .. } = event {
+                return Err(SpeakError::new_err(message));
+            }
+        }
+        Ok(())
+    }
+
+    /// The voices the daemon has.
+    fn list_voices(&self, py: Python<'_>) -> PyResult<Vec<String>> {
+        let client = self.client.clone();
+        Ok(interruptible(py, move || client.list_voices().map_err(Failure::from))??)
+    }
+
+    /// The daemon's /health: {"status": ..., "voices": [...]}.
+    fn health<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let client = self.client.clone();
+        let health = interruptible(py, move || client.health().map_err(Failure::from))??;
+        let dict = PyDict::new(py);
+        dict.set_item("status", health.status)?;
+        dict.set_item("voices", health.voices)?;
+        Ok(dict)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Speak({:?})", self.client.url())
+    }
+}
+
+/// A synthesis's samples as they arrive, each chunk an array('h').
+/// Each step waits for the next; cancel() or Ctrl-C ends it.
+#[pyclass(module = "speakturbo_client")]
+struct Chunks {
+    receiver: Mutex<Receiver<Message>>,
+    /// Bytes not yet given out: the first byte of a sample split between reads
+    pending: Vec<u8>,
+}
+
+impl Chunks {
+    /// Read as far as the end of the WAV header, so that the daemon's
+    /// refusal raises here rather than from the first step.
+    fn open(py: Python<'_>, receiver: Receiver<Message>) -> PyResult<Chunks> {
+        let mut chunks = Chunks { receiver: Mutex::new(receiver), pending: Vec::new() };
+        loop {
+            match chunks.receive(py)? {
+                Some(bytes) => chunks.pending.extend(bytes),
+                None => return Err(SpeakError::new_err("Truncated WAV header")),
+            }
+            let header = wav::parse_header(&chunks.pending).map_err(|e| SpeakError::new_err(e.to_string()))?;
+            if let Some((format, consumed)) = header {
+                if (format.channels, format.sample_rate, format.bits_per_sample) != (1, SAMPLE_RATE, 16) {
+                    return Err(SpeakError::new_err(format!("Unexpected audio format from the daemon: {:?}", format)));
+                }
+                chunks.pending.drain(..consumed as usize);
+                return Ok(chunks);
+            }
+        }
+    }
+
+    /// The next read, None at the end; Ctrl-C cancels.
+    fn receive(&mut self, py: Python<'_>) -> PyResult<Option<Vec<u8>>> {
+        match receive(py, &self.receiver) {
+            Ok(Some(read)) => Ok(Some(read?)),
+            Ok(None) => Ok(None),
+            Err(interrupted) => {
+                self.cancel();
+                Err(interrupted)
+            }
+        }
+    }
+
+    /// The next whole samples, None at the end.
+    fn next_samples(&mut self, py: Python<'_>) -> PyResult<Option<Vec<i16>>> {
+        while self.pending.len() < 2 {
+            match self.receive(py)? {
+                Some(bytes) => self.pending.extend(bytes),
+                None => return Ok(None),
+            }
+        }
+        let whole = self.pending.len() & !1;
+        let samples = self.pending[..whole].chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect();
+        self.pending.drain(..whole);
+        Ok(Some(samples))
+    }
+}
+
+#[pymethods]
+impl Chunks {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<Py<PyAny>>> {
+        match self.next_samples(py)? {
+            Some(samples) => Ok(Some(samples_array(py, &samples)?.unbind())),
+            None => Ok(None),
+        }
+    }
+
+    /// Stop the synthesis and close its connection; iteration ends.
+    fn cancel(&self) {
+        // A receiver whose sender is already gone reads as the end, and
+        // dropping the reader's receiver stops the reader
+        *self.receiver.lock().unwrap() = mpsc::sync_channel(0).1;
+    }
+}
+
+/// The library's stand-in daemon on a free local port, for tests: it
+/// streams a sine sweep for any text in VOICES' voices.
+#[pyclass(module = "speakturbo_client", frozen)]
+struct MockDaemon {
+    daemon: Mutex<Option<mock_daemon::MockDaemon>>,
+    url: String,
+}
+
+#[pymethods]
+impl MockDaemon {
+    #[new]
+    fn new() -> PyResult<MockDaemon> {
+        let daemon = mock_daemon::MockDaemon::start()?;
+        Ok(MockDaemon { url: daemon.url(), daemon: Mutex::new(Some(daemon)) })
+    }
+
+    #[getter]
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// How many samples the mock gives `text`.
+    #[staticmethod]
+    fn samples_for(text: &str) -> usize {
+        mock_daemon::samples_for(text)
+    }
+
+    fn close(&self) {
+        self.daemon.lock().unwrap().take();
+    }
+
+    fn __enter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    #[pyo3(signature = (*_exc))]
+    fn __exit__(&self, _exc: &Bound<'_, PyTuple>) {
+        self.close();
+    }
+}
+
+/// A client for the speakturbo daemon: streaming synthesis, voices and
+/// playback.
+#[pymodule]
+#[pyo3(name = "speakturbo_client")]
+fn speakturbo_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Speak>()?;
+    m.add_class::<Chunks>()?;
+    m.add_class::<MockDaemon>()?;
+    m.add("SpeakError", m.py().get_type::<SpeakError>())?;
+    m.add("DaemonUnreachable", m.py().get_type::<DaemonUnreachable>())?;
+    m.add("DaemonError", m.py().get_type::<DaemonError>())?;
+    m.add("DEFAULT_URL", DEFAULT_URL)?;
+    m.add("SAMPLE_RATE", SAMPLE_RATE)?;
+    m.add("VOICES", VOICES.to_vec())?;
+    Ok(())
+}
@@ -0,0 +1,90 @@
+"""
+Tests for the Python bindings, against the library's mock daemon.
+
+Run with: maturin develop && pytest tests/
+"""
+
+import _thread
+import array
+import io
+import socket
+import threading
+import time
+import wave
+
+import pytest
+
+from speakturbo_client import (
+    DaemonError,
+    DaemonUnreachable,
+    MockDaemon,
+    SAMPLE_RATE,
+    Speak,
+    VOICES,
+)
+
+
+@pytest.fixture
+def daemon():
+    with MockDaemon() as daemon:
+        yield daemon
+
+
+class TestSynthesize:
+    def test_wav_is_the_daemons_stream(self, daemon):
+        data = Speak(daemon.url).synthesize("Hello there", voice="jean")
+        with wave.open(io.BytesIO(data)) as wav:
+            assert (wav.getnchannels(), wav.getframerate(), wav.getsampwidth()) == (1, SAMPLE_RATE, 2)
+        assert len(data) == 44 + 2 * MockDaemon.samples_for("Hello there")
+
+    def test_samples_are_an_int16_buffer(self, daemon):
+        samples = Speak(daemon.url).synthesize("Hello there", output="samples", seed=7, rate=1.2)
+        assert isinstance(samples, array.array) and samples.typecode == "h"
+        assert memoryview(samples).format == "h"
+        assert len(samples) == MockDaemon.samples_for("Hello there")
+
+    def test_chunks_stream_the_same_samples(self, daemon):
+        speak = Speak(daemon.url)
+        whole = speak.synthesize("Hello there", output="samples")
+        chunks = list(speak.synthesize("Hello there", output="chunks"))
+        assert len(chunks) > 1
+        assert sum(chunks, array.array("h")) == whole
+
+    def test_cancel_ends_the_chunks(self, daemon):
+        chunks = Speak(daemon.url).synthesize("A long line " * 20, output="chunks")
+        next(chunks)
+        chunks.cancel()
+        assert list(chunks) == []
+
+
+class TestErrors:
+    def test_the_daemons_refusal(self, daemon):
+        with pytest.raises(DaemonError) as refused:
+            Speak(daemon.url).synthesize("Hi", voice="nobody")
+        assert refused.value.args[1] == 400
+
+    def test_no_daemon(self):
+        with pytest.raises(DaemonUnreachable):
+            Speak("http://127.0.0.1:9").list_voices()
+
+    def test_bad_requests_are_value_errors(self, daemon):
+        with pytest.raises(ValueError, match="Rate 9 is outside"):
+            Speak(daemon.url).synthesize("Hi", rate=9)
+        with pytest.raises(ValueError, match="output is"):
+            Speak(daemon.url).synthesize("Hi", output="mp3")
+
+    def test_ctrl_c_cancels_a_stalled_synthesis(self):
+        # A daemon that accepts and never answers
+        with socket.create_server(("127.0.0.1", 0)) as server:
+            url = "http://127.0.0.1:%d" % server.getsockname()[1]
+            threading.Timer(0.2, _thread.interrupt_main).start()
+            start = time.monotonic()
+            with pytest.raises(KeyboardInterrupt):
+                Speak(url).synthesize("Hi")
+            assert time.monotonic() - start < 2
+
+
+def test_voices_and_health(daemon):
+    speak = Speak(daemon.url)
+    assert speak.list_voices() == VOICES
+    assert speak.health() == {"status": "ready", "voices": VOICES}
@@ -0,0 +1,322 @@
+//! Abbreviations spelled out before synthesis ("Dr." -> "Doctor"), from a
+//! built-in English table plus any `--abbrev-file`.
+
+use regex::Regex;
+
+/// Abbreviations that come before what they're about (a name, an example),
+/// so their period never ends a sentence
+const LEADING: &[(&str, &str)] = &[
+    ("Mr.", "Mister"),
+    ("Mrs.", "Missus"),
+    ("Prof.", "Professor"),
+    ("Rev.", "Reverend"),
+    ("Hon.", "Honorable"),
+    ("Gen.", "General"),
+    ("Col.", "Colonel"),
+    ("Capt.", "Captain"),
+    ("Lt.", "Lieutenant"),
+    ("Sgt.", "Sergeant"),
+    ("Mt.", "Mount"),
+    ("e.g.", "for example"),
+    ("i.e.", "that is"),
+    ("vs.", "versus"),
+    ("cf.", "compare"),
+    ("approx.", "approximately"),
+];
+
+/// Abbreviations that can end a sentence, keeping their period when they do
+const TRAILING: &[(&str, &str)] = &[
+    ("etc.", "et cetera"),
+    ("Jr.", "Junior"),
+    ("Sr.", "Senior"),
+    ("Ave.", "Avenue"),
+    ("Blvd.", "Boulevard"),
+    ("Rd.", "Road"),
+    ("Ln.", "Lane"),
+    ("dept.", "department"),
+    ("Inc.", "Incorporated"),
+    ("Ltd.", "Limited"),
+];
+
+/// A title before a name, a place after one: "St. James St." is
+/// "Saint James Street"
+const TITLE_OR_PLACE: &[(&str, &str, &str)] = &[("St.", "Saint", "Street"), ("Dr.", "Doctor", "Drive")];
+
+/// Quotes and brackets around a word, not part of it
+const OPENERS: &[char] = &['(', '[', '{', '"', '\'', '“', '‘', '«'];
+const TRAILERS: &[char] = &[',', ';', ':', '!', '?', ')', ']', '}', '"', '\'', '”', '’', '»'];
+
+/// Expansions from an `--abbrev-file`, tried before the built-in ones
+#[derive(Clone, Debug, Default)]
+pub struct Table {
+    words: Vec<(String, String)>,
+    /// `/pattern/` lines, replacing matches anywhere outside code; `$1`
+    /// and so on in the expansion stand for the pattern's groups
+    patterns: Vec<(Regex, String)>,
+}
+
+impl Table {
+    /// Reads "abbreviation<TAB>expansion" lines; blank lines and lines
+    /// starting with '#' are skipped. Unlike the built-in ones, these keep
+    /// their period only at the very end of the text.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Self::parse(&text).map_err(|e| format!("{}:{}", path, e))
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut table = Table::default();
+        for (n, line) in text.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((short, long)) = line.split_once('\t') else {
+                return Err(format!("{}: expected \"abbreviation<TAB>expansion\"", n + 1));
+            };
+            let (short, long) = (short.trim(), long.trim().to_string());
+            match short.strip_prefix('/').and_then(|s| s.strip_suffix('/')) {
+                Some(pattern) if !pattern.is_empty() => {
+                    let regex = crate::pattern::compile(pattern).map_err(|e| format!("{}: {}", n + 1, e))?;
+                    table.patterns.push((regex, long));
+                }
+                _ if short.is_empty() || short.contains(char::is_whitespace) => {
+                    return Err(format!("{}: the abbreviation must be one word (or a /pattern/)", n + 1));
+                }
+                _ => table.words.push((short.to_string(), long)),
+            }
+        }
+        Ok(table)
+    }
+}
+
+/// `text` with its abbreviations spelled out, leaving URLs, paths and code
+/// (between backticks, too) alone.
+pub fn expand(text: &str, user: Option<&Table>) -> String {
+    let tokens = tokens(text);
+    let mut out = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut in_code = false;
+    for (k, &(start, end)) in tokens.iter().enumerate() {
+        let token = &text[start..end];
+        let was_code = in_code;
+        in_code ^= token.matches('`').count() % 2 == 1;
+        if was_code || in_code || code_like(token) {
+            continue;
+        }
+        let core = token.trim_start_matches(OPENERS);
+        let core_start = start + token.len() - core.len();
+        let core = core.trim_end_matches(TRAILERS);
+        let word = |i: usize| tokens.get(i).map(|&(s, e)| text[s..e].trim_start_matches(OPENERS));
+        let context = Context { before: k.checked_sub(1).and_then(word), after: word(k + 1), sentence_start: sentence_start(text, &tokens, k, user) };
+        if let Some((len, expansion)) = lookup(core, user, &context) {
+            out.push_str(&text[copied..core_start]);
+            out.push_str(&expansion);
+            copied = core_start + len;
+        }
+    }
+    out.push_str(&text[copied..]);
+    match user {
+        Some(table) => table.patterns.iter().fold(out, |text, (regex, long)| replace(&text, regex, long)),
+        None => out,
+    }
+}
+
+/// The words around an abbreviation
+struct Context<'a> {
+    before: Option<&'a str>,
+    after: Option<&'a str>,
+    /// Whether the word before starts its sentence
+    sentence_start: bool,
+}
+
+fn capitalized(word: Option<&str>) -> bool {
+    word.is_some_and(|w| w.starts_with(char::is_uppercase))
+}
+
+/// The expansion of `core`, and how many of its bytes it replaces.
+fn lookup(core: &str, user: Option<&Table>, context: &Context) -> Option<(usize, String)> {
+    // A period ending the sentence after an abbreviation without one: "ASAP."
+    let candidates = [Some(core), core.strip_suffix('.')];
+    for word in candidates.into_iter().flatten() {
+        let user_words = user.into_iter().flat_map(|t| t.words.iter().map(|(s, l)| (s.as_str(), l.as_str(), None)));
+        let built_in = LEADING.iter().map(|&(s, l)| (s, l, Some(true))).chain(TRAILING.iter().map(|&(s, l)| (s, l, Some(false))));
+        let either = TITLE_OR_PLACE.iter().map(|&(short, title, place)| {
+            // A capitalized word after, and no name (as opposed to the
+            // sentence's first word) before: a title
+            let name_before = capitalized(context.before) && !context.sentence_start;
+            match capitalized(context.after) && !name_before {
+                true => (short, title, Some(true)),
+                false => (short, place, Some(false)),
+            }
+        });
+        for (short, long, leading) in user_words.chain(either).chain(built_in) {
+            let Some(long) = cased(word, short, long) else { continue };
+            let ends_sentence = short.ends_with('.')
+                && match leading {
+                    Some(leading) => !leading && context.after.is_none_or(|w| w.starts_with(char::is_uppercase)),
+                    None => context.after.is_none(),
+                };
+            return Some((word.len(), if ends_sentence { long + "." } else { long }));
+        }
+    }
+    None
+}
+
+/// `long` if `word` is `short` as written, capitalized or in capitals,
+/// with its case following along.
+fn cased(word: &str, short: &str, long: &str) -> Option<String> {
+    if word == short {
+        return Some(long.to_string());
+    }
+    let mut chars = short.chars();
+    let first = chars.next()?;
+    if word == first.to_uppercase().chain(chars).collect::<String>() {
+        let mut chars = long.chars();
+        return Some(chars.next().map_or(String::new(), |c| c.to_uppercase().chain(chars).collect()));
+    }
+    (word.chars().filter(|c| c.is_alphabetic()).count() > 1 && word == short.to_uppercase()).then(|| long.to_uppercase())
+}
+
+/// Byte spans of the whitespace-separated tokens of `text`.
+fn tokens(text: &str) -> Vec<(usize, usize)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (at, c) in text.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(s)) => {
+                tokens.push((s, at));
+                start = None;
+            }
+            (false, None) => start = Some(at),
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        tokens.push((s, text.len()));
+    }
+    tokens
+}
+
+/// Whether token `k - 1` starts a sentence: the token before it ends with
+/// punctuation, and not an abbreviation's period.
+fn sentence_start(text: &str, tokens: &[(usize, usize)], k: usize, user: Option<&Table>) -> bool {
+    if k < 2 {
+        return true;
+    }
+    let (s, e) = tokens[k - 2];
+    let word = text[s..e].trim_start_matches(OPENERS).trim_end_matches(TRAILERS);
+    let abbreviation = |short: &str| cased(word, short, "").is_some();
+    let known = user.is_some_and(|t| t.words.iter().any(|(short, _)| abbreviation(short)))
+        || LEADING.iter().chain(TRAILING).any(|(short, _)| abbreviation(short))
+        || TITLE_OR_PLACE.iter().any(|(short, _, _)| abbreviation(short));
+    word.ends_with(['!', '?', '…']) || (word.ends_with('.') && !known)
+}
+
+/// URLs, paths, e-mail addresses, identifiers and calls.
+fn code_like(token: &str) -> bool {
+    let core = token.trim_start_matches(OPENERS).trim_end_matches(TRAILERS);
+    token.contains("://")
+        || token.starts_with("www.")
+        || core.contains(['/', '\\', '_', '=', '<', '>', '{', '}', '@', '#', '$', '|', '~', '('])
+}
+
+/// Byte spans of the URLs, paths and code in `text`, to leave alone.
+pub fn code_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut in_code = false;
+    tokens(text)
+        .into_iter()
+        .filter(|&(s, e)| {
+            let was_code = in_code;
+            in_code ^= text[s..e].matches('`').count() % 2 == 1;
+            was_code || in_code || code_like(&text[s..e])
+        })
+        .collect()
+}
+
+/// `text` with the matches of `regex` outside code replaced by `long`.
+fn replace(text: &str, regex: &Regex, long: &str) -> String {
+    let protected = code_spans(text);
+    let mut out = String::with_capacity(text.len());
+    let mut at = 0;
+    while at < text.len() {
+        let Some(groups) = regex.captures_at(text, at) else { break };
+        let (start, end) = (groups.get_match().start(), groups.get_match().end());
+        let next = text[start..].chars().next().map_or(1, char::len_utf8);
+        if end == start || protected.iter().any(|&(s, e)| start < e && end > s) {
+            out.push_str(&text[at..start + next]);
+            at = start + next;
+            continue;
+        }
+        out.push_str(&text[at..start]);
+        groups.expand(long, &mut out);
+        at = end;
+    }
+    out.push_str(&text[at..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sentence;
+
+    #[test]
+    fn expands_built_in_abbreviations() {
+        let cases = [
+            ("Dr. Smith arrived at 5 St. James St.", "Doctor Smith arrived at 5 Saint James Street."),
+            ("I met Dr. Watson on Mulholland Dr. yesterday.", "I met Doctor Watson on Mulholland Drive yesterday."),
+            ("At St. Mary's, Mr. Jones waited.", "At Saint Mary's, Mister Jones waited."),
+            ("We live on Main St. Then we moved.", "We live on Main Street. Then we moved."),
+            ("Fruit (e.g., apples), nuts, etc. and so on.", "Fruit (for example, apples), nuts, et cetera and so on."),
+            ("Cats vs. dogs, i.e. chaos, etc.", "Cats versus dogs, that is chaos, et cetera."),
+            ("E.g. this. ETC. too", "For example this. ET CETERA too"),
+            // Not as written, capitalized or in capitals
+            ("dr. who", "dr. who"),
+            ("Drums. Stop.", "Drums. Stop."),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(expand(text, None), expected, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn leaves_code_and_urls_alone() {
+        let cases = [
+            "See https://example.com/St./etc.",
+            "Run `ls etc. Dr. x` first",
+            "The path is /usr/etc.",
+            "call foo(e.g.)",
+            "mail Dr.@example.com",
+        ];
+        for text in cases {
+            assert_eq!(expand(text, None), text);
+        }
+    }
+
+    #[test]
+    fn reads_user_tables() {
+        let table = Table::parse("# mine\nASAP\tas soon as possible\nbtw\tby the way\n\nDr.\tDoc\n/(\\d+)x/\t$1 times\n").unwrap();
+        assert_eq!(
+            expand("Dr. Who, ASAP. Btw, 3x, not in v3x_y. Asap, Dr.", Some(&table)),
+            "Doc Who, as soon as possible. By the way, 3 times, not in v3x_y. Asap, Doc."
+        );
+        assert_eq!(Table::parse("a\tb\nbad line\n").unwrap_err(), "2: expected \"abbreviation<TAB>expansion\"");
+        assert!(Table::parse("/(/\tx\n").unwrap_err().starts_with("1: invalid regex"));
+        assert!(Table::parse("two words\tx\n").unwrap_err().contains("one word"));
+    }
+
+    #[test]
+    fn keeps_sentence_boundaries() {
+        // St. and Dr. don't end sentences, before or after expansion
+        let text = "Dr. Smith lives at 5 St. James St. He walks to St. Paul's daily.";
+        assert_eq!(sentence::split(text), ["Dr. Smith lives at 5 St. James St. He walks to St. Paul's daily."]);
+        assert_eq!(
+            sentence::split(&expand(text, None)),
+            ["Doctor Smith lives at 5 Saint James Street.", "He walks to Saint Paul's daily."]
+        );
+        assert_eq!(
+            sentence::split(&expand("Buy milk, eggs, etc. Then go home.", None)),
+            ["Buy milk, eggs, et cetera.", "Then go home."]
+        );
+    }
+}
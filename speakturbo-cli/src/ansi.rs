@@ -0,0 +1,97 @@
+//! Terminal escape sequences in piped program output (colors, cursor
+//! movement, window titles) are removed so they aren't read out as symbols.
+
+#[derive(Clone, Copy, Default)]
+enum State {
+    #[default]
+    Text,
+    Escape,
+    /// ESC followed by intermediate bytes, as in `ESC ( B`
+    EscapeIntermediate,
+    /// Control sequence: `ESC [` parameters, then a final byte
+    Csi,
+    /// OSC, DCS and the other strings ended by BEL or `ESC \`
+    Str,
+    StrEscape,
+}
+
+/// Strips escape sequences from text arriving in pieces. A sequence cut
+/// off at the end of one piece is finished in the next; one still open
+/// at the end of input is dropped.
+#[derive(Default)]
+pub struct Stripper {
+    state: State,
+}
+
+impl Stripper {
+    /// `bytes` without escape sequences, and with progress-bar rewrites
+    /// collapsed to what was left showing.
+    pub fn clean(&mut self, bytes: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(bytes.len());
+        for &b in bytes {
+            self.state = match (self.state, b) {
+                (State::Str | State::StrEscape, 0x1b) => State::StrEscape,
+                // ESC starts a sequence, abandoning any unfinished one
+                (_, 0x1b) => State::Escape,
+                (State::Escape, b'[') => State::Csi,
+                (State::Escape, b']' | b'P' | b'X' | b'^' | b'_') => State::Str,
+                (State::Escape | State::EscapeIntermediate, 0x20..=0x2f) => State::EscapeIntermediate,
+                (State::Escape | State::EscapeIntermediate, 0x30..=0x7e) => State::Text,
+                (State::Csi, 0x20..=0x3f) => State::Csi,
+                (State::Csi, 0x40..=0x7e) => State::Text,
+                (State::Str, 0x07) => State::Text,
+                (State::StrEscape, b'\\') => State::Text,
+                (State::Str | State::StrEscape, _) => State::Str,
+                // Text, or a malformed sequence given up on
+                _ => {
+                    out.push(b);
+                    State::Text
+                }
+            };
+        }
+        collapse_rewrites(out)
+    }
+}
+
+/// `text` without escape sequences; see [`Stripper::clean`].
+pub fn strip(text: &str) -> String {
+    String::from_utf8_lossy(&Stripper::default().clean(text.as_bytes())).into_owned()
+}
+
+/// Progress bars redraw their line after a carriage return; keep the last
+/// non-empty version of each line.
+fn collapse_rewrites(text: Vec<u8>) -> Vec<u8> {
+    if !text.contains(&b'\r') {
+        return text;
+    }
+    let lines: Vec<&[u8]> = text
+        .split(|&b| b == b'\n')
+        .map(|line| line.split(|&b| b == b'\r').rfind(|s| !s.is_empty()).unwrap_or_default())
+        .collect();
+    lines.join(&b'\n')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_escape_sequences() {
+        assert_eq!(strip("\x1b[1;31merror\x1b[0m: \x1b[38;5;208mbad\x1b[m"), "error: bad");
+        assert_eq!(strip("\x1b[2K\x1b[1G\x1b[?25lmoved\x1b[3A \x1b(Bcharset\x1b7"), "moved charset");
+        assert_eq!(strip("\x1b]0;title\x07a\x1b]8;;https://x.y\x1b\\link\x1b]8;;\x1b\\"), "alink");
+        assert_eq!(strip("Compiling 1/3\rCompiling 3/3\r\nDone\r"), "Compiling 3/3\nDone");
+        assert_eq!(strip("café \x1b[1mbold\x1b[0m"), "café bold");
+    }
+
+    #[test]
+    fn carries_a_partial_sequence_across_pieces() {
+        let mut stripper = Stripper::default();
+        assert_eq!(stripper.clean(b"red \x1b[3"), b"red ");
+        assert_eq!(stripper.clean(b"1mtext\x1b"), b"text");
+        assert_eq!(stripper.clean(b"[0m done"), b" done");
+        // Still open at end of input: dropped
+        assert_eq!(strip("done\x1b[3"), "done");
+        assert_eq!(strip("done\x1b]0;unterminated title"), "done");
+    }
+}
@@ -0,0 +1,218 @@
+//! `--batch-dir`: synthesize every text file under a directory into a
+//! mirrored tree of WAVs under -o, skipping outputs that are up to date.
+
+use crate::exit::{self, Kind};
+use crate::output::ExistingPolicy;
+use crate::report::{self, Report};
+use crate::reporter;
+use crate::sequence::{self, Item, ItemResult};
+use crate::summary::{self, Entry};
+use crate::Args;
+use anyhow::{bail, Context, Result};
+use globset::{GlobBuilder, GlobMatcher};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+enum State {
+    Pending(String),
+    UpToDate,
+    Failed(String),
+}
+
+struct Source {
+    path: PathBuf,
+    output: PathBuf,
+    state: State,
+}
+
+pub fn run(args: &Args, dir: &str, start: Instant) -> Result<()> {
+    let out_dir = Path::new(args.output.as_deref().unwrap_or("."));
+    let root = Path::new(dir);
+    if !root.is_dir() {
+        bail!("{} is not a directory", dir);
+    }
+    let files = collect(root, args.recursive, &args.glob)?;
+    if files.is_empty() {
+        bail!("No files matching {} in {}", args.glob, dir);
+    }
+
+    let mut sources: Vec<Source> = files
+        .into_iter()
+        .map(|rel| {
+            let path = root.join(&rel);
+            let output = out_dir.join(&rel).with_extension("wav");
+            let state = if !args.force && up_to_date(&path, &output) {
+                State::UpToDate
+            } else {
+                match crate::read_file(&path.to_string_lossy(), args.force_text) {
+                    Ok(text) if text.trim().is_empty() => State::Failed("No text".to_string()),
+                    Ok(text) => State::Pending(crate::normalized(args, text)),
+                    Err(e) => State::Failed(format!("{:#}", e)),
+                }
+            };
+            Source { path, output, state }
+        })
+        .collect();
+
+    let pending: Vec<usize> = (0..sources.len())
+        .filter(|&i| matches!(sources[i].state, State::Pending(_)))
+        .collect();
+    let outputs: Vec<String> = sources.iter().map(|s| s.output.to_string_lossy().into_owned()).collect();
+
+    if args.dry_run {
+        let plans: Vec<_> = pending
+            .iter()
+            .map(|&i| match &sources[i].state {
+                State::Pending(text) => crate::dry_run_plan(args, text, Some(&outputs[i]), ExistingPolicy::Overwrite),
+                _ => unreachable!(),
+            })
+            .collect();
+        crate::dry_run::report_all(&plans, args.json)?;
+        if !args.json {
+            reporter::info(format_args!("{} up to date", sources.len() - pending.len()));
+        }
+        return Ok(());
+    }
+
+    for &i in &pending {
+        if let Some(parent) = sources[i].output.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("Cannot create {}", parent.display()))?;
+        }
+    }
+    // Stale outputs are replaced, as make would rebuild them
+    let items: Vec<Item> = pending
+        .iter()
+        .map(|&i| match &sources[i].state {
+            State::Pending(text) => Item { text, output: Some(outputs[i].clone()), policy: ExistingPolicy::Overwrite, args: None },
+            _ => unreachable!(),
+        })
+        .collect();
+    let results = sequence::one_by_one(args, &items, start)?;
+
+    let mut done: Vec<Option<ItemResult>> = (0..sources.len()).map(|_| None).collect();
+    // The texts that failed to synthesize, to try again
+    let mut failed_texts: Vec<Option<String>> = (0..sources.len()).map(|_| None).collect();
+    for r in results {
+        let i = pending[r.n - 1];
+        if let Some(error) = &r.error {
+            if let State::Pending(text) = std::mem::replace(&mut sources[i].state, State::Failed(error.clone())) {
+                failed_texts[i] = Some(text);
+            }
+        }
+        done[i] = Some(r);
+    }
+
+    let mut entries = Vec::new();
+    for (i, source) in sources.iter().enumerate() {
+        let text = match &source.state {
+            State::Pending(text) => Some(text.as_str()),
+            _ => failed_texts[i].as_deref(),
+        };
+        let mut report = match &done[i] {
+            Some(r) => r.report(args, text.unwrap_or("")),
+            None => Report {
+                status: report::Status::Skipped,
+                voice: args.voice.clone(),
+                text_chars: 0,
+                elapsed: std::time::Duration::ZERO,
+                output: Some(outputs[i].clone()),
+                checksum: None,
+                source: None,
+                error: None,
+                kind: None,
+                measured: speakturbo_client::SynthesisStats::default(),
+            },
+        };
+        report.source = Some(source.path.to_string_lossy().into_owned());
+        match &source.state {
+            State::UpToDate => {}
+            State::Failed(e) => {
+                report.status = report::Status::Error;
+                report.error = Some(e.clone());
+                // Not synthesized: the file couldn't be read or had no text
+                report.kind = report.kind.or(Some(Kind::Input));
+            }
+            // Not reached after Ctrl+C or --fail-fast
+            State::Pending(_) if done[i].is_none() => report.status = report::Status::Pending,
+            State::Pending(_) => {}
+        }
+        let job = text.map(|text| summary::job(text, &args.voice, Some(&outputs[i])));
+        entries.push(Entry { label: source.path.display().to_string(), report, job });
+    }
+
+    if args.json {
+        report::print_all(&entries.iter().map(|e| e.report.clone()).collect::<Vec<_>>());
+    }
+    summary::finish(&entries, args.report.as_deref(), args.json)
+}
+
+/// The output exists and is at least as new as its source.
+fn up_to_date(source: &Path, output: &Path) -> bool {
+    let modified = |p: &Path| fs::metadata(p).and_then(|m| m.modified()).ok();
+    match (modified(source), modified(output)) {
+        (Some(src), Some(out)) => out >= src,
+        _ => false,
+    }
+}
+
+/// Matching files under `root` as paths relative to it, sorted so runs
+/// are deterministic. Patterns with a `/` match the relative path, others
+/// just the file name.
+fn collect(root: &Path, recursive: bool, pattern: &str) -> Result<Vec<PathBuf>> {
+    let matcher = matcher(pattern).map_err(|e| exit::fail(Kind::Usage, format!("Bad --glob: {}", e)))?;
+    let mut found = Vec::new();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(rel_dir) = dirs.pop() {
+        let dir = root.join(&rel_dir);
+        let entries = fs::read_dir(&dir).with_context(|| format!("Cannot read {}", dir.display()))?;
+        for entry in entries {
+            let entry = entry?;
+            let rel = rel_dir.join(entry.file_name());
+            // Symlinked directories are not followed, so there are no loops
+            if entry.file_type()?.is_dir() {
+                if recursive {
+                    dirs.push(rel);
+                }
+                continue;
+            }
+            if !entry.path().is_file() {
+                continue;
+            }
+            let subject = match pattern.contains('/') {
+                true => rel.to_string_lossy().replace('\\', "/"),
+                false => entry.file_name().to_string_lossy().into_owned(),
+            };
+            if matcher.is_match(&subject) {
+                found.push(rel);
+            }
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
+/// Shell-style matching: `*` and `?` (not across `/`), `[abc]`, `[a-z]`,
+/// `[!x]`, and `{a,b}` for either.
+fn matcher(pattern: &str) -> Result<GlobMatcher, globset::Error> {
+    Ok(GlobBuilder::new(pattern).literal_separator(true).build()?.compile_matcher())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs_match_like_the_shell() {
+        let glob_match = |pattern: &str, text: &str| matcher(pattern).unwrap().is_match(text);
+        assert!(glob_match("*.txt", "intro.txt"));
+        assert!(!glob_match("*.txt", "intro.txt.bak"));
+        assert!(glob_match("ch??-*.txt", "ch01-start.txt"));
+        assert!(glob_match("[a-c]*", "beta.txt"));
+        assert!(!glob_match("[!a-c]*", "beta.txt"));
+        assert!(glob_match("*/*.txt", "part1/intro.txt"));
+        assert!(!glob_match("*.txt", "part1/intro.txt"));
+        assert!(!glob_match("ch?", "ch/"));
+        assert!(matcher("[a-").is_err());
+    }
+}
@@ -0,0 +1,330 @@
+//! `speakturbo bench`: one text synthesized over and over, through the
+//! same request and stream path as speaking it, with the timings of each
+//! run summed up. Warm-up runs come first and aren't counted. With
+//! --concurrency, several runs are in flight at once to see what the
+//! daemon keeps up with; a 429 is waited out and retried.
+
+use crate::chime::Chimes;
+use crate::exit::{self, Kind};
+use crate::{interrupt, report, reporter, Args, SAMPLE_RATE};
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::io::Read;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub struct Options {
+    pub iterations: usize,
+    pub warmup: usize,
+    pub concurrency: usize,
+    pub play: bool,
+}
+
+/// One run's timings, from the start of its (last) request
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sample {
+    /// Until the daemon's response headers
+    pub ttfb: Duration,
+    /// Until the first audio past the WAV header
+    pub first_sample: Duration,
+    /// Until the last of the audio arrived
+    pub total: Duration,
+    pub audio: Duration,
+}
+
+impl Sample {
+    /// Synthesis time over audio time: below 1 is faster than real time
+    pub fn realtime_factor(&self) -> f64 {
+        match self.audio.is_zero() {
+            true => 0.0,
+            false => self.total.as_secs_f64() / self.audio.as_secs_f64(),
+        }
+    }
+}
+
+/// min, median, p95 and max of some values, by nearest rank
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Summary {
+    pub min: f64,
+    pub median: f64,
+    pub p95: f64,
+    pub max: f64,
+}
+
+pub fn summarize(values: &[f64]) -> Option<Summary> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let rank = |p: f64| sorted[((p * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len()) - 1];
+    Some(Summary { min: *sorted.first()?, median: rank(0.5), p95: rank(0.95), max: *sorted.last()? })
+}
+
+/// Lines of "  12.0–15.0ms  ████ 4", `buckets` of them between the least
+/// and greatest of `values`.
+pub fn histogram(values: &[f64], buckets: usize, unit: &str) -> Vec<String> {
+    let Some(Summary { min, max, .. }) = summarize(values) else {
+        return Vec::new();
+    };
+    let buckets = if max > min { buckets.max(1) } else { 1 };
+    let width = (max - min) / buckets as f64;
+    let mut counts = vec![0usize; buckets];
+    for value in values {
+        let i = if width > 0.0 { ((value - min) / width) as usize } else { 0 };
+        counts[i.min(buckets - 1)] += 1;
+    }
+    let most = counts.iter().copied().max().unwrap_or(1);
+    counts
+        .iter()
+        .enumerate()
+        .map(|(i, &count)| {
+            let from = min + width * i as f64;
+            let bar = format!("{} {}", "█".repeat((count * 40).div_ceil(most)), count);
+            format!("  {:>7.1}–{:<7}  {}", from, format!("{:.1}{}", from + width, unit), bar.trim_start())
+        })
+        .collect()
+}
+
+/// Notes when the audio starts and ends as it's read, wherever that is.
+struct Timed<R> {
+    inner: R,
+    times: Arc<Mutex<Times>>,
+}
+
+#[derive(Default)]
+struct Times {
+    bytes: u64,
+    first: Option<Instant>,
+    end: Option<Instant>,
+}
+
+impl<R: Read> Read for Timed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        let mut times = self.times.lock().unwrap();
+        times.bytes += n as u64;
+        if times.bytes > 44 && times.first.is_none() {
+            times.first = Some(Instant::now());
+        }
+        if n == 0 && !buf.is_empty() && times.end.is_none() {
+            times.end = Some(Instant::now());
+        }
+        Ok(n)
+    }
+}
+
+/// One synthesis of `text`, played or just read; the 429s it waited out.
+fn once(args: &Args, text: &str, play: bool) -> Result<(Sample, u32)> {
+    let mut throttled = 0;
+    let (audio, begun, ttfb) = loop {
+        let begun = Instant::now();
+        match crate::open_audio(args, None, text) {
+            Ok((audio, _, _)) => break (audio, begun, begun.elapsed()),
+            Err(e) => match crate::backoff(&e, throttled) {
+                Some(wait) if throttled < crate::MAX_THROTTLED => {
+                    throttled += 1;
+                    std::thread::sleep(wait);
+                }
+                _ => return Err(e),
+            },
+        }
+    };
+    let times = Arc::new(Mutex::new(Times::default()));
+    let timed = Timed { inner: audio, times: Arc::clone(&times) };
+    match play {
+        true => {
+            crate::stream_audio(Box::new(timed), crate::Shown::Position(None), begun, true, None, Chimes::default(), usize::MAX)?;
+        }
+        false => {
+            std::io::copy(&mut interrupt::for_work().reader(timed), &mut std::io::sink())?;
+        }
+    }
+    let times = times.lock().unwrap();
+    let since = |at: Option<Instant>| at.map_or(Duration::ZERO, |at| at.saturating_duration_since(begun));
+    let samples = times.bytes.saturating_sub(44) / 2;
+    let sample = Sample {
+        ttfb,
+        first_sample: since(times.first),
+        total: since(Some(times.end.unwrap_or_else(Instant::now))),
+        audio: Duration::from_secs_f64(samples as f64 / SAMPLE_RATE as f64),
+    };
+    Ok((sample, throttled))
+}
+
+/// What the runs came to, in the order they finished.
+struct Outcome {
+    samples: Vec<Sample>,
+    failures: Vec<anyhow::Error>,
+    throttled: u32,
+    wall: Duration,
+}
+
+/// `count` runs, `concurrency` at a time.
+fn runs(args: &Args, text: &str, count: usize, options: &Options) -> Outcome {
+    let next = AtomicUsize::new(0);
+    let outcome = Mutex::new(Outcome { samples: Vec::new(), failures: Vec::new(), throttled: 0, wall: Duration::ZERO });
+    let started = Instant::now();
+    std::thread::scope(|scope| {
+        for _ in 0..options.concurrency.min(count) {
+            scope.spawn(|| {
+                while next.fetch_add(1, Ordering::SeqCst) < count && !interrupt::requested() {
+                    let result = once(args, text, options.play);
+                    let mut outcome = outcome.lock().unwrap();
+                    match result {
+                        Ok((sample, throttled)) => {
+                            outcome.samples.push(sample);
+                            outcome.throttled += throttled;
+                        }
+                        Err(e) => outcome.failures.push(e),
+                    }
+                }
+            });
+        }
+    });
+    let mut outcome = outcome.into_inner().unwrap();
+    outcome.wall = started.elapsed();
+    outcome
+}
+
+pub fn run(args: &mut Args, text: &str, options: Options) -> Result<()> {
+    // Every run goes to the daemon, and none is kept for --replay
+    args.no_record = true;
+    interrupt::install();
+    let text = crate::normalized(args, text.to_string());
+    let mode = if options.play { "played" } else { "not played" };
+    reporter::info(format_args!(
+        "Bench: {} runs of {} chars in {}, after {} warm-up, {} at a time, {}",
+        options.iterations,
+        text.chars().count(),
+        args.voice,
+        options.warmup,
+        options.concurrency,
+        mode
+    ));
+    if options.warmup > 0 {
+        let warmup = runs(args, &text, options.warmup, &options);
+        if let Some(e) = warmup.failures.into_iter().next().filter(|_| warmup.samples.is_empty()) {
+            return Err(e.context("Warm-up failed"));
+        }
+    }
+    let mut outcome = runs(args, &text, options.iterations, &options);
+    if outcome.samples.is_empty() && !outcome.failures.is_empty() {
+        return Err(outcome.failures.remove(0));
+    }
+
+    match args.json {
+        true => {
+            println!("{}", to_json(args, &text, &options, &outcome));
+            report::mark_printed();
+        }
+        false => print_human(&outcome),
+    }
+    if interrupt::requested() {
+        return Err(exit::fail(Kind::Interrupted, "Interrupted"));
+    }
+    Ok(())
+}
+
+/// Each measure's name and its values in the unit shown
+fn measures(samples: &[Sample]) -> [(&'static str, Vec<f64>); 4] {
+    let ms = |f: fn(&Sample) -> Duration| samples.iter().map(|s| f(s).as_secs_f64() * 1000.0).collect();
+    [
+        ("ttfb_ms", ms(|s| s.ttfb)),
+        ("first_sample_ms", ms(|s| s.first_sample)),
+        ("total_ms", ms(|s| s.total)),
+        ("realtime_factor", samples.iter().map(Sample::realtime_factor).collect()),
+    ]
+}
+
+/// Runs a second and seconds of audio a second
+fn throughput(outcome: &Outcome) -> (f64, f64) {
+    let wall = outcome.wall.as_secs_f64().max(f64::EPSILON);
+    let audio: f64 = outcome.samples.iter().map(|s| s.audio.as_secs_f64()).sum();
+    (outcome.samples.len() as f64 / wall, audio / wall)
+}
+
+fn print_human(outcome: &Outcome) {
+    let labels = ["time to first byte", "first sample", "total", "realtime factor"];
+    println!("{:<20} {:>9} {:>9} {:>9} {:>9}", "", "min", "median", "p95", "max");
+    for ((name, values), label) in measures(&outcome.samples).iter().zip(labels) {
+        let Some(s) = summarize(values) else { continue };
+        let cell = |v: f64| match name.ends_with("_ms") {
+            true => format!("{:.1}ms", v),
+            false => format!("{:.3}", v),
+        };
+        println!("{:<20} {:>9} {:>9} {:>9} {:>9}", label, cell(s.min), cell(s.median), cell(s.p95), cell(s.max));
+    }
+    let (runs, audio) = throughput(outcome);
+    println!("\nThroughput: {:.2} runs/s, {:.2}s of audio a second", runs, audio);
+    if outcome.throttled > 0 {
+        println!("Throttled (429): {} times", outcome.throttled);
+    }
+    if let Some(e) = outcome.failures.first() {
+        println!("Failed: {} runs (first: {:#})", outcome.failures.len(), e);
+    }
+    let totals = &measures(&outcome.samples)[2].1;
+    println!("\nTotal time:");
+    for line in histogram(totals, 8, "ms") {
+        println!("{}", line);
+    }
+}
+
+fn to_json(args: &Args, text: &str, options: &Options, outcome: &Outcome) -> Value {
+    let (runs, audio) = throughput(outcome);
+    let round = |v: f64, places: i32| Value::from((v * 10f64.powi(places)).round() / 10f64.powi(places));
+    let mut fields = vec![
+        ("schema", Value::from(report::SCHEMA)),
+        ("voice", args.voice.as_str().into()),
+        ("text_chars", text.chars().count().into()),
+        ("iterations", outcome.samples.len().into()),
+        ("warmup", options.warmup.into()),
+        ("concurrency", options.concurrency.into()),
+        ("played", options.play.into()),
+        ("failures", outcome.failures.len().into()),
+        ("throttled", u64::from(outcome.throttled).into()),
+        ("wall_ms", (outcome.wall.as_millis() as u64).into()),
+        ("runs_per_sec", round(runs, 2)),
+        ("audio_secs_per_sec", round(audio, 2)),
+    ];
+    for (name, values) in measures(&outcome.samples) {
+        let places = if name.ends_with("_ms") { 1 } else { 3 };
+        let summary = summarize(&values).map_or(Value::Null, |s| {
+            json!({"min": round(s.min, places), "median": round(s.median, places), "p95": round(s.p95, places), "max": round(s.max, places)})
+        });
+        fields.push((name, summary));
+    }
+    Value::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_by_nearest_rank() {
+        let values: Vec<f64> = (1..=20).rev().map(f64::from).collect();
+        assert_eq!(summarize(&values), Some(Summary { min: 1.0, median: 10.0, p95: 19.0, max: 20.0 }));
+        assert_eq!(summarize(&[7.0]), Some(Summary { min: 7.0, median: 7.0, p95: 7.0, max: 7.0 }));
+        assert_eq!(summarize(&[]), None);
+        let sample = Sample {
+            ttfb: Duration::from_millis(20),
+            first_sample: Duration::from_millis(30),
+            total: Duration::from_millis(500),
+            audio: Duration::from_secs(2),
+        };
+        assert_eq!(sample.realtime_factor(), 0.25);
+    }
+
+    #[test]
+    fn draws_a_histogram() {
+        let lines = histogram(&[10.0, 11.0, 12.0, 19.0, 20.0], 2, "ms");
+        assert_eq!(
+            lines,
+            [
+                format!("     10.0–15.0ms   {} 3", "█".repeat(40)),
+                format!("     15.0–20.0ms   {} 2", "█".repeat(27)),
+            ]
+        );
+        assert_eq!(histogram(&[5.0, 5.0], 4, "ms").len(), 1);
+        assert!(histogram(&[], 4, "ms").is_empty());
+    }
+}
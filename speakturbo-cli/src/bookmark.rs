@@ -0,0 +1,382 @@
+//! `--bookmark NAME` and `--resume NAME`: where a long text was stopped,
+//! kept in the state directory so that it can be listened to in pieces.
+//! A bookmark holds the character offset of the sentence that was playing
+//! and a hash of the whole text; resuming a text that hashes differently
+//! is refused unless `--resume-force`, which looks for the sentence.
+
+use crate::checksum::{Algorithm, Digest};
+use crate::exit::{self, Kind};
+use crate::join::Span;
+use crate::reporter;
+use crate::sentence;
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Subcommand)]
+pub enum Action {
+    /// Forget saved positions
+    Delete {
+        #[arg(value_name = "NAME", required = true)]
+        names: Vec<String>,
+    },
+}
+
+/// A position saved in a text
+#[derive(Debug, PartialEq)]
+pub struct Saved {
+    pub name: String,
+    pub time: u64,
+    /// Hash of the whole text, as [`hash`] makes it
+    pub hash: String,
+    /// Characters before the sentence to start from
+    pub offset: usize,
+    /// That sentence, numbered from 1, and how many the text has
+    pub sentence: usize,
+    pub sentences: usize,
+    /// Characters in the whole text
+    pub chars: usize,
+    /// The sentence itself, for finding it again in a changed text
+    pub text: String,
+}
+
+/// A bookmark's name, as --bookmark and --resume take it: letters,
+/// digits, '-', '_' and '.', so that it makes a file name.
+pub fn name(value: &str) -> Result<String, String> {
+    let allowed = |c: char| c.is_alphanumeric() || matches!(c, '-' | '_' | '.');
+    match !value.is_empty() && !value.starts_with('.') && value.chars().all(allowed) {
+        true => Ok(value.to_string()),
+        false => Err("use letters, digits, '-', '_' and '.' (not first)".to_string()),
+    }
+}
+
+/// Where bookmarks are kept: `bookmarks/` in the state directory.
+fn dir() -> Result<PathBuf> {
+    crate::history::state_dir().map(|d| d.join("bookmarks")).context("No state directory: set XDG_STATE_HOME or HOME")
+}
+
+/// What identifies a text across readings
+pub fn hash(text: &str) -> String {
+    let mut digest = Digest::new(Algorithm::Sha256);
+    digest.update(text.as_bytes());
+    digest.finish_hex()
+}
+
+/// A bookmarked reading: the whole text, where in it this reading starts,
+/// and the name its position is saved under.
+pub struct Reading<'a> {
+    name: &'a str,
+    text: &'a str,
+    /// Byte offset this reading starts from
+    from: usize,
+}
+
+impl<'a> Reading<'a> {
+    /// A reading of `text` saved as `name`: from the start, or with
+    /// `resume`, from where that bookmark stopped.
+    pub fn open(name: &'a str, text: &'a str, resume: Option<&str>, force: bool) -> Result<Reading<'a>> {
+        let from = match resume {
+            Some(resume) => start(&dir()?, resume, text, force)?,
+            None => 0,
+        };
+        Ok(Reading { name, text, from })
+    }
+
+    /// What's left to read
+    pub fn remaining(&self) -> &'a str {
+        &self.text[self.from..]
+    }
+
+    /// Save the sentence playing after `played` samples of the remaining
+    /// text, which played as `spans`, or in one piece of about `length`
+    /// samples when it wasn't split.
+    pub fn stopped(&self, spans: &[Span], played: u64, length: Option<u64>) -> Result<()> {
+        let remaining = self.remaining();
+        let starts = sentence_starts(remaining);
+        let whole;
+        let spans = match spans {
+            [] => {
+                whole = [Span { text: remaining.to_string(), sentences: 1..=starts.len().max(1), start: 0, end: length }];
+                &whole[..]
+            }
+            spans => spans,
+        };
+        let n = sentence_at(spans, played).clamp(1, starts.len().max(1));
+        let at = self.from + starts.get(n - 1).copied().unwrap_or(0);
+        let saved = saved(self.name, self.text, at);
+        write(&dir()?, &saved)?;
+        reporter::info(format_args!(
+            "Bookmark {}: sentence {} of {} ({}%)",
+            saved.name,
+            saved.sentence,
+            saved.sentences,
+            saved.offset * 100 / saved.chars.max(1)
+        ));
+        Ok(())
+    }
+
+    /// The text has been read to the end: its bookmark goes.
+    pub fn finished(&self) -> Result<()> {
+        let path = dir()?.join(file_name(self.name));
+        match fs::remove_file(&path) {
+            Ok(()) => reporter::info(format_args!("Bookmark {}: finished, removed", self.name)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).with_context(|| format!("Cannot remove {}", path.display())),
+        }
+        Ok(())
+    }
+}
+
+/// The byte offset to resume `text` from: where bookmark `name` stopped,
+/// or when the text has changed and `force`, the start of the sentence
+/// nearest it.
+fn start(dir: &Path, name: &str, text: &str, force: bool) -> Result<usize> {
+    let saved = read(dir, name)?.ok_or_else(|| exit::fail(Kind::Usage, format!("No bookmark {} (see `speakturbo bookmarks`)", name)))?;
+    let starts = sentence_starts(text);
+    let at = if saved.hash == hash(text) {
+        byte_offset(text, saved.offset)
+    } else if force {
+        let at = nearest(text, &starts, &saved);
+        reporter::warning(format_args!("the text has changed since bookmark {} was saved; starting from the sentence nearest it", name));
+        at
+    } else {
+        let message = format!("The text has changed since bookmark {} was saved; --resume-force starts from the sentence nearest it", name);
+        return Err(exit::fail(Kind::Input, message));
+    };
+    let n = starts.iter().take_while(|&&start| start <= at).count().max(1);
+    reporter::info(format_args!("Resuming {} at sentence {} of {}", name, n, starts.len()));
+    Ok(at)
+}
+
+/// In a changed text, the start of the saved sentence where it still
+/// appears, the appearance nearest where it was; else of the sentence
+/// that starts nearest the saved offset.
+fn nearest(text: &str, starts: &[usize], saved: &Saved) -> usize {
+    let was = byte_offset(text, saved.offset);
+    let distance = |at: &usize| at.abs_diff(was);
+    let sentences = sentence::split(text);
+    let same = starts.iter().zip(&sentences).filter(|(_, s)| **s == saved.text).map(|(at, _)| *at).min_by_key(distance);
+    same.or_else(|| starts.iter().copied().min_by_key(distance)).unwrap_or(0)
+}
+
+/// The sentence playing after `played` samples, numbered from 1 in the
+/// text the `spans` were planned from: in the chunk playing, the sentence
+/// as far through its characters as playback is through its samples.
+pub fn sentence_at(spans: &[Span], played: u64) -> usize {
+    let Some(span) = spans.iter().rev().find(|span| span.start <= played).or(spans.first()) else {
+        return 1;
+    };
+    // A chunk not yet all read is taken from its start
+    let through = match span.end {
+        Some(end) if end > span.start => (played.saturating_sub(span.start) as f64 / (end - span.start) as f64).min(1.0),
+        _ => 0.0,
+    };
+    let lengths: Vec<usize> = sentence::split(&span.text).iter().map(|s| s.chars().count()).collect();
+    let at = through * lengths.iter().sum::<usize>() as f64;
+    let mut before = 0;
+    let mut k = 0;
+    for (i, len) in lengths.iter().enumerate() {
+        if (before as f64) > at {
+            break;
+        }
+        k = i;
+        before += len;
+    }
+    (span.sentences.start() + k).min(*span.sentences.end())
+}
+
+/// The byte offsets where `text`'s sentences start
+fn sentence_starts(text: &str) -> Vec<usize> {
+    sentence::split(text).iter().map(|s| s.as_ptr() as usize - text.as_ptr() as usize).collect()
+}
+
+fn byte_offset(text: &str, chars: usize) -> usize {
+    text.char_indices().nth(chars).map_or(text.len(), |(at, _)| at)
+}
+
+/// A bookmark `name` in `text` at byte offset `at`, a sentence's start.
+fn saved(name: &str, text: &str, at: usize) -> Saved {
+    let sentences = sentence::split(text);
+    let starts = sentence_starts(text);
+    let n = starts.iter().take_while(|&&start| start <= at).count().max(1);
+    Saved {
+        name: name.to_string(),
+        time: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+        hash: hash(text),
+        offset: text[..at].chars().count(),
+        sentence: n,
+        sentences: sentences.len(),
+        chars: text.chars().count(),
+        text: sentences.get(n - 1).map_or(String::new(), |s| s.to_string()),
+    }
+}
+
+fn file_name(name: &str) -> String {
+    format!("{}.json", name)
+}
+
+fn to_json(saved: &Saved) -> Value {
+    json!({
+        "name": saved.name,
+        "time": saved.time,
+        "hash": saved.hash,
+        "offset": saved.offset,
+        "sentence": saved.sentence,
+        "sentences": saved.sentences,
+        "chars": saved.chars,
+        "text": saved.text,
+    })
+}
+
+fn parse(v: &Value) -> Option<Saved> {
+    let string = |k: &str| match v.get(k) {
+        Some(Value::String(s)) => Some(s.clone()),
+        _ => None,
+    };
+    let number = |k: &str| v.get(k).and_then(Value::as_f64).map(|n| n as u64);
+    Some(Saved {
+        name: string("name")?,
+        time: number("time").unwrap_or(0),
+        hash: string("hash")?,
+        offset: number("offset")? as usize,
+        sentence: number("sentence").unwrap_or(1) as usize,
+        sentences: number("sentences").unwrap_or(0) as usize,
+        chars: number("chars").unwrap_or(0) as usize,
+        text: string("text").unwrap_or_default(),
+    })
+}
+
+/// Written to a temporary file and renamed over the old one, so that a
+/// crash leaves one or the other.
+fn write(dir: &Path, saved: &Saved) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("Cannot create {}", dir.display()))?;
+    let path = dir.join(file_name(&saved.name));
+    let partial = dir.join(format!(".{}.{}", file_name(&saved.name), std::process::id()));
+    fs::write(&partial, format!("{}\n", to_json(saved)))
+        .and_then(|()| fs::rename(&partial, &path))
+        .with_context(|| format!("Cannot save bookmark {}", path.display()))
+}
+
+fn read(dir: &Path, name: &str) -> Result<Option<Saved>> {
+    let path = dir.join(file_name(name));
+    match fs::read_to_string(&path) {
+        Ok(content) => Ok(serde_json::from_str(&content).ok().as_ref().and_then(parse)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Cannot read {}", path.display())),
+    }
+}
+
+/// Every saved bookmark, by name.
+fn read_all(dir: &Path) -> Result<Vec<Saved>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Cannot read {}", dir.display())),
+    };
+    let mut all = Vec::new();
+    for entry in entries {
+        let file = entry?.file_name();
+        let Some(name) = file.to_str().and_then(|f| f.strip_suffix(".json")).filter(|n| !n.starts_with('.')) else {
+            continue;
+        };
+        all.extend(read(dir, name)?);
+    }
+    all.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(all)
+}
+
+/// `speakturbo bookmarks`: list the saved positions, or delete some.
+pub fn run(action: Option<&Action>, as_json: bool) -> Result<()> {
+    let dir = dir()?;
+    match action {
+        None => {
+            for saved in read_all(&dir)? {
+                if as_json {
+                    println!("{}", to_json(&saved));
+                } else {
+                    println!(
+                        "{:<16} {}  sentence {} of {} ({}%)  {}",
+                        saved.name,
+                        crate::history::utc_timestamp(saved.time),
+                        saved.sentence,
+                        saved.sentences,
+                        saved.offset * 100 / saved.chars.max(1),
+                        crate::text::excerpt(&saved.text)
+                    );
+                }
+            }
+        }
+        Some(Action::Delete { names }) => {
+            for name in names {
+                match fs::remove_file(dir.join(file_name(name))) {
+                    Ok(()) => reporter::info(format_args!("Deleted bookmark {}", name)),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                        return Err(exit::fail(Kind::Usage, format!("No bookmark {}", name)));
+                    }
+                    Err(e) => return Err(e).with_context(|| format!("Cannot delete bookmark {}", name)),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "One two three. Four five six seven. Eight. Nine ten eleven twelve.";
+
+    fn span(text: &str, sentences: std::ops::RangeInclusive<usize>, start: u64, end: Option<u64>) -> Span {
+        Span { text: text.to_string(), sentences, start, end }
+    }
+
+    #[test]
+    fn maps_samples_played_to_a_sentence() {
+        let spans = [span("One two three. Four five six seven.", 1..=2, 0, Some(1000)), span("Eight. Nine ten eleven twelve.", 3..=4, 1200, None)];
+        assert_eq!(sentence_at(&spans, 0), 1);
+        // "One two three." is 14 of the chunk's 34 characters
+        assert_eq!(sentence_at(&spans, 400), 1);
+        assert_eq!(sentence_at(&spans, 500), 2);
+        // In the silence after a chunk, its last sentence
+        assert_eq!(sentence_at(&spans, 1100), 2);
+        // A chunk whose end isn't known yet is taken from its start
+        assert_eq!(sentence_at(&spans, 5000), 3);
+        assert_eq!(sentence_at(&[], 5000), 1);
+    }
+
+    #[test]
+    fn resumes_where_it_stopped_and_finds_the_sentence_in_a_changed_text() {
+        let dir = std::env::temp_dir().join(format!("speakturbo-bookmarks-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let at = TEXT.find("Eight").unwrap();
+        write(&dir, &saved("article", TEXT, at)).unwrap();
+        assert_eq!(read(&dir, "article").unwrap().unwrap().sentence, 3);
+        assert_eq!(start(&dir, "article", TEXT, false).unwrap(), at);
+
+        let changed = "Zero. One two three. Four five six seven. Eight. Nine ten eleven twelve.";
+        let e = start(&dir, "article", changed, false).unwrap_err();
+        assert!(format!("{:#}", e).contains("--resume-force"), "{:#}", e);
+        assert_eq!(start(&dir, "article", changed, true).unwrap(), changed.find("Eight").unwrap());
+        // Without the sentence, the one starting nearest where it was
+        let rewritten = "One two three. Four five six seven. Ate. Nine ten eleven twelve.";
+        assert_eq!(start(&dir, "article", rewritten, true).unwrap(), rewritten.find("Ate").unwrap());
+        assert!(start(&dir, "other", TEXT, false).is_err());
+
+        write(&dir, &saved("book", TEXT, 0)).unwrap();
+        let names: Vec<String> = read_all(&dir).unwrap().into_iter().map(|s| s.name).collect();
+        assert_eq!(names, ["article", "book"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn names_make_file_names() {
+        assert!(name("chapter-3_draft.v2").is_ok());
+        assert!(name("../etc").is_err());
+        assert!(name(".hidden").is_err());
+        assert!(name("").is_err());
+    }
+}
@@ -0,0 +1,151 @@
+//! `--emulate-breaks`: pauses written into the text, for daemons that
+//! don't honor SSML. `<break time="500ms"/>` and `[[pause 500ms]]` split
+//! the text; the parts are synthesized separately with silence between.
+
+use anyhow::{bail, Context, Result};
+use std::time::Duration;
+
+/// Longest single pause; longer ones are shortened to this
+pub const MAX_PAUSE: Duration = Duration::from_secs(10);
+
+#[derive(Debug, PartialEq)]
+pub enum Part {
+    Text(String),
+    Pause(Duration),
+}
+
+/// `text` split at its pauses, in order. Adjacent pauses add up; blank
+/// text between them is dropped.
+pub fn split(text: &str) -> Result<Vec<Part>> {
+    let mut parts = Vec::new();
+    let mut rest = text;
+    while let Some((at, len, pause)) = next_pause(rest)? {
+        push_text(&mut parts, &rest[..at]);
+        match parts.last_mut() {
+            Some(Part::Pause(previous)) => *previous += pause,
+            _ => parts.push(Part::Pause(pause)),
+        }
+        rest = &rest[at + len..];
+    }
+    push_text(&mut parts, rest);
+    Ok(parts)
+}
+
+fn push_text(parts: &mut Vec<Part>, text: &str) {
+    let text = text.trim();
+    if !text.is_empty() {
+        parts.push(Part::Text(text.to_string()));
+    }
+}
+
+/// The first pause marker in `text`: its offset, length and duration.
+fn next_pause(text: &str) -> Result<Option<(usize, usize, Duration)>> {
+    let lower = text.to_ascii_lowercase();
+    let tag = lower.find("<break").filter(|&at| {
+        // Not <breakfast>
+        lower[at + 6..].starts_with(|c: char| c.is_whitespace() || c == '/' || c == '>')
+    });
+    let bracket = lower.find("[[pause");
+    match (tag, bracket) {
+        (Some(at), b) if b.is_none_or(|b| at < b) => {
+            let len = lower[at..].find('>').context("Unclosed <break> tag")? + 1;
+            Ok(Some((at, len, break_tag(&text[at..at + len])?)))
+        }
+        (_, Some(at)) => {
+            let len = lower[at..].find("]]").context("Unclosed [[pause ...]]")? + 2;
+            let pause = duration(&text[at + 7..at + len - 2])?;
+            Ok(Some((at, len, pause)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// The pause of a `<break>` tag: its `time`, or else its `strength` as
+/// SSML defines the levels (medium when neither is given).
+fn break_tag(tag: &str) -> Result<Duration> {
+    if let Some(time) = attribute(tag, "time") {
+        return duration(time);
+    }
+    let millis = match attribute(tag, "strength").unwrap_or("medium") {
+        "none" => 0,
+        "x-weak" => 100,
+        "weak" => 250,
+        "medium" => 500,
+        "strong" => 1000,
+        "x-strong" => 2000,
+        other => bail!("Unknown break strength {:?} in {}", other, tag),
+    };
+    Ok(Duration::from_millis(millis))
+}
+
+/// The quoted value of attribute `name` in `tag`.
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = tag;
+    while let Some(at) = rest.find(name) {
+        let after = rest[at + name.len()..].trim_start();
+        let preceded = rest[..at].ends_with(char::is_whitespace);
+        if let (true, Some(value)) = (preceded, after.strip_prefix('=')) {
+            let value = value.trim_start();
+            let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+            return value[1..].split(quote).next();
+        }
+        rest = &rest[at + name.len()..];
+    }
+    None
+}
+
+/// "500ms", "1.5s" or "2 s", capped at [`MAX_PAUSE`].
+fn duration(spec: &str) -> Result<Duration> {
+    let spec = spec.trim().to_ascii_lowercase();
+    let (number, scale) = match spec.strip_suffix("ms") {
+        Some(number) => (number, 0.001),
+        None => match spec.strip_suffix('s') {
+            Some(number) => (number, 1.0),
+            None => bail!("Pause {:?} needs a unit, like 500ms or 1.5s", spec),
+        },
+    };
+    let seconds = number
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|n| n.is_finite() && *n >= 0.0)
+        .with_context(|| format!("Bad pause length {:?}", spec))?;
+    Ok(Duration::from_secs_f64((seconds * scale).min(MAX_PAUSE.as_secs_f64())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(n: u64) -> Part {
+        Part::Pause(Duration::from_millis(n))
+    }
+
+    fn text(s: &str) -> Part {
+        Part::Text(s.to_string())
+    }
+
+    #[test]
+    fn splits_at_pauses() {
+        assert_eq!(
+            split(r#"One. <break time="500ms"/> Two.[[pause 1.5s]]Three. <BREAK strength='strong' /> Four"#).unwrap(),
+            [text("One."), ms(500), text("Two."), ms(1500), text("Three."), ms(1000), text("Four")]
+        );
+        assert_eq!(
+            split("[[pause 1s]] <break/> Hello <breakfast> [[pause 2 S]]").unwrap(),
+            [ms(1500), text("Hello <breakfast>"), ms(2000)]
+        );
+        assert_eq!(split("No pauses here.").unwrap(), [text("No pauses here.")]);
+    }
+
+    #[test]
+    fn reads_durations() {
+        assert_eq!(duration("250ms").unwrap(), Duration::from_millis(250));
+        assert_eq!(duration(" 0.75 s").unwrap(), Duration::from_millis(750));
+        assert_eq!(duration("90s").unwrap(), MAX_PAUSE);
+        assert!(duration("500").unwrap_err().to_string().contains("needs a unit"));
+        assert!(duration("-1s").is_err());
+        assert!(split("Wait [[pause 1s").is_err());
+        assert!(split(r#"<break strength="loud"/>"#).is_err());
+    }
+}
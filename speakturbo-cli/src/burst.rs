@@ -0,0 +1,261 @@
+//! `--debounce-ms` and `--max-per-minute`: the records of the streaming
+//! modes paced, so that a burst of activity doesn't turn into a wall of
+//! speech. Debouncing holds records until the source has gone quiet, then
+//! passes on what came, joined or only the newest (--debounce-strategy).
+//! The rate limit drops records past --max-per-minute, counting them, and
+//! once the rate is back under says how many: "…and 7 more messages".
+//! Times are [`Instant`]s, which a change of the wall clock doesn't move.
+
+use crate::reporter;
+use crate::stream::Record;
+use crate::Args;
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TrySendError};
+use std::time::{Duration, Instant};
+
+/// How often a quiet spell or a freed-up minute is looked for
+const TICK: Duration = Duration::from_millis(20);
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+/// What a quiet spell after a burst passes on
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Strategy {
+    /// Every record that came, a line each
+    Concat,
+    /// Only the newest
+    Last,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Options {
+    pub debounce: Option<Duration>,
+    pub strategy: Strategy,
+    pub max_per_minute: Option<u32>,
+}
+
+impl Options {
+    /// The pacing `args` ask for; None when records go through as they
+    /// come. --watch-clipboard waits 300ms for copying to settle unless
+    /// told otherwise, and then speaks only the newest value.
+    pub fn of(args: &Args) -> Option<Options> {
+        #[cfg(feature = "clipboard")]
+        let clipboard = args.watch_clipboard;
+        #[cfg(not(feature = "clipboard"))]
+        let clipboard = false;
+        let debounce = args.debounce_ms.or(clipboard.then_some(300)).filter(|&ms| ms > 0).map(Duration::from_millis);
+        let strategy = args.debounce_strategy.unwrap_or(if clipboard { Strategy::Last } else { Strategy::Concat });
+        (debounce.is_some() || args.max_per_minute.is_some()).then_some(Options { debounce, strategy, max_per_minute: args.max_per_minute })
+    }
+}
+
+/// `records` paced by `options`, leaving out those `keep` doesn't want
+/// before they count. Records that failed to read go through at once.
+pub fn paced(records: Receiver<Record>, options: Options, keep: impl Fn(&str) -> bool + Send + 'static) -> Receiver<Record> {
+    let (tx, rx) = mpsc::sync_channel(1);
+    std::thread::spawn(move || {
+        let mut pacer = Pacer::new(options);
+        // Passed on, but not yet taken by the speaker, which is busy
+        let mut ready = VecDeque::new();
+        loop {
+            let ended = match records.recv_timeout(TICK) {
+                Ok(record) => {
+                    if record.text.as_deref().map_or(true, &keep) {
+                        ready.extend(pacer.arrive(record, Instant::now()));
+                    }
+                    false
+                }
+                Err(RecvTimeoutError::Timeout) => false,
+                Err(RecvTimeoutError::Disconnected) => true,
+            };
+            if ended {
+                ready.extend(pacer.finish());
+                for record in ready {
+                    if tx.send(record).is_err() {
+                        return;
+                    }
+                }
+                return;
+            }
+            ready.extend(pacer.tick(Instant::now()));
+            // Never blocked on the speaker, so arrivals keep being counted
+            while let Some(record) = ready.pop_front() {
+                match tx.try_send(record) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(record)) => {
+                        ready.push_front(record);
+                        break;
+                    }
+                    Err(TrySendError::Disconnected(_)) => return,
+                }
+            }
+        }
+    });
+    rx
+}
+
+struct Pacer {
+    options: Options,
+    /// Held until the source goes quiet
+    pending: Vec<Record>,
+    /// When the newest of `pending` came
+    latest: Option<Instant>,
+    /// When each record passed on in the last minute was
+    sent: VecDeque<Instant>,
+    /// Records over the rate since the last summary
+    dropped: usize,
+    /// The newest record's position, for the summary's
+    n: usize,
+}
+
+impl Pacer {
+    fn new(options: Options) -> Pacer {
+        Pacer { options, pending: Vec::new(), latest: None, sent: VecDeque::new(), dropped: 0, n: 0 }
+    }
+
+    /// The records to pass on now that `record` has come.
+    fn arrive(&mut self, record: Record, now: Instant) -> Vec<Record> {
+        let mut out = Vec::new();
+        self.n = self.n.max(record.n);
+        match (&record.text, self.options.debounce) {
+            (Ok(_), Some(_)) => {
+                self.pending.push(record);
+                self.latest = Some(now);
+            }
+            (Ok(_), None) => self.admit(record, now, &mut out),
+            (Err(_), _) => out.push(record),
+        }
+        out
+    }
+
+    /// The records to pass on at `now`, with nothing new come: those held
+    /// once the source has been quiet long enough, and the summary of
+    /// those dropped once there's room for it.
+    fn tick(&mut self, now: Instant) -> Vec<Record> {
+        let mut out = Vec::new();
+        let quiet = match (self.options.debounce, self.latest) {
+            (Some(debounce), Some(latest)) => now.duration_since(latest) >= debounce,
+            _ => false,
+        };
+        if quiet {
+            if let Some(record) = self.combined() {
+                self.admit(record, now, &mut out);
+            }
+        }
+        self.summarize(now, &mut out, false);
+        out
+    }
+
+    /// What's left once the source has ended: the held records whether or
+    /// not it has been quiet, and the summary, the rate aside.
+    fn finish(&mut self) -> Vec<Record> {
+        let now = Instant::now();
+        let mut out = Vec::new();
+        if let Some(record) = self.combined() {
+            self.admit(record, now, &mut out);
+        }
+        self.summarize(now, &mut out, true);
+        out
+    }
+
+    /// The held records as one, per the strategy.
+    fn combined(&mut self) -> Option<Record> {
+        self.latest = None;
+        let last = self.pending.last()?.n;
+        let pending = std::mem::take(&mut self.pending);
+        let texts = pending.into_iter().filter_map(|record| record.text.ok());
+        let text = match self.options.strategy {
+            Strategy::Concat => texts.collect::<Vec<_>>().join("\n"),
+            Strategy::Last => texts.last().unwrap_or_default(),
+        };
+        Some(Record { n: last, text: Ok(text) })
+    }
+
+    /// Pass `record` on if the last minute has room for it, or count it.
+    fn admit(&mut self, record: Record, now: Instant, out: &mut Vec<Record>) {
+        self.summarize(now, out, false);
+        if self.room(now) {
+            self.sent.push_back(now);
+            out.push(record);
+        } else {
+            self.dropped += 1;
+        }
+    }
+
+    /// Say how many were dropped, once there's room for it (or `anyway`).
+    fn summarize(&mut self, now: Instant, out: &mut Vec<Record>, anyway: bool) {
+        if self.dropped == 0 || !(anyway || self.room(now)) {
+            return;
+        }
+        let dropped = std::mem::take(&mut self.dropped);
+        reporter::info(format_args!("Skipped {} item{} over --max-per-minute", dropped, if dropped == 1 { "" } else { "s" }));
+        self.sent.push_back(now);
+        let text = format!("…and {} more message{}", dropped, if dropped == 1 { "" } else { "s" });
+        out.push(Record { n: self.n, text: Ok(text) });
+    }
+
+    /// Whether the last minute has passed on fewer than the most allowed.
+    fn room(&mut self, now: Instant) -> bool {
+        while self.sent.front().is_some_and(|&at| now.duration_since(at) >= MINUTE) {
+            self.sent.pop_front();
+        }
+        self.options.max_per_minute.is_none_or(|max| self.sent.len() < max as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(n: usize, text: &str) -> Record {
+        Record { n, text: Ok(text.to_string()) }
+    }
+
+    fn texts(records: Vec<Record>) -> Vec<String> {
+        records.into_iter().map(|r| r.text.unwrap()).collect()
+    }
+
+    #[test]
+    fn waits_for_quiet_then_passes_on_the_burst() {
+        let t0 = Instant::now();
+        let ms = |ms| t0 + Duration::from_millis(ms);
+        for (strategy, said) in [(Strategy::Concat, "build started\ntests running\ntests passed"), (Strategy::Last, "tests passed")] {
+            let mut pacer = Pacer::new(Options { debounce: Some(Duration::from_millis(500)), strategy, max_per_minute: None });
+            assert!(pacer.arrive(record(1, "build started"), ms(0)).is_empty());
+            assert!(pacer.arrive(record(2, "tests running"), ms(300)).is_empty());
+            assert!(pacer.tick(ms(700)).is_empty(), "only 400ms of quiet");
+            assert!(pacer.arrive(record(3, "tests passed"), ms(750)).is_empty());
+            let out = pacer.tick(ms(1250));
+            assert_eq!(out[0].n, 3);
+            assert_eq!(texts(out), [said]);
+            assert!(pacer.tick(ms(5000)).is_empty());
+        }
+    }
+
+    #[test]
+    fn drops_records_over_the_rate_and_says_how_many() {
+        let t0 = Instant::now();
+        let secs = |s| t0 + Duration::from_secs(s);
+        let mut pacer = Pacer::new(Options { debounce: None, strategy: Strategy::Concat, max_per_minute: Some(2) });
+        let mut said = Vec::new();
+        for n in 1..=9 {
+            said.extend(texts(pacer.arrive(record(n, &format!("message {}", n)), secs(n as u64))));
+        }
+        assert_eq!(said, ["message 1", "message 2"]);
+        assert!(pacer.tick(secs(60)).is_empty(), "message 1 is still within the minute");
+        assert_eq!(texts(pacer.tick(secs(61))), ["…and 7 more messages"]);
+        assert_eq!(texts(pacer.arrive(record(10, "message 10"), secs(62))), ["message 10"]);
+        assert!(pacer.arrive(record(11, "message 11"), secs(63)).is_empty());
+        assert_eq!(texts(pacer.finish()), ["…and 1 more message"]);
+    }
+
+    #[test]
+    fn passes_failed_records_straight_on() {
+        let mut pacer = Pacer::new(Options { debounce: Some(Duration::from_secs(1)), strategy: Strategy::Concat, max_per_minute: Some(1) });
+        let now = Instant::now();
+        assert!(pacer.arrive(record(1, "held"), now).is_empty());
+        let failed = pacer.arrive(Record { n: 2, text: Err(anyhow::anyhow!("record is too long")) }, now);
+        assert!(failed[0].text.is_err());
+        assert_eq!(texts(pacer.finish()), ["held"]);
+    }
+}
@@ -0,0 +1,269 @@
+//! `speakturbo cache ...`: inspect and maintain the audio cache.
+
+use speakturbo_client::cache::{self, Cache, CacheConfig, Removed};
+use crate::progress::human_bytes;
+use crate::reporter::{self, Mark};
+use crate::SpeakRequest;
+use anyhow::{bail, Context, Result};
+use clap::Subcommand;
+use serde_json::json;
+use std::io::Read;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Subcommand)]
+pub enum Action {
+    /// Show entry count, size and hit rate
+    Stats,
+
+    /// Remove old entries or shrink the cache
+    #[command(group = clap::ArgGroup::new("limit").required(true).multiple(true))]
+    Prune {
+        /// Shrink to this size, dropping least recently used first (e.g. 200M, 1G)
+        #[arg(long, value_name = "SIZE", value_parser = parse_size, group = "limit")]
+        max_size: Option<u64>,
+
+        /// Drop entries not used for this long (e.g. 30d, 12h)
+        #[arg(long, value_name = "AGE", value_parser = parse_age, group = "limit")]
+        older_than: Option<Duration>,
+    },
+
+    /// Remove every cached entry
+    Clear,
+
+    /// Synthesize each line of a file into the cache ahead of time
+    Warm {
+        /// Phrases, one per line ("-" for stdin)
+        #[arg(short, long, value_name = "FILE")]
+        file: String,
+
+        #[arg(short, long, default_value = "alba")]
+        voice: String,
+
+        /// Phrases to synthesize at once
+        #[arg(short, long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..=32))]
+        jobs: u32,
+    },
+}
+
+/// The CLI's cache of up to `max_bytes`, in the default directory if
+/// there is one. Lookups are counted there, for `cache stats`.
+pub fn config(max_bytes: u64) -> Option<CacheConfig> {
+    let dir = cache::default_dir()?;
+    Some(CacheConfig { max_bytes, lifetime_counters: true, ..CacheConfig::new(dir) })
+}
+
+pub fn run(action: &Action, max_bytes: u64, as_json: bool) -> Result<()> {
+    let config = config(max_bytes).context("No cache directory: set XDG_CACHE_HOME or HOME")?;
+    let cache = Cache::new(config);
+    match action {
+        Action::Stats => stats(&cache, as_json),
+        Action::Prune { max_size, older_than } => {
+            let removed = cache.prune(*max_size, *older_than)?;
+            report_removed(&cache, &removed, as_json)
+        }
+        Action::Clear => {
+            let removed = cache.clear()?;
+            report_removed(&cache, &removed, as_json)
+        }
+        Action::Warm { file, voice, jobs } => warm(&cache, file, voice, *jobs as usize, as_json),
+    }
+}
+
+fn stats(cache: &Cache, as_json: bool) -> Result<()> {
+    let entries = cache.entries()?;
+    let bytes: u64 = entries.iter().map(|e| e.len).sum();
+    let (hits, misses) = cache.counters();
+    let lookups = hits + misses;
+    let rate = (lookups > 0).then(|| hits as f64 / lookups as f64);
+
+    if as_json {
+        let value = json!({
+            "dir": cache.dir().to_string_lossy(),
+            "entries": entries.len(),
+            "bytes": bytes,
+            "hits": hits,
+            "misses": misses,
+            "hit_rate": rate,
+        });
+        println!("{}", value);
+        return Ok(());
+    }
+
+    println!("Cache:    {}", cache.dir().display());
+    println!("Entries:  {} ({})", entries.len(), human_bytes(bytes));
+    match rate {
+        Some(rate) => println!("Hit rate: {:.0}% ({} hits, {} misses)", rate * 100.0, hits, misses),
+        None => println!("Hit rate: -"),
+    }
+    Ok(())
+}
+
+fn report_removed(cache: &Cache, removed: &Removed, as_json: bool) -> Result<()> {
+    let remaining = cache.entries()?;
+    let remaining_bytes: u64 = remaining.iter().map(|e| e.len).sum();
+    if as_json {
+        let value = json!({
+            "removed": removed.entries,
+            "freed_bytes": removed.bytes,
+            "entries": remaining.len(),
+            "bytes": remaining_bytes,
+        });
+        println!("{}", value);
+    } else {
+        println!(
+            "Removed {} entries ({}), {} left ({})",
+            removed.entries,
+            human_bytes(removed.bytes),
+            remaining.len(),
+            human_bytes(remaining_bytes)
+        );
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Warmed {
+    Stored,
+    Cached,
+    Failed,
+}
+
+fn warm(cache: &Cache, file: &str, voice: &str, jobs: usize, as_json: bool) -> Result<()> {
+    let mut input = String::new();
+    if file == "-" {
+        std::io::stdin().read_to_string(&mut input)?;
+    } else {
+        input = std::fs::read_to_string(file).with_context(|| format!("Cannot read {}", file))?;
+    }
+    let mut phrases: Vec<&str> = Vec::new();
+    for line in input.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if !phrases.contains(&line) {
+            phrases.push(line);
+        }
+    }
+
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(vec![(Warmed::Failed, None::<String>); phrases.len()]);
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.min(phrases.len()) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(text) = phrases.get(i) else { break };
+                let result = warm_one(cache, text, voice);
+                if !as_json {
+                    match &result {
+                        Ok(Warmed::Stored) => reporter::info(format_args!("{} {}", reporter::mark(Mark::Done), text)),
+                        Ok(_) => reporter::info(format_args!("= {}", text)),
+                        Err(e) => reporter::error(format_args!("{} {}: {:#}", reporter::mark(Mark::Failed), text, e)),
+                    }
+                }
+                results.lock().unwrap()[i] = match result {
+                    Ok(w) => (w, None),
+                    Err(e) => (Warmed::Failed, Some(format!("{:#}", e))),
+                };
+            });
+        }
+    });
+
+    let results = results.into_inner().unwrap();
+    let count = |kind| results.iter().filter(|(w, _)| *w == kind).count();
+    let (stored, cached, failed) = (count(Warmed::Stored), count(Warmed::Cached), count(Warmed::Failed));
+
+    if as_json {
+        let errors: Vec<_> = phrases
+            .iter()
+            .zip(&results)
+            .filter_map(|(text, (_, err))| Some(json!({"text": text, "error": err.as_deref()?})))
+            .collect();
+        let value = json!({
+            "phrases": phrases.len(),
+            "stored": stored,
+            "cached": cached,
+            "failed": failed,
+            "errors": errors,
+        });
+        println!("{}", value);
+    } else {
+        reporter::info(format_args!(
+            "Warmed {} phrases: {} stored, {} already cached, {} failed",
+            phrases.len(),
+            stored,
+            cached,
+            failed
+        ));
+    }
+
+    if failed > 0 {
+        bail!("{} of {} phrases failed", failed, phrases.len());
+    }
+    Ok(())
+}
+
+fn warm_one(cache: &Cache, text: &str, voice: &str) -> Result<Warmed> {
+    let request = SpeakRequest::new(text).voice(voice).build()?;
+    let key = cache::key(crate::daemon_url(), &request);
+    if cache.contains(&key) {
+        return Ok(Warmed::Cached);
+    }
+    let audio = crate::client().synthesize(&request)?;
+    std::io::copy(&mut cache.tee(&key, audio), &mut std::io::sink())?;
+    Ok(Warmed::Stored)
+}
+
+/// "200M", "1.5G", "512K" or plain bytes.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let n: f64 = number.trim().parse().map_err(|_| format!("invalid size: {}", s))?;
+    let scale = match unit.to_ascii_uppercase().trim_end_matches(['B', 'I']) {
+        "" => 1u64,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => return Err(format!("invalid size unit: {}", unit)),
+    };
+    if n < 0.0 {
+        return Err(format!("invalid size: {}", s));
+    }
+    Ok((n * scale as f64) as u64)
+}
+
+/// "30d", "12h", "45m", "90s" or "2w".
+pub fn parse_age(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let (number, unit) = s.split_at(s.len().saturating_sub(1));
+    let n: f64 = number.parse().map_err(|_| format!("invalid age: {} (try 30d or 12h)", s))?;
+    let secs = match unit {
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        "d" => 86400.0,
+        "w" => 604800.0,
+        _ => return Err(format!("invalid age unit in {} (use s, m, h, d or w)", s)),
+    };
+    if n < 0.0 {
+        return Err(format!("invalid age: {}", s));
+    }
+    Ok(Duration::from_secs_f64(n * secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes_and_ages() {
+        assert_eq!(parse_size("200M"), Ok(200 << 20));
+        assert_eq!(parse_size("1.5GB"), Ok(3 << 29));
+        assert_eq!(parse_size("512KiB"), Ok(512 << 10));
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert!(parse_size("12X").is_err());
+
+        assert_eq!(parse_age("30d"), Ok(Duration::from_secs(30 * 86400)));
+        assert_eq!(parse_age("90s"), Ok(Duration::from_secs(90)));
+        assert!(parse_age("30").is_err());
+    }
+}
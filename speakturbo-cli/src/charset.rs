@@ -0,0 +1,89 @@
+//! Decoding fetched pages that aren't UTF-8: the charset labels the web
+//! still commonly serves, without an encoding library.
+
+use anyhow::{bail, Result};
+
+// Windows-1252 code points for bytes 0x80..=0x9F; the rest match Latin-1
+const CP1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}', '\u{90}', '‘',
+    '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+/// Decode `bytes` labelled `label` (from Content-Type or a meta tag). A
+/// byte-order mark wins over the label; no label means UTF-8.
+pub fn decode(bytes: &[u8], label: Option<&str>) -> Result<String> {
+    if let Some(rest) = bytes.strip_prefix(b"\xef\xbb\xbf") {
+        return Ok(String::from_utf8_lossy(rest).into_owned());
+    }
+    if let Some(rest) = bytes.strip_prefix(b"\xff\xfe") {
+        return Ok(utf16(rest, u16::from_le_bytes));
+    }
+    if let Some(rest) = bytes.strip_prefix(b"\xfe\xff") {
+        return Ok(utf16(rest, u16::from_be_bytes));
+    }
+    let label = label.map(|l| l.trim().trim_matches(['"', '\'']).to_ascii_lowercase());
+    Ok(match label.as_deref() {
+        None | Some("utf-8" | "utf8" | "unicode-1-1-utf-8") => String::from_utf8_lossy(bytes).into_owned(),
+        // As browsers do, Latin-1 and ASCII labels mean Windows-1252
+        Some("windows-1252" | "cp1252" | "iso-8859-1" | "iso8859-1" | "latin1" | "l1" | "us-ascii" | "ascii") => {
+            bytes.iter().map(|&b| cp1252(b)).collect()
+        }
+        Some("iso-8859-15" | "iso8859-15" | "latin9") => bytes.iter().map(|&b| latin9(b)).collect(),
+        Some("utf-16le" | "utf-16") => utf16(bytes, u16::from_le_bytes),
+        Some("utf-16be") => utf16(bytes, u16::from_be_bytes),
+        Some(other) => bail!("Unsupported charset {:?}", other),
+    })
+}
+
+/// The charset declared by a `<meta>` tag near the top of an HTML page.
+pub fn sniff_meta(bytes: &[u8]) -> Option<String> {
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(2048)]).to_ascii_lowercase();
+    let start = head.find("charset=")? + "charset=".len();
+    let value = head[start..].trim_start_matches(['"', '\'']);
+    let end = value.find(|c: char| !(c.is_ascii_alphanumeric() || "-_:.".contains(c))).unwrap_or(value.len());
+    Some(value[..end].to_string()).filter(|v| !v.is_empty())
+}
+
+fn cp1252(b: u8) -> char {
+    match b {
+        0x80..=0x9f => CP1252_HIGH[(b - 0x80) as usize],
+        _ => b as char,
+    }
+}
+
+fn latin9(b: u8) -> char {
+    match b {
+        0xa4 => '€',
+        0xa6 => 'Š',
+        0xa8 => 'š',
+        0xb4 => 'Ž',
+        0xb8 => 'ž',
+        0xbc => 'Œ',
+        0xbd => 'œ',
+        0xbe => 'Ÿ',
+        _ => b as char,
+    }
+}
+
+fn utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> String {
+    let units = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+    char::decode_utf16(units).map(|c| c.unwrap_or('\u{fffd}')).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_legacy_charsets() {
+        assert_eq!(decode(b"caf\xe9 \x93hi\x94", Some("ISO-8859-1")).unwrap(), "café “hi”");
+        assert_eq!(decode(b"\xa4", Some("latin9")).unwrap(), "€");
+        assert_eq!(decode(b"\xff\xfeh\0i\0", Some("latin1")).unwrap(), "hi");
+        assert!(decode(b"x", Some("koi8-r")).is_err());
+        assert_eq!(sniff_meta(b"<meta charset=\"Windows-1252\">").as_deref(), Some("windows-1252"));
+        assert_eq!(
+            sniff_meta(b"<meta http-equiv=Content-Type content='text/html; charset=iso-8859-1'>").as_deref(),
+            Some("iso-8859-1")
+        );
+    }
+}
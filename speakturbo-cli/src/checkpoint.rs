@@ -0,0 +1,169 @@
+//! `--checkpoint PATH`: a record of the items a sequence or batch has
+//! finished, so that running it again picks up where it stopped. Each
+//! finished item appends a line and syncs it to disk:
+//!
+//!     <item> <bytes> <sha256 of the output>
+//!
+//! where the item is a hash of everything that makes its audio and where
+//! it goes. An item is skipped when its line is there and its output is
+//! still what was written; a changed row hashes differently and runs
+//! again. A line cut short by a crash is ignored.
+
+use crate::checksum::{Algorithm, Digest};
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+pub struct Checkpoint {
+    file: Mutex<File>,
+    /// Each recorded item's output size and hash
+    done: HashMap<String, (u64, String)>,
+}
+
+impl Checkpoint {
+    /// The record at `path`, created if there is none; with `restart`,
+    /// started over.
+    pub fn open(path: &Path, restart: bool) -> Result<Checkpoint> {
+        let cannot = || format!("Cannot open the checkpoint {}", path.display());
+        let mut file = OpenOptions::new().read(true).append(true).create(true).open(path).with_context(cannot)?;
+        if restart {
+            file.set_len(0).with_context(cannot)?;
+        }
+        let mut content = String::new();
+        file.read_to_string(&mut content).with_context(cannot)?;
+        // What a crash left of the last line goes on a line of its own
+        if !content.is_empty() && !content.ends_with('\n') {
+            file.write_all(b"\n").with_context(cannot)?;
+        }
+        Ok(Checkpoint { file: Mutex::new(file), done: parse(&content) })
+    }
+
+    /// Whether `item` was finished by an earlier run and `output` is
+    /// still what it wrote.
+    pub fn completed(&self, item: &str, output: &Path) -> bool {
+        match self.done.get(item) {
+            Some((bytes, hash)) => file_digest(output).is_ok_and(|(b, h)| b == *bytes && h == *hash),
+            None => false,
+        }
+    }
+
+    /// Record `item` as finished, with `output` as it is now.
+    pub fn record(&self, item: &str, output: &Path) -> Result<()> {
+        let (bytes, hash) = file_digest(output).with_context(|| format!("Cannot read {}", output.display()))?;
+        let mut file = self.file.lock().unwrap();
+        file.write_all(format!("{} {} {}\n", item, bytes, hash).as_bytes())
+            .and_then(|()| file.sync_data())
+            .context("Cannot write the checkpoint")
+    }
+}
+
+// Bump when the key inputs change
+const KEY_VERSION: &str = "1";
+
+/// Everything that changes an item's audio
+pub struct Key<'a> {
+    pub text: &'a str,
+    pub voice: &'a str,
+    pub daemon: &'a str,
+    /// Extra /tts parameters, as a JSON Lines job gives them
+    pub params: &'a [(String, String)],
+}
+
+impl Key<'_> {
+    pub fn hash(&self) -> String {
+        let mut digest = Digest::new(Algorithm::Sha256);
+        let params = self.params.iter().flat_map(|(name, value)| [name.as_str(), value.as_str()]);
+        // None is hashed as it was before there were parameters
+        for field in [KEY_VERSION, self.daemon, self.voice, self.text].into_iter().chain(params) {
+            // Length-prefixed so ("ab", "c") and ("a", "bc") differ
+            digest.update(&(field.len() as u64).to_le_bytes());
+            digest.update(field.as_bytes());
+        }
+        digest.finish_hex()
+    }
+}
+
+/// What identifies an item across runs: its audio's key and its output
+pub fn item(key: &Key, output: &str) -> String {
+    let mut digest = Digest::new(Algorithm::Sha256);
+    for field in [key.hash().as_str(), output] {
+        digest.update(&(field.len() as u64).to_le_bytes());
+        digest.update(field.as_bytes());
+    }
+    digest.finish_hex()
+}
+
+fn parse(content: &str) -> HashMap<String, (u64, String)> {
+    content
+        .lines()
+        .filter_map(|line| match line.split(' ').collect::<Vec<_>>()[..] {
+            [item, bytes, hash] if item.len() == 64 && hash.len() == 64 => Some((item.to_string(), (bytes.parse().ok()?, hash.to_string()))),
+            _ => None,
+        })
+        .collect()
+}
+
+fn file_digest(path: &Path) -> std::io::Result<(u64, String)> {
+    let mut file = File::open(path)?;
+    let mut digest = Digest::new(Algorithm::Sha256);
+    let mut chunk = [0u8; 64 * 1024];
+    let mut bytes = 0;
+    loop {
+        match file.read(&mut chunk)? {
+            0 => return Ok((bytes, digest.finish_hex())),
+            read => {
+                digest.update(&chunk[..read]);
+                bytes += read as u64;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_separate_fields() {
+        let a = Key { text: "ab", voice: "c", daemon: "d", params: &[] }.hash();
+        let b = Key { text: "a", voice: "bc", daemon: "d", params: &[] }.hash();
+        assert_ne!(a, b);
+        assert_eq!(a, Key { text: "ab", voice: "c", daemon: "d", params: &[] }.hash());
+        let speed = [("speed".to_string(), "1.2".to_string())];
+        assert_ne!(a, Key { text: "ab", voice: "c", daemon: "d", params: &speed }.hash());
+    }
+
+    #[test]
+    fn skips_only_what_is_unchanged() {
+        let dir = std::env::temp_dir().join(format!("speakturbo-checkpoint-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("run.checkpoint");
+        let (one, two) = (dir.join("1.wav"), dir.join("2.wav"));
+        std::fs::write(&one, b"RIFF one").unwrap();
+        std::fs::write(&two, b"RIFF two").unwrap();
+        let key = |text| Key { text, voice: "alba", daemon: "d", params: &[] };
+        let (a, b) = (item(&key("One"), "1.wav"), item(&key("Two"), "2.wav"));
+        assert_ne!(a, item(&key("One"), "2.wav"));
+
+        let checkpoint = Checkpoint::open(&path, false).unwrap();
+        checkpoint.record(&a, &one).unwrap();
+        checkpoint.record(&b, &two).unwrap();
+        drop(checkpoint);
+        // A crash partway through a line
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"0123").unwrap();
+        std::fs::write(&two, b"RIFF changed").unwrap();
+
+        let checkpoint = Checkpoint::open(&path, false).unwrap();
+        assert!(checkpoint.completed(&a, &one));
+        assert!(!checkpoint.completed(&b, &two), "the output changed");
+        assert!(!checkpoint.completed(&item(&key("One!"), "1.wav"), &one), "the text changed");
+        checkpoint.record(&b, &two).unwrap();
+        drop(checkpoint);
+        assert!(Checkpoint::open(&path, false).unwrap().completed(&b, &two));
+        assert!(!Checkpoint::open(&path, true).unwrap().completed(&a, &one));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
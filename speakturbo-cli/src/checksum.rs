@@ -0,0 +1,219 @@
+//! Streaming digests of saved output (`--checksum`).
+
+use serde::{Deserialize, Serialize};
+use std::io::{Seek, SeekFrom, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+    Sha256,
+    Blake3,
+}
+
+impl Algorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Blake3 => "blake3",
+        }
+    }
+}
+
+/// A saved file's digest, for its line on stdout and --json
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Checksum {
+    pub algorithm: Algorithm,
+    pub hex: String,
+}
+
+impl Checksum {
+    /// The coreutils line, "<algo>  <hex>  <file>"
+    pub fn line(&self, file: impl std::fmt::Display) -> String {
+        format!("{}  {}  {}", self.algorithm.name(), self.hex, file)
+    }
+}
+
+pub enum Digest {
+    Sha256(Box<ring::digest::Context>),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Digest {
+    pub fn new(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::Sha256 => Digest::Sha256(Box::new(ring::digest::Context::new(&ring::digest::SHA256))),
+            Algorithm::Blake3 => Digest::Blake3(Box::default()),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Digest::Sha256(ctx) => ctx.update(data),
+            Digest::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    pub fn finish_hex(self) -> String {
+        let bytes: Vec<u8> = match self {
+            Digest::Sha256(ctx) => ctx.finish().as_ref().to_vec(),
+            Digest::Blake3(hasher) => hasher.finalize().as_bytes().to_vec(),
+        };
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+/// Writer adapter that hashes every byte on its way to the inner writer,
+/// so the digest is of exactly what landed on disk.
+pub struct HashingWriter<W> {
+    inner: W,
+    algorithm: Algorithm,
+    digest: Digest,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub fn new(inner: W, algorithm: Algorithm) -> Self {
+        Self { inner, algorithm, digest: Digest::new(algorithm) }
+    }
+
+    pub fn finish(self) -> (W, Checksum) {
+        (self.inner, Checksum { algorithm: self.algorithm, hex: self.digest.finish_hex() })
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.digest.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writer adapter for a file whose header is rewritten once the rest is
+/// written, as a saved WAV's sizes are: it keeps an image of what it
+/// writes and seeks over, header patches and all, and hashes that last.
+/// A digest can't take the header after the audio that follows it, so
+/// the audio is held in memory rather than read back from disk.
+pub struct HeaderLast<W> {
+    inner: W,
+    algorithm: Algorithm,
+    image: Vec<u8>,
+    at: usize,
+}
+
+impl<W: Write + Seek> HeaderLast<W> {
+    pub fn new(inner: W, algorithm: Algorithm) -> Self {
+        Self { inner, algorithm, image: Vec::new(), at: 0 }
+    }
+
+    pub fn finish(self) -> (W, Checksum) {
+        let mut digest = Digest::new(self.algorithm);
+        digest.update(&self.image);
+        (self.inner, Checksum { algorithm: self.algorithm, hex: digest.finish_hex() })
+    }
+}
+
+impl<W: Write> Write for HeaderLast<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        let end = self.at + n;
+        if end > self.image.len() {
+            self.image.resize(end, 0);
+        }
+        self.image[self.at..end].copy_from_slice(&buf[..n]);
+        self.at = end;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Seek> Seek for HeaderLast<W> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let at = self.inner.seek(pos)?;
+        self.at = at as usize;
+        Ok(at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(algorithm: Algorithm, input: &[u8]) -> String {
+        let mut digest = Digest::new(algorithm);
+        digest.update(input);
+        digest.finish_hex()
+    }
+
+    #[test]
+    fn sha256_known_vectors() {
+        assert_eq!(
+            hex(Algorithm::Sha256, b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(Algorithm::Sha256, b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn blake3_known_vectors() {
+        assert_eq!(
+            hex(Algorithm::Blake3, b""),
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+        );
+        assert_eq!(
+            hex(Algorithm::Blake3, &[0]),
+            "2d3adedff11b61f14c886e35afa036736dcd87a74d27b5c1510225d0f592e213"
+        );
+
+        // Official vectors use the repeating 0..251 byte pattern
+        let pattern = |n: usize| (0..n).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        assert_eq!(
+            hex(Algorithm::Blake3, &pattern(1024)),
+            "42214739f095a406f3fc83deb889744ac00df831c10daa55189b5d121c855af7"
+        );
+        assert_eq!(
+            hex(Algorithm::Blake3, &pattern(1025)),
+            "d00278ae47eb27b34faecf67b4fe263f82d5412916c1ffd97c8cb7fb814b8444"
+        );
+        assert_eq!(
+            hex(Algorithm::Blake3, &pattern(2048)),
+            "e776b6028c7cd22a4d0ba182a8bf62205d2ef576467e838ed6f2529b85fba24a"
+        );
+    }
+
+    #[test]
+    fn blake3_is_split_invariant() {
+        // Exercise chunk and parent merging across odd write boundaries
+        let data: Vec<u8> = (0..5000u32).map(|i| (i % 251) as u8).collect();
+        let whole = hex(Algorithm::Blake3, &data);
+
+        let mut digest = Digest::new(Algorithm::Blake3);
+        for piece in data.chunks(333) {
+            digest.update(piece);
+        }
+        assert_eq!(digest.finish_hex(), whole);
+    }
+
+    #[test]
+    fn hashes_the_header_as_patched() {
+        let mut writer = HeaderLast::new(std::io::Cursor::new(Vec::new()), Algorithm::Sha256);
+        writer.write_all(b"HEAD????body").unwrap();
+        writer.seek(SeekFrom::Start(4)).unwrap();
+        writer.write_all(b"0008").unwrap();
+        writer.seek(SeekFrom::End(0)).unwrap();
+        let (file, checksum) = writer.finish();
+        assert_eq!(file.get_ref().as_slice(), b"HEAD0008body");
+        assert_eq!(checksum.hex, hex(Algorithm::Sha256, b"HEAD0008body"));
+    }
+}
@@ -0,0 +1,114 @@
+//! Earcons played around the speech (`--chime-before` / `--chime-after`).
+
+use crate::wav;
+use crate::SAMPLE_RATE;
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+/// The daemon's output format, which chimes are converted to
+pub const FORMAT: wav::WavFormat = wav::WavFormat { channels: 1, sample_rate: SAMPLE_RATE, bits_per_sample: 16 };
+
+/// Chime samples at the daemon's rate, mono 16-bit
+#[derive(Default)]
+pub struct Chimes {
+    pub before: Vec<i16>,
+    pub after: Vec<i16>,
+}
+
+impl Chimes {
+    /// Resolve the flag values: `Some(None)` is the built-in earcon,
+    /// `Some(Some(path))` a WAV file. Fails on unreadable files, so call
+    /// this before contacting the daemon.
+    pub fn load(before: Option<&Option<String>>, after: Option<&Option<String>>) -> Result<Self> {
+        let resolve = |flag: Option<&Option<String>>, rising: bool| -> Result<Vec<i16>> {
+            match flag {
+                None => Ok(Vec::new()),
+                Some(None) => Ok(builtin(rising)),
+                Some(Some(path)) => decode(Path::new(path)),
+            }
+        };
+        Ok(Chimes { before: resolve(before, true)?, after: resolve(after, false)? })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.before.is_empty() && self.after.is_empty()
+    }
+
+    /// Wrap the daemon's PCM so the chimes land in the same stream.
+    pub fn wrap<'a, R: Read + 'a>(&self, audio: R) -> impl Read + 'a {
+        std::io::Cursor::new(to_bytes(&self.before))
+            .chain(audio)
+            .chain(std::io::Cursor::new(to_bytes(&self.after)))
+    }
+}
+
+fn to_bytes(samples: &[i16]) -> Vec<u8> {
+    samples.iter().flat_map(|s| s.to_le_bytes()).collect()
+}
+
+/// Two short tones: rising before the speech, falling after it.
+fn builtin(rising: bool) -> Vec<i16> {
+    let (first, second) = if rising { (660.0, 880.0) } else { (880.0, 660.0) };
+    let mut out = Vec::new();
+    for freq in [first, second] {
+        out.extend(tone(freq, 0.12));
+        out.extend(std::iter::repeat_n(0, SAMPLE_RATE as usize / 50));
+    }
+    // A beat of silence so the chime doesn't run into the first word
+    if rising {
+        out.extend(std::iter::repeat_n(0, SAMPLE_RATE as usize / 10));
+    }
+    out
+}
+
+fn tone(freq: f32, secs: f32) -> impl Iterator<Item = i16> {
+    let rate = SAMPLE_RATE as f32;
+    let len = (secs * rate) as usize;
+    let attack = rate * 0.005;
+    (0..len).map(move |i| {
+        let t = i as f32 / rate;
+        let gain = (i as f32 / attack).min(1.0) * (-t * 25.0).exp();
+        let s = (2.0 * std::f32::consts::PI * freq * t).sin() * gain * 0.3;
+        (s * i16::MAX as f32) as i16
+    })
+}
+
+/// Decode a WAV file to the daemon's format.
+fn decode(path: &Path) -> Result<Vec<i16>> {
+    let file = File::open(path).with_context(|| format!("Cannot open chime {}", path.display()))?;
+    wav::decode(&mut BufReader::new(file), SAMPLE_RATE).with_context(|| format!("Invalid chime {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_and_resamples_stereo_wav() {
+        let path = std::env::temp_dir().join(format!("speakturbo-chime-{}.wav", std::process::id()));
+        let format = wav::WavFormat { channels: 2, sample_rate: 48000, bits_per_sample: 16 };
+        let mut bytes = Vec::new();
+        wav::write_header(&mut bytes, format, 4800 * 4).unwrap();
+        for _ in 0..4800 {
+            bytes.extend_from_slice(&1000i16.to_le_bytes());
+            bytes.extend_from_slice(&3000i16.to_le_bytes());
+        }
+        std::fs::write(&path, bytes).unwrap();
+
+        let samples = decode(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(samples.len(), 2400);
+        assert!(samples.iter().all(|&s| s == 2000));
+    }
+
+    #[test]
+    fn rejects_non_wav() {
+        let path = std::env::temp_dir().join(format!("speakturbo-chime-bad-{}.wav", std::process::id()));
+        std::fs::write(&path, b"not a wav file at all").unwrap();
+        let err = decode(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(format!("{:#}", err).contains("Invalid chime"));
+    }
+}
@@ -0,0 +1,165 @@
+//! `--clipboard`, `--primary-selection` and `--watch-clipboard`: read the
+//! system clipboard through the platform's own tool, picked at runtime
+//! (wl-paste, xclip/xsel, pbpaste, PowerShell).
+
+use crate::stream::Record;
+use anyhow::{bail, Result};
+use std::io::ErrorKind;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+// How often --watch-clipboard looks; one short-lived process per look
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Selection {
+    /// What was explicitly copied (CLIPBOARD on X11)
+    Clipboard,
+    /// What was last selected with the mouse, X11 and Wayland only
+    Primary,
+}
+
+impl Selection {
+    fn flag(self) -> &'static str {
+        match self {
+            Selection::Clipboard => "--clipboard",
+            Selection::Primary => "--primary-selection",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Selection::Clipboard => "Clipboard",
+            Selection::Primary => "Primary selection",
+        }
+    }
+}
+
+enum Content {
+    Text(String),
+    Empty,
+    /// Holds something else, e.g. an image; the tool's reason if it gave one
+    NotText(Option<String>),
+}
+
+/// The selection's text, trimmed. Fails when it's empty, holds something
+/// other than text, or isn't reachable.
+pub fn read(selection: Selection) -> Result<String> {
+    let name = selection.name();
+    match fetch(selection)? {
+        Content::Text(text) => Ok(text),
+        Content::Empty => bail!("{} is empty", name),
+        Content::NotText(Some(reason)) => bail!("{} holds no text ({})", name, reason),
+        Content::NotText(None) => bail!("{} holds no text", name),
+    }
+}
+
+/// Each new clipboard text as it's copied; --debounce-ms waits for a run
+/// of copies to settle (see [`crate::burst`]). The value present at
+/// startup and repeats of the last one are not sent.
+pub fn watch() -> Result<Receiver<Record>> {
+    // Fails here if no clipboard is reachable at all
+    let mut last = match fetch(Selection::Clipboard)? {
+        Content::Text(text) => Some(text),
+        _ => None,
+    };
+    let (tx, rx) = mpsc::sync_channel(1);
+    std::thread::spawn(move || {
+        let mut n = 0;
+        loop {
+            std::thread::sleep(POLL_INTERVAL);
+            let current = match fetch(Selection::Clipboard) {
+                Ok(Content::Text(text)) => text,
+                _ => continue,
+            };
+            if last.as_ref() == Some(&current) {
+                continue;
+            }
+            n += 1;
+            last = Some(current.clone());
+            if tx.send(Record { n, text: Ok(current) }).is_err() {
+                return;
+            }
+        }
+    });
+    Ok(rx)
+}
+
+fn fetch(selection: Selection) -> Result<Content> {
+    let candidates = commands(selection)?;
+    let mut missing = Vec::new();
+    for mut command in candidates {
+        let program = command.get_program().to_string_lossy().into_owned();
+        let output = match command.stdin(Stdio::null()).output() {
+            Ok(output) => output,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                missing.push(program);
+                continue;
+            }
+            Err(e) => bail!("Cannot run {}: {}", program, e),
+        };
+        if !output.status.success() {
+            let reason = String::from_utf8_lossy(&output.stderr);
+            let reason = reason.lines().next().map(str::trim).filter(|l| !l.is_empty());
+            return Ok(Content::NotText(reason.map(|r| format!("{}: {}", program, r))));
+        }
+        let Ok(text) = String::from_utf8(output.stdout) else {
+            return Ok(Content::NotText(Some("not valid UTF-8".to_string())));
+        };
+        let text = text.trim();
+        if text.is_empty() {
+            return Ok(Content::Empty);
+        }
+        return Ok(Content::Text(text.to_string()));
+    }
+    bail!("No clipboard tool found (tried {}); install one to use {}", missing.join(", "), selection.flag())
+}
+
+/// Wayland first when both are set: XWayland's selections can be stale.
+/// wl-paste reads PRIMARY through the wlr data-control protocol.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn commands(selection: Selection) -> Result<Vec<Command>> {
+    let set = |var: &str| std::env::var_os(var).is_some_and(|v| !v.is_empty());
+    let primary = selection == Selection::Primary;
+    if set("WAYLAND_DISPLAY") {
+        let mut wl_paste = Command::new("wl-paste");
+        wl_paste.args(["--no-newline", "--type", "text"]);
+        if primary {
+            wl_paste.arg("--primary");
+        }
+        return Ok(vec![wl_paste]);
+    }
+    if set("DISPLAY") {
+        let mut xclip = Command::new("xclip");
+        xclip.args(["-selection", if primary { "primary" } else { "clipboard" }]);
+        xclip.args(["-out", "-target", "UTF8_STRING"]);
+        let mut xsel = Command::new("xsel");
+        xsel.args([if primary { "--primary" } else { "--clipboard" }, "--output"]);
+        return Ok(vec![xclip, xsel]);
+    }
+    bail!("No clipboard reachable: neither WAYLAND_DISPLAY nor DISPLAY is set (SSH session?)")
+}
+
+#[cfg(target_os = "macos")]
+fn commands(selection: Selection) -> Result<Vec<Command>> {
+    if selection == Selection::Primary {
+        bail!("macOS has no primary selection; use --clipboard after copying");
+    }
+    Ok(vec![Command::new("pbpaste")])
+}
+
+#[cfg(windows)]
+fn commands(selection: Selection) -> Result<Vec<Command>> {
+    if selection == Selection::Primary {
+        bail!("Windows has no primary selection; use --clipboard after copying");
+    }
+    let mut powershell = Command::new("powershell");
+    powershell.args(["-NoProfile", "-Command", "Get-Clipboard -Raw"]);
+    Ok(vec![powershell])
+}
+
+#[cfg(not(any(unix, windows)))]
+fn commands(selection: Selection) -> Result<Vec<Command>> {
+    bail!("{} is not supported on this platform", selection.flag())
+}
@@ -0,0 +1,243 @@
+//! `speakturbo compare --voices a,b,c TEXT`: the same text in each voice,
+//! played in order with a label before each, then how quickly each voice
+//! answered. --jobs syntheses run at once (one by default, so that the
+//! timings don't compete) while playback keeps to the order.
+//!
+//! --randomize shuffles the order and labels the takes "Sample 1", "Sample
+//! 2"..., naming their voices only in the key at the end.
+
+use crate::exit::{self, Kind};
+use crate::reporter::{self, Mark};
+use crate::{chime, config, interrupt, pick, sequence, wav, Args, Shown};
+use anyhow::{Context, Result};
+use speakturbo_client::cache::Cache;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+pub struct Options<'a> {
+    pub voices: &'a [String],
+    pub print_labels: bool,
+    pub randomize: bool,
+    /// Also save each take here, named after its voice
+    pub dir: Option<&'a Path>,
+}
+
+/// A voice's synthesis of the text
+struct Take {
+    /// The daemon's stream, header and all
+    stream: Vec<u8>,
+    /// The label, spoken, unless it's printed
+    label: Option<Vec<u8>>,
+    /// From the request to the first audio
+    latency: Duration,
+    /// From the request to the end of the audio
+    took: Duration,
+    hit: bool,
+}
+
+impl Take {
+    fn audio_secs(&self) -> f64 {
+        self.stream.len().saturating_sub(wav::HEADER_LEN as usize) as f64 / 2.0 / crate::SAMPLE_RATE as f64
+    }
+}
+
+pub fn run(args: &mut Args, text: &str, options: Options, start: Instant) -> Result<()> {
+    crate::playback::check()?;
+    let mut voices: Vec<String> = options.voices.iter().map(|v| config::resolve(&args.aliases, v).to_string()).collect();
+    if options.randomize {
+        pick::shuffle(&mut voices, args.seed)?;
+    }
+    let labels: Vec<String> = match options.randomize {
+        true => (1..=voices.len()).map(|n| format!("Sample {}", n)).collect(),
+        false => voices.iter().map(|v| capitalized(v)).collect(),
+    };
+    if let Some(dir) = options.dir {
+        std::fs::create_dir_all(dir).with_context(|| format!("Cannot create {}", dir.display()))?;
+    }
+    // Each line's speaker is its voice, as in preview; labels are in the invocation's
+    let text = crate::normalized(args, text.split_whitespace().collect::<Vec<_>>().join(" "));
+    args.dialogue = true;
+    args.cast = voices.iter().chain([&args.voice]).map(|v| (v.clone(), v.clone())).collect();
+    let args = &*args;
+
+    interrupt::install();
+    let cache = crate::open_cache(args);
+    let workers = (args.jobs.unwrap_or(1) as usize).clamp(1, voices.len().max(1));
+    let next = AtomicUsize::new(0);
+    let (tx, rx) = mpsc::channel();
+    let mut results: Vec<Option<Result<Take>>> = (0..voices.len()).map(|_| None).collect();
+    std::thread::scope(|scope| -> Result<()> {
+        for _ in 0..workers {
+            let tx = tx.clone();
+            let (next, voices, labels, text, cache) = (&next, &voices, &labels, &text, cache.as_ref());
+            scope.spawn(move || loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= voices.len() || interrupt::requested() {
+                    break;
+                }
+                let take = synthesize(args, cache, &voices[i], text, (!options.print_labels).then_some(&labels[i]));
+                if tx.send((i, take)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(tx);
+
+        for i in 0..voices.len() {
+            while results[i].is_none() {
+                match rx.recv() {
+                    Ok((n, take)) => results[n] = Some(take),
+                    Err(_) => return Ok(()),
+                }
+            }
+            if interrupt::requested() {
+                return Ok(());
+            }
+            let take = match &results[i] {
+                Some(Ok(take)) => take,
+                Some(Err(e)) => {
+                    reporter::error(format_args!("{} {}: {:#}", reporter::mark(Mark::Failed), labels[i], e));
+                    continue;
+                }
+                None => unreachable!(),
+            };
+            if let Some(dir) = options.dir {
+                save(&dir.join(format!("{}.wav", voices[i])), &take.stream)?;
+            }
+            if i > 0 {
+                std::thread::sleep(crate::gap(args, 0));
+            }
+            match &take.label {
+                Some(label) => play(label.clone(), start)?,
+                None => reporter::info(format_args!("{} {}", reporter::mark(Mark::Playing), labels[i])),
+            }
+            play(take.stream.clone(), start)?;
+        }
+        Ok(())
+    })?;
+    if interrupt::requested() {
+        std::process::exit(Kind::Interrupted.code());
+    }
+
+    let rows: Vec<(&str, &str, Option<&Take>)> = (0..voices.len())
+        .map(|i| (labels[i].as_str(), voices[i].as_str(), results[i].as_ref().and_then(|r| r.as_ref().ok())))
+        .collect();
+    for line in summary(&rows, options.randomize) {
+        reporter::info(line);
+    }
+    if let Some(dir) = options.dir {
+        reporter::info(format_args!("Saved in {}, a file per voice", dir.display()));
+    }
+    let failures: Vec<Kind> = results.iter().filter_map(|r| r.as_ref()?.as_ref().err()).map(Kind::of).collect();
+    match failures.len() {
+        0 => Ok(()),
+        n if n < voices.len() => Err(exit::fail(Kind::Partial, format!("{} of {} voices failed", n, voices.len()))),
+        n => Err(exit::fail(sequence::shared(&failures), format!("{} of {} voices failed", n, voices.len()))),
+    }
+}
+
+/// `text` in `voice`, timed, and `label` in the invocation's voice
+fn synthesize(args: &Args, cache: Option<&Cache>, voice: &str, text: &str, label: Option<&String>) -> Result<Take> {
+    let read_all = |line: String| -> Result<Vec<u8>> {
+        let (mut audio, _, _) = crate::open_audio(args, cache, &line)?;
+        let mut stream = Vec::new();
+        audio.read_to_end(&mut stream)?;
+        Ok(stream)
+    };
+    let label = label.map(|label| read_all(format!("{}: {}.", args.voice, label))).transpose()?;
+    let began = Instant::now();
+    let (mut audio, _, hit) = crate::open_audio(args, cache, &format!("{}: {}", voice, text))?;
+    let mut stream = Vec::new();
+    let mut latency = None;
+    let mut chunk = [0u8; 8192];
+    loop {
+        match audio.read(&mut chunk)? {
+            0 => break,
+            n => stream.extend_from_slice(&chunk[..n]),
+        }
+        if latency.is_none() && stream.len() > wav::HEADER_LEN as usize {
+            latency = Some(began.elapsed());
+        }
+    }
+    let took = began.elapsed();
+    Ok(Take { stream, label, latency: latency.unwrap_or(took), took, hit })
+}
+
+fn play(stream: Vec<u8>, start: Instant) -> Result<()> {
+    let samples = stream.len().saturating_sub(wav::HEADER_LEN as usize) as u64 / 2;
+    let audio: crate::AudioStream = Box::new(Cursor::new(stream));
+    crate::stream_audio(audio, Shown::Position(Some(samples)), start, true, None, chime::Chimes::default(), usize::MAX)?;
+    Ok(())
+}
+
+/// The daemon's stream as a WAV file, its sizes filled in. Its name
+/// isn't shown, which would give a --randomize sample away.
+fn save(path: &Path, stream: &[u8]) -> Result<()> {
+    let pcm = stream.get(wav::HEADER_LEN as usize..).unwrap_or_default();
+    let mut file = Vec::with_capacity(stream.len());
+    wav::write_header(&mut file, chime::FORMAT, pcm.len() as u32)?;
+    file.extend_from_slice(pcm);
+    std::fs::write(path, file).with_context(|| format!("Cannot write {}", path.display()))
+}
+
+fn capitalized(voice: &str) -> String {
+    let mut chars = voice.chars();
+    chars.next().into_iter().flat_map(char::to_uppercase).chain(chars).collect()
+}
+
+/// A line per take, as (label, voice, take if it was synthesized): how
+/// soon its audio began and how long it took; with --randomize, a key.
+fn summary(rows: &[(&str, &str, Option<&Take>)], randomize: bool) -> Vec<String> {
+    let name = |label: &str, voice: &str| match randomize {
+        true => format!("{} was {}", label, voice),
+        false => voice.to_string(),
+    };
+    let width = rows.iter().map(|(label, voice, _)| name(label, voice).chars().count()).max().unwrap_or(0);
+    rows.iter()
+        .map(|(label, voice, take)| {
+            let timing = match take {
+                Some(take) => format!(
+                    "first audio after {} ms, {:.1}s of audio in {:.1}s{}",
+                    take.latency.as_millis(),
+                    take.audio_secs(),
+                    take.took.as_secs_f64(),
+                    if take.hit { " (cached)" } else { "" }
+                ),
+                None => "failed".to_string(),
+            };
+            format!("{:<width$}  {}", name(label, voice), timing, width = width)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn take(latency_ms: u64, samples: usize, took_ms: u64, hit: bool) -> Take {
+        let stream = vec![0u8; wav::HEADER_LEN as usize + samples * 2];
+        Take { stream, label: None, latency: Duration::from_millis(latency_ms), took: Duration::from_millis(took_ms), hit }
+    }
+
+    #[test]
+    fn sums_up_each_voice() {
+        let (alba, javert) = (take(212, 57600, 1100, false), take(9, 24000, 20, true));
+        let rows = [("Alba", "alba", Some(&alba)), ("Marius", "marius", None), ("Javert", "javert", Some(&javert))];
+        assert_eq!(
+            summary(&rows, false),
+            [
+                "alba    first audio after 212 ms, 2.4s of audio in 1.1s",
+                "marius  failed",
+                "javert  first audio after 9 ms, 1.0s of audio in 0.0s (cached)",
+            ]
+        );
+        let rows = [("Sample 1", "javert", Some(&javert)), ("Sample 2", "alba", Some(&alba))];
+        assert_eq!(
+            summary(&rows, true),
+            ["Sample 1 was javert  first audio after 9 ms, 1.0s of audio in 0.0s (cached)", "Sample 2 was alba    first audio after 212 ms, 2.4s of audio in 1.1s"]
+        );
+    }
+}
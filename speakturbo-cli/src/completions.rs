@@ -0,0 +1,355 @@
+//! `speakturbo completions SHELL`: a completion script for bash, zsh or
+//! fish, written from the command's own flags and subcommands. Values of
+//! --voice (and --device) are completed at tab time by the hidden
+//! `speakturbo __complete voices|devices`, which remembers its answer for
+//! a few seconds so that tabbing again doesn't ask the daemon again.
+
+use crate::Args;
+use clap::{Arg, CommandFactory};
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// What `__complete` lists
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Dynamic {
+    /// The daemon's voices, aliases, random and rotate
+    Voices,
+    /// Audio output devices
+    Devices,
+}
+
+impl Dynamic {
+    fn name(self) -> &'static str {
+        match self {
+            Dynamic::Voices => "voices",
+            Dynamic::Devices => "devices",
+        }
+    }
+}
+
+/// Flags whose values come from `__complete`, by clap id
+const DYNAMIC: &[(&str, Dynamic)] = &[("voice", Dynamic::Voices), ("device", Dynamic::Devices)];
+
+/// How long `__complete` reuses what it found
+pub const FRESH: Duration = Duration::from_secs(5);
+
+enum Values {
+    /// A switch
+    None,
+    /// Anything, files included
+    Any,
+    List(Vec<String>),
+    Dynamic(Dynamic),
+}
+
+struct Flag {
+    long: Option<String>,
+    short: Option<char>,
+    /// The first line of its help
+    help: String,
+    values: Values,
+    /// Allowed after a subcommand as well as before
+    global: bool,
+}
+
+impl Flag {
+    /// "--voice|-v", for a shell `case`
+    fn pattern(&self) -> String {
+        let names: Vec<String> = self.long.iter().map(|l| format!("--{}", l)).chain(self.short.map(|s| format!("-{}", s))).collect();
+        names.join("|")
+    }
+}
+
+/// The top level (named "") or one subcommand: its flags, its own
+/// subcommands with their help, and the values its arguments can take.
+struct Context {
+    name: String,
+    flags: Vec<Flag>,
+    subcommands: Vec<(String, String)>,
+}
+
+impl Context {
+    fn of(command: &clap::Command, name: &str) -> Context {
+        let flags = command.get_arguments().filter(|a| !a.is_positional() && !a.is_hide_set()).map(flag).collect();
+        let values = command
+            .get_positionals()
+            .flat_map(|a| a.get_possible_values())
+            .filter(|v| !v.is_hide_set())
+            .map(|v| (v.get_name().to_string(), first_line(v.get_help().map(|s| s.to_string()))));
+        let subcommands = command
+            .get_subcommands()
+            .filter(|c| !c.is_hide_set())
+            .map(|c| (c.get_name().to_string(), first_line(c.get_about().map(|s| s.to_string()))))
+            .chain(values)
+            .collect();
+        Context { name: name.to_string(), flags, subcommands }
+    }
+
+    /// Flags and subcommand names, space-separated
+    fn words(&self) -> String {
+        let flags = self.flags.iter().flat_map(|f| f.long.iter().map(|l| format!("--{}", l)).chain(f.short.map(|s| format!("-{}", s))));
+        let names = self.subcommands.iter().map(|(name, _)| name.clone());
+        names.chain(flags).collect::<Vec<_>>().join(" ")
+    }
+}
+
+fn flag(arg: &Arg) -> Flag {
+    let id = arg.get_id().as_str();
+    let values = match DYNAMIC.iter().find(|(flag, _)| *flag == id) {
+        Some((_, dynamic)) => Values::Dynamic(*dynamic),
+        None if !arg.get_action().takes_values() => Values::None,
+        None => match arg.get_possible_values().as_slice() {
+            [] => Values::Any,
+            values => Values::List(values.iter().filter(|v| !v.is_hide_set()).map(|v| v.get_name().to_string()).collect()),
+        },
+    };
+    Flag {
+        long: arg.get_long().map(str::to_string),
+        short: arg.get_short(),
+        help: first_line(arg.get_help().map(|s| s.to_string())),
+        values,
+        global: arg.is_global_set(),
+    }
+}
+
+fn first_line(text: Option<String>) -> String {
+    text.unwrap_or_default().lines().next().unwrap_or_default().to_string()
+}
+
+/// The top level and each subcommand
+fn contexts() -> Vec<Context> {
+    let command = Args::command();
+    let mut contexts = vec![Context::of(&command, "")];
+    contexts.extend(command.get_subcommands().filter(|c| !c.is_hide_set()).map(|c| Context::of(c, c.get_name())));
+    contexts
+}
+
+pub fn script(shell: Shell) -> String {
+    let program = Args::command().get_name().to_string();
+    let contexts = contexts();
+    match shell {
+        Shell::Bash => bash(&program, &contexts),
+        Shell::Zsh => zsh(&program, &contexts),
+        Shell::Fish => fish(&program, &contexts),
+    }
+}
+
+/// `case` arms completing flag values: from `__complete`, or a fixed list.
+/// `dynamic` and `list` turn a command or the words into the shell's
+/// completion.
+fn value_arms(program: &str, contexts: &[Context], dynamic: impl Fn(String) -> String, list: impl Fn(String) -> String) -> String {
+    let mut arms = String::new();
+    let mut seen = Vec::new();
+    for flag in contexts.iter().flat_map(|c| &c.flags) {
+        let reply = match &flag.values {
+            Values::Dynamic(what) => dynamic(format!("{} __complete {} 2>/dev/null", program, what.name())),
+            Values::List(values) => list(values.join(" ")),
+            Values::None | Values::Any => continue,
+        };
+        let pattern = flag.pattern();
+        if !seen.contains(&pattern) {
+            let _ = writeln!(arms, "        {}) {}; return ;;", pattern, reply);
+            seen.push(pattern);
+        }
+    }
+    arms
+}
+
+/// The subcommand names, for a `case`
+fn subcommand_pattern(contexts: &[Context]) -> String {
+    contexts.iter().filter(|c| !c.name.is_empty()).map(|c| c.name.as_str()).collect::<Vec<_>>().join("|")
+}
+
+fn bash(program: &str, contexts: &[Context]) -> String {
+    let function = format!("_{}", program.replace('-', "_"));
+    let arms = value_arms(
+        program,
+        contexts,
+        |command| format!("COMPREPLY=($(compgen -W \"$({})\" -- \"$cur\"))", command),
+        |words| format!("COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))", words),
+    );
+    let mut words = String::new();
+    // The top level last, as the catch-all
+    for context in contexts.iter().rev() {
+        let label = if context.name.is_empty() { "*".to_string() } else { context.name.clone() };
+        let _ = writeln!(words, "        {}) words=\"{}\" ;;", label, context.words());
+    }
+    format!(
+        "{function}() {{
+    local cur=${{COMP_WORDS[COMP_CWORD]}} prev=${{COMP_WORDS[COMP_CWORD-1]}} sub= words i
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        case ${{COMP_WORDS[i]}} in
+            {subcommands}) sub=${{COMP_WORDS[i]}}; break ;;
+        esac
+    done
+    case $prev in
+{arms}    esac
+    case $sub in
+{words}    esac
+    COMPREPLY=($(compgen -W \"$words\" -- \"$cur\"))
+}}
+complete -o default -F {function} {program}
+",
+        subcommands = subcommand_pattern(contexts),
+    )
+}
+
+fn zsh(program: &str, contexts: &[Context]) -> String {
+    let function = format!("_{}", program.replace('-', "_"));
+    let arms = value_arms(
+        program,
+        contexts,
+        |command| format!("compadd -- ${{(f)\"$({})\"}}", command),
+        |words| format!("compadd -- {}", words),
+    );
+    let mut words = String::new();
+    for context in contexts.iter().rev() {
+        let label = if context.name.is_empty() { "*".to_string() } else { context.name.clone() };
+        let _ = writeln!(words, "        {}) compadd -- {} ;;", label, context.words());
+    }
+    format!(
+        "#compdef {program}
+{function}() {{
+    local sub= i
+    for ((i = 2; i < CURRENT; i++)); do
+        case $words[i] in
+            {subcommands}) sub=$words[i]; break ;;
+        esac
+    done
+    case $words[CURRENT-1] in
+{arms}    esac
+    case $sub in
+{words}    esac
+    [[ $PREFIX == -* ]] || _files
+}}
+compdef {function} {program}
+",
+        subcommands = subcommand_pattern(contexts),
+    )
+}
+
+fn fish(program: &str, contexts: &[Context]) -> String {
+    let quote = |text: &str| format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"));
+    let mut out = String::new();
+    for context in contexts {
+        let condition = match context.name.as_str() {
+            "" => "__fish_use_subcommand".to_string(),
+            name => format!("__fish_seen_subcommand_from {}", name),
+        };
+        for (name, help) in &context.subcommands {
+            let _ = writeln!(out, "complete -c {} -n {} -f -a {} -d {}", program, quote(&condition), name, quote(help));
+        }
+        for flag in &context.flags {
+            let mut line = format!("complete -c {}", program);
+            if !flag.global {
+                let _ = write!(line, " -n {}", quote(&condition));
+            }
+            if let Some(long) = &flag.long {
+                let _ = write!(line, " -l {}", long);
+            }
+            if let Some(short) = flag.short {
+                let _ = write!(line, " -s {}", short);
+            }
+            match &flag.values {
+                Values::None => {}
+                Values::Any => line.push_str(" -r"),
+                Values::List(values) => {
+                    let _ = write!(line, " -x -a {}", quote(&values.join(" ")));
+                }
+                Values::Dynamic(what) => {
+                    let _ = write!(line, " -x -a {}", quote(&format!("({} __complete {} 2>/dev/null)", program, what.name())));
+                }
+            }
+            let _ = writeln!(line, " -d {}", quote(&flag.help));
+            out.push_str(&line);
+        }
+    }
+    out
+}
+
+/// `speakturbo __complete`: one value per line, from a list saved in
+/// `dir` less than [`FRESH`] ago when there is one.
+pub fn complete(what: Dynamic, aliases: &[(String, String)], dir: Option<&Path>) {
+    let fetch = || match what {
+        Dynamic::Voices => {
+            let mut voices = crate::daemon_voices().unwrap_or_else(|| crate::VOICES.iter().map(|v| v.to_string()).collect());
+            voices.extend(aliases.iter().map(|(alias, _)| alias.clone()));
+            voices.extend(["random".to_string(), "rotate".to_string()]);
+            voices
+        }
+        Dynamic::Devices => crate::playback::devices(),
+    };
+    let values = match dir {
+        Some(dir) => cached(&dir.join(format!("complete-{}", what.name())), FRESH, fetch),
+        None => fetch(),
+    };
+    for value in values {
+        println!("{}", value);
+    }
+}
+
+/// What's saved at `path` if it was saved within `fresh`; else what
+/// `fetch` finds, saved there for next time.
+fn cached(path: &Path, fresh: Duration, fetch: impl FnOnce() -> Vec<String>) -> Vec<String> {
+    let age = fs::metadata(path).and_then(|m| m.modified()).ok().and_then(|t| SystemTime::now().duration_since(t).ok());
+    if age.is_some_and(|age| age < fresh) {
+        if let Ok(saved) = fs::read_to_string(path) {
+            return saved.lines().map(str::to_string).collect();
+        }
+    }
+    let values = fetch();
+    if let Some(dir) = path.parent() {
+        // Only a speed-up: completion goes on without it
+        let _ = fs::create_dir_all(dir).and_then(|()| fs::write(path, values.join("\n") + "\n"));
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_scripts_from_the_command() {
+        let bash = script(Shell::Bash);
+        assert!(bash.contains("        --voice|-v) COMPREPLY=($(compgen -W \"$(speakturbo __complete voices 2>/dev/null)\" -- \"$cur\")); return ;;\n"));
+        assert!(bash.contains("        --emoji) COMPREPLY=($(compgen -W \"strip keep speak\" -- \"$cur\")); return ;;\n"));
+        assert!(bash.contains("--max-buffer-ms"));
+        assert!(bash.contains("complete -o default -F _speakturbo speakturbo\n"));
+        assert!(!bash.contains("__complete)"), "the hidden subcommand isn't offered");
+        assert!(bash.contains("        completions) words=\"bash zsh fish\" ;;\n"));
+
+        let zsh = script(Shell::Zsh);
+        assert!(zsh.starts_with("#compdef speakturbo\n"));
+        assert!(zsh.contains("--voice|-v) compadd -- ${(f)\"$(speakturbo __complete voices 2>/dev/null)\"}; return ;;"));
+
+        let fish = script(Shell::Fish);
+        assert!(fish.contains(
+            "complete -c speakturbo -l voice -s v -x -a '(speakturbo __complete voices 2>/dev/null)' -d "
+        ));
+        assert!(fish.contains("complete -c speakturbo -n '__fish_use_subcommand' -f -a preview -d 'Hear a sample phrase in each voice, one after another'\n"));
+        assert!(fish.contains("complete -c speakturbo -l quiet -s q -d 'Quiet mode - -q leaves warnings and errors, -qq only the exit status'\n"));
+        assert!(fish.contains("-n '__fish_seen_subcommand_from cache' -f -a prune"));
+    }
+
+    #[test]
+    fn reuses_fresh_lists() {
+        let dir = std::env::temp_dir().join(format!("speakturbo-complete-{}", std::process::id()));
+        let path = dir.join("complete-voices");
+        let list = |names: &[&str]| names.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(cached(&path, FRESH, || list(&["alba", "jean"])), list(&["alba", "jean"]));
+        assert_eq!(cached(&path, FRESH, || panic!("asked again")), list(&["alba", "jean"]));
+        // Stale: asked again
+        assert_eq!(cached(&path, Duration::ZERO, || list(&["marius"])), list(&["marius"]));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,625 @@
+//! The config file, `$XDG_CONFIG_HOME/speakturbo/config.toml` (or
+//! `--config`). It holds defaults for flags, by their long names (see
+//! [`crate::settings`]); `[profile.NAME]`, sets of defaults picked with
+//! --profile that replace the top-level ones; `[[rules]]`, regex
+//! substitutions applied in order to every text before anything else;
+//! `[aliases]`, names of your own for voices; `[voices.by-language]`, the
+//! voice for each language, for --lang and --auto-voice;
+//! `[dialogue.cast]`, the voices of --dialogue characters;
+//! `[phrases]`, texts for `speakturbo say NAME`; and `[notifications]`,
+//! the apps `speakturbo notify-listen` reads aloud, and how.
+//!
+//! ```toml
+//! voice = "deep"
+//! max-buffer-ms = 5000
+//! no-cache = true
+//!
+//! [profile.narration]
+//! max-buffer-ms = 60000
+//! chunk-chars = 800
+//!
+//! [profile.audiobook]
+//! inherits = "narration"          # its settings first, then these
+//! voice = "jean"
+//!
+//! [[rules]]
+//! name = "ticket ids"
+//! pattern = '\[[A-Z]+-\d+\] ?'
+//!
+//! [[rules]]
+//! pattern = 'k8s'
+//! replacement = "kubernetes"
+//! flags = "i"                    # i: ignore case, s: '.' matches newlines, m: '^ $' at lines
+//!
+//! [aliases]
+//! deep = "javert"                 # alias = voice, or an alias of one
+//!
+//! [voices.by-language]
+//! en = "alba"                     # ISO 639-1 language code = voice
+//! fr = "javert"
+//!
+//! [dialogue.cast]
+//! GUARD = "javert"                # character = voice
+//!
+//! [phrases]
+//! build-done = "{project} built."   # {name}: another phrase's text
+//! project = "Speak Turbo"
+//! alarm = { text = "Wake up!", voice = "jean" }
+//!
+//! [notifications]
+//! include = ["Slack", "Thunderbird"]   # only these apps (default: all)
+//! exclude = ["Spotify"]
+//! template = "{app}: {summary}. {body}"
+//! ```
+
+use crate::{reporter, text};
+use anyhow::{bail, Context, Result};
+use indexmap::IndexMap;
+use regex::Regex;
+use serde::Deserialize;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use toml::Spanned;
+
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    /// Where it was read from, if anywhere
+    pub path: Option<PathBuf>,
+    /// Defaults for flags, in the file's order
+    pub flags: Vec<Flag>,
+    /// Settings nobody knows, to warn about
+    pub warnings: Vec<String>,
+    /// `[profile.NAME]`, in the file's order
+    pub profiles: Vec<Profile>,
+    pub rules: Vec<Rule>,
+    /// (language code, voice)
+    pub by_language: Vec<(String, String)>,
+    /// (character, voice)
+    pub cast: Vec<(String, String)>,
+    /// (alias, the voice it stands for)
+    pub aliases: Vec<(String, String)>,
+    /// `[phrases]`, in the file's order
+    pub phrases: Vec<Phrase>,
+    pub notifications: Notifications,
+}
+
+/// `max-buffer-ms = 5000`
+#[derive(Clone, Debug)]
+pub struct Flag {
+    pub key: String,
+    /// As it would be given on the command line
+    pub value: String,
+    /// "path:line", for messages
+    pub at: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct Profile {
+    pub name: String,
+    /// The profile whose settings it starts from
+    inherits: Option<String>,
+    flags: Vec<Flag>,
+}
+
+/// `build-done = "Build finished."`
+#[derive(Clone, Debug)]
+pub struct Phrase {
+    pub name: String,
+    /// With any `{other}` phrases expanded
+    pub text: String,
+    pub voice: Option<String>,
+}
+
+/// `[notifications]`
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[cfg_attr(not(feature = "dbus"), allow(dead_code))]
+pub struct Notifications {
+    /// Apps to read, by name; empty for all
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    /// With {app}, {summary} and {body}
+    pub template: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Rule {
+    /// `rule 2 "ticket ids" (config.toml:7)`, for messages
+    label: String,
+    regex: Regex,
+    /// With `$1` and so on for the pattern's groups
+    replacement: String,
+}
+
+/// `$XDG_CONFIG_HOME/speakturbo/config.toml`, falling back to
+/// `~/.config/speakturbo/config.toml` (Roaming AppData on Windows).
+pub fn default_path() -> Option<PathBuf> {
+    speakturbo_client::dirs::config().map(|base| base.join("speakturbo").join("config.toml"))
+}
+
+/// The config at `path`, or else at the default path if there's a file
+/// there; empty without either.
+pub fn load(path: Option<&Path>) -> Result<Config> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => match default_path().filter(|p| p.exists()) {
+            Some(path) => path,
+            None => return Ok(Config::default()),
+        },
+    };
+    let text = std::fs::read_to_string(&path).with_context(|| format!("Can't read the config {}", path.display()))?;
+    let config = parse(&text, &path.display().to_string())?;
+    Ok(Config { path: Some(path), ..config })
+}
+
+impl Config {
+    /// The flag defaults with `profile` (and what it inherits) over the
+    /// top-level ones.
+    pub fn flags(&self, profile: Option<&str>) -> Result<Vec<Flag>, String> {
+        let mut flags = self.flags.clone();
+        let Some(name) = profile else {
+            return Ok(flags);
+        };
+        let find = |name: &str| self.profiles.iter().find(|p| p.name == name);
+        let Some(profile) = find(name) else {
+            let names: Vec<&str> = self.profiles.iter().map(|p| p.name.as_str()).collect();
+            return Err(match names.as_slice() {
+                [] => format!("unknown profile '{}'; the config has no [profile.NAME] sections", name),
+                _ => format!("unknown profile '{}'; the config has {}", name, names.join(", ")),
+            });
+        };
+        // Bases first, so each profile overrides what it inherits; parse
+        // has ruled out cycles and unknown bases
+        let mut chain = vec![profile];
+        while let Some(base) = chain[chain.len() - 1].inherits.as_deref().and_then(find) {
+            chain.push(base);
+        }
+        for profile in chain.iter().rev() {
+            for flag in &profile.flags {
+                match flags.iter_mut().find(|f| f.key == flag.key) {
+                    Some(old) => *old = flag.clone(),
+                    None => flags.push(flag.clone()),
+                }
+            }
+        }
+        Ok(flags)
+    }
+}
+
+/// Where a key or value is, as "path:line", for messages
+struct Source<'a> {
+    path: &'a str,
+    text: &'a str,
+}
+
+impl Source<'_> {
+    fn at(&self, span: Range<usize>) -> String {
+        format!("{}:{}", self.path, text::line_at(self.text, span.start))
+    }
+
+    fn error(&self, e: toml::de::Error) -> anyhow::Error {
+        anyhow::anyhow!("{}: {}", self.at(e.span().unwrap_or(0..0)), e.message())
+    }
+}
+
+/// A table's keys and values in the file's order, each with its place
+type Entries<T> = IndexMap<Spanned<String>, Spanned<T>>;
+
+/// The tables a config may have besides flag defaults
+const SECTIONS: &[&str] = &["profile", "rules", "aliases", "voices", "dialogue", "phrases", "notifications"];
+
+/// The file's tables, as written
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Sections {
+    profile: Entries<Entries<toml::Value>>,
+    rules: Vec<Spanned<RuleFields>>,
+    aliases: Entries<String>,
+    voices: Voices,
+    dialogue: Dialogue,
+    phrases: Entries<toml::Value>,
+    notifications: Notifications,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Voices {
+    #[serde(rename = "by-language")]
+    by_language: Entries<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Dialogue {
+    cast: IndexMap<String, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleFields {
+    name: Option<String>,
+    pattern: Option<String>,
+    #[serde(default)]
+    replacement: String,
+    #[serde(default)]
+    flags: String,
+}
+
+/// `alarm = { text = "Wake up!", voice = "jean" }`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PhraseFields {
+    text: String,
+    voice: Option<String>,
+}
+
+fn parse(text: &str, path: &str) -> Result<Config> {
+    let src = Source { path, text };
+    // Read twice: once for the flag defaults, whatever their names, and
+    // once for the tables
+    let top: Entries<toml::Value> = toml::from_str(text).map_err(|e| src.error(e))?;
+    let sections: Sections = toml::from_str(text).map_err(|e| src.error(e))?;
+    let mut config = Config::default();
+    for (key, value) in &top {
+        if SECTIONS.contains(&key.get_ref().as_str()) {
+            continue;
+        }
+        if crate::settings::is_setting(key.get_ref()) {
+            config.flags.push(flag(key, value, &src, "")?);
+            continue;
+        }
+        const EXPECTED: &str = "a flag's name, [profile.NAME], [[rules]], [aliases], [voices.by-language], [dialogue.cast], [phrases] or [notifications]";
+        config.warnings.push(unknown(key.get_ref(), &src.at(key.span()), EXPECTED));
+    }
+    config.profiles = profiles(&sections.profile, &src, &mut config.warnings)?;
+    for (i, fields) in sections.rules.iter().enumerate() {
+        config.rules.push(rule(fields, i + 1, &src)?);
+    }
+    config.aliases = aliases(&sections.aliases, &src)?;
+    config.by_language = by_language(&sections.voices.by_language, &src)?;
+    config.cast = sections.dialogue.cast.into_iter().collect();
+    config.phrases = phrases(&sections.phrases, &src)?;
+    config.notifications = sections.notifications;
+    // The tables' voices may be aliases too, whichever came first
+    for (_, voice) in config.by_language.iter_mut().chain(&mut config.cast) {
+        *voice = resolve(&config.aliases, voice).to_string();
+    }
+    for voice in config.phrases.iter_mut().filter_map(|p| p.voice.as_mut()) {
+        *voice = resolve(&config.aliases, voice).to_string();
+    }
+    Ok(config)
+}
+
+/// What a value is, for "should be a string, not an integer".
+pub fn kind(value: &toml::Value) -> String {
+    let name = value.type_str();
+    let article = if name.starts_with(['a', 'e', 'i', 'o', 'u']) { "an" } else { "a" };
+    format!("{} {}", article, name)
+}
+
+/// `[profile.NAME]` tables of flag defaults, each checked to inherit from
+/// a profile that exists and, in the end, from none.
+fn profiles(table: &Entries<Entries<toml::Value>>, src: &Source, warnings: &mut Vec<String>) -> Result<Vec<Profile>> {
+    let mut found = Vec::new();
+    for (name, settings) in table {
+        let mut profile = Profile { name: name.get_ref().clone(), inherits: None, flags: Vec::new() };
+        let label = format!(" (profile {})", profile.name);
+        for (key, value) in settings.get_ref() {
+            match (value.get_ref(), key.get_ref().as_str()) {
+                (toml::Value::String(base), "inherits") => profile.inherits = Some(base.clone()),
+                (other, "inherits") => {
+                    bail!("{}: profile.{}: inherits should be a profile name, not {}", src.at(key.span()), profile.name, kind(other));
+                }
+                (_, key_name) if crate::settings::is_setting(key_name) => {
+                    profile.flags.push(flag(key, value, src, &label)?);
+                }
+                (_, key_name) => warnings.push(unknown(key_name, &src.at(key.span()), "a flag's name or inherits")),
+            }
+        }
+        found.push((profile, src.at(name.span())));
+    }
+    let names: Vec<&str> = found.iter().map(|(p, _)| p.name.as_str()).collect();
+    let inherits = |name: &str| found.iter().find(|(p, _)| p.name == name).and_then(|(p, _)| p.inherits.as_deref());
+    for (profile, at) in &found {
+        if let Some(base) = profile.inherits.as_deref().filter(|base| !names.contains(base)) {
+            bail!("{}: profile.{}: inherits unknown profile {:?} (profiles: {})", at, profile.name, base, names.join(", "));
+        }
+        let mut chain = vec![profile.name.as_str()];
+        while let Some(next) = inherits(chain[chain.len() - 1]) {
+            let cycle = chain.contains(&next);
+            chain.push(next);
+            if cycle {
+                bail!("{}: profile.{}: inherits {} is a cycle", at, profile.name, chain.join(" → "));
+            }
+        }
+    }
+    Ok(found.into_iter().map(|(p, _)| p).collect())
+}
+
+fn flag(key: &Spanned<String>, value: &Spanned<toml::Value>, src: &Source, label: &str) -> Result<Flag> {
+    let at = src.at(key.span());
+    let value = match value.get_ref() {
+        toml::Value::String(s) => s.clone(),
+        toml::Value::Integer(n) => n.to_string(),
+        toml::Value::Float(x) => x.to_string(),
+        toml::Value::Boolean(b) => b.to_string(),
+        other => bail!("{}: {} should be a single value, not {}", at, key.get_ref(), kind(other)),
+    };
+    Ok(Flag { key: key.get_ref().clone(), value, at: format!("{}{}", at, label) })
+}
+
+/// The warning for a top-level key that's neither a setting nor a table
+/// we read, with the nearest setting if it looks like a typo of one.
+fn unknown(key: &str, at: &str, expected: &str) -> String {
+    let nearest = crate::settings::settings()
+        .into_iter()
+        .map(|s| (crate::voices::distance(key, &s.key), s.key))
+        .filter(|(d, _)| *d <= crate::voices::MAX_DISTANCE)
+        .min();
+    match nearest {
+        Some((_, setting)) => format!("{}: unknown setting {:?}, ignored (did you mean {:?}?)", at, key, setting),
+        None => format!("{}: unknown setting {:?}, ignored (expected {})", at, key, expected),
+    }
+}
+
+/// The voice `name` stands for: its target if it's an alias, else itself.
+pub fn resolve<'a>(aliases: &'a [(String, String)], name: &'a str) -> &'a str {
+    aliases.iter().find(|(alias, _)| alias == name).map_or(name, |(_, voice)| voice)
+}
+
+/// `[aliases]`, each resolved to a voice. An alias may name another alias
+/// whose target is a voice, but no longer chain, and no cycle.
+fn aliases(table: &Entries<String>, src: &Source) -> Result<Vec<(String, String)>> {
+    if let Some(alias) = table.keys().find(|alias| crate::VOICES.contains(&alias.get_ref().as_str())) {
+        bail!("{}: aliases: {:?} is already a voice", src.at(alias.span()), alias.get_ref());
+    }
+    let target = |name: &str| table.iter().find(|(alias, _)| alias.get_ref() == name).map(|(_, target)| target.get_ref().as_str());
+    let mut resolved = Vec::new();
+    for alias in table.keys() {
+        let mut chain = vec![alias.get_ref().as_str()];
+        while let Some(next) = target(chain[chain.len() - 1]) {
+            let cycle = chain.contains(&next);
+            chain.push(next);
+            if cycle {
+                bail!("{}: aliases: {} is a cycle", src.at(alias.span()), chain.join(" → "));
+            }
+        }
+        if chain.len() > 3 {
+            bail!("{}: aliases: {} is more than one hop (point {} at a voice)", src.at(alias.span()), chain.join(" → "), alias.get_ref());
+        }
+        resolved.push((alias.get_ref().clone(), chain[chain.len() - 1].to_string()));
+    }
+    Ok(resolved)
+}
+
+fn by_language(languages: &Entries<String>, src: &Source) -> Result<Vec<(String, String)>> {
+    let mut found = Vec::new();
+    for (code, voice) in languages {
+        if !(2..=3).contains(&code.get_ref().len()) || !code.get_ref().bytes().all(|b| b.is_ascii_lowercase()) {
+            bail!("{}: voices.by-language: {:?} isn't a language code like \"fr\"", src.at(code.span()), code.get_ref());
+        }
+        found.push((code.get_ref().clone(), voice.get_ref().clone()));
+    }
+    Ok(found)
+}
+
+/// `[phrases]`: each a string, or a table with its text and voice.
+fn phrases(table: &Entries<toml::Value>, src: &Source) -> Result<Vec<Phrase>> {
+    let mut found = Vec::new();
+    for (name, value) in table {
+        let at = src.at(name.span());
+        let (text, voice) = match value.get_ref() {
+            toml::Value::String(text) => (text.clone(), None),
+            fields @ toml::Value::Table(_) => {
+                let fields: PhraseFields = fields.clone().try_into().map_err(|e: toml::de::Error| anyhow::anyhow!("{}: phrases.{}: {}", at, name.get_ref(), e.message()))?;
+                (fields.text, fields.voice)
+            }
+            other => bail!("{}: phrases.{} should be a string or a table, not {}", at, name.get_ref(), kind(other)),
+        };
+        found.push((name.get_ref().as_str(), at, text, voice));
+    }
+    let texts: Vec<(&str, &str)> = found.iter().map(|(name, _, text, _)| (*name, text.as_str())).collect();
+    let mut phrases = Vec::new();
+    for (name, at, _, voice) in &found {
+        let text = crate::phrases::expand(name, &texts).map_err(|e| anyhow::anyhow!("{}: phrases.{}: {}", at, name, e))?;
+        phrases.push(Phrase { name: name.to_string(), text, voice: voice.clone() });
+    }
+    Ok(phrases)
+}
+
+fn rule(fields: &Spanned<RuleFields>, n: usize, src: &Source) -> Result<Rule> {
+    let at = src.at(fields.span());
+    let fields = fields.get_ref();
+    let label = match &fields.name {
+        Some(name) => format!("rule {} {:?} ({})", n, name, at),
+        None => format!("rule {} ({})", n, at),
+    };
+    let Some(pattern) = &fields.pattern else {
+        bail!("{} has no pattern", label);
+    };
+    if let Some(bad) = fields.flags.chars().find(|c| !"ism".contains(*c)) {
+        bail!("{}: unknown flag {:?} (expected i, s or m)", label, bad);
+    }
+    let full = match fields.flags.as_str() {
+        "" => pattern.clone(),
+        flags => format!("(?{}){}", flags, pattern),
+    };
+    let regex = crate::pattern::compile(&full).map_err(|e| anyhow::anyhow!("{}: pattern {:?}: {}", label, pattern, e))?;
+    Ok(Rule { label, regex, replacement: fields.replacement.clone() })
+}
+
+/// `text` through `rules` in order; `debug` prints each rule's before and
+/// after.
+pub fn apply(text: String, rules: &[Rule], debug: bool) -> String {
+    rules.iter().fold(text, |text, rule| {
+        let after = rule.regex.replace_all(&text, rule.replacement.as_str()).into_owned();
+        if debug && after == text {
+            reporter::info(format_args!("{}: no match", rule.label));
+        } else if debug {
+            reporter::info(format_args!("{}:\n  before: {:?}\n  after:  {:?}", rule.label, text, after));
+        }
+        after
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_rules_in_order() {
+        let config = parse(
+            "[[rules]]\nname = \"tickets\"\npattern = '\\[[A-Z]+-\\d+\\] ?'\n\n[[rules]]\npattern = 'k8s'\nreplacement = \"kubernetes\"\nflags = \"i\"\n\n[[rules]]\npattern = '(?s)\\n-- \\n.*'\n\n[[rules]]\npattern = 'kubernetes (\\w+)'\nreplacement = \"$1 on kubernetes\"\n",
+            "config.toml",
+        )
+        .unwrap();
+        assert_eq!(config.rules.len(), 4);
+        assert_eq!(config.rules[0].label, "rule 1 \"tickets\" (config.toml:1)");
+        let text = "[JIRA-1234] Deploy K8S cluster\n-- \nBob\nSRE".to_string();
+        assert_eq!(apply(text, &config.rules, false), "Deploy cluster on kubernetes");
+    }
+
+    #[test]
+    fn resolves_aliases() {
+        let config = parse(
+            "[voices.by-language]\nfr = 'deep'\n\n[aliases]\ndeep = 'javert'\nnarrator = 'deep'\nmine = 'custom-voice'\n\n[dialogue.cast]\nGUARD = 'narrator'\n",
+            "config.toml",
+        )
+        .unwrap();
+        let pairs = |list: &[(String, String)]| list.iter().map(|(a, b)| format!("{}={}", a, b)).collect::<Vec<_>>();
+        assert_eq!(pairs(&config.aliases), ["deep=javert", "narrator=javert", "mine=custom-voice"]);
+        assert_eq!(pairs(&config.by_language), ["fr=javert"]);
+        assert_eq!(pairs(&config.cast), ["GUARD=javert"]);
+        assert_eq!(resolve(&config.aliases, "narrator"), "javert");
+        assert_eq!(resolve(&config.aliases, "alba"), "alba");
+    }
+
+    #[test]
+    fn reads_phrases() {
+        let config = parse(
+            "[aliases]\ndeep = 'javert'\n\n[phrases]\nbuild-done = '{project} built.'\nproject = 'Speak Turbo'\nalarm = { text = 'Wake up!', voice = 'deep' }\n",
+            "config.toml",
+        )
+        .unwrap();
+        let phrases: Vec<String> = config.phrases.iter().map(|p| format!("{}={:?} {:?}", p.name, p.text, p.voice)).collect();
+        assert_eq!(
+            phrases,
+            ["build-done=\"Speak Turbo built.\" None", "project=\"Speak Turbo\" None", "alarm=\"Wake up!\" Some(\"javert\")"]
+        );
+    }
+
+    #[test]
+    fn reads_notification_filters() {
+        let config = parse("[notifications]\ninclude = ['Slack', 'Thunderbird']\ntemplate = '{app} says {summary}'\n", "config.toml").unwrap();
+        assert_eq!(config.notifications.include, ["Slack", "Thunderbird"]);
+        assert_eq!((config.notifications.exclude.len(), config.notifications.template.as_deref()), (0, Some("{app} says {summary}")));
+        let wrong = parse("[notifications]\nexclude = 'Spotify'\n", "config.toml").unwrap_err();
+        assert_eq!(wrong.to_string(), "config.toml:2: invalid type: string \"Spotify\", expected a sequence");
+    }
+
+    #[test]
+    fn reads_flag_defaults() {
+        let config = parse(
+            "voice = 'deep'\nmax-buffer-ms = 5000\nno-cache = true\nvolume = 3\nmax-bufer-ms = 1\n\n[aliases]\ndeep = 'javert'\n",
+            "config.toml",
+        )
+        .unwrap();
+        let flags: Vec<String> = config.flags.iter().map(|f| format!("{}={} ({})", f.key, f.value, f.at)).collect();
+        assert_eq!(
+            flags,
+            ["voice=deep (config.toml:1)", "max-buffer-ms=5000 (config.toml:2)", "no-cache=true (config.toml:3)"]
+        );
+        assert_eq!(
+            config.warnings,
+            [
+                "config.toml:4: unknown setting \"volume\", ignored (expected a flag's name, [profile.NAME], [[rules]], [aliases], [voices.by-language], [dialogue.cast], [phrases] or [notifications])",
+                "config.toml:5: unknown setting \"max-bufer-ms\", ignored (did you mean \"max-buffer-ms\"?)",
+            ]
+        );
+    }
+
+    #[test]
+    fn layers_profiles() {
+        let config = parse(
+            "voice = 'alba'\ngap-ms = 10\n\n[profile.narration]\nmax-buffer-ms = 60000\ngap-ms = 500\n\n[profile.audiobook]\ninherits = 'narration'\nvoice = 'jean'\nvolume = 2\n\n[profile.alerts]\nquiet = true\n",
+            "config.toml",
+        )
+        .unwrap();
+        let flags = |profile| -> Vec<String> {
+            config.flags(profile).unwrap().iter().map(|f| format!("{}={} ({})", f.key, f.value, f.at)).collect()
+        };
+        assert_eq!(flags(None), ["voice=alba (config.toml:1)", "gap-ms=10 (config.toml:2)"]);
+        assert_eq!(
+            flags(Some("narration")),
+            [
+                "voice=alba (config.toml:1)",
+                "gap-ms=500 (config.toml:6 (profile narration))",
+                "max-buffer-ms=60000 (config.toml:5 (profile narration))",
+            ]
+        );
+        assert_eq!(
+            flags(Some("audiobook")),
+            [
+                "voice=jean (config.toml:10 (profile audiobook))",
+                "gap-ms=500 (config.toml:6 (profile narration))",
+                "max-buffer-ms=60000 (config.toml:5 (profile narration))",
+            ]
+        );
+        assert_eq!(
+            config.warnings,
+            ["config.toml:11: unknown setting \"volume\", ignored (expected a flag's name or inherits)"]
+        );
+        assert_eq!(
+            config.flags(Some("narator")).unwrap_err(),
+            "unknown profile 'narator'; the config has narration, audiobook, alerts"
+        );
+        assert_eq!(
+            Config::default().flags(Some("x")).unwrap_err(),
+            "unknown profile 'x'; the config has no [profile.NAME] sections"
+        );
+    }
+
+    #[test]
+    fn names_the_bad_rule() {
+        let cases = [
+            ("[[rules]]\npattern = 'a('\n", "rule 1 (c.toml:1): pattern \"a(\": invalid regex \"a(\": unclosed group"),
+            (
+                "[[rules]]\npattern = 'a'\n[[rules]]\nname = \"x\"\npattern = '\\w{5000}'\n",
+                "rule 2 \"x\" (c.toml:3): pattern \"\\\\w{5000}\": regex \"\\\\w{5000}\" is too big (over 1048576 bytes compiled)",
+            ),
+            ("[[rules]]\npattern = 'a'\nflags = \"g\"\n", "rule 1 (c.toml:1): unknown flag 'g' (expected i, s or m)"),
+            ("[[rules]]\nreplacement = 'a'\n", "rule 1 (c.toml:1) has no pattern"),
+            ("[[rules]]\npatern = 'a'\n", "c.toml:2: unknown field `patern`, expected one of `name`, `pattern`, `replacement`, `flags`"),
+            ("[[rules]]\npattern = 3\n", "c.toml:2: invalid type: integer `3`, expected a string"),
+            ("voice = ['alba']\n", "c.toml:1: voice should be a single value, not an array"),
+            ("[aliases]\ndeep = 'javert'\nalba = 'jean'\n", "c.toml:3: aliases: \"alba\" is already a voice"),
+            ("[aliases]\na = 'b'\nb = 'a'\n", "c.toml:2: aliases: a → b → a is a cycle"),
+            ("[aliases]\nme = 'me'\n", "c.toml:2: aliases: me → me is a cycle"),
+            (
+                "[aliases]\na = 'b'\nb = 'c'\nc = 'jean'\n",
+                "c.toml:2: aliases: a → b → c → jean is more than one hop (point a at a voice)",
+            ),
+            ("[dialogue.cast]\nGUARD = true\n", "c.toml:2: invalid type: boolean `true`, expected a string"),
+            ("[voices.by-lang]\nfr = 'jean'\n", "c.toml:1: unknown field `by-lang`, expected `by-language`"),
+            ("[voices.by-language]\nfr = 'jean'\nFrench = 'alba'\n", "c.toml:3: voices.by-language: \"French\" isn't a language code like \"fr\""),
+            ("[voices.by-language]\nfr = 2\n", "c.toml:2: invalid type: integer `2`, expected a string"),
+            ("[[rules]]\npattern = 'a\n", "c.toml:2: invalid literal string, expected `'`"),
+            ("[phrases]\nhi = 3\n", "c.toml:2: phrases.hi should be a string or a table, not an integer"),
+            ("[phrases]\nhi = { voice = 'jean' }\n", "c.toml:2: phrases.hi: missing field `text`"),
+            ("[phrases]\nhi = { text = 'Hi', volume = 2 }\n", "c.toml:2: phrases.hi: unknown field `volume`, expected `text` or `voice`"),
+            ("[phrases]\na = '{b}'\nb = 'and {a}'\n", "c.toml:2: phrases.a: a → b → a is a cycle"),
+            ("[phrases]\ndone = '{projet}'\nproject = 'x'\n", "c.toml:2: phrases.done: {projet} isn't a phrase (did you mean 'project'?)"),
+            ("[profile]\nfast = 1\n", "c.toml:2: invalid type: integer `1`, expected a map"),
+            ("[profile.a]\ninherits = 'b'\n", "c.toml:1: profile.a: inherits unknown profile \"b\" (profiles: a)"),
+            ("[profile.a]\ninherits = ['b']\n", "c.toml:2: profile.a: inherits should be a profile name, not an array"),
+            ("[profile.a]\ninherits = 'a'\n", "c.toml:1: profile.a: inherits a → a is a cycle"),
+            (
+                "[profile.a]\ninherits = 'b'\n[profile.b]\ninherits = 'c'\n[profile.c]\ninherits = 'b'\n",
+                "c.toml:1: profile.a: inherits a → b → c → b is a cycle",
+            ),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(parse(text, "c.toml").unwrap_err().to_string(), expected, "{:?}", text);
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! What the terminal on stderr can show. Unix terminals take UTF-8 and
+//! escape sequences as they are. A Windows console is set up once: UTF-8
+//! output and escape processing are turned on, and put back as they were
+//! when the process exits. Only a console host known to draw the marks
+//! (Windows Terminal, VS Code's) gets ⚡/▶/✓; cmd.exe's gets ASCII ones.
+
+use std::sync::OnceLock;
+
+#[derive(Clone, Copy)]
+struct Console {
+    /// The ⚡/▶/✓ marks draw as themselves
+    unicode: bool,
+    /// "\r\x1b[2K" redraws a line in place
+    escapes: bool,
+}
+
+fn console() -> Console {
+    static CONSOLE: OnceLock<Console> = OnceLock::new();
+    *CONSOLE.get_or_init(setup)
+}
+
+/// Whether the marks can be printed as they are, rather than --no-emoji's.
+pub fn unicode() -> bool {
+    console().unicode
+}
+
+/// Whether lines can be redrawn in place (the progress and playback lines).
+pub fn escapes() -> bool {
+    console().escapes
+}
+
+#[cfg(not(windows))]
+fn setup() -> Console {
+    Console { unicode: true, escapes: true }
+}
+
+#[cfg(windows)]
+fn setup() -> Console {
+    use std::ffi::c_void;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    const STD_ERROR_HANDLE: u32 = -12i32 as u32;
+    const CP_UTF8: u32 = 65001;
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetStdHandle(which: u32) -> *mut c_void;
+        fn GetConsoleMode(console: *mut c_void, mode: *mut u32) -> i32;
+        fn SetConsoleMode(console: *mut c_void, mode: u32) -> i32;
+        fn GetConsoleOutputCP() -> u32;
+        fn SetConsoleOutputCP(page: u32) -> i32;
+    }
+
+    /// The code page and mode found, for putting back
+    static PAGE: AtomicU32 = AtomicU32::new(0);
+    static MODE: AtomicU32 = AtomicU32::new(0);
+
+    extern "C" fn restore() {
+        unsafe {
+            SetConsoleOutputCP(PAGE.load(Ordering::SeqCst));
+            SetConsoleMode(GetStdHandle(STD_ERROR_HANDLE), MODE.load(Ordering::SeqCst));
+        }
+    }
+
+    let handle = unsafe { GetStdHandle(STD_ERROR_HANDLE) };
+    let mut mode = 0;
+    // Redirected: bytes go to a file or pipe as they are
+    if unsafe { GetConsoleMode(handle, &mut mode) } == 0 {
+        return Console { unicode: true, escapes: true };
+    }
+    PAGE.store(unsafe { GetConsoleOutputCP() }, Ordering::SeqCst);
+    MODE.store(mode, Ordering::SeqCst);
+    let utf8 = unsafe { SetConsoleOutputCP(CP_UTF8) } != 0;
+    let escapes = unsafe { SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) } != 0;
+    unsafe { libc::atexit(restore) };
+    let host = |name: &str| std::env::var_os(name).is_some_and(|v| !v.is_empty());
+    Console { unicode: utf8 && (host("WT_SESSION") || host("TERM_PROGRAM")), escapes }
+}
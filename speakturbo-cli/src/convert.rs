@@ -0,0 +1,127 @@
+//! `speakturbo convert INPUT... -o OUTPUT`: text to files and nothing
+//! else, for servers and containers without a sound stack. No audio device
+//! is opened on any path it takes. The inputs become the invocation they
+//! stand for (-f files, a --batch-dir directory, or a `batch` manifest),
+//! so the text goes through the same preparation and chunking as ever.
+//!
+//! -o can follow the inputs. The flags that need it on the command line
+//! (--force, --checksum...) come before `convert` with it, as top-level
+//! flags do: `speakturbo --force -o out.wav convert in.txt`.
+
+use crate::exit::{self, Kind};
+use crate::settings::Source;
+use crate::{Args, Command};
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// Settings that only mean something while audio plays
+const PLAYBACK_ONLY: [&str; 10] =
+    ["karaoke", "chars-per-second", "rtp", "serve", "stream-lines", "stream-null", "follow", "watch-clipboard", "replay", "gap-ms"];
+
+/// Turn `convert` in `args` into the save-only invocation it stands for.
+/// A playback setting on the command line is a usage error; one from the
+/// config or environment is dropped.
+pub fn rewrite(args: &mut Args, values: &[(String, String, Source)]) -> Result<()> {
+    if let Some((key, _, _)) = values.iter().find(|(key, _, source)| *source == Source::CommandLine && PLAYBACK_ONLY.contains(&key.as_str())) {
+        return Err(exit::fail(Kind::Usage, format!("--{} is for playback; convert only saves", key)));
+    }
+    let Some(Command::Convert { inputs, output }) = args.command.take() else {
+        unreachable!("only called for convert");
+    };
+    args.karaoke = false;
+    args.rtp = None;
+    args.serve = None;
+    args.stream_lines = false;
+    args.stream_null = false;
+    args.follow = None;
+    #[cfg(feature = "clipboard")]
+    {
+        args.watch_clipboard = false;
+    }
+    args.replay = None;
+    args.gap_ms = None;
+    // Chimes have nowhere to go but the file
+    args.chime_in_file = true;
+    args.output = match (args.output.take(), output) {
+        (Some(_), Some(_)) => return Err(exit::fail(Kind::Usage, "convert takes -o once, before it or after the inputs")),
+        (before, after) => before.or(after),
+    };
+
+    match inputs.as_slice() {
+        [input] if manifest(input).is_some() => {
+            let path = PathBuf::from(input);
+            args.command = Some(match manifest(input) {
+                Some(Manifest::Csv) => Command::Batch { manifest: Some(path), jsonl: None },
+                _ => Command::Batch { manifest: None, jsonl: Some(path) },
+            });
+            return Ok(());
+        }
+        inputs if inputs.iter().any(|input| manifest(input).is_some()) => {
+            return Err(exit::fail(Kind::Usage, "convert takes a manifest on its own"));
+        }
+        _ => {}
+    }
+    if args.output.is_none() {
+        return Err(exit::fail(Kind::Usage, "convert needs -o: a file, a {n} template or a directory"));
+    }
+    match inputs.as_slice() {
+        [dir] if Path::new(dir).is_dir() => args.batch_dir = Some(dir.clone()),
+        inputs if inputs.iter().any(|input| Path::new(input).is_dir()) => {
+            return Err(exit::fail(Kind::Usage, "convert takes a directory on its own"));
+        }
+        _ => args.file = inputs,
+    }
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+enum Manifest {
+    Csv,
+    JsonLines,
+}
+
+fn manifest(input: &str) -> Option<Manifest> {
+    match Path::new(input).extension()?.to_str()?.to_lowercase().as_str() {
+        "csv" => Some(Manifest::Csv),
+        "jsonl" | "ndjson" => Some(Manifest::JsonLines),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    /// `argv` converted, with --karaoke set from `karaoke` if anywhere
+    fn converted(argv: &[&str], karaoke: Option<Source>) -> Result<Args> {
+        let mut args = Args::try_parse_from(argv)?;
+        let values: Vec<(String, String, Source)> = karaoke.into_iter().map(|source| ("karaoke".to_string(), "(none)".to_string(), source)).collect();
+        rewrite(&mut args, &values)?;
+        Ok(args)
+    }
+
+    #[test]
+    fn stands_for_the_save_only_invocation() {
+        let args = converted(&["speakturbo", "convert", "a.txt", "-", "-o", "part-{n}.wav"], None).unwrap();
+        assert_eq!((args.file, args.output.as_deref(), args.command.is_none()), (vec!["a.txt".to_string(), "-".to_string()], Some("part-{n}.wav"), true));
+        let args = converted(&["speakturbo", "--force", "-o", "{n}.wav", "convert", "prompts.CSV"], None).unwrap();
+        assert!(matches!(args.command, Some(Command::Batch { manifest: Some(_), jsonl: None })));
+        let args = converted(&["speakturbo", "--karaoke", "convert", "jobs.jsonl", "-o", "{n}.wav"], Some(Source::File("c.toml:3".to_string()))).unwrap();
+        assert!(matches!(args.command, Some(Command::Batch { manifest: None, jsonl: Some(_) })) && !args.karaoke && args.chime_in_file);
+    }
+
+    #[test]
+    fn refuses_playback_and_mixed_inputs() {
+        let cases: [(&[&str], Option<Source>, &str); 5] = [
+            (&["speakturbo", "--karaoke", "convert", "a.txt", "-o", "a.wav"], Some(Source::CommandLine), "--karaoke is for playback; convert only saves"),
+            (&["speakturbo", "convert", "a.txt", "b.csv", "-o", "{n}.wav"], None, "convert takes a manifest on its own"),
+            (&["speakturbo", "-o", "a.wav", "convert", "a.txt", "-o", "b.wav"], None, "convert takes -o once, before it or after the inputs"),
+            (&["speakturbo", "convert", "a.txt"], None, "convert needs -o: a file, a {n} template or a directory"),
+            (&["speakturbo", "convert", "a.txt", ".", "-o", "out"], None, "convert takes a directory on its own"),
+        ];
+        for (argv, karaoke, error) in cases {
+            assert_eq!(converted(argv, karaoke).err().map(|e| e.to_string()), Some(error.to_string()), "{:?}", argv);
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! `speakturbo daemon ...`: what the daemon at --daemon-url says of itself.
+
+use serde_json::{json, Value};
+use anyhow::Result;
+use clap::Subcommand;
+use speakturbo_client::{Capabilities, Health, SpeakClient};
+
+#[derive(Subcommand)]
+pub enum Action {
+    /// Show whether the daemon is ready, its voices, and its version and features
+    Status,
+}
+
+pub fn run(action: &Action, client: &SpeakClient, as_json: bool) -> Result<()> {
+    match action {
+        Action::Status => status(client, as_json),
+    }
+}
+
+fn status(client: &SpeakClient, as_json: bool) -> Result<()> {
+    let health = client.health()?;
+    let capabilities = client.capabilities()?;
+    if as_json {
+        println!("{}", to_json(client.url(), &health, &capabilities));
+        return Ok(());
+    }
+
+    println!("Daemon:   {}", client.url());
+    println!("Status:   {}", health.status);
+    println!("Voices:   {}", health.voices.join(", "));
+    match &capabilities.version {
+        Some(version) => println!("Version:  {}", version),
+        None => println!("Version:  - (no /capabilities: a legacy daemon)"),
+    }
+    match capabilities.features.is_empty() {
+        true => println!("Features: -"),
+        false => println!("Features: {}", capabilities.features.join(", ")),
+    }
+    Ok(())
+}
+
+fn to_json(url: &str, health: &Health, capabilities: &Capabilities) -> Value {
+    json!({
+        "url": url,
+        "status": health.status,
+        "voices": health.voices,
+        "capabilities": capabilities.to_json(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use speakturbo_client::mock_daemon::MockTransport;
+
+    #[test]
+    fn reports_a_legacy_daemon_as_such() {
+        let client = SpeakClient::with_transport("mock:", MockTransport::new());
+        let (health, capabilities) = (client.health().unwrap(), client.capabilities().unwrap());
+        let json = to_json(client.url(), &health, &capabilities);
+        assert_eq!(json.get("status"), Some(&Value::from("ready")));
+        assert_eq!(json.get("capabilities").unwrap().to_string(), r#"{"version":null,"features":[],"legacy":true}"#);
+    }
+}
@@ -0,0 +1,148 @@
+//! `--dedupe[=WINDOW]`: in the streaming modes, a text already spoken
+//! within the window is skipped, compared once normalized, so a log line
+//! repeated hundreds of times is heard once. When a window with skips
+//! closes, the skips are reported, and with --dedupe-announce spoken: "Previous
+//! message repeated 14 times". Texts are known by their SHA-256, and only
+//! the most recently used [`CAPACITY`] of them are remembered.
+
+use crate::checksum::{Algorithm, Digest};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// The window without one given
+pub const WINDOW: &str = "30s";
+
+/// Texts remembered at most; the least recently used go first
+pub const CAPACITY: usize = 1024;
+
+/// Whether to speak a text
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Speak,
+    /// Spoken within the window; the skips in it so far, this one included
+    Skip { repeats: usize },
+}
+
+/// A window closed with skips in it
+#[derive(Debug, PartialEq, Eq)]
+pub struct Repeated {
+    /// The record that opened it
+    pub n: usize,
+    pub repeats: usize,
+}
+
+impl Repeated {
+    /// What --dedupe-announce says.
+    pub fn summary(&self) -> String {
+        match self.repeats {
+            1 => "Previous message repeated once".to_string(),
+            n => format!("Previous message repeated {} times", n),
+        }
+    }
+}
+
+struct Seen {
+    n: usize,
+    /// When the window opened
+    spoken: Instant,
+    skips: usize,
+    /// When it was last looked up, by the tick of `Dedupe::clock`
+    used: u64,
+}
+
+pub struct Dedupe {
+    window: Duration,
+    seen: HashMap<String, Seen>,
+    clock: u64,
+}
+
+impl Dedupe {
+    pub fn new(window: Duration) -> Dedupe {
+        Dedupe { window, seen: HashMap::new(), clock: 0 }
+    }
+
+    /// Whether record `n`, `text` once normalized, is to be spoken at `now`.
+    pub fn check(&mut self, n: usize, text: &str, now: Instant) -> Verdict {
+        self.clock += 1;
+        let mut digest = Digest::new(Algorithm::Sha256);
+        digest.update(text.as_bytes());
+        let key = digest.finish_hex();
+        if let Some(seen) = self.seen.get_mut(&key) {
+            seen.used = self.clock;
+            if now.duration_since(seen.spoken) < self.window {
+                seen.skips += 1;
+                return Verdict::Skip { repeats: seen.skips };
+            }
+            *seen = Seen { n, spoken: now, skips: 0, used: self.clock };
+            return Verdict::Speak;
+        }
+        if self.seen.len() >= CAPACITY {
+            let oldest = self.seen.iter().min_by_key(|(_, seen)| seen.used).map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.seen.remove(&oldest);
+            }
+        }
+        self.seen.insert(key, Seen { n, spoken: now, skips: 0, used: self.clock });
+        Verdict::Speak
+    }
+
+    /// The windows closed by `now` that had skips, oldest first; every
+    /// closed window is forgotten.
+    pub fn closed(&mut self, now: Instant) -> Vec<Repeated> {
+        let window = self.window;
+        let mut repeated = Vec::new();
+        self.seen.retain(|_, seen| {
+            let open = now.duration_since(seen.spoken) < window;
+            if !open && seen.skips > 0 {
+                repeated.push((seen.spoken, Repeated { n: seen.n, repeats: seen.skips }));
+            }
+            open
+        });
+        repeated.sort_by_key(|(spoken, _)| *spoken);
+        repeated.into_iter().map(|(_, repeated)| repeated).collect()
+    }
+
+    /// Every window, closed now that the records have ended.
+    pub fn finish(&mut self) -> Vec<Repeated> {
+        let later = self.seen.values().map(|seen| seen.spoken + self.window).max();
+        later.map_or_else(Vec::new, |later| self.closed(later))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_repeats_within_the_window_and_counts_them() {
+        let t0 = Instant::now();
+        let secs = |s| t0 + Duration::from_secs(s);
+        let mut dedupe = Dedupe::new(Duration::from_secs(30));
+        assert_eq!(dedupe.check(1, "disk almost full", secs(0)), Verdict::Speak);
+        assert_eq!(dedupe.check(2, "backup started", secs(1)), Verdict::Speak);
+        assert_eq!(dedupe.check(3, "disk almost full", secs(5)), Verdict::Skip { repeats: 1 });
+        assert_eq!(dedupe.check(4, "disk almost full", secs(29)), Verdict::Skip { repeats: 2 });
+        assert_eq!(dedupe.closed(secs(29)), []);
+        assert_eq!(dedupe.closed(secs(30)), [Repeated { n: 1, repeats: 2 }]);
+        assert_eq!(dedupe.check(5, "disk almost full", secs(31)), Verdict::Speak, "a new window");
+        assert_eq!(Repeated { n: 1, repeats: 2 }.summary(), "Previous message repeated 2 times");
+
+        assert_eq!(dedupe.check(6, "disk almost full", secs(32)), Verdict::Skip { repeats: 1 });
+        assert_eq!(dedupe.finish(), [Repeated { n: 5, repeats: 1 }]);
+        assert_eq!(dedupe.finish(), []);
+    }
+
+    #[test]
+    fn forgets_the_least_recently_used_past_its_capacity() {
+        let now = Instant::now();
+        let mut dedupe = Dedupe::new(Duration::from_secs(30));
+        for n in 0..CAPACITY {
+            dedupe.check(n, &format!("line {}", n), now);
+        }
+        assert_eq!(dedupe.check(0, "line 0", now), Verdict::Skip { repeats: 1 });
+        assert_eq!(dedupe.check(CAPACITY, "one more", now), Verdict::Speak);
+        assert_eq!(dedupe.seen.len(), CAPACITY);
+        assert_eq!(dedupe.check(0, "line 0", now), Verdict::Skip { repeats: 2 }, "used lately, so kept");
+        assert_eq!(dedupe.check(1, "line 1", now), Verdict::Speak, "the least recently used, forgotten");
+    }
+}
@@ -0,0 +1,185 @@
+//! `--dialogue`: scripts whose lines start with who's speaking, as in
+//! "marius: Who goes there?". Each line is its own synthesis in that
+//! speaker's voice. Speakers are voice names and aliases, or characters
+//! that the config's `[dialogue.cast]` gives a voice.
+
+use crate::join::{self, Piece};
+use crate::reporter;
+use anyhow::Result;
+use std::time::Duration;
+
+pub struct Cast<'a> {
+    /// `[dialogue.cast]`: (character, voice)
+    pub characters: &'a [(String, String)],
+    /// `[aliases]`: (alias, voice)
+    pub aliases: &'a [(String, String)],
+    /// For lines without a speaker, and speakers nobody knows
+    pub default: &'a str,
+}
+
+impl Cast<'_> {
+    /// `speaker`'s voice, if it's a character, an alias or a voice name.
+    fn voice(&self, speaker: &str) -> Option<String> {
+        let speaker = speaker.to_lowercase();
+        let named = self.characters.iter().chain(self.aliases).find(|(name, _)| name.to_lowercase() == speaker);
+        if let Some((_, voice)) = named {
+            return Some(voice.clone());
+        }
+        crate::VOICES.iter().find(|v| **v == speaker).map(|v| v.to_string())
+    }
+
+    /// Who could have been meant, for the unknown-speaker warning.
+    fn listing(&self) -> String {
+        let voices = crate::VOICES.join(", ");
+        match self.characters {
+            [] => format!("no [dialogue.cast] in the config; voices: {}", voices),
+            characters => {
+                let names: Vec<&str> = characters.iter().map(|(name, _)| name.as_str()).collect();
+                format!("cast: {}; voices: {}", names.join(", "), voices)
+            }
+        }
+    }
+}
+
+/// The speaker and speech of a line that starts "name:", the name being
+/// a word or two.
+pub fn speaker(line: &str) -> Option<(&str, &str)> {
+    let (name, speech) = line.split_once(':')?;
+    let name = name.trim();
+    let plausible = name.chars().next().is_some_and(char::is_alphabetic)
+        && name.chars().count() <= 32
+        && name.split_whitespace().count() <= 2
+        && name.chars().all(|c| c.is_alphanumeric() || " _.'-".contains(c));
+    let spaced = speech.is_empty() || speech.starts_with(char::is_whitespace);
+    (plausible && spaced).then(|| (name, speech.trim()))
+}
+
+/// Each line of `text` through `normalize`, which would otherwise run the
+/// lines together; speakers are kept as they are, blank lines dropped.
+pub fn normalize_lines(text: &str, normalize: impl Fn(String) -> String) -> String {
+    let lines: Vec<String> = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| match speaker(line) {
+            Some((name, speech)) => format!("{}: {}", name, normalize(speech.to_string())),
+            None => normalize(line.to_string()),
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/// The lines of a script, for -o templates that write one file per line.
+pub fn lines(text: &str) -> Vec<String> {
+    text.lines().filter(|line| !line.trim().is_empty()).map(str::to_string).collect()
+}
+
+/// `text` as it will be synthesized: each line in chunks as
+/// [`join::plan`] cuts them, `gap` of silence between lines. Each chunk is
+/// "voice: speech", for [`split`]. Unknown speakers get the default voice
+/// and a warning.
+pub fn plan(
+    text: &str,
+    cast: &Cast,
+    gap: Duration,
+    emulate_breaks: bool,
+    chunk_chars: usize,
+) -> Result<Vec<Piece>> {
+    let mut pieces = Vec::new();
+    let mut unknown: Vec<&str> = Vec::new();
+    let mut sentences_before = 0;
+    for line in text.lines() {
+        let (voice, speech) = match speaker(line) {
+            None => (cast.default.to_string(), line.trim()),
+            Some((name, speech)) => match cast.voice(name) {
+                Some(voice) => (voice, speech),
+                None => {
+                    if !unknown.contains(&name) {
+                        reporter::warning(format_args!("unknown speaker {:?}; using {} ({})", name, cast.default, cast.listing()));
+                    }
+                    unknown.push(name);
+                    (cast.default.to_string(), speech)
+                }
+            },
+        };
+        if speech.is_empty() {
+            continue;
+        }
+        if !pieces.is_empty() && !gap.is_zero() {
+            pieces.push(Piece::Silence(gap));
+        }
+        let mut last = sentences_before;
+        for piece in join::plan(speech, emulate_breaks, chunk_chars)? {
+            pieces.push(match piece {
+                Piece::Text { text, sentences } => {
+                    last = sentences.end() + sentences_before;
+                    let sentences = sentences.start() + sentences_before..=last;
+                    Piece::Text { text: format!("{}: {}", voice, text), sentences }
+                }
+                silence => silence,
+            });
+        }
+        sentences_before = last;
+    }
+    Ok(pieces)
+}
+
+/// A planned chunk's voice and speech.
+pub fn split(chunk: &str) -> Option<(&str, &str)> {
+    chunk.split_once(": ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_speakers() {
+        let cases = [
+            ("marius: Who goes there?", Some(("marius", "Who goes there?"))),
+            ("  The Guard:   Halt!", Some(("The Guard", "Halt!"))),
+            ("Dr. Jekyll: Good evening.", Some(("Dr. Jekyll", "Good evening."))),
+            ("NARRATOR:", Some(("NARRATOR", ""))),
+            ("It was late: the lamps were out.", None),
+            ("See http://example.com for more", None),
+            ("Time 10:30 arrived", None),
+            ("No speaker here.", None),
+        ];
+        for (line, expected) in cases {
+            assert_eq!(speaker(line), expected, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn plans_a_voice_per_line() {
+        let characters = vec![("GUARD".to_string(), "javert".to_string())];
+        let aliases = vec![("deep".to_string(), "javert".to_string())];
+        let cast = Cast { characters: &characters, aliases: &aliases, default: "alba" };
+        let script = "Guard: Who goes there?\n\nMarius: A friend.\nDeep: Halt.\nThe night was cold.\nBob: Hello?\nNARRATOR:";
+        let pieces = plan(script, &cast, Duration::from_millis(300), false, 0).unwrap();
+        let gap = || Piece::Silence(Duration::from_millis(300));
+        let text = |text: &str, n: usize| Piece::Text { text: text.to_string(), sentences: n..=n };
+        assert_eq!(
+            pieces,
+            [
+                text("javert: Who goes there?", 1),
+                gap(),
+                text("marius: A friend.", 2),
+                gap(),
+                text("javert: Halt.", 3),
+                gap(),
+                text("alba: The night was cold.", 4),
+                gap(),
+                text("alba: Hello?", 5),
+            ]
+        );
+        assert_eq!(split("javert: Who goes there? Me: no"), Some(("javert", "Who goes there? Me: no")));
+    }
+
+    #[test]
+    fn normalizes_each_line() {
+        let text = "marius:  Who   goes\tthere?\n\n  plain   words \nBob: hi";
+        let normalized = normalize_lines(text, |s| s.split_whitespace().collect::<Vec<_>>().join(" "));
+        assert_eq!(normalized, "marius: Who goes there?\nplain words\nBob: hi");
+        assert_eq!(lines("a: one\n\nb: two\n"), ["a: one", "b: two"]);
+    }
+}
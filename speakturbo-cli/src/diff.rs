@@ -0,0 +1,275 @@
+//! `--diff FILE`: speak only what changed in a file since it was last
+//! read this way, for a status file re-rendered every minute. The file
+//! as last spoken is kept in `snapshots/` in the state directory, under
+//! the SHA-256 of its path; the new and changed lines are spoken, the
+//! removed ones with --speak-deletions, and the snapshot moves on only
+//! once they have been. Snapshots of files not read for --diff-keep go.
+
+use crate::checksum::{Algorithm, Digest};
+use crate::exit::{self, Kind};
+use crate::reporter;
+use crate::text;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Past this many line pairs the changed middle of a file isn't diffed
+/// line by line, but taken as replaced whole
+const LIMIT: usize = 4_000_000;
+
+pub struct Options {
+    /// "new:" and "changed:" before the lines
+    pub labels: bool,
+    /// On the first reading, keep the snapshot without speaking
+    pub initial_silent: bool,
+    pub deletions: bool,
+    /// Snapshots not read for this long are pruned
+    pub keep: Duration,
+    pub force_text: bool,
+}
+
+/// A line that differs from the snapshot
+#[derive(Debug, PartialEq, Eq)]
+pub enum Change<'a> {
+    New(&'a str),
+    /// In place of a removed line
+    Changed(&'a str),
+    Removed(&'a str),
+}
+
+/// What a reading of the file has to say, and the snapshot it makes
+pub struct Changes {
+    pub text: String,
+    snapshot: PathBuf,
+    current: String,
+}
+
+impl Changes {
+    /// The text has been spoken: the next reading starts from here.
+    pub fn save(&self) -> Result<()> {
+        write(&self.snapshot, &self.current)
+    }
+}
+
+/// Where snapshots are kept: `snapshots/` in the state directory.
+fn dir() -> Result<PathBuf> {
+    crate::history::state_dir().map(|d| d.join("snapshots")).context("No state directory: set XDG_STATE_HOME or HOME")
+}
+
+/// What `path` has to say since its snapshot; None when nothing, the
+/// snapshot then brought up to date.
+pub fn read(path: &str, options: &Options) -> Result<Option<Changes>> {
+    let dir = dir()?;
+    prune(&dir, options.keep);
+    let current = read_settled(Path::new(path), options.force_text)?;
+    let snapshot = dir.join(snapshot_name(Path::new(path))?);
+    let previous = match fs::read_to_string(&snapshot) {
+        Ok(previous) => Some(previous),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e).with_context(|| format!("Cannot read {}", snapshot.display())),
+    };
+    let text = match &previous {
+        None if options.initial_silent => {
+            reporter::info(format_args!("{}: first reading, kept without speaking", path));
+            String::new()
+        }
+        None => current.clone(),
+        Some(previous) => said(&diff(previous, &current), options),
+    };
+    let changes = Changes { text, snapshot, current };
+    if changes.text.trim().is_empty() {
+        if previous.is_some() {
+            reporter::info(format_args!("{}: no changes", path));
+        }
+        // Saved anyway, so that a file read often isn't pruned
+        changes.save()?;
+        return Ok(None);
+    }
+    Ok(Some(changes))
+}
+
+/// The file's text, read again when it was replaced while being read, as
+/// a file written to a temporary name and renamed over is.
+fn read_settled(path: &Path, force_text: bool) -> Result<String> {
+    let fail = |e: std::io::Error| exit::fail(Kind::Input, format!("Cannot read {}: {}", path.display(), e));
+    let before = stamp(path).map_err(fail)?;
+    let mut bytes = fs::read(path).map_err(fail)?;
+    if stamp(path).map_err(fail)? != before {
+        reporter::info(format_args!("{} changed while being read; reading it again", path.display()));
+        bytes = fs::read(path).map_err(fail)?;
+    }
+    text::decode(bytes, &path.display().to_string(), force_text)
+}
+
+/// What changes when a file is rewritten or replaced
+fn stamp(path: &Path) -> std::io::Result<(u64, Option<SystemTime>, u64)> {
+    let metadata = fs::metadata(path)?;
+    #[cfg(unix)]
+    let inode = std::os::unix::fs::MetadataExt::ino(&metadata);
+    #[cfg(not(unix))]
+    let inode = 0;
+    Ok((metadata.len(), metadata.modified().ok(), inode))
+}
+
+/// A file's snapshot: named for the hash of its full path, so that the
+/// same file is found whichever directory it's named from.
+fn snapshot_name(path: &Path) -> Result<String> {
+    let full = fs::canonicalize(path).with_context(|| format!("Cannot resolve {}", path.display()))?;
+    let mut digest = Digest::new(Algorithm::Sha256);
+    digest.update(full.to_string_lossy().as_bytes());
+    Ok(format!("{}.txt", digest.finish_hex()))
+}
+
+/// Written to a temporary file and renamed over the old one, so that a
+/// crash leaves one or the other.
+fn write(snapshot: &Path, text: &str) -> Result<()> {
+    let dir = snapshot.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(dir).with_context(|| format!("Cannot create {}", dir.display()))?;
+    let name = snapshot.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let partial = dir.join(format!(".{}.{}", name, std::process::id()));
+    fs::write(&partial, text)
+        .and_then(|()| fs::rename(&partial, snapshot))
+        .with_context(|| format!("Cannot save snapshot {}", snapshot.display()))
+}
+
+/// Remove the snapshots last saved longer than `keep` ago. Failing to is
+/// only reported: the reading goes on.
+fn prune(dir: &Path, keep: Duration) -> usize {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let stale = entry.metadata().ok().and_then(|m| m.modified().ok()).and_then(|at| at.elapsed().ok()).is_some_and(|age| age > keep);
+        if !stale {
+            continue;
+        }
+        match fs::remove_file(entry.path()) {
+            Ok(()) => removed += 1,
+            Err(e) => reporter::warning(format_args!("cannot remove old snapshot {}: {}", entry.path().display(), e)),
+        }
+    }
+    if removed > 0 {
+        reporter::info(format_args!("Pruned {} snapshot{} older than --diff-keep", removed, if removed == 1 { "" } else { "s" }));
+    }
+    removed
+}
+
+/// The lines of `new` that differ from `old`. In each run of differing
+/// lines, those that take the place of removed ones are changed, the rest
+/// new or removed. Blank lines don't count.
+pub fn diff<'a>(old: &'a str, new: &'a str) -> Vec<Change<'a>> {
+    let old: Vec<&str> = old.lines().filter(|l| !l.trim().is_empty()).collect();
+    let new: Vec<&str> = new.lines().filter(|l| !l.trim().is_empty()).collect();
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    let (old, new) = (&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+
+    // Whether each line is kept, from the longest common subsequence
+    let (mut kept_old, mut kept_new) = (vec![false; old.len()], vec![false; new.len()]);
+    if old.len().saturating_mul(new.len()) <= LIMIT {
+        let width = new.len() + 1;
+        let mut lcs = vec![0u32; (old.len() + 1) * width];
+        for i in (0..old.len()).rev() {
+            for j in (0..new.len()).rev() {
+                lcs[i * width + j] = match old[i] == new[j] {
+                    true => lcs[(i + 1) * width + j + 1] + 1,
+                    false => lcs[(i + 1) * width + j].max(lcs[i * width + j + 1]),
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < old.len() && j < new.len() {
+            if old[i] == new[j] {
+                (kept_old[i], kept_new[j]) = (true, true);
+                (i, j) = (i + 1, j + 1);
+            } else if lcs[(i + 1) * width + j] >= lcs[i * width + j + 1] {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+    }
+
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    loop {
+        let (from_i, from_j) = (i, j);
+        while i < old.len() && !kept_old[i] {
+            i += 1;
+        }
+        while j < new.len() && !kept_new[j] {
+            j += 1;
+        }
+        let (removed, added) = (&old[from_i..i], &new[from_j..j]);
+        let paired = removed.len().min(added.len());
+        changes.extend(added[..paired].iter().map(|l| Change::Changed(l)));
+        changes.extend(added[paired..].iter().map(|l| Change::New(l)));
+        changes.extend(removed[paired..].iter().map(|l| Change::Removed(l)));
+        if i == old.len() && j == new.len() {
+            break;
+        }
+        // Both at a kept line, the same one
+        (i, j) = (i + 1, j + 1);
+    }
+    changes
+}
+
+/// What the changes say, a line each. Removed lines always say so, or
+/// they would sound like new ones.
+fn said(changes: &[Change], options: &Options) -> String {
+    let lines = changes.iter().filter_map(|change| match change {
+        Change::New(line) if options.labels => Some(format!("new: {}", line.trim())),
+        Change::Changed(line) if options.labels => Some(format!("changed: {}", line.trim())),
+        Change::New(line) | Change::Changed(line) => Some(line.trim().to_string()),
+        Change::Removed(line) if options.deletions => Some(format!("removed: {}", line.trim())),
+        Change::Removed(_) => None,
+    });
+    lines.collect::<Vec<_>>().join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(labels: bool, deletions: bool) -> Options {
+        Options { labels, initial_silent: false, deletions, keep: Duration::from_secs(86400), force_text: false }
+    }
+
+    #[test]
+    fn finds_new_changed_and_removed_lines() {
+        let old = "build: passing\ndisk: 71%\n\nqueue: 3 jobs\nuptime: 4 days\n";
+        let new = "build: failing\ndisk: 71%\nqueue: 3 jobs\nload: high\n";
+        assert_eq!(diff(old, new), [Change::Changed("build: failing"), Change::Changed("load: high")]);
+        assert_eq!(said(&diff(old, new), &options(true, false)), "changed: build: failing\nchanged: load: high");
+
+        let old = "a\nb\nc\n";
+        let new = "a\nx\ny\nc\nd\n";
+        assert_eq!(diff(old, new), [Change::Changed("x"), Change::New("y"), Change::New("d")]);
+        assert_eq!(diff(new, old), [Change::Changed("b"), Change::Removed("y"), Change::Removed("d")]);
+        assert_eq!(said(&diff(new, old), &options(false, false)), "b");
+        assert_eq!(said(&diff(new, old), &options(false, true)), "b\nremoved: y\nremoved: d");
+        assert_eq!(diff(old, old), []);
+    }
+
+    #[test]
+    fn keeps_snapshots_by_path_and_prunes_old_ones() {
+        let dir = std::env::temp_dir().join(format!("speakturbo-snapshots-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let status = dir.join("status.txt");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&status, "one\n").unwrap();
+        let snapshot = dir.join("snapshots").join(snapshot_name(&status).unwrap());
+        assert_eq!(snapshot_name(&dir.join(".").join("status.txt")).unwrap(), snapshot_name(&status).unwrap());
+        write(&snapshot, "one\n").unwrap();
+        assert_eq!(fs::read_to_string(&snapshot).unwrap(), "one\n");
+        assert_eq!(read_settled(&status, false).unwrap(), "one\n");
+
+        assert_eq!(prune(&dir.join("snapshots"), Duration::from_secs(3600)), 0);
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(prune(&dir.join("snapshots"), Duration::from_millis(10)), 1);
+        assert!(!snapshot.exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
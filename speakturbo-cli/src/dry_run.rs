@@ -0,0 +1,170 @@
+//! `--dry-run`: describe what an invocation would do without synthesizing.
+
+use crate::output::{self, ExistingPolicy};
+use crate::progress::clock;
+use crate::daemon_url;
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::path::Path;
+use std::time::Duration;
+
+pub struct Plan<'a> {
+    pub text: &'a str,
+    /// Several, comma-separated, for a --dialogue
+    pub voice: String,
+    pub output: Option<&'a str>,
+    /// Where audio goes when not saved: speakers, rtp://..., http://...
+    pub sink: String,
+    pub policy: ExistingPolicy,
+    pub chars_per_second: f64,
+    /// Syntheses the text is cut into (--chunk-chars, --emulate-breaks)
+    pub chunks: usize,
+}
+
+/// Ask the daemon for an estimate if it offers one; older daemons 404.
+fn daemon_estimate(text: &str, voice: &str) -> Option<f64> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_millis(500))
+        .build();
+    let body = agent
+        .get(&format!("{}/estimate", daemon_url()))
+        .query("text", text)
+        .query("voice", voice)
+        .call()
+        .ok()?
+        .into_string()
+        .ok()?;
+    serde_json::from_str::<Value>(&body).ok()?.get("duration_secs")?.as_f64()
+}
+
+pub fn report(plan: &Plan, as_json: bool) -> Result<()> {
+    let summary = describe(plan);
+    if as_json {
+        println!("{}", summary);
+        crate::report::mark_printed();
+    } else {
+        println!("Dry run: nothing will be synthesized");
+        print_human(&summary, plan.chars_per_second);
+    }
+    Ok(())
+}
+
+/// Print each plan of a sequence; with --json as one array.
+pub fn report_all(plans: &[Plan], as_json: bool) -> Result<()> {
+    if as_json {
+        println!("{}", Value::Array(plans.iter().map(describe).collect()));
+        crate::report::mark_printed();
+        return Ok(());
+    }
+    println!("Dry run: nothing will be synthesized");
+    for (i, plan) in plans.iter().enumerate() {
+        println!("Item {}/{}:", i + 1, plans.len());
+        print_human(&describe(plan), plan.chars_per_second);
+    }
+    Ok(())
+}
+
+/// The dry-run summary as a JSON object.
+pub fn describe(plan: &Plan) -> Value {
+    summary(plan, daemon_estimate(plan.text, &plan.voice))
+}
+
+/// The summary, given the daemon's estimate in seconds if it made one.
+fn summary(plan: &Plan, daemon: Option<f64>) -> Value {
+    let characters = plan.text.chars().count();
+    let (estimate, source) = match daemon {
+        Some(secs) => (secs, "daemon"),
+        None => (characters as f64 / plan.chars_per_second, "heuristic"),
+    };
+    let destination = plan.output.unwrap_or(&plan.sink);
+    let action = match plan.output {
+        None => "play",
+        Some(path) => match output::check_existing(Path::new(path), plan.policy) {
+            Ok(Some(_)) => "skip",
+            Ok(None) if Path::new(path).exists() => match plan.policy {
+                ExistingPolicy::Append => "append",
+                _ => "overwrite",
+            },
+            Ok(None) => "write",
+            Err(_) => "refuse",
+        },
+    };
+
+    json!({
+        "dry_run": true,
+        "characters": characters,
+        "estimated_duration_secs": (estimate * 10.0).round() / 10.0,
+        "estimate_source": source,
+        "chunks": plan.chunks,
+        "voice": plan.voice,
+        "output": destination,
+        "output_action": action,
+    })
+}
+
+fn print_human(summary: &Value, chars_per_second: f64) {
+    let field = |k: &str| match summary.get(k) {
+        Some(Value::String(s)) => s.clone(),
+        Some(v) => v.to_string(),
+        None => String::new(),
+    };
+    let estimate = summary.get("estimated_duration_secs").and_then(Value::as_f64).unwrap_or(0.0);
+    println!("  characters: {}", field("characters"));
+    match field("estimate_source").as_str() {
+        "daemon" => println!("  estimate:   ~{} (daemon)", clock(Duration::from_secs_f64(estimate))),
+        _ => println!(
+            "  estimate:   ~{} ({} chars/s heuristic)",
+            clock(Duration::from_secs_f64(estimate)),
+            chars_per_second
+        ),
+    }
+    println!("  chunks:     {}", field("chunks"));
+    println!("  voice:      {}", field("voice"));
+    println!("  output:     {} ({})", field("output"), field("output_action"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan<'a>(output: Option<&'a str>, policy: ExistingPolicy) -> Plan<'a> {
+        Plan {
+            text: "Twenty characters...",
+            voice: "alba".to_string(),
+            output,
+            sink: "speakers".to_string(),
+            policy,
+            chars_per_second: 15.0,
+            chunks: 1,
+        }
+    }
+
+    #[test]
+    fn describes_the_plan_as_one_json_object() {
+        assert_eq!(
+            summary(&plan(None, ExistingPolicy::Refuse), None).to_string(),
+            r#"{"dry_run":true,"characters":20,"estimated_duration_secs":1.3,"estimate_source":"heuristic","chunks":1,"voice":"alba","output":"speakers","output_action":"play"}"#
+        );
+        let daemon = summary(&plan(None, ExistingPolicy::Refuse), Some(2.04));
+        assert_eq!(daemon.get("estimated_duration_secs").and_then(Value::as_f64), Some(2.0));
+        assert!(matches!(daemon.get("estimate_source"), Some(Value::String(s)) if s == "daemon"));
+    }
+
+    #[test]
+    fn says_what_would_happen_to_the_output() {
+        let existing = std::env::temp_dir().join(format!("speakturbo-dry-run-{}.wav", std::process::id()));
+        std::fs::write(&existing, b"").unwrap();
+        let existing = existing.to_str().unwrap();
+        let missing = format!("{}.missing", existing);
+        let action = |output: &str, policy| match summary(&plan(Some(output), policy), None).get("output_action") {
+            Some(Value::String(action)) => action.clone(),
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(action(&missing, ExistingPolicy::Refuse), "write");
+        assert_eq!(action(existing, ExistingPolicy::Refuse), "refuse");
+        assert_eq!(action(existing, ExistingPolicy::Overwrite), "overwrite");
+        assert_eq!(action(existing, ExistingPolicy::Append), "append");
+        assert_eq!(action(existing, ExistingPolicy::Skip), "skip");
+        std::fs::remove_file(existing).unwrap();
+    }
+}
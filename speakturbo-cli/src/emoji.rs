@@ -0,0 +1,200 @@
+//! `--emoji`: what to do with emoji, which daemons skip or read as "unknown
+//! character". Each emoji is taken whole, as its grapheme cluster: ZWJ
+//! sequences (👨‍👩‍👧), flags (🇫🇷, 🏴 with tags), keycaps (1️⃣) and skin tones.
+//! Which clusters are emoji, and their names, come from the `emojis` crate.
+
+use emojis::{Emoji, SkinTone};
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Mode {
+    /// Remove emoji, with their variation selectors and skin tones
+    Strip,
+    /// Leave them in the text
+    Keep,
+    /// Read out their names, set off by commas
+    Speak,
+}
+
+/// Punctuation that ends a name as well as a comma would
+const CLOSING: &str = ",.!?;:…)]\"'”’";
+
+const ZWJ: char = '\u{200d}';
+
+/// What a grapheme cluster is, as far as --emoji goes
+enum Cluster {
+    /// An emoji, with its data where the crate has it
+    Emoji(Option<&'static Emoji>),
+    /// Variation selectors, skin tones, keycaps or tags with nothing to
+    /// modify
+    Stray,
+    Text,
+}
+
+fn cluster(text: &str) -> Cluster {
+    if let Some(emoji) = lookup(text) {
+        return Cluster::Emoji(Some(emoji));
+    }
+    // A sequence the data doesn't list goes by its first part
+    if let Some(first) = text.split(ZWJ).next().filter(|first| first.len() < text.len()).and_then(lookup) {
+        return Cluster::Emoji(Some(first));
+    }
+    let first = text.chars().next().unwrap_or_default();
+    if text.chars().all(is_modifier) {
+        Cluster::Stray
+    } else if ('\u{1f1e6}'..='\u{1faff}').contains(&first) {
+        // Newer than the data, or a lone regional indicator
+        Cluster::Emoji(None)
+    } else {
+        Cluster::Text
+    }
+}
+
+/// The emoji `text` is. A symbol such as ©, ↔ or ☀ is one only with U+FE0F
+/// after it, as the data writes it, and never with U+FE0E.
+fn lookup(text: &str) -> Option<&'static Emoji> {
+    // Red heart: text style by the book, but an emoji in any chat
+    emojis::get(text).filter(|emoji| emoji.as_str() == text || text.chars().count() > 1 || text == "\u{2764}")
+}
+
+fn is_modifier(c: char) -> bool {
+    matches!(c, '\u{fe0e}' | '\u{fe0f}' | '\u{20e3}' | '\u{1f3fb}'..='\u{1f3ff}' | '\u{e0020}'..='\u{e007f}')
+}
+
+/// `text` with its emoji stripped, kept or named, per `mode`.
+pub fn handle(text: &str, mode: Mode) -> String {
+    if mode == Mode::Keep {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    // After a name, the comma that closes it unless punctuation follows
+    let mut after_name = false;
+    // Whitespace dropped before the punctuation closing a name
+    let mut skip_to = 0;
+    for (at, part) in text.grapheme_indices(true) {
+        if at < skip_to {
+            continue;
+        }
+        match cluster(part) {
+            Cluster::Emoji(Some(emoji)) if mode == Mode::Speak => {
+                let trimmed = out.trim_end().len();
+                out.truncate(trimmed);
+                if !out.is_empty() && !out.ends_with(['(', '[', '"', '\'', '“', '‘', '\n', ',']) {
+                    out.push(',');
+                }
+                if !out.is_empty() && !out.ends_with(['(', '[', '"', '\'', '“', '‘', '\n']) {
+                    out.push(' ');
+                }
+                out.push_str(&name(emoji));
+                after_name = true;
+            }
+            Cluster::Emoji(_) | Cluster::Stray => {}
+            Cluster::Text => {
+                if after_name {
+                    after_name = false;
+                    let rest = &text[at..];
+                    let next = rest.trim_start();
+                    if next.chars().next().is_none_or(|c| CLOSING.contains(c)) {
+                        // Punctuation closes the name instead, right after it
+                        skip_to = at + rest.len() - next.len();
+                        if skip_to > at {
+                            continue;
+                        }
+                    } else {
+                        out.push(',');
+                        if !part.starts_with(char::is_whitespace) {
+                            out.push(' ');
+                        }
+                    }
+                }
+                // A stray modifier can join the cluster before it, a space say
+                out.extend(part.chars().filter(|&c| !is_modifier(c)));
+            }
+        }
+    }
+    out
+}
+
+/// What to call an emoji: its CLDR short name, without skin tones or what
+/// follows a colon, but flags by their place and keycaps by their key.
+fn name(emoji: &Emoji) -> String {
+    let emoji = emoji.with_skin_tone(SkinTone::Default).unwrap_or(emoji);
+    match emoji.name().split_once(": ") {
+        Some(("flag", place)) => format!("{} flag", place),
+        Some(("keycap", "#")) => "keycap number sign".to_string(),
+        Some(("keycap", "*")) => "keycap asterisk".to_string(),
+        Some(("keycap", key)) => format!("keycap {}", key),
+        Some((name, _)) => name.to_string(),
+        None => emoji.name().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The emoji clusters of `text`
+    fn clusters(text: &str) -> Vec<&str> {
+        text.graphemes(true).filter(|part| matches!(cluster(part), Cluster::Emoji(_))).collect()
+    }
+
+    #[test]
+    fn segments_emoji_clusters() {
+        let family = "\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}\u{200d}\u{1f466}";
+        let coder = "\u{1f9d1}\u{1f3fd}\u{200d}\u{1f4bb}";
+        let rainbow = "\u{1f3f3}\u{fe0f}\u{200d}\u{1f308}";
+        let facepalm = "\u{1f926}\u{1f3fb}\u{200d}\u{2640}\u{fe0f}";
+        let england = "\u{1f3f4}\u{e0067}\u{e0062}\u{e0065}\u{e006e}\u{e0067}\u{e007f}";
+        let cases: &[(String, &[&str])] = &[
+            (format!("Hi {}!", family), &[family]),
+            (format!("{}{}", coder, rainbow), &[coder, rainbow]),
+            ("🇺🇸🇬🇧🇫".to_string(), &["🇺🇸", "🇬🇧", "🇫"]),
+            (format!("{} at {}", facepalm, england), &[facepalm, england]),
+            ("1\u{fe0f}\u{20e3} #\u{20e3} 1 #".to_string(), &["1\u{fe0f}\u{20e3}", "#\u{20e3}"]),
+            ("👍🏽👍 ❤\u{fe0f}\u{200d}\u{1f525}".to_string(), &["👍🏽", "👍", "❤\u{fe0f}\u{200d}\u{1f525}"]),
+            // Text-style symbols are only emoji when asked to be
+            ("© 2024 ↔ ☀ ☀\u{fe0f} ✓".to_string(), &["☀\u{fe0f}"]),
+            ("❤\u{fe0e} plain".to_string(), &[]),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(clusters(text), *expected, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn strips_emoji() {
+        let cases = [
+            ("Ship it 🚀🚀", "Ship it "),
+            ("Nice 👍🏽 work", "Nice  work"),
+            ("Family \u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467} time", "Family  time"),
+            // Orphaned skin tones and selectors go too; © and ✓ stay
+            ("odd \u{1f3fb}\u{fe0f} bits © ✓", "odd  bits © ✓"),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(handle(text, Mode::Strip), expected, "{:?}", text);
+        }
+        assert_eq!(handle("Keep 👍", Mode::Keep), "Keep 👍");
+    }
+
+    #[test]
+    fn speaks_emoji_names() {
+        let cases = [
+            ("Great job 👍", "Great job, thumbs up"),
+            ("Great job 👍!", "Great job, thumbs up!"),
+            ("Great job 👍 see you", "Great job, thumbs up, see you"),
+            ("👍 see you", "thumbs up, see you"),
+            ("Deploy done 🚀🎉 now", "Deploy done, rocket, party popper, now"),
+            ("Yes, 👍🏿, sure", "Yes, thumbs up, sure"),
+            ("Team \u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}\u{200d}\u{1f466}.", "Team, family."),
+            ("Pride \u{1f3f3}\u{fe0f}\u{200d}\u{1f308} and 🇫🇷 and 🇺🇸", "Pride, rainbow flag, and, France flag, and, United States flag"),
+            ("Ugh \u{1f926}\u{1f3fb}\u{200d}\u{2640}\u{fe0f}", "Ugh, woman facepalming"),
+            ("Press 1\u{fe0f}\u{20e3} now", "Press, keycap 1, now"),
+            ("(🔥)", "(fire)"),
+            // Unnamed ones go, as with strip; normalizing tidies the spaces
+            ("Unknown \u{1fafc} here", "Unknown  here"),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(handle(text, Mode::Speak), expected, "{:?}", text);
+        }
+    }
+}
@@ -0,0 +1,352 @@
+//! `--epub`: read a book chapter by chapter. An EPUB is a zip whose package
+//! file (the OPF) lists the chapter documents in reading order, the spine.
+
+use crate::html::{Document, Render};
+use crate::{charset, reporter, sequence, text, Args};
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use std::time::Instant;
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+// Parts that would inflate past this are refused (zip bombs)
+const MAX_PART_BYTES: u64 = 256 << 20;
+
+type Archive = ZipArchive<Cursor<Vec<u8>>>;
+
+// Images, footnote markers and the notes themselves are not read aloud
+const CHAPTER_TEXT: Render = Render { drop_boilerplate: true, drop_images: true, drop_note_refs: true };
+
+/// Chapters picked with --chapters: "3", "3-5", "7-" (to the end), or a
+/// comma-separated list of those.
+#[derive(Clone, Debug)]
+pub struct Chapters(Vec<(usize, Option<usize>)>);
+
+impl Chapters {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let number = |n: &str| {
+            n.trim().parse::<usize>().ok().filter(|&n| n > 0).ok_or_else(|| format!("{:?} is not a chapter number", n.trim()))
+        };
+        let mut ranges = Vec::new();
+        for part in s.split(',') {
+            let range = match part.split_once('-') {
+                Some((first, last)) if last.trim().is_empty() => (number(first)?, None),
+                Some((first, last)) => (number(first)?, Some(number(last)?)),
+                None => (number(part)?, Some(number(part)?)),
+            };
+            if range.1.is_some_and(|last| last < range.0) {
+                return Err(format!("{:?} runs backwards", part.trim()));
+            }
+            ranges.push(range);
+        }
+        Ok(Chapters(ranges))
+    }
+
+    /// The chapter numbers picked, in order and without repeats.
+    fn select(&self, total: usize) -> Result<Vec<usize>> {
+        let mut picked = Vec::new();
+        for &(first, last) in &self.0 {
+            let last = last.unwrap_or(total);
+            if first.max(last) > total {
+                bail!("Chapter {} is past the end; the book has {}", first.max(last), total);
+            }
+            picked.extend(first..=last);
+        }
+        picked.sort_unstable();
+        picked.dedup();
+        Ok(picked)
+    }
+}
+
+pub struct Book {
+    /// Reading a part moves the archive's cursor, chapter by chapter
+    archive: RefCell<Archive>,
+    title: Option<String>,
+    /// Archive paths of the chapter documents, in reading order
+    spine: Vec<String>,
+}
+
+struct Chapter {
+    title: String,
+    /// Everything after the heading
+    text: String,
+}
+
+impl Book {
+    pub fn open(path: &str) -> Result<Self> {
+        let data = fs::read(path).with_context(|| format!("Cannot read {}", path))?;
+        let mut archive = ZipArchive::new(Cursor::new(data))?;
+
+        let container = Document::parse(&part(&mut archive, "META-INF/container.xml")?);
+        let opf_path = container
+            .elements("rootfile")
+            .find_map(|id| container.attr(id, "full-path"))
+            .context("META-INF/container.xml names no package file")?
+            .to_string();
+        let opf = Document::parse(&part(&mut archive, &opf_path)?);
+        let base = opf_path.rsplit_once('/').map_or("", |(dir, _)| dir);
+
+        let manifest: HashMap<&str, &str> = opf
+            .elements("item")
+            .filter_map(|id| Some((opf.attr(id, "id")?, opf.attr(id, "href")?)))
+            .collect();
+        let spine = opf
+            .elements("itemref")
+            .map(|id| {
+                let idref = opf.attr(id, "idref").unwrap_or("");
+                match manifest.get(idref) {
+                    Some(href) => Ok(resolve(base, href)),
+                    None => bail!("{}: spine item {:?} is not in the manifest", opf_path, idref),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if spine.is_empty() {
+            bail!("{}: the spine lists no chapters", opf_path);
+        }
+
+        let title = opf.elements("title").next().map(|id| flatten(&opf.text_of(&[id], Render::default())));
+        Ok(Book { title: title.filter(|t| !t.is_empty()), archive: RefCell::new(archive), spine })
+    }
+
+    /// Chapter `n` (1-based): its first heading, falling back to the
+    /// document title, and its text with the heading taken off the top.
+    fn chapter(&self, n: usize) -> Result<Chapter> {
+        let doc = Document::parse(&part(&mut self.archive.borrow_mut(), &self.spine[n - 1])?);
+        let heading = ["h1", "h2", "h3"].iter().find_map(|h| doc.elements(h).next());
+        let heading = heading.map(|h| doc.text_of(&[h], CHAPTER_TEXT)).unwrap_or_default();
+        let body = doc.elements("body").next().unwrap_or_else(|| doc.root());
+        let text = doc.text_of(&[body], CHAPTER_TEXT);
+
+        let title = match flatten(&heading) {
+            title if title.is_empty() => {
+                doc.elements("title").next().map(|id| flatten(&doc.text_of(&[id], Render::default()))).unwrap_or_default()
+            }
+            title => title,
+        };
+        let text = match text.strip_prefix(heading.as_str()) {
+            Some(rest) if !heading.is_empty() => rest.trim_start().to_string(),
+            _ => text,
+        };
+        Ok(Chapter { title, text })
+    }
+}
+
+impl Chapter {
+    /// The text to speak, announced: "Chapter three: The Storm."
+    fn spoken(&self, n: usize) -> String {
+        let mut announcement = match self.title.as_str() {
+            "" => format!("Chapter {}", text::cardinal(n as u64)),
+            // The heading already says which chapter it is
+            title if title.to_lowercase().starts_with("chapter") => title.to_string(),
+            title => format!("Chapter {}: {}", text::cardinal(n as u64), title),
+        };
+        if !announcement.ends_with(|c: char| ".!?:;".contains(c)) {
+            announcement.push('.');
+        }
+        match self.text.is_empty() {
+            true => announcement,
+            false => format!("{}\n\n{}", announcement, self.text),
+        }
+    }
+}
+
+pub fn run(args: &Args, path: &str, start: Instant) -> Result<()> {
+    let book = Book::open(path).with_context(|| format!("{} is not a readable EPUB", path))?;
+    let total = book.spine.len();
+    if args.list_chapters {
+        return list(&book, args.json);
+    }
+    let picked = match &args.chapters {
+        Some(chapters) => chapters.select(total)?,
+        None => (1..=total).collect(),
+    };
+
+    let mut texts = Vec::new();
+    let mut outputs = Vec::new();
+    for n in picked {
+        let chapter = book.chapter(n).with_context(|| format!("{}: chapter {} is unreadable", path, n))?;
+        // Covers and other image-only pages
+        if chapter.title.is_empty() && chapter.text.is_empty() {
+            reporter::info(format_args!("Skipping chapter {}: no text", n));
+            continue;
+        }
+        texts.push(crate::normalized(args, chapter.spoken(n)));
+        outputs.push(args.output.as_deref().map(|output| output_path(output, n, total)));
+    }
+    if texts.is_empty() {
+        bail!("No readable text in the chapters picked");
+    }
+
+    if let Some(dir) = args.output.as_deref().filter(|o| !o.contains("{n}") && !args.dry_run) {
+        fs::create_dir_all(dir).with_context(|| format!("Cannot create {}", dir))?;
+    }
+    sequence::run_to(args, &texts, outputs, start)
+}
+
+/// `-o` is either a template with `{n}` or a directory to hold
+/// `chapter-{n}.wav`, numbered by spine position.
+fn output_path(output: &str, n: usize, total: usize) -> String {
+    match output.contains("{n}") {
+        true => sequence::expand(output, n, total),
+        false => Path::new(output).join(sequence::expand("chapter-{n}.wav", n, total)).to_string_lossy().into_owned(),
+    }
+}
+
+fn list(book: &Book, json: bool) -> Result<()> {
+    let width = book.spine.len().to_string().len();
+    let mut array = Vec::new();
+    if let (Some(title), false) = (&book.title, json) {
+        println!("{}", title);
+    }
+    for (i, href) in book.spine.iter().enumerate() {
+        let chapter = book.chapter(i + 1).with_context(|| format!("{} is unreadable", href))?;
+        let title = match chapter.title.as_str() {
+            "" => "(untitled)",
+            title => title,
+        };
+        match json {
+            true => array.push(json!({"n": i + 1, "title": title, "href": href})),
+            false => println!("{:>width$}  {}", i + 1, title, width = width),
+        }
+    }
+    if json {
+        println!("{}", Value::Array(array));
+    }
+    Ok(())
+}
+
+/// The text of part `name`, decompressed and checksummed.
+fn part(archive: &mut Archive, name: &str) -> Result<String> {
+    let file = archive.by_name(name).map_err(|e| match e {
+        ZipError::FileNotFound => anyhow!("{} is missing", name),
+        e => anyhow!(e).context(format!("{}: unreadable", name)),
+    })?;
+    if file.size() > MAX_PART_BYTES {
+        bail!("{}: too large ({} bytes)", name, file.size());
+    }
+    let mut data = Vec::with_capacity(file.size() as usize);
+    file.take(MAX_PART_BYTES).read_to_end(&mut data).with_context(|| format!("{}: corrupt compressed data", name))?;
+    charset::decode(&data, None)
+}
+
+fn flatten(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// A manifest href as an archive path: relative to the OPF's directory,
+/// percent-decoded, without its fragment, and with `..` worked out.
+fn resolve(base: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or("");
+    let mut segments: Vec<String> = base.split('/').filter(|s| !s.is_empty()).map(str::to_string).collect();
+    for segment in href.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            _ => segments.push(percent_decode(segment)),
+        }
+    }
+    segments.join("/")
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = s.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(files: &[(&str, &str)]) -> Result<Book> {
+        let path = std::env::temp_dir().join(format!("speakturbo-epub-{}-{}.epub", std::process::id(), files.len()));
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, body) in files {
+            zip.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+            std::io::Write::write_all(&mut zip, body.as_bytes()).unwrap();
+        }
+        fs::write(&path, zip.finish().unwrap().into_inner()).unwrap();
+        let book = Book::open(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        book
+    }
+
+    #[test]
+    fn reads_chapters_in_spine_order() {
+        let book = book(&[
+            ("mimetype", "application/epub+zip"),
+            (
+                "META-INF/container.xml",
+                r#"<?xml version="1.0"?><container><rootfiles>
+                   <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+                   </rootfiles></container>"#,
+            ),
+            (
+                "OEBPS/content.opf",
+                r#"<package><metadata><dc:title>The Voyage</dc:title></metadata><manifest>
+                   <item id="c1" href="Text/cover.xhtml" media-type="application/xhtml+xml"/>
+                   <item id="c2" href="Text/storm%20chapter.xhtml" media-type="application/xhtml+xml"/>
+                   </manifest><spine><itemref idref="c1"/><itemref idref="c2"/></spine></package>"#,
+            ),
+            ("OEBPS/Text/cover.xhtml", r#"<html><body><img src="cover.jpg" alt="Cover"/></body></html>"#),
+            (
+                "OEBPS/Text/storm chapter.xhtml",
+                r#"<html><head><title>ch2</title></head><body><section epub:type="chapter">
+                   <h1>The Storm<a epub:type="noteref" href="notes.xhtml#n1">1</a></h1>
+                   <p>Rain fell<sup>2</sup> hard.</p><img src="map.png" alt="A map"/>
+                   <aside epub:type="footnote">Not read.</aside></section></body></html>"#,
+            ),
+        ])
+        .unwrap();
+        assert_eq!(book.title.as_deref(), Some("The Voyage"));
+        assert_eq!(book.spine, ["OEBPS/Text/cover.xhtml", "OEBPS/Text/storm chapter.xhtml"]);
+        let cover = book.chapter(1).unwrap();
+        assert_eq!((cover.title.as_str(), cover.text.as_str()), ("", ""));
+        assert_eq!(book.chapter(2).unwrap().spoken(2), "Chapter two: The Storm.\n\nRain fell hard.");
+    }
+
+    #[test]
+    fn names_the_unreadable_part() {
+        let err = book(&[("mimetype", "application/epub+zip")]).err().unwrap();
+        assert_eq!(err.to_string(), "META-INF/container.xml is missing");
+        let err = book(&[
+            ("META-INF/container.xml", r#"<container><rootfile full-path="book.opf"/></container>"#),
+            ("book.opf", r#"<package><spine><itemref idref="gone"/></spine></package>"#),
+        ])
+        .err()
+        .unwrap();
+        assert_eq!(err.to_string(), "book.opf: spine item \"gone\" is not in the manifest");
+    }
+
+    #[test]
+    fn picks_chapter_ranges() {
+        let picked = |s: &str, total| Chapters::parse(s).unwrap().select(total).map_err(|e| e.to_string());
+        assert_eq!(picked("3-5", 9), Ok(vec![3, 4, 5]));
+        assert_eq!(picked("1, 7-, 4", 8), Ok(vec![1, 4, 7, 8]));
+        assert_eq!(picked("2-12", 10), Err("Chapter 12 is past the end; the book has 10".to_string()));
+        assert!(Chapters::parse("5-3").is_err());
+        assert!(Chapters::parse("0").is_err());
+        assert_eq!(resolve("OEBPS", "../images/a%20b.png#x"), "images/a b.png");
+    }
+}
@@ -0,0 +1,231 @@
+//! `speakturbo exec -- CMD...`: run a command and say how it ended. Its
+//! output reaches the terminal unchanged; with `--speak-lines`, the lines
+//! matching a pattern are spoken as they appear, through the same queue as
+//! `--stream-lines`. Its exit status becomes speakturbo's.
+//!
+//! `--when-done LABEL -- CMD...` is the quieter sibling: the command has
+//! the terminal to itself, and only the end is spoken, from a template.
+
+use crate::exit::{self, Kind};
+use crate::stream::{self, Record};
+use crate::{ansi, interrupt, reporter, Args};
+use anyhow::Result;
+use regex::Regex;
+use std::io::{self, Read, Write};
+use std::process::{Command, ExitStatus};
+use std::sync::mpsc::{self, Sender};
+use std::time::{Duration, Instant};
+
+pub fn run(args: &Args, command: &[String], speak_lines: Option<&Regex>, start: Instant) -> Result<()> {
+    let (program, rest) = command.split_first().ok_or_else(|| exit::fail(Kind::Usage, "exec needs a command"))?;
+    let mut child = Command::new(program);
+    child.args(rest);
+    // Only a pattern needs the output; otherwise it goes straight to the terminal
+    let output = match speak_lines {
+        Some(_) => {
+            let (reader, writer) = io::pipe()?;
+            child.stdout(writer.try_clone()?).stderr(writer);
+            Some(reader)
+        }
+        None => None,
+    };
+    let began = Instant::now();
+    let mut child = child.spawn().map_err(|e| exit::fail(Kind::Usage, format!("Cannot run {}: {}", program, e)))?;
+    interrupt::forward_to(child.id());
+
+    if let (Some(output), Some(pattern)) = (output, speak_lines) {
+        let (tx, records) = mpsc::channel();
+        let pattern = pattern.clone();
+        let max_bytes = args.max_record_bytes as usize;
+        let force_text = args.force_text;
+        std::thread::spawn(move || pass_through(output, &pattern, max_bytes, force_text, tx));
+        if let Err(e) = stream::run(args, records, false, start) {
+            reporter::warning(format_args!("not speaking the output: {:#}", e));
+        }
+    }
+    let status = child.wait()?;
+
+    if !interrupt::requested() {
+        let said = crate::normalized(args, outcome(status, began.elapsed()));
+        if let Err(e) = crate::speak(args, &said, None, crate::existing_policy(args), start) {
+            reporter::warning(format_args!("not speaking how it ended: {:#}", e));
+        }
+    }
+    match code(status) {
+        0 => Ok(()),
+        code => std::process::exit(code),
+    }
+}
+
+/// `--when-done`: runs `args.text` as a command and says how it ended
+/// once it has, if it took at least --min-duration.
+pub fn when_done(args: &mut Args, label: &str, start: Instant) -> Result<()> {
+    let (program, rest) = args.text.split_first().ok_or_else(|| exit::fail(Kind::Usage, "--when-done needs a command after --"))?;
+    let began = Instant::now();
+    let mut child = Command::new(program).args(rest).spawn().map_err(|e| exit::fail(Kind::Usage, format!("Cannot run {}: {}", program, e)))?;
+    interrupt::forward_to(child.id());
+    let status = child.wait()?;
+    let took = began.elapsed();
+
+    if !interrupt::requested() && took.as_secs_f64() >= args.min_duration {
+        if !status.success() {
+            if let Some(voice) = args.failure_voice.take() {
+                args.voice = crate::config::resolve(&args.aliases, &voice).to_string();
+            }
+            if let Some(chime) = args.failure_chime.take() {
+                args.chime_before = Some(chime);
+            }
+        }
+        let said = crate::normalized(args, message(&args.when_done_message, label, status, took));
+        if let Err(e) = crate::speak(args, &said, None, crate::existing_policy(args), start) {
+            reporter::warning(format_args!("not speaking how it ended: {:#}", e));
+        }
+    }
+    match code(status) {
+        0 => Ok(()),
+        code => std::process::exit(code),
+    }
+}
+
+/// `template` with {label}, {outcome} ("finished", "failed with exit code
+/// 1", "was terminated by signal 9") and {duration} filled in
+fn message(template: &str, label: &str, status: ExitStatus, took: Duration) -> String {
+    let outcome = match (status.success(), status.code(), signal(status)) {
+        (true, _, _) => "finished".to_string(),
+        (_, Some(code), _) => format!("failed with exit code {}", code),
+        (_, None, Some(signal)) => format!("was terminated by signal {}", signal),
+        _ => "failed".to_string(),
+    };
+    template.replace("{label}", label).replace("{outcome}", &outcome).replace("{duration}", &spoken_duration(took))
+}
+
+/// Copies `output` to stdout as it comes, and sends each line that
+/// matches `pattern` once its escape sequences are gone.
+fn pass_through(mut output: impl Read, pattern: &Regex, max_bytes: usize, force_text: bool, tx: Sender<Record>) {
+    let mut chunk = [0u8; 8192];
+    let mut line = Vec::new();
+    let mut len = 0;
+    let mut n = 0;
+    let mut ansi = ansi::Stripper::default();
+    let mut send = |n: usize, line: &[u8], len: usize| {
+        let clean = ansi.clean(line);
+        if let Some(Record { text: Ok(text), .. }) = stream::record(n, &clean, len, b'\n', max_bytes, force_text) {
+            if pattern.is_match(&text) {
+                // Speaking may have stopped; the output keeps flowing
+                let _ = tx.send(Record { n, text: Ok(text) });
+            }
+        }
+    };
+    loop {
+        let read = match output.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        };
+        let mut stdout = io::stdout().lock();
+        let _ = stdout.write_all(&chunk[..read]);
+        let _ = stdout.flush();
+        for piece in chunk[..read].split_inclusive(|&b| b == b'\n') {
+            let (body, complete) = match piece.strip_suffix(b"\n") {
+                Some(body) => (body, true),
+                None => (piece, false),
+            };
+            line.extend_from_slice(&body[..body.len().min(max_bytes.saturating_sub(line.len()))]);
+            len += body.len();
+            if complete {
+                n += 1;
+                send(n, &line, len);
+                line.clear();
+                len = 0;
+            }
+        }
+    }
+    if len > 0 {
+        send(n + 1, &line, len);
+    }
+}
+
+/// "command succeeded in 42 seconds", "command failed with exit code 101"
+fn outcome(status: ExitStatus, took: Duration) -> String {
+    if status.success() {
+        return format!("command succeeded in {}", spoken_duration(took));
+    }
+    match (status.code(), signal(status)) {
+        (Some(code), _) => format!("command failed with exit code {}", code),
+        (None, Some(signal)) => format!("command was killed by signal {}", signal),
+        (None, None) => "command failed".to_string(),
+    }
+}
+
+fn spoken_duration(took: Duration) -> String {
+    let plural = |n: u64, unit: &str| format!("{} {}{}", n, unit, if n == 1 { "" } else { "s" });
+    match took.as_secs() {
+        secs if secs < 60 => plural(secs, "second"),
+        secs if secs % 60 == 0 => plural(secs / 60, "minute"),
+        secs => format!("{} {}", plural(secs / 60, "minute"), plural(secs % 60, "second")),
+    }
+}
+
+/// The exit status a shell would give: the code, or 128 and the signal
+fn code(status: ExitStatus) -> i32 {
+    status.code().or_else(|| signal(status).map(|signal| 128 + signal)).unwrap_or(1)
+}
+
+#[cfg(unix)]
+fn signal(status: ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+#[cfg(not(unix))]
+fn signal(_: ExitStatus) -> Option<i32> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(shell: &str) -> ExitStatus {
+        Command::new("sh").args(["-c", shell]).status().unwrap()
+    }
+
+    #[test]
+    fn says_how_the_command_ended() {
+        let cases = [
+            ("true", 42, "command succeeded in 42 seconds", 0),
+            ("true", 61, "command succeeded in 1 minute 1 second", 0),
+            ("true", 120, "command succeeded in 2 minutes", 0),
+            ("exit 101", 3, "command failed with exit code 101", 101),
+            ("kill -TERM $$", 3, "command was killed by signal 15", 143),
+        ];
+        for (shell, secs, said, exit_code) in cases {
+            let status = status(shell);
+            assert_eq!((outcome(status, Duration::from_secs(secs)), code(status)), (said.to_string(), exit_code), "{:?}", shell);
+        }
+    }
+
+    #[test]
+    fn fills_in_the_when_done_message() {
+        let template = "{label} {outcome} after {duration}";
+        let cases = [
+            ("true", "the backup finished after 5 minutes 3 seconds"),
+            ("exit 23", "the backup failed with exit code 23 after 5 minutes 3 seconds"),
+            ("kill -KILL $$", "the backup was terminated by signal 9 after 5 minutes 3 seconds"),
+        ];
+        for (shell, said) in cases {
+            assert_eq!(message(template, "the backup", status(shell), Duration::from_secs(303)), said, "{:?}", shell);
+        }
+        assert_eq!(message("{label}: {outcome}", "tests", status("exit 1"), Duration::ZERO), "tests: failed with exit code 1");
+    }
+
+    #[test]
+    fn passes_output_through_and_picks_lines() {
+        let output = b"Compiling a\n\x1b[31merror\x1b[0m: oops\npartial error".to_vec();
+        let (tx, rx) = mpsc::channel();
+        pass_through(io::Cursor::new(output), &Regex::new("error").unwrap(), 1024, false, tx);
+        let spoken: Vec<(usize, String)> = rx.iter().map(|r| (r.n, r.text.unwrap())).collect();
+        assert_eq!(spoken, [(2, "error: oops".to_string()), (3, "partial error".to_string())]);
+    }
+}
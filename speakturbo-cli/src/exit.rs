@@ -0,0 +1,145 @@
+//! Exit statuses by kind of failure, so that a script can tell a daemon
+//! that's down from a voice it doesn't have. Errors stay anyhow chains
+//! until main, which reads the kind off the chain with [`Kind::of`].
+
+use serde::{Deserialize, Serialize};
+use speakturbo_client::SpeakError;
+use std::fmt;
+
+/// Serialized as --json's "kind", in snake_case
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    /// Bad flags or settings, or a voice the daemon doesn't have
+    Usage,
+    DaemonUnreachable,
+    /// The daemon answered, with an error
+    DaemonError,
+    AudioUnavailable,
+    /// Unreadable, empty, binary or over-long text
+    Input,
+    Interrupted,
+    /// An -o FIFO's reader went away
+    ReaderGone,
+    /// Some items of a sequence or batch failed, and others didn't
+    Partial,
+    /// The --on-complete hook failed, with --strict-hooks
+    Hook,
+    Other,
+}
+
+impl Kind {
+    pub fn code(self) -> i32 {
+        match self {
+            Kind::Other => 1,
+            // As clap exits on a usage error
+            Kind::Usage => 2,
+            Kind::DaemonUnreachable => 3,
+            Kind::DaemonError => 4,
+            Kind::AudioUnavailable => 5,
+            Kind::Input => 6,
+            Kind::Partial => 7,
+            Kind::Hook => 8,
+            Kind::Interrupted => 130,
+            // As if killed by SIGPIPE
+            Kind::ReaderGone => 141,
+        }
+    }
+
+    /// The kind of the first cause in `error`'s chain that has one.
+    pub fn of(error: &anyhow::Error) -> Kind {
+        error.chain().find_map(kind_of).unwrap_or(Kind::Other)
+    }
+}
+
+fn kind_of(cause: &(dyn std::error::Error + 'static)) -> Option<Kind> {
+    if let Some(failure) = cause.downcast_ref::<Failure>() {
+        return Some(failure.kind);
+    }
+    if cause.is::<crate::fifo::ReaderGone>() {
+        return Some(Kind::ReaderGone);
+    }
+    if cause.is::<crate::text::Binary>() {
+        return Some(Kind::Input);
+    }
+    if cause.is::<clap::Error>() {
+        return Some(Kind::Usage);
+    }
+    match cause.downcast_ref::<SpeakError>()? {
+        SpeakError::DaemonUnreachable { .. } | SpeakError::Timeout { .. } => Some(Kind::DaemonUnreachable),
+        SpeakError::DaemonError { .. } => Some(Kind::DaemonError),
+        SpeakError::OutputDevice { .. } => Some(Kind::AudioUnavailable),
+        SpeakError::Cancelled => Some(Kind::Interrupted),
+        SpeakError::Request(_) => Some(Kind::Usage),
+        // Audio and JSON are as often the user's files as the daemon's
+        _ => None,
+    }
+}
+
+/// An error that says what kind it is, where nothing in its chain would.
+#[derive(Debug)]
+pub struct Failure {
+    kind: Kind,
+    message: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+/// `message` as an error of `kind`.
+pub fn fail(kind: Kind, message: impl Into<String>) -> anyhow::Error {
+    Failure { kind, message: message.into() }.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Kind; 10] = [
+        Kind::Usage,
+        Kind::DaemonUnreachable,
+        Kind::DaemonError,
+        Kind::AudioUnavailable,
+        Kind::Input,
+        Kind::Interrupted,
+        Kind::ReaderGone,
+        Kind::Partial,
+        Kind::Hook,
+        Kind::Other,
+    ];
+
+    #[test]
+    fn reads_the_kind_off_the_chain() {
+        let client = speakturbo_client::SpeakClient::new("http://127.0.0.1:9");
+        let refused = anyhow::Error::from(client.health().unwrap_err()).context("No voices");
+        let refusal = SpeakError::DaemonError { status: 400, message: "unknown voice nobody".to_string(), retry_after: None };
+        let cases = [
+            (refused, Kind::DaemonUnreachable),
+            (refusal.into(), Kind::DaemonError),
+            (SpeakError::output_device("no device").into(), Kind::AudioUnavailable),
+            (anyhow::Error::from(SpeakError::BadAudio { reason: "Not a WAV stream".to_string() }).context("Invalid chime c.wav"), Kind::Other),
+            (fail(Kind::Input, "No text").context("Cannot read a.txt"), Kind::Input),
+            (anyhow::Error::from(crate::fifo::ReaderGone("out.fifo".to_string())), Kind::ReaderGone),
+            (anyhow::anyhow!("something else"), Kind::Other),
+        ];
+        for (error, kind) in cases {
+            assert_eq!(Kind::of(&error), kind, "{:#}", error);
+        }
+        assert_eq!(fail(Kind::Usage, "bad").to_string(), "bad");
+    }
+
+    #[test]
+    fn gives_each_kind_its_own_code_and_name() {
+        for (i, a) in ALL.iter().enumerate() {
+            for b in &ALL[i + 1..] {
+                assert_ne!(a.code(), b.code(), "{:?} {:?}", a, b);
+                assert_ne!(serde_json::to_value(a).unwrap(), serde_json::to_value(b).unwrap(), "{:?} {:?}", a, b);
+            }
+        }
+    }
+}
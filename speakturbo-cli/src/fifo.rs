@@ -0,0 +1,175 @@
+//! Named pipes as output targets: wait for the reader instead of blocking
+//! forever, and report a departed reader distinctly.
+
+use anyhow::{bail, Context, Result};
+use std::fs::File;
+use std::path::Path;
+use std::time::Duration;
+
+/// The process reading the FIFO closed it before the audio was done.
+#[derive(Debug)]
+pub struct ReaderGone(pub String);
+
+impl std::fmt::Display for ReaderGone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "reader closed {} before the audio ended", self.0)
+    }
+}
+
+impl std::error::Error for ReaderGone {}
+
+/// Map a broken pipe anywhere in `err` to `ReaderGone`.
+pub fn reader_gone(err: anyhow::Error, path: &Path) -> anyhow::Error {
+    let broken = err
+        .chain()
+        .filter_map(|e| e.downcast_ref::<std::io::Error>())
+        .any(|e| e.kind() == std::io::ErrorKind::BrokenPipe);
+    if broken {
+        ReaderGone(path.display().to_string()).into()
+    } else {
+        err
+    }
+}
+
+#[cfg(unix)]
+pub fn is_fifo(path: &Path) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    std::fs::metadata(path).map(|m| m.file_type().is_fifo()).unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+pub fn is_fifo(_path: &Path) -> bool {
+    false
+}
+
+/// Open a FIFO for writing once a reader has it open. `wait` of None waits
+/// indefinitely; Ctrl+C always stops the wait.
+#[cfg(unix)]
+pub fn open_writer(path: &Path, wait: Option<Duration>) -> Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::io::AsRawFd;
+
+    let started = std::time::Instant::now();
+    loop {
+        // Non-blocking open fails with ENXIO until someone opens the read end
+        let opened = std::fs::OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path);
+        match opened {
+            Ok(file) => {
+                // Back to blocking writes so a slow reader applies backpressure
+                unsafe {
+                    let fd = file.as_raw_fd();
+                    let flags = libc::fcntl(fd, libc::F_GETFL);
+                    libc::fcntl(fd, libc::F_SETFL, flags & !libc::O_NONBLOCK);
+                }
+                return Ok(file);
+            }
+            Err(e) if e.raw_os_error() == Some(libc::ENXIO) => {
+                if crate::interrupt::requested() {
+                    bail!("interrupted");
+                }
+                if wait.is_some_and(|w| started.elapsed() >= w) {
+                    bail!(
+                        "No reader opened {} within {}s",
+                        path.display(),
+                        wait.unwrap_or_default().as_secs_f64()
+                    );
+                }
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            Err(e) => return Err(e).with_context(|| format!("Cannot open {}", path.display())),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn open_writer(path: &Path, _wait: Option<Duration>) -> Result<File> {
+    File::create(path).with_context(|| format!("Cannot open {}", path.display()))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::chime::Chimes;
+    use crate::output::{self, ExistingPolicy, SaveOptions};
+    use crate::wav;
+    use std::io::Read;
+    use std::path::PathBuf;
+
+    fn mkfifo(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("speakturbo-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let c_path = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
+        path
+    }
+
+    fn daemon_wav(samples: usize) -> Vec<u8> {
+        let format = wav::WavFormat { channels: 1, sample_rate: 24000, bits_per_sample: 16 };
+        let mut bytes = Vec::new();
+        wav::write_header(&mut bytes, format, wav::STREAMING_DATA_LEN).unwrap();
+        bytes.extend((0..samples).flat_map(|i| (i as i16).to_le_bytes()));
+        bytes
+    }
+
+    fn options(wait: Option<Duration>) -> SaveOptions {
+        SaveOptions {
+            policy: ExistingPolicy::Refuse,
+            checksum: None,
+            envelope: None,
+            chimes: Chimes::default(),
+            fifo_wait: wait,
+        }
+    }
+
+    #[test]
+    fn streams_to_a_waiting_reader() {
+        let path = mkfifo("fifo-ok");
+        let reader_path = path.clone();
+        let reader = std::thread::spawn(move || {
+            let mut out = Vec::new();
+            File::open(reader_path).unwrap().read_to_end(&mut out).unwrap();
+            out
+        });
+
+        let audio = daemon_wav(1000);
+        assert!(output::check_existing(&path, ExistingPolicy::Refuse).unwrap().is_none());
+        output::save(&path, &options(Some(Duration::from_secs(5))), &audio[..]).unwrap();
+        let received = reader.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // Not seekable, so the streaming placeholder sizes stay
+        assert_eq!(received, audio);
+    }
+
+    #[test]
+    fn departed_reader_is_reported() {
+        let path = mkfifo("fifo-gone");
+        let reader_path = path.clone();
+        let reader = std::thread::spawn(move || {
+            let mut head = [0u8; 16];
+            File::open(reader_path).unwrap().read_exact(&mut head).unwrap();
+        });
+
+        let audio = daemon_wav(1 << 20);
+        let err = output::save(&path, &options(Some(Duration::from_secs(5))), &audio[..])
+            .map(|_| ())
+            .unwrap_err();
+        reader.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(err.downcast_ref::<ReaderGone>().is_some(), "{:#}", err);
+    }
+
+    #[test]
+    fn gives_up_without_a_reader() {
+        let path = mkfifo("fifo-none");
+        let audio = daemon_wav(10);
+        let err = output::save(&path, &options(Some(Duration::from_millis(100))), &audio[..])
+            .map(|_| ())
+            .unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(format!("{:#}", err).contains("No reader"));
+    }
+}
@@ -0,0 +1,154 @@
+//! `--follow`: speak lines appended to a file, like `tail -f`. Survives
+//! truncation and rotation by watching the file's size and identity.
+
+use crate::stream::{self, Record};
+use anyhow::{Context, Result};
+use std::fs::{self, File, Metadata};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::time::Duration;
+
+/// Lines appended to `path` from now on (or from its start), delivered as
+/// they appear. Polls every `interval`; the reader never ends by itself.
+pub fn lines(
+    path: &str,
+    from_start: bool,
+    interval: Duration,
+    max_bytes: usize,
+    force_text: bool,
+) -> Result<Receiver<Record>> {
+    let mut file = File::open(path).with_context(|| format!("Cannot open {}", path))?;
+    let meta = file.metadata()?;
+    let mut id = identity(&meta);
+    let mut pos = if from_start { 0 } else { meta.len() };
+
+    let path = PathBuf::from(path);
+    let (tx, rx) = mpsc::sync_channel(1);
+    std::thread::spawn(move || {
+        let mut splitter = Splitter { buf: Vec::new(), len: 0, n: 0, max_bytes, force_text };
+        let mut failing = false;
+        loop {
+            if read_new(&mut file, &path, &mut pos, &mut splitter, &mut failing, &tx).is_err() {
+                return;
+            }
+            std::thread::sleep(interval);
+            match fs::metadata(&path) {
+                // Rotated: finish the old file, then start the new one from the top
+                Ok(meta) if identity(&meta) != id => {
+                    if read_new(&mut file, &path, &mut pos, &mut splitter, &mut failing, &tx).is_err() {
+                        return;
+                    }
+                    if let Ok(reopened) = File::open(&path) {
+                        file = reopened;
+                        id = identity(&meta);
+                        pos = 0;
+                        splitter.reset();
+                    }
+                }
+                // Truncated in place
+                Ok(meta) if meta.len() < pos => {
+                    pos = 0;
+                    splitter.reset();
+                }
+                // Unchanged, grown, or missing mid-rotation: keep the handle
+                _ => {}
+            }
+        }
+    });
+    Ok(rx)
+}
+
+/// Pass everything past `pos` to the splitter. Errs only when the receiving
+/// end is gone. A read error comes through as an error record, once while
+/// `failing`, and the read is tried again next time.
+fn read_new(file: &mut File, path: &Path, pos: &mut u64, splitter: &mut Splitter, failing: &mut bool, tx: &SyncSender<Record>) -> Result<(), ()> {
+    let mut fresh = Vec::new();
+    if let Err(e) = file.seek(SeekFrom::Start(*pos)).and_then(|_| file.read_to_end(&mut fresh)) {
+        if !std::mem::replace(failing, true) {
+            let error = anyhow::Error::from(e).context(format!("Cannot read {}", path.display()));
+            tx.send(Record { n: splitter.n + 1, text: Err(error) }).map_err(|_| ())?;
+        }
+        return Ok(());
+    }
+    *failing = false;
+    *pos += fresh.len() as u64;
+    splitter.push(&fresh, tx)
+}
+
+#[cfg(unix)]
+fn identity(meta: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((meta.dev(), meta.ino()))
+}
+
+/// No inode to compare; rotation then shows up only as truncation.
+#[cfg(not(unix))]
+fn identity(_meta: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Reassembles lines from arbitrary chunks; a line without its newline
+/// waits for the next chunk.
+struct Splitter {
+    buf: Vec<u8>,
+    len: usize,
+    n: usize,
+    max_bytes: usize,
+    force_text: bool,
+}
+
+impl Splitter {
+    fn push(&mut self, bytes: &[u8], tx: &SyncSender<Record>) -> Result<(), ()> {
+        for piece in bytes.split_inclusive(|&b| b == b'\n') {
+            let (body, complete) = match piece.strip_suffix(b"\n") {
+                Some(body) => (body, true),
+                None => (piece, false),
+            };
+            let room = self.max_bytes.saturating_sub(self.buf.len());
+            self.buf.extend_from_slice(&body[..body.len().min(room)]);
+            self.len += body.len();
+            if complete {
+                self.n += 1;
+                if let Some(record) = stream::record(self.n, &self.buf, self.len, b'\n', self.max_bytes, self.force_text) {
+                    tx.send(record).map_err(|_| ())?;
+                }
+                self.reset();
+            }
+        }
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.buf.clear();
+        self.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn speaks_appended_lines_across_truncation() {
+        let dir = std::env::temp_dir().join(format!("speakturbo-follow-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        fs::write(&path, "old line\n").unwrap();
+
+        let rx = lines(path.to_str().unwrap(), false, Duration::from_millis(10), 1024, false).unwrap();
+        let next = || rx.recv_timeout(Duration::from_secs(2)).unwrap().text.unwrap();
+        let mut log = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        write!(log, "first ").unwrap();
+        log.flush().unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        writeln!(log, "half\nsecond").unwrap();
+        assert_eq!(next(), "first half");
+        assert_eq!(next(), "second");
+
+        fs::write(&path, "after truncate\n").unwrap();
+        assert_eq!(next(), "after truncate");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
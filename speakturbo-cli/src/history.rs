@@ -0,0 +1,201 @@
+//! Opt-in log of what was spoken (`--history`) and `speakturbo history`.
+
+use speakturbo_client::cache::Cache;
+use crate::hooks::Status;
+use crate::SpeakRequest;
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use serde_json::{json, Value};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Rotate to history.jsonl.1 past this size; one old file is kept
+const MAX_LOG_BYTES: u64 = 1 << 20;
+// Longer texts are cut; such entries can only be replayed from the cache
+const MAX_TEXT_CHARS: usize = 1000;
+
+#[derive(Subcommand)]
+pub enum Action {
+    /// Speak a logged entry again, from the cache when possible
+    Play {
+        /// Entry id, as shown by `speakturbo history`
+        id: u64,
+    },
+}
+
+pub struct Entry {
+    pub id: u64,
+    pub time: u64,
+    pub voice: String,
+    pub status: String,
+    pub duration_ms: u64,
+    pub text: String,
+    pub truncated: bool,
+}
+
+/// `$XDG_STATE_HOME/speakturbo`, falling back to `~/.local/state/speakturbo`
+/// (Local AppData on Windows).
+pub fn state_dir() -> Option<PathBuf> {
+    speakturbo_client::dirs::state().map(|base| base.join("speakturbo"))
+}
+
+fn log_path() -> Option<PathBuf> {
+    state_dir().map(|d| d.join("history.jsonl"))
+}
+
+fn rotated_path() -> Option<PathBuf> {
+    state_dir().map(|d| d.join("history.jsonl.1"))
+}
+
+/// Append one finished invocation to the log.
+pub fn append(text: &str, voice: &str, status: Status, elapsed: Duration) -> Result<()> {
+    let path = log_path().context("No state directory: set XDG_STATE_HOME or HOME")?;
+    fs::create_dir_all(path.parent().unwrap_or(&path))?;
+    if fs::metadata(&path).is_ok_and(|m| m.len() > MAX_LOG_BYTES) {
+        if let Some(rotated) = rotated_path() {
+            fs::rename(&path, rotated)?;
+        }
+    }
+
+    let id = read_all()?.last().map_or(1, |e| e.id + 1);
+    let text = text.trim();
+    let truncated = text.chars().count() > MAX_TEXT_CHARS;
+    let kept: String = text.chars().take(MAX_TEXT_CHARS).collect();
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let line = json!({
+        "id": id,
+        "time": time,
+        "voice": voice,
+        "status": status.as_str(),
+        "duration_ms": elapsed.as_millis() as u64,
+        "text": kept,
+        "truncated": truncated,
+    });
+
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&path).with_context(|| format!("Cannot open {}", path.display()))?;
+    writeln!(file, "{}", line)?;
+    Ok(())
+}
+
+/// Every logged entry, oldest first, including the rotated file.
+pub fn read_all() -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for path in [rotated_path(), log_path()].into_iter().flatten() {
+        let content = match fs::read_to_string(&path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).with_context(|| format!("Cannot read {}", path.display())),
+        };
+        // Skip lines we can't parse, e.g. one cut short by a crash
+        entries.extend(content.lines().filter_map(|l| parse_entry(&serde_json::from_str(l).ok()?)));
+    }
+    Ok(entries)
+}
+
+fn parse_entry(v: &Value) -> Option<Entry> {
+    let string = |k: &str| match v.get(k) {
+        Some(Value::String(s)) => Some(s.clone()),
+        _ => None,
+    };
+    let number = |k: &str| v.get(k).and_then(Value::as_f64).map(|n| n as u64);
+    Some(Entry {
+        id: number("id")?,
+        time: number("time").unwrap_or(0),
+        voice: string("voice")?,
+        status: string("status").unwrap_or_default(),
+        duration_ms: number("duration_ms").unwrap_or(0),
+        text: string("text")?,
+        truncated: matches!(v.get("truncated"), Some(Value::Bool(true))),
+    })
+}
+
+/// `speakturbo history`: the most recent entries, optionally filtered.
+pub fn list(limit: usize, pattern: Option<&str>, as_json: bool) -> Result<()> {
+    let pattern = pattern.map(str::to_lowercase);
+    let entries: Vec<Entry> = read_all()?
+        .into_iter()
+        .filter(|e| pattern.as_ref().is_none_or(|p| e.text.to_lowercase().contains(p)))
+        .collect();
+    let shown = &entries[entries.len().saturating_sub(limit)..];
+
+    for e in shown {
+        if as_json {
+            let line = json!({
+                "id": e.id,
+                "time": e.time,
+                "voice": e.voice,
+                "status": e.status,
+                "duration_ms": e.duration_ms,
+                "text": e.text,
+                "truncated": e.truncated,
+            });
+            println!("{}", line);
+        } else {
+            println!(
+                "{:>5}  {}  {:<8} {:<11} {:>6.1}s  {}",
+                e.id,
+                utc_timestamp(e.time),
+                e.voice,
+                e.status,
+                e.duration_ms as f64 / 1000.0,
+                crate::text::excerpt(&e.text)
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Find a logged entry and return its audio: cached if possible, otherwise
+/// synthesized again (and cached on the way).
+pub fn open(id: u64, cache: Option<&Cache>) -> Result<(Entry, crate::AudioStream)> {
+    let entry = read_all()?
+        .into_iter()
+        .find(|e| e.id == id)
+        .with_context(|| format!("No history entry {}", id))?;
+
+    let request = SpeakRequest::new(&entry.text).voice(&entry.voice).build()?;
+    let client = crate::cached_client(cache);
+    let audio = match entry.truncated {
+        true => client.cached(&request).with_context(|| format!("Entry {} was too long to log in full and is no longer cached", id))?,
+        false => client.synthesize(&request)?,
+    };
+    Ok((entry, audio.into_reader()))
+}
+
+/// "YYYY-MM-DD HH:MMZ" for a Unix time, without a date library.
+pub fn utc_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    // Civil-from-days, after Howard Hinnant's date algorithms
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02} {:02}:{:02}Z", year, month, day, rem / 3600, rem % 3600 / 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_utc_timestamps() {
+        assert_eq!(utc_timestamp(0), "1970-01-01 00:00Z");
+        assert_eq!(utc_timestamp(951_782_400), "2000-02-29 00:00Z");
+        assert_eq!(utc_timestamp(1_792_052_342), "2026-10-15 08:19Z");
+    }
+}
@@ -0,0 +1,113 @@
+//! `--on-complete` / `--on-error` shell hooks.
+
+use crate::reporter;
+use std::process::Command;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Interrupted,
+    Error,
+}
+
+impl Status {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Ok => "ok",
+            Status::Interrupted => "interrupted",
+            Status::Error => "error",
+        }
+    }
+}
+
+pub struct Outcome<'a> {
+    pub status: Status,
+    pub elapsed: Duration,
+    pub output: Option<&'a str>,
+    pub voice: &'a str,
+    pub error: Option<String>,
+}
+
+/// Run `cmd` through the platform shell with the outcome in its environment.
+/// Returns true if the hook ran and exited successfully.
+pub fn run(label: &str, cmd: &str, outcome: &Outcome) -> bool {
+    let mut command = shell(cmd);
+    command
+        .env("SPEAKTURBO_STATUS", outcome.status.as_str())
+        .env("SPEAKTURBO_DURATION_MS", outcome.elapsed.as_millis().to_string())
+        .env("SPEAKTURBO_OUTPUT", outcome.output.unwrap_or(""))
+        .env("SPEAKTURBO_VOICE", outcome.voice);
+    // Not one inherited from a speakturbo that ran this one
+    match &outcome.error {
+        Some(error) => command.env("SPEAKTURBO_ERROR", error),
+        None => command.env_remove("SPEAKTURBO_ERROR"),
+    };
+
+    match command.status() {
+        Ok(status) if status.success() => true,
+        Ok(status) => {
+            match status.code() {
+                Some(code) => reporter::warning(format_args!("{} hook exited with code {}", label, code)),
+                None => reporter::warning(format_args!("{} hook was killed by a signal", label)),
+            }
+            false
+        }
+        Err(e) => {
+            reporter::warning(format_args!("{} hook failed to start: {}", label, e));
+            false
+        }
+    }
+}
+
+#[cfg(unix)]
+fn shell(cmd: &str) -> Command {
+    let mut c = Command::new("sh");
+    c.arg("-c").arg(cmd);
+    c
+}
+
+#[cfg(windows)]
+fn shell(cmd: &str) -> Command {
+    let mut c = Command::new("cmd");
+    c.arg("/C").arg(cmd);
+    c
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn outcome(status: Status, error: Option<&str>) -> Outcome<'static> {
+        Outcome { status, elapsed: Duration::from_millis(1234), output: Some("out.wav"), voice: "alba", error: error.map(str::to_string) }
+    }
+
+    /// What a hook sees of its environment, and whether it counted as a success
+    fn seen(name: &str, outcome: &Outcome, exit: u8) -> (String, bool) {
+        let file = std::env::temp_dir().join(format!("speakturbo-hook-{}-{}", name, std::process::id()));
+        let cmd = format!(
+            "printf '%s|%s|%s|%s|%s' \"$SPEAKTURBO_STATUS\" \"$SPEAKTURBO_DURATION_MS\" \"$SPEAKTURBO_OUTPUT\" \"$SPEAKTURBO_VOICE\" \"${{SPEAKTURBO_ERROR-unset}}\" > '{}'; exit {}",
+            file.display(),
+            exit
+        );
+        let ok = run("on-complete", &cmd, outcome);
+        let env = std::fs::read_to_string(&file).unwrap();
+        std::fs::remove_file(&file).unwrap();
+        (env, ok)
+    }
+
+    #[test]
+    fn passes_the_outcome_in_the_environment() {
+        assert_eq!(seen("ok", &outcome(Status::Ok, None), 0), ("ok|1234|out.wav|alba|unset".to_string(), true));
+        assert_eq!(seen("interrupted", &outcome(Status::Interrupted, None), 0).0, "interrupted|1234|out.wav|alba|unset");
+        let failed = outcome(Status::Error, Some("Daemon unreachable"));
+        assert_eq!(seen("error", &failed, 0).0, "error|1234|out.wav|alba|Daemon unreachable");
+    }
+
+    #[test]
+    fn reports_a_failing_hook() {
+        assert_eq!(seen("exit", &outcome(Status::Ok, None), 3), ("ok|1234|out.wav|alba|unset".to_string(), false));
+        assert!(!run("on-error", "exec /nonexistent/hook", &outcome(Status::Error, Some("x"))));
+        assert!(!run("on-complete", "kill -9 $$", &outcome(Status::Ok, None)), "killed by a signal");
+    }
+}
@@ -0,0 +1,351 @@
+//! Tolerant HTML parsing and speech-friendly text rendering. Pages are
+//! parsed by html5ever, as a browser would, so malformed markup never
+//! fails; from the tree this finds the main content of a page and reads it
+//! as paragraphs.
+
+use crate::normalize;
+use ego_tree::iter::Edge;
+use ego_tree::{NodeId, NodeRef};
+use scraper::{Html, Node};
+use std::collections::{HashMap, HashSet};
+
+// Never rendered: code, metadata, and widgets with no readable text
+const SKIPPED: &[&str] = &[
+    "script", "style", "head", "template", "noscript", "svg", "iframe", "object", "canvas", "select", "button",
+];
+// Page furniture dropped when extracting an article
+const BOILERPLATE: &[&str] = &["nav", "header", "footer", "aside", "form", "menu"];
+const BLOCK: &[&str] = &[
+    "address", "article", "blockquote", "caption", "dd", "details", "div", "dl", "dt", "figcaption", "figure",
+    "h1", "h2", "h3", "h4", "h5", "h6", "hr", "li", "main", "ol", "p", "pre", "section", "summary", "table",
+    "tbody", "td", "tfoot", "th", "thead", "tr", "ul", "body", "html",
+];
+
+pub struct Document {
+    html: Html,
+}
+
+/// What to leave out when rendering text.
+#[derive(Clone, Copy, Default)]
+pub struct Render {
+    /// Navigation, headers, footers, asides and forms
+    pub drop_boilerplate: bool,
+    /// Image alt text
+    pub drop_images: bool,
+    /// Footnote and endnote reference markers
+    pub drop_note_refs: bool,
+}
+
+impl Document {
+    pub fn parse(html: &str) -> Self {
+        Document { html: Html::parse_document(html) }
+    }
+
+    /// The document itself, above its root element.
+    pub fn root(&self) -> NodeId {
+        self.html.tree.root().id()
+    }
+
+    fn node(&self, id: NodeId) -> NodeRef<'_, Node> {
+        self.html.tree.get(id).expect("node of this document")
+    }
+
+    fn name(&self, id: NodeId) -> Option<&str> {
+        self.node(id).value().as_element().map(|e| e.name())
+    }
+
+    pub fn attr(&self, id: NodeId, key: &str) -> Option<&str> {
+        self.node(id).value().as_element()?.attr(key)
+    }
+
+    /// Elements named `name`, with or without a namespace prefix, in
+    /// document order. Handy for XML such as EPUB package files.
+    pub fn elements<'a>(&'a self, name: &'a str) -> impl Iterator<Item = NodeId> + 'a {
+        self.html.tree.root().descendants().map(|node| node.id()).filter(move |&id| {
+            self.name(id).is_some_and(|n| n == name || n.rsplit_once(':').is_some_and(|(_, local)| local == name))
+        })
+    }
+
+    /// The whole document as text, skipping code and metadata.
+    pub fn text(&self) -> String {
+        self.text_of(&[self.root()], Render::default())
+    }
+
+    /// The given elements as text, in order.
+    pub fn text_of(&self, ids: &[NodeId], options: Render) -> String {
+        let mut out = Renderer::default();
+        for &id in ids {
+            self.render(id, options, &mut out);
+            out.brk = 2;
+        }
+        out.out
+    }
+
+    /// Footnote markers: EPUB noteref links, and superscripts or in-page
+    /// links whose whole text is a short number or symbol like "12" or "*".
+    fn is_note_ref(&self, id: NodeId, name: &str) -> bool {
+        if self.attr(id, "epub:type").is_some_and(|t| t.split_whitespace().any(|t| t == "noteref")) {
+            return true;
+        }
+        let in_page_link = name == "a" && self.attr(id, "href").is_some_and(|h| h.contains('#'));
+        if name != "sup" && !in_page_link {
+            return false;
+        }
+        let text = self.text_of(&[id], Render::default());
+        let marker = text.trim_matches(['[', ']', '(', ')']);
+        !marker.is_empty() && marker.chars().count() <= 3 && marker.chars().all(|c| c.is_ascii_digit() || "*†‡§".contains(c))
+    }
+
+    /// Walked edge by edge rather than recursively, so deep nesting can't
+    /// overflow the stack.
+    fn render(&self, id: NodeId, options: Render, out: &mut Renderer) {
+        // The element being left out, if inside one
+        let mut skipping = None;
+        for edge in self.node(id).traverse() {
+            match edge {
+                Edge::Open(_) if skipping.is_some() => {}
+                Edge::Open(node) => match node.value() {
+                    Node::Text(text) => out.text(text),
+                    Node::Element(element) => {
+                        let name = element.name();
+                        let dropped = SKIPPED.contains(&name)
+                            || options.drop_boilerplate && BOILERPLATE.contains(&name)
+                            || options.drop_note_refs && self.is_note_ref(node.id(), name);
+                        if dropped {
+                            skipping = Some(node.id());
+                            continue;
+                        }
+                        match name {
+                            "br" => out.brk = out.brk.max(1),
+                            "img" if !options.drop_images => {
+                                if let Some(alt) = element.attr("alt") {
+                                    out.text(alt);
+                                }
+                            }
+                            "li" | "tr" | "dt" | "dd" => out.brk = out.brk.max(1),
+                            "td" | "th" => out.space = true,
+                            _ if BLOCK.contains(&name) => out.brk = 2,
+                            _ => {}
+                        }
+                    }
+                    _ => {}
+                },
+                Edge::Close(node) if skipping == Some(node.id()) => skipping = None,
+                Edge::Close(_) if skipping.is_some() => {}
+                Edge::Close(node) => {
+                    let name = node.value().as_element().map_or("", |e| e.name());
+                    if BLOCK.contains(&name) && !matches!(name, "li" | "tr" | "td" | "th" | "dt" | "dd") {
+                        out.brk = 2;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Characters of readable text under `id`, and how many are link text.
+    fn text_len(&self, id: NodeId) -> (usize, usize) {
+        let is = |node: NodeRef<Node>, names: &[&str]| node.value().as_element().is_some_and(|e| names.contains(&e.name()));
+        let mut links = self.node(id).ancestors().filter(|&a| is(a, &["a"])).count();
+        let (mut skipped, mut len, mut linked) = (0, 0, 0);
+        for edge in self.node(id).traverse() {
+            match edge {
+                Edge::Open(node) => match node.value() {
+                    Node::Text(text) if skipped == 0 => {
+                        let n: usize = text.split_whitespace().map(|w| w.chars().count() + 1).sum();
+                        len += n;
+                        if links > 0 {
+                            linked += n;
+                        }
+                    }
+                    _ => {
+                        skipped += usize::from(is(node, SKIPPED));
+                        links += usize::from(is(node, &["a"]));
+                    }
+                },
+                Edge::Close(node) => {
+                    skipped -= usize::from(is(node, SKIPPED));
+                    links -= usize::from(is(node, &["a"]));
+                }
+            }
+        }
+        (len, linked)
+    }
+
+    /// The element most likely to hold the article, by text density: each
+    /// paragraph scores for its parent and, at half weight, its
+    /// grandparent; link-heavy blocks are penalized.
+    pub fn main_content(&self) -> Option<NodeId> {
+        let mut scores: HashMap<NodeId, f64> = HashMap::new();
+        for node in self.html.tree.nodes() {
+            if !matches!(self.name(node.id()), Some("p" | "pre" | "blockquote" | "td")) {
+                continue;
+            }
+            let furniture = |a: NodeRef<Node>| a.value().as_element().is_some_and(|e| BOILERPLATE.contains(&e.name()) || SKIPPED.contains(&e.name()));
+            if node.ancestors().any(furniture) {
+                continue;
+            }
+            let (len, _) = self.text_len(node.id());
+            if len < 25 {
+                continue;
+            }
+            let commas = self.text_of(&[node.id()], Render::default()).matches(',').count();
+            let score = 1.0 + commas as f64 + (len / 100).min(3) as f64;
+            let Some(parent) = node.parent() else { continue };
+            *scores.entry(parent.id()).or_default() += score;
+            if let Some(grandparent) = parent.parent() {
+                *scores.entry(grandparent.id()).or_default() += score / 2.0;
+            }
+        }
+        // In document order, so that ties go the same way every time
+        self.html
+            .tree
+            .nodes()
+            .filter(|node| node.value().is_element())
+            .filter_map(|node| Some((node.id(), *scores.get(&node.id())?)))
+            .map(|(id, score)| {
+                let (len, linked) = self.text_len(id);
+                let density = if len == 0 { 1.0 } else { linked as f64 / len as f64 };
+                (id, score * (1.0 - density))
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .filter(|&(_, score)| score > 0.0)
+            .map(|(id, _)| id)
+    }
+
+    /// Elements matching `selector`, outermost only, in document order.
+    pub fn select(&self, selector: &Selector) -> Vec<NodeId> {
+        let matched: HashSet<NodeId> = self.html.select(&selector.0).map(|e| e.id()).collect();
+        self.html.select(&selector.0).filter(|e| !e.ancestors().any(|a| matched.contains(&a.id()))).map(|e| e.id()).collect()
+    }
+}
+
+/// The text of an HTML page for speaking: the elements matching
+/// `selector`, or else the whole document. None if nothing matches.
+pub fn to_speech(html: &str, selector: Option<&Selector>) -> Option<String> {
+    let doc = Document::parse(html);
+    match selector {
+        Some(selector) => {
+            let matched = doc.select(selector);
+            (!matched.is_empty()).then(|| doc.text_of(&matched, Render::default()))
+        }
+        None => Some(doc.text()),
+    }
+}
+
+/// Collapses whitespace and turns block boundaries into line and paragraph
+/// breaks.
+#[derive(Default)]
+struct Renderer {
+    out: String,
+    space: bool,
+    /// 1 for a line break before the next text, 2 for a paragraph break
+    brk: u8,
+}
+
+impl Renderer {
+    fn text(&mut self, text: &str) {
+        for c in text.chars().filter(|c| !normalize::INVISIBLE.contains(c)) {
+            if c.is_whitespace() {
+                self.space = true;
+                continue;
+            }
+            if !self.out.is_empty() {
+                match self.brk {
+                    0 if self.space => self.out.push(' '),
+                    0 => {}
+                    1 => self.out.push('\n'),
+                    _ => self.out.push_str("\n\n"),
+                }
+            }
+            self.brk = 0;
+            self.space = false;
+            self.out.push(c);
+        }
+    }
+}
+
+/// A CSS selector, as `--selector` takes it.
+#[derive(Clone, Debug)]
+pub struct Selector(scraper::Selector);
+
+impl Selector {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        scraper::Selector::parse(s).map(Selector).map_err(|e| format!("bad selector {:?}: {}", s, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_blocks_as_paragraphs() {
+        let doc = Document::parse(
+            "<html><head><title>T</title><style>p{}</style></head><body>\
+             <h1>Title</h1><p>First   line<br>second line.<p>Next &amp; last &#x2019;para&#8217;\
+             <ul><li>one<li>two</ul><img alt='A chart'><script>var x = 1 < 2;</script></body>",
+        );
+        assert_eq!(doc.text(), "Title\n\nFirst line\nsecond line.\n\nNext & last ’para’\n\none\ntwo\n\nA chart");
+    }
+
+    #[test]
+    fn survives_malformed_markup() {
+        let doc = Document::parse("<div><p>a < b</span></div></div><p class=\"x>unclosed");
+        assert_eq!(doc.text(), "a < b");
+    }
+
+    #[test]
+    fn survives_deep_nesting() {
+        let html = format!("{}deep{}", "<div><b>".repeat(5_000), "</span>".repeat(10));
+        assert_eq!(to_speech(&html, None).as_deref(), Some("deep"));
+    }
+
+    #[test]
+    fn decodes_entities() {
+        let golden = [
+            ("Fish &amp; chips", "Fish & chips"),
+            ("it&#x2019;s &#8220;quoted&#8221;", "it’s “quoted”"),
+            ("&lt;b&gt; is bold", "<b> is bold"),
+            ("caf&eacute; &euro;5 &copy;", "café €5 ©"),
+            ("a&nbsp;b&thinsp;c", "a b c"),
+            ("&#0; &#xD800; &#99999999;", "\u{fffd} \u{fffd} \u{fffd}"),
+            ("&unknown; &amp &# & x", "&unknown; & &# & x"),
+        ];
+        for (html, text) in golden {
+            assert_eq!(to_speech(html, None).as_deref(), Some(text), "{}", html);
+        }
+    }
+
+    #[test]
+    fn normalizes_whitespace() {
+        let golden = [
+            ("  lots \n\t of   space  ", "lots of space"),
+            ("<p>one</p>\n\n\n<p>two</p>", "one\n\ntwo"),
+            ("<span>in</span><b>line</b> <i>words</i>", "inline words"),
+            ("line<br><br>break", "line\nbreak"),
+            ("soft\u{ad}hy\u{200b}phen", "softhyphen"),
+            ("<div><div>nested</div></div><div>blocks</div>", "nested\n\nblocks"),
+            ("<table><tr><th>a</th><th>b</th><tr><td>1<td>2</table>", "a b\n1 2"),
+        ];
+        for (html, text) in golden {
+            assert_eq!(to_speech(html, None).as_deref(), Some(text), "{:?}", html);
+        }
+    }
+
+    #[test]
+    fn finds_the_article_among_page_furniture() {
+        let filler = "Words, words, and more words that make a paragraph long enough to count. ";
+        let html = format!(
+            "<body><nav><a href=/>Home</a> <a href=/about>About us and everything else we do</a></nav>\
+             <div id=main><p>{0}</p><p>{0}</p></div><footer><p>{0}</p></footer></body>",
+            filler
+        );
+        let doc = Document::parse(&html);
+        let main = doc.main_content().unwrap();
+        assert_eq!(doc.attr(main, "id"), Some("main"));
+
+        let selected = doc.select(&Selector::parse("footer p, nav").unwrap());
+        assert_eq!(selected.len(), 2);
+        assert!(doc.text_of(&selected, Render::default()).starts_with("Home About us"));
+    }
+}
@@ -0,0 +1,208 @@
+//! Ctrl+C handling: cancel the process's [`CancellationToken`] so that
+//! syntheses and playback stop cleanly and completion hooks still run,
+//! instead of the process dying mid-write. A signal handler can't take the
+//! token's lock, so it writes to a pipe, and a thread of ours cancels. On
+//! Windows the console's handler runs on a thread of its own and cancels
+//! directly; closing the console window is a Ctrl+C with a deadline.
+
+use speakturbo_client::CancellationToken;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, Ordering};
+use std::sync::OnceLock;
+
+/// Signals seen, so that a second one can exit at once
+static SIGNALS: AtomicU32 = AtomicU32::new(0);
+
+/// The write end of the pipe to the thread that cancels the token
+static WAKE: AtomicI32 = AtomicI32::new(-1);
+
+/// Whether a first Ctrl+C leaves the syntheses under way to finish
+static LET_FINISH: AtomicBool = AtomicBool::new(false);
+
+/// The process `speakturbo exec` runs, which signals are passed on to
+static CHILD: AtomicI32 = AtomicI32::new(0);
+
+/// Set when an urgent invocation has cut this one off
+static PREEMPTED: AtomicBool = AtomicBool::new(false);
+
+/// What an urgent --priority invocation sends the one it cuts off
+#[cfg(unix)]
+pub const PREEMPT: libc::c_int = libc::SIGUSR1;
+
+#[cfg(unix)]
+extern "C" fn on_signal(signal: libc::c_int, info: *mut libc::siginfo_t, _: *mut libc::c_void) {
+    let child = CHILD.load(Ordering::SeqCst);
+    if child > 0 && !from_terminal(info) {
+        unsafe { libc::kill(child, signal) };
+    }
+    // A second Ctrl+C while we're winding down exits immediately
+    if SIGNALS.fetch_add(1, Ordering::SeqCst) > 0 {
+        unsafe { libc::_exit(crate::exit::Kind::Interrupted.code()) };
+    }
+    let fd = WAKE.load(Ordering::SeqCst);
+    if fd >= 0 {
+        unsafe { libc::write(fd, [1u8].as_ptr().cast(), 1) };
+    }
+}
+
+/// Not a Ctrl+C: it doesn't count towards a second one
+#[cfg(unix)]
+extern "C" fn on_preempt(_: libc::c_int) {
+    PREEMPTED.store(true, Ordering::SeqCst);
+    let fd = WAKE.load(Ordering::SeqCst);
+    if fd >= 0 {
+        unsafe { libc::write(fd, [1u8].as_ptr().cast(), 1) };
+    }
+}
+
+/// Whether the terminal sent the signal, as it does Ctrl+C to every
+/// process in the foreground, a child included.
+#[cfg(target_os = "linux")]
+fn from_terminal(info: *mut libc::siginfo_t) -> bool {
+    !info.is_null() && unsafe { (*info).si_code } == libc::SI_KERNEL
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn from_terminal(_: *mut libc::siginfo_t) -> bool {
+    false
+}
+
+pub fn install() {
+    #[cfg(unix)]
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        handle(signal);
+    }
+    #[cfg(windows)]
+    console::handle();
+}
+
+/// What Ctrl+C cancels: the whole process's work.
+pub fn token() -> &'static CancellationToken {
+    static TOKEN: OnceLock<CancellationToken> = OnceLock::new();
+    TOKEN.get_or_init(CancellationToken::new)
+}
+
+/// The token a synthesis is to stop at: [`token`], unless
+/// [`let_work_finish`] has been called, when it's one nothing cancels.
+pub fn for_work() -> CancellationToken {
+    match LET_FINISH.load(Ordering::SeqCst) {
+        true => CancellationToken::new(),
+        false => token().clone(),
+    }
+}
+
+/// Start the thread that cancels the token when the handler writes to
+/// its pipe, once.
+#[cfg(unix)]
+fn listen() {
+    static LISTENING: OnceLock<()> = OnceLock::new();
+    LISTENING.get_or_init(|| {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return;
+        }
+        let spawned = std::thread::Builder::new().name("speakturbo-signals".into()).spawn(move || {
+            let mut byte = 0u8;
+            while unsafe { libc::read(fds[0], (&mut byte as *mut u8).cast(), 1) } < 0
+                && std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted
+            {}
+            token().cancel();
+        });
+        if spawned.is_ok() {
+            WAKE.store(fds[1], Ordering::SeqCst);
+        }
+    });
+}
+
+#[cfg(unix)]
+fn handle(signal: libc::c_int) {
+    listen();
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        let handler = on_signal as extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void);
+        action.sa_sigaction = handler as libc::sighandler_t;
+        action.sa_flags = libc::SA_SIGINFO;
+        libc::sigaction(signal, &action, std::ptr::null_mut());
+    }
+}
+
+#[cfg(windows)]
+mod console {
+    use super::{token, SIGNALS};
+    use std::sync::atomic::Ordering;
+    use std::sync::OnceLock;
+    use std::time::Duration;
+
+    const CTRL_C_EVENT: u32 = 0;
+    const CTRL_BREAK_EVENT: u32 = 1;
+    const CTRL_CLOSE_EVENT: u32 = 2;
+
+    /// Windows ends the process this long after a close at the latest
+    const CLOSE_GRACE: Duration = Duration::from_secs(4);
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetConsoleCtrlHandler(handler: Option<unsafe extern "system" fn(u32) -> i32>, add: i32) -> i32;
+    }
+
+    unsafe extern "system" fn on_event(event: u32) -> i32 {
+        if !matches!(event, CTRL_C_EVENT | CTRL_BREAK_EVENT | CTRL_CLOSE_EVENT) {
+            return 0;
+        }
+        // A second Ctrl+C while we're winding down exits immediately,
+        // putting the console back on the way out
+        if SIGNALS.fetch_add(1, Ordering::SeqCst) > 0 {
+            std::process::exit(crate::exit::Kind::Interrupted.code());
+        }
+        token().cancel();
+        if event == CTRL_CLOSE_EVENT {
+            // Returning lets Windows end the process: wind down first
+            std::thread::sleep(CLOSE_GRACE);
+        }
+        1
+    }
+
+    pub fn handle() {
+        static HANDLING: OnceLock<()> = OnceLock::new();
+        HANDLING.get_or_init(|| {
+            unsafe { SetConsoleCtrlHandler(Some(on_event), 1) };
+        });
+    }
+}
+
+/// Pass SIGINT, SIGTERM and SIGHUP on to `pid` from now on, besides
+/// stopping playback as [`install`] does.
+pub fn forward_to(pid: u32) {
+    CHILD.store(pid as i32, Ordering::SeqCst);
+    install();
+    #[cfg(unix)]
+    handle(libc::SIGHUP);
+}
+
+/// From now on a first Ctrl+C only stops new work from starting: what's
+/// under way finishes, and a second one exits at once.
+pub fn let_work_finish() {
+    LET_FINISH.store(true, Ordering::SeqCst);
+    install();
+}
+
+/// Let an urgent invocation cut this one's playback off from now on, as
+/// Ctrl+C would, with [`preempted`] telling the two apart.
+pub fn preemptible() {
+    #[cfg(unix)]
+    {
+        listen();
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = on_preempt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            libc::sigaction(PREEMPT, &action, std::ptr::null_mut());
+        }
+    }
+}
+
+pub fn preempted() -> bool {
+    PREEMPTED.load(Ordering::SeqCst)
+}
+
+pub fn requested() -> bool {
+    token().is_cancelled()
+}
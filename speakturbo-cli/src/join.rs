@@ -0,0 +1,503 @@
+//! One text synthesized in parts, read back as a single WAV stream: each
+//! part's audio follows the last with no gap beyond the silence asked for,
+//! so playback and saving treat it like any other daemon response.
+
+use crate::breaks::{self, Part};
+use crate::chime::FORMAT;
+use crate::prefetch::{Open, Prefetch};
+use crate::reporter;
+use crate::sentence;
+use crate::text;
+use crate::wav;
+use crate::{AudioStream, Events, SpeakEvent};
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, PartialEq)]
+pub enum Piece {
+    /// A chunk to synthesize, and which sentences of the whole text it holds
+    Text { text: String, sentences: RangeInclusive<usize> },
+    Silence(Duration),
+}
+
+/// `text` as it will be synthesized: split at its pauses with
+/// --emulate-breaks, and into chunks of at most `chunk_chars` characters.
+pub fn plan(text: &str, emulate_breaks: bool, chunk_chars: usize) -> Result<Vec<Piece>> {
+    let parts = match emulate_breaks {
+        true => breaks::split(text)?,
+        false => vec![Part::Text(text.to_string())],
+    };
+    let mut pieces = Vec::new();
+    let mut next_sentence = 1;
+    for part in parts {
+        match part {
+            Part::Pause(duration) => pieces.push(Piece::Silence(duration)),
+            Part::Text(text) => {
+                for (text, sentences) in sentence::chunks(&text, chunk_chars, next_sentence) {
+                    next_sentence = sentences.end() + 1;
+                    pieces.push(Piece::Text { text, sentences });
+                }
+            }
+        }
+    }
+    Ok(pieces)
+}
+
+/// `pieces` with `gap` of silence between each two parts that would
+/// otherwise follow one another directly.
+pub fn spaced(pieces: Vec<Piece>, gap: Duration) -> Vec<Piece> {
+    let mut spaced = Vec::with_capacity(pieces.len());
+    for piece in pieces {
+        if !gap.is_zero() && matches!((spaced.last(), &piece), (Some(Piece::Text { .. }), Piece::Text { .. })) {
+            spaced.push(Piece::Silence(gap));
+        }
+        spaced.push(piece);
+    }
+    spaced
+}
+
+/// How a failed part is reported: "Lost sentences 4–6 ("It was…")".
+fn lost(text: &str, sentences: &RangeInclusive<usize>) -> String {
+    let which = match sentences.start() == sentences.end() {
+        true => format!("sentence {}", sentences.start()),
+        false => format!("sentences {}–{}", sentences.start(), sentences.end()),
+    };
+    format!("Lost {} (\"{}\")", which, text::excerpt(text))
+}
+
+/// Each part fades in and out over this long, so cutting to silence
+/// doesn't click
+const FADE: Duration = Duration::from_millis(5);
+
+const FRAME_BYTES: usize = FORMAT.channels as usize * FORMAT.bits_per_sample as usize / 8;
+
+fn bytes_for(duration: Duration) -> usize {
+    (duration.as_secs_f64() * FORMAT.sample_rate as f64) as usize * FRAME_BYTES
+}
+
+/// Where a chunk lies in the joined audio, in samples after the header,
+/// for --bookmark to tell which sentence was playing
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub sentences: RangeInclusive<usize>,
+    pub start: u64,
+    /// Known once the chunk has all been read
+    pub end: Option<u64>,
+}
+
+/// The chunks' spans as they're read, shared with whoever asked for them
+pub type Spans = Arc<Mutex<Vec<Span>>>;
+
+pub struct Options {
+    /// Chunks synthesizing at once
+    pub jobs: usize,
+    /// Finished chunks held ahead of the one playing, at most
+    pub max_ahead: Duration,
+    /// Print each chunk's synthesis time and the waits at the end
+    pub stats: bool,
+    /// How long consecutive chunks overlap (zero: they don't)
+    pub crossfade: Duration,
+    /// Told as each chunk starts streaming
+    pub events: Events,
+    /// Where to record each chunk's span, if anywhere
+    pub spans: Option<Spans>,
+}
+
+/// The parts' audio in order. The first chunk streams as it's
+/// synthesized; the others are synthesized ahead by a [`Prefetch`] pool.
+pub struct Joined {
+    pieces: VecDeque<Piece>,
+    prefetch: Option<Prefetch>,
+    /// The next chunk to take from `prefetch`
+    next_chunk: usize,
+    current: Option<AudioStream>,
+    /// What's lost if the current part fails
+    current_lost: String,
+    /// Set while the first chunk streams from the daemon
+    first_started: Option<Instant>,
+    /// Bytes of the current part passed on so far
+    emitted: usize,
+    /// The newest bytes of the current part, held back until it's known
+    /// whether they're the last (and need fading out)
+    tail: Vec<u8>,
+    /// Whether the current part's start still needs fading in
+    fade_in: bool,
+    /// The last part's end, to crossfade into the current part's start
+    overlap: Vec<u8>,
+    crossfade_bytes: usize,
+    silence_left: usize,
+    pending: VecDeque<u8>,
+    /// Audio bytes queued so far, the header aside
+    written: u64,
+    stats: Option<Stats>,
+    events: Events,
+    spans: Option<Spans>,
+}
+
+#[derive(Default)]
+struct Stats {
+    jobs: usize,
+    took: Vec<Duration>,
+    stalled: Duration,
+}
+
+impl Joined {
+    /// Starts the first chunk's synthesis, so a daemon that's down fails
+    /// here rather than as silence, and the pool on the rest.
+    pub fn new(pieces: Vec<Piece>, open: Open, options: Options) -> Result<Self> {
+        let mut pieces: VecDeque<Piece> = pieces.into();
+        let mut silence_left = 0;
+        let (text, sentences) = loop {
+            match pieces.pop_front() {
+                Some(Piece::Silence(duration)) => silence_left += bytes_for(duration),
+                Some(Piece::Text { text, sentences }) => break (text, sentences),
+                None => bail!("No text between the pauses"),
+            }
+        };
+        let current_lost = lost(&text, &sentences);
+        let mut first = open(&text)?;
+        let (format, _) = wav::read_header(&mut first)?;
+        if format != FORMAT {
+            bail!("Unexpected audio format from the daemon: {:?}", format);
+        }
+
+        let rest: Vec<String> = pieces
+            .iter()
+            .filter_map(|piece| match piece {
+                Piece::Text { text, .. } => Some(text.clone()),
+                Piece::Silence(_) => None,
+            })
+            .collect();
+        let prefetch = (!rest.is_empty())
+            .then(|| Prefetch::start(rest, open, options.jobs.max(1), bytes_for(options.max_ahead)));
+        let mut pending = VecDeque::new();
+        let mut header = Vec::new();
+        wav::write_header(&mut header, FORMAT, wav::STREAMING_DATA_LEN)?;
+        pending.extend(header);
+        options.events.emit(SpeakEvent::ChunkStarted { index: 0 });
+        let joined = Joined {
+            pieces,
+            prefetch,
+            next_chunk: 0,
+            current: Some(first),
+            current_lost,
+            first_started: Some(Instant::now()),
+            emitted: 0,
+            tail: Vec::new(),
+            fade_in: true,
+            overlap: Vec::new(),
+            crossfade_bytes: bytes_for(options.crossfade),
+            silence_left,
+            pending,
+            written: 0,
+            stats: options.stats.then(|| Stats { jobs: options.jobs, ..Stats::default() }),
+            events: options.events,
+            spans: options.spans,
+        };
+        joined.span_started(text, sentences);
+        Ok(joined)
+    }
+
+    /// A chunk starts after the audio queued so far and any silence still
+    /// to come before it.
+    fn span_started(&self, text: String, sentences: RangeInclusive<usize>) {
+        if let Some(spans) = &self.spans {
+            let start = (self.written + self.silence_left as u64) / FRAME_BYTES as u64;
+            spans.lock().unwrap().push(Span { text, sentences, start, end: None });
+        }
+    }
+
+    /// The current chunk has all been queued.
+    fn span_ended(&self) {
+        if let Some(spans) = &self.spans {
+            if let Some(span) = spans.lock().unwrap().last_mut() {
+                span.end = Some(self.written / FRAME_BYTES as u64);
+            }
+        }
+    }
+
+    /// The first chunk is in; from now on the pool may run all `--jobs`.
+    fn first_done(&mut self) {
+        if let Some(started) = self.first_started.take() {
+            if let Some(stats) = &mut self.stats {
+                stats.took.push(started.elapsed());
+            }
+            if let Some(prefetch) = &self.prefetch {
+                prefetch.streamed();
+            }
+        }
+    }
+
+    fn take_next(&mut self, text: &str, sentences: &RangeInclusive<usize>) -> Result<()> {
+        self.current_lost = lost(text, sentences);
+        let prefetch = self.prefetch.as_ref().expect("chunks after the first are prefetched");
+        let (done, waited) = prefetch.take(self.next_chunk);
+        self.next_chunk += 1;
+        // The first chunk isn't prefetched
+        self.events.emit(SpeakEvent::ChunkStarted { index: self.next_chunk });
+        self.span_started(text.to_string(), sentences.clone());
+        if let Some(stats) = &mut self.stats {
+            stats.took.push(done.took);
+            stats.stalled += waited;
+        }
+        let samples = done.audio.map_err(|e| anyhow!("{}: {}", self.current_lost, e))?;
+        self.current = Some(Box::new(io::Cursor::new(samples)));
+        self.emitted = 0;
+        self.fade_in = true;
+        Ok(())
+    }
+
+    fn report(&mut self) {
+        let Some(stats) = self.stats.take() else { return };
+        if stats.took.len() < 2 {
+            return;
+        }
+        reporter::info(format_args!("Chunks: {}, {} at a time", stats.took.len(), stats.jobs));
+        for (i, took) in stats.took.iter().enumerate() {
+            reporter::info(format_args!("  chunk {}: synthesized in {}ms", i + 1, took.as_millis()));
+        }
+        reporter::info(format_args!("Stalled: {}ms waiting for chunks", stats.stalled.as_millis()));
+    }
+
+    /// Queue more output; false at the end of the last part.
+    fn fill(&mut self) -> Result<bool> {
+        if self.silence_left > 0 {
+            let n = self.silence_left.min(8192);
+            self.pending.extend(std::iter::repeat_n(0, n));
+            self.silence_left -= n;
+            self.written += n as u64;
+            return Ok(true);
+        }
+        let fade_bytes = bytes_for(FADE);
+        if let Some(audio) = &mut self.current {
+            let mut chunk = [0u8; 4096];
+            let n = audio.read(&mut chunk).with_context(|| self.current_lost.clone())?;
+            self.tail.extend_from_slice(&chunk[..n]);
+            if !self.overlap.is_empty() {
+                // Wait for as much of this part as the last one left over
+                if self.tail.len() < self.overlap.len() && n > 0 {
+                    return Ok(true);
+                }
+                if self.tail.len() < self.overlap.len() {
+                    self.tail.resize(self.overlap.len(), 0);
+                }
+                crossfade(&std::mem::take(&mut self.overlap), &mut self.tail);
+                self.fade_in = false;
+            }
+            // Chunks running straight into another overlap it; others fade out
+            let overlap = match self.pieces.front() {
+                Some(Piece::Text { .. }) => self.crossfade_bytes,
+                _ => 0,
+            };
+            let ready = match n {
+                // The end: the tail is the part's last bytes
+                0 => {
+                    self.current = None;
+                    self.first_done();
+                    self.tail.truncate(self.tail.len() / FRAME_BYTES * FRAME_BYTES);
+                    let len = self.tail.len();
+                    if overlap > 0 {
+                        self.overlap = self.tail.split_off(len - overlap.min(len));
+                    } else {
+                        for (i, sample) in self.tail.chunks_exact_mut(2).enumerate().rev().take(fade_bytes / 2) {
+                            scale(sample, (len / 2 - 1 - i) as f32 / (fade_bytes / 2) as f32);
+                        }
+                    }
+                    self.tail.len()
+                }
+                _ => self.tail.len().saturating_sub(fade_bytes.max(overlap)) / FRAME_BYTES * FRAME_BYTES,
+            };
+            if self.fade_in {
+                for (i, sample) in self.tail[..ready].chunks_exact_mut(2).enumerate() {
+                    let at = self.emitted / 2 + i;
+                    if at >= fade_bytes / 2 {
+                        break;
+                    }
+                    scale(sample, at as f32 / (fade_bytes / 2) as f32);
+                }
+            }
+            self.emitted += ready;
+            self.written += ready as u64;
+            self.pending.extend(self.tail.drain(..ready));
+            if n == 0 {
+                self.span_ended();
+            }
+            return Ok(true);
+        }
+        match self.pieces.pop_front() {
+            Some(Piece::Silence(duration)) => self.silence_left = bytes_for(duration),
+            Some(Piece::Text { text, sentences }) => self.take_next(&text, &sentences)?,
+            None => {
+                self.report();
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+fn scale(sample: &mut [u8], factor: f32) {
+    let value = i16::from_le_bytes([sample[0], sample[1]]) as f32 * factor;
+    sample.copy_from_slice(&(value as i16).to_le_bytes());
+}
+
+/// Mixes `from`, the end of one part, into the start of `into` with an
+/// equal-power crossfade: the gains' squares always add up to one, so
+/// the loudness holds steady across the seam.
+fn crossfade(from: &[u8], into: &mut [u8]) {
+    let samples = from.len() / 2;
+    for (i, (a, b)) in from.chunks_exact(2).zip(into.chunks_exact_mut(2)).enumerate() {
+        let t = (i as f32 + 0.5) / samples as f32 * std::f32::consts::FRAC_PI_2;
+        let mixed = i16::from_le_bytes([a[0], a[1]]) as f32 * t.cos() + i16::from_le_bytes([b[0], b[1]]) as f32 * t.sin();
+        b.copy_from_slice(&(mixed.clamp(i16::MIN as f32, i16::MAX as f32) as i16).to_le_bytes());
+    }
+}
+
+impl Read for Joined {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pending.is_empty() {
+            match self.fill() {
+                Ok(true) => {}
+                Ok(false) => return Ok(0),
+                Err(e) => return Err(io::Error::other(format!("{:#}", e))),
+            }
+        }
+        let n = buf.len().min(self.pending.len());
+        for (slot, byte) in buf.iter_mut().zip(self.pending.drain(..n)) {
+            *slot = byte;
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn options() -> Options {
+        Options { jobs: 2, max_ahead: Duration::from_secs(30), stats: false, crossfade: Duration::ZERO, events: Events::none(), spans: None }
+    }
+
+    fn tone(hz: f32, samples: usize) -> AudioStream {
+        let mut bytes = Vec::new();
+        wav::write_header(&mut bytes, FORMAT, (samples * 2) as u32).unwrap();
+        for i in 0..samples {
+            let value = 8000.0 * (i as f32 * hz * std::f32::consts::TAU / FORMAT.sample_rate as f32).sin();
+            bytes.extend((value as i16).to_le_bytes());
+        }
+        Box::new(io::Cursor::new(bytes))
+    }
+
+    #[test]
+    fn crossfades_without_a_dip_or_bump() {
+        let pieces = vec![
+            Piece::Text { text: "400".into(), sentences: 1..=1 },
+            Piece::Text { text: "1000".into(), sentences: 2..=2 },
+        ];
+        let open: Open = Arc::new(|text: &str| Ok(tone(text.parse().unwrap(), 12000)));
+        let options = Options { crossfade: Duration::from_millis(50), ..options() };
+        let mut out = Vec::new();
+        Joined::new(pieces, open, options).unwrap().read_to_end(&mut out).unwrap();
+        let samples: Vec<f32> = out[44..].chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]]) as f32).collect();
+        // The two parts overlap by 50ms (1200 samples)
+        assert_eq!(samples.len(), 12000 + 12000 - 1200);
+
+        // 10ms windows hold whole cycles of both tones
+        let rms = |window: &[f32]| (window.iter().map(|s| s * s).sum::<f32>() / window.len() as f32).sqrt();
+        let steady = rms(&samples[4800..5040]);
+        for start in (9600..13200).step_by(240) {
+            let db = 20.0 * (rms(&samples[start..start + 240]) / steady).log10();
+            assert!(db.abs() < 0.5, "{:.2} dB at sample {}", db, start);
+        }
+    }
+
+    /// A daemon response of `samples` samples at full scale.
+    fn response(samples: usize) -> AudioStream {
+        let mut bytes = Vec::new();
+        wav::write_header(&mut bytes, FORMAT, (samples * 2) as u32).unwrap();
+        bytes.extend((0..samples).flat_map(|_| 10000i16.to_le_bytes()));
+        Box::new(io::Cursor::new(bytes))
+    }
+
+    #[test]
+    fn spaces_parts_that_run_together() {
+        let text = |n: usize| Piece::Text { text: n.to_string(), sentences: n..=n };
+        let pause = || Piece::Silence(Duration::from_millis(500));
+        let gap = Duration::from_millis(300);
+        let spaced = spaced(vec![text(1), text(2), pause(), text(3)], gap);
+        assert_eq!(spaced, [text(1), Piece::Silence(gap), text(2), pause(), text(3)]);
+        assert_eq!(super::spaced(vec![text(1), text(2)], Duration::ZERO), [text(1), text(2)]);
+    }
+
+    #[test]
+    fn joins_parts_with_silence_between() {
+        let pieces = vec![
+            Piece::Silence(Duration::from_millis(10)),
+            Piece::Text { text: "one".into(), sentences: 1..=1 },
+            Piece::Silence(Duration::from_millis(100)),
+            Piece::Text { text: "two".into(), sentences: 2..=2 },
+        ];
+        let asked = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&asked);
+        let open: Open = Arc::new(move |text: &str| {
+            log.lock().unwrap().push(text.to_string());
+            Ok(response(2400))
+        });
+        let mut joined = Joined::new(pieces, open, options()).unwrap();
+        let mut out = Vec::new();
+        joined.read_to_end(&mut out).unwrap();
+        assert_eq!(*asked.lock().unwrap(), ["one", "two"]);
+
+        let (format, _) = wav::read_header(&mut &out[..]).unwrap();
+        assert_eq!(format, FORMAT);
+        let samples: Vec<i16> = out[44..].chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect();
+        assert_eq!(samples.len(), 240 + 2400 + 2400 + 2400);
+        assert!(samples[..240].iter().all(|&s| s == 0));
+        // Faded in from silence, full in the middle, faded out to silence
+        assert_eq!(samples[240], 0);
+        assert!(samples[240 + 60] > 4000 && samples[240 + 60] < 6000);
+        assert_eq!(samples[240 + 1200], 10000);
+        assert!(samples[240 + 2399] < 100);
+        assert!(samples[2640..5040].iter().all(|&s| s == 0));
+        assert_eq!(samples[5040], 0);
+        assert_eq!(samples[5040 + 1200], 10000);
+    }
+
+    #[test]
+    fn records_where_each_chunk_lies() {
+        let pieces = vec![
+            Piece::Silence(Duration::from_millis(10)),
+            Piece::Text { text: "One. Two.".into(), sentences: 1..=2 },
+            Piece::Silence(Duration::from_millis(100)),
+            Piece::Text { text: "Three.".into(), sentences: 3..=3 },
+        ];
+        let open: Open = Arc::new(|_: &str| Ok(response(2400)));
+        let spans = Spans::default();
+        let options = Options { spans: Some(Arc::clone(&spans)), ..options() };
+        let mut out = Vec::new();
+        Joined::new(pieces, open, options).unwrap().read_to_end(&mut out).unwrap();
+        let span = |text: &str, sentences, start, end| Span { text: text.into(), sentences, start, end: Some(end) };
+        assert_eq!(*spans.lock().unwrap(), [span("One. Two.", 1..=2, 240, 2640), span("Three.", 3..=3, 5040, 7440)]);
+    }
+
+    #[test]
+    fn names_the_part_that_failed() {
+        let pieces = vec![
+            Piece::Text { text: "fine".into(), sentences: 1..=2 },
+            Piece::Text { text: "Broken. Part.".into(), sentences: 3..=4 },
+        ];
+        let open: Open = Arc::new(|text: &str| match text {
+            "fine" => Ok(response(100)),
+            _ => bail!("Daemon not running?"),
+        });
+        let mut joined = Joined::new(pieces, open, options()).unwrap();
+        let error = joined.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(error.to_string(), "Lost sentences 3–4 (\"Broken. Part.\"): Daemon not running?");
+    }
+}
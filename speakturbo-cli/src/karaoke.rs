@@ -0,0 +1,225 @@
+//! `--karaoke`: the text on the terminal as it's spoken, the sentence being
+//! heard highlighted. The daemon sends no word timings, so each sentence's
+//! start is approximated from its share of the text's characters, and the
+//! highlight moves a sentence at a time. Where it is follows the samples
+//! playback has taken, so it catches up by itself after any stall.
+
+use crate::sentence;
+use std::io::{IsTerminal, Write};
+use std::ops::Range;
+
+const HIGHLIGHT: &str = "\x1b[7m";
+const PLAIN: &str = "\x1b[27m";
+
+pub struct Karaoke {
+    text: String,
+    /// Each sentence's bytes in `text`, and where it starts as a share of
+    /// the whole
+    spans: Vec<(Range<usize>, f64)>,
+    /// `text` wrapped to the terminal's width
+    lines: Vec<Range<usize>>,
+    /// The most lines shown at once; longer texts scroll
+    rows: usize,
+    current: Option<usize>,
+    /// Lines on the screen, once drawn
+    drawn: usize,
+    finished: bool,
+}
+
+impl Karaoke {
+    /// None unless stderr is a terminal that takes escape sequences.
+    pub fn new(text: &str) -> Option<Karaoke> {
+        if !std::io::stderr().is_terminal() || !crate::console::escapes() {
+            return None;
+        }
+        let (columns, rows) = terminal_size().unwrap_or((80, 24));
+        Some(Karaoke::with_size(text, columns, rows))
+    }
+
+    fn with_size(text: &str, columns: usize, rows: usize) -> Karaoke {
+        Karaoke {
+            text: text.to_string(),
+            spans: spans(text),
+            lines: wrap(text, columns.max(1)),
+            // One row is left for the line the cursor ends on
+            rows: rows.saturating_sub(1).max(1),
+            current: None,
+            drawn: 0,
+            finished: false,
+        }
+    }
+
+    /// Playback has taken `played` samples of about `total`.
+    pub fn update(&mut self, played: u64, total: Option<u64>) {
+        let current = self.heard(played, total);
+        if current != self.current || self.drawn == 0 {
+            self.current = current;
+            let highlight = current.map(|i| self.spans[i].0.clone());
+            self.draw(highlight);
+        }
+    }
+
+    /// The sentence `played` samples of `total` into the text
+    fn heard(&self, played: u64, total: Option<u64>) -> Option<usize> {
+        let at = match total {
+            Some(total) if total > 0 => played as f64 / total as f64,
+            _ => 0.0,
+        };
+        self.spans.iter().rposition(|(_, start)| *start <= at)
+    }
+
+    /// The lines on the screen with `highlight` marked, scrolled so that
+    /// its start shows.
+    fn frame(&self, highlight: Option<Range<usize>>) -> Vec<String> {
+        let shown = self.lines.len().min(self.rows);
+        let first = highlight.as_ref().and_then(|h| self.lines.iter().position(|line| line.end > h.start)).unwrap_or(0);
+        // A line of what came before, for context
+        let top = first.saturating_sub(1).min(self.lines.len() - shown);
+        self.lines[top..top + shown]
+            .iter()
+            .map(|line| {
+                let text = &self.text[line.clone()];
+                let Some(h) = highlight.as_ref() else { return text.to_string() };
+                let (from, to) = (h.start.clamp(line.start, line.end), h.end.clamp(line.start, line.end));
+                if from == to {
+                    return text.to_string();
+                }
+                let (from, to) = (from - line.start, to - line.start);
+                format!("{}{}{}{}{}", &text[..from], HIGHLIGHT, &text[from..to], PLAIN, &text[to..])
+            })
+            .collect()
+    }
+
+    fn draw(&mut self, highlight: Option<Range<usize>>) {
+        let frame = self.frame(highlight);
+        let mut err = std::io::stderr().lock();
+        // Back to the first line drawn last time
+        let _ = match self.drawn {
+            0 | 1 => write!(err, "\r"),
+            n => write!(err, "\r\x1b[{}A", n - 1),
+        };
+        let lines: Vec<String> = frame.iter().map(|line| format!("\x1b[2K{}", line)).collect();
+        let _ = write!(err, "{}", lines.join("\r\n"));
+        let _ = err.flush();
+        self.drawn = frame.len();
+    }
+
+    /// Leave the text without its highlight, and the cursor after it.
+    pub fn finish(&mut self) {
+        if self.finished || self.drawn == 0 {
+            return;
+        }
+        self.finished = true;
+        self.draw(None);
+        eprintln!();
+    }
+}
+
+impl Drop for Karaoke {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/// The sentences of `text` as byte ranges, each with the share of the
+/// characters before it.
+fn spans(text: &str) -> Vec<(Range<usize>, f64)> {
+    let total = text.chars().count().max(1) as f64;
+    sentence::split(text)
+        .into_iter()
+        .map(|s| {
+            let start = s.as_ptr() as usize - text.as_ptr() as usize;
+            (start..start + s.len(), text[..start].chars().count() as f64 / total)
+        })
+        .collect()
+}
+
+/// `text`'s lines at most `columns` characters wide, broken between words
+/// and at its own line breaks, as byte ranges without the spaces between.
+fn wrap(text: &str, columns: usize) -> Vec<Range<usize>> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    for paragraph in text.split('\n') {
+        let mut line: Option<Range<usize>> = None;
+        let mut width = 0;
+        for word in paragraph.split_whitespace() {
+            let start = offset + (word.as_ptr() as usize - paragraph.as_ptr() as usize);
+            let chars = word.chars().count();
+            match &mut line {
+                Some(current) if width + 1 + chars <= columns => {
+                    current.end = start + word.len();
+                    width += 1 + chars;
+                }
+                _ => {
+                    lines.extend(line.take());
+                    // A word wider than a line is cut wherever it reaches the edge
+                    let mut rest = (start, word);
+                    while rest.1.chars().count() > columns {
+                        let cut = rest.1.char_indices().nth(columns).map_or(rest.1.len(), |(at, _)| at);
+                        lines.push(rest.0..rest.0 + cut);
+                        rest = (rest.0 + cut, &rest.1[cut..]);
+                    }
+                    width = rest.1.chars().count();
+                    line = Some(rest.0..rest.0 + rest.1.len());
+                }
+            }
+        }
+        lines.push(line.unwrap_or(offset..offset));
+        offset += paragraph.len() + 1;
+    }
+    lines
+}
+
+#[cfg(unix)]
+fn terminal_size() -> Option<(usize, usize)> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let ok = unsafe { libc::ioctl(libc::STDERR_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    (ok && size.ws_col > 0 && size.ws_row > 0).then_some((size.ws_col as usize, size.ws_row as usize))
+}
+
+#[cfg(not(unix))]
+fn terminal_size() -> Option<(usize, usize)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_between_words() {
+        let lines = |text: &str, columns| -> Vec<String> { wrap(text, columns).into_iter().map(|r| text[r].to_string()).collect() };
+        assert_eq!(lines("The quick brown fox jumps.", 10), ["The quick", "brown fox", "jumps."]);
+        assert_eq!(lines("One.\n\nTwo  three", 20), ["One.", "", "Two  three"]);
+        assert_eq!(lines("a abcdefghij b", 4), ["a", "abcd", "efgh", "ij b"]);
+        assert_eq!(lines("café crème", 4), ["café", "crèm", "e"]);
+    }
+
+    #[test]
+    fn times_sentences_by_their_characters() {
+        let text = "Hi there. How are you? Fine.";
+        let timed: Vec<(&str, f64)> = spans(text).into_iter().map(|(r, start)| (&text[r], (start * 100.0).round())).collect();
+        assert_eq!(timed, [("Hi there.", 0.0), ("How are you?", 36.0), ("Fine.", 82.0)]);
+    }
+
+    #[test]
+    fn highlights_the_sentence_heard() {
+        let karaoke = Karaoke::with_size("Hi there. How are you? Fine.", 12, 10);
+        let cases = [
+            (0, Some(100), vec!["\x1b[7mHi there.\x1b[27m", "How are you?", "Fine."]),
+            (40, Some(100), vec!["Hi there.", "\x1b[7mHow are you?\x1b[27m", "Fine."]),
+            (90, Some(100), vec!["Hi there.", "How are you?", "\x1b[7mFine.\x1b[27m"]),
+            // Past an estimate that fell short
+            (150, Some(100), vec!["Hi there.", "How are you?", "\x1b[7mFine.\x1b[27m"]),
+            (40, None, vec!["\x1b[7mHi there.\x1b[27m", "How are you?", "Fine."]),
+        ];
+        for (played, total, expected) in cases {
+            let highlight = karaoke.heard(played, total).map(|i| karaoke.spans[i].0.clone());
+            assert_eq!(karaoke.frame(highlight), expected, "{:?}", (played, total));
+        }
+        // Across a line break, and scrolled to in a short window
+        let karaoke = Karaoke::with_size("One two three. Four five six seven. Eight.", 10, 3);
+        assert_eq!(karaoke.frame(Some(15..35)), ["three.", "\x1b[7mFour five\x1b[27m"]);
+        assert_eq!(karaoke.frame(Some(36..42)), ["six seven.", "\x1b[7mEight.\x1b[27m"]);
+    }
+}
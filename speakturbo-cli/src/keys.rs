@@ -0,0 +1,58 @@
+//! Keys pressed at the terminal while audio plays: q stops playback as
+//! Ctrl+C does. Read only while a [`Keys`] is held, and only when stdin is
+//! a terminal, so text piped in is never taken for keys.
+
+use crate::interrupt;
+
+/// The terminal's settings from before, put back on drop
+pub struct Keys {
+    #[cfg(unix)]
+    saved: Option<libc::termios>,
+}
+
+/// Read keys from now on, without echo or waiting for Enter.
+pub fn listen() -> Keys {
+    #[cfg(unix)]
+    {
+        use std::io::IsTerminal;
+        if !std::io::stdin().is_terminal() {
+            return Keys { saved: None };
+        }
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } != 0 {
+            return Keys { saved: None };
+        }
+        let mut raw = saved;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            return Keys { saved: None };
+        }
+        let _ = std::thread::Builder::new().name("keys".into()).spawn(|| loop {
+            let mut key = 0u8;
+            match unsafe { libc::read(libc::STDIN_FILENO, (&mut key as *mut u8).cast(), 1) } {
+                1 if key == b'q' || key == b'Q' => {
+                    interrupt::token().cancel();
+                    return;
+                }
+                1 => {}
+                0 => return,
+                _ if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted => {}
+                _ => return,
+            }
+        });
+        Keys { saved: Some(saved) }
+    }
+    #[cfg(not(unix))]
+    Keys {}
+}
+
+impl Drop for Keys {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(saved) = &self.saved {
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved) };
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! Voices by language, from the config's `[voices.by-language]` table:
+//! --lang picks one by code, and --auto-voice by whatlang's guess at what
+//! language a text is in.
+
+use whatlang::Lang;
+
+/// Below this a guess isn't trusted and --lang's voice or --voice is used
+/// instead. Lower than whatlang's own 0.9 for a reliable guess, which
+/// close languages such as Dutch and Afrikaans rarely reach
+pub const MIN_CONFIDENCE: f64 = 0.5;
+
+/// `lang`'s ISO 639-1 code, as `[voices.by-language]` keys
+pub fn code(lang: Lang) -> &'static str {
+    match lang {
+        Lang::Epo => "eo",
+        Lang::Eng => "en",
+        Lang::Rus => "ru",
+        Lang::Cmn => "zh",
+        Lang::Spa => "es",
+        Lang::Por => "pt",
+        Lang::Ita => "it",
+        Lang::Ben => "bn",
+        Lang::Fra => "fr",
+        Lang::Deu => "de",
+        Lang::Ukr => "uk",
+        Lang::Kat => "ka",
+        Lang::Ara => "ar",
+        Lang::Hin => "hi",
+        Lang::Jpn => "ja",
+        Lang::Heb => "he",
+        Lang::Yid => "yi",
+        Lang::Pol => "pl",
+        Lang::Amh => "am",
+        Lang::Jav => "jv",
+        Lang::Kor => "ko",
+        Lang::Nob => "nb",
+        Lang::Dan => "da",
+        Lang::Swe => "sv",
+        Lang::Fin => "fi",
+        Lang::Tur => "tr",
+        Lang::Nld => "nl",
+        Lang::Hun => "hu",
+        Lang::Ces => "cs",
+        Lang::Ell => "el",
+        Lang::Bul => "bg",
+        Lang::Bel => "be",
+        Lang::Mar => "mr",
+        Lang::Kan => "kn",
+        Lang::Ron => "ro",
+        Lang::Slv => "sl",
+        Lang::Hrv => "hr",
+        Lang::Srp => "sr",
+        Lang::Mkd => "mk",
+        Lang::Lit => "lt",
+        Lang::Lav => "lv",
+        Lang::Est => "et",
+        Lang::Tam => "ta",
+        Lang::Vie => "vi",
+        Lang::Urd => "ur",
+        Lang::Tha => "th",
+        Lang::Guj => "gu",
+        Lang::Uzb => "uz",
+        Lang::Pan => "pa",
+        Lang::Aze => "az",
+        Lang::Ind => "id",
+        Lang::Tel => "te",
+        Lang::Pes => "fa",
+        Lang::Mal => "ml",
+        Lang::Ori => "or",
+        Lang::Mya => "my",
+        Lang::Nep => "ne",
+        Lang::Sin => "si",
+        Lang::Khm => "km",
+        Lang::Tuk => "tk",
+        Lang::Aka => "ak",
+        Lang::Zul => "zu",
+        Lang::Sna => "sn",
+        Lang::Afr => "af",
+        Lang::Lat => "la",
+        Lang::Slk => "sk",
+        Lang::Cat => "ca",
+        Lang::Tgl => "tl",
+        Lang::Hye => "hy",
+        Lang::Cym => "cy",
+    }
+}
+
+/// The voice `by_language` gives `code`, for --lang.
+pub fn configured<'a>(code: &str, by_language: &'a [(String, String)]) -> Result<&'a str, String> {
+    let code = code.to_lowercase();
+    if let Some((_, voice)) = by_language.iter().find(|(c, _)| *c == code) {
+        return Ok(voice);
+    }
+    match by_language {
+        [] => Err(format!("no voice configured for '{}'; add one to [voices.by-language] in the config", code)),
+        _ => {
+            let known: Vec<&str> = by_language.iter().map(|(c, _)| c.as_str()).collect();
+            Err(format!("no voice configured for '{}'; known languages: {}", code, known.join(", ")))
+        }
+    }
+}
+
+/// The voice `voices` (language code, voice) gives the language of `text`,
+/// or `fallback` when that's unclear or unmapped; -v says which.
+pub fn voice<'a>(text: &str, voices: &'a [(String, String)], fallback: &'a str) -> &'a str {
+    let note = |message: String| tracing::info!(target: "voice", "auto voice: {}", message);
+    let guess = match whatlang::detect(text) {
+        Some(guess) if guess.confidence() >= MIN_CONFIDENCE => guess,
+        Some(guess) => {
+            note(format!(
+                "unsure of the language ({}, confidence {:.2}); using {}",
+                guess.lang().eng_name(),
+                guess.confidence(),
+                fallback
+            ));
+            return fallback;
+        }
+        None => {
+            note(format!("no idea of the language; using {}", fallback));
+            return fallback;
+        }
+    };
+    let (name, code) = (guess.lang().eng_name(), code(guess.lang()));
+    match voices.iter().find(|(c, _)| c == code) {
+        Some((_, voice)) => {
+            note(format!("{} (confidence {:.2}) → {}", name, guess.confidence(), voice));
+            voice
+        }
+        None => {
+            note(format!(
+                "{} (confidence {:.2}), but [voices.by-language] has no voice for {}; using {}",
+                name,
+                guess.confidence(),
+                code,
+                fallback
+            ));
+            fallback
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_languages() {
+        let cases = [
+            ("The meeting is at noon and we will be there with the slides.", Some("en")),
+            ("Je pense que la réunion est à midi, mais il faut vérifier avec elle.", Some("fr")),
+            ("El informe está listo y lo enviamos por correo con las notas.", Some("es")),
+            ("Ich glaube, die Besprechung ist um zwölf und wir sind auch dabei.", Some("de")),
+            ("Non so se questo è il momento giusto, ma ci proviamo anche noi.", Some("it")),
+            ("Não sei se você vai, mas ela disse que também quer ir com os amigos.", Some("pt")),
+            ("De vergadering is om twaalf uur en wij zijn er ook bij met de nieuwe plannen.", Some("nl")),
+            ("Kubernetes deployment successful", None),
+        ];
+        for (text, expected) in cases {
+            let guess = whatlang::detect(text).filter(|g| g.confidence() >= MIN_CONFIDENCE);
+            assert_eq!(guess.map(|g| code(g.lang())), expected, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn resolves_voices_in_order() {
+        let voices = vec![("en".to_string(), "alba".to_string()), ("fr".to_string(), "javert".to_string())];
+        let french = "C'est une bonne idée, mais nous ne sommes pas prêts pour la suite.";
+        let german = "Das ist eine gute Idee, aber wir sind noch nicht so weit.";
+        // --lang, else --voice, is the fallback; a confident, mapped guess wins over it
+        let cases = [
+            (None, french, "javert"),
+            (Some("en"), french, "javert"),
+            (Some("FR"), german, "javert"),
+            (Some("en"), german, "alba"),
+            (None, german, "cosette"),
+            (Some("fr"), "Bonjour!", "javert"),
+            (None, "la", "cosette"),
+        ];
+        for (lang, text, expected) in cases {
+            let fallback = lang.map_or(Ok("cosette"), |code| configured(code, &voices)).unwrap();
+            assert_eq!(voice(text, &voices, fallback), expected, "{:?} {:?}", lang, text);
+        }
+        assert_eq!(configured("de", &voices), Err("no voice configured for 'de'; known languages: en, fr".to_string()));
+        assert_eq!(
+            configured("de", &[]),
+            Err("no voice configured for 'de'; add one to [voices.by-language] in the config".to_string())
+        );
+    }
+}
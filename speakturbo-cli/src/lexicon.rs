@@ -0,0 +1,289 @@
+//! `--lexicon`: words the daemon gets wrong, respelled ("nginx" -> "engine
+//! x") or given IPA phonemes, which go out as SSML `<phoneme>` tags with
+//! `--ssml`. Lexicons are TOML or JSON:
+//!
+//! ```toml
+//! case_sensitive = false          # for every word here; true by default
+//! [words]
+//! nginx = "engine x"
+//! kubectl = { say = "cube control", ipa = "kjuːb kənˈtɹoʊl", case_sensitive = true }
+//! ```
+
+use crate::config::kind;
+use crate::{abbrev, reporter, ssml, text};
+use anyhow::{bail, Result};
+use indexmap::IndexMap;
+use serde::Deserialize;
+use std::hash::Hash;
+use toml::Spanned;
+
+#[derive(Clone, Debug, PartialEq)]
+struct Rule {
+    word: String,
+    say: Option<String>,
+    ipa: Option<String>,
+    case_sensitive: bool,
+    /// Where the rule is defined, as "path:line"
+    origin: String,
+}
+
+/// One lexicon file's rules
+#[derive(Clone, Debug)]
+pub struct Lexicon(Vec<Rule>);
+
+/// A lexicon as written, its words keyed by `K`: with their place in a
+/// TOML file, bare from a JSON one
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, bound(deserialize = "K: Deserialize<'de> + Hash + Eq"))]
+struct File<K> {
+    #[serde(default = "case_sensitive_by_default")]
+    case_sensitive: bool,
+    words: IndexMap<K, toml::Value>,
+}
+
+/// A word as read: its line, the word, and what it's respelled as
+type Word = (usize, String, toml::Value);
+
+fn case_sensitive_by_default() -> bool {
+    true
+}
+
+/// `kubectl = { say = "cube control", ipa = "kjuːb kənˈtɹoʊl" }`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Fields {
+    say: Option<String>,
+    ipa: Option<String>,
+    case_sensitive: Option<bool>,
+}
+
+impl Lexicon {
+    /// Reads a lexicon, as JSON if it's named *.json or starts with '{'.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Self::parse(&text, path).map_err(|e| format!("{}:{}", path, e))
+    }
+
+    fn parse(text: &str, path: &str) -> Result<Self> {
+        let is_json = path.to_ascii_lowercase().ends_with(".json") || text.trim_start().starts_with('{');
+        let (case_sensitive, words) = match is_json {
+            true => from_json(text)?,
+            false => {
+                let file: File<Spanned<String>> = toml::from_str(text).map_err(|e| {
+                    anyhow::anyhow!("{}: {}", text::line_at(text, e.span().map_or(0, |s| s.start)), e.message())
+                })?;
+                let words = file.words.into_iter().map(|(word, value)| (text::line_at(text, word.span().start), word.into_inner(), value));
+                (file.case_sensitive, words.collect())
+            }
+        };
+        words.into_iter().map(|(line, word, value)| rule(line, word, value, case_sensitive, path)).collect::<Result<_>>().map(Lexicon)
+    }
+}
+
+fn rule(line: usize, word: String, value: toml::Value, case_sensitive: bool, path: &str) -> Result<Rule> {
+    let mut rule = Rule { word: word.trim().to_string(), say: None, ipa: None, case_sensitive, origin: format!("{}:{}", path, line) };
+    if rule.word.is_empty() {
+        bail!("{}: empty word", line);
+    }
+    match value {
+        toml::Value::String(say) => rule.say = Some(say),
+        fields @ toml::Value::Table(_) => {
+            let fields: Fields = fields.try_into().map_err(|e: toml::de::Error| anyhow::anyhow!("{}: {:?}: {}", line, rule.word, e.message()))?;
+            (rule.say, rule.ipa) = (fields.say, fields.ipa);
+            rule.case_sensitive = fields.case_sensitive.unwrap_or(case_sensitive);
+        }
+        value => bail!("{}: {:?} should be a respelling or a table, not {}", line, rule.word, kind(&value)),
+    }
+    if rule.say.is_none() && rule.ipa.is_none() {
+        bail!("{}: {:?} needs a say or an ipa", line, rule.word);
+    }
+    if [&rule.say, &rule.ipa].into_iter().flatten().any(|s| s.trim().is_empty()) {
+        bail!("{}: empty say or ipa for {:?}", line, rule.word);
+    }
+    Ok(rule)
+}
+
+/// A JSON lexicon's case sensitivity and words, each with its line, found
+/// by looking for the words in order.
+fn from_json(text: &str) -> Result<(bool, Vec<Word>)> {
+    let value: serde_json::Value = serde_json::from_str(text).map_err(|e| anyhow::anyhow!("{}: {}", e.line().max(1), e))?;
+    let file: File<String> = convert(&value)?.try_into().map_err(|e: toml::de::Error| anyhow::anyhow!("1: {}", e.message()))?;
+    let mut cursor = text.find("\"words\"").unwrap_or(0);
+    let mut words = Vec::new();
+    for (word, value) in file.words {
+        let quoted = serde_json::Value::from(word.as_str()).to_string();
+        cursor += text[cursor..].find(&quoted).unwrap_or(0);
+        words.push((text::line_at(text, cursor), word, value));
+    }
+    Ok((file.case_sensitive, words))
+}
+
+/// A JSON value as the TOML one it stands for.
+fn convert(value: &serde_json::Value) -> Result<toml::Value> {
+    Ok(match value {
+        serde_json::Value::Null => bail!("1: null isn't allowed"),
+        serde_json::Value::Bool(b) => toml::Value::Boolean(*b),
+        serde_json::Value::Number(n) => toml::Value::Float(n.as_f64().unwrap_or_default()),
+        serde_json::Value::String(s) => toml::Value::String(s.clone()),
+        serde_json::Value::Array(items) => toml::Value::Array(items.iter().map(convert).collect::<Result<_>>()?),
+        serde_json::Value::Object(fields) => {
+            toml::Value::Table(fields.iter().map(|(key, value)| Ok((key.clone(), convert(value)?))).collect::<Result<_>>()?)
+        }
+    })
+}
+
+/// `text` with the words of `lexicons` replaced, a later lexicon's rule for
+/// a word winning over an earlier one's. With `ssml`, `text` is SSML: only
+/// its text is touched, and words with phonemes get `<phoneme>` tags.
+/// `debug` prints the rules that fired.
+pub fn apply(text: &str, lexicons: &[Lexicon], ssml: bool, debug: bool) -> String {
+    if lexicons.is_empty() {
+        return text.to_string();
+    }
+    let mut rules: Vec<&Rule> = Vec::new();
+    for rule in lexicons.iter().flat_map(|l| &l.0) {
+        rules.retain(|r| r.word.to_lowercase() != rule.word.to_lowercase());
+        rules.push(rule);
+    }
+    // Longest first, so "Node.js" wins over "Node"
+    rules.sort_by_key(|r| std::cmp::Reverse(r.word.len()));
+    let mut fired = vec![0; rules.len()];
+
+    let out = match ssml {
+        true => ssml::map_text(text, &mut |segment| replace(segment, &rules, true, &mut fired)),
+        false => replace(text, &rules, false, &mut fired),
+    };
+    if debug {
+        let mut any = false;
+        for (rule, &count) in rules.iter().zip(&fired).filter(|(_, &count)| count > 0) {
+            let to = match (&rule.ipa, &rule.say) {
+                (Some(ipa), _) if ssml => format!("/{}/", ipa),
+                (_, Some(say)) => format!("{:?}", say),
+                _ => "unchanged (its ipa needs --ssml)".to_string(),
+            };
+            reporter::info(format_args!("Lexicon: {:?} -> {} ×{} ({})", rule.word, to, count, rule.origin));
+            any = true;
+        }
+        if !any {
+            reporter::info("Lexicon: no words matched");
+        }
+    }
+    out
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn replace(text: &str, rules: &[&Rule], ssml: bool, fired: &mut [usize]) -> String {
+    let protected = abbrev::code_spans(text);
+    let mut out = String::with_capacity(text.len());
+    let mut at = 0;
+    let mut previous = None;
+    while let Some(c) = text[at..].chars().next() {
+        let starts_word = previous.is_none_or(|p| !is_word_char(p)) || !is_word_char(c);
+        let matched = starts_word && !protected.iter().any(|&(s, e)| (s..e).contains(&at));
+        let hit = matched
+            .then(|| {
+                rules.iter().enumerate().find(|(_, rule)| {
+                    let Some(found) = text.get(at..at + rule.word.len()) else { return false };
+                    let same = match rule.case_sensitive {
+                        true => found == rule.word,
+                        false => found.to_lowercase() == rule.word.to_lowercase(),
+                    };
+                    let last = found.chars().next_back().is_some_and(is_word_char);
+                    same && !(last && text[at + found.len()..].starts_with(is_word_char))
+                })
+            })
+            .flatten();
+        match hit {
+            Some((n, rule)) => {
+                let found = &text[at..at + rule.word.len()];
+                fired[n] += 1;
+                match (&rule.ipa, &rule.say) {
+                    (Some(ipa), _) if ssml => {
+                        out.push_str(&format!("<phoneme alphabet=\"ipa\" ph=\"{}\">{}</phoneme>", ssml::escape(ipa), found))
+                    }
+                    (_, Some(say)) if ssml => out.push_str(&ssml::escape(say)),
+                    (_, Some(say)) => out.push_str(say),
+                    _ => out.push_str(found),
+                }
+                previous = found.chars().next_back();
+                at += found.len();
+            }
+            None => {
+                out.push(c);
+                previous = Some(c);
+                at += c.len_utf8();
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lexicon(text: &str, path: &str) -> Lexicon {
+        Lexicon::parse(text, path).unwrap()
+    }
+
+    #[test]
+    fn replaces_whole_words() {
+        let base = lexicon(
+            "[words]\nnginx = \"engine x\"\nSQL = \"sequel\"\nNode = \"nodd\"\n\"Node.js\" = \"node J S\"\n\"C++\" = 'see plus plus'\n",
+            "base.toml",
+        );
+        let cases = [
+            ("Restart nginx, then nginx-proxy.", "Restart engine x, then engine x-proxy."),
+            ("SQL and sql and MySQL", "sequel and sql and MySQL"),
+            ("Node.js beats Node, not Nodes.", "node J S beats nodd, not Nodes."),
+            ("I like C++.", "I like see plus plus."),
+            ("See https://nginx.org/ and `nginx -t`", "See https://nginx.org/ and `nginx -t`"),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(apply(text, std::slice::from_ref(&base), false, false), expected, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn later_lexicons_win_and_case_is_configurable() {
+        let first = lexicon("[words]\nnginx = \"engine x\"\nkubectl = \"cube cuttle\"\n", "a.toml");
+        let second = lexicon(
+            "case_sensitive = false\n[words]\nNGINX = \"N jinx\"\nkubectl = { say = \"cube control\", ipa = \"kjuːb\" }\n",
+            "b.toml",
+        );
+        let both = [first, second];
+        assert_eq!(apply("Nginx and kubectl", &both, false, false), "N jinx and cube control");
+        assert_eq!(
+            apply("<speak>Use kubectl &amp; <sub alias=\"x\">nginx</sub></speak>", &both, true, false),
+            "<speak>Use <phoneme alphabet=\"ipa\" ph=\"kjuːb\">kubectl</phoneme> &amp; <sub alias=\"x\">nginx</sub></speak>"
+        );
+    }
+
+    #[test]
+    fn reads_json_lexicons() {
+        let json = "{\n  \"words\": {\n    \"nginx\": \"engine x\",\n    \"k8s\": {\"say\": \"kubernetes\", \"case_sensitive\": false}\n  }\n}\n";
+        let lexicon = lexicon(json, "words.json");
+        assert_eq!(lexicon.0[1].origin, "words.json:4");
+        assert_eq!(apply("K8S on nginx", &[lexicon], false, false), "kubernetes on engine x");
+    }
+
+    #[test]
+    fn reports_malformed_entries_by_line() {
+        let cases = [
+            ("[words]\na = \"x\"\nb = 3\n", "x.toml", "3: \"b\" should be a respelling or a table, not an integer"),
+            ("[words]\nb = { sey = \"x\" }\n", "x.toml", "2: \"b\": unknown field `sey`, expected one of `say`, `ipa`, `case_sensitive`"),
+            ("[words]\nb = { case_sensitive = true }\n", "x.toml", "2: \"b\" needs a say or an ipa"),
+            ("words = 1\n", "x.toml", "1: invalid type: integer `1`, expected a map"),
+            ("[word]\n", "x.toml", "1: unknown field `word`, expected `case_sensitive` or `words`"),
+            ("[words]\nb = nope\n", "x.toml", "2: invalid float, expected `nan`"),
+            ("{\"words\": {\n\"a\": \"x\",\n\"b\": [1]}}", "x.json", "3: \"b\" should be a respelling or a table, not an array"),
+            ("{\"words\": {\n\"a\": \"x\"\n\"b\": 1}}", "x.json", "3: expected `,` or `}` at line 3 column 1"),
+        ];
+        for (text, path, expected) in cases {
+            assert_eq!(Lexicon::parse(text, path).unwrap_err().to_string(), expected, "{:?}", text);
+        }
+    }
+}
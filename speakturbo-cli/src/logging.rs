@@ -0,0 +1,418 @@
+//! Leveled tracing events on stderr for --verbose and SPEAKTURBO_LOG, for
+//! finding where the time goes. The request, the network reads and
+//! playback each run in a span that logs when it closes and how long it
+//! took, and every text line carries the time since the invocation
+//! started. --log-file keeps the same events as JSON lines, whatever the
+//! terminal shows.
+
+use std::cell::Cell;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::fmt::format::{FmtSpan, Writer};
+use tracing_subscriber::fmt::time::FormatTime;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+/// Names SPEAKTURBO_LOG filters on
+pub const TARGETS: &[&str] = &["request", "net", "playback", "voice"];
+
+/// main's errors: on the terminal only as JSON lines, where main doesn't
+/// print its "Error: ..."
+const MAIN: &str = "main";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    Text,
+    /// One object per line
+    Json,
+}
+
+/// A filter's level, "off" among them
+fn level(s: &str) -> Result<LevelFilter, String> {
+    ["off", "error", "warn", "info", "debug", "trace"]
+        .into_iter()
+        .find(|l| l.eq_ignore_ascii_case(s))
+        .and_then(|l| l.parse().ok())
+        .ok_or_else(|| format!("unknown level '{}'; use off, error, warn, info, debug or trace", s))
+}
+
+/// How much to log: a level for everything, and levels for some targets.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Filter {
+    default: LevelFilter,
+    targets: Vec<(String, LevelFilter)>,
+    /// Whether to log errors, whatever the levels say
+    errors: bool,
+}
+
+impl Filter {
+    /// SPEAKTURBO_LOG's `info`, `net=trace`, or both: `info,net=trace`.
+    pub fn parse(spec: &str) -> Result<Filter, String> {
+        let mut filter = Filter { default: LevelFilter::OFF, targets: Vec::new(), errors: true };
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((target, l)) => {
+                    if !TARGETS.contains(&target) {
+                        return Err(format!("unknown target '{}'; the targets are {}", target, TARGETS.join(", ")));
+                    }
+                    filter.targets.push((target.to_string(), level(l)?));
+                }
+                None => filter.default = level(directive)?,
+            }
+        }
+        Ok(filter)
+    }
+
+    /// From SPEAKTURBO_LOG if it's set, else one --verbose (info) or two
+    /// (debug). Without either, nothing is logged; -q leaves errors, and
+    /// -qq not even those.
+    pub fn new(verbose: u8, spec: Option<&str>, quiet: u8) -> Result<Filter, String> {
+        let mut filter = match spec.filter(|s| !s.trim().is_empty()) {
+            Some(spec) => Filter::parse(spec).map_err(|e| format!("SPEAKTURBO_LOG: {}", e))?,
+            None => {
+                let default = match verbose {
+                    0 => LevelFilter::OFF,
+                    1 => LevelFilter::INFO,
+                    2 => LevelFilter::DEBUG,
+                    _ => LevelFilter::TRACE,
+                };
+                Filter { default, targets: Vec::new(), errors: verbose > 0 }
+            }
+        };
+        if quiet > 0 {
+            filter = Filter { default: LevelFilter::OFF, targets: Vec::new(), errors: filter.errors && quiet < 2 };
+        }
+        Ok(filter)
+    }
+
+    /// Whether main's errors get through
+    fn shows_errors(&self) -> bool {
+        self.errors || self.default > LevelFilter::OFF
+    }
+
+    /// As EnvFilter directives: a later one for a target wins, and one
+    /// that's off still lets errors through if they're logged at all.
+    fn directives(&self) -> String {
+        let level = |l: LevelFilter| match self.errors {
+            true => l.max(LevelFilter::ERROR),
+            false => l,
+        };
+        let targets = self.targets.iter().map(|(target, l)| format!("{}={}", target, level(*l)));
+        std::iter::once(level(self.default).to_string()).chain(targets).collect::<Vec<_>>().join(",").to_lowercase()
+    }
+}
+
+/// Whether main's errors are logged as JSON lines rather than printed
+static ERRORS_AS_JSON: AtomicBool = AtomicBool::new(false);
+
+/// Requests made so far, for their ids
+static REQUESTS: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// The request this thread's spans are about
+    static REQUEST: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+/// Start logging; until then nothing is.
+pub fn init(filter: Filter, format: Format, file: Option<LogFile>, start: Instant) {
+    ERRORS_AS_JSON.store(format == Format::Json && filter.shows_errors(), Ordering::Relaxed);
+    let mut layers = vec![terminal(&filter, format, start, io::stderr)];
+    // What --log-file takes, whatever --verbose says
+    layers.extend(file.map(|file| json(file).with_filter(LevelFilter::INFO).boxed()));
+    let _ = tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layers));
+}
+
+type Boxed = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// The --verbose lines, as --log-format says
+fn terminal<W>(filter: &Filter, format: Format, start: Instant, writer: W) -> Boxed
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let mut directives = filter.directives();
+    if format == Format::Text {
+        directives.push_str(&format!(",{}=off", MAIN));
+    }
+    let filter = EnvFilter::new(directives);
+    match format {
+        Format::Text => tracing_subscriber::fmt::layer()
+            .with_writer(writer)
+            .with_ansi(false)
+            .with_timer(Since(start))
+            .with_span_events(FmtSpan::CLOSE)
+            .with_filter(filter)
+            .boxed(),
+        Format::Json => json(writer).with_filter(filter).boxed(),
+    }
+}
+
+/// A JSON object per line, with the spans an event is in; a span's
+/// closing line has it as "span", where "spans" leaves it out
+fn json<S, W>(writer: W) -> impl Layer<S>
+where
+    S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + 'static,
+{
+    tracing_subscriber::fmt::layer()
+        .json()
+        .flatten_event(true)
+        .with_current_span(true)
+        .with_span_list(true)
+        .with_writer(writer)
+        .with_span_events(FmtSpan::CLOSE)
+}
+
+/// "[    95.0ms]": the time since the invocation started
+struct Since(Instant);
+
+impl FormatTime for Since {
+    fn format_time(&self, w: &mut Writer<'_>) -> fmt::Result {
+        write!(w, "[{:>8.1}ms]", self.0.elapsed().as_secs_f64() * 1000.0)
+    }
+}
+
+/// Whether error events reach the terminal as JSON lines rather than
+/// as main's "Error: ..."
+pub fn errors_as_json() -> bool {
+    ERRORS_AS_JSON.load(Ordering::Relaxed)
+}
+
+/// Log main's error, to the terminal only when [`errors_as_json`].
+pub fn error(message: impl fmt::Display) {
+    tracing::error!(target: MAIN, "{}", message);
+}
+
+/// Tag this thread's spans from now on with a new request id.
+pub fn new_request() {
+    REQUEST.with(|r| r.set(Some(REQUESTS.fetch_add(1, Ordering::Relaxed) + 1)));
+}
+
+/// "4711-3": unique among invocations sharing a log file
+pub fn request_id() -> Option<String> {
+    REQUEST.with(Cell::get).map(|n| format!("{}-{}", std::process::id(), n))
+}
+
+/// --log-file: events appended as JSON lines, one write each so that
+/// invocations sharing the file don't interleave within a line. Past
+/// `max_bytes` it's renamed to PATH.1 (and PATH.1 to PATH.2, ...), with
+/// `keep` old files kept.
+pub struct LogFile {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: u32,
+    /// None once writing has failed: it's said once, then given up
+    file: Mutex<Option<fs::File>>,
+}
+
+impl LogFile {
+    pub fn open(path: &Path, max_bytes: u64, keep: u32) -> io::Result<LogFile> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let file = append_to(path)?;
+        Ok(LogFile { path: path.to_path_buf(), max_bytes, keep, file: Mutex::new(Some(file)) })
+    }
+
+    /// Append `line`, newline and all.
+    fn append(&self, line: &[u8]) {
+        let mut file = self.file.lock().unwrap();
+        let Some(open) = file.as_mut() else { return };
+        if let Err(e) = self.rotate(open, line.len() as u64).and_then(|()| open.write_all(line)) {
+            crate::reporter::warning(format_args!("log file {}: {}; no more events go to it", self.path.display(), e));
+            *file = None;
+        }
+    }
+
+    /// Rotate if `adding` would take the file past the limit, and reopen
+    /// if another invocation has rotated it.
+    fn rotate(&self, open: &mut fs::File, adding: u64) -> io::Result<()> {
+        let current = fs::metadata(&self.path).ok();
+        if !current.as_ref().is_some_and(|m| same_file(m, &open.metadata().ok())) {
+            *open = append_to(&self.path)?;
+        }
+        let len = current.map_or(0, |m| m.len());
+        if len == 0 || len + adding <= self.max_bytes {
+            return Ok(());
+        }
+        let numbered = |n: u32| PathBuf::from(format!("{}.{}", self.path.display(), n));
+        let _ = fs::remove_file(numbered(self.keep));
+        for n in (1..self.keep).rev() {
+            let _ = fs::rename(numbered(n), numbered(n + 1));
+        }
+        match self.keep {
+            0 => fs::remove_file(&self.path)?,
+            _ => fs::rename(&self.path, numbered(1))?,
+        }
+        *open = append_to(&self.path)?;
+        Ok(())
+    }
+}
+
+/// The JSON layer writes each event whole, in one call
+impl Write for &LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.append(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogFile {
+    type Writer = &'a LogFile;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self
+    }
+}
+
+fn append_to(path: &Path) -> io::Result<fs::File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+#[cfg(unix)]
+fn same_file(a: &fs::Metadata, b: &Option<fs::Metadata>) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    b.as_ref().is_some_and(|b| a.dev() == b.dev() && a.ino() == b.ino())
+}
+
+#[cfg(not(unix))]
+fn same_file(_: &fs::Metadata, _: &Option<fs::Metadata>) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// What a layer wrote, line by line
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Captured {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap().lines().map(str::to_string).collect()
+        }
+    }
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// The terminal's lines while `f` runs
+    fn logged(filter: &Filter, format: Format, f: impl FnOnce()) -> Vec<String> {
+        let captured = Captured::default();
+        let sink = captured.clone();
+        let layer = terminal(filter, format, Instant::now(), move || sink.clone());
+        tracing::subscriber::with_default(tracing_subscriber::registry().with(layer), f);
+        captured.lines()
+    }
+
+    #[test]
+    fn filters_by_level_and_target() {
+        let filter = Filter::parse("info, net=trace, playback=off").unwrap();
+        assert_eq!(filter.directives(), "info,net=trace,playback=error");
+        let lines = logged(&filter, Format::Text, || {
+            tracing::info!(target: "request", "request info");
+            tracing::debug!(target: "request", "request debug");
+            tracing::trace!(target: "net", "net trace");
+            tracing::info!(target: "playback", "playback info");
+            // Errors always get through
+            tracing::error!(target: "playback", "playback error");
+        });
+        let messages: Vec<&str> = lines.iter().filter_map(|l| l.rsplit(": ").next()).collect();
+        assert_eq!(messages, ["request info", "net trace", "playback error"]);
+        assert_eq!(Filter::parse("loud").unwrap_err(), "unknown level 'loud'; use off, error, warn, info, debug or trace");
+        assert_eq!(Filter::parse("disk=info").unwrap_err(), "unknown target 'disk'; the targets are request, net, playback, voice");
+    }
+
+    #[test]
+    fn takes_verbosity_from_flags_or_the_environment() {
+        let cases: &[(u8, Option<&str>, u8, &str)] = &[
+            (0, None, 0, "off"),
+            (1, None, 0, "info"),
+            (2, None, 0, "debug"),
+            (3, None, 0, "trace"),
+            (0, Some("debug"), 0, "debug"),
+            (2, Some("warn"), 0, "warn"),
+            (0, Some(""), 0, "off"),
+            (0, Some("off,net=info"), 0, "error,net=info"),
+            (0, Some("trace"), 1, "error"),
+            (0, Some("trace"), 2, "off"),
+        ];
+        for &(verbose, spec, quiet, expected) in cases {
+            let filter = Filter::new(verbose, spec, quiet).unwrap();
+            assert_eq!(filter.directives(), expected, "{:?}", (verbose, spec, quiet));
+        }
+        assert!(Filter::new(0, Some("nope"), 0).unwrap_err().starts_with("SPEAKTURBO_LOG: unknown level"));
+    }
+
+    #[test]
+    fn writes_text_or_json_lines() {
+        let filter = Filter::parse("info").unwrap();
+        let run = || {
+            let span = tracing::info_span!(target: "request", "request", request = "4711-3").entered();
+            tracing::info!(target: "request", "sent");
+            drop(span);
+            error("Daemon not running?");
+        };
+        let text = logged(&filter, Format::Text, run);
+        assert_eq!(text.len(), 2, "{:?}", text);
+        assert!(text[0].starts_with('[') && text[0].ends_with(r#"INFO request{request="4711-3"}: request: sent"#), "{}", text[0]);
+        assert!(text[1].contains("request: close time.busy="), "{}", text[1]);
+
+        // main's error is there as JSON, where main doesn't print it
+        let json: Vec<serde_json::Value> = logged(&filter, Format::Json, run).iter().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(json.len(), 3);
+        assert_eq!(json[0]["message"], "sent");
+        assert_eq!(json[0]["spans"][0]["request"], "4711-3");
+        assert_eq!(json[1]["message"], "close");
+        assert_eq!(json[1]["span"]["request"], "4711-3");
+        assert_eq!((&json[2]["level"], &json[2]["target"]), (&"ERROR".into(), &"main".into()));
+    }
+
+    #[test]
+    fn rotates_the_log_file() {
+        let dir = std::env::temp_dir().join(format!("speakturbo-log-{}", std::process::id()));
+        let path = dir.join("events.jsonl");
+        let file = LogFile::open(&path, 20, 2).unwrap();
+        for n in 0..7 {
+            file.append(format!("line {:02} ......\n", n).as_bytes());
+        }
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap_or_default();
+        assert_eq!(read("events.jsonl"), "line 06 ......\n");
+        assert_eq!(read("events.jsonl.1"), "line 05 ......\n");
+        assert_eq!(read("events.jsonl.2"), "line 04 ......\n");
+        assert!(!dir.join("events.jsonl.3").exists());
+
+        // Someone else rotated it: the next line starts a new file
+        fs::rename(&path, dir.join("moved.jsonl")).unwrap();
+        file.append(b"after\n");
+        assert_eq!(read("events.jsonl"), "after\n");
+
+        // A file that can't be written is given up on, not fatal
+        fs::remove_file(&path).unwrap();
+        fs::create_dir(&path).unwrap();
+        file.append(b"lost\n");
+        assert!(file.file.lock().unwrap().is_none());
+        file.append(b"also lost\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,14 +1,107 @@
-use anyhow::{Context, Result};
-use clap::Parser;
-use rodio::{OutputStream, Sink, Source};
-use std::collections::VecDeque;
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
+use speakturbo_client::buffer::LockFreeBuffer;
+use speakturbo_client::events::{ErrorKind, Events, SpeakEvent};
+use speakturbo_client::{mock_daemon, wav, Capabilities, SpeakClient, SpeakError, SpeakRequest, SpeakRequestBuilder, SynthesisStats, DEFAULT_URL, SAMPLE_RATE, VOICES};
 use std::io::Read;
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
 use std::time::{Duration, Instant};
 
-const DAEMON_URL: &str = "http://127.0.0.1:7125";
-const SAMPLE_RATE: u32 = 24000;
+mod abbrev;
+mod ansi;
+mod batch;
+mod bench;
+mod bookmark;
+mod breaks;
+mod burst;
+mod cache_cmd;
+mod charset;
+mod checkpoint;
+mod checksum;
+mod chime;
+#[cfg(feature = "clipboard")]
+mod clipboard;
+mod compare;
+mod completions;
+mod config;
+mod console;
+mod convert;
+mod dedupe;
+mod diff;
+mod daemon_cmd;
+mod dialogue;
+mod dry_run;
+mod emoji;
+mod epub;
+mod exec;
+mod exit;
+mod fifo;
+mod follow;
+mod history;
+mod hooks;
+mod html;
+mod interrupt;
+mod join;
+mod keys;
+mod language;
+mod karaoke;
+mod lexicon;
+mod logging;
+mod manifest;
+mod markdown;
+mod marks;
+mod normalize;
+mod numbers;
+#[cfg(feature = "notify")]
+mod notify;
+#[cfg(feature = "dbus")]
+mod notify_listen;
+mod output;
+mod pattern;
+mod phrases;
+mod pick;
+mod play;
+mod playback;
+mod players;
+mod png;
+mod prefetch;
+mod preview;
+mod progress;
+mod replay;
+mod report;
+mod reporter;
+mod rtp;
+mod self_test;
+mod sequence;
+mod sentence;
+mod serve;
+mod settings;
+mod spool;
+mod ssml;
+mod stream;
+mod summary;
+mod text;
+mod url;
+mod voices;
+mod waveform;
+
+use speakturbo_client::cache::Cache;
+use checksum::{Algorithm, Checksum};
+use chime::Chimes;
+use karaoke::Karaoke;
+use output::{ExistingPolicy, SaveOptions};
+use progress::{Progress, ProgressReader};
+use reporter::Mark;
+use waveform::{Envelope, SharedEnvelope};
+
+/// --daemon-url, once settled
+static DAEMON: OnceLock<String> = OnceLock::new();
+/// The client for it, so that what the daemon says of itself is asked once
+static CLIENT: OnceLock<SpeakClient> = OnceLock::new();
+/// Samples the last playback stopped short at, for --bookmark
+static STOPPED_AT: AtomicU64 = AtomicU64::new(0);
 
 // Buffer size: 150ms provides stable playback without perceptible latency
 const MIN_BUFFER_MS: u32 = 150;
@@ -18,195 +111,1992 @@ const MIN_BUFFER_SAMPLES: usize = (SAMPLE_RATE * MIN_BUFFER_MS / 1000) as usize;
 
 #[derive(Parser)]
 #[command(name = "speakturbo")]
+#[command(group = clap::ArgGroup::new("streaming").conflicts_with_all([
+    "text", "file", "batch_dir", "output", "rtp", "serve", "waveform", "replay", "dry_run", "chime_before",
+    "chime_after",
+]))]
+#[command(group = clap::ArgGroup::new("markup").args(["url", "html"]).multiple(true))]
 #[command(about = "Ultra-fast TTS CLI")]
 #[command(version)]
 struct Args {
-    /// Text to speak
-    text: Option<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Text to speak; several texts are spoken in sequence
+    text: Vec<String>,
+
+    /// Read the text from a file ("-" for stdin); repeat for a sequence
+    #[arg(short, long, value_name = "PATH", conflicts_with = "text")]
+    file: Vec<String>,
+
+    /// Read settings from this file instead of ~/.config/speakturbo/config.toml
+    /// (see `speakturbo config show`)
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Use the config's [profile.NAME] settings over its top-level ones
+    /// (or set SPEAKTURBO_PROFILE)
+    #[arg(long, value_name = "NAME", global = true)]
+    profile: Option<String>,
+
+    /// Skip the config's [[rules]] substitutions
+    #[arg(long)]
+    no_rules: bool,
+
+    /// Print the text before and after each config rule
+    #[arg(long, conflicts_with = "no_rules")]
+    rules_debug: bool,
+
+    /// The config's rules, once loaded
+    #[arg(skip)]
+    rules: Vec<config::Rule>,
+
+    /// Send text as it is: no Unicode, invisible-character or whitespace cleanup, no abbreviations spelled out
+    #[arg(long)]
+    no_normalize: bool,
+
+    /// Turn typographic quotes, dashes and ellipses into their ASCII equivalents
+    #[arg(long, conflicts_with = "no_normalize")]
+    ascii_punctuation: bool,
+
+    /// More abbreviations to spell out, as "abbreviation<TAB>expansion" lines
+    /// ("/pattern/<TAB>expansion" for a regex); these win over the built-in ones
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["no_normalize", "ssml"], value_parser = abbrev::Table::load)]
+    abbrev_file: Option<abbrev::Table>,
+
+    /// Respellings or IPA phonemes for words the daemon gets wrong, from a TOML
+    /// or JSON file; repeat for more, later files winning
+    #[arg(long, value_name = "PATH", value_parser = lexicon::Lexicon::load)]
+    lexicon: Vec<lexicon::Lexicon>,
+
+    /// Print which lexicon words were replaced
+    #[arg(long, requires = "lexicon")]
+    lexicon_debug: bool,
+
+    /// Write numbers out as words: "3.14", "3rd", "$5.20", "1999", "14:30", "1024MB"
+    #[arg(long, conflicts_with = "ssml")]
+    normalize_numbers: bool,
+
+    /// Guess the language of each text and use the voice the config's
+    /// [voices.by-language] table gives it; --lang or --voice when unsure
+    #[arg(long)]
+    auto_voice: bool,
+
+    /// With --auto-voice, guess again for each chunk of a long text, so the
+    /// voice can change partway through
+    #[arg(long, requires = "auto_voice")]
+    auto_voice_per_chunk: bool,
+
+    /// The config's [voices.by-language], once loaded
+    #[arg(skip)]
+    by_language: Vec<(String, String)>,
+
+    /// Read the input as a script: lines like "marius: Who goes there?" are
+    /// spoken by that voice, or by the voice the config's [dialogue.cast]
+    /// gives a character; one synthesis per line (-o line-{n}.wav for a file each)
+    #[arg(long, conflicts_with_all = ["ssml", "markdown", "html", "auto_voice"])]
+    dialogue: bool,
+
+    /// With --dialogue, silence between lines unless --gap-ms says otherwise
+    #[arg(long, value_name = "MS", default_value_t = 300, value_parser = clap::value_parser!(u64).range(0..=10_000))]
+    dialogue_gap_ms: u64,
+
+    /// The config's [dialogue.cast], once loaded
+    #[arg(skip)]
+    cast: Vec<(String, String)>,
+
+    /// The config's [aliases], once loaded; --voice is already resolved
+    #[arg(skip)]
+    aliases: Vec<(String, String)>,
+
+    /// The config's [phrases], once loaded
+    #[arg(skip)]
+    phrases: Vec<config::Phrase>,
+
+    /// The config's [notifications], once loaded
+    #[cfg(feature = "dbus")]
+    #[arg(skip)]
+    notifications: config::Notifications,
+
+    /// Extra /tts parameters, from a `batch --jsonl` job
+    #[arg(skip)]
+    params: Vec<(String, String)>,
+
+    /// What to do with emoji: strip them (the default, or keep under
+    /// --no-normalize), keep them, or speak their names ("thumbs up")
+    #[arg(long, value_name = "MODE", conflicts_with = "ssml")]
+    emoji: Option<emoji::Mode>,
+
+    /// Send input that looks binary anyway, with bad bytes replaced and control characters dropped
+    #[arg(long)]
+    force_text: bool,
+
+    /// Keep terminal escape sequences (colors etc.) in text from stdin instead of removing them
+    #[arg(long)]
+    keep_ansi: bool,
+
+    /// Read the input as Markdown: no formatting marks, URLs or code blocks (automatic for .md files)
+    #[arg(long)]
+    markdown: bool,
+
+    /// Read the input as HTML: tags dropped, blocks as paragraphs; with --url, the whole page
+    #[arg(long, conflicts_with = "markdown")]
+    html: bool,
+
+    /// Send the input to the daemon as SSML markup, unchanged (must be rooted at <speak>)
+    #[arg(long, conflicts_with_all = ["markdown", "html", "url", "epub", "batch_dir", "streaming"])]
+    ssml: bool,
+
+    /// Refuse texts longer than this many characters (see --truncate)
+    #[arg(long, value_name = "N", default_value_t = 100_000)]
+    max_chars: usize,
+
+    /// With --max-chars, speak the start of a text that's too long instead of refusing it
+    #[arg(long, conflicts_with = "ssml")]
+    truncate: bool,
+
+    /// Spoken after a text cut short by --truncate
+    #[arg(long, value_name = "TEXT", default_value = "… message truncated", requires = "truncate")]
+    truncate_suffix: String,
+
+    /// Cut texts longer than this into chunks at sentence boundaries, synthesized in turn (0: never)
+    #[arg(long, value_name = "N", default_value_t = 400)]
+    chunk_chars: usize,
+
+    /// Overlap consecutive chunks by this long with an equal-power crossfade, to hide seams (0: off)
+    #[arg(long, value_name = "MS", default_value_t = 0, value_parser = clap::value_parser!(u64).range(0..=1000))]
+    crossfade_ms: u64,
+
+    /// Pause at <break time="500ms"/> and [[pause 500ms]] by synthesizing the parts separately (pauses up to 10s)
+    #[arg(long, conflicts_with_all = ["ssml", "serve"])]
+    emulate_breaks: bool,
+
+    /// With Markdown, say "code block omitted" where a code block is skipped
+    #[arg(long)]
+    announce_code: bool,
+
+    /// Fetch a web page and speak its main text
+    #[arg(long, value_name = "URL", conflicts_with_all = ["text", "file", "batch_dir", "streaming"])]
+    url: Option<String>,
+
+    /// With --url or --html, read the elements matching this CSS selector instead of guessing
+    #[arg(long, value_name = "CSS", requires = "markup", value_parser = html::Selector::parse)]
+    selector: Option<html::Selector>,
+
+    /// Speak only the lines of FILE that are new or changed since the last
+    /// --diff of it (the whole file the first time)
+    #[arg(long, value_name = "FILE",
+          conflicts_with_all = ["text", "file", "url", "epub", "ssml", "batch_dir", "streaming", "replay"])]
+    diff: Option<String>,
+
+    /// With --diff, say "new:" or "changed:" before each line
+    #[arg(long, requires = "diff")]
+    diff_labels: bool,
+
+    /// With --diff, read a file seen for the first time without speaking it
+    #[arg(long, requires = "diff")]
+    diff_initial_silent: bool,
+
+    /// With --diff, also speak the lines removed, as "removed: ..."
+    #[arg(long, requires = "diff")]
+    speak_deletions: bool,
+
+    /// With --diff, forget the snapshots of files not read for AGE (30d, 12h...)
+    #[arg(long, value_name = "AGE", default_value = "30d", value_parser = cache_cmd::parse_age)]
+    diff_keep: Duration,
+
+    /// Read an EPUB book aloud, one synthesis per chapter
+    #[arg(long, value_name = "PATH",
+          conflicts_with_all = ["text", "file", "url", "batch_dir", "streaming", "serve", "waveform", "replay"])]
+    epub: Option<String>,
+
+    /// With --epub, only these chapters, e.g. 3-5 or 1,4,7- (numbered as --list-chapters shows)
+    #[arg(long, value_name = "LIST", requires = "epub", value_parser = epub::Chapters::parse)]
+    chapters: Option<epub::Chapters>,
+
+    /// With --epub, print the chapters with their numbers and titles
+    #[arg(long, requires = "epub")]
+    list_chapters: bool,
+
+    /// Speak the text currently on the clipboard
+    #[cfg(feature = "clipboard")]
+    #[arg(long, conflicts_with_all = ["text", "file", "url", "epub", "ssml", "batch_dir", "streaming", "diff"])]
+    clipboard: bool,
+
+    /// Speak the mouse selection (X11/Wayland PRIMARY) rather than the clipboard
+    #[cfg(feature = "clipboard")]
+    #[arg(long, conflicts_with_all = ["clipboard", "text", "file", "url", "epub", "ssml", "batch_dir", "streaming", "diff"])]
+    primary_selection: bool,
 
-    #[arg(short, long, default_value = "alba")]
+    /// Speak each new clipboard value as it's copied, until Ctrl+C
+    #[cfg(feature = "clipboard")]
+    #[arg(long, group = "streaming")]
+    watch_clipboard: bool,
+
+    /// When streaming, following or watching, wait for the source to be
+    /// quiet this long, then speak what came (default 300 with
+    /// --watch-clipboard, else none)
+    #[arg(long, value_name = "MS")]
+    debounce_ms: Option<u64>,
+
+    /// What --debounce-ms speaks after a burst: all of it (concat, the
+    /// default), or only the newest item (last, the default with --watch-clipboard)
+    #[arg(long, value_enum, value_name = "STRATEGY")]
+    debounce_strategy: Option<burst::Strategy>,
+
+    /// When streaming, following or watching, speak at most N items a
+    /// minute: the rest are dropped, and counted once the rate is back under
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    max_per_minute: Option<u32>,
+
+    /// When streaming, following or watching, skip a text already spoken
+    /// in the last WINDOW (30s, 5m...; default 30s), compared once normalized
+    #[arg(long, value_name = "WINDOW", num_args = 0..=1, require_equals = true, default_missing_value = dedupe::WINDOW, value_parser = cache_cmd::parse_age)]
+    dedupe: Option<Duration>,
+
+    /// With --dedupe, say how often a text was repeated once its window closes
+    #[arg(long, requires = "dedupe")]
+    dedupe_announce: bool,
+
+    /// With --watch-clipboard or notify-listen, queue new values instead of cutting off the current one
+    #[arg(long)]
+    queue: bool,
+
+    /// Allow clipboard or selection text into the history log (kept out by default)
+    #[cfg(feature = "clipboard")]
+    #[arg(long)]
+    history_clipboard: bool,
+
+    /// Silence between items played one after another: texts, --stream-lines
+    /// records, batch jobs, --dialogue lines (default --dialogue-gap-ms) and
+    /// the parts of a long text (default 0)
+    #[arg(long, value_name = "MS")]
+    gap_ms: Option<u64>,
+
+    /// Stop a sequence at the first item that fails
+    #[arg(long)]
+    fail_fast: bool,
+
+    /// Write a sequence or batch's summary as JSON to PATH, and its failed items to failed.jsonl beside it
+    #[arg(long, value_name = "PATH")]
+    report: Option<PathBuf>,
+
+    /// Record each item of a sequence or batch in PATH as it finishes, and skip those recorded when run again
+    #[arg(long, value_name = "PATH")]
+    checkpoint: Option<PathBuf>,
+
+    /// Start over, ignoring what --checkpoint recorded
+    #[arg(long, requires = "checkpoint")]
+    restart: bool,
+
+    /// Synthesize every matching file under DIR into the -o directory
+    #[arg(long, value_name = "DIR", requires = "output",
+          conflicts_with_all = ["text", "file", "serve", "append", "waveform", "replay"])]
+    batch_dir: Option<String>,
+
+    /// With --batch-dir, descend into subdirectories
+    #[arg(long, requires = "batch_dir")]
+    recursive: bool,
+
+    /// With --batch-dir, only files matching PATTERN (*, ?, [...] and {a,b})
+    #[arg(long, value_name = "PATTERN", default_value = "*.txt", requires = "batch_dir")]
+    glob: String,
+
+    /// Read stdin line by line and speak each line as it arrives
+    #[arg(long, group = "streaming")]
+    stream_lines: bool,
+
+    /// Like --stream-lines, but records on stdin are separated by NUL bytes
+    #[arg(long, group = "streaming")]
+    stream_null: bool,
+
+    /// Speak lines appended to FILE, like `tail -f`, until Ctrl+C
+    #[arg(long, value_name = "FILE", group = "streaming")]
+    follow: Option<String>,
+
+    /// With --follow, speak the lines already in the file first
+    #[arg(long, requires = "follow")]
+    follow_from_start: bool,
+
+    /// With --follow, seconds between checks for new lines
+    #[arg(long, value_name = "SECS", default_value_t = 0.5)]
+    follow_interval: f64,
+
+    /// When streaming or following, only speak lines matching REGEX
+    #[arg(long, value_name = "REGEX", requires = "streaming", value_parser = pattern::compile)]
+    filter: Option<regex::Regex>,
+
+    /// Longest record accepted when streaming; longer ones are reported and skipped
+    #[arg(long, value_name = "SIZE", default_value = "64K", value_parser = cache_cmd::parse_size)]
+    max_record_bytes: u64,
+
+    /// Syntheses queued ahead of playback when streaming
+    #[arg(long, value_name = "N", default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..=64))]
+    max_in_flight: u32,
+
+    /// Syntheses at once: items of a sequence or batch, saved or played in order (default 1), chunks of a long text (default 2)
+    #[arg(short, long, value_parser = clap::value_parser!(u32).range(1..=32))]
+    jobs: Option<u32>,
+
+    /// Tries more for an item of a sequence or batch the daemon fails in passing; a 429 is waited out besides
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    retries: u32,
+
+    /// Audio to hold ahead of playback: samples waiting to play, and synthesized chunks waiting their turn
+    #[arg(long, value_name = "MS", default_value_t = 30_000, value_parser = clap::value_parser!(u64).range(1000..))]
+    max_buffer_ms: u64,
+
+    /// Voice, alias, or "random" or "rotate" for one of the daemon's voices
+    /// (see --voice-pool)
+    #[arg(short, long, default_value = "alba", global = true)]
     voice: String,
 
+    /// Take the closest voice to a misspelt --voice name (within two edits)
+    /// instead of refusing it
+    #[arg(long)]
+    fuzzy_voice: bool,
+
+    /// With --voice random or rotate, pick only from these voices
+    #[arg(long, value_name = "VOICES", value_delimiter = ',')]
+    voice_pool: Vec<String>,
+
+    /// With --voice random, pick the same voice for the same seed
+    #[arg(long, value_name = "N")]
+    seed: Option<u64>,
+
+    /// Use the voice the config's [voices.by-language] table gives this
+    /// language code (en = "alba", fr = "javert")
+    #[arg(long, value_name = "CODE", conflicts_with = "voice")]
+    lang: Option<String>,
+
+    /// Where the daemon listens
+    #[arg(long, value_name = "URL", default_value = DEFAULT_URL)]
+    daemon_url: String,
+
     #[arg(short, long)]
     output: Option<String>,
 
+    /// Overwrite the output file if it already exists
+    #[arg(long, requires = "output", conflicts_with_all = ["append", "skip_existing"])]
+    force: bool,
+
+    /// Append to an existing output WAV instead of replacing it
+    #[arg(long, requires = "output", conflicts_with = "skip_existing")]
+    append: bool,
+
+    /// Leave an existing output file untouched and skip synthesis
+    #[arg(long, requires = "output")]
+    skip_existing: bool,
+
+    /// Print a digest of the saved file, as it is once written
+    #[arg(long, value_enum, requires = "output", conflicts_with = "append")]
+    checksum: Option<Algorithm>,
+
+    /// Also write the checksum line to a sidecar file next to the output
+    #[arg(long, requires = "checksum")]
+    checksum_file: bool,
+
+    /// When -o is a FIFO, seconds to wait for its reader (0 waits forever)
+    #[arg(long, value_name = "SECS", default_value_t = 30.0)]
+    fifo_timeout: f64,
+
     #[arg(long)]
     list_voices: bool,
+
+    /// Stream to an RTP L16 receiver at HOST:PORT instead of playing locally
+    #[arg(long, value_name = "HOST:PORT", conflicts_with = "output")]
+    rtp: Option<String>,
+
+    /// Serve the audio over HTTP at ADDR (e.g. 0.0.0.0:8080) instead of playing it
+    #[arg(long, value_name = "ADDR", conflicts_with_all = ["output", "rtp"])]
+    serve: Option<String>,
+
+    /// Keep serving after the first transfer completes
+    #[arg(long, requires = "serve")]
+    serve_keep: bool,
+
+    /// Render a peak waveform of the audio to this PNG
+    #[arg(long, value_name = "PNG", conflicts_with = "serve")]
+    waveform: Option<String>,
+
+    /// Waveform image size
+    #[arg(long, value_name = "WxH", default_value = "800x200", value_parser = waveform::parse_size)]
+    waveform_size: (u32, u32),
+
+    /// Print transfer statistics when done
+    #[arg(long)]
+    stats: bool,
+
+    /// Show what would be synthesized and where, without doing it
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Speaking rate assumed by --dry-run when the daemon can't estimate,
+    /// and by --karaoke when it doesn't say how long the audio is
+    #[arg(long, default_value_t = 15.0, value_name = "N")]
+    chars_per_second: f64,
+
+    /// Show the text while it plays, the sentence being spoken highlighted
+    #[arg(long, conflicts_with_all = ["output", "rtp", "serve", "ssml"])]
+    karaoke: bool,
+
+    /// Save where playback stops (Ctrl+C, or q at the terminal) as NAME, to
+    /// go on from there with --resume NAME; reading to the end removes it
+    #[arg(long, value_name = "NAME", value_parser = bookmark::name,
+          conflicts_with_all = ["output", "rtp", "serve", "streaming", "batch_dir", "epub", "replay", "when_done"])]
+    bookmark: Option<String>,
+
+    /// Start from the sentence bookmark NAME stopped at, and keep the
+    /// bookmark moving (see `speakturbo bookmarks`)
+    #[arg(long, value_name = "NAME", value_parser = bookmark::name,
+          conflicts_with_all = ["output", "rtp", "serve", "streaming", "batch_dir", "epub", "replay", "when_done"])]
+    resume: Option<String>,
+
+    /// With --resume, start from the sentence nearest the bookmark even
+    /// though the text has changed since
+    #[arg(long, requires = "resume")]
+    resume_force: bool,
+
+    /// Print a line when this many minutes of playback are left (once the length is known)
+    #[arg(long, value_name = "MINUTES", value_parser = clap::value_parser!(u64).range(1..=600))]
+    announce_remaining: Option<u64>,
+
+    /// Expect other invocations to play at the same time: when one is
+    /// playing as this one starts, play 4.5 dB quieter so the sum doesn't clip
+    #[arg(long, conflicts_with_all = ["output", "rtp", "serve"])]
+    mix: bool,
+
+    /// Wait for a turn among other invocations given --priority: higher
+    /// levels first, then the earliest, the low ones moving up the longer
+    /// they wait. urgent cuts off whatever is playing unless it's urgent too
+    #[arg(long, value_enum, value_name = "LEVEL", conflicts_with_all = ["output", "rtp", "serve"])]
+    priority: Option<players::Priority>,
+
+    /// Shell command to run after playback or saving finishes
+    #[arg(long, value_name = "CMD")]
+    on_complete: Option<String>,
+
+    /// Shell command to run instead of --on-complete when speaking fails
+    #[arg(long, value_name = "CMD")]
+    on_error: Option<String>,
+
+    /// Exit non-zero when a hook command fails
+    #[arg(long)]
+    strict_hooks: bool,
+
+    /// Run the command given after -- with the terminal to itself, then
+    /// say that LABEL finished, failed or was terminated, and how long it took
+    #[arg(long, value_name = "LABEL", conflicts_with_all = ["file", "batch_dir", "output", "streaming", "replay"])]
+    when_done: Option<String>,
+
+    /// What --when-done says; {label}, {outcome} and {duration} are filled in
+    #[arg(long, value_name = "TEMPLATE", default_value = "{label} {outcome} after {duration}")]
+    when_done_message: String,
+
+    /// With --when-done, the voice that says a failure
+    #[arg(long, value_name = "VOICE", requires = "when_done")]
+    failure_voice: Option<String>,
+
+    /// With --when-done, a chime before a failure: built-in, or --failure-chime=PATH for a WAV
+    #[arg(long, value_name = "PATH", num_args = 0..=1, require_equals = true, requires = "when_done")]
+    failure_chime: Option<Option<String>>,
+
+    /// With --when-done, say nothing for a command done in under SECS
+    #[arg(long, value_name = "SECS", default_value_t = 0.0, requires = "when_done")]
+    min_duration: f64,
+
+    /// Play a chime before speaking: built-in, or --chime-before=PATH for a WAV
+    #[arg(long, value_name = "PATH", num_args = 0..=1, require_equals = true, conflicts_with_all = ["rtp", "serve"])]
+    chime_before: Option<Option<String>>,
+
+    /// Play a chime after speaking: built-in, or --chime-after=PATH for a WAV
+    #[arg(long, value_name = "PATH", num_args = 0..=1, require_equals = true, conflicts_with_all = ["rtp", "serve"])]
+    chime_after: Option<Option<String>>,
+
+    /// With -o, write the chimes into the saved file too
+    #[arg(long, requires = "output")]
+    chime_in_file: bool,
+
+    /// With -o, put --gap-ms between the parts of a long text in the saved
+    /// file too (a --dialogue script's gaps are always saved)
+    #[arg(long, requires = "output")]
+    gap_in_file: bool,
+
+    /// Show a desktop notification when done or on failure
+    #[cfg(feature = "notify")]
+    #[arg(long)]
+    notify: bool,
+
+    /// Print machine-readable JSON to stdout
+    #[arg(long, global = true)]
+    json: bool,
     
-    /// Quiet mode - minimal output
-    #[arg(short, long)]
-    quiet: bool,
+    /// Play a recent utterance again without the daemon (-1 last, -2 the one before, ...)
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "-1", allow_negative_numbers = true,
+          conflicts_with_all = ["text", "output", "rtp", "serve"])]
+    replay: Option<i32>,
+
+    /// Don't keep this utterance for --replay (for sensitive text)
+    #[arg(long)]
+    no_record: bool,
+
+    /// Log this invocation to the history (see `speakturbo history`)
+    #[arg(long)]
+    history: bool,
+
+    /// Keep this invocation out of the history log
+    #[arg(long)]
+    no_history: bool,
+
+    /// Don't read or write the local audio cache
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Only play cached audio; fail instead of contacting the daemon
+    #[arg(long, conflicts_with_all = ["no_cache", "serve"])]
+    cache_only: bool,
+
+    /// Cache size cap in MB; least recently used entries go first
+    #[arg(long, value_name = "MB", default_value_t = 256)]
+    cache_max_mb: u64,
+
+    /// Quiet mode - -q leaves warnings and errors, -qq only the exit status
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    quiet: u8,
+
+    /// Mark lines in ASCII (*, >, ok, x, ->) instead of ⚡, ▶, ✓, ✗ and →
+    #[arg(long, global = true)]
+    no_emoji: bool,
+
+    /// Log what happens along the way to stderr: the request, playback and
+    /// decisions such as the language --auto-voice detected; twice, each
+    /// network read too. SPEAKTURBO_LOG takes a filter instead, such as
+    /// "info,net=debug"
+    #[arg(long, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// How to write --verbose's log lines
+    #[arg(long, value_enum, value_name = "FORMAT", default_value = "text")]
+    log_format: logging::Format,
+
+    /// Also append events to FILE as JSON lines, at info and above whatever
+    /// --verbose or --quiet say; several invocations can share it
+    #[arg(long, value_name = "FILE")]
+    log_file: Option<PathBuf>,
+
+    /// Rotate --log-file to FILE.1 once it would grow past SIZE
+    #[arg(long, value_name = "SIZE", default_value = "10M", value_parser = cache_cmd::parse_size)]
+    log_max_size: u64,
+
+    /// Rotated log files to keep, FILE.1 being the newest
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    log_keep: u32,
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+#[derive(Subcommand)]
+enum Command {
+    /// Manage the local audio cache
+    Cache {
+        #[command(subcommand)]
+        action: cache_cmd::Action,
+    },
+
+    /// Ask the daemon how it is
+    Daemon {
+        #[command(subcommand)]
+        action: daemon_cmd::Action,
+    },
+
+    /// Show what was spoken with --history, or play an entry again
+    #[command(args_conflicts_with_subcommands = true)]
+    History {
+        #[command(subcommand)]
+        action: Option<history::Action>,
+
+        /// Show at most this many of the latest entries
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+
+        /// Only entries whose text contains PATTERN (case-insensitive)
+        #[arg(long, value_name = "PATTERN")]
+        grep: Option<String>,
+    },
+
+    /// List the positions saved with --bookmark, or delete some
+    #[command(args_conflicts_with_subcommands = true)]
+    Bookmarks {
+        #[command(subcommand)]
+        action: Option<bookmark::Action>,
+    },
+
+    /// Play saved WAV files through the usual playback, one after another (--gap-ms between)
+    Play {
+        #[arg(value_name = "FILE", required = true)]
+        files: Vec<String>,
+    },
+
+    /// Print a completion script: `source <(speakturbo completions bash)`
+    Completions {
+        shell: completions::Shell,
+    },
+
+    /// Values for completion scripts, one per line
+    #[command(name = "__complete", hide = true)]
+    Complete {
+        what: completions::Dynamic,
+    },
+
+    /// Show the settings in effect
+    Config {
+        #[command(subcommand)]
+        action: settings::Action,
+    },
+
+    /// Hear a sample phrase in each voice, one after another
+    Preview {
+        /// Voices or aliases to hear (default: all the daemon has)
+        #[arg(value_name = "VOICE")]
+        voices: Vec<String>,
+
+        /// The phrase to speak
+        #[arg(long, default_value = preview::SAMPLE)]
+        text: String,
+
+        /// Save one file per voice in DIR, named after the voice, instead of playing
+        #[arg(short, long, value_name = "DIR")]
+        output: Option<PathBuf>,
+
+        /// Silence between samples
+        #[arg(long, value_name = "MS", default_value_t = 500)]
+        gap_ms: u64,
+    },
+
+    /// Hear one text in several voices, a label before each, and how fast each answered
+    Compare {
+        /// The text to speak
+        #[arg(value_name = "TEXT")]
+        text: String,
+
+        /// Voices or aliases to compare, comma-separated
+        #[arg(long, value_name = "LIST", value_delimiter = ',', required = true)]
+        voices: Vec<String>,
+
+        /// Print each label instead of speaking it
+        #[arg(long)]
+        print_labels: bool,
+
+        /// Play in a random order as "Sample 1", "Sample 2"..., naming the voices only at the end
+        #[arg(long)]
+        randomize: bool,
+
+        /// Also save each voice's take in DIR, named after the voice
+        #[arg(short, long, value_name = "DIR")]
+        output: Option<PathBuf>,
+    },
+
+    /// Speak phrases from the config's [phrases] by name, in order
+    Say {
+        #[arg(value_name = "NAME", required_unless_present = "list")]
+        names: Vec<String>,
+
+        /// List the phrases instead
+        #[arg(long, conflicts_with = "names")]
+        list: bool,
+    },
+
+    /// Time repeated syntheses of a text, as speaking it would make them
+    Bench {
+        /// Runs to time
+        #[arg(long, value_name = "N", default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+        iterations: u32,
+
+        /// Runs before those, left out of the numbers
+        #[arg(long, value_name = "N", default_value_t = 1)]
+        warmup: u32,
+
+        /// The text to synthesize
+        #[arg(long, default_value = preview::SAMPLE)]
+        text: String,
+
+        /// Read the audio without playing it
+        #[arg(long)]
+        no_play: bool,
+
+        /// Runs in flight at once, to see what the daemon keeps up with
+        #[arg(long, value_name = "N", default_value_t = 1, requires = "no_play", value_parser = clap::value_parser!(u32).range(1..=64))]
+        concurrency: u32,
+    },
+
+    /// Run a command, pass its output through, and say how it ended
+    Exec {
+        /// The command and its arguments, after --
+        #[arg(value_name = "COMMAND", required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+
+        /// Also speak the output lines matching REGEX as they appear
+        #[arg(long, value_name = "REGEX", value_parser = pattern::compile)]
+        speak_lines: Option<regex::Regex>,
+    },
+
+    /// Synthesize each row of a CSV: a text column, and optional voice,
+    /// output and style ones (a style is a [profile.NAME] of the config)
+    Batch {
+        #[arg(value_name = "MANIFEST", required_unless_present = "jsonl")]
+        manifest: Option<PathBuf>,
+
+        /// Jobs as JSON Lines instead, from FILE or stdin: {"text", "voice",
+        /// "output", "params": {...}}, other fields going to the daemon too;
+        /// a result line is printed as each one finishes
+        #[arg(long, value_name = "FILE", num_args = 0..=1, default_missing_value = "-", conflicts_with = "manifest")]
+        jsonl: Option<PathBuf>,
+    },
+
+    /// Save to files without playing anything or opening an audio device:
+    /// text files ("-" for stdin), a directory of them, or a CSV or JSON
+    /// Lines manifest as `batch` takes
+    Convert {
+        #[arg(value_name = "INPUT", required = true)]
+        inputs: Vec<String>,
+
+        /// A file, a template numbering several inputs as the top-level -o does, or a directory for a directory
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<String>,
+    },
+
+    /// Speak desktop notifications as they appear, until Ctrl+C; the
+    /// config's [notifications] picks the apps
+    #[cfg(feature = "dbus")]
+    NotifyListen {
+        /// What to say, with {app}, {summary} and {body} (default: the config's, or "{app}: {summary}. {body}")
+        #[arg(long, value_name = "TEMPLATE")]
+        template: Option<String>,
+
+        /// Leave out notifications less urgent than this
+        #[arg(long, value_enum, value_name = "LEVEL", default_value = "low")]
+        min_urgency: notify_listen::Urgency,
+
+        /// Speak identical notifications this close together once
+        #[arg(long, value_name = "MS", default_value_t = 3000)]
+        dedupe_ms: u64,
+    },
+
+    /// Check the whole pipeline against a mock daemon in this process
+    SelfTest {
+        /// Stop short of playing the audio
+        #[arg(long)]
+        no_audio: bool,
+    },
+}
+
+fn main() {
+    let argv: Vec<std::ffi::OsString> = std::env::args_os().collect();
+    let given = Args::parse_from(&argv);
     let start = Instant::now();
+    if let Err(e) = run(&argv, &given, start) {
+        // Settled already unless it failed first
+        reporter::init(given.quiet, given.no_emoji);
+        // Reported already if it got as far as speaking
+        if given.json && !report::printed() {
+            json_report(&given, "", Err(&e), SynthesisStats::default(), start).print();
+        }
+        let kind = exit::Kind::of(&e);
+        if !logging::errors_as_json() {
+            reporter::error(format_args!("Error: {:#}", e));
+        }
+        logging::error(format_args!("{:#}", e));
+        std::process::exit(kind.code());
+    }
+}
+
+fn run(argv: &[std::ffi::OsString], given: &Args, start: Instant) -> Result<()> {
+    let config = config::load(given.config.as_deref()).map_err(|e| exit::fail(exit::Kind::Usage, format!("{:#}", e)))?;
+    let profile = settings::profile(given, |name| std::env::var(name).ok());
+    let flags = config.flags(profile.as_ref().map(|(name, _)| name.as_str())).map_err(|e| exit::fail(exit::Kind::Usage, e))?;
+    let settings::Resolved { mut args, values } = settings::resolve(argv, &flags, |name| std::env::var(name).ok())?;
+    // The self-test brings its own daemon
+    if !matches!(args.command, Some(Command::SelfTest { .. })) {
+        let _ = DAEMON.set(args.daemon_url.trim_end_matches('/').to_string());
+    }
+    reporter::init(args.quiet, args.no_emoji);
+    if let Some(minutes) = args.announce_remaining {
+        progress::announce_remaining(Duration::from_secs(minutes * 60));
+    }
+    if args.mix {
+        playback::mix();
+    }
+    if let Some(priority) = args.priority {
+        playback::queue(priority);
+    }
+    let filter = logging::Filter::new(args.verbose, std::env::var("SPEAKTURBO_LOG").ok().as_deref(), args.quiet);
+    let log_file = match &args.log_file {
+        Some(path) => Some(
+            logging::LogFile::open(path, args.log_max_size, args.log_keep)
+                .map_err(|e| exit::fail(exit::Kind::Usage, format!("Cannot open log file {}: {}", path.display(), e)))?,
+        ),
+        None => None,
+    };
+    logging::init(filter.map_err(|e| exit::fail(exit::Kind::Usage, e))?, args.log_format, log_file, start);
+    for warning in &config.warnings {
+        reporter::warning(warning);
+    }
+    stream::warn_unless_streaming(&args, streaming(&args));
+    if matches!(args.command, Some(Command::Convert { .. })) {
+        convert::rewrite(&mut args, &values)?;
+    }
+
+    match &args.command {
+        Some(Command::Completions { shell }) => {
+            print!("{}", completions::script(*shell));
+            return Ok(());
+        }
+        Some(Command::Complete { what }) => {
+            completions::complete(*what, &config.aliases, history::state_dir().as_deref());
+            return Ok(());
+        }
+        Some(Command::Config { action: settings::Action::Show }) => {
+            return settings::show(&values, config.path.as_deref(), profile.as_ref(), args.json);
+        }
+        Some(Command::Cache { action }) => {
+            return cache_cmd::run(action, args.cache_max_mb * 1024 * 1024, args.json);
+        }
+        Some(Command::Daemon { action }) => return daemon_cmd::run(action, &client(), args.json),
+        Some(Command::History { action: Some(history::Action::Play { id }), .. }) => {
+            let (entry, audio) = history::open(*id, open_cache(&args).as_ref())?;
+            reporter::info(format_args!("Playing #{} ({}): {}", entry.id, entry.voice, text::excerpt(&entry.text)));
+            return play_again(audio, start);
+        }
+        Some(Command::History { action: None, limit, grep }) => {
+            return history::list(*limit, grep.as_deref(), args.json);
+        }
+        Some(Command::Play { files }) => return play::run(&args, files, start),
+        Some(Command::Bookmarks { action }) => return bookmark::run(action.as_ref(), args.json),
+        Some(Command::SelfTest { no_audio }) => {
+            let play = !*no_audio;
+            return self_test::run(&mut args, play);
+        }
+        Some(Command::Say { list: true, .. }) => {
+            phrases::list(&config.phrases, args.json);
+            return Ok(());
+        }
+        Some(Command::Preview { .. } | Command::Compare { .. } | Command::Say { .. } | Command::Bench { .. } | Command::Exec { .. }) | None => {}
+        Some(Command::Batch { .. }) => {}
+        #[cfg(feature = "dbus")]
+        Some(Command::NotifyListen { .. }) => {}
+        Some(Command::Convert { .. }) => unreachable!("rewritten above"),
+    }
+
+    // A manifest's styles are profiles, settled as the invocation's were
+    let for_styles = matches!(args.command, Some(Command::Batch { .. })).then(|| config.clone());
+    if let Err(e) = prepare(&mut args, config) {
+        if args.json {
+            json_report(&args, "", Err(&e), SynthesisStats::default(), start).print();
+        }
+        return Err(e);
+    }
+
+    match args.command.take() {
+        Some(Command::Preview { voices, text, output, gap_ms }) => {
+            args.gap_ms = Some(gap_ms);
+            return preview::run(&mut args, &voices, &text, output.as_deref(), start);
+        }
+        Some(Command::Compare { text, voices, print_labels, randomize, output }) => {
+            let options = compare::Options { voices: &voices, print_labels, randomize, dir: output.as_deref() };
+            return compare::run(&mut args, &text, options, start);
+        }
+        Some(Command::Say { names, .. }) => return phrases::run(&mut args, &names, start),
+        Some(Command::Bench { iterations, warmup, text, no_play, concurrency }) => {
+            let options = bench::Options {
+                iterations: iterations as usize,
+                warmup: warmup as usize,
+                concurrency: concurrency as usize,
+                play: !no_play,
+            };
+            return bench::run(&mut args, &text, options);
+        }
+        Some(Command::Exec { command, speak_lines }) => return exec::run(&args, &command, speak_lines.as_ref(), start),
+        #[cfg(feature = "dbus")]
+        Some(Command::NotifyListen { template, min_urgency, dedupe_ms }) => {
+            let options = notify_listen::Options { template, min_urgency, dedupe: Duration::from_millis(dedupe_ms) };
+            return notify_listen::run(&args, &options, start);
+        }
+        Some(Command::Batch { manifest, jsonl }) => {
+            let config = for_styles.unwrap_or_default();
+            let invoked = profile.map(|(name, _)| name);
+            let styled = |style: Option<&str>| -> Result<Args> {
+                let flags = config.flags(style.or(invoked.as_deref())).map_err(|e| exit::fail(exit::Kind::Usage, e))?;
+                let mut styled = settings::resolve(argv, &flags, |name| std::env::var(name).ok())?.args;
+                prepare(&mut styled, config.clone())?;
+                Ok(styled)
+            };
+            let source = match (manifest, jsonl) {
+                (_, Some(path)) => manifest::Source::JsonLines(path),
+                (Some(path), None) => manifest::Source::Csv(path),
+                (None, None) => unreachable!("clap requires one"),
+            };
+            return manifest::run(&args, &source, &styled, start);
+        }
+        _ => {}
+    }
 
     if args.list_voices {
-        println!("Voices: alba, marius, javert, jean, fantine, cosette, eponine, azelma");
+        println!("Voices: {}", VOICES.join(", "));
+        if !args.aliases.is_empty() {
+            let aliases: Vec<String> = args.aliases.iter().map(|(alias, voice)| format!("{} → {}", alias, voice)).collect();
+            println!("Aliases: {}", aliases.join(", "));
+        }
         return Ok(());
     }
 
-    let text = match args.text {
-        Some(t) => t,
-        None => {
-            let mut buf = String::new();
-            std::io::stdin().read_to_string(&mut buf)?;
-            buf
+    if let Some(label) = args.when_done.take() {
+        return exec::when_done(&mut args, &label, start);
+    }
+
+    if let Some(back) = args.replay {
+        let (recorded, audio) = replay::open(back.unsigned_abs() as usize)?;
+        reporter::info(format_args!("Replaying ({}): {}", recorded.voice, text::excerpt(&recorded.text)));
+        return play_again(Box::new(audio), start);
+    }
+
+    // What a single text's playback or saving saw, for --json
+    let mut measured = SynthesisStats::default();
+    let mut checksum = None;
+    let (text, mut result) = if let Some(dir) = &args.batch_dir {
+        (String::new(), batch::run(&args, dir, start))
+    } else if let Some(path) = &args.epub {
+        (String::new(), epub::run(&args, path, start))
+    } else if let Some(path) = &args.follow {
+        let interval = Duration::from_secs_f64(args.follow_interval.max(0.01));
+        let result = follow::lines(path, args.follow_from_start, interval, args.max_record_bytes as usize, args.force_text)
+            .and_then(|records| stream::run(&args, records, false, start));
+        (String::new(), result)
+    } else if let Some(path) = &args.diff {
+        let options = diff::Options {
+            labels: args.diff_labels,
+            initial_silent: args.diff_initial_silent,
+            deletions: args.speak_deletions,
+            keep: args.diff_keep,
+            force_text: args.force_text,
+        };
+        let suffix = args.truncate.then_some(args.truncate_suffix.as_str());
+        match diff::read(path, &options) {
+            Ok(Some(changes)) => {
+                let text = normalized(&args, changes.text.clone());
+                let result = text::limit(text.clone(), args.max_chars, suffix)
+                    .and_then(|limited| speak(&args, &limited, args.output.as_deref(), existing_policy(&args), start))
+                    .map(|spoken| (measured, checksum) = spoken);
+                // Until spoken in full, the changes are spoken again next time
+                let result = match interrupt::requested() || args.dry_run {
+                    true => result,
+                    false => result.and_then(|()| changes.save()),
+                };
+                (text, result)
+            }
+            Ok(None) => (String::new(), Ok(())),
+            Err(e) => (String::new(), Err(e)),
+        }
+    } else if let Some(result) = watch_clipboard(&args, start) {
+        (String::new(), result)
+    } else if args.stream_lines || args.stream_null {
+        let stdin = std::io::BufReader::new(std::io::stdin());
+        let delimiter = if args.stream_null { b'\0' } else { b'\n' };
+        let max = args.max_record_bytes as usize;
+        let records = stream::records(stdin, delimiter, max, !args.keep_ansi, args.force_text);
+        (String::new(), stream::run(&args, records, false, start))
+    } else {
+        match read_items(&args) {
+            Ok(items) => {
+                // A dialogue saved to line-{n}.wav is a sequence of its lines
+                let per_line = args.dialogue && args.output.as_ref().is_some_and(|o| o.contains("{n}"));
+                let lines: Vec<String> = match per_line {
+                    true => items.iter().flat_map(|text| dialogue::lines(text)).collect(),
+                    false => Vec::new(),
+                };
+                let result = match items.as_slice() {
+                    _ if per_line => sequence::run(&args, &lines, start),
+                    [text] => speak(&args, text, args.output.as_deref(), existing_policy(&args), start).map(|spoken| (measured, checksum) = spoken),
+                    _ if args.bookmark.is_some() || args.resume.is_some() => {
+                        Err(exit::fail(exit::Kind::Usage, "--bookmark and --resume read a single text"))
+                    }
+                    _ => sequence::run(&args, &items, start),
+                };
+                (items.join("\n\n"), result)
+            }
+            Err(e) => (String::new(), Err(e)),
+        }
+    };
+
+    let status = match &result {
+        _ if interrupt::requested() => hooks::Status::Interrupted,
+        Ok(()) => hooks::Status::Ok,
+        Err(_) => hooks::Status::Error,
+    };
+    let hook = match status {
+        hooks::Status::Error => args.on_error.as_ref().or(args.on_complete.as_ref()),
+        _ => args.on_complete.as_ref(),
+    };
+    let hook_ok = match hook {
+        Some(cmd) => {
+            let outcome = hooks::Outcome {
+                status,
+                elapsed: start.elapsed(),
+                output: args.output.as_deref(),
+                voice: &args.voice,
+                error: result.as_ref().err().map(|e| format!("{:#}", e)),
+            };
+            let label = if status == hooks::Status::Error && args.on_error.is_some() {
+                "on-error"
+            } else {
+                "on-complete"
+            };
+            hooks::run(label, cmd, &outcome)
+        }
+        None => true,
+    };
+    // A failure already says what went wrong, and ran --on-error besides
+    if !hook_ok && args.strict_hooks && result.is_ok() {
+        result = Err(exit::fail(exit::Kind::Hook, "The on-complete hook failed (--strict-hooks)"));
+    }
+
+    #[cfg(feature = "notify")]
+    if args.notify {
+        let error = result.as_ref().err().map(|e| format!("{:#}", e));
+        let summary = notify::summary(status, args.output.as_deref(), start.elapsed(), error.as_deref());
+        notify::send(&summary, &text::excerpt(&text));
+    }
+    #[cfg(not(feature = "notify"))]
+    let _ = &text;
+
+    if args.history && !args.no_history && !text.trim().is_empty() && !keep_out_of_history(&args) {
+        if let Err(e) = history::append(&text, &args.voice, status, start.elapsed()) {
+            reporter::warning(format_args!("not kept in the history: {:#}", e));
+        }
+    }
+
+    if args.json && !report::printed() {
+        let mut report = json_report(&args, &text, result.as_ref().map(|_| ()), measured, start);
+        report.checksum = checksum;
+        if status == hooks::Status::Interrupted {
+            report.status = report::Status::Interrupted;
+            report.kind = Some(exit::Kind::Interrupted);
+        }
+        report.print();
+    }
+
+    if status == hooks::Status::Interrupted {
+        std::process::exit(exit::Kind::Interrupted.code());
+    }
+    result
+}
+
+/// Takes the config's rules, voices and aliases into `args`, and settles
+/// --voice on one the daemon has.
+fn prepare(args: &mut Args, config: config::Config) -> Result<()> {
+    if !args.no_rules {
+        args.rules = config.rules;
+    }
+    args.by_language = config.by_language;
+    args.cast = config.cast;
+    args.voice = config::resolve(&config.aliases, &args.voice).to_string();
+    for voice in &mut args.voice_pool {
+        *voice = config::resolve(&config.aliases, voice).to_string();
+    }
+    args.aliases = config.aliases;
+    args.phrases = config.phrases;
+    #[cfg(feature = "dbus")]
+    {
+        args.notifications = config.notifications;
+    }
+    if args.auto_voice && args.by_language.is_empty() {
+        return Err(exit::fail(exit::Kind::Usage, "--auto-voice needs a [voices.by-language] table in the config, such as fr = \"javert\""));
+    }
+    if let Some(code) = &args.lang {
+        let voice = language::configured(code, &args.by_language).map_err(|e| exit::fail(exit::Kind::Usage, e))?.to_string();
+        if let Some(known) = daemon_voices().filter(|known| !known.contains(&voice)) {
+            let message = format!("[voices.by-language] gives '{}' the voice {:?}, which the daemon doesn't have ({})", code, voice, known.join(", "));
+            return Err(exit::fail(exit::Kind::Usage, message));
+        }
+        args.voice = voice;
+    }
+    if (args.voice == "random" || args.voice == "rotate") && !args.list_voices {
+        let Some(voices) = daemon_voices() else {
+            let message = format!("--voice {} needs the daemon's list of voices, and it didn't answer (is it running?)", args.voice);
+            return Err(exit::fail(exit::Kind::DaemonUnreachable, message));
+        };
+        let candidates = pick::candidates(&voices, &args.voice_pool).map_err(|e| exit::fail(exit::Kind::Usage, e.to_string()))?;
+        let voice = match args.voice.as_str() {
+            "random" => pick::random(&candidates, args.seed)?,
+            _ => {
+                let counter = history::state_dir().context("No state directory: set XDG_STATE_HOME or HOME")?;
+                pick::rotate(&candidates, &counter.join("voice-rotation"))?
+            }
+        };
+        reporter::info(format_args!("Voice: {} ({})", voice, args.voice));
+        args.voice = voice;
+    }
+    // Names we know are taken as they are; others are checked with the
+    // daemon, when it answers, for a suggestion rather than its error
+    if !VOICES.contains(&args.voice.as_str()) && !args.list_voices {
+        if let Some(known) = daemon_voices() {
+            args.voice = voices::matching(&args.voice, &known, args.fuzzy_voice).map_err(|e| exit::fail(exit::Kind::Usage, e))?.to_string();
+        }
+    }
+    Ok(())
+}
+
+/// --json's report on the whole invocation, when it isn't a sequence
+fn json_report(args: &Args, text: &str, result: Result<(), &anyhow::Error>, measured: SynthesisStats, start: Instant) -> report::Report {
+    report::Report {
+        status: if result.is_ok() { report::Status::Ok } else { report::Status::Error },
+        voice: args.voice.clone(),
+        text_chars: text.chars().count(),
+        elapsed: start.elapsed(),
+        output: args.output.clone(),
+        checksum: None,
+        source: None,
+        error: result.err().map(|e| format!("{:#}", e)),
+        kind: result.err().map(exit::Kind::of),
+        measured,
+    }
+}
+
+fn open_cache(args: &Args) -> Option<Cache> {
+    match args.no_cache {
+        true => None,
+        false => cache_cmd::config(args.cache_max_mb * 1024 * 1024).map(Cache::new),
+    }
+}
+
+/// Play already-synthesized audio (--replay, history play).
+fn play_again(audio: AudioStream, start: Instant) -> Result<()> {
+    interrupt::install();
+    stream_audio(audio, Shown::Position(None), start, false, None, Chimes::default(), usize::MAX)?;
+    if interrupt::requested() {
+        std::process::exit(exit::Kind::Interrupted.code());
+    }
+    Ok(())
+}
+
+/// Run --watch-clipboard if it was asked for (and built in).
+fn watch_clipboard(args: &Args, start: Instant) -> Option<Result<()>> {
+    #[cfg(feature = "clipboard")]
+    if args.watch_clipboard {
+        let records = clipboard::watch();
+        return Some(records.and_then(|records| stream::run(args, records, !args.queue, start)));
+    }
+    let _ = (args, start);
+    None
+}
+
+/// Whether `args` ask for records spoken as they come, where pacing them
+/// means something.
+fn streaming(args: &Args) -> bool {
+    #[cfg(feature = "clipboard")]
+    let watching = args.watch_clipboard;
+    #[cfg(not(feature = "clipboard"))]
+    let watching = false;
+    #[cfg(feature = "dbus")]
+    let listening = matches!(args.command, Some(Command::NotifyListen { .. }));
+    #[cfg(not(feature = "dbus"))]
+    let listening = false;
+    let speak_lines = matches!(args.command, Some(Command::Exec { speak_lines: Some(_), .. }));
+    args.stream_lines || args.stream_null || args.follow.is_some() || watching || listening || speak_lines
+}
+
+/// Clipboard text may be a password or similar: logged only on request.
+fn keep_out_of_history(args: &Args) -> bool {
+    #[cfg(feature = "clipboard")]
+    return (args.clipboard || args.primary_selection) && !args.history_clipboard;
+    #[cfg(not(feature = "clipboard"))]
+    {
+        let _ = args;
+        false
+    }
+}
+
+/// The texts to speak, in order: positional texts, else -f files, else
+/// a web page or the clipboard, else stdin. Each is held to --max-chars.
+fn read_items(args: &Args) -> Result<Vec<String>> {
+    let suffix = args.truncate.then_some(args.truncate_suffix.as_str());
+    read_texts(args)?
+        .into_iter()
+        .map(|text| text::limit(text, args.max_chars, suffix))
+        .collect()
+}
+
+fn read_texts(args: &Args) -> Result<Vec<String>> {
+    if let Some(url) = &args.url {
+        return Ok(vec![normalized(args, url::read(url, args.selector.as_ref(), args.html)?)]);
+    }
+    #[cfg(feature = "clipboard")]
+    if args.clipboard || args.primary_selection {
+        let selection = match args.primary_selection {
+            true => clipboard::Selection::Primary,
+            false => clipboard::Selection::Clipboard,
+        };
+        return Ok(vec![normalized(args, clipboard::read(selection)?)]);
+    }
+    let items = if !args.text.is_empty() {
+        args.text.clone()
+    } else if !args.file.is_empty() {
+        args.file.iter().map(|path| read_file(path, args.force_text)).collect::<Result<_>>()?
+    } else {
+        vec![read_file("-", args.force_text)?]
+    };
+    if args.ssml {
+        // Markup goes out as written: no stripping or normalization, only
+        // lexicon words
+        for (i, text) in items.iter().enumerate() {
+            let source = match args.file.get(i) {
+                Some(path) if path != "-" => path.clone(),
+                _ if !args.text.is_empty() => format!("item {}", i + 1),
+                _ => "stdin".to_string(),
+            };
+            ssml::validate(text).map_err(|e| exit::fail(exit::Kind::Input, format!("Invalid SSML in {}: {:#}", source, e)))?;
+        }
+        return Ok(items.iter().map(|text| lexicon::apply(text, &args.lexicon, true, args.lexicon_debug)).collect());
+    }
+    let items: Vec<String> = items
+        .into_iter()
+        .enumerate()
+        .map(|(i, text)| {
+            let path = args.file.get(i);
+            let from_stdin = args.text.is_empty() && path.is_none_or(|p| p == "-");
+            let text = match from_stdin && !args.keep_ansi {
+                true => ansi::strip(&text),
+                false => text,
+            };
+            let text = if args.markdown || path.is_some_and(|p| markdown::is_markdown_path(p)) {
+                markdown::to_speech(&text, args.announce_code)
+            } else if args.html {
+                html::to_speech(&text, args.selector.as_ref())
+                    .ok_or_else(|| exit::fail(exit::Kind::Input, "Nothing in the input matches the selector"))?
+            } else {
+                text
+            };
+            Ok(match args.dialogue {
+                true => dialogue::normalize_lines(&text, |line| normalized(args, line)),
+                false => normalized(args, text),
+            })
+        })
+        .collect::<Result<_>>()?;
+
+    for (i, text) in items.iter().enumerate() {
+        if text.trim().is_empty() {
+            match (items.len(), args.file.get(i)) {
+                (1, _) => return Err(exit::fail(exit::Kind::Input, "No text")),
+                (_, Some(path)) => return Err(exit::fail(exit::Kind::Input, format!("No text in {}", path))),
+                (_, None) => return Err(exit::fail(exit::Kind::Input, format!("No text in item {}", i + 1))),
+            }
         }
+    }
+    Ok(items)
+}
+
+/// `text` cleaned up for speaking (see normalize.rs), unless --no-normalize.
+fn normalized(args: &Args, text: String) -> String {
+    let text = config::apply(text, &args.rules, args.rules_debug);
+    let default = match args.no_normalize {
+        true => emoji::Mode::Keep,
+        false => emoji::Mode::Strip,
+    };
+    let text = emoji::handle(&text, args.emoji.unwrap_or(default));
+    let text = match args.no_normalize {
+        true => text,
+        false => normalize::normalize(&text, args.ascii_punctuation),
+    };
+    let text = lexicon::apply(&text, &args.lexicon, false, args.lexicon_debug);
+    let text = match args.no_normalize {
+        true => text,
+        false => abbrev::expand(&text, args.abbrev_file.as_ref()),
     };
+    match args.normalize_numbers {
+        true => numbers::expand(&text, &numbers::ENGLISH),
+        false => text,
+    }
+}
 
-    if text.trim().is_empty() {
-        eprintln!("Error: No text");
-        std::process::exit(1);
+fn read_file(path: &str, force_text: bool) -> Result<String> {
+    if path == "-" {
+        let mut buf = Vec::new();
+        std::io::stdin().read_to_end(&mut buf)?;
+        return text::decode(buf, "stdin", force_text);
     }
+    let bytes = std::fs::read(path).map_err(|e| exit::fail(exit::Kind::Input, format!("Cannot read {}: {}", path, e)))?;
+    text::decode(bytes, path, force_text)
+}
 
-    let url = format!("{}/tts?text={}&voice={}", 
-        DAEMON_URL, 
-        urlencoding::encode(&text),
-        urlencoding::encode(&args.voice)
-    );
+/// Synthesize one text to `output`, or to the speakers/RTP/HTTP sink,
+/// returning what it saw of the audio and the saved file's --checksum.
+fn speak(args: &Args, text: &str, output: Option<&str>, policy: ExistingPolicy, start: Instant) -> Result<(SynthesisStats, Option<Checksum>)> {
+    // From here on Ctrl+C stops playback cleanly so hooks still run
+    interrupt::install();
 
-    // Fast HTTP request
-    let response = ureq::get(&url)
-        .call()
-        .context("Daemon not running?")?;
+    // Bad chime files fail here, before any daemon request
+    let chimes = Chimes::load(args.chime_before.as_ref(), args.chime_after.as_ref())?;
 
-    if let Some(output_path) = args.output {
-        let mut file = std::fs::File::create(&output_path)?;
-        std::io::copy(&mut response.into_reader(), &mut file)?;
-        if !args.quiet {
-            eprintln!("Saved: {}", output_path);
+    // Under --bookmark or --resume, what's left of the text is read
+    let reading = match args.bookmark.as_deref().or(args.resume.as_deref()) {
+        Some(name) => Some(bookmark::Reading::open(name, text, args.resume.as_deref(), args.resume_force)?),
+        None => None,
+    };
+    let text = reading.as_ref().map_or(text, |reading| reading.remaining());
+
+    if args.dry_run {
+        dry_run::report(&dry_run_plan(args, text, output, policy), args.json)?;
+        return Ok((SynthesisStats::default(), None));
+    }
+    if let Some(output_path) = output {
+        if let Some(outcome) = output::check_existing(Path::new(output_path), policy)? {
+            reporter::info(format_args!("{}: {}", outcome.label(), output_path));
+            return Ok((SynthesisStats::default(), None));
         }
+    }
+
+    if let Some(addr) = &args.serve {
+        // Synthesis starts per client connection, not up front
+        let request = request_for(args)?.text(text).voice(voice_for(args, text)).build().map_err(|e| exit::fail(exit::Kind::Usage, e.to_string()))?;
+        let fetch = || Ok(client().synthesize(&request)?);
+        serve::serve(addr, args.serve_keep, fetch)?;
+        return Ok((SynthesisStats::default(), None));
+    }
+
+    if output.is_none() && args.rtp.is_none() {
+        // Nowhere to play it is found before the daemon is asked for it
+        playback::check()?;
+    }
+    let cache = open_cache(args);
+    let spans = reading.is_some().then(join::Spans::default);
+    let (audio, expected, hit) = open_audio_spanned(args, cache.as_ref(), text, spans.clone())?;
+    let envelope = args.waveform.as_ref().map(|_| Envelope::shared(args.waveform_size.0 as usize));
+
+    let mut checksum = None;
+    let measured = if let Some(output_path) = output {
+        let progress = Progress::new(
+            format!("Saving {}", output_path),
+            expected,
+            args.quiet > 0,
+        );
+        let mut reader = report::Tap::new(ProgressReader::new(interrupt::for_work().reader(audio), progress));
+        let chimes = if args.chime_in_file { chimes } else { Chimes::default() };
+        let opts = SaveOptions {
+            policy,
+            checksum: args.checksum,
+            envelope: envelope.clone(),
+            chimes,
+            fifo_wait: (args.fifo_timeout > 0.0).then(|| Duration::from_secs_f64(args.fifo_timeout)),
+        };
+        let saved = output::save(Path::new(output_path), &opts, &mut reader)?;
+        reporter::info(format_args!("{}: {}", saved.outcome.label(), output_path));
+        if let Some(sum) = &saved.checksum {
+            // Under --json it's in the report, stdout being the report's
+            if !args.json {
+                println!("{}", sum.line(output_path));
+            }
+            if args.checksum_file {
+                output::write_sidecar(Path::new(output_path), sum)?;
+            }
+        }
+        checksum = saved.checksum;
+        reader.measured(start)
+    } else if let Some(target) = &args.rtp {
+        let buffer = spawn_net_reader(audio, start, marks::printer(false), envelope.clone(), buffer_limit(args))?;
+        wait_for_prebuffer(&buffer);
+        let (playing, arrow) = (reporter::mark(Mark::Playing), reporter::mark(Mark::Arrow));
+        reporter::timing_line(format_args!("{} {}ms {} rtp://{}", playing, start.elapsed().as_millis(), arrow, target));
+        let stats = rtp::send(&buffer, target)?;
+        if let Some(error) = buffer.error() {
+            bail!(error);
+        }
+        reporter::timing(Mark::Done, start);
+        if args.stats {
+            reporter::info(format_args!("RTP: {} packets sent, {} late", stats.packets, stats.late));
+        }
+        SynthesisStats { underruns: Some(stats.late), ..measured(&buffer, start) }
     } else {
-        stream_audio(response, start, args.quiet)?;
+        let expected = expected.map(|bytes| bytes.saturating_sub(44) / 2);
+        let shown = match args.karaoke.then(|| Karaoke::new(text)).flatten() {
+            Some(karaoke) => {
+                // Without the daemon's word, the usual speaking rate
+                let estimate = text.chars().count() as f64 / args.chars_per_second * SAMPLE_RATE as f64;
+                Shown::Karaoke(karaoke, expected.or(Some(estimate as u64)))
+            }
+            None => {
+                if args.karaoke {
+                    reporter::warning("--karaoke needs a terminal on stderr");
+                }
+                Shown::Position(expected)
+            }
+        };
+        let _keys = reading.is_some().then(keys::listen);
+        let measured = stream_audio(audio, shown, start, false, envelope.clone(), chimes, buffer_limit(args))?;
+        if let (Some(reading), Some(spans)) = (&reading, &spans) {
+            match interrupt::requested() {
+                true => {
+                    let estimate = text.chars().count() as f64 / args.chars_per_second * SAMPLE_RATE as f64;
+                    let length = expected.or(Some(estimate as u64));
+                    reading.stopped(&spans.lock().unwrap(), STOPPED_AT.load(Ordering::Relaxed), length)?;
+                }
+                false => reading.finished()?,
+            }
+        }
+        measured
+    };
+    let measured = synthesis_stats(args, text, cache.as_ref(), hit, measured);
+    if args.stats {
+        reporter::info(format_args!("Stats: {}", measured));
+        if let Some(pace) = measured.pace() {
+            reporter::info(format_args!("Pace: {}", pace));
+        }
+        if let Some((db, others)) = playback::mixed() {
+            reporter::info(format_args!("Mix: {} dB, over {} other player{}", db, others, if others == 1 { "" } else { "s" }));
+        }
     }
 
-    Ok(())
+    if let (Some(path), Some(envelope)) = (&args.waveform, &envelope) {
+        let envelope = envelope.lock().unwrap();
+        waveform::write_png(&envelope, args.waveform_size.1, &args.voice, Path::new(path))?;
+        reporter::info(format_args!("Waveform: {}", path));
+    }
+
+    Ok((measured, checksum))
+}
+
+/// What to do about an existing -o file, from --force/--append/--skip-existing.
+fn existing_policy(args: &Args) -> ExistingPolicy {
+    ExistingPolicy::from_flags(args.force, args.append, args.skip_existing)
+}
+
+fn dry_run_plan<'a>(
+    args: &'a Args,
+    text: &'a str,
+    output: Option<&'a str>,
+    policy: ExistingPolicy,
+) -> dry_run::Plan<'a> {
+    let sink = match (&args.rtp, &args.serve) {
+        (Some(target), _) => format!("rtp://{}", target),
+        (_, Some(addr)) => format!("http://{}/", addr),
+        _ => "speakers".to_string(),
+    };
+    let chunks: Vec<String> = plan(args, text).map_or(Vec::new(), |pieces| {
+        pieces
+            .into_iter()
+            .filter_map(|p| match p {
+                join::Piece::Text { text, .. } => Some(text),
+                join::Piece::Silence(_) => None,
+            })
+            .collect()
+    });
+    let voice = match args.dialogue {
+        // Each line's own, in order of appearance
+        true => {
+            let mut voices: Vec<&str> = Vec::new();
+            for (voice, _) in chunks.iter().filter_map(|c| dialogue::split(c)) {
+                if !voices.contains(&voice) {
+                    voices.push(voice);
+                }
+            }
+            voices.join(", ")
+        }
+        false => voice_for(args, text).to_string(),
+    };
+    dry_run::Plan {
+        text: text.trim(),
+        voice,
+        output,
+        sink,
+        policy,
+        chars_per_second: args.chars_per_second,
+        chunks: chunks.len().max(1),
+    }
 }
 
-fn stream_audio(response: ureq::Response, start: Instant, quiet: bool) -> Result<()> {
-    let (_stream, stream_handle) = OutputStream::try_default()
-        .context("No audio output")?;
-    let sink = Sink::try_new(&stream_handle)?;
+/// The audio for `text`: from the cache, or fetched (and cached as it's
+/// read). Also returns the expected size and whether it was a cache hit.
+fn open_audio(args: &Args, cache: Option<&Cache>, text: &str) -> Result<(AudioStream, Option<u64>, bool)> {
+    open_audio_spanned(args, cache, text, None)
+}
 
+/// [`open_audio`], recording in `spans` where each chunk lies when the
+/// text is read in parts.
+fn open_audio_spanned(args: &Args, cache: Option<&Cache>, text: &str, spans: Option<join::Spans>) -> Result<(AudioStream, Option<u64>, bool)> {
+    let fetcher = Fetcher {
+        client: cached_client(cache),
+        voice: voice_for(args, text).to_string(),
+        voices: match args.auto_voice_per_chunk {
+            true => Some(args.by_language.clone()),
+            false => None,
+        },
+        dialogue: args.dialogue,
+        cache_only: args.cache_only,
+        request: request_for(args)?,
+    };
+    let voice = fetcher.voice.clone();
+    if args.stats && !args.dialogue && !args.auto_voice_per_chunk {
+        reporter::info(format_args!("Voice: {}", voice));
+    }
+    let pieces = plan(args, text)?;
+    let (audio, expected, hit) = match pieces.as_slice() {
+        // A dialogue's pieces say which voice reads them
+        [join::Piece::Text { text: piece, .. }] if args.dialogue => fetcher.fetch(piece)?,
+        [join::Piece::Text { .. }] => fetcher.fetch(text)?,
+        // Parts are fetched as playback reaches them
+        _ => {
+            let options = join::Options {
+                jobs: args.jobs.unwrap_or(2) as usize,
+                max_ahead: Duration::from_millis(args.max_buffer_ms),
+                stats: args.stats,
+                crossfade: Duration::from_millis(args.crossfade_ms),
+                events: marks::printer(false),
+                spans,
+            };
+            let open: prefetch::Open = Arc::new(move |part| fetcher.fetch(part).map(|(audio, _, _)| audio));
+            let joined = join::Joined::new(pieces, open, options)?;
+            (Box::new(joined) as AudioStream, None, false)
+        }
+    };
+    let audio = match args.no_record {
+        true => audio,
+        false => replay::record(audio, text, &voice),
+    };
+    Ok((audio, expected, hit))
+}
+
+/// The voice for `text`: under --auto-voice, the one [voices.by-language]
+/// gives its language if that's clear, else --lang's or --voice. Per chunk,
+/// the fetcher decides.
+fn voice_for<'a>(args: &'a Args, text: &str) -> &'a str {
+    match args.auto_voice && !args.auto_voice_per_chunk {
+        true => language::voice(text, &args.by_language, &args.voice),
+        false => &args.voice,
+    }
+}
+
+/// `text` as it will be synthesized: in chunks and pauses, or under
+/// --dialogue, line by line.
+fn plan(args: &Args, text: &str) -> Result<Vec<join::Piece>> {
+    match args.dialogue {
+        true => {
+            let cast = dialogue::Cast { characters: &args.cast, aliases: &args.aliases, default: &args.voice };
+            dialogue::plan(text, &cast, gap(args, args.dialogue_gap_ms), args.emulate_breaks, chunk_chars(args))
+        }
+        // Saved, the parts run together unless --gap-in-file
+        false => {
+            let gap = match args.output.is_some() && !args.gap_in_file {
+                true => Duration::ZERO,
+                false => gap(args, 0),
+            };
+            Ok(join::spaced(join::plan(text, args.emulate_breaks, chunk_chars(args))?, gap))
+        }
+    }
+}
+
+/// The silence between items played one after another: --gap-ms, or
+/// `default_ms` where it isn't given.
+fn gap(args: &Args, default_ms: u64) -> Duration {
+    Duration::from_millis(args.gap_ms.unwrap_or(default_ms))
+}
+
+/// --chunk-chars, except that SSML is never cut.
+fn chunk_chars(args: &Args) -> usize {
+    match args.ssml {
+        true => 0,
+        false => args.chunk_chars,
+    }
+}
+
+/// What a synthesis request needs, owned so that later parts of a split
+/// text can be fetched from the reader thread.
+struct Fetcher {
+    /// Reading through the cache, unless --no-cache
+    client: SpeakClient,
+    voice: String,
+    /// Under --auto-voice-per-chunk, the [voices.by-language] map to pick each
+    /// part's voice from, `voice` when unsure
+    voices: Option<Vec<(String, String)>>,
+    /// Texts are --dialogue chunks, "voice: speech"
+    dialogue: bool,
+    cache_only: bool,
+    /// Each request's options, its text and voice aside
+    request: SpeakRequestBuilder,
+}
+
+impl Fetcher {
+    fn fetch(&self, text: &str) -> Result<(AudioStream, Option<u64>, bool)> {
+        logging::new_request();
+        let _span = tracing::info_span!(target: "request", "request", request = logging::request_id()).entered();
+        let (voice, text) = match (&self.voices, self.dialogue.then(|| dialogue::split(text)).flatten()) {
+            (_, Some((voice, speech))) => (voice, speech),
+            (Some(voices), None) => (language::voice(text, voices, &self.voice), text),
+            (None, None) => (self.voice.as_str(), text),
+        };
+        let request = self.request.clone().text(text).voice(voice).build().map_err(|e| exit::fail(exit::Kind::Usage, e.to_string()))?;
+        let hit = |audio: speakturbo_client::AudioStream| {
+            tracing::info!(target: "request", "cache hit for {} chars in {}", text.chars().count(), voice);
+            let len = audio.content_length();
+            (audio.into_reader(), len, true)
+        };
+        if self.cache_only {
+            return self.client.cached(&request).map(hit).context("Not in the cache (--cache-only)");
+        }
+        let client = &self.client;
+        // The daemon is asked what it takes only when it's about to be sent
+        // the request; one that can't be asked fails in the synthesis itself
+        let cached = client.cache().is_some_and(|cache| cache.contains(&speakturbo_client::cache::key(client.url(), &request)));
+        let capabilities = match cached {
+            true => Capabilities::legacy(),
+            false => client.capabilities().unwrap_or_default(),
+        };
+        warn_unsupported(&capabilities, &request);
+        let (method, url) = match capabilities.supports(Capabilities::POST) {
+            true => ("POST", format!("{}/tts {}", client.url(), request.to_json())),
+            false => ("GET", client.request_url(&request)),
+        };
+        tracing::info!(target: "request", "{} {} chars in {}: {}", method, text.chars().count(), voice, url);
+        let audio = match client.synthesize_cancellable(&request, &interrupt::for_work()) {
+            // The daemon answers but won't take markup: say so rather than play nothing
+            Err(SpeakError::DaemonError { status, message, .. }) if request.ssml() => {
+                let message = format!("The daemon rejected the SSML (HTTP {}: {}); it may not support --ssml", status, message);
+                return Err(exit::fail(exit::Kind::DaemonError, message));
+            }
+            Err(e) => {
+                tracing::error!(target: "request", "{}", e);
+                return Err(e.into());
+            }
+            Ok(audio) if audio.is_cached() => return Ok(hit(audio)),
+            Ok(audio) => audio,
+        };
+        let expected = audio.expected_bytes();
+        tracing::info!(
+            target: "request",
+            "response {} (Content-Length {}, X-Audio-Duration {})",
+            audio.status(),
+            audio.content_length().map_or("-".to_string(), |n| n.to_string()),
+            audio.duration_hint().map_or("-".to_string(), |secs| secs.to_string())
+        );
+        // Stored in the cache as it plays; only complete streams are kept
+        Ok((audio.into_reader(), expected, false))
+    }
+}
+
+/// The daemon's base URL: --daemon-url, or the default before it's read.
+fn daemon_url() -> &'static str {
+    DAEMON.get().map_or(DEFAULT_URL, String::as_str)
+}
+
+/// What the invocation asks the daemon for besides a text and its voice:
+/// --ssml and a job's params, those the request has fields for in them.
+/// Every synthesis request starts here.
+fn request_for(args: &Args) -> Result<SpeakRequestBuilder> {
+    let mut request = SpeakRequest::new("").voice(&args.voice).ssml(args.ssml);
+    for (name, value) in &args.params {
+        let number = || exit::fail(exit::Kind::Usage, format!("{} {:?} isn't a number", name, value));
+        request = match name.as_str() {
+            "style" => request.style(value),
+            "rate" => request.rate(value.parse().map_err(|_| number())?),
+            "seed" => request.seed(value.parse().map_err(|_| number())?),
+            _ => request.param(name, value),
+        };
+    }
+    Ok(request)
+}
+
+/// The client for the daemon at --daemon-url; its clones share what the
+/// daemon's capabilities are once they've been asked.
+fn client() -> SpeakClient {
+    CLIENT.get_or_init(|| SpeakClient::new(daemon_url())).clone()
+}
+
+/// Warn, once an invocation, of the options in `request` that the daemon
+/// says it doesn't take; a legacy daemon can't say, and isn't warned of.
+fn warn_unsupported(capabilities: &Capabilities, request: &SpeakRequest) {
+    static WARNED: AtomicBool = AtomicBool::new(false);
+    let unsupported = capabilities.unsupported(request);
+    if unsupported.is_empty() || WARNED.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    let version = capabilities.version.as_deref().unwrap_or("of unknown version");
+    reporter::warning(format_args!("daemon {} doesn't take {}; {} ignored", version, unsupported.join(" or "), if unsupported.len() == 1 { "it's" } else { "they're" }));
+}
+
+/// [`client`], reading through `cache` if there is one.
+fn cached_client(cache: Option<&Cache>) -> SpeakClient {
+    match cache {
+        Some(cache) => client().with_cache(cache.clone()),
+        None => client(),
+    }
+}
+
+/// The voices the daemon has, from /health; None if it can't say.
+fn daemon_voices() -> Option<Vec<String>> {
+    let agent = ureq::AgentBuilder::new().timeout(Duration::from_millis(500)).build();
+    SpeakClient::with_agent(daemon_url(), agent).list_voices().ok()
+}
+
+/// The daemon's WAV stream, or a cached copy of it
+type AudioStream = Box<dyn Read + Send>;
+
+/// 429s in a row before a synthesis gives up
+const MAX_THROTTLED: u32 = 8;
+
+/// How long the daemon asks to be left alone for, if `error` is its 429;
+/// else doubling from half a second.
+fn backoff(error: &anyhow::Error, attempt: u32) -> Option<Duration> {
+    let Some(SpeakError::DaemonError { status: 429, retry_after, .. }) = error.downcast_ref::<SpeakError>() else {
+        return None;
+    };
+    Some(retry_after.unwrap_or(Duration::from_millis(500 << attempt.min(4))))
+}
+
+/// Whether `error` is the daemon failing in passing: unreachable, or a 5xx
+/// before any audio.
+fn transient(error: &anyhow::Error) -> bool {
+    match error.downcast_ref::<SpeakError>() {
+        Some(SpeakError::DaemonError { status, .. }) => *status >= 500,
+        Some(SpeakError::DaemonUnreachable { .. } | SpeakError::Timeout { .. }) => true,
+        _ => false,
+    }
+}
+
+/// What the terminal shows while audio plays, with the samples expected
+/// when the daemon said, or --karaoke's estimate
+enum Shown {
+    Position(Option<u64>),
+    Karaoke(Karaoke, Option<u64>),
+}
+
+fn stream_audio(
+    audio: AudioStream,
+    shown: Shown,
+    start: Instant,
+    quiet: bool,
+    envelope: Option<SharedEnvelope>,
+    chimes: Chimes,
+    limit: usize,
+) -> Result<SynthesisStats> {
+    let output = playback::Output::open()?;
+
+    let events = marks::printer(quiet);
+    let buffer = spawn_net_reader(audio, start, events.clone(), envelope, limit)?;
+    wait_for_prebuffer(&buffer);
+
+    // Play!
+    let _span = tracing::info_span!(target: "playback", "playing", request = logging::request_id()).entered();
+    output.speech(StreamSource::with_chimes(Arc::clone(&buffer), chimes).watched(events.clone(), start));
+    let (mut karaoke, expected) = match shown {
+        Shown::Position(expected) => (None, expected),
+        Shown::Karaoke(karaoke, expected) => (Some(karaoke), expected),
+    };
+    let mut position = progress::Playback::new(SAMPLE_RATE, quiet || reporter::quiet() || karaoke.is_some());
+    let mut ticks = 0u32;
+    while !output.is_empty() {
+        // Once the stream has ended, its length is known
+        let pushed = buffer.pushed();
+        let total = match buffer.is_done() {
+            true => Some(pushed),
+            false => expected.map(|expected| expected.max(pushed)),
+        };
+        let length = match (buffer.is_done(), total) {
+            (true, _) => progress::Length::Exactly(pushed),
+            (false, Some(total)) => progress::Length::About(total),
+            (false, None) => progress::Length::Unknown,
+        };
+        position.update(buffer.played(), length);
+        if let Some(karaoke) = &mut karaoke {
+            karaoke.update(buffer.played(), total);
+        }
+        if interrupt::requested() {
+            STOPPED_AT.store(buffer.played(), Ordering::Relaxed);
+            cut_off(&output);
+            events.emit(SpeakEvent::Error { kind: ErrorKind::Interrupted, message: "interrupted".to_string() });
+            events.flush();
+            return Ok(measured(&buffer, start));
+        }
+        std::thread::sleep(Duration::from_millis(10));
+        buffer.sample_lead();
+        ticks += 1;
+        if ticks.is_multiple_of(10) {
+            tracing::debug!(target: "playback", "buffer {}", occupancy(&buffer));
+        }
+    }
+    position.finish();
+    if let Some(karaoke) = &mut karaoke {
+        karaoke.finish();
+    }
+    if let Some(error) = buffer.error() {
+        events.flush();
+        bail!(error);
+    }
+
+    // What --json reports is what the Finished event carried, with what
+    // only the caller knows added
+    let stats = measured(&buffer, start);
+    let ms = buffer.drained().saturating_duration_since(start).as_millis() as u64;
+    events.emit(SpeakEvent::Finished { ms, stats: stats.clone() });
+    events.flush();
+    Ok(stats)
+}
+
+/// Skip the WAV header and start the network reader thread feeding a shared buffer.
+/// An optional envelope taps the samples for --waveform. Reading pauses
+/// while `limit` samples are waiting to play. What arrives goes to `events`.
+fn spawn_net_reader(
+    mut reader: AudioStream,
+    start: Instant,
+    events: Events,
+    envelope: Option<SharedEnvelope>,
+    limit: usize,
+) -> Result<Arc<LockFreeBuffer>> {
     // Lock-free-ish shared state
     let buffer = Arc::new(LockFreeBuffer::new());
     let buffer_clone = Arc::clone(&buffer);
 
     // Skip WAV header
-    let mut reader = response.into_reader();
     let mut header = [0u8; 44];
     reader.read_exact(&mut header)?;
 
     // Network reader thread - HIGH PRIORITY
     let start_clone = start;
+    let request = logging::request_id();
     std::thread::Builder::new()
         .name("net-reader".into())
         .spawn(move || {
+            let _span = tracing::info_span!(target: "net", "reading the stream", request).entered();
             let mut chunk_buf = [0u8; 4096];
             let mut first = true;
+            let mut total = 0u64;
             
             loop {
+                while buffer_clone.len() >= limit && !interrupt::requested() {
+                    std::thread::sleep(Duration::from_millis(10));
+                }
                 match reader.read(&mut chunk_buf) {
                     Ok(0) => {
+                        tracing::info!(target: "net", "end of stream after {} bytes", total);
                         buffer_clone.set_done();
                         break;
                     }
                     Ok(n) => {
-                        if first && !quiet {
-                            eprintln!("⚡ {}ms", start_clone.elapsed().as_millis());
+                        if first {
+                            buffer_clone.mark_first();
+                            events.emit(SpeakEvent::FirstByte { ms: start_clone.elapsed().as_millis() as u64 });
                             first = false;
                         }
                         
-                        // Direct byte-to-sample conversion, no allocation
-                        for chunk in chunk_buf[..n].chunks_exact(2) {
-                            let sample = i16::from_le_bytes([chunk[0], chunk[1]]);
-                            buffer_clone.push(sample);
+                        if let Some(envelope) = &envelope {
+                            envelope.lock().unwrap().push_bytes(&chunk_buf[..n]);
                         }
+                        total += n as u64;
+
+                        buffer_clone.push_bytes(&chunk_buf[..n]);
+                        events.emit(SpeakEvent::Progress { samples_emitted: buffer_clone.played(), bytes_received: buffer_clone.received() });
+                        tracing::debug!(target: "net", "read {} bytes ({} in all), buffer {}", n, total, occupancy(&buffer_clone));
                     }
-                    Err(_) => {
-                        buffer_clone.set_done();
+                    Err(e) => {
+                        tracing::error!(target: "net", "read failed after {} bytes: {}", total, e);
+                        events.emit(SpeakEvent::Error { kind: ErrorKind::Stream, message: e.to_string() });
+                        buffer_clone.fail(e.to_string());
                         break;
                     }
                 }
             }
         })?;
 
-    // Wait for minimal buffer
-    while buffer.len() < MIN_BUFFER_SAMPLES && !buffer.is_done() {
-        std::thread::sleep(Duration::from_micros(500)); // 0.5ms polling
-    }
-
-    if !quiet {
-        eprintln!("▶ {}ms", start.elapsed().as_millis());
-    }
+    Ok(buffer)
+}
 
-    // Play!
-    let source = StreamSource { buffer, samples_emitted: 0 };
-    sink.append(source);
-    sink.sleep_until_end();
+/// --max-buffer-ms in samples.
+fn buffer_limit(args: &Args) -> usize {
+    (args.max_buffer_ms * SAMPLE_RATE as u64 / 1000) as usize
+}
 
-    if !quiet {
-        eprintln!("✓ {}ms", start.elapsed().as_millis());
+/// Wait for minimal buffer
+fn wait_for_prebuffer(buffer: &LockFreeBuffer) {
+    while buffer.len() < MIN_BUFFER_SAMPLES && !buffer.is_done() && !interrupt::requested() {
+        std::thread::sleep(Duration::from_micros(500)); // 0.5ms polling
     }
+    tracing::info!(target: "playback", "prebuffer reached: {}", occupancy(buffer));
+}
 
-    Ok(())
+/// "3600 samples (150ms)"
+fn occupancy(buffer: &LockFreeBuffer) -> String {
+    let samples = buffer.len();
+    format!("{} samples ({}ms)", samples, samples as u64 * 1000 / SAMPLE_RATE as u64)
 }
 
-/// Simple lock-free-ish ring buffer using atomic operations
-struct LockFreeBuffer {
-    data: std::sync::Mutex<VecDeque<i16>>,
-    len: AtomicUsize,
-    done: AtomicBool,
+/// For --json and --stats: what a stream of the daemon's took in and how
+/// playback went
+fn measured(buffer: &LockFreeBuffer, start: Instant) -> SynthesisStats {
+    SynthesisStats::of(buffer, chime::FORMAT, start)
 }
 
-impl LockFreeBuffer {
-    fn new() -> Self {
-        Self {
-            data: std::sync::Mutex::new(VecDeque::with_capacity(SAMPLE_RATE as usize)),
-            len: AtomicUsize::new(0),
-            done: AtomicBool::new(false),
-        }
+/// `stats` of the synthesis of `text`, with the parts it was read in, the
+/// daemon asked, and with a cache, whether it had the audio.
+fn synthesis_stats(args: &Args, text: &str, cache: Option<&Cache>, hit: bool, stats: SynthesisStats) -> SynthesisStats {
+    let parts = plan(args, text).map_or(1, |pieces| pieces.iter().filter(|p| matches!(p, join::Piece::Text { .. })).count());
+    SynthesisStats {
+        cache_hit: cache.is_some().then_some(hit),
+        chunks: parts.max(1) as u64,
+        endpoint: Some(daemon_url().to_string()),
+        ..stats
     }
+}
 
-    fn push(&self, sample: i16) {
-        self.data.lock().unwrap().push_back(sample);
-        self.len.fetch_add(1, Ordering::Release);
+/// Stop `output`, fading out if an urgent invocation is what stopped it.
+fn cut_off(output: &playback::Output) {
+    match interrupt::preempted() {
+        true => output.fade_out(playback::FADE_OUT),
+        false => output.stop(),
     }
+}
 
-    fn pop(&self) -> Option<i16> {
-        let mut data = self.data.lock().unwrap();
-        if let Some(s) = data.pop_front() {
-            self.len.fetch_sub(1, Ordering::Release);
-            Some(s)
-        } else {
-            None
-        }
-    }
+/// Streams the daemon's samples, with any chimes played gaplessly around them.
+struct StreamSource {
+    buffer: Arc<LockFreeBuffer>,
+    samples_emitted: usize,
+    /// Waiting on an empty buffer, counted once as an underrun
+    starved: bool,
+    /// Applied to every sample, chimes included (--mix)
+    gain: Option<f32>,
+    before: std::vec::IntoIter<i16>,
+    after: std::vec::IntoIter<i16>,
+    /// Where the first sample and underruns are told, timed from `start`
+    events: Events,
+    start: Instant,
+}
 
-    fn len(&self) -> usize {
-        self.len.load(Ordering::Acquire)
+impl StreamSource {
+    fn new(buffer: Arc<LockFreeBuffer>) -> Self {
+        Self::with_chimes(buffer, Chimes::default())
     }
 
-    fn is_done(&self) -> bool {
-        self.done.load(Ordering::Acquire)
+    fn with_chimes(buffer: Arc<LockFreeBuffer>, chimes: Chimes) -> Self {
+        Self {
+            buffer,
+            samples_emitted: 0,
+            starved: false,
+            gain: None,
+            before: chimes.before.into_iter(),
+            after: chimes.after.into_iter(),
+            events: Events::none(),
+            start: Instant::now(),
+        }
     }
 
-    fn set_done(&self) {
-        self.done.store(true, Ordering::Release);
+    /// This source, telling `events` how playback goes.
+    fn watched(self, events: Events, start: Instant) -> Self {
+        Self { events, start, ..self }
     }
-}
 
-struct StreamSource {
-    buffer: Arc<LockFreeBuffer>,
-    samples_emitted: usize,
-}
-
-impl Iterator for StreamSource {
-    type Item = i16;
+    /// This source, played at `gain` if there is one.
+    #[cfg_attr(not(feature = "playback"), allow(dead_code))]
+    fn gained(self, gain: Option<f32>) -> Self {
+        Self { gain, ..self }
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
+    fn unscaled(&mut self) -> Option<i16> {
+        if let Some(sample) = self.before.next() {
+            return Some(sample);
+        }
         loop {
             if let Some(sample) = self.buffer.pop() {
                 // Apply fade-in to first FADE_IN_SAMPLES to eliminate startup transients
@@ -216,12 +2106,27 @@ impl Iterator for StreamSource {
                 } else {
                     sample
                 };
+                if self.samples_emitted == 0 {
+                    self.buffer.mark_playing();
+                    self.events.emit(SpeakEvent::FirstSample { ms: self.start.elapsed().as_millis() as u64 });
+                }
                 self.samples_emitted += 1;
+                if self.starved {
+                    self.starved = false;
+                    self.buffer.note_refilled();
+                }
                 return Some(output);
             }
             
-            if self.buffer.is_done() {
-                return None;
+            // Done is set after the last push, so an empty buffer then is the end
+            if self.buffer.is_done() && self.buffer.is_empty() {
+                self.buffer.mark_drained();
+                return self.after.next();
+            }
+            if !self.starved {
+                self.starved = true;
+                self.buffer.note_underrun();
+                self.events.emit(SpeakEvent::Underrun);
             }
             
             // Spin-wait (aggressive but low latency)
@@ -230,27 +2135,11 @@ impl Iterator for StreamSource {
     }
 }
 
-impl Source for StreamSource {
-    fn current_frame_len(&self) -> Option<usize> { None }
-    fn channels(&self) -> u16 { 1 }
-    fn sample_rate(&self) -> u32 { SAMPLE_RATE }
-    fn total_duration(&self) -> Option<Duration> { None }
-}
+impl Iterator for StreamSource {
+    type Item = i16;
 
-mod urlencoding {
-    pub fn encode(s: &str) -> String {
-        let mut r = String::with_capacity(s.len() * 2);
-        for c in s.chars() {
-            match c {
-                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' | '~' => r.push(c),
-                ' ' => r.push_str("%20"),
-                _ => {
-                    for b in c.to_string().as_bytes() {
-                        r.push_str(&format!("%{:02X}", b));
-                    }
-                }
-            }
-        }
-        r
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = self.unscaled()?;
+        Some(self.gain.map_or(sample, |gain| (sample as f32 * gain) as i16))
     }
 }
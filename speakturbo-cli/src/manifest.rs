@@ -0,0 +1,447 @@
+//! `speakturbo batch MANIFEST.csv`: a synthesis per row of a CSV whose
+//! header names its columns: text, and any of voice, output and style. An
+//! empty cell leaves the invocation's setting: -o's {n} template for the
+//! output, the voice, the profile in effect for the style. A style is a
+//! [profile.NAME] of the config. Rows count from 1 after the header.
+//!
+//! `speakturbo batch --jsonl [FILE]` takes the same jobs as JSON Lines
+//! for programs to drive: {"text", "voice", "output", "params": {...}},
+//! with `params` and any other field sent to the daemon as they are. A
+//! report line goes to stdout as each job finishes.
+
+use crate::exit::{self, Kind};
+use crate::report::{self, Report};
+use crate::reporter::{self, Mark};
+use crate::sequence::{self, Item, ItemResult};
+use crate::summary::{self, Entry};
+use crate::{config, Args};
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+const COLUMNS: [&str; 4] = ["text", "voice", "output", "style"];
+
+pub enum Source {
+    Csv(PathBuf),
+    /// "-" for stdin
+    JsonLines(PathBuf),
+}
+
+impl Source {
+    fn name(&self) -> String {
+        match self {
+            Source::JsonLines(path) if path.as_os_str() == "-" => "stdin".to_string(),
+            Source::Csv(path) | Source::JsonLines(path) => path.display().to_string(),
+        }
+    }
+
+    /// What its entries are numbered as
+    fn unit(&self) -> &'static str {
+        match self {
+            Source::Csv(_) => "row",
+            Source::JsonLines(_) => "line",
+        }
+    }
+}
+
+/// A job as the manifest has it, without its empty cells
+#[derive(Debug, PartialEq)]
+struct Row {
+    n: usize,
+    text: String,
+    voice: Option<String>,
+    output: Option<String>,
+    style: Option<String>,
+    /// Extra /tts parameters
+    params: Vec<(String, String)>,
+}
+
+/// A row's number and what's wrong with it
+type Problem = (usize, String);
+
+/// A row's style, voice and parameters
+type Key = (Option<String>, Option<String>, Vec<(String, String)>);
+
+/// The settings for a style, or with None for the invocation's profile
+pub type Styled<'a> = dyn Fn(Option<&str>) -> Result<Args> + 'a;
+
+/// A row ready to synthesize
+struct Planned {
+    n: usize,
+    text: String,
+    output: String,
+    /// Its settings among those the rows made, if not the invocation's
+    settings: Option<usize>,
+}
+
+pub fn run(args: &Args, source: &Source, styled: &Styled, start: Instant) -> Result<()> {
+    let (name, unit) = (source.name(), source.unit());
+    let streamed = matches!(source, Source::JsonLines(_));
+    let rows = match source {
+        Source::Csv(path) => {
+            let input = crate::read_file(&path.to_string_lossy(), args.force_text)?;
+            rows(&input).map_err(|e| exit::fail(Kind::Input, format!("{}: {}", name, e)))?
+        }
+        Source::JsonLines(path) => jobs(&crate::read_file(&path.to_string_lossy(), args.force_text)?),
+    };
+    let last = match rows.last() {
+        Some(Ok(row)) => row.n,
+        Some(Err((n, _))) => *n,
+        None if streamed => return Err(exit::fail(Kind::Input, format!("{}: no jobs", name))),
+        None => return Err(exit::fail(Kind::Input, format!("{}: no rows after the header", name))),
+    };
+
+    // Each (style, voice, parameters) the rows ask for, settled once
+    let mut settings: Vec<(Key, Result<Args, String>)> = Vec::new();
+    let mut planned: Vec<Planned> = Vec::new();
+    let mut problems: Vec<Problem> = Vec::new();
+    // The rows with a problem as jobs, to be fixed and run again
+    let mut rejected: Vec<(usize, Value)> = Vec::new();
+    let reject = |row: &Row| (row.n, summary::job(&row.text, row.voice.as_deref().unwrap_or(&args.voice), row.output.as_deref()));
+    for row in rows {
+        let row = match row {
+            Ok(row) => row,
+            Err(problem) => {
+                problems.push(problem);
+                continue;
+            }
+        };
+        let output = match output_for(row.output.as_deref(), args.output.as_deref(), row.n, last) {
+            Ok(output) => output,
+            Err(e) => {
+                problems.push((row.n, e));
+                rejected.push(reject(&row));
+                continue;
+            }
+        };
+        if let Some(other) = planned.iter().find(|p| p.output == output) {
+            problems.push((row.n, format!("{} is {} {}'s output too", output, unit, other.n)));
+            rejected.push(reject(&row));
+            continue;
+        }
+        let key = (row.style.clone(), row.voice.clone(), row.params.clone());
+        let index = match key {
+            (None, None, ref params) if params.is_empty() => None,
+            _ => Some(match settings.iter().position(|(k, _)| *k == key) {
+                Some(i) => i,
+                None => {
+                    let made = styled(key.0.as_deref()).map_err(|e| format!("{:#}", e)).map(|mut styled| {
+                        if let Some(voice) = &key.1 {
+                            styled.voice = config::resolve(&styled.aliases, voice).to_string();
+                        }
+                        styled.params = key.2.clone();
+                        styled
+                    });
+                    settings.push((key, made));
+                    settings.len() - 1
+                }
+            }),
+        };
+        let row_args = match index.map(|i| &settings[i].1) {
+            None => args,
+            Some(Ok(row_args)) => row_args,
+            Some(Err(e)) => {
+                problems.push((row.n, e.clone()));
+                rejected.push(reject(&row));
+                continue;
+            }
+        };
+        let text = crate::normalized(row_args, row.text);
+        planned.push(Planned { n: row.n, text, output, settings: index });
+    }
+    problems.sort_by_key(|(n, _)| *n);
+    let settings: Vec<Option<Args>> = settings.into_iter().map(|(_, made)| made.ok()).collect();
+    let args_of = |p: &Planned| p.settings.and_then(|i| settings[i].as_ref());
+    let total = planned.len() + problems.len();
+
+    if args.dry_run {
+        let plans: Vec<_> = planned
+            .iter()
+            .map(|p| {
+                let row_args = args_of(p).unwrap_or(args);
+                crate::dry_run_plan(row_args, &p.text, Some(&p.output), crate::existing_policy(row_args))
+            })
+            .collect();
+        crate::dry_run::report_all(&plans, args.json)?;
+        report_problems(unit, &problems);
+        return match problems.len() {
+            0 => Ok(()),
+            n => Err(exit::fail(Kind::Input, format!("{} of {} {}s can't be synthesized", n, total, unit))),
+        };
+    }
+    if let (true, Some((n, problem))) = (args.fail_fast, problems.first()) {
+        return Err(exit::fail(Kind::Input, format!("{} {} {}: {}", name, unit, n, problem)));
+    }
+
+    let failure = |n: usize, problem: &str| {
+        let mut report = unsynthesized(args, None);
+        report.status = report::Status::Error;
+        report.error = Some(problem.to_string());
+        report.kind = Some(Kind::Input);
+        report.source = Some(format!("{} {} {}", name, unit, n));
+        report
+    };
+    if streamed {
+        for (n, problem) in &problems {
+            println!("{}", failure(*n, problem).to_json());
+        }
+    }
+
+    for p in &planned {
+        if let Some(parent) = Path::new(&p.output).parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).with_context(|| format!("Cannot create {}", parent.display()))?;
+        }
+    }
+    let items: Vec<Item> = planned
+        .iter()
+        .map(|p| Item {
+            text: &p.text,
+            output: Some(p.output.clone()),
+            policy: crate::existing_policy(args_of(p).unwrap_or(args)),
+            args: args_of(p),
+        })
+        .collect();
+    let report_of = |p: &Planned, done: Option<&ItemResult>| {
+        let row_args = args_of(p).unwrap_or(args);
+        let mut report = match done {
+            Some(r) => r.report(row_args, &p.text),
+            // Not reached after Ctrl+C or --fail-fast
+            None => Report { status: report::Status::Pending, ..unsynthesized(row_args, Some(p.output.clone())) },
+        };
+        report.source = Some(format!("{} {} {}", name, unit, p.n));
+        report
+    };
+    let results = sequence::one_by_one_each(args, &items, start, |r| {
+        if streamed {
+            println!("{}", report_of(&planned[r.n - 1], Some(r)).to_json());
+        }
+    })?;
+
+    let mut entries: Vec<(usize, Entry)> = Vec::new();
+    for (i, p) in planned.iter().enumerate() {
+        let done = results.iter().find(|r| r.n == i + 1);
+        let row_args = args_of(p).unwrap_or(args);
+        let job = match (summary::job(&p.text, &row_args.voice, Some(&p.output)), row_args.params.as_slice()) {
+            (Value::Object(mut fields), params) if !params.is_empty() => {
+                let params = params.iter().map(|(name, value)| (name.clone(), Value::from(value.as_str())));
+                fields.insert("params".to_string(), Value::Object(params.collect()));
+                Value::Object(fields)
+            }
+            (job, _) => job,
+        };
+        entries.push((p.n, Entry { label: format!("{} {}", unit, p.n), report: report_of(p, done), job: Some(job) }));
+    }
+    for (n, problem) in &problems {
+        let job = rejected.iter().find(|(m, _)| m == n).map(|(_, job)| job.clone());
+        entries.push((*n, Entry { label: format!("{} {}", unit, n), report: failure(*n, problem), job }));
+    }
+    entries.sort_by_key(|(n, _)| *n);
+    let entries: Vec<Entry> = entries.into_iter().map(|(_, entry)| entry).collect();
+
+    if streamed {
+        // The lines went out as the jobs finished
+        report::mark_printed();
+    } else if args.json {
+        report::print_all(&entries.iter().map(|e| e.report.clone()).collect::<Vec<_>>());
+    }
+    summary::finish(&entries, args.report.as_deref(), args.json && !streamed)
+}
+
+fn report_problems(unit: &str, problems: &[Problem]) {
+    for (n, problem) in problems {
+        reporter::error(format_args!("{} {} {}: {}", reporter::mark(Mark::Failed), unit, n, problem));
+    }
+}
+
+/// The report of a row that wasn't synthesized
+fn unsynthesized(args: &Args, output: Option<String>) -> Report {
+    Report {
+        status: report::Status::Skipped,
+        voice: args.voice.clone(),
+        text_chars: 0,
+        elapsed: std::time::Duration::ZERO,
+        output,
+        checksum: None,
+        source: None,
+        error: None,
+        kind: None,
+        measured: speakturbo_client::SynthesisStats::default(),
+    }
+}
+
+/// The manifest's rows, each with what's wrong with it if anything is.
+/// What's wrong with the CSV or its header is wrong for the whole file.
+fn rows(input: &str) -> Result<Vec<Result<Row, Problem>>, String> {
+    // Flexible: a row of the wrong length is that row's problem, not the file's
+    let mut reader = csv::ReaderBuilder::new().has_headers(false).flexible(true).from_reader(input.as_bytes());
+    let mut records = reader.records().collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())?.into_iter();
+    let header = records.next().ok_or("empty; its first line must name the columns")?;
+    // Where each of COLUMNS is in the header
+    let mut at = [None; COLUMNS.len()];
+    for (i, name) in header.iter().enumerate() {
+        let name = name.trim().to_lowercase();
+        let Some(column) = COLUMNS.iter().position(|c| *c == name) else {
+            return Err(format!("unknown column {:?} in the header; the columns are {}", name, COLUMNS.join(", ")));
+        };
+        if at[column].replace(i).is_some() {
+            return Err(format!("the header names {} twice", name));
+        }
+    }
+    let Some(text) = at[0] else {
+        return Err(format!("the header has no text column, only {}", header.iter().collect::<Vec<_>>().join(", ")));
+    };
+    let rows = records
+        .filter(|record| !record.iter().all(|field| field.trim().is_empty()))
+        .map(|record| {
+            // Numbered by line, blank ones too, as a spreadsheet numbers rows
+            let n = record.position().map_or(0, |at| at.line() as usize - 1);
+            if record.len() != header.len() {
+                return Err((n, format!("{} columns in the header, {} in the row", header.len(), record.len())));
+            }
+            let cell = |column: usize| at[column].map(|i| record[i].trim()).filter(|v| !v.is_empty()).map(str::to_string);
+            if record[text].trim().is_empty() {
+                return Err((n, "No text".to_string()));
+            }
+            let text = record[text].trim().to_string();
+            Ok(Row { n, text, voice: cell(1), output: cell(2), style: cell(3), params: Vec::new() })
+        })
+        .collect();
+    Ok(rows)
+}
+
+/// The JSON Lines jobs, each with what's wrong with it if anything is.
+/// Blank lines are passed over.
+fn jobs(input: &str) -> Vec<Result<Row, Problem>> {
+    input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| job(i + 1, line).map_err(|e| (i + 1, e)))
+        .collect()
+}
+
+fn job(n: usize, line: &str) -> Result<Row, String> {
+    let Value::Object(fields) = serde_json::from_str(line).map_err(|e| format!("Not JSON: {:#}", e))? else {
+        return Err("Not a JSON object".to_string());
+    };
+    let mut row = Row { n, text: String::new(), voice: None, output: None, style: None, params: Vec::new() };
+    for (name, value) in fields {
+        match (name.as_str(), value) {
+            ("text", Value::String(text)) => row.text = text.trim().to_string(),
+            ("voice", Value::String(voice)) => row.voice = Some(voice).filter(|v| !v.is_empty()),
+            ("output", Value::String(output)) => row.output = Some(output).filter(|o| !o.is_empty()),
+            ("voice" | "output", Value::Null) => {}
+            ("text" | "voice" | "output", _) => return Err(format!("\"{}\" isn't a string", name)),
+            ("params", Value::Object(params)) => {
+                for (name, value) in params {
+                    row.params.push(param(name, value)?);
+                }
+            }
+            ("params", _) => return Err("\"params\" isn't an object".to_string()),
+            (_, value) => row.params.push(param(name, value)?),
+        }
+    }
+    match row.text.is_empty() {
+        true => Err("No text".to_string()),
+        false => Ok(row),
+    }
+}
+
+/// A parameter for the daemon, as the query string will have it
+fn param(name: String, value: Value) -> Result<(String, String), String> {
+    match value {
+        Value::String(value) => Ok((name, value)),
+        Value::Number(_) | Value::Bool(_) => Ok((name, value.to_string())),
+        _ => Err(format!("The parameter {:?} isn't a string, number or boolean", name)),
+    }
+}
+
+/// The row's own output, else -o's `template` numbered for row `n` of `last`
+fn output_for(own: Option<&str>, template: Option<&str>, n: usize, last: usize) -> Result<String, String> {
+    match (own, template) {
+        (Some(own), _) => Ok(own.to_string()),
+        (None, Some(template)) if template.contains("{n}") => Ok(sequence::expand(template, n, last)),
+        (None, Some(template)) => Err(format!("no output, and -o {} has no {{n}} to number it by", template)),
+        (None, None) => Err("no output, and no -o template for one (e.g. -o prompt-{n}.wav)".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(n: usize, text: &str, voice: Option<&str>, output: Option<&str>) -> Result<Row, Problem> {
+        let owned = |v: Option<&str>| v.map(str::to_string);
+        Ok(Row { n, text: text.to_string(), voice: owned(voice), output: owned(output), style: None, params: Vec::new() })
+    }
+
+    #[test]
+    fn reads_rows_by_the_header() {
+        let input = "\u{feff}Output,text,voice\nhi.wav,\"Hello, you\",alba\n,Bye,\n\n,,\nx.wav,\"\",alba\nonly one\n";
+        let expected = vec![
+            row(1, "Hello, you", Some("alba"), Some("hi.wav")),
+            row(2, "Bye", None, None),
+            Err((5, "No text".to_string())),
+            Err((6, "3 columns in the header, 1 in the row".to_string())),
+        ];
+        assert_eq!(rows(input), Ok(expected));
+
+        let cases = [
+            ("voice,output\nalba,a.wav", "the header has no text column, only voice, output"),
+            ("text,notes\nHi,x", "unknown column \"notes\" in the header; the columns are text, voice, output, style"),
+            ("text,Text\na,b", "the header names text twice"),
+            ("", "empty; its first line must name the columns"),
+        ];
+        for (input, error) in cases {
+            assert_eq!(rows(input), Err(error.to_string()), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn reads_jobs_with_their_parameters() {
+        let input = concat!(
+            "{\"text\": \"Hi\", \"voice\": \"alba\", \"params\": {\"speed\": 1.2}, \"seed\": 7, \"fast\": true}\n",
+            "\n",
+            "{\"text\": \"Bye\", \"output\": \"\"}\n",
+            "{\"text\": 3}\n",
+            "not json\n",
+            "{\"text\": \"x\", \"params\": {\"list\": []}}\n",
+            "[1]\n",
+            "{\"voice\": \"alba\"}",
+        );
+        let params = |pairs: &[(&str, &str)]| pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let expected = vec![
+            Ok(Row {
+                n: 1,
+                text: "Hi".to_string(),
+                voice: Some("alba".to_string()),
+                output: None,
+                style: None,
+                params: params(&[("speed", "1.2"), ("seed", "7"), ("fast", "true")]),
+            }),
+            row(3, "Bye", None, None),
+            Err((4, "\"text\" isn't a string".to_string())),
+            Err((5, "Not JSON: expected ident at line 1 column 2".to_string())),
+            Err((6, "The parameter \"list\" isn't a string, number or boolean".to_string())),
+            Err((7, "Not a JSON object".to_string())),
+            Err((8, "No text".to_string())),
+        ];
+        assert_eq!(jobs(input), expected);
+    }
+
+    #[test]
+    fn numbers_outputs_from_the_template() {
+        let cases = [
+            (Some("own.wav"), Some("p-{n}.wav"), Ok("own.wav")),
+            (None, Some("p-{n}.wav"), Ok("p-07.wav")),
+            (None, Some("p.wav"), Err("no output, and -o p.wav has no {n} to number it by")),
+            (None, None, Err("no output, and no -o template for one (e.g. -o prompt-{n}.wav)")),
+        ];
+        for (own, template, expected) in cases {
+            let expected = expected.map(str::to_string).map_err(str::to_string);
+            assert_eq!(output_for(own, template, 7, 12), expected, "{:?}", (own, template));
+        }
+    }
+}
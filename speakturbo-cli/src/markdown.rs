@@ -0,0 +1,346 @@
+//! `--markdown`: read Markdown as prose. Formatting marks and link URLs go,
+//! code blocks are skipped, headings become sentences, and lists and tables
+//! are read item by item. Parsed by pulldown-cmark: CommonMark plus GFM
+//! tables, task lists, strikethrough and footnotes.
+
+use crate::{html, text};
+use pulldown_cmark::{Event, LinkType, Options, Parser, Tag, TagEnd};
+
+/// Whether a file's name says it's Markdown.
+pub fn is_markdown_path(path: &str) -> bool {
+    let lower = path.to_ascii_lowercase();
+    [".md", ".markdown", ".mdown", ".mkd"].iter().any(|ext| lower.ends_with(ext))
+}
+
+/// The speakable text of `markdown`, one paragraph per block.
+pub fn to_speech(markdown: &str, announce_code: bool) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES | Options::ENABLE_TASKLISTS | Options::ENABLE_STRIKETHROUGH;
+    let mut reader = Reader { announce_code, ..Reader::default() };
+    for event in Parser::new_ext(markdown, options) {
+        reader.event(event);
+    }
+    reader.out.join("\n\n")
+}
+
+#[derive(Default)]
+struct Reader {
+    out: Vec<String>,
+    announce_code: bool,
+    /// The inline text of the block being read
+    inline: String,
+    /// Text not yet in `inline`, its bare URLs still to be shortened
+    text: String,
+    /// Inside a code block, which isn't read
+    code: bool,
+    /// Inside an autolink, whose text is its URL
+    autolink: bool,
+    /// An HTML block's markup so far
+    html: String,
+    /// The lists being read, innermost last
+    lists: Vec<Vec<Item>>,
+    /// The table being read: its header, the row being read, the rows read
+    header: Vec<String>,
+    row: Vec<String>,
+    rows: Vec<String>,
+}
+
+/// A list item: its text and its nested items', already read
+#[derive(Default)]
+struct Item {
+    text: String,
+    nested: Vec<String>,
+}
+
+impl Reader {
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => self.code = true,
+            Event::End(TagEnd::CodeBlock) => {
+                self.code = false;
+                if self.announce_code {
+                    self.push("Code block omitted.".to_string());
+                }
+            }
+            _ if self.code => {}
+            Event::Text(text) if self.autolink => {
+                self.flush_text();
+                self.inline.push_str(&host(&text));
+            }
+            Event::Text(text) => self.text.push_str(&text),
+            // Code spans are read as written
+            Event::Code(code) => {
+                self.flush_text();
+                self.inline.push_str(code.trim());
+            }
+            Event::SoftBreak | Event::HardBreak => self.text.push(' '),
+            Event::Start(Tag::Link { link_type: LinkType::Autolink, .. }) => self.autolink = true,
+            Event::End(TagEnd::Link) => self.autolink = false,
+            Event::Html(markup) => self.html.push_str(&markup),
+            Event::End(TagEnd::HtmlBlock) => {
+                let markup = std::mem::take(&mut self.html);
+                self.push(html::to_speech(&markup, None).unwrap_or_default());
+            }
+            Event::End(TagEnd::Paragraph) => {
+                let text = self.take();
+                self.block(text);
+            }
+            // A heading is read as a sentence of its own; the paragraph
+            // break after it gives the pause
+            Event::End(TagEnd::Heading(_)) => {
+                let text = sentence(&self.take());
+                self.block(text);
+            }
+            Event::Start(Tag::List(_)) => {
+                self.item_text();
+                self.lists.push(Vec::new());
+            }
+            Event::Start(Tag::Item) => {
+                if let Some(items) = self.lists.last_mut() {
+                    items.push(Item::default());
+                }
+            }
+            Event::End(TagEnd::Item) => self.item_text(),
+            Event::End(TagEnd::List(_)) => self.end_list(),
+            Event::End(TagEnd::TableCell) => {
+                let cell = self.take();
+                self.row.push(cell);
+            }
+            Event::End(TagEnd::TableHead) => self.header = std::mem::take(&mut self.row),
+            Event::End(TagEnd::TableRow) => {
+                let row = table_row(&self.header, &std::mem::take(&mut self.row));
+                self.rows.push(row);
+            }
+            Event::End(TagEnd::Table) => {
+                self.header.clear();
+                let rows: Vec<String> = std::mem::take(&mut self.rows).into_iter().filter(|r| !r.is_empty()).collect();
+                self.push(rows.join("\n"));
+            }
+            // Inline HTML tags, footnote markers, checkboxes and rules: nothing to read
+            _ => {}
+        }
+    }
+
+    fn flush_text(&mut self) {
+        self.inline.push_str(&shorten_urls(&self.text));
+        self.text.clear();
+    }
+
+    /// The inline text read since the last block, whitespace collapsed.
+    fn take(&mut self) -> String {
+        self.flush_text();
+        let text = self.inline.split_whitespace().collect::<Vec<_>>().join(" ");
+        self.inline.clear();
+        text
+    }
+
+    fn push(&mut self, text: String) {
+        let text = text.trim();
+        if !text.is_empty() {
+            self.out.push(text.to_string());
+        }
+    }
+
+    /// A block's text: a list item's, inside a list, else a block of its own.
+    fn block(&mut self, text: String) {
+        match self.lists.last_mut().and_then(|items| items.last_mut()) {
+            Some(item) => append(&mut item.text, &text),
+            None => self.push(text),
+        }
+    }
+
+    /// The text read so far into the current list item: that of a tight
+    /// list, whose items have no paragraphs.
+    fn item_text(&mut self) {
+        let text = self.take();
+        if let Some(item) = self.lists.last_mut().and_then(|items| items.last_mut()) {
+            append(&mut item.text, &text);
+        }
+    }
+
+    /// A list read as "First, ...; second, ...". Nested items join their
+    /// parent's.
+    fn end_list(&mut self) {
+        let items: Vec<String> = self
+            .lists
+            .pop()
+            .unwrap_or_default()
+            .iter()
+            .map(|item| {
+                let text = clause(&item.text);
+                let nested: Vec<String> = item.nested.iter().map(|n| clause(n)).filter(|n| !n.is_empty()).collect();
+                match nested.is_empty() {
+                    true => text,
+                    false => format!("{}: {}", text, nested.join(", ")),
+                }
+            })
+            .filter(|item| !item.is_empty())
+            .collect();
+        if let Some(parent) = self.lists.last_mut().and_then(|items| items.last_mut()) {
+            parent.nested.extend(items);
+            return;
+        }
+        let spoken = match items.as_slice() {
+            [] => String::new(),
+            [only] => sentence(only),
+            _ => {
+                let numbered: Vec<String> =
+                    items.iter().enumerate().map(|(n, item)| format!("{}, {}", text::ordinal(n as u64 + 1), item)).collect();
+                capitalize(&sentence(&numbered.join("; ")))
+            }
+        };
+        self.push(spoken);
+    }
+}
+
+fn append(to: &mut String, text: &str) {
+    if !text.is_empty() {
+        if !to.is_empty() {
+            to.push(' ');
+        }
+        to.push_str(text);
+    }
+}
+
+/// Bare URLs in `text` spoken as their site, trailing punctuation kept.
+fn shorten_urls(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(at) = rest.find("http") {
+        let starts_word = rest[..at].chars().next_back().is_none_or(|c| c.is_whitespace() || c == '(');
+        let (before, from) = rest.split_at(at);
+        out.push_str(before);
+        if !(starts_word && (from.starts_with("http://") || from.starts_with("https://"))) {
+            out.push_str("http");
+            rest = &from[4..];
+            continue;
+        }
+        let end = from.find(char::is_whitespace).unwrap_or(from.len());
+        let url = &from[..end];
+        let bare = url.trim_end_matches(['.', ',', ';', ':', ')', '!', '?']);
+        out.push_str(&host(bare));
+        out.push_str(&url[bare.len()..]);
+        rest = &from[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// A URL spoken as its site, e.g. "github.com".
+fn host(url: &str) -> String {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let host = rest.split(['/', '?', '#']).next().unwrap_or(rest);
+    host.trim_start_matches("www.").to_string()
+}
+
+/// One row as a sentence, each value after its column's header.
+fn table_row(header: &[String], row: &[String]) -> String {
+    let pairs: Vec<String> = row
+        .iter()
+        .enumerate()
+        .map(|(i, cell)| (header.get(i).map_or("", String::as_str), cell.as_str()))
+        .filter(|(_, cell)| !cell.is_empty())
+        .map(|(head, cell)| match head.is_empty() {
+            true => cell.to_string(),
+            false => format!("{}: {}", head, cell),
+        })
+        .collect();
+    match pairs.is_empty() {
+        true => String::new(),
+        false => sentence(&pairs.join(", ")),
+    }
+}
+
+/// `text` ending in a full stop unless it already ends a sentence.
+fn sentence(text: &str) -> String {
+    let text = text.trim();
+    match text.is_empty() || text.ends_with(['.', '!', '?', ':', ';', '…']) {
+        true => text.to_string(),
+        false => format!("{}.", text),
+    }
+}
+
+/// `text` without its closing punctuation, to sit inside a longer sentence.
+fn clause(text: &str) -> String {
+    text.trim().trim_end_matches(['.', ';', ',']).to_string()
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    chars.next().map_or_else(String::new, |first| first.to_uppercase().chain(chars).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const README: &str = r#"# speakturbo
+
+[![CI](https://github.com/x/y/badge.svg)](https://github.com/x/y/actions)
+
+Ultra-fast **text-to-speech** for the `terminal`. See [the docs](https://example.com/docs "Docs")
+or https://speakturbo.dev/install.
+
+Installation
+------------
+
+```bash
+curl -sSL https://example.com/install.sh | sh
+```
+
+- Fast: *90ms* to first sound.
+- Offline
+  - no account
+  - no network
+- [x] Works on Linux
+
+1. Start the daemon.
+2. Run `speakturbo "Hello"`.
+
+| Voice | Accent | Notes |
+|-------|:------:|-------|
+| alba  | Scottish | default |
+| javert | French \| Parisian | |
+
+<p align="center"><a href="https://example.com"><img src="logo.png" alt="Logo"></a> Home</p>
+
+> **Note:** snake_case_names and 2 * 3 stay as written[^1]. Mail <me@example.com>.
+
+[^1]: Footnotes are read at the end.
+
+[docs]: https://example.com/docs
+"#;
+
+    #[test]
+    fn reads_a_readme_as_prose() {
+        let expected = "speakturbo.
+
+CI
+
+Ultra-fast text-to-speech for the terminal. See the docs or speakturbo.dev.
+
+Installation.
+
+First, Fast: 90ms to first sound; second, Offline: no account, no network; third, Works on Linux.
+
+First, Start the daemon; second, Run speakturbo \"Hello\".
+
+Voice: alba, Accent: Scottish, Notes: default.
+Voice: javert, Accent: French | Parisian.
+
+Logo Home
+
+Note: snake_case_names and 2 * 3 stay as written. Mail me@example.com.
+
+Footnotes are read at the end.";
+        assert_eq!(to_speech(README, false), expected);
+    }
+
+    #[test]
+    fn announces_code_blocks_when_asked() {
+        let markdown = "Before.\n\n    indented code\n\n~~~\nfenced\n~~~\n\nAfter.";
+        assert_eq!(to_speech(markdown, false), "Before.\n\nAfter.");
+        assert_eq!(to_speech(markdown, true), "Before.\n\nCode block omitted.\n\nCode block omitted.\n\nAfter.");
+        assert!(is_markdown_path("docs/README.MD"));
+        assert!(!is_markdown_path("notes.txt"));
+    }
+}
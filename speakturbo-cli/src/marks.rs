@@ -0,0 +1,30 @@
+//! The ⚡/▶/✓ timing lines, printed from a stream's events: each says
+//! when what it marks happened, as the reader or playback saw it, not when
+//! the main thread got round to noticing. They print on the events'
+//! thread, so the audio thread only ever sends.
+
+use crate::reporter::{self, Mark};
+use speakturbo_client::events::{Events, SpeakEvent};
+use std::sync::OnceLock;
+
+/// Where a stream's events go: the invocation's printer, or nowhere when
+/// `quiet`.
+pub fn printer(quiet: bool) -> Events {
+    static PRINTER: OnceLock<Events> = OnceLock::new();
+    match quiet {
+        true => Events::none(),
+        false => PRINTER.get_or_init(|| Events::callback(print)).clone(),
+    }
+}
+
+fn print(event: SpeakEvent) {
+    match event {
+        SpeakEvent::FirstByte { ms } => reporter::timing_at(Mark::First, ms),
+        SpeakEvent::FirstSample { ms } => reporter::timing_at(Mark::Playing, ms),
+        SpeakEvent::Finished { ms, .. } => reporter::timing_at(Mark::Done, ms),
+        SpeakEvent::ChunkStarted { index } => tracing::info!(target: "events", "part {} started", index + 1),
+        SpeakEvent::Underrun => tracing::debug!(target: "events", "playback ran dry"),
+        SpeakEvent::Error { kind, message } => tracing::debug!(target: "events", "{:?}: {}", kind, message),
+        _ => {}
+    }
+}
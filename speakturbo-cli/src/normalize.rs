@@ -0,0 +1,115 @@
+//! Default cleanup of input text (`--no-normalize` turns it off): the
+//! invisible characters, decomposed accents and ragged whitespace that text
+//! copied from PDFs and web pages brings along.
+
+use icu_normalizer::ComposingNormalizerBorrowed;
+
+/// Zero-width characters, byte-order marks and soft hyphens: invisible on
+/// screen, but heard as odd pauses.
+pub const INVISIBLE: &[char] = &['\u{ad}', '\u{180e}', '\u{200b}', '\u{200c}', '\u{200d}', '\u{2060}', '\u{feff}'];
+
+/// `text` with invisible characters removed, composed to NFC, and with
+/// whitespace collapsed; paragraph breaks stay, as sentence boundaries.
+/// With `ascii_punctuation`, typographic quotes and dashes become ASCII.
+pub fn normalize(text: &str, ascii_punctuation: bool) -> String {
+    let visible: String = text.chars().filter(|c| !INVISIBLE.contains(c)).collect();
+    let composed = ComposingNormalizerBorrowed::new_nfc().normalize(&visible);
+    match ascii_punctuation {
+        true => paragraphs(&ascii(&composed)),
+        false => paragraphs(&composed),
+    }
+}
+
+/// One space between words, and a blank line between paragraphs. Every
+/// paragraph but the last ends a sentence, so it's read as one.
+fn paragraphs(text: &str) -> String {
+    let mut paragraphs = Vec::new();
+    let mut current = String::new();
+    for line in text.lines().chain([""]) {
+        if line.trim().is_empty() {
+            if !current.is_empty() {
+                paragraphs.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        for word in line.split_whitespace() {
+            if !current.is_empty() {
+                current.push(' ');
+            }
+            current.push_str(word);
+        }
+    }
+    let last = paragraphs.len().saturating_sub(1);
+    for paragraph in &mut paragraphs[..last] {
+        let end = paragraph.trim_end_matches(['"', '\'', ')', ']', '”', '’', '»']);
+        if !end.ends_with(['.', '!', '?', ':', ';', '…', '。', '！', '？']) {
+            paragraph.push('.');
+        }
+    }
+    paragraphs.join("\n\n")
+}
+
+fn ascii(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '‘' | '’' | '‚' | '‛' | '′' => out.push('\''),
+            '“' | '”' | '„' | '‟' | '″' | '«' | '»' => out.push('"'),
+            '‐' | '‑' | '‒' | '–' | '−' => out.push('-'),
+            // A dash between words is a pause, not a hyphen
+            '—' | '―' => out.push_str(" - "),
+            '…' => out.push_str("..."),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(cases: &[(&str, &str)], ascii_punctuation: bool) {
+        for (input, expected) in cases {
+            assert_eq!(normalize(input, ascii_punctuation), *expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn removes_invisible_characters() {
+        check(
+            &[
+                ("\u{feff}Hello", "Hello"),
+                ("zero\u{200b}width", "zerowidth"),
+                ("hyphen\u{ad}ation", "hyphenation"),
+                ("word\u{2060}joiner\u{feff}", "wordjoiner"),
+            ],
+            false,
+        );
+    }
+
+    #[test]
+    fn composes_accents() {
+        check(&[("cafe\u{301}", "café"), ("A\u{30a}ngstro\u{308}m", "Ångström"), ("ﬁ", "ﬁ")], false);
+    }
+
+    #[test]
+    fn collapses_whitespace_but_keeps_paragraphs() {
+        check(
+            &[
+                ("  lots \t of\u{a0}space  ", "lots of space"),
+                ("wrapped\nline\r\nbreaks", "wrapped line breaks"),
+                ("Heading\n\n\n  \nBody text", "Heading.\n\nBody text"),
+                ("Done!\n\n\"Quoted.\"\n\nlast", "Done!\n\n\"Quoted.\"\n\nlast"),
+                ("", ""),
+            ],
+            false,
+        );
+    }
+
+    #[test]
+    fn maps_punctuation_to_ascii_on_request() {
+        check(&[("“It’s—well…”", "\"It's - well...\""), ("pages 3–5, «non»", "pages 3-5, \"non\"")], true);
+        check(&[("“It’s—well…”", "“It’s—well…”")], false);
+    }
+}
@@ -0,0 +1,104 @@
+//! `--notify` desktop notifications. Best effort: a missing notification
+//! service is not an error.
+
+use crate::hooks::Status;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+/// Summary line for the finished job, e.g. "SpeakTurbo: saved digest.wav, 12m34s".
+pub fn summary(status: Status, output: Option<&str>, elapsed: Duration, error: Option<&str>) -> String {
+    let took = span(elapsed);
+    match (status, output, error) {
+        (Status::Interrupted, _, _) => format!("SpeakTurbo: interrupted after {}", took),
+        (Status::Error, _, Some(e)) => format!("SpeakTurbo: {}", e),
+        (Status::Error, _, None) => "SpeakTurbo: failed".to_string(),
+        (Status::Ok, Some(path), _) => {
+            let name = std::path::Path::new(path)
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.to_string());
+            format!("SpeakTurbo: saved {}, {}", name, took)
+        }
+        (Status::Ok, None, _) => format!("SpeakTurbo: done, {}", took),
+    }
+}
+
+fn span(d: Duration) -> String {
+    let secs = d.as_secs();
+    match secs {
+        0..=59 => format!("{:.1}s", d.as_secs_f64()),
+        60..=3599 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+/// Show a notification; silently does nothing if no service is available.
+pub fn send(summary: &str, body: &str) {
+    for mut command in commands(summary, body) {
+        let ok = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map(|s| s.success())
+            .unwrap_or(false);
+        if ok {
+            return;
+        }
+    }
+}
+
+/// freedesktop Notifications via notify-send, or straight over D-Bus with gdbus.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn commands(summary: &str, body: &str) -> Vec<Command> {
+    let mut notify_send = Command::new("notify-send");
+    notify_send.args(["--app-name=SpeakTurbo", summary, body]);
+
+    let mut gdbus = Command::new("gdbus");
+    gdbus.args([
+        "call",
+        "--session",
+        "--dest=org.freedesktop.Notifications",
+        "--object-path=/org/freedesktop/Notifications",
+        "--method=org.freedesktop.Notifications.Notify",
+        "SpeakTurbo",
+        "0",
+        "",
+        &gvariant_string(summary),
+        &gvariant_string(body),
+        "[]",
+        "{}",
+        "-1",
+    ]);
+    vec![notify_send, gdbus]
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn gvariant_string(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+#[cfg(target_os = "macos")]
+fn commands(summary: &str, body: &str) -> Vec<Command> {
+    let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+    let script = format!("display notification {} with title {}", quote(body), quote(summary));
+    let mut osascript = Command::new("osascript");
+    osascript.arg("-e").arg(script);
+    vec![osascript]
+}
+
+#[cfg(not(unix))]
+fn commands(_summary: &str, _body: &str) -> Vec<Command> {
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_names_the_saved_file() {
+        let s = summary(Status::Ok, Some("/tmp/out/digest.wav"), Duration::from_secs(754), None);
+        assert_eq!(s, "SpeakTurbo: saved digest.wav, 12m34s");
+    }
+}
@@ -0,0 +1,360 @@
+//! `speakturbo notify-listen`: desktop notifications read aloud as they
+//! appear. dbus-monitor watches the session bus for Notify calls to
+//! org.freedesktop.Notifications, as a monitor where the bus allows it and
+//! by eavesdropping where it doesn't, so the notification daemon still
+//! shows them. Each is put in words from a template and spoken through the
+//! same queue as `--stream-lines`, cutting off the one before unless
+//! --queue. The config's `[notifications]` picks the apps:
+//!
+//! ```toml
+//! [notifications]
+//! include = ["Slack", "Thunderbird"]   # only these (default: all)
+//! exclude = ["Spotify"]
+//! template = "{app} says {summary}"    # {app}, {summary} and {body}
+//! ```
+
+use crate::config::Notifications;
+use crate::exit::{self, Kind};
+use crate::stream::{self, Record};
+use crate::{interrupt, reporter, Args};
+use anyhow::{bail, Result};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, ErrorKind};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+pub const TEMPLATE: &str = "{app}: {summary}. {body}";
+
+/// The Notify calls, and what the bus says of them
+const RULE: &str = "type='method_call',interface='org.freedesktop.Notifications',member='Notify'";
+
+/// What --notify shows, never read back
+const OWN_APP: &str = "SpeakTurbo";
+
+/// The urgency hint, as the spec numbers it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+pub enum Urgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Notification {
+    pub app: String,
+    pub summary: String,
+    pub body: String,
+    /// Normal when the hint is missing
+    pub urgency: Urgency,
+}
+
+pub struct Options {
+    pub template: Option<String>,
+    pub min_urgency: Urgency,
+    /// Identical notifications within this long of each other are spoken once
+    pub dedupe: Duration,
+}
+
+pub fn run(args: &Args, options: &Options, start: Instant) -> Result<()> {
+    let mut monitor = Command::new("dbus-monitor");
+    monitor.args(["--session", RULE]).stdin(Stdio::null()).stdout(Stdio::piped());
+    let mut child = match monitor.spawn() {
+        Ok(child) => child,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Err(exit::fail(Kind::Usage, "notify-listen needs dbus-monitor (from the dbus tools) on PATH"));
+        }
+        Err(e) => bail!("Cannot run dbus-monitor: {}", e),
+    };
+    // SIGTERM or Ctrl+C ends the monitor too, and with it the records
+    interrupt::forward_to(child.id());
+    let output = child.stdout.take().expect("piped");
+    let template = options.template.clone().or_else(|| args.notifications.template.clone()).unwrap_or_else(|| TEMPLATE.to_string());
+    let mut filter = Filter::new(&args.notifications, options.min_urgency, options.dedupe);
+    let (tx, records) = mpsc::sync_channel(1);
+    std::thread::spawn(move || {
+        let mut parser = Parser::default();
+        let mut n = 0;
+        for line in BufReader::new(output).lines() {
+            let Ok(line) = line else { return };
+            let Some(notification) = parser.line(&line) else { continue };
+            n += 1;
+            let text = said(&template, &notification);
+            if !filter.admits(&notification, &text, Instant::now()) {
+                continue;
+            }
+            if tx.send(Record { n, text: Ok(text) }).is_err() {
+                return;
+            }
+        }
+    });
+    reporter::info(format_args!("Listening for notifications (Ctrl+C to stop)"));
+    let spoken = stream::run(args, records, !args.queue, start);
+    let _ = child.kill();
+    let status = child.wait()?;
+    spoken?;
+    if !interrupt::requested() && !status.success() {
+        bail!("dbus-monitor stopped ({}); is there a session bus?", status);
+    }
+    Ok(())
+}
+
+/// `template` with {app}, {summary} and {body} filled in, and the seams
+/// an empty one or the summary's own punctuation leaves tidied up.
+pub fn said(template: &str, notification: &Notification) -> String {
+    let filled = template
+        .replace("{app}", notification.app.trim())
+        .replace("{summary}", notification.summary.trim())
+        .replace("{body}", &plain(&notification.body));
+    let mut out = String::with_capacity(filled.len());
+    for word in filled.split_whitespace() {
+        // What an empty {summary} or {body} leaves: "Slack: ."
+        if word == "." && (out.is_empty() || out.ends_with(['.', '!', '?', ':'])) {
+            continue;
+        }
+        // The template's period after the summary's own: "Done!." or "Done.."
+        let doubled = word.strip_suffix('.').is_some_and(|w| w.ends_with(['!', '?', ':', '.'])) && !word.ends_with("...");
+        let word = if doubled { &word[..word.len() - 1] } else { word };
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        out.push_str(word);
+    }
+    out.trim_end_matches(':').to_string()
+}
+
+/// A body as speech: the markup notification daemons allow taken out.
+fn plain(body: &str) -> String {
+    crate::html::Document::parse(body).text()
+}
+
+/// Which notifications are spoken
+struct Filter {
+    /// Lowercased; empty for all
+    include: Vec<String>,
+    exclude: Vec<String>,
+    min_urgency: Urgency,
+    dedupe: Duration,
+    /// Texts spoken within the last `dedupe`, oldest first
+    recent: VecDeque<(String, Instant)>,
+}
+
+impl Filter {
+    fn new(config: &Notifications, min_urgency: Urgency, dedupe: Duration) -> Filter {
+        let lower = |apps: &[String]| apps.iter().map(|app| app.to_lowercase()).collect();
+        Filter { include: lower(&config.include), exclude: lower(&config.exclude), min_urgency, dedupe, recent: VecDeque::new() }
+    }
+
+    /// Whether `notification`, in words `text`, is to be spoken at `now`;
+    /// if so, a repeat of it isn't for a while.
+    fn admits(&mut self, notification: &Notification, text: &str, now: Instant) -> bool {
+        let app = notification.app.to_lowercase();
+        if app == OWN_APP.to_lowercase() || self.exclude.contains(&app) || !(self.include.is_empty() || self.include.contains(&app)) {
+            return false;
+        }
+        if notification.urgency < self.min_urgency || text.is_empty() {
+            return false;
+        }
+        while self.recent.front().is_some_and(|(_, at)| now.duration_since(*at) >= self.dedupe) {
+            self.recent.pop_front();
+        }
+        if self.recent.iter().any(|(said, _)| said == text) {
+            return false;
+        }
+        self.recent.push_back((text.to_string(), now));
+        true
+    }
+}
+
+/// dbus-monitor's output, a line at a time. A message is a header line,
+/// then its arguments three spaces in, what they hold further in:
+///
+/// ```text
+/// method call time=1700000000.1 sender=:1.7 -> destination=:1.3 serial=9 path=/org/freedesktop/Notifications; interface=org.freedesktop.Notifications; member=Notify
+///    string "Slack"
+///    uint32 0
+///    string ""
+///    string "New message"
+///    string "Ada: lunch?"
+///    array [
+///    ]
+///    array [
+///       dict entry(
+///          string "urgency"
+///          variant             byte 1
+///       )
+///    ]
+///    int32 -1
+/// ```
+///
+/// Strings are printed as they are, so one with newlines runs on over
+/// lines of its own until a closing quote ends one.
+#[derive(Default)]
+struct Parser {
+    /// Within a Notify call
+    notify: bool,
+    /// The call's arguments so far, strings' text and other types' names
+    args: Vec<String>,
+    /// A string running on: its text so far, and whether it's an argument
+    open: Option<(String, bool)>,
+    /// The hint whose value comes next
+    hint: Option<String>,
+    urgency: Option<Urgency>,
+}
+
+impl Parser {
+    /// The notification `line` completes, if it does.
+    fn line(&mut self, line: &str) -> Option<Notification> {
+        if let Some((mut text, argument)) = self.open.take() {
+            text.push('\n');
+            match line.strip_suffix('"') {
+                Some(end) => {
+                    text.push_str(end);
+                    self.string(text, argument);
+                }
+                None => {
+                    text.push_str(line);
+                    self.open = Some((text, argument));
+                }
+            }
+            return None;
+        }
+        let item = line.trim_start();
+        let depth = line.len() - item.len();
+        if depth == 0 {
+            *self = Parser::default();
+            self.notify = item.starts_with("method call") && item.contains("interface=org.freedesktop.Notifications;") && item.ends_with("member=Notify");
+            return None;
+        }
+        if !self.notify || item.starts_with(']') || item.starts_with(')') {
+            return None;
+        }
+        let argument = depth == 3;
+        if let Some(rest) = item.strip_prefix("string \"") {
+            match rest.strip_suffix('"') {
+                Some(text) => self.string(text.to_string(), argument),
+                None => self.open = Some((rest.to_string(), argument)),
+            }
+            return None;
+        }
+        if let (Some(value), Some("urgency")) = (item.strip_prefix("variant"), self.hint.as_deref()) {
+            self.urgency = match value.split_whitespace().last() {
+                Some("0") => Some(Urgency::Low),
+                Some("2") => Some(Urgency::Critical),
+                _ => Some(Urgency::Normal),
+            };
+            return None;
+        }
+        if !argument {
+            return None;
+        }
+        self.args.push(item.split_whitespace().next().unwrap_or_default().to_string());
+        // The timeout is the last of the eight
+        if self.args.len() < 8 {
+            return None;
+        }
+        self.notify = false;
+        let args = std::mem::take(&mut self.args);
+        Some(Notification {
+            app: args[0].clone(),
+            summary: args[3].clone(),
+            body: args[4].clone(),
+            urgency: self.urgency.take().unwrap_or(Urgency::Normal),
+        })
+    }
+
+    fn string(&mut self, text: String, argument: bool) {
+        match argument {
+            true => self.args.push(text),
+            // Within the hints, a dict entry's key
+            false => self.hint = Some(text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MONITORED: &str = "signal time=1700000000.0 sender=org.freedesktop.DBus -> destination=:1.9 serial=2 path=/org/freedesktop/DBus; interface=org.freedesktop.DBus; member=NameAcquired
+   string \":1.9\"
+method call time=1700000000.1 sender=:1.7 -> destination=:1.3 serial=9 path=/org/freedesktop/Notifications; interface=org.freedesktop.Notifications; member=Notify
+   string \"Slack\"
+   uint32 0
+   string \"\"
+   string \"New message\"
+   string \"<b>Ada</b>: lunch &amp;
+a walk?\"
+   array [
+      string \"default\"
+      string \"Open\"
+   ]
+   array [
+      dict entry(
+         string \"urgency\"
+         variant             byte 2
+      )
+      dict entry(
+         string \"sender-pid\"
+         variant             int64 4242
+      )
+   ]
+   int32 -1
+method call time=1700000001.1 sender=:1.8 -> destination=:1.3 serial=4 path=/org/freedesktop/Notifications; interface=org.freedesktop.Notifications; member=Notify
+   string \"Thunderbird\"
+   uint32 0
+   string \"mail\"
+   string \"2 new messages\"
+   string \"\"
+   array [
+   ]
+   array [
+   ]
+   int32 5000
+";
+
+    fn notification(app: &str, summary: &str, body: &str, urgency: Urgency) -> Notification {
+        Notification { app: app.to_string(), summary: summary.to_string(), body: body.to_string(), urgency }
+    }
+
+    #[test]
+    fn reads_notify_calls_from_the_monitor() {
+        let mut parser = Parser::default();
+        let found: Vec<Notification> = MONITORED.lines().filter_map(|line| parser.line(line)).collect();
+        assert_eq!(
+            found,
+            [
+                notification("Slack", "New message", "<b>Ada</b>: lunch &amp;\na walk?", Urgency::Critical),
+                notification("Thunderbird", "2 new messages", "", Urgency::Normal),
+            ]
+        );
+    }
+
+    #[test]
+    fn puts_a_notification_in_words() {
+        let slack = notification("Slack", "New message", "<b>Ada</b>: lunch &amp;\na walk?", Urgency::Normal);
+        assert_eq!(said(TEMPLATE, &slack), "Slack: New message. Ada: lunch & a walk?");
+        assert_eq!(said(TEMPLATE, &notification("Make", "Build failed!", "", Urgency::Normal)), "Make: Build failed!");
+        assert_eq!(said("{app} says {summary}", &slack), "Slack says New message");
+    }
+
+    #[test]
+    fn filters_by_app_and_urgency_and_drops_repeats() {
+        let config = Notifications { include: Vec::new(), exclude: vec!["spotify".to_string()], template: None };
+        let mut filter = Filter::new(&config, Urgency::Normal, Duration::from_secs(2));
+        let now = Instant::now();
+        let mut admits = |n: &Notification, at: u64| filter.admits(n, &said(TEMPLATE, n), now + Duration::from_millis(at));
+        let mail = notification("Thunderbird", "2 new messages", "", Urgency::Normal);
+        assert!(admits(&mail, 0));
+        assert!(!admits(&mail, 1500), "a repeat within the window");
+        assert!(admits(&mail, 2500));
+        assert!(!admits(&notification("Spotify", "Now playing", "", Urgency::Normal), 0));
+        assert!(!admits(&notification("Updates", "3 available", "", Urgency::Low), 0));
+        assert!(!admits(&notification("SpeakTurbo", "SpeakTurbo: done, 1.2s", "", Urgency::Normal), 0));
+
+        let config = Notifications { include: vec!["Slack".to_string()], ..config };
+        let mut only_slack = Filter::new(&config, Urgency::Low, Duration::ZERO);
+        assert!(only_slack.admits(&notification("slack", "Hi", "", Urgency::Low), "Hi", now));
+        assert!(!only_slack.admits(&mail, "2 new messages", now));
+    }
+}
@@ -0,0 +1,460 @@
+//! `--normalize-numbers`: numbers written out as words before synthesis,
+//! for daemons that read "3.14" as "three dot one four". What the words
+//! are comes from a [`Language`] table; English is the only one so far.
+
+use crate::text;
+
+pub struct Currency {
+    pub symbol: char,
+    pub one: &'static str,
+    pub many: &'static str,
+    pub cent: &'static str,
+    pub cents: &'static str,
+}
+
+pub struct Unit {
+    pub symbol: &'static str,
+    pub one: &'static str,
+    pub many: &'static str,
+}
+
+/// How one language writes numbers, and reads them.
+pub struct Language {
+    pub thousands_separator: char,
+    pub decimal_separator: char,
+    pub cardinal: fn(u64) -> String,
+    pub ordinal: fn(u64) -> String,
+    /// A four-digit number read as a year, if it's in the range read so
+    pub year: fn(u64) -> Option<String>,
+    /// Hours and minutes of a clock time
+    pub time: fn(u64, u64) -> String,
+    pub point: &'static str,
+    pub minus: &'static str,
+    pub percent: &'static str,
+    /// Between the whole and cent amounts of money
+    pub and: &'static str,
+    /// Endings that make a number ordinal, as in "3rd"
+    pub ordinal_suffixes: &'static [&'static str],
+    /// Words that multiply an amount, as in "$5 million"
+    pub scales: &'static [&'static str],
+    pub currencies: &'static [Currency],
+    /// Units; one-letter symbols only count when written against the number
+    pub units: &'static [Unit],
+}
+
+pub const ENGLISH: Language = Language {
+    thousands_separator: ',',
+    decimal_separator: '.',
+    cardinal: text::cardinal,
+    ordinal: text::ordinal,
+    year: english_year,
+    time: english_time,
+    point: "point",
+    minus: "minus",
+    percent: "percent",
+    and: "and",
+    ordinal_suffixes: &["st", "nd", "rd", "th"],
+    scales: &["thousand", "million", "billion", "trillion"],
+    currencies: &[
+        Currency { symbol: '$', one: "dollar", many: "dollars", cent: "cent", cents: "cents" },
+        Currency { symbol: '€', one: "euro", many: "euros", cent: "cent", cents: "cents" },
+        Currency { symbol: '£', one: "pound", many: "pounds", cent: "penny", cents: "pence" },
+        Currency { symbol: '¥', one: "yen", many: "yen", cent: "sen", cents: "sen" },
+    ],
+    units: &[
+        Unit { symbol: "B", one: "byte", many: "bytes" },
+        Unit { symbol: "KB", one: "kilobyte", many: "kilobytes" },
+        Unit { symbol: "kB", one: "kilobyte", many: "kilobytes" },
+        Unit { symbol: "MB", one: "megabyte", many: "megabytes" },
+        Unit { symbol: "GB", one: "gigabyte", many: "gigabytes" },
+        Unit { symbol: "TB", one: "terabyte", many: "terabytes" },
+        Unit { symbol: "KiB", one: "kibibyte", many: "kibibytes" },
+        Unit { symbol: "MiB", one: "mebibyte", many: "mebibytes" },
+        Unit { symbol: "GiB", one: "gibibyte", many: "gibibytes" },
+        Unit { symbol: "Mbps", one: "megabit per second", many: "megabits per second" },
+        Unit { symbol: "mm", one: "millimeter", many: "millimeters" },
+        Unit { symbol: "cm", one: "centimeter", many: "centimeters" },
+        Unit { symbol: "m", one: "meter", many: "meters" },
+        Unit { symbol: "km", one: "kilometer", many: "kilometers" },
+        Unit { symbol: "km/h", one: "kilometer per hour", many: "kilometers per hour" },
+        Unit { symbol: "mph", one: "mile per hour", many: "miles per hour" },
+        Unit { symbol: "mi", one: "mile", many: "miles" },
+        Unit { symbol: "ft", one: "foot", many: "feet" },
+        Unit { symbol: "mg", one: "milligram", many: "milligrams" },
+        Unit { symbol: "g", one: "gram", many: "grams" },
+        Unit { symbol: "kg", one: "kilogram", many: "kilograms" },
+        Unit { symbol: "lb", one: "pound", many: "pounds" },
+        Unit { symbol: "lbs", one: "pound", many: "pounds" },
+        Unit { symbol: "oz", one: "ounce", many: "ounces" },
+        Unit { symbol: "ml", one: "milliliter", many: "milliliters" },
+        Unit { symbol: "L", one: "liter", many: "liters" },
+        Unit { symbol: "ms", one: "millisecond", many: "milliseconds" },
+        Unit { symbol: "s", one: "second", many: "seconds" },
+        Unit { symbol: "min", one: "minute", many: "minutes" },
+        Unit { symbol: "h", one: "hour", many: "hours" },
+        Unit { symbol: "Hz", one: "hertz", many: "hertz" },
+        Unit { symbol: "kHz", one: "kilohertz", many: "kilohertz" },
+        Unit { symbol: "MHz", one: "megahertz", many: "megahertz" },
+        Unit { symbol: "GHz", one: "gigahertz", many: "gigahertz" },
+        Unit { symbol: "W", one: "watt", many: "watts" },
+        Unit { symbol: "kW", one: "kilowatt", many: "kilowatts" },
+        Unit { symbol: "kWh", one: "kilowatt hour", many: "kilowatt hours" },
+        Unit { symbol: "V", one: "volt", many: "volts" },
+        Unit { symbol: "mAh", one: "milliamp hour", many: "milliamp hours" },
+        Unit { symbol: "°C", one: "degree Celsius", many: "degrees Celsius" },
+        Unit { symbol: "°F", one: "degree Fahrenheit", many: "degrees Fahrenheit" },
+        Unit { symbol: "°", one: "degree", many: "degrees" },
+    ],
+};
+
+/// 1999 -> "nineteen ninety-nine", 1905 -> "nineteen oh five",
+/// 2005 -> "two thousand five", 2010 -> "twenty ten".
+fn english_year(year: u64) -> Option<String> {
+    if !(1100..=2099).contains(&year) {
+        return None;
+    }
+    let (century, rest) = (year / 100, year % 100);
+    Some(match rest {
+        _ if (2000..2010).contains(&year) => text::cardinal(year),
+        0 => format!("{} hundred", text::cardinal(century)),
+        1..=9 => format!("{} oh {}", text::cardinal(century), text::cardinal(rest)),
+        _ => format!("{} {}", text::cardinal(century), text::cardinal(rest)),
+    })
+}
+
+/// 14:30 -> "fourteen thirty", 9:05 -> "nine oh five", 10:00 -> "ten o'clock".
+fn english_time(hours: u64, minutes: u64) -> String {
+    match minutes {
+        0 if hours <= 12 => format!("{} o'clock", text::cardinal(hours)),
+        0 => format!("{} hundred", text::cardinal(hours)),
+        1..=9 => format!("{} oh {}", text::cardinal(hours), text::cardinal(minutes)),
+        _ => format!("{} {}", text::cardinal(hours), text::cardinal(minutes)),
+    }
+}
+
+/// `text` with its numbers in words. Numbers that are part of something
+/// else, like "mp3", "v1.2.3" or "192.168.0.1", are left alone.
+pub fn expand(text: &str, language: &Language) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let after_space = i == 0 || chars[i - 1].is_whitespace() || chars[i - 1] == '(';
+        let negative = chars[i] == '-' && after_space && chars.get(i + 1).is_some_and(char::is_ascii_digit);
+        let reading = match negative {
+            true => read(&chars, i + 1, language).map(|(words, len)| (format!("{} {}", language.minus, words), len + 1)),
+            false => read(&chars, i, language),
+        };
+        if let Some((words, len)) = reading {
+            out.push_str(&words);
+            i += len;
+            continue;
+        }
+        // Anything else is copied through, a word (and any digits in it) at a time
+        let word = i + word_len(&chars[i..]).max(1);
+        out.extend(&chars[i..word]);
+        i = word;
+    }
+    out
+}
+
+/// Characters in the word starting `chars`: letters and digits, joined by
+/// punctuation as in "v1.2.3", "COVID-19" or "example.com/page2".
+fn word_len(chars: &[char]) -> usize {
+    let mut len = 0;
+    while len < chars.len() {
+        let joined = ".,:/-_".contains(chars[len]) && len > 0 && chars.get(len + 1).is_some_and(|c| c.is_alphanumeric());
+        if !(chars[len].is_alphanumeric() || joined) {
+            break;
+        }
+        len += 1;
+    }
+    len
+}
+
+/// A number written as digits, from its separators.
+struct Number {
+    digits: String,
+    fraction: Option<String>,
+    grouped: bool,
+}
+
+impl Number {
+    fn value(&self) -> Option<u64> {
+        self.digits.parse().ok().filter(|_| self.digits.len() <= 18)
+    }
+
+    fn is_one(&self) -> bool {
+        self.digits == "1" && self.fraction.is_none()
+    }
+
+    /// Cardinal words; digit by digit with leading zeros ("007") and after
+    /// the point.
+    fn words(&self, language: &Language) -> String {
+        let one_by_one = |digits: &str| {
+            let words: Vec<String> = digits.chars().map(|d| (language.cardinal)(d.to_digit(10).unwrap_or(0) as u64)).collect();
+            words.join(" ")
+        };
+        let whole = match self.value() {
+            Some(n) if !(self.digits.starts_with('0') && self.digits.len() > 1) => (language.cardinal)(n),
+            _ => one_by_one(&self.digits),
+        };
+        match &self.fraction {
+            Some(fraction) => format!("{} {} {}", whole, language.point, one_by_one(fraction)),
+            None => whole,
+        }
+    }
+}
+
+/// The digits at `chars[at..]`: groups of three after thousands separators
+/// and a fraction after the decimal separator. Also returns its length.
+fn number(chars: &[char], at: usize, language: &Language) -> Option<(Number, usize)> {
+    let digit = |i: usize| chars.get(i).is_some_and(char::is_ascii_digit);
+    let mut i = at;
+    while digit(i) {
+        i += 1;
+    }
+    if i == at {
+        return None;
+    }
+    let mut digits: String = chars[at..i].iter().collect();
+    let mut grouped = false;
+    while chars.get(i) == Some(&language.thousands_separator)
+        && (i + 1..i + 4).all(digit)
+        && !digit(i + 4)
+        && (grouped || digits.len() <= 3)
+    {
+        digits.extend(&chars[i + 1..i + 4]);
+        grouped = true;
+        i += 4;
+    }
+    let mut fraction = None;
+    if chars.get(i) == Some(&language.decimal_separator) && digit(i + 1) {
+        let start = i + 1;
+        i = start;
+        while digit(i) {
+            i += 1;
+        }
+        fraction = Some(chars[start..i].iter().collect());
+    }
+    Some((Number { digits, fraction, grouped }, i - at))
+}
+
+/// The letters (or unit symbol characters) at `chars[at..]`.
+fn suffix(chars: &[char], at: usize) -> String {
+    chars[at.min(chars.len())..]
+        .iter()
+        .take_while(|c| c.is_alphabetic() || **c == '°' || **c == '/')
+        .collect()
+}
+
+/// The number at `chars[at..]` in words, and how many characters it took.
+fn read(chars: &[char], at: usize, language: &Language) -> Option<(String, usize)> {
+    if at > 0 && (chars[at - 1].is_alphanumeric() || chars[at - 1] == '_') {
+        return None;
+    }
+    if let Some(currency) = language.currencies.iter().find(|c| chars.get(at) == Some(&c.symbol)) {
+        let (n, len) = number(chars, at + 1, language)?;
+        return money(chars, at + 1 + len, &n, currency, language).map(|(words, rest)| (words, 1 + len + rest));
+    }
+    let (n, len) = number(chars, at, language)?;
+    let end = at + len;
+    let next = chars.get(end).copied();
+    // "1.2.3", "192.168.0.1": not a number to read
+    if next.is_some_and(|c| c == language.decimal_separator || c == language.thousands_separator)
+        && chars.get(end + 1).is_some_and(char::is_ascii_digit)
+    {
+        return None;
+    }
+
+    if next == Some('%') {
+        return Some((format!("{} {}", n.words(language), language.percent), len + 1));
+    }
+    if next == Some(':') && n.fraction.is_none() && !n.grouped {
+        return clock(chars, at, &n, language);
+    }
+    let letters = suffix(chars, end);
+    if !letters.is_empty() {
+        let lower = letters.to_lowercase();
+        if n.fraction.is_none() && language.ordinal_suffixes.contains(&lower.as_str()) {
+            return Some(((language.ordinal)(n.value()?), len + letters.chars().count()));
+        }
+        let unit = language.units.iter().find(|u| u.symbol == letters)?;
+        return Some((measure(&n, unit, language), len + letters.chars().count()));
+    }
+    if next == Some(' ') {
+        let spaced = suffix(chars, end + 1);
+        let unit = language.units.iter().find(|u| u.symbol == spaced && u.symbol.chars().count() > 1);
+        let bounded = !chars.get(end + 1 + spaced.chars().count()).is_some_and(|c| c.is_alphanumeric());
+        if let (Some(unit), true) = (unit, bounded) {
+            return Some((measure(&n, unit, language), len + 1 + spaced.chars().count()));
+        }
+    }
+
+    let year = match (n.value(), n.grouped, &n.fraction, n.digits.len()) {
+        (Some(y), false, None, 4) => (language.year)(y),
+        _ => None,
+    };
+    Some((year.unwrap_or_else(|| n.words(language)), len))
+}
+
+fn measure(n: &Number, unit: &Unit, language: &Language) -> String {
+    format!("{} {}", n.words(language), if n.is_one() { unit.one } else { unit.many })
+}
+
+/// "14:30"; hours and minutes only, so "1:2:3" and "12:34:56" are left alone.
+fn clock(chars: &[char], at: usize, hours: &Number, language: &Language) -> Option<(String, usize)> {
+    let start = at + hours.digits.len() + 1;
+    let minutes: String = chars.get(start..start + 2)?.iter().collect();
+    let followed = chars.get(start + 2).copied();
+    if !minutes.chars().all(|c| c.is_ascii_digit()) || followed.is_some_and(|c| c.is_alphanumeric() || c == ':') {
+        return None;
+    }
+    let (h, m) = (hours.value()?, minutes.parse::<u64>().ok()?);
+    if h > 23 || m > 59 || hours.digits.len() > 2 {
+        return None;
+    }
+    Some(((language.time)(h, m), hours.digits.len() + 3))
+}
+
+/// "$5.20" -> "five dollars and twenty cents"; "$3 million" -> "three
+/// million dollars". `end` is where the number after the symbol ends.
+fn money(chars: &[char], end: usize, n: &Number, currency: &Currency, language: &Language) -> Option<(String, usize)> {
+    if chars.get(end).is_some_and(|c| c.is_alphanumeric()) {
+        return None;
+    }
+    let scale = (chars.get(end) == Some(&' '))
+        .then(|| suffix(chars, end + 1))
+        .filter(|word| language.scales.contains(&word.as_str()));
+    if let Some(scale) = scale {
+        let words = format!("{} {} {}", n.words(language), scale, currency.many);
+        return Some((words, 1 + scale.chars().count()));
+    }
+    let name = |one: bool| if one { currency.one } else { currency.many };
+    let words = match n.fraction.as_deref() {
+        Some(cents) if cents.len() == 2 => {
+            let cents = cents.parse::<u64>().ok()?;
+            let whole = n.value()?;
+            let cents_words = format!("{} {}", (language.cardinal)(cents), if cents == 1 { currency.cent } else { currency.cents });
+            match (whole, cents) {
+                (0, _) => cents_words,
+                (_, 0) => format!("{} {}", (language.cardinal)(whole), name(whole == 1)),
+                _ => format!("{} {} {} {}", (language.cardinal)(whole), name(whole == 1), language.and, cents_words),
+            }
+        }
+        _ => format!("{} {}", n.words(language), name(n.is_one())),
+    };
+    Some((words, 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(cases: &[(&str, &str)]) {
+        for (input, expected) in cases {
+            assert_eq!(expand(input, &ENGLISH), *expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn integers_and_decimals() {
+        check(&[
+            ("0", "zero"),
+            ("7 days", "seven days"),
+            ("42", "forty-two"),
+            ("1,024 files", "one thousand twenty-four files"),
+            ("1,234,567", "one million two hundred thirty-four thousand five hundred sixty-seven"),
+            ("3.14", "three point one four"),
+            ("0.5", "zero point five"),
+            ("1,000.25", "one thousand point two five"),
+            ("It was -5 outside", "It was minus five outside"),
+            ("007", "zero zero seven"),
+            ("12345678901234567890", "one two three four five six seven eight nine zero one two three four five six seven eight nine zero"),
+            ("pages 10-20", "pages ten-twenty"),
+            ("Pi is 3.14.", "Pi is three point one four."),
+        ]);
+    }
+
+    #[test]
+    fn ordinals_percentages_and_money() {
+        check(&[
+            ("the 3rd time", "the third time"),
+            ("21st century", "twenty-first century"),
+            ("1ST place", "first place"),
+            ("100th", "one hundredth"),
+            ("50%", "fifty percent"),
+            ("3.5% of it", "three point five percent of it"),
+            ("$5.20", "five dollars and twenty cents"),
+            ("$1.01", "one dollar and one cent"),
+            ("$1", "one dollar"),
+            ("$0.99", "ninety-nine cents"),
+            ("$5.00", "five dollars"),
+            ("$2.5", "two point five dollars"),
+            ("$1,500", "one thousand five hundred dollars"),
+            ("$3 million budget", "three million dollars budget"),
+            ("€20", "twenty euros"),
+            ("£1.50", "one pound and fifty pence"),
+        ]);
+    }
+
+    #[test]
+    fn years_and_times() {
+        check(&[
+            ("in 1999", "in nineteen ninety-nine"),
+            ("1905", "nineteen oh five"),
+            ("1900", "nineteen hundred"),
+            ("2000", "two thousand"),
+            ("2005", "two thousand five"),
+            ("2010", "twenty ten"),
+            ("2024", "twenty twenty-four"),
+            // Outside the years read as such, and grouped numbers
+            ("1000", "one thousand"),
+            ("3000", "three thousand"),
+            ("2,024", "two thousand twenty-four"),
+            ("at 14:30", "at fourteen thirty"),
+            ("9:05", "nine oh five"),
+            ("10:00", "ten o'clock"),
+            ("18:00", "eighteen hundred"),
+            ("00:30", "zero thirty"),
+            ("25:00", "25:00"),
+            ("12:34:56", "12:34:56"),
+        ]);
+    }
+
+    #[test]
+    fn units() {
+        check(&[
+            ("1024MB", "one thousand twenty-four megabytes"),
+            ("1 GB", "one gigabyte"),
+            ("5km", "five kilometers"),
+            ("1.5 km", "one point five kilometers"),
+            ("20°C", "twenty degrees Celsius"),
+            ("1°C", "one degree Celsius"),
+            ("90°", "ninety degrees"),
+            ("100 km/h", "one hundred kilometers per hour"),
+            ("6ft", "six feet"),
+            ("250ms", "two hundred fifty milliseconds"),
+            ("5m", "five meters"),
+            // One-letter units only when attached
+            ("5 m away", "five m away"),
+            ("3 MBs", "three MBs"),
+        ]);
+    }
+
+    #[test]
+    fn leaves_numbers_inside_other_things() {
+        check(&[
+            ("mp3 player", "mp3 player"),
+            ("v1.2.3", "v1.2.3"),
+            ("version 1.2.3", "version 1.2.3"),
+            ("192.168.0.1", "192.168.0.1"),
+            ("1,2,3", "1,2,3"),
+            ("COVID-19", "COVID-19"),
+            ("H2O", "H2O"),
+            ("a 4K screen", "a 4K screen"),
+            ("example.com/page2", "example.com/page2"),
+            ("snake_case_2", "snake_case_2"),
+        ]);
+    }
+}
@@ -0,0 +1,309 @@
+//! Saving synthesized audio to disk.
+
+use crate::checksum::{Algorithm, Checksum, HashingWriter, HeaderLast};
+use crate::chime::{self, Chimes};
+use crate::fifo;
+use crate::wav;
+use crate::waveform::{EnvelopeWriter, SharedEnvelope};
+use anyhow::{bail, Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// What to do when the output path already exists
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExistingPolicy {
+    Refuse,
+    Overwrite,
+    Append,
+    Skip,
+}
+
+impl ExistingPolicy {
+    pub fn from_flags(force: bool, append: bool, skip_existing: bool) -> Self {
+        if force {
+            ExistingPolicy::Overwrite
+        } else if append {
+            ExistingPolicy::Append
+        } else if skip_existing {
+            ExistingPolicy::Skip
+        } else {
+            ExistingPolicy::Refuse
+        }
+    }
+}
+
+/// Per-item decision, reported after each save (and in batch summaries)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SaveOutcome {
+    Written,
+    Overwritten,
+    Appended,
+    Skipped,
+}
+
+impl SaveOutcome {
+    pub fn label(self) -> &'static str {
+        match self {
+            SaveOutcome::Written => "Saved",
+            SaveOutcome::Overwritten => "Overwrote",
+            SaveOutcome::Appended => "Appended",
+            SaveOutcome::Skipped => "Skipped",
+        }
+    }
+}
+
+/// Decide up front whether an item needs synthesizing at all, so skipped
+/// and refused outputs never cost a daemon request.
+pub fn check_existing(path: &Path, policy: ExistingPolicy) -> Result<Option<SaveOutcome>> {
+    // Writing into a pipe clobbers nothing
+    if !path.exists() || fifo::is_fifo(path) {
+        return Ok(None);
+    }
+    match policy {
+        ExistingPolicy::Refuse => bail!(
+            "output exists, pass --force or --append: {}",
+            path.display()
+        ),
+        ExistingPolicy::Skip => Ok(Some(SaveOutcome::Skipped)),
+        ExistingPolicy::Overwrite | ExistingPolicy::Append => Ok(None),
+    }
+}
+
+pub struct SaveOptions {
+    pub policy: ExistingPolicy,
+    pub checksum: Option<Algorithm>,
+    /// Tap for --waveform, fed the PCM as it is written
+    pub envelope: Option<SharedEnvelope>,
+    /// Earcons written around the speech (--chime-in-file)
+    pub chimes: Chimes,
+    /// How long to wait for a FIFO's reader; None waits forever
+    pub fifo_wait: Option<Duration>,
+}
+
+pub struct Saved {
+    pub outcome: SaveOutcome,
+    /// Digest of the file as written, when a checksum was requested
+    pub checksum: Option<Checksum>,
+}
+
+/// Write the daemon's WAV stream to `path` according to `opts`.
+/// Call `check_existing` first; this assumes the policy permits writing.
+pub fn save<R: Read>(path: &Path, opts: &SaveOptions, mut audio: R) -> Result<Saved> {
+    let exists = path.exists();
+    let (format, _) = wav::read_header(&mut audio)?;
+    if !opts.chimes.is_empty() && format != chime::FORMAT {
+        bail!("Cannot add chimes to {}Hz/{}ch audio", format.sample_rate, format.channels);
+    }
+    let mut audio = opts.chimes.wrap(audio);
+
+    if fifo::is_fifo(path) {
+        let checksum = write_fifo(path, format, opts, &mut audio).map_err(|e| fifo::reader_gone(e, path))?;
+        return Ok(Saved { outcome: SaveOutcome::Written, checksum });
+    }
+
+    if exists && opts.policy == ExistingPolicy::Append {
+        append(path, format, &mut audio, opts.envelope.clone())?;
+        return Ok(Saved { outcome: SaveOutcome::Appended, checksum: None });
+    }
+
+    let Some(mut file) = create(path, opts.policy)? else {
+        return Ok(Saved { outcome: SaveOutcome::Skipped, checksum: None });
+    };
+
+    // Of the file as it ends up, sizes patched
+    let checksum = match opts.checksum {
+        None => {
+            write_patched(&mut file, format, &mut audio, opts.envelope.clone())?;
+            None
+        }
+        Some(algorithm) => {
+            let mut writer = HeaderLast::new(file, algorithm);
+            write_patched(&mut writer, format, &mut audio, opts.envelope.clone())?;
+            Some(writer.finish().1)
+        }
+    };
+
+    let outcome = if exists { SaveOutcome::Overwritten } else { SaveOutcome::Written };
+    Ok(Saved { outcome, checksum })
+}
+
+/// Open `path` for writing as `policy` allows, or None when skipping it.
+/// Refusing and skipping only create the file if it's still missing, so
+/// that one written since `check_existing` isn't clobbered.
+fn create(path: &Path, policy: ExistingPolicy) -> Result<Option<File>> {
+    let mut options = OpenOptions::new();
+    match policy {
+        ExistingPolicy::Refuse | ExistingPolicy::Skip => options.read(true).write(true).create_new(true),
+        ExistingPolicy::Overwrite | ExistingPolicy::Append => options.read(true).write(true).create(true).truncate(true),
+    };
+    match options.open(path) {
+        Ok(file) => Ok(Some(file)),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists && policy == ExistingPolicy::Skip => Ok(None),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => bail!("output exists, pass --force or --append: {}", path.display()),
+        Err(e) => Err(e).with_context(|| format!("Cannot create {}", path.display())),
+    }
+}
+
+/// A WAV of `audio`, its sizes patched in once it's all written.
+fn write_patched<R: Read, W: Write + Seek>(out: &mut W, format: wav::WavFormat, audio: &mut R, envelope: Option<SharedEnvelope>) -> Result<()> {
+    wav::write_header(out, format, 0)?;
+    let data_len = copy_pcm(audio, out, envelope)?;
+    wav::patch_sizes(out, wav::HEADER_LEN, data_len)?;
+    Ok(())
+}
+
+/// Stream into a FIFO. Pipes can't seek, so the header keeps the streaming
+/// sizes instead of being patched.
+fn write_fifo<R: Read>(path: &Path, format: wav::WavFormat, opts: &SaveOptions, audio: &mut R) -> Result<Option<Checksum>> {
+    let file = fifo::open_writer(path, opts.fifo_wait)?;
+    match opts.checksum {
+        None => {
+            let mut file = file;
+            wav::write_header(&mut file, format, wav::STREAMING_DATA_LEN)?;
+            copy_pcm(audio, &mut file, opts.envelope.clone())?;
+            Ok(None)
+        }
+        Some(algorithm) => {
+            let mut writer = HashingWriter::new(file, algorithm);
+            wav::write_header(&mut writer, format, wav::STREAMING_DATA_LEN)?;
+            copy_pcm(audio, &mut writer, opts.envelope.clone())?;
+            Ok(Some(writer.finish().1))
+        }
+    }
+}
+
+/// Write a digest's line to a `<file>.<algo>` sidecar next to the output.
+pub fn write_sidecar(path: &Path, checksum: &Checksum) -> Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut sidecar_path = path.as_os_str().to_owned();
+    sidecar_path.push(format!(".{}", checksum.algorithm.name()));
+    std::fs::write(&sidecar_path, format!("{}\n", checksum.line(name)))
+        .with_context(|| format!("Cannot write {}", Path::new(&sidecar_path).display()))
+}
+
+/// Concatenate the PCM of `audio` (already past its header) onto an existing WAV file.
+fn append<R: Read>(path: &Path, incoming: wav::WavFormat, audio: &mut R, envelope: Option<SharedEnvelope>) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .with_context(|| format!("Cannot open {}", path.display()))?;
+
+    let (existing, data_offset) = wav::read_header(&mut file)
+        .with_context(|| format!("Cannot append to {}", path.display()))?;
+    if existing != incoming {
+        bail!(
+            "Cannot append to {}: it is {}Hz/{}ch/{}-bit, new audio is {}Hz/{}ch/{}-bit",
+            path.display(),
+            existing.sample_rate, existing.channels, existing.bits_per_sample,
+            incoming.sample_rate, incoming.channels, incoming.bits_per_sample,
+        );
+    }
+
+    // Trust the file length over the header: ours may hold streaming placeholders
+    let end = file.seek(SeekFrom::End(0))?;
+    let appended = copy_pcm(audio, &mut file, envelope)?;
+    wav::patch_sizes(&mut file, data_offset, end - data_offset + appended)?;
+    Ok(())
+}
+
+fn copy_pcm<R: Read, W: Write>(audio: &mut R, out: &mut W, envelope: Option<SharedEnvelope>) -> Result<u64> {
+    Ok(match envelope {
+        Some(envelope) => std::io::copy(audio, &mut EnvelopeWriter::new(out, envelope))?,
+        None => std::io::copy(audio, out)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn daemon_wav(samples: &[i16]) -> Vec<u8> {
+        let format = wav::WavFormat { channels: 1, sample_rate: 24000, bits_per_sample: 16 };
+        let mut bytes = Vec::new();
+        wav::write_header(&mut bytes, format, wav::STREAMING_DATA_LEN).unwrap();
+        bytes.extend(samples.iter().flat_map(|s| s.to_le_bytes()));
+        bytes
+    }
+
+    fn options(policy: ExistingPolicy) -> SaveOptions {
+        SaveOptions { policy, checksum: None, envelope: None, chimes: Chimes::default(), fifo_wait: None }
+    }
+
+    /// The sizes in a saved file's header, and its samples
+    fn saved(path: &Path) -> (u32, u32, Vec<i16>) {
+        let bytes = std::fs::read(path).unwrap();
+        let size = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let samples = bytes[44..].chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect();
+        (size(4), size(40), samples)
+    }
+
+    fn temp(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("speakturbo-output-{}-{}.wav", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn refuses_an_existing_file_even_one_made_after_the_check() {
+        let path = temp("refuse");
+        assert_eq!(check_existing(&path, ExistingPolicy::Refuse).unwrap(), None);
+        let saved_as = save(&path, &options(ExistingPolicy::Refuse), &daemon_wav(&[1, 2, 3])[..]).unwrap();
+        assert_eq!(saved_as.outcome, SaveOutcome::Written);
+        assert_eq!(saved(&path), (42, 6, vec![1, 2, 3]));
+
+        assert!(check_existing(&path, ExistingPolicy::Refuse).unwrap_err().to_string().contains("--force"));
+        // As if another process wrote it between the check and the save
+        let e = save(&path, &options(ExistingPolicy::Refuse), &daemon_wav(&[9])[..]).map(|_| ()).unwrap_err();
+        assert!(e.to_string().contains("output exists"), "{:#}", e);
+        assert_eq!(saved(&path).2, [1, 2, 3]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn checksums_the_file_as_saved() {
+        let path = temp("checksum");
+        let options = SaveOptions { checksum: Some(Algorithm::Sha256), ..options(ExistingPolicy::Refuse) };
+        let saved_as = save(&path, &options, &daemon_wav(&[1, 2, 3])[..]).unwrap();
+        assert_eq!(saved(&path), (42, 6, vec![1, 2, 3]), "sizes patched as without --checksum");
+        let mut digest = crate::checksum::Digest::new(Algorithm::Sha256);
+        digest.update(&std::fs::read(&path).unwrap());
+        assert_eq!(saved_as.checksum.map(|c| c.hex), Some(digest.finish_hex()));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn overwrites_with_force() {
+        let path = temp("force");
+        std::fs::write(&path, b"an older, longer file than the new one").unwrap();
+        assert_eq!(check_existing(&path, ExistingPolicy::Overwrite).unwrap(), None);
+        let saved_as = save(&path, &options(ExistingPolicy::Overwrite), &daemon_wav(&[4])[..]).unwrap();
+        assert_eq!(saved_as.outcome, SaveOutcome::Overwritten);
+        assert_eq!(saved(&path), (38, 2, vec![4]));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn appends_and_patches_the_sizes() {
+        let path = temp("append");
+        save(&path, &options(ExistingPolicy::Append), &daemon_wav(&[1, 2])[..]).unwrap();
+        let saved_as = save(&path, &options(ExistingPolicy::Append), &daemon_wav(&[3, 4, 5])[..]).unwrap();
+        assert_eq!(saved_as.outcome, SaveOutcome::Appended);
+        assert_eq!(saved(&path), (46, 10, vec![1, 2, 3, 4, 5]));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn skips_an_existing_file() {
+        let path = temp("skip");
+        std::fs::write(&path, b"keep me").unwrap();
+        assert_eq!(check_existing(&path, ExistingPolicy::Skip).unwrap(), Some(SaveOutcome::Skipped));
+        let saved_as = save(&path, &options(ExistingPolicy::Skip), &daemon_wav(&[1])[..]).unwrap();
+        assert_eq!(saved_as.outcome, SaveOutcome::Skipped);
+        assert_eq!(std::fs::read(&path).unwrap(), b"keep me");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,36 @@
+//! Regular expressions from the user, for `--filter` and friends, compiled
+//! with the regex crate under size limits: matching takes linear time, so
+//! a pattern can only cost memory, and that is capped.
+
+use regex::{Regex, RegexBuilder};
+
+/// Most memory a compiled pattern may take
+const SIZE_LIMIT: usize = 1 << 20;
+/// Most memory a search's lazy DFA may cache
+const DFA_SIZE_LIMIT: usize = 1 << 20;
+
+/// `pattern` compiled, or why not in one line.
+pub fn compile(pattern: &str) -> Result<Regex, String> {
+    RegexBuilder::new(pattern).size_limit(SIZE_LIMIT).dfa_size_limit(DFA_SIZE_LIMIT).build().map_err(|e| match e {
+        // The crate's message points at the pattern over several lines;
+        // its last line says what's wrong
+        regex::Error::Syntax(message) => {
+            let why = message.lines().last().unwrap_or_default();
+            format!("invalid regex {:?}: {}", pattern, why.trim_start_matches("error: "))
+        }
+        regex::Error::CompiledTooBig(limit) => format!("regex {:?} is too big (over {} bytes compiled)", pattern, limit),
+        e => format!("invalid regex {:?}: {}", pattern, e),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explains_bad_patterns_in_a_line() {
+        assert!(compile(r"(?i)^build (failed|broken)\b").unwrap().is_match("BUILD failed at 3"));
+        assert_eq!(compile("a(").unwrap_err(), "invalid regex \"a(\": unclosed group");
+        assert_eq!(compile(r"\w{5000}").unwrap_err(), "regex \"\\\\w{5000}\" is too big (over 1048576 bytes compiled)");
+    }
+}
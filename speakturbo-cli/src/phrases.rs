@@ -0,0 +1,146 @@
+//! `speakturbo say NAME...`: the config's `[phrases]`, texts spoken often
+//! enough to have a name. A phrase may take in another as `{name}`. Each
+//! is spoken as its own synthesis of the same text every time, so after
+//! the first it plays from the audio cache.
+
+use crate::config::Phrase;
+use crate::exit::{self, Kind};
+use crate::{report, sequence, voices, Args};
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::time::Instant;
+
+/// The text of `name` in `phrases` (name, text), each `{other}` replaced
+/// with that phrase's, itself expanded.
+pub fn expand(name: &str, phrases: &[(&str, &str)]) -> Result<String, String> {
+    expand_within(name, phrases, &mut Vec::new())
+}
+
+/// `within`: the phrases being expanded, to catch cycles
+fn expand_within<'a>(name: &'a str, phrases: &[(&'a str, &'a str)], within: &mut Vec<&'a str>) -> Result<String, String> {
+    let Some(&(_, text)) = phrases.iter().find(|(n, _)| *n == name) else {
+        let names: Vec<&str> = phrases.iter().map(|(n, _)| *n).collect();
+        return Err(format!("{{{}}} isn't a phrase{}", name, suggestion(name, &names)));
+    };
+    within.push(name);
+    let mut expanded = String::new();
+    let mut rest = text;
+    while let Some((before, after)) = rest.split_once('{') {
+        let Some((other, after)) = after.split_once('}') else { break };
+        if within.contains(&other) {
+            let chain: Vec<&str> = within.iter().copied().chain([other]).collect();
+            return Err(format!("{} is a cycle", chain.join(" → ")));
+        }
+        expanded.push_str(before);
+        expanded.push_str(&expand_within(other, phrases, within)?);
+        rest = after;
+    }
+    expanded.push_str(rest);
+    within.pop();
+    Ok(expanded)
+}
+
+/// " (did you mean 'build-done'?)" for the nearest of `names`, if any is near.
+fn suggestion(name: &str, names: &[&str]) -> String {
+    let nearest = names
+        .iter()
+        .map(|n| (voices::distance(&name.to_lowercase(), &n.to_lowercase()), *n))
+        .filter(|(d, _)| *d <= voices::MAX_DISTANCE)
+        .min();
+    nearest.map_or(String::new(), |(_, n)| format!(" (did you mean '{}'?)", n))
+}
+
+fn find<'a>(phrases: &'a [Phrase], name: &str) -> Result<&'a Phrase, String> {
+    if let Some(phrase) = phrases.iter().find(|p| p.name == name) {
+        return Ok(phrase);
+    }
+    let names: Vec<&str> = phrases.iter().map(|p| p.name.as_str()).collect();
+    Err(match (names.as_slice(), suggestion(name, &names)) {
+        ([], _) => format!("unknown phrase '{}'; the config has no [phrases] table", name),
+        (_, nearest) if !nearest.is_empty() => format!("unknown phrase '{}'{}", name, nearest),
+        _ => format!("unknown phrase '{}'; the config has {}", name, names.join(", ")),
+    })
+}
+
+/// `say --list`: each phrase, its voice if it has one, and its text.
+pub fn list(phrases: &[Phrase], as_json: bool) {
+    if as_json {
+        let listed = phrases.iter().map(|p| {
+            json!({"name": p.name, "voice": p.voice, "text": p.text})
+        });
+        println!("{}", Value::Array(listed.collect()));
+        report::mark_printed();
+        return;
+    }
+    let width = phrases.iter().map(|p| p.name.chars().count()).max().unwrap_or(0);
+    for phrase in phrases {
+        let voice = phrase.voice.as_deref().map_or(String::new(), |v| format!("({}) ", v));
+        println!("{:<width$}  {}{}", phrase.name, voice, crate::text::excerpt(&phrase.text), width = width);
+    }
+}
+
+/// Speaks the phrases called `names` in order, each in its own voice or
+/// else --voice's.
+pub fn run(args: &mut Args, names: &[String], start: Instant) -> Result<()> {
+    let mut lines = Vec::new();
+    for name in names {
+        let phrase = find(&args.phrases, name).map_err(|e| exit::fail(Kind::Usage, e))?;
+        let voice = phrase.voice.clone().unwrap_or_else(|| args.voice.clone());
+        let text = crate::normalized(args, phrase.text.clone());
+        lines.push((voice, text));
+    }
+
+    // Spoken as --dialogue lines so that each can have its voice
+    args.dialogue = true;
+    args.cast = lines.iter().map(|(voice, _)| (voice.clone(), voice.clone())).collect();
+    let lines: Vec<String> = lines.into_iter().map(|(voice, text)| format!("{}: {}", voice, text)).collect();
+    match lines.len() {
+        1 => sequence::run_to(args, &lines, vec![args.output.clone()], start),
+        _ => sequence::run(args, &lines, start),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_phrases_within_phrases() {
+        let phrases = [
+            ("done", "{project} is done."),
+            ("project", "Speak Turbo"),
+            ("both", "{done} {done}"),
+            ("braces", "a {b"),
+            ("typo", "{projet}"),
+            ("loop", "{again}"),
+            ("again", "and {loop}"),
+            ("me", "{me}"),
+        ];
+        let cases = [
+            ("done", Ok("Speak Turbo is done.")),
+            ("both", Ok("Speak Turbo is done. Speak Turbo is done.")),
+            ("braces", Ok("a {b")),
+            ("typo", Err("{projet} isn't a phrase (did you mean 'project'?)")),
+            ("loop", Err("loop → again → loop is a cycle")),
+            ("me", Err("me → me is a cycle")),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(expand(name, &phrases), expected.map(str::to_string).map_err(str::to_string), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn suggests_the_phrase_meant() {
+        let phrase = |name: &str| Phrase { name: name.to_string(), text: "Hi".to_string(), voice: None };
+        let phrases = [phrase("build-done"), phrase("deploy")];
+        assert_eq!(find(&phrases, "deploy").map(|p| p.name.as_str()), Ok("deploy"));
+        let cases = [
+            ("build-dne", "unknown phrase 'build-dne' (did you mean 'build-done'?)"),
+            ("lunch", "unknown phrase 'lunch'; the config has build-done, deploy"),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(find(&phrases, name).map(|p| p.name.clone()), Err(expected.to_string()), "{:?}", name);
+        }
+        assert_eq!(find(&[], "x").unwrap_err(), "unknown phrase 'x'; the config has no [phrases] table");
+    }
+}
@@ -0,0 +1,142 @@
+//! `--voice random` and `--voice rotate`: a voice picked from the daemon's
+//! list, or from the part of it --voice-pool names, for a little variety.
+
+use anyhow::{bail, Context, Result};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::fs;
+use std::path::Path;
+
+/// The voices to pick from: `pool` in its own order, each checked against
+/// the daemon's `voices`, or all of `voices` without one.
+pub fn candidates(voices: &[String], pool: &[String]) -> Result<Vec<String>> {
+    if voices.is_empty() {
+        bail!("The daemon lists no voices to pick from");
+    }
+    if pool.is_empty() {
+        return Ok(voices.to_vec());
+    }
+    let mut picked = Vec::new();
+    for voice in pool {
+        if !voices.contains(voice) {
+            bail!("--voice-pool: the daemon has no voice {:?} (it has {})", voice, voices.join(", "));
+        }
+        if !picked.contains(voice) {
+            picked.push(voice.clone());
+        }
+    }
+    Ok(picked)
+}
+
+/// One of `candidates`, uniformly: reproducibly from `seed`, else from
+/// system randomness.
+pub fn random(candidates: &[String], seed: Option<u64>) -> Result<String> {
+    let z = split_mix(&mut seeded(seed)?);
+    Ok(candidates[(z % candidates.len() as u64) as usize].clone())
+}
+
+/// `items` in a random order (Fisher-Yates), reproducible from `seed`.
+pub fn shuffle<T>(items: &mut [T], seed: Option<u64>) -> Result<()> {
+    let mut state = seeded(seed)?;
+    for i in (1..items.len()).rev() {
+        let j = (split_mix(&mut state) % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+    Ok(())
+}
+
+/// `seed`, else one from system randomness
+fn seeded(seed: Option<u64>) -> Result<u64> {
+    if let Some(seed) = seed {
+        return Ok(seed);
+    }
+    let mut bytes = [0u8; 8];
+    SystemRandom::new().fill(&mut bytes).map_err(|_| anyhow::anyhow!("No system randomness"))?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// A step of SplitMix64, so that nearby seeds give unrelated numbers
+fn split_mix(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// The next of `candidates` after the one the last run took, by the
+/// counter kept in `counter`.
+pub fn rotate(candidates: &[String], counter: &Path) -> Result<String> {
+    let n: u64 = fs::read_to_string(counter).ok().and_then(|s| s.trim().parse().ok()).unwrap_or(0);
+    if let Some(dir) = counter.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Can't create {}", dir.display()))?;
+    }
+    // Renamed into place, so a run that dies halfway leaves the old count
+    let partial = counter.with_extension("partial");
+    fs::write(&partial, format!("{}\n", n.wrapping_add(1)))
+        .and_then(|()| fs::rename(&partial, counter))
+        .with_context(|| format!("Can't save the rotation in {}", counter.display()))?;
+    Ok(candidates[(n % candidates.len() as u64) as usize].clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn narrows_to_the_pool() {
+        let voices = names(&["alba", "marius", "javert"]);
+        assert_eq!(candidates(&voices, &[]).unwrap(), voices);
+        assert_eq!(candidates(&voices, &names(&["javert", "alba", "javert"])).unwrap(), names(&["javert", "alba"]));
+        assert_eq!(
+            candidates(&voices, &names(&["alba", "bob"])).unwrap_err().to_string(),
+            "--voice-pool: the daemon has no voice \"bob\" (it has alba, marius, javert)"
+        );
+        assert!(candidates(&[], &[]).is_err());
+    }
+
+    #[test]
+    fn picks_at_random_reproducibly() {
+        let voices = names(&["alba", "marius", "javert", "jean"]);
+        let mut seen = Vec::new();
+        for seed in 0..64 {
+            let voice = random(&voices, Some(seed)).unwrap();
+            assert_eq!(voice, random(&voices, Some(seed)).unwrap());
+            if !seen.contains(&voice) {
+                seen.push(voice);
+            }
+        }
+        assert_eq!(seen.len(), voices.len());
+        assert!(voices.contains(&random(&voices, None).unwrap()));
+    }
+
+    #[test]
+    fn shuffles_reproducibly() {
+        let voices = names(&["alba", "marius", "javert", "jean"]);
+        let shuffled = |seed| {
+            let mut order = voices.clone();
+            shuffle(&mut order, Some(seed)).unwrap();
+            order
+        };
+        assert_eq!(shuffled(7), shuffled(7));
+        assert!((0..16).any(|seed| shuffled(seed) != voices));
+        let mut sorted = shuffled(7);
+        sorted.sort();
+        assert_eq!(sorted, names(&["alba", "javert", "jean", "marius"]));
+    }
+
+    #[test]
+    fn rotates_across_runs() {
+        let dir = std::env::temp_dir().join(format!("speakturbo-rotate-{}", std::process::id()));
+        let counter = dir.join("voice-rotation");
+        let voices = names(&["alba", "marius", "javert"]);
+        let picked: Vec<String> = (0..4).map(|_| rotate(&voices, &counter).unwrap()).collect();
+        assert_eq!(picked, names(&["alba", "marius", "javert", "alba"]));
+        // A smaller pool carries on from the same count
+        assert_eq!(rotate(&voices[..2], &counter).unwrap(), "alba");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,93 @@
+//! `speakturbo play FILE...`: saved clips through the same playback as
+//! speech, one after another with --gap-ms between them. Each is decoded
+//! to the daemon's format first, as a chime is, so it plays exactly as a
+//! synthesis streamed from the daemon would.
+
+use crate::chime::{self, Chimes};
+use crate::exit::{self, Kind};
+use crate::reporter::{self, Mark};
+use crate::{interrupt, sequence, wav, Args, Shown};
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read};
+use std::path::Path;
+use std::time::Instant;
+
+pub fn run(args: &Args, files: &[String], start: Instant) -> Result<()> {
+    crate::playback::check()?;
+    interrupt::install();
+    let mut failures: Vec<anyhow::Error> = Vec::new();
+    for (i, path) in files.iter().enumerate() {
+        if i > 0 {
+            std::thread::sleep(crate::gap(args, 0));
+        }
+        if interrupt::requested() {
+            break;
+        }
+        let played = open(Path::new(path)).and_then(|(audio, samples)| {
+            reporter::info(format_args!("Playing {}", path));
+            crate::stream_audio(audio, Shown::Position(Some(samples)), start, false, None, Chimes::default(), usize::MAX)
+        });
+        match played {
+            Ok(measured) if args.stats => reporter::info(format_args!("{}: {}", path, measured)),
+            Ok(_) => {}
+            Err(e) => {
+                reporter::error(format_args!("{} {:#}", reporter::mark(Mark::Failed), e));
+                failures.push(e);
+            }
+        }
+    }
+    if interrupt::requested() {
+        std::process::exit(Kind::Interrupted.code());
+    }
+    match failures.len() {
+        0 => Ok(()),
+        n if n == files.len() && n == 1 => Err(failures.remove(0)),
+        n => {
+            let kind = match n < files.len() {
+                true => Kind::Partial,
+                false => sequence::shared(&failures.iter().map(Kind::of).collect::<Vec<_>>()),
+            };
+            Err(exit::fail(kind, format!("{} of {} files couldn't be played", n, files.len())))
+        }
+    }
+}
+
+/// The file at `path` as a stream in the daemon's format, and how many
+/// samples it holds.
+fn open(path: &Path) -> Result<(crate::AudioStream, u64)> {
+    let file = File::open(path).with_context(|| format!("Cannot open {}", path.display()))?;
+    let samples = wav::decode(&mut BufReader::new(file), crate::SAMPLE_RATE).map_err(|e| exit::fail(Kind::Input, format!("{}: {:#}", path.display(), e)))?;
+    let mut stream = Vec::with_capacity(wav::HEADER_LEN as usize + samples.len() * 2);
+    wav::write_header(&mut stream, chime::FORMAT, (samples.len() * 2) as u32)?;
+    stream.extend(samples.iter().flat_map(|s| s.to_le_bytes()));
+    Ok((Box::new(Cursor::new(stream)) as Box<dyn Read + Send>, samples.len() as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_clips_to_the_daemon_format() {
+        let dir = std::env::temp_dir().join(format!("speakturbo-play-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let clip = dir.join("clip.wav");
+        let mut bytes = Vec::new();
+        wav::write_header(&mut bytes, wav::WavFormat { channels: 1, sample_rate: 48000, bits_per_sample: 16 }, 9600).unwrap();
+        bytes.extend(std::iter::repeat_n(500i16.to_le_bytes(), 4800).flatten());
+        std::fs::write(&clip, bytes).unwrap();
+
+        let (mut audio, samples) = open(&clip).unwrap();
+        let mut stream = Vec::new();
+        audio.read_to_end(&mut stream).unwrap();
+        assert_eq!((samples, stream.len()), (2400, 44 + 4800));
+        assert_eq!(wav::read_header(&mut &stream[..]).unwrap().0, chime::FORMAT);
+
+        std::fs::write(dir.join("notes.txt"), "These are notes, not audio.").unwrap();
+        let reason = |name: &str| format!("{:#}", open(&dir.join(name)).err().unwrap());
+        assert!(reason("notes.txt").ends_with("notes.txt: Not a WAV stream"), "{}", reason("notes.txt"));
+        assert!(reason("missing.wav").contains("Cannot open"), "{}", reason("missing.wav"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,240 @@
+//! The only code that touches an audio device: an [`Output`] on the
+//! default device, playing speech, chimes and silence in the order they're
+//! queued. Built without the `playback` feature (for servers that only
+//! ever save with -o), rodio isn't linked, and opening an output fails
+//! with [`SpeakError::OutputDevice`] saying so.
+//!
+//! An open output is in the [`players`] registry. Under --mix it plays
+//! [`MIX_DB`] quieter when another invocation is playing as it opens, so
+//! that the two together don't clip. Under --priority it first waits its
+//! turn in the registry's queue.
+
+use crate::players::{self, Player, Priority, Turn};
+use crate::{interrupt, StreamSource};
+use speakturbo_client::SpeakError;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// The gain --mix plays at over another invocation
+pub const MIX_DB: f64 = -4.5;
+
+/// --mix, once settled
+static MIX: AtomicBool = AtomicBool::new(false);
+
+/// The gain an output took for --mix, and the players it was over
+static MIXED: OnceLock<(f64, usize)> = OnceLock::new();
+
+/// --priority, once settled
+static PRIORITY: OnceLock<Priority> = OnceLock::new();
+
+/// How long a waiter leaves between looks at the queue
+const POLL: Duration = Duration::from_millis(50);
+
+/// How long playback cut off by an urgent invocation takes to fade out
+pub const FADE_OUT: Duration = Duration::from_millis(150);
+
+/// Play over other invocations from now on (--mix).
+pub fn mix() {
+    MIX.store(true, Ordering::SeqCst);
+}
+
+/// The gain --mix applied, in dB, and how many others were playing; None
+/// if it didn't need to.
+pub fn mixed() -> Option<(f64, usize)> {
+    MIXED.get().copied()
+}
+
+/// Wait for a turn among other invocations before playing (--priority).
+pub fn queue(priority: Priority) {
+    let _ = PRIORITY.set(priority);
+}
+
+/// This process in the registry while an output is open, once it's its
+/// turn, and the gain to play at: below one under --mix with others playing.
+#[cfg_attr(not(feature = "playback"), allow(dead_code))]
+fn register() -> (Option<players::Registered>, Option<f32>) {
+    let Some(registry) = players::Registry::open() else {
+        return (None, None);
+    };
+    interrupt::preemptible();
+    let mixing = MIX.load(Ordering::SeqCst);
+    let mode = if mixing { players::Mode::Mixing } else { players::Mode::Solo };
+    let mut me = Player::me(mode);
+    if let Some(&priority) = PRIORITY.get() {
+        me = Player { priority, waiting: true, ..me };
+    }
+    // In first, so that two starting together each see the other
+    let registered = registry.register(&me);
+    if me.waiting {
+        wait(&registry, &me);
+        me.waiting = false;
+        if let Some(registered) = &registered {
+            let _ = registered.update(&me);
+        }
+    }
+    let others = registry.others(std::process::id()).len();
+    if !mixing || others == 0 {
+        return (registered, None);
+    }
+    let _ = MIXED.set((MIX_DB, others));
+    (registered, Some(10f64.powf(MIX_DB / 20.0) as f32))
+}
+
+/// Until `me` is at the front of the queue with no solo player left, or
+/// Ctrl+C; an urgent waiter cuts off each player in its way once.
+#[cfg_attr(not(feature = "playback"), allow(dead_code))]
+fn wait(registry: &players::Registry, me: &Player) {
+    let mut cut_off = Vec::new();
+    while !interrupt::requested() {
+        match players::turn(me, &registry.others(me.pid), players::now()) {
+            Turn::Play => return,
+            Turn::Wait { preempt } => {
+                for pid in preempt {
+                    if cut_off.contains(&pid) {
+                        continue;
+                    }
+                    tracing::info!(target: "playback", "cutting off player {}", pid);
+                    players::preempt(pid);
+                    cut_off.push(pid);
+                }
+            }
+        }
+        std::thread::sleep(POLL);
+    }
+}
+
+/// Why there's no output in a build without playback
+const WITHOUT: &str = "speakturbo was compiled without playback; save the audio with -o instead";
+
+/// The default output device, playing what's appended to it in turn
+#[cfg(feature = "playback")]
+pub struct Output {
+    /// Kept open while the sink plays through it
+    _stream: rodio::OutputStream,
+    sink: rodio::Sink,
+    /// Under --mix with others playing
+    gain: Option<f32>,
+    _registered: Option<players::Registered>,
+}
+
+/// Never made: there's no device to play on
+#[cfg(not(feature = "playback"))]
+pub struct Output(std::convert::Infallible);
+
+/// Ok if this build can play audio; a caller about to fetch audio only to
+/// play it asks first.
+pub fn check() -> Result<(), SpeakError> {
+    match cfg!(feature = "playback") {
+        true => Ok(()),
+        false => Err(SpeakError::output_device(WITHOUT)),
+    }
+}
+
+#[cfg(feature = "playback")]
+impl Output {
+    pub fn open() -> Result<Output, SpeakError> {
+        let (registered, gain) = register();
+        let (stream, handle) = rodio::OutputStream::try_default().map_err(SpeakError::output_device)?;
+        let sink = rodio::Sink::try_new(&handle).map_err(SpeakError::output_device)?;
+        Ok(Output { _stream: stream, sink, gain, _registered: registered })
+    }
+
+    pub fn speech(&self, source: StreamSource) {
+        self.sink.append(source.gained(self.gain));
+    }
+
+    /// Mono samples at the daemon's rate: a chime.
+    pub fn samples(&self, mut samples: Vec<i16>) {
+        if let Some(gain) = self.gain {
+            samples.iter_mut().for_each(|sample| *sample = (*sample as f32 * gain) as i16);
+        }
+        self.sink.append(rodio::buffer::SamplesBuffer::new(1, crate::SAMPLE_RATE, samples));
+    }
+
+    pub fn silence(&self, duration: Duration) {
+        use rodio::Source;
+        self.sink.append(rodio::source::Zero::<i16>::new(1, crate::SAMPLE_RATE).take_duration(duration));
+    }
+
+    /// What's queued, the one playing included
+    pub fn len(&self) -> usize {
+        self.sink.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sink.empty()
+    }
+
+    /// Stop playing and drop everything queued.
+    pub fn stop(&self) {
+        self.sink.stop();
+    }
+
+    /// Stop as [`stop`](Self::stop) does, turned down over `duration`
+    /// rather than cut off mid-word.
+    pub fn fade_out(&self, duration: Duration) {
+        const STEPS: u32 = 10;
+        for step in 1..=STEPS {
+            self.sink.set_volume(1.0 - step as f32 / STEPS as f32);
+            std::thread::sleep(duration / STEPS);
+        }
+        self.sink.stop();
+    }
+}
+
+#[cfg(not(feature = "playback"))]
+impl Output {
+    pub fn open() -> Result<Output, SpeakError> {
+        Err(SpeakError::output_device(WITHOUT))
+    }
+
+    pub fn speech(&self, _: StreamSource) {
+        match self.0 {}
+    }
+
+    pub fn samples(&self, _: Vec<i16>) {
+        match self.0 {}
+    }
+
+    pub fn silence(&self, _: Duration) {
+        match self.0 {}
+    }
+
+    pub fn len(&self) -> usize {
+        match self.0 {}
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self.0 {}
+    }
+
+    pub fn stop(&self) {
+        match self.0 {}
+    }
+
+    pub fn fade_out(&self, _: Duration) {
+        match self.0 {}
+    }
+}
+
+#[cfg(feature = "playback")]
+impl rodio::Source for StreamSource {
+    fn current_frame_len(&self) -> Option<usize> { None }
+    fn channels(&self) -> u16 { 1 }
+    fn sample_rate(&self) -> u32 { crate::SAMPLE_RATE }
+    fn total_duration(&self) -> Option<Duration> { None }
+}
+
+/// The output devices' names, as the audio system calls them; none
+/// without playback.
+pub fn devices() -> Vec<String> {
+    #[cfg(feature = "playback")]
+    {
+        use rodio::cpal::traits::{DeviceTrait, HostTrait};
+        if let Ok(devices) = rodio::cpal::default_host().output_devices() {
+            return devices.filter_map(|d| d.name().ok()).collect();
+        }
+    }
+    Vec::new()
+}
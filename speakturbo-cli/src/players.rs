@@ -0,0 +1,344 @@
+//! The invocations playing audio at the moment, or waiting to: a file per
+//! process in the state directory's `players/`, named by its pid. It holds
+//! "solo", or "mixing" for one that plays over others on purpose (--mix),
+//! then its priority, when it arrived, and whether it's still waiting its
+//! turn (--priority). A process that dies leaves its file behind; the next
+//! look finds it stale and removes it.
+//!
+//! The queue has no keeper: every waiter reads the others' entries and
+//! works out the same order, so the one at the front starts once no solo
+//! player is left.
+
+use std::cmp::Reverse;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long a waiter waits before it's taken as one priority higher, so
+/// that a steady flow of higher ones can't hold it back for ever; aging
+/// stops below urgent, which alone cuts others off.
+pub const AGING: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Solo,
+    /// Playing over the others on purpose (`--mix`)
+    Mixing,
+}
+
+impl Mode {
+    fn as_str(self) -> &'static str {
+        match self {
+            Mode::Solo => "solo",
+            Mode::Mixing => "mixing",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+    /// Cuts off whatever is playing, unless that's urgent too
+    Urgent,
+}
+
+impl Priority {
+    fn as_str(self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+            Priority::Urgent => "urgent",
+        }
+    }
+
+    fn parse(word: &str) -> Option<Priority> {
+        [Priority::Low, Priority::Normal, Priority::High, Priority::Urgent].into_iter().find(|p| p.as_str() == word)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Player {
+    pub pid: u32,
+    pub mode: Mode,
+    pub priority: Priority,
+    /// When it arrived, in ms since the epoch
+    pub since: u64,
+    /// Still waiting its turn (--priority), not yet playing
+    pub waiting: bool,
+}
+
+impl Player {
+    /// This process playing now, in `mode`, at normal priority.
+    pub fn me(mode: Mode) -> Player {
+        Player { pid: std::process::id(), mode, priority: Priority::Normal, since: now(), waiting: false }
+    }
+
+    fn entry(&self) -> String {
+        let state = if self.waiting { "waiting" } else { "playing" };
+        format!("{} {} {} {}", self.mode.as_str(), self.priority.as_str(), self.since, state)
+    }
+
+    /// An entry as [`entry`](Self::entry) writes it; anything missing is
+    /// taken as a solo player at normal priority, playing.
+    fn parse(pid: u32, entry: &str) -> Player {
+        let mut words = entry.split_whitespace();
+        let mode = match words.next() {
+            Some("mixing") => Mode::Mixing,
+            _ => Mode::Solo,
+        };
+        let priority = words.next().and_then(Priority::parse).unwrap_or(Priority::Normal);
+        let since = words.next().and_then(|w| w.parse().ok()).unwrap_or(0);
+        Player { pid, mode, priority, since, waiting: words.next() == Some("waiting") }
+    }
+
+    /// Its place in the queue at `now`: aged, urgent first, then the
+    /// earliest to arrive.
+    fn place(&self, now: u64) -> (Reverse<u64>, u64, u32) {
+        let rank = match self.priority {
+            Priority::Urgent => Priority::Urgent as u64,
+            p => (p as u64 + now.saturating_sub(self.since) / AGING.as_millis() as u64).min(Priority::High as u64),
+        };
+        (Reverse(rank), self.since, self.pid)
+    }
+}
+
+/// What a waiter is to do next
+#[derive(Debug, PartialEq, Eq)]
+pub enum Turn {
+    Play,
+    /// Look again shortly, having cut off these players
+    Wait { preempt: Vec<u32> },
+}
+
+/// Whether `me`, waiting, may play at `now` with `others` as they are.
+/// Solo players hold the queue, mixing ones don't; an urgent waiter at the
+/// front cuts off the players that aren't urgent.
+pub fn turn(me: &Player, others: &[Player], now: u64) -> Turn {
+    let playing: Vec<&Player> = others.iter().filter(|o| !o.waiting && o.mode == Mode::Solo).collect();
+    let ahead = others.iter().any(|o| o.waiting && o.place(now) < me.place(now));
+    if playing.is_empty() && !ahead {
+        return Turn::Play;
+    }
+    let preempt = match me.priority == Priority::Urgent && !ahead {
+        true => playing.iter().filter(|o| o.priority != Priority::Urgent).map(|o| o.pid).collect(),
+        false => Vec::new(),
+    };
+    Turn::Wait { preempt }
+}
+
+/// Ms since the epoch, as entries keep time.
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+pub struct Registry {
+    dir: PathBuf,
+}
+
+/// A process's entry, removed on drop
+pub struct Registered {
+    path: PathBuf,
+}
+
+impl Registered {
+    /// Replace the entry, all at once so that no reader sees half of it.
+    pub fn update(&self, player: &Player) -> std::io::Result<()> {
+        write(&self.path, player)
+    }
+}
+
+/// A name that doesn't parse as a pid, so readers pass it by
+fn write(path: &std::path::Path, player: &Player) -> std::io::Result<()> {
+    let partial = path.with_extension("partial");
+    fs::write(&partial, player.entry())?;
+    fs::rename(&partial, path)
+}
+
+impl Drop for Registered {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl Registry {
+    /// The registry in the state directory, if there is one.
+    pub fn open() -> Option<Registry> {
+        crate::history::state_dir().map(|d| Registry { dir: d.join("players") })
+    }
+
+    /// Record `player` until the entry is dropped; None if it can't be
+    /// written, playback going ahead regardless.
+    pub fn register(&self, player: &Player) -> Option<Registered> {
+        fs::create_dir_all(&self.dir).ok()?;
+        let path = self.dir.join(player.pid.to_string());
+        write(&path, player).ok()?;
+        Some(Registered { path })
+    }
+
+    /// The players other than `pid`, by pid; those whose process is gone
+    /// are removed.
+    pub fn others(&self, pid: u32) -> Vec<Player> {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        let mut players = Vec::new();
+        for entry in entries.flatten() {
+            let Some(other) = entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok()) else {
+                continue;
+            };
+            if other == pid {
+                continue;
+            }
+            if !alive(other) {
+                let _ = fs::remove_file(entry.path());
+                continue;
+            }
+            // Gone between the listing and now
+            let Ok(text) = fs::read_to_string(entry.path()) else {
+                continue;
+            };
+            players.push(Player::parse(other, &text));
+        }
+        players.sort_by_key(|player| player.pid);
+        players
+    }
+}
+
+#[cfg(unix)]
+fn alive(pid: u32) -> bool {
+    // Signal 0 only checks; EPERM is a process someone else owns
+    let found = unsafe { libc::kill(pid as libc::pid_t, 0) } == 0;
+    found || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Cut off `pid`'s playback: it fades out and ends as if interrupted.
+#[cfg(unix)]
+pub fn preempt(pid: u32) {
+    unsafe { libc::kill(pid as libc::pid_t, crate::interrupt::PREEMPT) };
+}
+
+/// Nothing to send: a console's Ctrl+C would reach every process in it
+#[cfg(not(unix))]
+pub fn preempt(_: u32) {}
+
+#[cfg(windows)]
+fn alive(pid: u32) -> bool {
+    use std::ffi::c_void;
+    const PROCESS_QUERY_LIMITED_INFORMATION: u32 = 0x1000;
+    const STILL_ACTIVE: u32 = 259;
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn OpenProcess(access: u32, inherit: i32, pid: u32) -> *mut c_void;
+        fn GetExitCodeProcess(process: *mut c_void, code: *mut u32) -> i32;
+        fn CloseHandle(handle: *mut c_void) -> i32;
+    }
+    let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid) };
+    if process.is_null() {
+        return false;
+    }
+    let mut code = 0;
+    let running = unsafe { GetExitCodeProcess(process, &mut code) } != 0 && code == STILL_ACTIVE;
+    unsafe { CloseHandle(process) };
+    running
+}
+
+#[cfg(not(any(unix, windows)))]
+fn alive(_: u32) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn sees_other_live_players_and_drops_stale_ones() {
+        let dir = std::env::temp_dir().join(format!("speakturbo-players-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let registry = Registry { dir: dir.clone() };
+        let me = std::process::id();
+        let parent = std::os::unix::process::parent_id();
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        child.wait().unwrap();
+        let gone = child.id();
+
+        let player = |pid, mode| Player { pid, mode, priority: Priority::Normal, since: 1, waiting: false };
+
+        let mine = registry.register(&player(me, Mode::Mixing)).unwrap();
+        let theirs = registry.register(&player(parent, Mode::Solo)).unwrap();
+        let stale = registry.register(&player(gone, Mode::Mixing)).unwrap();
+        std::mem::forget(stale);
+        assert_eq!(registry.others(me), [player(parent, Mode::Solo)]);
+        assert!(!dir.join(gone.to_string()).exists(), "the stale entry is removed");
+        assert_eq!(registry.others(parent), [player(me, Mode::Mixing)]);
+
+        let waiting = Player { priority: Priority::Urgent, waiting: true, ..player(me, Mode::Solo) };
+        mine.update(&waiting).unwrap();
+        assert_eq!(registry.others(parent), [waiting]);
+
+        drop(theirs);
+        assert_eq!(registry.others(me), []);
+        drop(mine);
+        assert_eq!(registry.others(parent), []);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    fn waiter(pid: u32, priority: Priority, since: u64) -> Player {
+        Player { pid, mode: Mode::Solo, priority, since, waiting: true }
+    }
+
+    #[test]
+    fn waiters_go_by_priority_then_arrival() {
+        let low = waiter(1, Priority::Low, 100);
+        let normal = waiter(2, Priority::Normal, 200);
+        let high = waiter(3, Priority::High, 300);
+        let later = waiter(4, Priority::High, 400);
+        let all = [low.clone(), normal.clone(), high.clone(), later.clone()];
+        let others = |me: &Player| all.iter().filter(|o| o.pid != me.pid).cloned().collect::<Vec<_>>();
+
+        assert_eq!(turn(&high, &others(&high), 500), Turn::Play);
+        for me in [&low, &normal, &later] {
+            assert_eq!(turn(me, &others(me), 500), Turn::Wait { preempt: vec![] });
+        }
+        // A solo player holds everyone; a mixing one doesn't
+        let playing = Player { waiting: false, ..waiter(5, Priority::Low, 0) };
+        assert_eq!(turn(&high, std::slice::from_ref(&playing), 500), Turn::Wait { preempt: vec![] });
+        assert_eq!(turn(&high, &[Player { mode: Mode::Mixing, ..playing }], 500), Turn::Play);
+    }
+
+    #[test]
+    fn a_long_wait_goes_up_a_priority_but_never_to_urgent() {
+        let low = waiter(1, Priority::Low, 0);
+        let normal = waiter(2, Priority::Normal, AGING.as_millis() as u64);
+        let urgent = waiter(3, Priority::Urgent, 10 * AGING.as_millis() as u64);
+        let now = AGING.as_millis() as u64 + 1;
+        // Aged to normal, and there first
+        assert_eq!(turn(&low, std::slice::from_ref(&normal), now), Turn::Play);
+        assert_eq!(turn(&normal, std::slice::from_ref(&low), now), Turn::Wait { preempt: vec![] });
+        let much_later = 100 * AGING.as_millis() as u64;
+        assert_eq!(turn(&low, std::slice::from_ref(&urgent), much_later), Turn::Wait { preempt: vec![] });
+    }
+
+    #[test]
+    fn urgent_cuts_off_all_but_urgent_players() {
+        let urgent = waiter(1, Priority::Urgent, 100);
+        let playing = |pid, priority| Player { waiting: false, ..waiter(pid, priority, 0) };
+        assert_eq!(turn(&urgent, &[playing(2, Priority::High)], 200), Turn::Wait { preempt: vec![2] });
+        assert_eq!(turn(&urgent, &[playing(2, Priority::Urgent)], 200), Turn::Wait { preempt: vec![] });
+        // Not while another urgent one is ahead of it
+        let first = waiter(3, Priority::Urgent, 50);
+        assert_eq!(turn(&urgent, &[playing(2, Priority::Low), first], 200), Turn::Wait { preempt: vec![] });
+        let high = waiter(4, Priority::High, 100);
+        assert_eq!(turn(&high, &[playing(2, Priority::Low)], 200), Turn::Wait { preempt: vec![] });
+    }
+
+    #[test]
+    fn reads_entries_from_before_priorities() {
+        assert_eq!(Player::parse(7, "mixing"), Player { pid: 7, mode: Mode::Mixing, priority: Priority::Normal, since: 0, waiting: false });
+        assert_eq!(Player::parse(7, &waiter(7, Priority::Low, 5).entry()), waiter(7, Priority::Low, 5));
+    }
+}
@@ -0,0 +1,64 @@
+//! Just enough PNG to write an 8-bit RGB image.
+
+use anyhow::Result;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::io::Write;
+
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    /// Row-major RGB triples
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32, fill: [u8; 3]) -> Self {
+        let pixels = fill.repeat((width * height) as usize);
+        Self { width, height, pixels }
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, rgb: [u8; 3]) {
+        if x < self.width && y < self.height {
+            let i = ((y * self.width + x) * 3) as usize;
+            self.pixels[i..i + 3].copy_from_slice(&rgb);
+        }
+    }
+
+    #[cfg(test)]
+    pub fn get(&self, x: u32, y: u32) -> [u8; 3] {
+        let i = ((y * self.width + x) * 3) as usize;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2]]
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        out.extend_from_slice(b"\x89PNG\r\n\x1a\n");
+
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&self.width.to_be_bytes());
+        ihdr.extend_from_slice(&self.height.to_be_bytes());
+        ihdr.extend_from_slice(&[8, 2, 0, 0, 0]); // 8-bit, truecolor, deflate, no filter, no interlace
+        chunk(&mut out, b"IHDR", &ihdr);
+
+        // Each scanline is prefixed with filter type 0 (None)
+        let mut zlib = ZlibEncoder::new(Vec::new(), Compression::default());
+        for row in self.pixels.chunks_exact(self.width as usize * 3) {
+            zlib.write_all(&[0])?;
+            zlib.write_all(row)?;
+        }
+        chunk(&mut out, b"IDAT", &zlib.finish()?);
+        chunk(&mut out, b"IEND", &[]);
+        Ok(out)
+    }
+}
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    out.extend_from_slice(&crc.finalize().to_be_bytes());
+}
@@ -0,0 +1,212 @@
+//! Chunks of a long text synthesized ahead of the one playing, several at
+//! a time (`--jobs`), and handed back strictly in order however they finish.
+
+use crate::chime::FORMAT;
+use crate::{wav, AudioStream};
+use anyhow::{bail, Result};
+use std::io::Read;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Tries per chunk before its sentences are given up on
+const ATTEMPTS: u32 = 3;
+
+/// Starts a synthesis, as the daemon's (or the cache's) WAV stream
+pub type Open = Arc<dyn Fn(&str) -> Result<AudioStream> + Send + Sync>;
+
+pub struct Done {
+    /// The chunk's samples, header stripped, or why there are none
+    pub audio: Result<Vec<u8>, String>,
+    /// How long synthesis took, retries included
+    pub took: Duration,
+}
+
+enum Slot {
+    Waiting,
+    Done(Done),
+    Taken,
+}
+
+struct Queue {
+    texts: Vec<String>,
+    slots: Vec<Slot>,
+    /// The next chunk to start, and the next to be taken
+    next: usize,
+    cursor: usize,
+    /// Syntheses running, counting one outside the pool while `streaming`
+    running: usize,
+    streaming: bool,
+    /// Bytes finished but not yet taken
+    ready_bytes: usize,
+    stopped: bool,
+}
+
+pub struct Prefetch {
+    shared: Arc<(Mutex<Queue>, Condvar)>,
+}
+
+impl Prefetch {
+    /// Starts synthesizing `texts`, at most `jobs` at once counting the
+    /// chunk already streaming outside the pool, and pausing while
+    /// `max_ready_bytes` of finished chunks wait to be taken.
+    pub fn start(texts: Vec<String>, open: Open, jobs: usize, max_ready_bytes: usize) -> Self {
+        let workers = jobs.min(texts.len());
+        let queue = Queue {
+            slots: texts.iter().map(|_| Slot::Waiting).collect(),
+            texts,
+            next: 0,
+            cursor: 0,
+            running: 0,
+            streaming: true,
+            ready_bytes: 0,
+            stopped: false,
+        };
+        let shared = Arc::new((Mutex::new(queue), Condvar::new()));
+        for _ in 0..workers {
+            let shared = Arc::clone(&shared);
+            let open = Arc::clone(&open);
+            std::thread::spawn(move || work(&shared, &open, jobs, max_ready_bytes));
+        }
+        Prefetch { shared }
+    }
+
+    /// The streaming chunk has finished synthesizing; another may start.
+    pub fn streamed(&self) {
+        let (queue, changed) = &*self.shared;
+        queue.lock().unwrap().streaming = false;
+        changed.notify_all();
+    }
+
+    /// Chunk `n`, waiting for it if it isn't done, and how long that was.
+    pub fn take(&self, n: usize) -> (Done, Duration) {
+        let (queue, changed) = &*self.shared;
+        let waited = Instant::now();
+        let mut queue = queue.lock().unwrap();
+        queue.cursor = n;
+        changed.notify_all();
+        loop {
+            if let Slot::Done(_) = queue.slots[n] {
+                let Slot::Done(done) = std::mem::replace(&mut queue.slots[n], Slot::Taken) else { unreachable!() };
+                queue.ready_bytes -= done.audio.as_ref().map_or(0, Vec::len);
+                changed.notify_all();
+                return (done, waited.elapsed());
+            }
+            queue = changed.wait(queue).unwrap();
+        }
+    }
+}
+
+impl Drop for Prefetch {
+    fn drop(&mut self) {
+        let (queue, changed) = &*self.shared;
+        queue.lock().unwrap().stopped = true;
+        changed.notify_all();
+    }
+}
+
+fn work(shared: &(Mutex<Queue>, Condvar), open: &Open, jobs: usize, max_ready_bytes: usize) {
+    let (queue, changed) = shared;
+    loop {
+        let (n, text) = {
+            let mut queue = queue.lock().unwrap();
+            loop {
+                if queue.stopped || queue.next >= queue.texts.len() {
+                    return;
+                }
+                let busy = queue.running + queue.streaming as usize;
+                // The chunk being waited for always starts, budget or not
+                let room = queue.ready_bytes < max_ready_bytes || queue.next == queue.cursor;
+                if busy < jobs && room {
+                    break;
+                }
+                queue = changed.wait(queue).unwrap();
+            }
+            queue.next += 1;
+            queue.running += 1;
+            (queue.next - 1, queue.texts[queue.next - 1].clone())
+        };
+
+        let started = Instant::now();
+        let mut attempt = 1;
+        let audio = loop {
+            match synthesize(open, &text) {
+                Err(_) if attempt < ATTEMPTS && !queue.lock().unwrap().stopped => {
+                    std::thread::sleep(Duration::from_millis(250 * attempt as u64));
+                    attempt += 1;
+                }
+                result => break result.map_err(|e| format!("{:#}", e)),
+            }
+        };
+
+        let mut queue = queue.lock().unwrap();
+        queue.running -= 1;
+        queue.ready_bytes += audio.as_ref().map_or(0, Vec::len);
+        queue.slots[n] = Slot::Done(Done { audio, took: started.elapsed() });
+        changed.notify_all();
+    }
+}
+
+/// One chunk's samples, in full.
+fn synthesize(open: &Open, text: &str) -> Result<Vec<u8>> {
+    let mut audio = open(text)?;
+    let (format, _) = wav::read_header(&mut audio)?;
+    if format != FORMAT {
+        bail!("Unexpected audio format from the daemon: {:?}", format);
+    }
+    let mut samples = Vec::new();
+    audio.read_to_end(&mut samples)?;
+    Ok(samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn response(value: i16, samples: usize) -> AudioStream {
+        let mut bytes = Vec::new();
+        wav::write_header(&mut bytes, FORMAT, (samples * 2) as u32).unwrap();
+        bytes.extend((0..samples).flat_map(|_| value.to_le_bytes()));
+        Box::new(std::io::Cursor::new(bytes))
+    }
+
+    #[test]
+    fn hands_chunks_back_in_order() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&attempts);
+        // Later chunks finish first; "3" fails once before it works
+        let open: Open = Arc::new(move |text: &str| {
+            let n: i16 = text.parse().unwrap();
+            std::thread::sleep(Duration::from_millis(40 - 10 * n as u64));
+            if n == 3 && counted.fetch_add(1, Ordering::SeqCst) == 0 {
+                bail!("busy");
+            }
+            Ok(response(n, 10))
+        });
+        let prefetch = Prefetch::start((0..4).map(|n| n.to_string()).collect(), open, 3, usize::MAX);
+        prefetch.streamed();
+        for n in 0..4 {
+            let (done, _) = prefetch.take(n);
+            assert_eq!(done.audio.unwrap()[..2], (n as i16).to_le_bytes());
+        }
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn stops_ahead_at_the_byte_budget() {
+        let started = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&started);
+        let open: Open = Arc::new(move |_: &str| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            Ok(response(1, 100))
+        });
+        let prefetch = Prefetch::start(vec!["a".into(); 6], open, 2, 300);
+        prefetch.streamed();
+        std::thread::sleep(Duration::from_millis(100));
+        // Two 200-byte chunks pass the 300-byte budget; nothing more starts
+        assert_eq!(started.load(Ordering::SeqCst), 2);
+        prefetch.take(0);
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(started.load(Ordering::SeqCst), 3);
+    }
+}
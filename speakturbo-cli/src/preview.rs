@@ -0,0 +1,69 @@
+//! `speakturbo preview`: a sample phrase in each voice, back to back, to
+//! choose between them by ear. Each sample starts with its voice saying
+//! its own name, and they go through the sequence machinery as a
+//! --dialogue with one line per voice.
+
+use crate::{config, reporter, sequence, Args};
+use anyhow::{bail, Result};
+use std::path::Path;
+use std::time::Instant;
+
+pub const SAMPLE: &str = "The quick brown fox jumps over the lazy dog.";
+
+/// One line per voice: "alba: Alba. The quick brown fox…".
+pub fn lines(voices: &[String], sample: &str) -> Vec<String> {
+    voices
+        .iter()
+        .map(|voice| {
+            let mut name = voice.chars();
+            let spoken: String = name.next().into_iter().flat_map(char::to_uppercase).chain(name).collect();
+            format!("{}: {}. {}", voice, spoken, sample)
+        })
+        .collect()
+}
+
+/// `alba.wav`, with anything but letters, digits, '-' and '_' replaced.
+fn file_name(voice: &str) -> String {
+    let stem: String = voice.chars().map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect();
+    format!("{}.wav", stem)
+}
+
+/// Speaks `text` in each of `voices`, or saves it to a file per voice in
+/// `dir`. A voice that fails doesn't stop the others.
+pub fn run(args: &mut Args, voices: &[String], text: &str, dir: Option<&Path>, start: Instant) -> Result<()> {
+    let voices: Vec<String> = match voices {
+        [] => match crate::daemon_voices() {
+            Some(voices) if !voices.is_empty() => voices,
+            _ => bail!("No voices to preview: the daemon didn't list any (is it running?)"),
+        },
+        named => named.iter().map(|v| config::resolve(&args.aliases, v).to_string()).collect(),
+    };
+    let sample = crate::normalized(args, text.to_string());
+    reporter::info(format_args!("Previewing: {}", voices.join(", ")));
+
+    // Each line's speaker is its voice, whatever the daemon calls it
+    args.dialogue = true;
+    args.cast = voices.iter().map(|v| (v.clone(), v.clone())).collect();
+    let outputs = match dir {
+        Some(dir) => {
+            std::fs::create_dir_all(dir)?;
+            args.output = Some(dir.display().to_string());
+            args.force = true;
+            voices.iter().map(|v| Some(dir.join(file_name(v)).display().to_string())).collect()
+        }
+        None => vec![None; voices.len()],
+    };
+    sequence::run_to(args, &lines(&voices, &sample), outputs, start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn announces_each_voice() {
+        let voices = vec!["alba".to_string(), "javert".to_string()];
+        assert_eq!(lines(&voices, "Hi."), ["alba: Alba. Hi.", "javert: Javert. Hi."]);
+        assert_eq!(file_name("en/us voice"), "en_us_voice.wav");
+    }
+}
@@ -0,0 +1,266 @@
+//! Single-line stderr progress displays: for long saves, and for the
+//! position in playback.
+
+use crate::reporter::{self, Mark};
+use std::io::{IsTerminal, Read, Write};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+// Redraw at most ~10 times per second
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The playback position moves a second at a time
+const PLAYBACK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A line redrawn in place on stderr, at most once every `interval`, and
+/// only when not quiet and stderr is a TTY that takes escape sequences.
+struct StatusLine {
+    interval: Duration,
+    last_draw: Option<Instant>,
+    enabled: bool,
+    drawn: bool,
+}
+
+impl StatusLine {
+    fn new(interval: Duration, quiet: bool) -> Self {
+        Self { interval, last_draw: None, enabled: !quiet && std::io::stderr().is_terminal() && crate::console::escapes(), drawn: false }
+    }
+
+    /// Whether it's time to draw again
+    fn due(&mut self) -> bool {
+        let now = Instant::now();
+        if !self.enabled || self.last_draw.is_some_and(|t| now - t < self.interval) {
+            return false;
+        }
+        self.last_draw = Some(now);
+        true
+    }
+
+    fn draw(&mut self, line: &str) {
+        let mut err = std::io::stderr().lock();
+        let _ = write!(err, "\r\x1b[2K{}", line);
+        let _ = err.flush();
+        self.drawn = true;
+    }
+
+    fn finish(&mut self) {
+        if self.drawn {
+            let mut err = std::io::stderr().lock();
+            let _ = write!(err, "\r\x1b[2K");
+            let _ = err.flush();
+            self.drawn = false;
+        }
+    }
+}
+
+pub struct Progress {
+    label: String,
+    total: Option<u64>,
+    start: Instant,
+    line: StatusLine,
+}
+
+impl Progress {
+    /// A progress line that only renders when not quiet and stderr is a TTY.
+    pub fn new(label: impl Into<String>, total: Option<u64>, quiet: bool) -> Self {
+        Self { label: label.into(), total, start: Instant::now(), line: StatusLine::new(REDRAW_INTERVAL, quiet) }
+    }
+
+    pub fn update(&mut self, done: u64) {
+        if !self.line.due() {
+            return;
+        }
+
+        let elapsed = self.start.elapsed();
+        let rate = done as f64 / elapsed.as_secs_f64().max(0.001);
+        let mut line = format!(
+            "{}  {}  {}/s  {}",
+            self.label,
+            human_bytes(done),
+            human_bytes(rate as u64),
+            clock(elapsed),
+        );
+        if let Some(total) = self.total.filter(|&t| t > 0) {
+            let pct = (done as f64 / total as f64 * 100.0).min(100.0);
+            line.push_str(&format!("  {:.0}%", pct));
+            if rate > 0.0 && done < total {
+                let eta = Duration::from_secs_f64((total - done) as f64 / rate);
+                line.push_str(&format!("  ETA {}", clock(eta)));
+            }
+        }
+
+        self.line.draw(&line);
+    }
+
+    /// Erase the line so the final status message starts clean.
+    pub fn finish(&mut self) {
+        self.line.finish();
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/// --announce-remaining, once settled
+static ANNOUNCE: OnceLock<Duration> = OnceLock::new();
+
+/// Say so when `left` of playback remains; the first call wins.
+pub fn announce_remaining(left: Duration) {
+    let _ = ANNOUNCE.set(left);
+}
+
+/// How long a playback lasts, in samples
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Length {
+    Unknown,
+    /// What the daemon's headers said, or the cache's copy's size
+    About(u64),
+    /// All of it has arrived
+    Exactly(u64),
+}
+
+/// "▶ 01:24 / ~06:10 (23%) −04:46" while audio plays. The position counts
+/// the samples playback has taken, so it and the time left stand still
+/// while playback waits.
+pub struct Playback {
+    rate: u32,
+    line: StatusLine,
+    /// Until --announce-remaining's line has been printed: when the time
+    /// left was last seen above it, if it has been
+    announce: Option<(Duration, bool)>,
+}
+
+impl Playback {
+    pub fn new(rate: u32, quiet: bool) -> Self {
+        let announce = ANNOUNCE.get().map(|left| (*left, false));
+        Self { rate, line: StatusLine::new(PLAYBACK_INTERVAL, quiet), announce }
+    }
+
+    /// `played` samples of `length`
+    pub fn update(&mut self, played: u64, length: Length) {
+        if let (Some((left, above)), Length::About(total) | Length::Exactly(total)) = (&mut self.announce, length) {
+            let remaining = Duration::from_secs(total.saturating_sub(played) / self.rate as u64);
+            if remaining > *left {
+                *above = true;
+            } else if *above {
+                // Only a playback that was longer is told it has this long left
+                let minutes = left.as_secs() / 60;
+                self.line.finish();
+                reporter::info(format_args!("{} {} minute{} left", reporter::mark(Mark::Playing), minutes, if minutes == 1 { "" } else { "s" }));
+                self.announce = None;
+            }
+        }
+        if !self.line.due() {
+            return;
+        }
+        let line = playback_line(reporter::mark(Mark::Playing), reporter::mark(Mark::Remaining), played, length, self.rate);
+        self.line.draw(&line);
+    }
+
+    /// Erase the line before the final ✓.
+    pub fn finish(&mut self) {
+        self.line.finish();
+    }
+}
+
+impl Drop for Playback {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+fn playback_line(mark: &str, minus: &str, played: u64, length: Length, rate: u32) -> String {
+    let at = |samples: u64| position(Duration::from_secs(samples / rate as u64));
+    let (total, about) = match length {
+        Length::About(total) if total > 0 => (total, "~"),
+        Length::Exactly(total) if total > 0 => (total, ""),
+        _ => return format!("{} {} / ?", mark, at(played)),
+    };
+    // An estimate can fall short; the end is at least where playback is
+    let total = total.max(played);
+    let left = position(Duration::from_secs((total - played).div_ceil(rate as u64)));
+    format!("{} {} / {}{} ({}%) {}{}", mark, at(played), about, at(total), played * 100 / total, minus, left)
+}
+
+/// Reader adapter that reports bytes read to a `Progress`.
+pub struct ProgressReader<R> {
+    inner: R,
+    progress: Progress,
+    done: u64,
+}
+
+impl<R> ProgressReader<R> {
+    pub fn new(inner: R, progress: Progress) -> Self {
+        Self { inner, progress, done: 0 }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.done += n as u64;
+        self.progress.update(self.done);
+        Ok(n)
+    }
+}
+
+pub fn human_bytes(n: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = n as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", n, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// m:ss (or h:mm:ss) formatting for elapsed/ETA display
+pub fn clock(d: Duration) -> String {
+    let secs = d.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+/// mm:ss (or h:mm:ss), for a position that keeps its width as it moves
+fn position(d: Duration) -> String {
+    match d.as_secs() {
+        secs if secs >= 3600 => clock(d),
+        secs => format!("{:02}:{:02}", secs / 60, secs % 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shows_the_playback_position() {
+        let rate = 24000;
+        let secs = |n: u64| n * rate as u64;
+        let cases = [
+            (84, Length::About(secs(370)), "▶ 01:24 / ~06:10 (22%) −04:46"),
+            (84, Length::Unknown, "▶ 01:24 / ?"),
+            (0, Length::About(0), "▶ 00:00 / ?"),
+            // Past a short estimate
+            (90, Length::About(secs(60)), "▶ 01:30 / ~01:30 (100%) −00:00"),
+            (3725, Length::Exactly(secs(7200)), "▶ 1:02:05 / 2:00:00 (51%) −57:55"),
+            // Part of a second left is a second left
+            (59, Length::Exactly(secs(60) - 1), "▶ 00:59 / 00:59 (98%) −00:01"),
+        ];
+        for (played, length, expected) in cases {
+            let line = playback_line("▶", "−", secs(played), length, rate);
+            assert_eq!(line, expected, "{:?}", (played, length));
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! `--replay`: a small ring of recent utterances kept next to the cache,
+//! so the last few can be played again without the daemon.
+
+use crate::spool::Spool;
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Utterances kept for --replay -N
+pub const HISTORY_LEN: usize = 10;
+
+pub struct Recorded {
+    pub text: String,
+    pub voice: String,
+    pub audio: PathBuf,
+}
+
+fn history_dir() -> Option<PathBuf> {
+    speakturbo_client::cache::default_dir().map(|d| d.join("recent"))
+}
+
+/// Record the stream as the most recent utterance once it completes.
+/// Passes the stream through untouched when there's nowhere to record.
+pub fn record<R: Read + Send + 'static>(inner: R, text: &str, voice: &str) -> Box<dyn Read + Send> {
+    match history_dir() {
+        Some(dir) => record_in(inner, dir, text, voice),
+        None => Box::new(inner),
+    }
+}
+
+fn record_in<R: Read + Send + 'static>(inner: R, dir: PathBuf, text: &str, voice: &str) -> Box<dyn Read + Send> {
+    // Zero-padded so names sort chronologically
+    let stamp = format!(
+        "{:020}",
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos()
+    );
+    let meta = json!({"text": text, "voice": voice});
+    let tmp = dir.join(format!(".{}.{}.tmp", stamp, std::process::id()));
+    Box::new(Spool::new(inner, tmp, move |tmp| {
+        fs::write(dir.join(format!("{}.json", stamp)), meta.to_string())?;
+        fs::rename(tmp, dir.join(format!("{}.wav", stamp)))?;
+        trim(&dir)
+    }))
+}
+
+/// Recorded utterances, newest first.
+pub fn list() -> Result<Vec<Recorded>> {
+    match history_dir() {
+        Some(dir) => list_in(&dir),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn list_in(dir: &Path) -> Result<Vec<Recorded>> {
+    let mut stamps = wav_stamps(dir)?;
+    stamps.reverse();
+    Ok(stamps
+        .into_iter()
+        .filter_map(|stamp| {
+            let meta = fs::read_to_string(dir.join(format!("{}.json", stamp))).ok()?;
+            let meta: Value = serde_json::from_str(&meta).ok()?;
+            let field = |k: &str| match meta.get(k) {
+                Some(Value::String(s)) => s.clone(),
+                _ => String::new(),
+            };
+            Some(Recorded { text: field("text"), voice: field("voice"), audio: dir.join(format!("{}.wav", stamp)) })
+        })
+        .collect())
+}
+
+/// Open the `back`-th most recent utterance (1 is the last one).
+pub fn open(back: usize) -> Result<(Recorded, File)> {
+    pick(list()?, back)
+}
+
+fn pick(mut recent: Vec<Recorded>, back: usize) -> Result<(Recorded, File)> {
+    if recent.is_empty() {
+        bail!("Nothing to replay yet");
+    }
+    if back == 0 || back > recent.len() {
+        bail!("Only {} utterance(s) recorded, can't replay -{}", recent.len(), back);
+    }
+    let recorded = recent.swap_remove(back - 1);
+    let file = File::open(&recorded.audio)
+        .with_context(|| format!("Cannot open {}", recorded.audio.display()))?;
+    Ok((recorded, file))
+}
+
+fn wav_stamps(dir: &Path) -> Result<Vec<String>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Cannot read {}", dir.display())),
+    };
+    let mut stamps: Vec<String> = entries
+        .filter_map(|e| {
+            let name = e.ok()?.file_name().into_string().ok()?;
+            Some(name.strip_suffix(".wav")?.to_string())
+        })
+        .collect();
+    stamps.sort();
+    Ok(stamps)
+}
+
+/// Drop everything but the newest `HISTORY_LEN` utterances.
+fn trim(dir: &Path) -> Result<()> {
+    let stamps = wav_stamps(dir)?;
+    let excess = stamps.len().saturating_sub(HISTORY_LEN);
+    for stamp in &stamps[..excess] {
+        let _ = fs::remove_file(dir.join(format!("{}.wav", stamp)));
+        let _ = fs::remove_file(dir.join(format!("{}.json", stamp)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(dir: &Path, text: &str) {
+        let mut replayed = Vec::new();
+        record_in(std::io::Cursor::new(format!("audio of {}", text).into_bytes()), dir.to_path_buf(), text, "alba")
+            .read_to_end(&mut replayed)
+            .unwrap();
+        assert_eq!(replayed, format!("audio of {}", text).as_bytes(), "passed through as it is");
+    }
+
+    #[test]
+    fn keeps_the_newest_utterances_newest_first() {
+        let dir = std::env::temp_dir().join(format!("speakturbo-recent-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        assert!(list_in(&dir).unwrap().is_empty());
+        for n in 1..=HISTORY_LEN + 2 {
+            record(&dir, &format!("utterance {}", n));
+        }
+        let recent = list_in(&dir).unwrap();
+        let texts: Vec<&str> = recent.iter().map(|r| r.text.as_str()).collect();
+        assert_eq!(texts.len(), HISTORY_LEN, "the oldest trimmed");
+        assert_eq!(texts[0], format!("utterance {}", HISTORY_LEN + 2));
+        assert_eq!(texts[HISTORY_LEN - 1], "utterance 3");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2 * HISTORY_LEN, "no temporary files left");
+
+        let (recorded, mut file) = pick(list_in(&dir).unwrap(), 2).unwrap();
+        assert_eq!((recorded.text.as_str(), recorded.voice.as_str()), ("utterance 11", "alba"));
+        let mut audio = String::new();
+        file.read_to_string(&mut audio).unwrap();
+        assert_eq!(audio, "audio of utterance 11");
+
+        let e = pick(list_in(&dir).unwrap(), HISTORY_LEN + 1).map(|_| ()).unwrap_err();
+        assert_eq!(e.to_string(), format!("Only {} utterance(s) recorded, can't replay -{}", HISTORY_LEN, HISTORY_LEN + 1));
+        assert!(pick(list_in(&dir).unwrap(), 0).is_err());
+        assert_eq!(pick(Vec::new(), 1).map(|_| ()).unwrap_err().to_string(), "Nothing to replay yet");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn an_interrupted_stream_is_not_recorded() {
+        let dir = std::env::temp_dir().join(format!("speakturbo-recent-cut-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut reader = record_in(std::io::Cursor::new(vec![0u8; 100]), dir.clone(), "cut short", "alba");
+        reader.read_exact(&mut [0u8; 10]).unwrap();
+        drop(reader);
+        assert!(list_in(&dir).unwrap().is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,287 @@
+//! --json's account of an invocation, printed to stdout once it's over:
+//! one object for a single text, an array for a sequence or batch, and on
+//! failure too, so that scripts needn't read the ⚡/▶/✓ lines off stderr.
+//! Fields may be added under the same [`SCHEMA`], never renamed or removed.
+
+use crate::checksum::Checksum;
+use crate::exit::Kind;
+use crate::hooks;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use speakturbo_client::SynthesisStats;
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// The "schema" field, raised on any change a reader could trip over
+pub const SCHEMA: u64 = 1;
+
+/// Set once this invocation has put its JSON on stdout
+static PRINTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Error,
+    Interrupted,
+    /// --batch-dir: the output was up to date
+    Skipped,
+    /// --batch-dir: not reached after Ctrl+C or --fail-fast
+    Pending,
+}
+
+impl From<hooks::Status> for Status {
+    fn from(status: hooks::Status) -> Self {
+        match status {
+            hooks::Status::Ok => Status::Ok,
+            hooks::Status::Error => Status::Error,
+            hooks::Status::Interrupted => Status::Interrupted,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    pub status: Status,
+    pub voice: String,
+    pub text_chars: usize,
+    #[serde(rename = "elapsed_ms", with = "millis")]
+    pub elapsed: Duration,
+    pub output: Option<String>,
+    /// --checksum's digest of `output`
+    pub checksum: Option<Checksum>,
+    /// --batch-dir: the file the text came from
+    pub source: Option<String>,
+    pub error: Option<String>,
+    /// What sort of failure `error` is, as the exit status says
+    pub kind: Option<Kind>,
+    /// What playback or saving saw of the audio, the library's own
+    /// account of a synthesis; the defaults where it didn't get that far.
+    /// Its fields as they are, so that --json can't drift from them
+    #[serde(flatten)]
+    pub measured: SynthesisStats,
+}
+
+/// A report as printed, under its "schema"
+#[derive(Serialize, Deserialize)]
+struct Versioned<R> {
+    schema: u64,
+    #[serde(flatten)]
+    report: R,
+}
+
+/// `elapsed` in whole milliseconds
+mod millis {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(elapsed: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(elapsed.as_millis() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
+
+impl Report {
+    pub fn to_json(&self) -> Value {
+        serde_json::to_value(Versioned { schema: SCHEMA, report: self }).expect("a report is plain data")
+    }
+
+    pub fn print(&self) {
+        println!("{}", self.to_json());
+        mark_printed();
+    }
+}
+
+/// Print `reports` as one array.
+pub fn print_all(reports: &[Report]) {
+    println!("{}", Value::Array(reports.iter().map(Report::to_json).collect()));
+    mark_printed();
+}
+
+/// For output that is this invocation's JSON without being a report:
+/// --dry-run's plans, --stream-lines' line per record.
+pub fn mark_printed() {
+    PRINTED.store(true, Ordering::Relaxed);
+}
+
+pub fn printed() -> bool {
+    PRINTED.load(Ordering::Relaxed)
+}
+
+/// Counts what passes through on the way to a file, for its
+/// [`SynthesisStats`].
+pub struct Tap<R> {
+    inner: R,
+    bytes: u64,
+    first: Option<Instant>,
+    ended: Option<Instant>,
+}
+
+impl<R> Tap<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, bytes: 0, first: None, ended: None }
+    }
+
+    /// What went through, the 44-byte WAV header aside
+    pub fn measured(&self, start: Instant) -> SynthesisStats {
+        SynthesisStats::of_audio(crate::chime::FORMAT, self.bytes.saturating_sub(44), start, self.first, self.ended)
+    }
+}
+
+impl<R: Read> Read for Tap<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        match n {
+            0 if !buf.is_empty() => self.ended = Some(Instant::now()),
+            _ if self.first.is_none() => self.first = Some(Instant::now()),
+            _ => {}
+        }
+        self.bytes += n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksum::Algorithm;
+
+    const KINDS: [Kind; 10] = [
+        Kind::Usage,
+        Kind::DaemonUnreachable,
+        Kind::DaemonError,
+        Kind::AudioUnavailable,
+        Kind::Input,
+        Kind::Interrupted,
+        Kind::ReaderGone,
+        Kind::Partial,
+        Kind::Hook,
+        Kind::Other,
+    ];
+
+    /// A report back from its JSON, as a script would read it
+    fn from_json(value: Value) -> Result<Report, String> {
+        let read: Versioned<Report> = serde_json::from_value(value).map_err(|e| e.to_string())?;
+        match read.schema {
+            SCHEMA => Ok(read.report),
+            schema => Err(format!("schema {} isn't {}", schema, SCHEMA)),
+        }
+    }
+
+    fn report() -> Report {
+        Report {
+            status: Status::Ok,
+            voice: "alba".to_string(),
+            text_chars: 12,
+            elapsed: Duration::from_millis(1830),
+            output: Some("out \"1\".wav".to_string()),
+            checksum: Some(Checksum { algorithm: Algorithm::Sha256, hex: "ab12".to_string() }),
+            source: None,
+            error: None,
+            kind: None,
+            measured: SynthesisStats {
+                first_byte_ms: Some(60),
+                first_sample_ms: Some(95),
+                bytes: 60000,
+                audio_secs: Some(1.25),
+                realtime_factor: Some(0.4),
+                cache_hit: Some(false),
+                underruns: Some(0),
+                synthesis_speed: Some(3.086),
+                stall_ms: Some(0),
+                lead_min_ms: Some(140),
+                lead_median_ms: Some(420),
+                lead_max_ms: Some(900),
+                chunks: 1,
+                endpoint: Some("http://127.0.0.1:7125".to_string()),
+            },
+        }
+    }
+
+    #[test]
+    fn keeps_its_fields_stable() {
+        assert_eq!(
+            report().to_json().to_string(),
+            concat!(
+                r#"{"schema":1,"status":"ok","voice":"alba","text_chars":12,"elapsed_ms":1830,"output":"out \"1\".wav","checksum":{"algorithm":"sha256","hex":"ab12"},"#,
+                r#""source":null,"error":null,"kind":null,"first_byte_ms":60,"first_sample_ms":95,"bytes":60000,"audio_secs":1.25,"#,
+                r#""realtime_factor":0.4,"cache_hit":false,"underruns":0,"synthesis_speed":3.086,"stall_ms":0,"lead_min_ms":140,"#,
+                r#""lead_median_ms":420,"lead_max_ms":900,"chunks":1,"endpoint":"http://127.0.0.1:7125"}"#
+            )
+        );
+    }
+
+    /// --json and the library's serde output can't tell one synthesis's
+    /// stats differently: every field serde writes is in the report, as
+    /// serde writes it, and the report has no stats of its own besides.
+    #[test]
+    fn reports_the_library_stats_as_serde_writes_them() {
+        let report = report();
+        let Value::Object(serialized) = serde_json::to_value(&report.measured).unwrap() else {
+            panic!("the stats didn't serialize as an object");
+        };
+        let Value::Object(reported) = report.to_json() else { unreachable!() };
+        let own = ["schema", "status", "voice", "text_chars", "elapsed_ms", "output", "checksum", "source", "error", "kind"];
+        let stats: Vec<_> = reported.into_iter().filter(|(key, _)| !own.contains(&key.as_str())).collect();
+        assert_eq!(stats, serialized.clone().into_iter().collect::<Vec<_>>());
+        assert_eq!(report.measured.to_json(), Value::Object(serialized));
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let failed = Report {
+            status: Status::Error,
+            output: None,
+            checksum: None,
+            error: Some("Daemon not running?".to_string()),
+            kind: Some(Kind::DaemonUnreachable),
+            measured: SynthesisStats::default(),
+            ..report()
+        };
+        let skipped = Report { status: Status::Skipped, source: Some("texts/a.txt".to_string()), ..report() };
+        let statuses = [Status::Interrupted, Status::Pending].map(|status| Report { status, ..report() });
+        let kinds = KINDS.map(|kind| Report { kind: Some(kind), ..failed.clone() });
+        for report in [report(), failed, skipped].into_iter().chain(statuses).chain(kinds) {
+            let read = from_json(serde_json::from_str(&report.to_json().to_string()).unwrap());
+            assert_eq!(read, Ok(report.clone()), "{:?}", report);
+        }
+        let with = |key: &str, value: Value| {
+            let mut json = report().to_json();
+            json[key] = value;
+            json
+        };
+        let without = |key: &str| {
+            let mut json = report().to_json();
+            json.as_object_mut().unwrap().remove(key);
+            json
+        };
+        let cases = [
+            (without("schema"), "missing field `schema`"),
+            (with("schema", 2.into()), "schema 2 isn't 1"),
+            (with("status", "done".into()), "unknown variant `done`, expected one of `ok`, `error`, `interrupted`, `skipped`, `pending`"),
+            (with("voice", 7.into()), "invalid type: integer `7`, expected a string"),
+            (with("kind", "lost".into()), "unknown variant `lost`"),
+        ];
+        for (json, expected) in cases {
+            let read = from_json(json.clone());
+            assert!(read.as_ref().is_err_and(|e| e.starts_with(expected)), "{}: {:?}", json, read);
+        }
+    }
+
+    #[test]
+    fn measures_what_it_reads() {
+        let start = Instant::now();
+        let audio = vec![0u8; 44 + 2 * 12000];
+        let mut tap = Tap::new(audio.as_slice());
+        std::io::copy(&mut tap, &mut std::io::sink()).unwrap();
+        let measured = tap.measured(start);
+        assert_eq!(measured.audio_secs, Some(0.5));
+        assert!(measured.first_sample_ms.is_some());
+        assert_eq!(measured.realtime_factor.map(|rtf| rtf < 1.0), Some(true));
+        let nothing = Tap::new(&b""[..]).measured(start);
+        assert_eq!((nothing.first_byte_ms, nothing.audio_secs), (None, None));
+    }
+}
@@ -0,0 +1,168 @@
+//! What speakturbo tells the person at the terminal on stderr, by how
+//! quiet they asked it to be. The ⚡/▶/✓ timing lines show only when
+//! stderr is a terminal; -q leaves warnings and errors; -qq leaves nothing,
+//! for the exit status alone. --no-emoji spells the marks in ASCII, as
+//! does a console that can't draw them (see [`crate::console`]).
+//! --verbose's log is [`crate::logging`]'s, and goes its own way.
+
+use crate::console;
+use std::fmt;
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Line {
+    /// "⚡ 95ms": how long until the first audio, playback, the end
+    Timing,
+    /// What's being done or was done: "Saved: out.wav"
+    Info,
+    Warning,
+    Error,
+}
+
+/// The marks that start lines
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mark {
+    /// The first audio arrived
+    First,
+    Playing,
+    Done,
+    Failed,
+    /// Sent on, or started
+    Arrow,
+    /// Before the time left to play
+    Remaining,
+}
+
+impl Mark {
+    fn as_str(self, ascii: bool) -> &'static str {
+        match (self, ascii) {
+            (Mark::First, false) => "⚡",
+            (Mark::Playing, false) => "▶",
+            (Mark::Done, false) => "✓",
+            (Mark::Failed, false) => "✗",
+            (Mark::Arrow, false) => "→",
+            (Mark::Remaining, false) => "−",
+            (Mark::First, true) => "*",
+            (Mark::Playing, true) => ">",
+            (Mark::Done, true) => "ok",
+            (Mark::Failed, true) => "x",
+            (Mark::Arrow, true) => "->",
+            (Mark::Remaining, true) => "-",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Reporter {
+    /// -q once or more
+    pub quiet: u8,
+    /// Whether stderr is a terminal
+    pub terminal: bool,
+    pub ascii: bool,
+}
+
+impl Reporter {
+    pub fn shows(&self, line: Line) -> bool {
+        match line {
+            Line::Timing => self.quiet == 0 && self.terminal,
+            Line::Info => self.quiet == 0,
+            Line::Warning | Line::Error => self.quiet < 2,
+        }
+    }
+
+    /// `message` as a `line` would print, if it would
+    pub fn render(&self, line: Line, message: impl fmt::Display) -> Option<String> {
+        self.shows(line).then(|| match line {
+            Line::Warning => format!("Warning: {}", message),
+            _ => message.to_string(),
+        })
+    }
+}
+
+static REPORTER: OnceLock<Reporter> = OnceLock::new();
+
+/// Settle the levels; the first call wins. Until then, everything shows
+/// but the timing lines off a terminal.
+pub fn init(quiet: u8, no_emoji: bool) {
+    let ascii = no_emoji || !console::unicode();
+    let _ = REPORTER.set(Reporter { quiet, terminal: std::io::stderr().is_terminal(), ascii });
+}
+
+fn reporter() -> Reporter {
+    REPORTER.get().copied().unwrap_or(Reporter { quiet: 0, terminal: std::io::stderr().is_terminal(), ascii: !console::unicode() })
+}
+
+/// Whether -q was given, which also hides the progress lines
+pub fn quiet() -> bool {
+    reporter().quiet > 0
+}
+
+/// `mark` as this invocation spells it
+pub fn mark(mark: Mark) -> &'static str {
+    mark.as_str(reporter().ascii)
+}
+
+fn print(line: Line, message: impl fmt::Display) {
+    if let Some(text) = reporter().render(line, message) {
+        eprintln!("{}", text);
+    }
+}
+
+/// "⚡ 95ms": `mark` and the time since `start`
+pub fn timing(mark: Mark, start: Instant) {
+    timing_at(mark, start.elapsed().as_millis() as u64);
+}
+
+/// "⚡ 95ms": `mark` and the `ms` it came at
+pub fn timing_at(mark: Mark, ms: u64) {
+    timing_line(format_args!("{} {}ms", self::mark(mark), ms));
+}
+
+/// A timing line that says more than [`timing`]'s
+pub fn timing_line(message: impl fmt::Display) {
+    print(Line::Timing, message);
+}
+
+pub fn info(message: impl fmt::Display) {
+    print(Line::Info, message);
+}
+
+/// Prints "Warning: " and `message`.
+pub fn warning(message: impl fmt::Display) {
+    print(Line::Warning, message);
+}
+
+pub fn error(message: impl fmt::Display) {
+    print(Line::Error, message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prints_what_each_level_allows() {
+        let lines = [(Line::Timing, "⚡ 95ms"), (Line::Info, "Saved: a.wav"), (Line::Warning, "odd"), (Line::Error, "Error: gone")];
+        let cases: [(u8, bool, [Option<&str>; 4]); 5] = [
+            (0, true, [Some("⚡ 95ms"), Some("Saved: a.wav"), Some("Warning: odd"), Some("Error: gone")]),
+            (0, false, [None, Some("Saved: a.wav"), Some("Warning: odd"), Some("Error: gone")]),
+            (1, true, [None, None, Some("Warning: odd"), Some("Error: gone")]),
+            (2, true, [None, None, None, None]),
+            (3, false, [None, None, None, None]),
+        ];
+        for (quiet, terminal, expected) in cases {
+            let reporter = Reporter { quiet, terminal, ascii: false };
+            let printed = lines.map(|(line, message)| reporter.render(line, message));
+            assert_eq!(printed, expected.map(|e| e.map(str::to_string)), "-q ×{} on a terminal: {}", quiet, terminal);
+        }
+    }
+
+    #[test]
+    fn spells_marks_in_ascii() {
+        let marks = [Mark::First, Mark::Playing, Mark::Done, Mark::Failed, Mark::Arrow];
+        assert_eq!(marks.map(|m| m.as_str(false)), ["⚡", "▶", "✓", "✗", "→"]);
+        assert_eq!(marks.map(|m| m.as_str(true)), ["*", ">", "ok", "x", "->"]);
+    }
+}
@@ -0,0 +1,153 @@
+//! RTP (RFC 3550) L16 mono output sink.
+
+use crate::{LockFreeBuffer, SAMPLE_RATE};
+use anyhow::{Context, Result};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+// 20ms packets, the usual RTP audio ptime
+const PACKET_SAMPLES: usize = (SAMPLE_RATE / 50) as usize;
+// L16's static payload types (10/11) are fixed at 44.1kHz, so use a dynamic one
+const PAYLOAD_TYPE: u8 = 96;
+
+pub struct RtpStats {
+    pub packets: u64,
+    pub late: u64,
+}
+
+/// Send everything in `buffer` to `target` as RTP, paced by the sample clock.
+/// The buffer doubles as the jitter reservoir: a fast daemon fills it ahead
+/// of the clock, a slow one shows up as late packets.
+pub fn send(buffer: &LockFreeBuffer, target: &str) -> Result<RtpStats> {
+    // Random SSRC and initial sequence/timestamp, per RFC 3550
+    let rng = SystemRandom::new();
+    let mut seed = [0u8; 10];
+    rng.fill(&mut seed).map_err(|_| anyhow::anyhow!("No system randomness"))?;
+    let first = First {
+        ssrc: u32::from_be_bytes([seed[0], seed[1], seed[2], seed[3]]),
+        seq: u16::from_be_bytes([seed[4], seed[5]]),
+        timestamp: u32::from_be_bytes([seed[6], seed[7], seed[8], seed[9]]),
+    };
+    send_from(buffer, target, first)
+}
+
+/// Where a stream's numbering starts
+struct First {
+    ssrc: u32,
+    seq: u16,
+    timestamp: u32,
+}
+
+fn send_from(buffer: &LockFreeBuffer, target: &str, first: First) -> Result<RtpStats> {
+    let addr = target
+        .to_socket_addrs()
+        .with_context(|| format!("Invalid RTP target: {}", target))?
+        .next()
+        .with_context(|| format!("RTP target did not resolve: {}", target))?;
+    let bind = if addr.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" };
+    let socket = UdpSocket::bind(bind).context("Cannot open UDP socket")?;
+    socket.connect(addr).with_context(|| format!("Cannot reach {}", addr))?;
+
+    let First { ssrc, mut seq, mut timestamp } = first;
+
+    let mut stats = RtpStats { packets: 0, late: 0 };
+    let mut packet = Vec::with_capacity(12 + PACKET_SAMPLES * 2);
+    let mut clock = Instant::now();
+    let mut sent_samples: u64 = 0;
+
+    while !crate::interrupt::requested() {
+        // Deadline comes from samples already sent, not from network arrival
+        let due = clock + Duration::from_secs_f64(sent_samples as f64 / SAMPLE_RATE as f64);
+        let now = Instant::now();
+        if due > now {
+            std::thread::sleep(due - now);
+        }
+
+        // Starved: wait for a full packet and shift the clock by the stall
+        let mut late = false;
+        let stall_start = Instant::now();
+        while buffer.len() < PACKET_SAMPLES && !buffer.is_done() && !crate::interrupt::requested() {
+            late = true;
+            std::thread::sleep(Duration::from_micros(500));
+        }
+        if late {
+            clock += stall_start.elapsed();
+            stats.late += 1;
+        }
+
+        packet.clear();
+        packet.push(0x80); // V=2, no padding/extension/CSRC
+        packet.push(if stats.packets == 0 { 0x80 | PAYLOAD_TYPE } else { PAYLOAD_TYPE });
+        packet.extend_from_slice(&seq.to_be_bytes());
+        packet.extend_from_slice(&timestamp.to_be_bytes());
+        packet.extend_from_slice(&ssrc.to_be_bytes());
+
+        let mut samples = 0;
+        while samples < PACKET_SAMPLES {
+            match buffer.pop() {
+                // L16 is network byte order
+                Some(sample) => packet.extend_from_slice(&sample.to_be_bytes()),
+                None => break,
+            }
+            samples += 1;
+        }
+        if samples == 0 {
+            break;
+        }
+
+        match socket.send(&packet) {
+            // ICMP port-unreachable from an earlier packet; receivers may come and go
+            Err(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => {}
+            result => {
+                result.context("RTP send failed")?;
+            }
+        }
+        stats.packets += 1;
+        seq = seq.wrapping_add(1);
+        timestamp = timestamp.wrapping_add(samples as u32);
+        sent_samples += samples as u64;
+    }
+
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sends_numbered_big_endian_l16_packets() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let target = receiver.local_addr().unwrap().to_string();
+
+        // Two full packets and part of a third
+        let samples: Vec<i16> = (0..2 * PACKET_SAMPLES + 100).map(|i| (i as i16).wrapping_mul(97).wrapping_sub(20000)).collect();
+        let buffer = LockFreeBuffer::new();
+        for &sample in &samples {
+            buffer.push(sample);
+        }
+        buffer.set_done();
+        let first = First { ssrc: 0xDEADBEEF, seq: 0xFFFE, timestamp: u32::MAX - 500 };
+        let stats = send_from(&buffer, &target, first).unwrap();
+        assert_eq!(stats.packets, 3);
+
+        let mut received = Vec::new();
+        let mut packet = [0u8; 2048];
+        for n in 0..3usize {
+            let len = receiver.recv(&mut packet).unwrap();
+            let packet = &packet[..len];
+            assert_eq!(packet[0], 0x80, "version 2, no padding, extension or CSRC");
+            assert_eq!(packet[1] & 0x7F, PAYLOAD_TYPE);
+            assert_eq!(packet[1] & 0x80 != 0, n == 0, "the marker starts the talkspurt");
+            let seq = u16::from_be_bytes([packet[2], packet[3]]);
+            assert_eq!(seq, 0xFFFEu16.wrapping_add(n as u16), "0xFFFE, 0xFFFF, then 0");
+            let timestamp = u32::from_be_bytes([packet[4], packet[5], packet[6], packet[7]]);
+            assert_eq!(timestamp, (u32::MAX - 500).wrapping_add((n * PACKET_SAMPLES) as u32), "advances by the samples sent");
+            assert_eq!(u32::from_be_bytes([packet[8], packet[9], packet[10], packet[11]]), 0xDEADBEEF);
+            received.extend(packet[12..].chunks_exact(2).map(|b| i16::from_be_bytes([b[0], b[1]])));
+        }
+        assert_eq!(received, samples);
+    }
+}
@@ -0,0 +1,194 @@
+//! `speakturbo self-test`: the whole client pipeline against a
+//! [`MockDaemon`] in this process, to check an installation without a real
+//! daemon or model. Each stage passes or fails on its own, as far as the
+//! stages before it allow.
+
+use crate::chime::Chimes;
+use crate::mock_daemon::{self, MockDaemon};
+use crate::output::{self, ExistingPolicy, SaveOptions};
+use crate::reporter::{self, Mark};
+use crate::{report, wav, Args, Events, StreamSource, SAMPLE_RATE};
+use anyhow::{bail, Context, Result};
+use serde_json::json;
+use std::time::Instant;
+
+const TEXT: &str = "Self-test: one, two, three.";
+
+pub enum Outcome {
+    Pass(String),
+    Fail(anyhow::Error),
+    Skipped(&'static str),
+}
+
+pub struct Stage {
+    pub name: &'static str,
+    pub outcome: Outcome,
+    pub ms: u64,
+}
+
+/// Runs `check` as the stage `name`, timed.
+fn stage(name: &'static str, check: impl FnOnce() -> Result<String>) -> Stage {
+    let started = Instant::now();
+    let outcome = match check() {
+        Ok(detail) => Outcome::Pass(detail),
+        Err(e) => Outcome::Fail(e),
+    };
+    Stage { name, outcome, ms: started.elapsed().as_millis() as u64 }
+}
+
+fn skipped(name: &'static str, why: &'static str) -> Stage {
+    Stage { name, outcome: Outcome::Skipped(why), ms: 0 }
+}
+
+/// Every stage against a new mock daemon; without `play`, playback is
+/// skipped and the samples stop at the buffer.
+pub fn stages(args: &Args, play: bool) -> Vec<Stage> {
+    let mut daemon = None;
+    let mut stages = vec![stage("daemon", || {
+        let mock = MockDaemon::start().context("Cannot start the mock daemon")?;
+        let url = mock.url();
+        if crate::DAEMON.set(url.clone()).is_err() {
+            bail!("The daemon URL was already set to {}", crate::daemon_url());
+        }
+        daemon = Some(mock);
+        Ok(format!("listening on {}", url))
+    })];
+    if daemon.is_none() {
+        return stages;
+    }
+
+    let expected = mock_daemon::samples_for(TEXT);
+    stages.push(stage("voices", || match crate::daemon_voices() {
+        Some(voices) if voices.contains(&args.voice) => Ok(format!("{} voices", voices.len())),
+        Some(voices) => bail!("{} isn't among {}", args.voice, voices.join(", ")),
+        None => bail!("No list of voices from /health"),
+    }));
+    stages.push(stage("request", || {
+        let (mut audio, _, _) = crate::open_audio(args, None, TEXT)?;
+        let (format, _) = wav::read_header(&mut audio)?;
+        if (format.channels, format.sample_rate, format.bits_per_sample) != (1, SAMPLE_RATE, 16) {
+            bail!("Expected 16-bit mono at {} Hz, got {:?}", SAMPLE_RATE, format);
+        }
+        let bytes = std::io::copy(&mut audio, &mut std::io::sink())?;
+        check_length(bytes / 2, expected)?;
+        Ok(format!("{}-bit, {} channel, {} Hz; {} bytes", format.bits_per_sample, format.channels, format.sample_rate, bytes))
+    }));
+    stages.push(stage("buffering", || {
+        let (audio, _, _) = crate::open_audio(args, None, TEXT)?;
+        let buffer = crate::spawn_net_reader(audio, Instant::now(), Events::none(), None, usize::MAX)?;
+        let samples = StreamSource::new(std::sync::Arc::clone(&buffer)).count();
+        if let Some(error) = buffer.error() {
+            bail!(error);
+        }
+        check_length(samples as u64, expected)?;
+        Ok(format!("{} samples", samples))
+    }));
+    stages.push(match play {
+        true => stage("playback", || {
+            let (audio, _, _) = crate::open_audio(args, None, TEXT)?;
+            let measured = crate::stream_audio(audio, crate::Shown::Position(None), Instant::now(), true, None, Chimes::default(), usize::MAX)?;
+            Ok(format!("{:.2}s played", measured.audio_secs.unwrap_or(0.0)))
+        }),
+        false => skipped("playback", "--no-audio"),
+    });
+    stages.push(stage("save", || {
+        let dir = std::env::temp_dir().join(format!("speakturbo-self-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("self-test.wav");
+        let saved = (|| {
+            let (audio, _, _) = crate::open_audio(args, None, TEXT)?;
+            let options = SaveOptions {
+                policy: ExistingPolicy::Overwrite,
+                checksum: None,
+                envelope: None,
+                chimes: Chimes::default(),
+                fifo_wait: None,
+            };
+            output::save(&path, &options, audio)?;
+            let mut file = std::fs::File::open(&path)?;
+            let (_, header) = wav::read_header(&mut file)?;
+            let bytes = file.metadata()?.len() - header;
+            check_length(bytes / 2, expected)?;
+            Ok(format!("{} bytes", bytes + header))
+        })();
+        let _ = std::fs::remove_dir_all(&dir);
+        saved
+    }));
+    stages
+}
+
+fn check_length(samples: u64, expected: usize) -> Result<()> {
+    match samples == expected as u64 {
+        true => Ok(()),
+        false => bail!("Expected {} samples, got {}", expected, samples),
+    }
+}
+
+pub fn run(args: &mut Args, play: bool) -> Result<()> {
+    args.no_record = true;
+    let stages = stages(args, play);
+    match args.json {
+        true => {
+            let listed = stages.iter().map(|stage| {
+                let (status, detail) = match &stage.outcome {
+                    Outcome::Pass(detail) => ("pass", detail.clone()),
+                    Outcome::Fail(e) => ("fail", format!("{:#}", e)),
+                    Outcome::Skipped(why) => ("skipped", why.to_string()),
+                };
+                json!({"stage": stage.name, "status": status, "detail": detail, "ms": stage.ms})
+            });
+            let passed = stages.iter().all(|s| !matches!(s.outcome, Outcome::Fail(_)));
+            println!("{}", json!({"schema": report::SCHEMA, "passed": passed, "stages": listed.collect::<Vec<_>>()}));
+            report::mark_printed();
+        }
+        false => {
+            for stage in &stages {
+                match &stage.outcome {
+                    Outcome::Pass(detail) => {
+                        reporter::info(format_args!("{} {:<10} {} ({}ms)", reporter::mark(Mark::Done), stage.name, detail, stage.ms))
+                    }
+                    Outcome::Fail(e) => reporter::error(format_args!("{} {:<10} {:#}", reporter::mark(Mark::Failed), stage.name, e)),
+                    Outcome::Skipped(why) => reporter::info(format_args!("- {:<10} skipped ({})", stage.name, why)),
+                }
+            }
+        }
+    }
+
+    let mut failed = stages.into_iter().filter_map(|stage| match stage.outcome {
+        Outcome::Fail(e) => Some((stage.name, e)),
+        _ => None,
+    });
+    match failed.next() {
+        None => Ok(()),
+        // The first failure's kind is the exit status
+        Some((name, e)) => {
+            let more = failed.count();
+            let message = match more {
+                0 => format!("Self-test failed at {}", name),
+                _ => format!("Self-test failed at {} and {} more", name, more),
+            };
+            Err(e.context(message))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn passes_against_the_mock() {
+        let args = Args::parse_from(["speakturbo", "--no-record"]);
+        let stages = stages(&args, false);
+        let names: Vec<&str> = stages.iter().map(|s| s.name).collect();
+        assert_eq!(names, ["daemon", "voices", "request", "buffering", "playback", "save"]);
+        for stage in &stages {
+            match &stage.outcome {
+                Outcome::Fail(e) => panic!("{}: {:#}", stage.name, e),
+                Outcome::Skipped(why) => assert_eq!((stage.name, *why), ("playback", "--no-audio")),
+                Outcome::Pass(_) => {}
+            }
+        }
+    }
+}
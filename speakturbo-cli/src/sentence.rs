@@ -0,0 +1,206 @@
+//! Sentence boundaries, for cutting long texts into chunks the daemon
+//! starts on quickly (`--chunk-chars`).
+
+use std::ops::RangeInclusive;
+
+/// Abbreviations whose period doesn't end a sentence, even before a
+/// capital: titles, mostly ("Dr. Smith")
+const ABBREVIATIONS: &[&str] = &[
+    "mr", "mrs", "ms", "dr", "prof", "rev", "hon", "gen", "col", "capt", "lt", "sgt", "st", "mt", "jr", "sr", "vs",
+    "cf", "fig", "approx", "dept", "est", "no",
+];
+
+/// Closing quotes and brackets that belong to the sentence they follow
+const CLOSERS: &[char] = &['"', '\'', '”', '’', '»', ')', ']'];
+
+fn is_terminator(c: char) -> bool {
+    matches!(c, '.' | '!' | '?' | '…')
+}
+
+/// The sentences of `text`, in order and trimmed.
+pub fn split(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((at, c)) = chars.next() {
+        if !is_terminator(c) {
+            continue;
+        }
+        let mut single_period = c == '.';
+        while chars.peek().is_some_and(|(_, next)| is_terminator(*next)) {
+            single_period = false;
+            chars.next();
+        }
+        while chars.peek().is_some_and(|(_, next)| CLOSERS.contains(next)) {
+            chars.next();
+        }
+        let end = chars.peek().map_or(text.len(), |&(end, _)| end);
+        let at_space = chars.peek().is_none_or(|(_, next)| next.is_whitespace());
+        if at_space && ends_sentence(&text[start..at], &text[end..], single_period) {
+            push_trimmed(&mut sentences, &text[start..end]);
+            start = end;
+        }
+    }
+    push_trimmed(&mut sentences, &text[start..]);
+    sentences
+}
+
+fn push_trimmed<'a>(sentences: &mut Vec<&'a str>, sentence: &'a str) {
+    let sentence = sentence.trim();
+    if !sentence.is_empty() {
+        sentences.push(sentence);
+    }
+}
+
+/// Whether punctuation after `before` ends the sentence, given the text
+/// `after` it. A lone period after an abbreviation, an initial ("J. R. R.")
+/// or a dotted word ("e.g.") doesn't; nothing followed by lowercase does.
+fn ends_sentence(before: &str, after: &str, single_period: bool) -> bool {
+    if after.trim_start().starts_with(char::is_lowercase) {
+        return false;
+    }
+    if !single_period {
+        return true;
+    }
+    let word = before.rsplit(char::is_whitespace).next().unwrap_or("");
+    let word = word.trim_start_matches(|c: char| !c.is_alphanumeric());
+    let initial = word.chars().count() == 1 && word.chars().all(char::is_alphabetic);
+    let dotted = word.contains('.') && word.chars().all(|c| c.is_alphabetic() || c == '.');
+    !(initial || dotted || ABBREVIATIONS.contains(&word.to_lowercase().as_str()))
+}
+
+/// `text` in chunks of at most `max_chars` characters, broken between
+/// sentences where possible, each with the 1-based sentences it covers.
+/// Sentences are numbered from `first`. A `max_chars` of 0 means one chunk.
+pub fn chunks(text: &str, max_chars: usize, first: usize) -> Vec<(String, RangeInclusive<usize>)> {
+    let sentences = split(text);
+    let count = sentences.len().max(1);
+    if max_chars == 0 || text.chars().count() <= max_chars {
+        return vec![(text.trim().to_string(), first..=first + count - 1)];
+    }
+
+    let offset = |s: &str| s.as_ptr() as usize - text.as_ptr() as usize;
+    let mut chunks = Vec::new();
+    // The chunk being filled: its byte span, characters and first sentence
+    let mut current: Option<(usize, usize, usize, usize)> = None;
+    for (i, sentence) in sentences.iter().enumerate() {
+        let n = first + i;
+        let (start, end) = (offset(sentence), offset(sentence) + sentence.len());
+        let len = sentence.chars().count();
+        if let Some((from, to, chars, since)) = current {
+            if chars + 1 + len <= max_chars {
+                current = Some((from, end, chars + 1 + len, since));
+                continue;
+            }
+            chunks.push((text[from..to].to_string(), since..=n - 1));
+            current = None;
+        }
+        if len <= max_chars {
+            current = Some((start, end, len, n));
+        } else {
+            chunks.extend(split_long(sentence, max_chars).into_iter().map(|part| (part.to_string(), n..=n)));
+        }
+    }
+    if let Some((from, to, _, since)) = current {
+        chunks.push((text[from..to].to_string(), since..=first + sentences.len() - 1));
+    }
+    chunks
+}
+
+/// The longest start of `text` that is whole sentences of at most
+/// `max_chars` characters in all; when even the first sentence is longer,
+/// its start cut as in [`chunks`].
+pub fn cut(text: &str, max_chars: usize) -> &str {
+    let limit = text.char_indices().nth(max_chars).map_or(text.len(), |(at, _)| at);
+    let whole = split(text)
+        .iter()
+        .map(|s| s.as_ptr() as usize - text.as_ptr() as usize + s.len())
+        .take_while(|&end| end <= limit)
+        .last();
+    match whole {
+        Some(end) => &text[..end],
+        None => text[..cut_long(text, max_chars)].trim(),
+    }
+}
+
+/// A sentence longer than `max_chars`, cut after a clause's punctuation if
+/// there's one in the second half of the limit, or else between words.
+fn split_long(sentence: &str, max_chars: usize) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut rest = sentence;
+    while rest.chars().count() > max_chars {
+        let cut = cut_long(rest, max_chars);
+        parts.push(rest[..cut].trim());
+        rest = rest[cut..].trim_start();
+    }
+    if !rest.is_empty() {
+        parts.push(rest);
+    }
+    parts
+}
+
+/// Where to cut `text` to keep at most `max_chars` of it (see [`split_long`]).
+fn cut_long(text: &str, max_chars: usize) -> usize {
+    let limit = text.char_indices().nth(max_chars).map_or(text.len(), |(at, _)| at);
+    let window = &text[..limit];
+    let clause = window
+        .rfind([',', ';', ':', '—'])
+        .map(|at| at + window[at..].chars().next().map_or(1, char::len_utf8))
+        .filter(|&at| at > limit / 2);
+    clause
+        .or_else(|| window.rfind(char::is_whitespace).filter(|&at| at > 0))
+        .unwrap_or(limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_sentence_boundaries() {
+        let cases: &[(&str, &[&str])] = &[
+            ("One. Two! Three? Four", &["One.", "Two!", "Three?", "Four"]),
+            ("Dr. Smith paid $3.50 at St. Mary's. Then he left.", &["Dr. Smith paid $3.50 at St. Mary's.", "Then he left."]),
+            ("Pi is 3.14159. It never ends.", &["Pi is 3.14159.", "It never ends."]),
+            ("He said \"Stop.\" Then he left.", &["He said \"Stop.\"", "Then he left."]),
+            ("\"Stop!\" he said. (It was late.) Fine.", &["\"Stop!\" he said.", "(It was late.)", "Fine."]),
+            ("Use a tool, e.g. Cargo. Or not.", &["Use a tool, e.g. Cargo.", "Or not."]),
+            ("Written by J. R. R. Tolkien. Good.", &["Written by J. R. R. Tolkien.", "Good."]),
+            ("Wait... What? Hmm… ok.", &["Wait...", "What?", "Hmm… ok."]),
+            ("It costs approx. ten euros.", &["It costs approx. ten euros."]),
+            ("", &[]),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(split(text), *expected, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn packs_sentences_into_chunks() {
+        let text = "First one here. Second one. Third sentence is longer. Fourth.";
+        assert_eq!(
+            chunks(text, 30, 1),
+            [
+                ("First one here. Second one.".to_string(), 1..=2),
+                ("Third sentence is longer.".to_string(), 3..=3),
+                ("Fourth.".to_string(), 4..=4),
+            ]
+        );
+        assert_eq!(chunks(text, 0, 5), [(text.to_string(), 5..=8)]);
+        assert_eq!(chunks("Short.", 400, 1), [("Short.".to_string(), 1..=1)]);
+    }
+
+    #[test]
+    fn cuts_long_sentences_at_clauses_then_words() {
+        let text = "Alpha beta gamma, delta epsilon zeta eta theta iota kappa lambda.";
+        assert_eq!(
+            chunks(text, 24, 1),
+            [
+                ("Alpha beta gamma,".to_string(), 1..=1),
+                ("delta epsilon zeta eta".to_string(), 1..=1),
+                ("theta iota kappa lambda.".to_string(), 1..=1),
+            ]
+        );
+        assert_eq!(split_long("abcdefghij", 4), ["abcd", "efgh", "ij"]);
+    }
+}
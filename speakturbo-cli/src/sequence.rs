@@ -0,0 +1,378 @@
+//! Several texts in one invocation, spoken in order as separate syntheses.
+
+use crate::checkpoint::{self, Checkpoint};
+use crate::checksum::Checksum;
+use crate::chime::Chimes;
+use crate::exit::{self, Kind};
+use crate::output::ExistingPolicy;
+use crate::report::{self, Report};
+use crate::reporter::{self, Mark};
+use crate::summary::{self, Entry};
+use crate::playback::Output;
+use crate::{interrupt, marks, Args, LockFreeBuffer, StreamSource, SynthesisStats};
+use anyhow::Result;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// One synthesis of a sequence or batch.
+pub struct Item<'a> {
+    pub text: &'a str,
+    pub output: Option<String>,
+    pub policy: ExistingPolicy,
+    /// Settings of its own, in place of the invocation's
+    pub args: Option<&'a Args>,
+}
+
+pub struct ItemResult {
+    /// 1-based position in the items passed in
+    pub n: usize,
+    pub output: Option<String>,
+    pub error: Option<String>,
+    pub kind: Option<Kind>,
+    pub measured: SynthesisStats,
+    /// --checksum's digest of `output`
+    pub checksum: Option<Checksum>,
+    /// From the start of the invocation to the item's end
+    pub elapsed: Duration,
+    /// Finished by an earlier run, as --checkpoint recorded
+    pub skipped: bool,
+}
+
+pub fn run(args: &Args, items: &[String], start: Instant) -> Result<()> {
+    if args.serve.is_some() {
+        return Err(exit::fail(Kind::Usage, "--serve takes a single text"));
+    }
+    if args.waveform.is_some() {
+        return Err(exit::fail(Kind::Usage, "--waveform takes a single text"));
+    }
+    let outputs: Vec<Option<String>> = match &args.output {
+        Some(template) if !template.contains("{n}") => {
+            return Err(exit::fail(Kind::Usage, "With several texts, -o needs a {n} placeholder (e.g. part-{n}.wav)"));
+        }
+        Some(template) => (1..=items.len()).map(|n| Some(expand(template, n, items.len()))).collect(),
+        None => vec![None; items.len()],
+    };
+    run_to(args, items, outputs, start)
+}
+
+/// Like [`run`], with each item's output path already worked out.
+pub fn run_to(args: &Args, items: &[String], outputs: Vec<Option<String>>, start: Instant) -> Result<()> {
+    let policy = crate::existing_policy(args);
+    if args.dry_run {
+        let plans: Vec<_> = items
+            .iter()
+            .zip(&outputs)
+            .map(|(text, output)| crate::dry_run_plan(args, text, output.as_deref(), policy))
+            .collect();
+        return crate::dry_run::report_all(&plans, args.json);
+    }
+
+    let results = if args.output.is_some() || args.rtp.is_some() {
+        let items: Vec<Item> = items
+            .iter()
+            .zip(outputs)
+            .map(|(text, output)| Item { text, output, policy, args: None })
+            .collect();
+        one_by_one(args, &items, start)?
+    } else {
+        play_gapless(args, items, start)?
+    };
+
+    let entries: Vec<Entry> = results
+        .iter()
+        .map(|r| Entry {
+            label: format!("item {}", r.n),
+            report: r.report(args, &items[r.n - 1]),
+            job: Some(summary::job(&items[r.n - 1], &args.voice, r.output.as_deref())),
+        })
+        .collect();
+    if args.json {
+        report::print_all(&entries.iter().map(|e| e.report.clone()).collect::<Vec<_>>());
+    }
+    // Played texts only need a table when one failed
+    let all_ok = entries.iter().all(|e| e.report.status == report::Status::Ok);
+    summary::finish(&entries, args.report.as_deref(), args.json || all_ok)
+}
+
+impl ItemResult {
+    /// A success with nothing measured
+    fn new(n: usize, output: Option<String>, elapsed: Duration) -> ItemResult {
+        ItemResult { n, output, error: None, kind: None, measured: SynthesisStats::default(), checksum: None, elapsed, skipped: false }
+    }
+
+    pub fn report(&self, args: &Args, text: &str) -> Report {
+        let status = match (&self.error, self.skipped) {
+            (Some(_), _) => report::Status::Error,
+            (None, true) => report::Status::Skipped,
+            (None, false) => report::Status::Ok,
+        };
+        Report {
+            status,
+            voice: args.voice.clone(),
+            text_chars: text.chars().count(),
+            elapsed: self.elapsed,
+            output: self.output.clone(),
+            checksum: self.checksum.clone(),
+            source: None,
+            error: self.error.clone(),
+            kind: self.kind,
+            measured: self.measured.clone(),
+        }
+    }
+}
+
+/// The kind that all the failures are, or [`Kind::Other`] if they differ.
+pub fn shared(kinds: &[Kind]) -> Kind {
+    match kinds.split_first() {
+        Some((first, rest)) if rest.iter().all(|k| k == first) => *first,
+        _ => Kind::Other,
+    }
+}
+
+/// `part-{n}.wav` -> `part-03.wav`, padded so the files sort in order.
+pub fn expand(template: &str, n: usize, total: usize) -> String {
+    let width = total.to_string().len();
+    template.replace("{n}", &format!("{:0width$}", n, width = width))
+}
+
+fn report_failure(n: usize, total: usize, error: &anyhow::Error) {
+    reporter::error(format_args!("{} [{}/{}] {:#}", reporter::mark(Mark::Failed), n, total, error));
+}
+
+/// Saving and RTP: each item goes through the single-text path. Saves run
+/// `--jobs` at a time; RTP items go out strictly in order.
+pub fn one_by_one(args: &Args, items: &[Item], start: Instant) -> Result<Vec<ItemResult>> {
+    one_by_one_each(args, items, start, |_| {})
+}
+
+/// Like [`one_by_one`], passing each result to `done` as it comes. With
+/// --jobs, Ctrl+C lets the items under way finish, and the time saved
+/// over one at a time is reported. With --checkpoint, the items an
+/// earlier run finished are skipped and each one finished is recorded.
+pub fn one_by_one_each(args: &Args, items: &[Item], start: Instant, done: impl Fn(&ItemResult) + Sync) -> Result<Vec<ItemResult>> {
+    let checkpoint = args.checkpoint.as_deref().map(|path| Checkpoint::open(path, args.restart)).transpose()?;
+    // An item's identity in the checkpoint, if it has an output to check
+    let identity = |item: &Item| {
+        let item_args = item.args.unwrap_or(args);
+        let key = checkpoint::Key { text: item.text, voice: &item_args.voice, daemon: crate::daemon_url(), params: &item_args.params };
+        item.output.as_deref().filter(|_| checkpoint.is_some()).map(|output| checkpoint::item(&key, output))
+    };
+    let workers = match args.rtp {
+        Some(_) => 1,
+        None => (args.jobs.unwrap_or(1) as usize).clamp(1, items.len().max(1)),
+    };
+    if workers > 1 {
+        interrupt::let_work_finish();
+    }
+    let began = Instant::now();
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let stopping = AtomicBool::new(false);
+    let under_way = AtomicUsize::new(0);
+    // Each item's own time, summed: roughly how long one at a time would take
+    let one_at_a_time = Mutex::new(Duration::ZERO);
+    let results = Mutex::new(Vec::new());
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                if interrupt::requested() {
+                    let left = under_way.load(Ordering::SeqCst);
+                    if workers > 1 && left > 0 && !stopping.swap(true, Ordering::SeqCst) {
+                        reporter::warning(format_args!("stopping; {} under way will finish (Ctrl+C again to stop now)", left));
+                    }
+                    break;
+                }
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(i) else { break };
+                let id = identity(item);
+                if let (Some(checkpoint), Some(id), Some(output)) = (&checkpoint, &id, &item.output) {
+                    if checkpoint.completed(id, Path::new(output)) {
+                        let result = ItemResult { skipped: true, ..ItemResult::new(i + 1, item.output.clone(), start.elapsed()) };
+                        done(&result);
+                        results.lock().unwrap().push(result);
+                        continue;
+                    }
+                }
+                // Played items, or items sent over RTP, are heard in turn
+                if i > 0 && (args.rtp.is_some() || item.output.is_none()) {
+                    std::thread::sleep(crate::gap(args, 0));
+                }
+                under_way.fetch_add(1, Ordering::SeqCst);
+                let began_item = Instant::now();
+                let outcome = with_retries(args, item, start);
+                *one_at_a_time.lock().unwrap() += began_item.elapsed();
+                under_way.fetch_sub(1, Ordering::SeqCst);
+                let (measured, checksum, error, kind) = match outcome {
+                    Ok((measured, checksum)) => {
+                        if let (Some(checkpoint), Some(id), Some(output)) = (&checkpoint, &id, &item.output) {
+                            if let Err(e) = checkpoint.record(id, Path::new(output)) {
+                                reporter::warning(format_args!("{:#}", e));
+                            }
+                        }
+                        (measured, checksum, None, None)
+                    }
+                    Err(e) => {
+                        report_failure(i + 1, items.len(), &e);
+                        if args.fail_fast {
+                            stop.store(true, Ordering::Relaxed);
+                        }
+                        (SynthesisStats::default(), None, Some(format!("{:#}", e)), Some(Kind::of(&e)))
+                    }
+                };
+                let elapsed = start.elapsed();
+                let result = ItemResult { error, kind, measured, checksum, ..ItemResult::new(i + 1, item.output.clone(), elapsed) };
+                done(&result);
+                results.lock().unwrap().push(result);
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|r| r.n);
+    if workers > 1 && !interrupt::requested() {
+        let (wall, serial) = (began.elapsed(), one_at_a_time.into_inner().unwrap());
+        reporter::info(speedup(workers, wall, serial));
+    }
+    Ok(results)
+}
+
+/// "4 jobs: 12.5s for 41.0s of synthesis one at a time (3.3× faster)"
+fn speedup(workers: usize, wall: Duration, serial: Duration) -> String {
+    let times = serial.as_secs_f64() / wall.as_secs_f64().max(0.001);
+    format!("{} jobs: {:.1}s for {:.1}s of synthesis one at a time ({:.1}× faster)", workers, wall.as_secs_f64(), serial.as_secs_f64(), times)
+}
+
+/// `item` through the single-text path, tried again while the daemon is
+/// busy (a 429, waited out as it asks) or failing in passing (--retries).
+/// The waits are the worker's own; the others carry on.
+fn with_retries(args: &Args, item: &Item, start: Instant) -> Result<(SynthesisStats, Option<Checksum>)> {
+    let (mut throttled, mut retried) = (0, 0);
+    loop {
+        let error = match crate::speak(item.args.unwrap_or(args), item.text, item.output.as_deref(), item.policy, start) {
+            Ok(spoken) => return Ok(spoken),
+            Err(e) => e,
+        };
+        let wait = match crate::backoff(&error, throttled) {
+            Some(wait) if throttled < crate::MAX_THROTTLED => {
+                throttled += 1;
+                wait
+            }
+            None if crate::transient(&error) && retried < args.retries => {
+                retried += 1;
+                Duration::from_millis(250 * retried as u64)
+            }
+            _ => return Err(error),
+        };
+        if interrupt::requested() {
+            return Err(error);
+        }
+        tracing::info!(target: "sequence", "trying again in {}ms: {:#}", wait.as_millis(), error);
+        std::thread::sleep(wait);
+    }
+}
+
+/// Speakers: one sink, with --jobs items (one by default) queued behind
+/// the one playing, so the next syntheses overlap the current playback
+/// and items follow in order with only --gap-ms of silence between.
+fn play_gapless(args: &Args, items: &[String], start: Instant) -> Result<Vec<ItemResult>> {
+    interrupt::install();
+    let chimes = Chimes::load(args.chime_before.as_ref(), args.chime_after.as_ref())?;
+    let sink = Output::open()?;
+    if !chimes.before.is_empty() {
+        sink.samples(chimes.before);
+    }
+
+    let cache = crate::open_cache(args);
+    let gap = crate::gap(args, 0);
+    let mut results = Vec::new();
+    // Each item's buffer with its cache hit and text, to measure once it has played
+    let mut playing = Vec::new();
+    let mut started = false;
+    // Counted in the sink's queue: an item, and the gap before it if there is one
+    let ahead = args.jobs.unwrap_or(1) as usize * if gap.is_zero() { 1 } else { 2 };
+    // With --fail-fast, an item whose stream broke off stops the rest
+    let broken = |playing: &[(usize, Arc<LockFreeBuffer>, bool, &String)]| args.fail_fast && playing.iter().any(|(_, buffer, _, _)| buffer.error().is_some());
+    for (i, text) in items.iter().enumerate() {
+        while sink.len() > ahead && !interrupt::requested() && !broken(&playing) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        if interrupt::requested() || broken(&playing) {
+            break;
+        }
+
+        let buffer: Result<(Arc<LockFreeBuffer>, bool)> = crate::open_audio(args, cache.as_ref(), text).and_then(|(audio, _, hit)| {
+            Ok((crate::spawn_net_reader(audio, start, marks::printer(started), None, crate::buffer_limit(args))?, hit))
+        });
+        match buffer {
+            Ok((buffer, hit)) => {
+                // Only the first item's arrival and start are marked
+                let events = marks::printer(started);
+                if !started {
+                    crate::wait_for_prebuffer(&buffer);
+                    started = true;
+                } else if !gap.is_zero() {
+                    sink.silence(gap);
+                }
+                sink.speech(StreamSource::new(Arc::clone(&buffer)).watched(events, start));
+                playing.push((results.len(), buffer, hit, text));
+                results.push(ItemResult::new(i + 1, None, Duration::ZERO));
+            }
+            Err(e) => {
+                report_failure(i + 1, items.len(), &e);
+                results.push(ItemResult {
+                    error: Some(format!("{:#}", e)),
+                    kind: Some(Kind::of(&e)),
+                    ..ItemResult::new(i + 1, None, start.elapsed())
+                });
+                if args.fail_fast {
+                    break;
+                }
+            }
+        }
+    }
+    if !chimes.after.is_empty() && !interrupt::requested() && !broken(&playing) {
+        sink.samples(chimes.after);
+    }
+
+    while !sink.is_empty() && !interrupt::requested() && !broken(&playing) {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    crate::cut_off(&sink);
+    for (i, buffer, hit, text) in playing {
+        results[i].measured = crate::synthesis_stats(args, text, cache.as_ref(), hit, crate::measured(&buffer, start));
+        results[i].elapsed = buffer.drained().saturating_duration_since(start);
+        // As stream_audio fails a single text whose stream broke off
+        if let Some(error) = buffer.error() {
+            let error = anyhow::anyhow!(error);
+            report_failure(results[i].n, items.len(), &error);
+            results[i].error = Some(format!("{:#}", error));
+            results[i].kind = Some(Kind::of(&error));
+        }
+    }
+    if !interrupt::requested() {
+        reporter::timing(Mark::Done, start);
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn says_how_much_faster_jobs_were() {
+        let said = speedup(4, Duration::from_millis(12_500), Duration::from_secs(41));
+        assert_eq!(said, "4 jobs: 12.5s for 41.0s of synthesis one at a time (3.3× faster)");
+    }
+
+    #[test]
+    fn pads_item_numbers_to_the_total() {
+        assert_eq!(expand("part-{n}.wav", 3, 9), "part-3.wav");
+        assert_eq!(expand("part-{n}.wav", 3, 12), "part-03.wav");
+        assert_eq!(expand("{n}/{n}.wav", 7, 100), "007/007.wav");
+    }
+}
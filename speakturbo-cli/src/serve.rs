@@ -0,0 +1,195 @@
+//! One-shot HTTP listener that streams the synthesized audio to a browser.
+
+use crate::reporter::{self, Mark};
+use anyhow::{Context, Result};
+use speakturbo_client::AudioStream;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+const PAGE: &str = "<!doctype html>\n<html><head><meta charset=\"utf-8\"><title>speakturbo</title></head>\n\
+<body><audio src=\"/audio.wav\" controls autoplay></audio></body></html>\n";
+
+// Cap on request headers; we only need the request line
+const MAX_REQUEST_BYTES: usize = 8192;
+
+/// Serve `/` (a page with an <audio> tag) and `/audio.wav` on `addr`.
+/// `fetch` is called per audio request, so synthesis starts when a client
+/// connects. Returns after the first complete transfer unless `keep` is set.
+pub fn serve<F>(addr: &str, keep: bool, fetch: F) -> Result<()>
+where
+    F: Fn() -> Result<AudioStream>,
+{
+    let listener = TcpListener::bind(addr).with_context(|| format!("Cannot listen on {}", addr))?;
+    serve_on(listener, keep, fetch)
+}
+
+fn serve_on<F>(listener: TcpListener, keep: bool, fetch: F) -> Result<()>
+where
+    F: Fn() -> Result<AudioStream>,
+{
+    reporter::info(format_args!("Serving on http://{}/", listener.local_addr()?));
+
+    // Poll so Ctrl+C is noticed between connections
+    listener.set_nonblocking(true)?;
+    while !crate::interrupt::requested() {
+        let mut stream = match listener.accept() {
+            Ok((s, _)) => s,
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(50));
+                continue;
+            }
+            Err(e) => {
+                reporter::warning(format_args!("accept failed: {}", e));
+                continue;
+            }
+        };
+        stream.set_nonblocking(false)?;
+        let peer = stream
+            .peer_addr()
+            .map(|a| a.to_string())
+            .unwrap_or_else(|_| "client".into());
+
+        let path = match read_request_path(&mut stream) {
+            Some(p) => p,
+            None => continue,
+        };
+
+        match path.as_str() {
+            "/" | "/index.html" => {
+                let _ = respond(&mut stream, "200 OK", "text/html; charset=utf-8", PAGE.as_bytes());
+            }
+            "/audio.wav" => {
+                reporter::info(format_args!("{} {} connected", reporter::mark(Mark::Arrow), peer));
+                match stream_audio(&mut stream, &fetch) {
+                    Ok(bytes) => {
+                        reporter::info(format_args!("{} sent {} bytes to {}", reporter::mark(Mark::Done), bytes, peer));
+                        if !keep {
+                            return Ok(());
+                        }
+                    }
+                    Err(e) => reporter::warning(format_args!("transfer to {} aborted: {:#}", peer, e)),
+                }
+            }
+            _ => {
+                let _ = respond(&mut stream, "404 Not Found", "text/plain", b"not found\n");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Read the request head and return the path of a GET, answering anything else.
+fn read_request_path(stream: &mut TcpStream) -> Option<String> {
+    stream.set_read_timeout(Some(Duration::from_secs(5))).ok()?;
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf).ok()?;
+        if n == 0 || head.len() + n > MAX_REQUEST_BYTES {
+            return None;
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+
+    let head = String::from_utf8_lossy(&head);
+    let mut parts = head.lines().next()?.split_whitespace();
+    let method = parts.next()?;
+    let target = parts.next()?;
+    if method != "GET" && method != "HEAD" {
+        let _ = respond(stream, "405 Method Not Allowed", "text/plain", b"GET only\n");
+        return None;
+    }
+    Some(target.split('?').next().unwrap_or("/").to_string())
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)
+}
+
+/// Forward the daemon's WAV stream chunk by chunk; returns bytes sent.
+fn stream_audio<F>(stream: &mut TcpStream, fetch: &F) -> Result<u64>
+where
+    F: Fn() -> Result<AudioStream>,
+{
+    let mut reader = match fetch() {
+        Ok(audio) => audio,
+        Err(e) => {
+            let msg = format!("{:#}\n", e);
+            let _ = respond(stream, "502 Bad Gateway", "text/plain", msg.as_bytes());
+            return Err(e);
+        }
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: audio/wav\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n"
+    )
+    .context("client disconnected")?;
+
+    // Dropping the daemon reader on a failed write aborts the synthesis download
+    let mut buf = [0u8; 4096];
+    let mut sent = 0u64;
+    loop {
+        if crate::interrupt::requested() {
+            anyhow::bail!("interrupted");
+        }
+        let n = reader.read(&mut buf).context("daemon stream failed")?;
+        if n == 0 {
+            break;
+        }
+        stream.write_all(&buf[..n]).context("client disconnected")?;
+        stream.flush().context("client disconnected")?;
+        sent += n as u64;
+    }
+    Ok(sent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use speakturbo_client::mock_daemon::MockDaemon;
+    use speakturbo_client::{SpeakClient, SpeakRequest};
+
+    /// The status line and body of a GET of `path`
+    fn get(addr: std::net::SocketAddr, path: &str) -> (String, Vec<u8>) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        let end = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let status = String::from_utf8_lossy(&response[..end]).lines().next().unwrap().to_string();
+        (status, response[end + 4..].to_vec())
+    }
+
+    #[test]
+    fn serves_the_page_then_the_daemons_audio() {
+        let daemon = MockDaemon::start().unwrap();
+        let client = SpeakClient::new(&daemon.url());
+        let request = SpeakRequest::new("Served to a browser.").build().unwrap();
+        let mut expected = Vec::new();
+        client.synthesize(&request).unwrap().read_to_end(&mut expected).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || serve_on(listener, false, || Ok(client.synthesize(&request)?)));
+
+        let (status, page) = get(addr, "/");
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert!(String::from_utf8_lossy(&page).contains("<audio src=\"/audio.wav\""));
+        assert_eq!(get(addr, "/missing").0, "HTTP/1.1 404 Not Found");
+
+        let (status, audio) = get(addr, "/audio.wav?t=1");
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(audio, expected);
+        // Without --serve-keep, done after one transfer
+        server.join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,393 @@
+//! Defaults for flags, from the environment or the config file. Every
+//! long flag is a setting of the same name: `max-buffer-ms = 5000` in the
+//! config, or SPEAKTURBO_MAX_BUFFER_MS=5000 in the environment. The command
+//! line wins over the environment, the environment over the config, and
+//! the config over the built-in default. A profile picked with --profile
+//! or SPEAKTURBO_PROFILE replaces the config's settings with its own.
+//!
+//! Flags without a value are set with 1, true, yes or on, and cleared with
+//! 0, false, no or off, in any case. An empty variable counts as unset.
+//! A default that doesn't fit a run, because it conflicts with a flag
+//! given there or needs one that isn't, is left out of that run.
+
+use crate::exit::{self, Kind};
+use crate::{config, Args};
+use anyhow::Result;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgAction, CommandFactory, FromArgMatches, Subcommand};
+use serde_json::json;
+use std::ffi::OsString;
+use std::path::Path;
+
+#[derive(Subcommand)]
+pub enum Action {
+    /// Print each setting in effect and where it came from: the command
+    /// line, the environment, the config file or the built-in default
+    Show,
+}
+
+/// Flags that say where settings come from, rather than being settings
+const NOT_SETTINGS: &[&str] = &["help", "version", "config", "profile"];
+
+pub struct Setting {
+    /// The flag's long name, which is also the config key
+    pub key: String,
+    /// clap's id for it
+    id: String,
+    /// Given as true or false, for a flag that takes no value
+    switch: bool,
+    /// Given as a number, for a flag that counts its repeats (-vv)
+    count: bool,
+}
+
+/// Every long flag of the top-level command.
+pub fn settings() -> Vec<Setting> {
+    Args::command()
+        .get_arguments()
+        .filter_map(|arg| {
+            let key = arg.get_long().filter(|key| !NOT_SETTINGS.contains(key))?;
+            let switch = matches!(arg.get_action(), ArgAction::SetTrue);
+            let count = matches!(arg.get_action(), ArgAction::Count);
+            Some(Setting { key: key.to_string(), id: arg.get_id().to_string(), switch, count })
+        })
+        .collect()
+}
+
+pub fn is_setting(key: &str) -> bool {
+    settings().iter().any(|s| s.key == key)
+}
+
+/// Where a setting's value came from
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    CommandLine,
+    /// The variable's name
+    Env(String),
+    /// "path:line" in the config
+    File(String),
+    Default,
+}
+
+impl Source {
+    fn describe(&self) -> &str {
+        match self {
+            Source::CommandLine => "command line",
+            Source::Env(name) | Source::File(name) => name,
+            Source::Default => "default",
+        }
+    }
+}
+
+/// The settings in effect
+pub struct Resolved {
+    pub args: Args,
+    /// (setting, value as the command line would give it, source); flags
+    /// with no value are "(none)"
+    pub values: Vec<(String, String, Source)>,
+}
+
+/// SPEAKTURBO_MAX_BUFFER_MS for max-buffer-ms
+pub fn env_name(key: &str) -> String {
+    format!("SPEAKTURBO_{}", key.to_uppercase().replace('-', "_"))
+}
+
+/// The profile to use and where it was named: --profile, else
+/// SPEAKTURBO_PROFILE from `env`.
+pub fn profile(args: &Args, env: impl Fn(&str) -> Option<String>) -> Option<(String, Source)> {
+    match &args.profile {
+        Some(name) => Some((name.clone(), Source::CommandLine)),
+        None => env("SPEAKTURBO_PROFILE").filter(|v| !v.is_empty()).map(|name| (name, Source::Env("SPEAKTURBO_PROFILE".to_string()))),
+    }
+}
+
+/// The command line `argv` with each setting it doesn't give taken from
+/// `env`, else from the config's `flags`, in one parse so that clap checks
+/// the values as it would on the command line.
+pub fn resolve(argv: &[OsString], flags: &[config::Flag], env: impl Fn(&str) -> Option<String>) -> Result<Resolved> {
+    let command = Args::command();
+    let given = command.clone().try_get_matches_from(argv)?;
+    // Defaults go before the command line's own flags and any subcommand
+    let with = |extra: &[String]| -> Vec<OsString> {
+        let (program, rest) = argv.split_first().map_or((OsString::from("speakturbo"), &[][..]), |(p, r)| (p.clone(), r));
+        std::iter::once(program).chain(extra.iter().map(OsString::from)).chain(rest.iter().cloned()).collect()
+    };
+
+    let mut extra = Vec::new();
+    let mut sources = Vec::new();
+    let settings = settings();
+    for setting in &settings {
+        if given.value_source(&setting.id) == Some(ValueSource::CommandLine) {
+            sources.push(Source::CommandLine);
+            continue;
+        }
+        let name = env_name(&setting.key);
+        let found = match env(&name).filter(|v| !v.is_empty()) {
+            Some(value) => Some((value, Source::Env(name))),
+            None => flags.iter().find(|f| f.key == setting.key).map(|f| (f.value.clone(), Source::File(f.at.clone()))),
+        };
+        let Some((value, source)) = found else {
+            sources.push(Source::Default);
+            continue;
+        };
+        let wrong = |e: String| exit::fail(Kind::Usage, format!("{}: {}: {}", source.describe(), setting.key, e));
+        let repeats = match (setting.switch, setting.count) {
+            (true, _) => switch(&value).map_err(wrong)? as usize,
+            (_, true) => count(&value).map_err(wrong)?,
+            _ => 0,
+        };
+        let flags = match setting.switch || setting.count {
+            true => vec![format!("--{}", setting.key); repeats],
+            false => vec![format!("--{}={}", setting.key, value)],
+        };
+        if flags.is_empty() {
+            // A switch turned off, as it is by default
+            sources.push(source);
+            continue;
+        }
+        let before = extra.len();
+        extra.extend(flags);
+        match command.clone().try_get_matches_from(with(&extra)) {
+            Ok(_) => sources.push(source),
+            Err(e) if matches!(e.kind(), ErrorKind::ArgumentConflict | ErrorKind::MissingRequiredArgument) => {
+                extra.truncate(before);
+                sources.push(Source::Default);
+            }
+            Err(e) => return Err(wrong(first_line(&e))),
+        }
+    }
+
+    let matches = command.try_get_matches_from(with(&extra))?;
+    let args = Args::from_arg_matches(&matches)?;
+    let values = settings
+        .into_iter()
+        .zip(sources)
+        .map(|(setting, source)| {
+            let value = match matches.get_raw(&setting.id) {
+                _ if setting.count => matches.get_count(&setting.id).to_string(),
+                Some(values) => values.map(|v| v.to_string_lossy()).collect::<Vec<_>>().join(","),
+                None => "(none)".to_string(),
+            };
+            (setting.key, value, source)
+        })
+        .collect();
+    Ok(Resolved { args, values })
+}
+
+/// "invalid value 'x' for '--jobs <JOBS>': ..." from clap's full message.
+fn first_line(e: &clap::Error) -> String {
+    let message = e.to_string();
+    message.lines().next().unwrap_or_default().trim_start_matches("error: ").to_string()
+}
+
+/// How many times to repeat a counted flag: a number, or a switch's word
+/// for once or not at all.
+fn count(value: &str) -> Result<usize, String> {
+    value.parse().or_else(|_| switch(value).map(usize::from)).map_err(|_| format!("expected a number, not {:?}", value))
+}
+
+fn switch(value: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "true" | "1" | "yes" | "on" => Ok(true),
+        "false" | "0" | "no" | "off" => Ok(false),
+        _ => Err(format!("expected 1, true, yes or on, or 0, false, no or off, not {:?}", value)),
+    }
+}
+
+/// `speakturbo config show`
+pub fn show(values: &[(String, String, Source)], config: Option<&Path>, profile: Option<&(String, Source)>, as_json: bool) -> Result<()> {
+    if as_json {
+        let settings: serde_json::Map<_, _> = values
+            .iter()
+            .map(|(key, value, source)| (key.clone(), json!({"value": value, "source": source.describe()})))
+            .collect();
+        let config = config.map(|p| p.display().to_string());
+        let profile = profile.map(|(name, _)| name);
+        println!("{}", json!({"config": config, "profile": profile, "settings": settings}));
+        return Ok(());
+    }
+    match config {
+        Some(path) => println!("Config: {}", path.display()),
+        None => match config::default_path() {
+            Some(path) => println!("Config: none ({} doesn't exist)", path.display()),
+            None => println!("Config: none"),
+        },
+    }
+    if let Some((name, source)) = profile {
+        println!("Profile: {} ({})", name, source.describe());
+    }
+    for (key, value, source) in values {
+        let source = match source {
+            Source::Env(name) => format!("env {}", name),
+            other => other.describe().to_string(),
+        };
+        println!("  {:<22} {:<26} {}", key, value, source);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn flag(key: &str, value: &str, line: usize) -> config::Flag {
+        config::Flag { key: key.to_string(), value: value.to_string(), at: format!("config.toml:{}", line) }
+    }
+
+    /// The settings for `argv` under `env` and the config's `flags`
+    fn resolved(argv: &[&str], env: &[(&str, &str)], flags: &[config::Flag]) -> Result<Resolved> {
+        let argv: Vec<OsString> = ["speakturbo"].iter().chain(argv).map(OsString::from).collect();
+        let lookup = |name: &str| env.iter().find(|(n, _)| *n == name).map(|(_, v)| v.to_string());
+        resolve(&argv, flags, lookup)
+    }
+
+    /// A setting's value and source
+    fn shown(resolved: &Resolved, key: &str) -> (String, Source) {
+        let (_, value, source) = resolved.values.iter().find(|(k, _, _)| k == key).unwrap();
+        (value.clone(), source.clone())
+    }
+
+    #[test]
+    fn makes_every_flag_a_setting() {
+        let keys: Vec<String> = settings().into_iter().map(|s| s.key).collect();
+        for key in ["voice", "max-buffer-ms", "no-cache", "quiet", "json", "dry-run", "voice-pool"] {
+            assert!(keys.iter().any(|k| k == key), "{}", key);
+        }
+        for key in NOT_SETTINGS {
+            assert!(!keys.iter().any(|k| k == key), "{}", key);
+        }
+        assert_eq!(env_name("max-buffer-ms"), "SPEAKTURBO_MAX_BUFFER_MS");
+        assert_eq!(env_name("voice"), "SPEAKTURBO_VOICE");
+    }
+
+    #[test]
+    fn layers_each_setting() {
+        // (key, command line, environment, config, default): each layer
+        // wins over the ones after it
+        let cases = [
+            ("voice", ["--voice=jean"], "marius", "javert", "alba"),
+            ("fuzzy-voice", ["--fuzzy-voice"], "true", "true", "false"),
+            ("daemon-url", ["--daemon-url=http://a:1"], "http://b:2", "http://c:3", "http://127.0.0.1:7125"),
+            ("max-buffer-ms", ["--max-buffer-ms=2000"], "3000", "4000", "30000"),
+            ("chunk-chars", ["--chunk-chars=100"], "200", "300", "400"),
+            ("crossfade-ms", ["--crossfade-ms=10"], "20", "30", "0"),
+            ("max-chars", ["--max-chars=10"], "20", "30", "100000"),
+            ("jobs", ["--jobs=3"], "4", "5", "(none)"),
+            ("gap-ms", ["--gap-ms=10"], "20", "30", "(none)"),
+            ("emoji", ["--emoji=keep"], "speak", "strip", "(none)"),
+            ("normalize-numbers", ["--normalize-numbers"], "true", "true", "false"),
+            ("no-cache", ["--no-cache"], "true", "true", "false"),
+            ("cache-max-mb", ["--cache-max-mb=10"], "20", "30", "256"),
+            ("no-emoji", ["--no-emoji"], "true", "true", "false"),
+            ("voice-pool", ["--voice-pool=alba,jean"], "marius", "javert,jean", "(none)"),
+            ("chars-per-second", ["--chars-per-second=12.5"], "14", "16", "15"),
+        ];
+        for (key, cli, env, file, default) in cases {
+            let name = env_name(key);
+            let env = [(name.as_str(), env)];
+            let flags = [flag(key, file, 3)];
+            let cli_value = cli[0].split_once('=').map_or("true", |(_, v)| v);
+            let layers = [
+                (&cli[..], &env[..], &flags[..], cli_value, Source::CommandLine),
+                (&[], &env, &flags, env[0].1, Source::Env(name.clone())),
+                (&[], &[], &flags, file, Source::File("config.toml:3".to_string())),
+                (&[], &[], &[], default, Source::Default),
+            ];
+            for (argv, env, flags, value, source) in layers {
+                let resolved = resolved(argv, env, flags).unwrap();
+                assert_eq!(shown(&resolved, key), (value.to_string(), source), "{} {:?}", key, argv);
+            }
+        }
+    }
+
+    #[test]
+    fn checks_values_like_the_command_line() {
+        let cases = [
+            (
+                &[("SPEAKTURBO_MAX_BUFFER_MS", "lots")][..],
+                vec![],
+                "SPEAKTURBO_MAX_BUFFER_MS: max-buffer-ms: invalid value 'lots' for '--max-buffer-ms <MS>': invalid digit found in string",
+            ),
+            (&[], vec![flag("jobs", "99", 2)], "config.toml:2: jobs: invalid value '99' for '--jobs <JOBS>': 99 is not in 1..=32"),
+            (
+                &[("SPEAKTURBO_NO_EMOJI", "maybe")],
+                vec![],
+                "SPEAKTURBO_NO_EMOJI: no-emoji: expected 1, true, yes or on, or 0, false, no or off, not \"maybe\"",
+            ),
+            (&[("SPEAKTURBO_QUIET", "maybe")], vec![], "SPEAKTURBO_QUIET: quiet: expected a number, not \"maybe\""),
+            (&[], vec![flag("emoji", "loud", 1)], "config.toml:1: emoji: invalid value 'loud' for '--emoji <MODE>'"),
+        ];
+        for (env, flags, expected) in cases {
+            let message = resolved(&[], env, &flags).err().map(|e| e.to_string()).unwrap_or_default();
+            assert!(message.starts_with(expected), "{:?}", message);
+        }
+        // Switches take the usual spellings; an empty variable is unset
+        let args = |env: &[(&str, &str)], flags: &[config::Flag]| resolved(&[], env, flags).unwrap().args;
+        for (value, expected) in [("1", true), ("YES", true), ("on", true), ("0", false), ("Off", false), ("no", false)] {
+            assert_eq!(args(&[("SPEAKTURBO_NO_EMOJI", value)], &[]).no_emoji, expected, "{:?}", value);
+        }
+        assert_eq!(args(&[("SPEAKTURBO_VOICE", "")], &[]).voice, "alba");
+        assert!(!args(&[("SPEAKTURBO_NO_CACHE", "0")], &[flag("no-cache", "true", 1)]).no_cache);
+        // Counted flags take a number, or a switch's words for 1 and 0
+        for (value, expected) in [("2", 2), ("on", 1), ("0", 0)] {
+            assert_eq!(args(&[("SPEAKTURBO_VERBOSE", value)], &[]).verbose, expected, "{:?}", value);
+        }
+        assert_eq!(args(&[], &[flag("verbose", "3", 1)]).verbose, 3);
+        // quiet = true in a profile, as before -qq
+        assert_eq!(args(&[], &[flag("quiet", "true", 1)]).quiet, 1);
+        assert_eq!(args(&[("SPEAKTURBO_QUIET", "2")], &[]).quiet, 2);
+    }
+
+    #[test]
+    fn leaves_out_defaults_that_dont_fit() {
+        // --verbose conflicts with quiet, and --lang with voice; glob needs
+        // --batch-dir, which this run doesn't have
+        let flags = [flag("quiet", "true", 1), flag("voice", "jean", 2), flag("glob", "*.md", 3), flag("gap-ms", "5", 4)];
+        let run = resolved(&["--verbose", "--lang=fr", "hello"], &[], &flags).unwrap();
+        assert!(run.args.quiet == 0 && run.args.verbose == 1);
+        assert_eq!(run.args.voice, "alba");
+        assert_eq!(run.args.glob, "*.txt");
+        assert_eq!(run.args.gap_ms, Some(5));
+        assert_eq!(run.args.text, ["hello"]);
+        assert_eq!(shown(&run, "quiet"), ("0".to_string(), Source::Default));
+        let batch = resolved(&["--batch-dir=in", "-o", "out"], &[], &flags).unwrap();
+        assert_eq!(batch.args.glob, "*.md");
+    }
+
+    #[test]
+    fn picks_the_profile() {
+        let env = |name: &str| (name == "SPEAKTURBO_PROFILE").then(|| "alerts".to_string());
+        let args = Args::try_parse_from(["speakturbo", "--profile", "narration"]).unwrap();
+        assert_eq!(profile(&args, env), Some(("narration".to_string(), Source::CommandLine)));
+        let args = Args::try_parse_from(["speakturbo", "config", "show", "--profile", "narration"]).unwrap();
+        assert_eq!(profile(&args, env), Some(("narration".to_string(), Source::CommandLine)));
+        let args = Args::try_parse_from(["speakturbo"]).unwrap();
+        assert_eq!(profile(&args, env), Some(("alerts".to_string(), Source::Env("SPEAKTURBO_PROFILE".to_string()))));
+        assert_eq!(profile(&args, |_| None), None);
+    }
+
+    #[test]
+    fn reads_the_process_environment() {
+        // Names no other test sets, through std::env as main reads them
+        std::env::set_var("SPEAKTURBO_CROSSFADE_MS", "25");
+        std::env::set_var("SPEAKTURBO_NORMALIZE_NUMBERS", "yes");
+        std::env::set_var("SPEAKTURBO_DIALOGUE_GAP_MS", "");
+        let argv: Vec<OsString> = ["speakturbo", "--chunk-chars", "120", "config", "show"].iter().map(OsString::from).collect();
+        let flags = [flag("crossfade-ms", "50", 1), flag("dialogue-gap-ms", "150", 2)];
+        let resolved = resolve(&argv, &flags, |name| std::env::var(name).ok()).unwrap();
+        std::env::remove_var("SPEAKTURBO_CROSSFADE_MS");
+        std::env::remove_var("SPEAKTURBO_NORMALIZE_NUMBERS");
+        std::env::remove_var("SPEAKTURBO_DIALOGUE_GAP_MS");
+
+        assert_eq!(resolved.args.crossfade_ms, 25);
+        assert!(resolved.args.normalize_numbers);
+        assert_eq!(resolved.args.dialogue_gap_ms, 150);
+        assert_eq!(resolved.args.chunk_chars, 120);
+        assert!(matches!(resolved.args.command, Some(crate::Command::Config { .. })));
+        let crossfade = Source::Env("SPEAKTURBO_CROSSFADE_MS".to_string());
+        assert_eq!(shown(&resolved, "crossfade-ms"), ("25".to_string(), crossfade));
+        assert_eq!(shown(&resolved, "dialogue-gap-ms"), ("150".to_string(), Source::File("config.toml:2".to_string())));
+        assert_eq!(shown(&resolved, "chunk-chars"), ("120".to_string(), Source::CommandLine));
+    }
+}
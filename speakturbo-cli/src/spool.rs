@@ -0,0 +1,59 @@
+//! Copy a stream to a temp file as it is read and publish it only once the
+//! stream completes, as the replay history does.
+
+use anyhow::Result;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+type Commit = Box<dyn FnOnce(&Path) -> Result<()> + Send>;
+
+/// Reader that spools everything it reads into `tmp`, then hands the file
+/// to `commit` at EOF. Spool failures only disable storing, never the read.
+pub struct Spool<R> {
+    inner: R,
+    file: Option<File>,
+    tmp: PathBuf,
+    commit: Option<Commit>,
+}
+
+impl<R> Spool<R> {
+    pub fn new(inner: R, tmp: PathBuf, commit: impl FnOnce(&Path) -> Result<()> + Send + 'static) -> Self {
+        let file = tmp
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| File::create(&tmp))
+            .ok();
+        Self { inner, file, tmp, commit: Some(Box::new(commit)) }
+    }
+}
+
+impl<R: Read> Read for Spool<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n == 0 {
+            if let Some(file) = self.file.take() {
+                drop(file);
+                let committed = self.commit.take().is_some_and(|commit| commit(&self.tmp).is_ok());
+                if !committed {
+                    let _ = fs::remove_file(&self.tmp);
+                }
+            }
+        } else if let Some(file) = &mut self.file {
+            if file.write_all(&buf[..n]).is_err() {
+                self.file = None;
+                let _ = fs::remove_file(&self.tmp);
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl<R> Drop for Spool<R> {
+    fn drop(&mut self) {
+        // Stream never finished: discard the partial copy
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.tmp);
+        }
+    }
+}
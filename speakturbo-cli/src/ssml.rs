@@ -0,0 +1,332 @@
+//! `--ssml`: markup sent to the daemon as it is. Checked first for
+//! well-formed XML rooted at `<speak>`, so a typo fails here with a line
+//! and column instead of being read out.
+
+use anyhow::{bail, Result};
+use std::fmt::Display;
+
+/// Ok if `text` is a well-formed XML document whose root is `<speak>`.
+pub fn validate(text: &str) -> Result<()> {
+    let mut p = Parser { s: text.strip_prefix('\u{feff}').unwrap_or(text), pos: 0 };
+    p.misc()?;
+    if p.rest().starts_with("<!DOCTYPE") {
+        p.doctype()?;
+        p.misc()?;
+    }
+    if !p.rest().starts_with('<') {
+        return p.fail("expected the <speak> root element");
+    }
+
+    let root_at = p.pos;
+    let (root, empty) = p.start_tag()?;
+    if root.rsplit(':').next() != Some("speak") {
+        p.pos = root_at;
+        return p.fail(format!("the root element is <{}>, not <speak>", root));
+    }
+    let mut open = if empty { vec![] } else { vec![root] };
+    while let Some(&current) = open.last() {
+        let rest = p.rest();
+        if rest.is_empty() {
+            return p.fail(format!("<{}> is never closed", current));
+        } else if rest.starts_with("</") {
+            p.pos += 2;
+            let name_at = p.pos;
+            let name = p.name()?;
+            p.skip_whitespace();
+            p.expect(">")?;
+            if name != current {
+                p.pos = name_at;
+                return p.fail(format!("</{}> does not close <{}>", name, current));
+            }
+            open.pop();
+        } else if rest.starts_with("<!--") {
+            p.comment()?;
+        } else if rest.starts_with("<![CDATA[") {
+            p.skip_past("]]>", "unterminated CDATA section")?;
+        } else if rest.starts_with("<?") {
+            p.skip_past("?>", "unterminated processing instruction")?;
+        } else if rest.starts_with('<') {
+            let (name, empty) = p.start_tag()?;
+            if !empty {
+                open.push(name);
+            }
+        } else if rest.starts_with('&') {
+            p.reference()?;
+        } else {
+            p.pos += rest.find(['<', '&']).unwrap_or(rest.len());
+        }
+    }
+    p.misc()?;
+    if !p.rest().is_empty() {
+        return p.fail("text after the closing </speak>");
+    }
+    Ok(())
+}
+
+struct Parser<'a> {
+    s: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.s[self.pos..]
+    }
+
+    fn fail<T>(&self, message: impl Display) -> Result<T> {
+        let before = &self.s[..self.pos];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+        bail!("SSML is not well-formed (line {}, column {}): {}", line, column, message)
+    }
+
+    fn expect(&mut self, literal: &str) -> Result<()> {
+        match self.rest().starts_with(literal) {
+            true => {
+                self.pos += literal.len();
+                Ok(())
+            }
+            false => self.fail(format!("expected {:?}", literal)),
+        }
+    }
+
+    fn skip_whitespace(&mut self) -> bool {
+        let rest = self.rest();
+        let skipped = rest.len() - rest.trim_start_matches([' ', '\t', '\r', '\n']).len();
+        self.pos += skipped;
+        skipped > 0
+    }
+
+    fn skip_past(&mut self, end: &str, unterminated: &str) -> Result<()> {
+        match self.rest().find(end) {
+            Some(at) => {
+                self.pos += at + end.len();
+                Ok(())
+            }
+            None => self.fail(unterminated),
+        }
+    }
+
+    /// Whitespace, comments and processing instructions (the XML
+    /// declaration among them) around the root element.
+    fn misc(&mut self) -> Result<()> {
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("<!--") {
+                self.comment()?;
+            } else if self.rest().starts_with("<?") {
+                self.skip_past("?>", "unterminated processing instruction")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn comment(&mut self) -> Result<()> {
+        self.pos += 4;
+        self.skip_past("-->", "unterminated comment")
+    }
+
+    /// A DOCTYPE, with any internal subset in brackets.
+    fn doctype(&mut self) -> Result<()> {
+        let mut depth = 0;
+        for (i, c) in self.rest().char_indices() {
+            match c {
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                '>' if depth == 0 => {
+                    self.pos += i + 1;
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+        self.fail("unterminated DOCTYPE")
+    }
+
+    fn name(&mut self) -> Result<&'a str> {
+        let rest = self.rest();
+        let starts = rest.starts_with(|c: char| c.is_alphabetic() || c == '_' || c == ':');
+        let len = rest.find(|c: char| !(c.is_alphanumeric() || "_:.-".contains(c))).unwrap_or(rest.len());
+        if !starts || len == 0 {
+            return self.fail("expected a name");
+        }
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    /// `<name attr="value" ...>` or `.../>`: the name, and whether the
+    /// element is empty.
+    fn start_tag(&mut self) -> Result<(&'a str, bool)> {
+        self.pos += 1;
+        let name = self.name()?;
+        let mut attributes = Vec::new();
+        loop {
+            let spaced = self.skip_whitespace();
+            if self.rest().starts_with("/>") {
+                self.pos += 2;
+                return Ok((name, true));
+            }
+            if self.rest().starts_with('>') {
+                self.pos += 1;
+                return Ok((name, false));
+            }
+            if self.rest().is_empty() {
+                return self.fail(format!("<{}> is not closed with '>'", name));
+            }
+            if !spaced {
+                return self.fail("expected whitespace, '>' or '/>'");
+            }
+            let attribute_at = self.pos;
+            let attribute = self.name()?;
+            if attributes.contains(&attribute) {
+                self.pos = attribute_at;
+                return self.fail(format!("attribute {:?} is repeated", attribute));
+            }
+            attributes.push(attribute);
+            self.skip_whitespace();
+            self.expect("=")?;
+            self.skip_whitespace();
+            let Some(quote) = self.rest().chars().next().filter(|c| *c == '"' || *c == '\'') else {
+                return self.fail(format!("the value of {:?} must be quoted", attribute));
+            };
+            self.pos += 1;
+            loop {
+                match self.rest().chars().next() {
+                    None => return self.fail(format!("the value of {:?} is never closed", attribute)),
+                    Some(c) if c == quote => break,
+                    Some('<') => return self.fail("'<' in an attribute value"),
+                    Some('&') => self.reference()?,
+                    Some(c) => self.pos += c.len_utf8(),
+                }
+            }
+            self.pos += 1;
+        }
+    }
+
+    /// `&amp;` and the other predefined entities, or a character reference.
+    fn reference(&mut self) -> Result<()> {
+        let rest = self.rest();
+        let end = rest.find(';').filter(|&end| end <= 12 && !rest[1..end].contains(char::is_whitespace));
+        let Some(end) = end else {
+            return self.fail("'&' must be written as &amp;");
+        };
+        let name = &rest[1..end];
+        let valid = match name.strip_prefix('#') {
+            Some(hex) if hex.starts_with('x') => u32::from_str_radix(&hex[1..], 16).ok().and_then(char::from_u32).is_some(),
+            Some(decimal) => decimal.parse().ok().and_then(char::from_u32).is_some(),
+            None => matches!(name, "amp" | "lt" | "gt" | "quot" | "apos"),
+        };
+        if !valid {
+            return self.fail(format!("unknown entity &{};", name));
+        }
+        self.pos += end + 1;
+        Ok(())
+    }
+}
+
+/// `text` escaped for XML character data and attribute values.
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Well-formed SSML `text` with `f` applied to its runs of character data,
+/// but not to references, nor inside `<phoneme>` or `<sub>`, whose words
+/// already say how they're spoken.
+pub fn map_text(text: &str, f: &mut dyn FnMut(&str) -> String) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut inside = 0usize;
+    while !rest.is_empty() {
+        let len = if rest.starts_with('<') {
+            let end = [("<!--", "-->"), ("<![CDATA[", "]]>"), ("<?", "?>")]
+                .iter()
+                .find(|(open, _)| rest.starts_with(open))
+                .and_then(|(_, close)| rest.find(close).map(|at| at + close.len()));
+            match end {
+                Some(end) => end,
+                None => {
+                    let end = tag_end(rest);
+                    let tag = &rest[..end];
+                    let name = tag.trim_start_matches(['<', '/']).split([' ', '\t', '\n', '\r', '/', '>']).next().unwrap_or("");
+                    if matches!(name.rsplit(':').next(), Some("phoneme" | "sub")) && !tag.ends_with("/>") {
+                        inside = if tag.starts_with("</") { inside.saturating_sub(1) } else { inside + 1 };
+                    }
+                    end
+                }
+            }
+        } else if rest.starts_with('&') {
+            rest.find(';').map_or(rest.len(), |at| at + 1)
+        } else {
+            let end = rest.find(['<', '&']).unwrap_or(rest.len());
+            if inside == 0 {
+                out.push_str(&f(&rest[..end]));
+                rest = &rest[end..];
+                continue;
+            }
+            end
+        };
+        out.push_str(&rest[..len]);
+        rest = &rest[len..];
+    }
+    out
+}
+
+/// The length of the tag starting `text`, quotes in attributes and all.
+fn tag_end(text: &str) -> usize {
+    let mut quote = None;
+    for (at, c) in text.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '>') => return at + 1,
+            _ => {}
+        }
+    }
+    text.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_well_formed_ssml() {
+        let ssml = r#"<?xml version="1.0"?>
+<!-- greeting -->
+<speak version="1.1" xmlns="http://www.w3.org/2001/10/synthesis">
+  Hello <break time="500ms"/> <emphasis level='strong'>world</emphasis> &amp; &#x2019;
+  <![CDATA[a < b]]>
+</speak>
+"#;
+        validate(ssml).unwrap();
+        validate("<speak/>").unwrap();
+        validate("<ssml:speak>hi</ssml:speak>").unwrap();
+    }
+
+    #[test]
+    fn points_at_the_problem() {
+        let error = |s: &str| validate(s).unwrap_err().to_string();
+        assert_eq!(
+            error("<speak>\n  <break time='1s'>\n</speak>"),
+            "SSML is not well-formed (line 3, column 3): </speak> does not close <break>"
+        );
+        assert_eq!(error("<speak>Salt & pepper</speak>"), "SSML is not well-formed (line 1, column 13): '&' must be written as &amp;");
+        assert_eq!(error("<voice>hi</voice>"), "SSML is not well-formed (line 1, column 1): the root element is <voice>, not <speak>");
+        assert_eq!(error("just text"), "SSML is not well-formed (line 1, column 1): expected the <speak> root element");
+        assert_eq!(error("<speak>hi"), "SSML is not well-formed (line 1, column 10): <speak> is never closed");
+        assert_eq!(error("<speak a=1/>"), "SSML is not well-formed (line 1, column 10): the value of \"a\" must be quoted");
+        assert_eq!(error("<speak/>\n<speak/>"), "SSML is not well-formed (line 2, column 1): text after the closing </speak>");
+    }
+}
@@ -0,0 +1,359 @@
+//! `--stream-lines`, `--stream-null`, `--follow` and `--watch-clipboard`:
+//! speak input as it arrives instead of waiting for EOF. Each record is synthesized as soon
+//! as it's read and queued behind the one playing, --gap-ms of silence
+//! between, with at most `--max-in-flight` queued. --debounce-ms and
+//! --max-per-minute pace the records first (see [`crate::burst`]), and
+//! --dedupe skips repeats (see [`crate::dedupe`]).
+
+use crate::dedupe::{Dedupe, Repeated, Verdict};
+use crate::playback::Output;
+use crate::reporter::{self, Mark};
+use crate::{ansi, burst, interrupt, text, Args, Events, LockFreeBuffer, StreamSource};
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub struct Record {
+    /// 1-based position in the input, counting blank and rejected records
+    pub n: usize,
+    pub text: Result<String>,
+}
+
+/// Non-blank records of `input`, split on `delimiter` and delivered as
+/// they are read. The channel holds one record, so a slow queue backs up
+/// into the producer's pipe. Records over `max_bytes` come through as
+/// errors, without ever being held in memory whole, and so does a read
+/// error, which ends the input. With `strip_ansi`, terminal escape
+/// sequences are removed, even ones split between records.
+pub fn records<R: BufRead + Send + 'static>(
+    mut input: R,
+    delimiter: u8,
+    max_bytes: usize,
+    strip_ansi: bool,
+    force_text: bool,
+) -> Receiver<Record> {
+    let (tx, rx) = mpsc::sync_channel(1);
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        let mut n = 0;
+        let mut ansi = strip_ansi.then(ansi::Stripper::default);
+        // A missing final delimiter still ends the last record
+        loop {
+            let len = match read_record(&mut input, delimiter, max_bytes, &mut buf) {
+                Ok(Some(len)) => len,
+                Ok(None) => break,
+                Err(e) => {
+                    let _ = tx.send(Record { n: n + 1, text: Err(anyhow::Error::from(e).context("Cannot read the input")) });
+                    break;
+                }
+            };
+            n += 1;
+            if let Some(stripper) = &mut ansi {
+                buf = stripper.clean(&buf);
+            }
+            let Some(record) = record(n, &buf, len, delimiter, max_bytes, force_text) else {
+                continue;
+            };
+            if tx.send(record).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+/// The record for `buf`, which held `len` bytes before being capped at
+/// `max_bytes`. None for blank records.
+pub fn record(n: usize, buf: &[u8], len: usize, delimiter: u8, max_bytes: usize, force_text: bool) -> Option<Record> {
+    if len > max_bytes {
+        let error = anyhow!("record is {} bytes, over the {}-byte limit (--max-record-bytes)", len, max_bytes);
+        return Some(Record { n, text: Err(error) });
+    }
+    let text = match text::decode(buf.to_vec(), &format!("record {}", n), force_text) {
+        Ok(text) => text,
+        Err(e) => return Some(Record { n, text: Err(e) }),
+    };
+    let text = match delimiter {
+        b'\n' => text.trim_end_matches('\r'),
+        _ => &text,
+    };
+    (!text.trim().is_empty()).then(|| Record { n, text: Ok(text.to_string()) })
+}
+
+/// Read up to the next `delimiter`, keeping at most `max_bytes` in `buf`.
+/// Returns the record's full length, or None at end of input.
+fn read_record(input: &mut impl BufRead, delimiter: u8, max_bytes: usize, buf: &mut Vec<u8>) -> io::Result<Option<usize>> {
+    buf.clear();
+    let mut len = 0;
+    loop {
+        let available = input.fill_buf()?;
+        if available.is_empty() {
+            return Ok((len > 0).then_some(len));
+        }
+        let (chunk, used, end) = match available.iter().position(|&b| b == delimiter) {
+            Some(i) => (&available[..i], i + 1, true),
+            None => (available, available.len(), false),
+        };
+        let room = max_bytes.saturating_sub(buf.len());
+        buf.extend_from_slice(&chunk[..chunk.len().min(room)]);
+        len += chunk.len();
+        input.consume(used);
+        if end {
+            return Ok(Some(len));
+        }
+    }
+}
+
+/// Speak records from `records` in arrival order until the sender is gone
+/// and everything queued has played. With `cut_off`, each record stops
+/// whatever is playing instead of queueing behind it.
+pub fn run(args: &Args, records: Receiver<Record>, cut_off: bool, start: Instant) -> Result<()> {
+    interrupt::install();
+    let records = match burst::Options::of(args) {
+        Some(options) => {
+            let filter = args.filter.clone();
+            burst::paced(records, options, move |text| filter.as_ref().is_none_or(|f| f.is_match(text)))
+        }
+        None => records,
+    };
+    let sink = Output::open()?;
+    let cache = crate::open_cache(args);
+    let gap = crate::gap(args, 0);
+    // A record queued behind another takes the gap before it as a place of its own
+    let max_in_flight = args.max_in_flight as usize * if gap.is_zero() { 1 } else { 2 };
+
+    let mut dedupe = args.dedupe.map(Dedupe::new);
+    // --dedupe-announce's summaries, spoken before the next record
+    let mut summaries: VecDeque<Repeated> = VecDeque::new();
+    let mut ended = false;
+    // Records playing or waiting to, in order, to report once played
+    let mut queued: VecDeque<Queued> = VecDeque::new();
+
+    let mut count = 0;
+    loop {
+        if interrupt::requested() {
+            crate::cut_off(&sink);
+            settle(&mut queued, Some("interrupted"), args.json);
+            return Ok(());
+        }
+        settle(&mut queued, None, args.json);
+        // Backpressure: leave records unread while the queue is full
+        if sink.len() >= max_in_flight {
+            std::thread::sleep(Duration::from_millis(10));
+            continue;
+        }
+        if let Some(dedupe) = &mut dedupe {
+            let closed = match ended {
+                true => dedupe.finish(),
+                false => dedupe.closed(Instant::now()),
+            };
+            for repeated in closed {
+                reporter::info(format_args!("{} [{}] repeated {} more time{}", reporter::mark(Mark::Arrow), repeated.n, repeated.repeats, if repeated.repeats == 1 { "" } else { "s" }));
+                if args.dedupe_announce {
+                    summaries.push_back(repeated);
+                }
+            }
+        }
+        // A summary is spoken as it is, never itself a repeat
+        let (record, repeats) = match summaries.pop_front() {
+            Some(repeated) => (Record { n: repeated.n, text: Ok(repeated.summary()) }, Some(repeated.repeats)),
+            None if ended => break,
+            None => match records.recv_timeout(Duration::from_millis(50)) {
+                Ok(record) => (record, None),
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => {
+                    ended = true;
+                    continue;
+                }
+            },
+        };
+        // Binary input ends the stream rather than failing record after record
+        if record.text.as_ref().is_err_and(|e| e.is::<text::Binary>()) {
+            sink.stop();
+            settle(&mut queued, Some("interrupted"), args.json);
+            return record.text.map(|_| ());
+        }
+        let record = Record { n: record.n, text: record.text.map(|text| crate::normalized(args, text)) };
+        if record.text.as_ref().is_ok_and(|text| text.is_empty()) {
+            continue;
+        }
+        if let (Ok(text), Some(filter), None) = (&record.text, &args.filter, repeats) {
+            if !filter.is_match(text) {
+                continue;
+            }
+        }
+        let n = record.n;
+        // How often this text has been skipped: 0 for one spoken
+        let repeats = match (&mut dedupe, &record.text, repeats) {
+            (Some(dedupe), Ok(text), None) => match dedupe.check(n, text, Instant::now()) {
+                Verdict::Speak => Some(0),
+                Verdict::Skip { repeats } => {
+                    if args.json {
+                        println!("{}", item(n, text, "skipped", None, Some(repeats)));
+                        crate::report::mark_printed();
+                    }
+                    continue;
+                }
+            },
+            (Some(_), _, repeats) => repeats.or(Some(0)),
+            (None, _, _) => None,
+        };
+        count += 1;
+
+        let text = record.text.as_deref().unwrap_or_default();
+        let buffer = record.text.as_ref().map_err(|e| anyhow!("{:#}", e)).and_then(|text| {
+            let (audio, _, _) = crate::open_audio(args, cache.as_ref(), text)?;
+            crate::spawn_net_reader(audio, start, Events::none(), None, crate::buffer_limit(args))
+        });
+        match buffer {
+            Ok(buffer) => {
+                reporter::info(format_args!("{} [{}] {}", reporter::mark(Mark::Arrow), n, text::excerpt(text)));
+                if cut_off && !sink.is_empty() {
+                    sink.stop();
+                    settle(&mut queued, Some("interrupted"), args.json);
+                }
+                // Nothing playing: prebuffer so the item doesn't start on an underrun
+                if sink.is_empty() || cut_off {
+                    crate::wait_for_prebuffer(&buffer);
+                } else if !gap.is_zero() {
+                    sink.silence(gap);
+                }
+                sink.speech(StreamSource::new(Arc::clone(&buffer)));
+                queued.push_back(Queued { n, text: text.to_string(), repeats, buffer });
+            }
+            Err(e) => {
+                reporter::error(format_args!("{} [{}] {:#}", reporter::mark(Mark::Failed), n, e));
+                if args.json {
+                    println!("{}", item(n, text, "error", Some(&format!("{:#}", e)), repeats));
+                    crate::report::mark_printed();
+                }
+            }
+        }
+    }
+
+    while !sink.is_empty() {
+        if interrupt::requested() {
+            crate::cut_off(&sink);
+            settle(&mut queued, Some("interrupted"), args.json);
+            return Ok(());
+        }
+        settle(&mut queued, None, args.json);
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    settle(&mut queued, Some("ok"), args.json);
+    reporter::timing_line(format_args!("{} {} records, {}ms", reporter::mark(Mark::Done), count, start.elapsed().as_millis()));
+    Ok(())
+}
+
+/// A record queued to play, its --json line waiting on how it went
+struct Queued {
+    n: usize,
+    text: String,
+    repeats: Option<usize>,
+    buffer: Arc<LockFreeBuffer>,
+}
+
+/// Report the records at the front of `queued` that have played out: a
+/// stream that broke off fails, as a single text's does. With `unplayed`,
+/// report them all, the ones not played out with that status.
+fn settle(queued: &mut VecDeque<Queued>, unplayed: Option<&str>, json: bool) {
+    while let Some(front) = queued.front() {
+        let played = front.buffer.ended().is_some() && front.buffer.is_empty();
+        if !played && unplayed.is_none() {
+            break;
+        }
+        let Some(record) = queued.pop_front() else { break };
+        let error = record.buffer.error();
+        if let Some(error) = &error {
+            reporter::error(format_args!("{} [{}] {}", reporter::mark(Mark::Failed), record.n, error));
+        }
+        if json {
+            let status = match (&error, played) {
+                (Some(_), _) => "error",
+                (None, true) => "ok",
+                (None, false) => unplayed.unwrap_or("ok"),
+            };
+            println!("{}", item(record.n, &record.text, status, error.as_deref(), record.repeats));
+            crate::report::mark_printed();
+        }
+    }
+}
+
+/// A record's --json line; with --dedupe, how often its text was
+/// repeated: 0 for one spoken, the skips so far for one skipped, and the
+/// skips it sums up for a --dedupe-announce summary.
+fn item(n: usize, text: &str, status: &str, error: Option<&str>, repeats: Option<usize>) -> Value {
+    let mut item = json!({"n": n, "text": text::excerpt(text), "status": status, "error": error});
+    if let Some(repeats) = repeats {
+        item["repeats"] = repeats.into();
+    }
+    item
+}
+
+/// Warn that the streaming modes' flags given do nothing elsewhere, where
+/// everything is spoken once anyway.
+pub fn warn_unless_streaming(args: &Args, streaming: bool) {
+    let given: Vec<&str> = [
+        (args.debounce_ms.is_some(), "--debounce-ms"),
+        (args.debounce_strategy.is_some(), "--debounce-strategy"),
+        (args.max_per_minute.is_some(), "--max-per-minute"),
+        (args.dedupe.is_some(), "--dedupe"),
+    ]
+    .into_iter()
+    .filter_map(|(given, flag)| given.then_some(flag))
+    .collect();
+    if !streaming && !given.is_empty() {
+        reporter::warning(format_args!("{} only apply to --follow, --watch-clipboard and the other streaming modes; ignored", given.join(" and ")));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn texts(input: &[u8], delimiter: u8, max_bytes: usize) -> Vec<(usize, String)> {
+        records(Cursor::new(input.to_vec()), delimiter, max_bytes, true, false)
+            .iter()
+            .map(|r| (r.n, r.text.unwrap_or_else(|e| format!("error: {}", e))))
+            .collect()
+    }
+
+    #[test]
+    fn splits_lines_and_drops_blank_ones() {
+        let got = texts(b"first\r\n\n  \nsecond\nno newline", b'\n', 100);
+        assert_eq!(got, [(1, "first".into()), (4, "second".into()), (5, "no newline".into())]);
+    }
+
+    #[test]
+    fn strips_colors_split_across_lines() {
+        let got = texts(b"\x1b[32mok\x1b[0m test\n\x1b[1\n\x1b[31mFAILED\x1b[0m\n", b'\n', 100);
+        assert_eq!(got, [(1, "ok test".into()), (3, "FAILED".into())]);
+    }
+
+    #[test]
+    fn nul_records_keep_newlines_and_enforce_the_cap() {
+        let got = texts(b"one\ntwo\0far too long\0last\0\xff\xfe", 0, 8);
+        assert_eq!(got[0], (1, "one\ntwo".into()));
+        assert!(got[1].1.starts_with("error: record is 12 bytes"));
+        assert_eq!(got[2], (3, "last".into()));
+        assert!(got[3].1.starts_with("error: record 4 looks binary (first invalid byte at offset 0)"));
+    }
+
+    #[test]
+    fn passes_read_errors_on_and_stops() {
+        struct Broken;
+        impl io::Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("device gone"))
+            }
+        }
+        let input = io::BufReader::new(io::Read::chain(&b"one\n"[..], Broken));
+        let got: Vec<_> = records(input, b'\n', 100, false, false).iter().map(|r| (r.n, r.text.map_err(|e| format!("{:#}", e)))).collect();
+        assert_eq!(got, [(1, Ok("one".to_string())), (2, Err("Cannot read the input: device gone".to_string()))]);
+    }
+}
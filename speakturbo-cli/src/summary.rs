@@ -0,0 +1,190 @@
+//! The end of a sequence or batch: how many items succeeded, were
+//! skipped or failed, with each failure's reason. --report writes the same
+//! as JSON for CI, and the failed items as `batch --jsonl` jobs to
+//! failed.jsonl beside it, to run just those again.
+
+use crate::exit::{self, Kind};
+use crate::report::{self, Report, Status};
+use crate::reporter::{self, Mark};
+use crate::sequence;
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::path::Path;
+
+pub struct Entry {
+    /// "row 3", "item 2" or the file the text came from
+    pub label: String,
+    pub report: Report,
+    /// The item as a `batch --jsonl` job, if it can be run again
+    pub job: Option<Value>,
+}
+
+/// A job for `text`, with its voice and output
+pub fn job(text: &str, voice: &str, output: Option<&str>) -> Value {
+    let mut job = json!({"text": text, "voice": voice});
+    if let Some(output) = output {
+        job["output"] = output.into();
+    }
+    job
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Counts {
+    succeeded: usize,
+    skipped: usize,
+    failed: usize,
+    /// Not reached after Ctrl+C or --fail-fast
+    pending: usize,
+}
+
+fn count(entries: &[Entry]) -> Counts {
+    let mut counts = Counts::default();
+    for entry in entries {
+        match entry.report.status {
+            Status::Ok => counts.succeeded += 1,
+            Status::Skipped => counts.skipped += 1,
+            Status::Error => counts.failed += 1,
+            Status::Pending | Status::Interrupted => counts.pending += 1,
+        }
+    }
+    counts
+}
+
+/// "3 succeeded, 1 skipped, 2 failed", and how many weren't reached
+fn totals(counts: &Counts) -> String {
+    let mut totals = format!("{} succeeded, {} skipped, {} failed", counts.succeeded, counts.skipped, counts.failed);
+    if counts.pending > 0 {
+        totals += &format!(", {} not reached", counts.pending);
+    }
+    totals
+}
+
+/// Each failure's label, padded to line up, and its reason
+fn failures(entries: &[Entry]) -> Vec<String> {
+    let failed: Vec<&Entry> = entries.iter().filter(|e| e.report.status == Status::Error).collect();
+    let width = failed.iter().map(|e| e.label.chars().count()).max().unwrap_or(0);
+    failed
+        .iter()
+        .map(|e| format!("{:<width$}  {}", e.label, e.report.error.as_deref().unwrap_or("failed"), width = width))
+        .collect()
+}
+
+fn to_json(entries: &[Entry], counts: &Counts) -> Value {
+    let items = entries.iter().map(|e| match e.report.to_json() {
+        Value::Object(mut fields) => {
+            fields.shift_insert(1, "item".to_string(), e.label.as_str().into());
+            Value::Object(fields)
+        }
+        other => other,
+    });
+    json!({
+        "schema": report::SCHEMA,
+        "succeeded": counts.succeeded,
+        "skipped": counts.skipped,
+        "failed": counts.failed,
+        "pending": counts.pending,
+        "items": items.collect::<Vec<_>>(),
+    })
+}
+
+/// Report on `entries` (the table, unless `quiet_table` as when --json
+/// has printed them), write --report's files, and fail if any item did:
+/// with the items' shared kind if none succeeded, else [`Kind::Partial`].
+pub fn finish(entries: &[Entry], report_path: Option<&Path>, quiet_table: bool) -> Result<()> {
+    let counts = count(entries);
+    if !quiet_table {
+        reporter::info(totals(&counts));
+        for line in failures(entries) {
+            reporter::error(format_args!("{} {}", reporter::mark(Mark::Failed), line));
+        }
+    }
+    if let Some(path) = report_path {
+        write(path, entries, &counts)?;
+    }
+    if counts.failed == 0 {
+        return Ok(());
+    }
+    let message = format!("{} of {} items failed", counts.failed, entries.len());
+    let kind = match counts.succeeded {
+        0 => sequence::shared(&entries.iter().filter_map(|e| e.report.kind).collect::<Vec<_>>()),
+        _ => Kind::Partial,
+    };
+    Err(exit::fail(kind, message))
+}
+
+/// The JSON summary at `path`, and the failed jobs in failed.jsonl beside
+/// it; an old failed.jsonl is removed when nothing failed.
+fn write(path: &Path, entries: &[Entry], counts: &Counts) -> Result<()> {
+    std::fs::write(path, format!("{}\n", to_json(entries, counts))).with_context(|| format!("Cannot write {}", path.display()))?;
+    let failed = path.with_file_name("failed.jsonl");
+    let jobs: Vec<String> = entries
+        .iter()
+        .filter(|e| e.report.status == Status::Error)
+        .filter_map(|e| e.job.as_ref().map(|job| format!("{}\n", job)))
+        .collect();
+    match jobs.is_empty() {
+        true if failed.exists() => std::fs::remove_file(&failed).with_context(|| format!("Cannot remove {}", failed.display())),
+        true => Ok(()),
+        false => std::fs::write(&failed, jobs.concat()).with_context(|| format!("Cannot write {}", failed.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn entry(label: &str, status: Status, error: Option<&str>) -> Entry {
+        let report = Report {
+            status,
+            voice: "alba".to_string(),
+            text_chars: 2,
+            elapsed: Duration::ZERO,
+            output: Some(format!("{}.wav", label)),
+            checksum: None,
+            source: None,
+            error: error.map(str::to_string),
+            kind: error.map(|_| Kind::DaemonError),
+            measured: speakturbo_client::SynthesisStats::default(),
+        };
+        Entry { label: label.to_string(), report, job: Some(job("Hi", "alba", Some(&format!("{}.wav", label)))) }
+    }
+
+    #[test]
+    fn sums_up_and_lists_the_failures() {
+        let entries = [
+            entry("item 1", Status::Ok, None),
+            entry("item 2", Status::Error, Some("HTTP 500")),
+            entry("item 3", Status::Skipped, None),
+            entry("item 10", Status::Error, Some("No text")),
+            entry("item 11", Status::Pending, None),
+        ];
+        let counts = count(&entries);
+        assert_eq!(totals(&counts), "1 succeeded, 1 skipped, 2 failed, 1 not reached");
+        assert_eq!(failures(&entries), ["item 2   HTTP 500", "item 10  No text"]);
+        let json = to_json(&entries, &counts).to_string();
+        assert!(json.starts_with("{\"schema\":1,\"succeeded\":1,\"skipped\":1,\"failed\":2,\"pending\":1,\"items\":[{\"schema\":1,\"item\":\"item 1\",\"status\":\"ok\""), "{}", json);
+    }
+
+    #[test]
+    fn tells_some_failed_from_all_failed() {
+        let kind = |entries: &[Entry]| finish(entries, None, true).err().map(|e| Kind::of(&e));
+        assert_eq!(kind(&[entry("a", Status::Ok, None), entry("b", Status::Error, Some("x"))]), Some(Kind::Partial));
+        assert_eq!(kind(&[entry("a", Status::Skipped, None), entry("b", Status::Error, Some("x"))]), Some(Kind::DaemonError));
+        assert_eq!(kind(&[entry("a", Status::Ok, None)]), None);
+    }
+
+    #[test]
+    fn writes_the_failed_jobs_beside_the_report() {
+        let dir = std::env::temp_dir().join(format!("speakturbo-summary-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("report.json");
+        let entries = [entry("a", Status::Ok, None), entry("b", Status::Error, Some("x"))];
+        write(&path, &entries, &count(&entries)).unwrap();
+        let failed = std::fs::read_to_string(dir.join("failed.jsonl")).unwrap();
+        assert_eq!(failed, "{\"text\":\"Hi\",\"voice\":\"alba\",\"output\":\"b.wav\"}\n");
+        write(&path, &entries[..1], &count(&entries[..1])).unwrap();
+        assert!(!dir.join("failed.jsonl").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
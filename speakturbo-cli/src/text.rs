@@ -0,0 +1,216 @@
+//! Helpers for the text being spoken.
+
+use crate::sentence;
+use crate::exit::{self, Kind};
+use anyhow::Result;
+use std::fmt;
+
+const EXCERPT_CHARS: usize = 80;
+// Invalid UTF-8 tolerated in text: at most one bad byte per this many
+const TEXT_BYTES_PER_INVALID: usize = 1000;
+
+/// The 1-based line of byte `offset` in `text`.
+pub fn line_at(text: &str, offset: usize) -> usize {
+    text.as_bytes()[..offset.min(text.len())].iter().filter(|&&b| b == b'\n').count() + 1
+}
+
+/// First ~80 characters of the text on one line.
+pub fn excerpt(text: &str) -> String {
+    let flat = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if flat.chars().count() <= EXCERPT_CHARS {
+        return flat;
+    }
+    let cut: String = flat.chars().take(EXCERPT_CHARS - 1).collect();
+    format!("{}…", cut.trim_end())
+}
+
+/// Input refused because it looks like binary data rather than text.
+#[derive(Debug)]
+pub struct Binary {
+    source: String,
+    /// First NUL or invalid UTF-8 byte
+    offset: usize,
+}
+
+impl fmt::Display for Binary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} looks binary (first invalid byte at offset {}); pass --force-text to send anyway",
+            self.source, self.offset
+        )
+    }
+}
+
+impl std::error::Error for Binary {}
+
+/// Decode input text. Anything with a NUL byte, or with more invalid UTF-8
+/// than a stray byte here and there, is refused as [`Binary`]. With
+/// `force_text` everything is accepted: bad bytes are replaced and control
+/// characters dropped.
+pub fn decode(bytes: Vec<u8>, source: &str, force_text: bool) -> Result<String> {
+    if force_text {
+        let text = String::from_utf8_lossy(&bytes);
+        return Ok(text.chars().filter(|&c| !c.is_control() || c == '\n' || c == '\t').collect());
+    }
+    let nul = bytes.iter().position(|&b| b == 0);
+    let bytes = match String::from_utf8(bytes) {
+        Ok(text) if nul.is_none() => return Ok(text),
+        Ok(text) => text.into_bytes(),
+        Err(e) => e.into_bytes(),
+    };
+
+    let (mut invalid, mut first_invalid, mut offset) = (0, None, 0);
+    for chunk in bytes.utf8_chunks() {
+        offset += chunk.valid().len();
+        if !chunk.invalid().is_empty() {
+            first_invalid.get_or_insert(offset);
+            invalid += chunk.invalid().len();
+        }
+        offset += chunk.invalid().len();
+    }
+    if nul.is_some() || invalid * TEXT_BYTES_PER_INVALID > bytes.len() {
+        let offset = nul.into_iter().chain(first_invalid).min().unwrap_or(0);
+        return Err(Binary { source: source.to_string(), offset }.into());
+    }
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// `n` in words, e.g. 42 -> "forty-two".
+pub fn cardinal(n: u64) -> String {
+    const ONES: [&str; 20] = [
+        "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten", "eleven", "twelve",
+        "thirteen", "fourteen", "fifteen", "sixteen", "seventeen", "eighteen", "nineteen",
+    ];
+    const TENS: [&str; 10] = ["", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety"];
+    const SCALES: [(u64, &str); 6] = [
+        (1_000_000_000_000_000_000, "quintillion"),
+        (1_000_000_000_000_000, "quadrillion"),
+        (1_000_000_000_000, "trillion"),
+        (1_000_000_000, "billion"),
+        (1_000_000, "million"),
+        (1_000, "thousand"),
+    ];
+    match n {
+        0..=19 => ONES[n as usize].to_string(),
+        20..=99 => match n % 10 {
+            0 => TENS[n as usize / 10].to_string(),
+            ones => format!("{}-{}", TENS[n as usize / 10], ONES[ones as usize]),
+        },
+        100..=999 => match n % 100 {
+            0 => format!("{} hundred", ONES[n as usize / 100]),
+            rest => format!("{} hundred {}", ONES[n as usize / 100], cardinal(rest)),
+        },
+        _ => {
+            let (scale, name) = SCALES.iter().find(|(scale, _)| n >= *scale).copied().unwrap_or((1, ""));
+            match n % scale {
+                0 => format!("{} {}", cardinal(n / scale), name),
+                rest => format!("{} {} {}", cardinal(n / scale), name, cardinal(rest)),
+            }
+        }
+    }
+}
+
+/// `text` if it's at most `max_chars` characters (--max-chars). Longer
+/// text is refused, or with a `truncate` suffix, cut after the last whole
+/// sentence that leaves room for the suffix, which is then appended.
+pub fn limit(text: String, max_chars: usize, truncate: Option<&str>) -> Result<String> {
+    let chars = text.chars().count();
+    if chars <= max_chars {
+        return Ok(text);
+    }
+    let Some(suffix) = truncate else {
+        let message = format!(
+            "The text is {} characters, over the --max-chars limit of {}; \
+             pass --truncate to speak only the start, or raise --max-chars",
+            chars, max_chars
+        );
+        return Err(exit::fail(Kind::Input, message));
+    };
+    let room = max_chars.saturating_sub(suffix.chars().count() + 1);
+    let kept = sentence::cut(&text, room).trim_end();
+    Ok(format!("{} {}", kept, suffix).trim().to_string())
+}
+
+/// `n` as an ordinal in words, e.g. 21 -> "twenty-first".
+pub fn ordinal(n: u64) -> String {
+    let words = cardinal(n);
+    let (head, last) = words.split_at(words.rfind([' ', '-']).map_or(0, |i| i + 1));
+    let last = match last {
+        "one" => "first".to_string(),
+        "two" => "second".to_string(),
+        "three" => "third".to_string(),
+        "five" => "fifth".to_string(),
+        "eight" => "eighth".to_string(),
+        "nine" => "ninth".to_string(),
+        "twelve" => "twelfth".to_string(),
+        tens if tens.ends_with('y') => format!("{}ieth", &tens[..tens.len() - 1]),
+        other => format!("{}th", other),
+    };
+    format!("{}{}", head, last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn excerpt_flattens_and_truncates() {
+        assert_eq!(excerpt("  hello\n\tworld  "), "hello world");
+        let long = "word ".repeat(40);
+        let e = excerpt(&long);
+        assert!(e.chars().count() <= EXCERPT_CHARS);
+        assert!(e.ends_with('…'));
+    }
+
+    #[test]
+    fn spells_out_numbers() {
+        assert_eq!(cardinal(3), "three");
+        assert_eq!(cardinal(42), "forty-two");
+        assert_eq!(cardinal(110), "one hundred ten");
+        assert_eq!(cardinal(2_000_019), "two million nineteen");
+        assert_eq!(cardinal(u64::MAX).split(' ').next(), Some("eighteen"));
+        assert_eq!(ordinal(1), "first");
+        assert_eq!(ordinal(12), "twelfth");
+        assert_eq!(ordinal(40), "fortieth");
+        assert_eq!(ordinal(101), "one hundred first");
+    }
+
+    #[test]
+    fn limits_length_in_characters() {
+        let text = "Één. Twee drie. Vier.".to_string();
+        assert_eq!(text.chars().count(), 21);
+        assert_eq!(limit(text.clone(), 21, None).unwrap(), text);
+        let error = limit(text.clone(), 20, None).unwrap_err().to_string();
+        assert!(error.starts_with("The text is 21 characters, over the --max-chars limit of 20;"), "{}", error);
+
+        // Whole sentences plus the suffix, never more than the limit
+        assert_eq!(limit(text.clone(), 20, Some("…cut")).unwrap(), "Één. Twee drie. …cut");
+        assert_eq!(limit(text.clone(), 19, Some("…cut")).unwrap(), "Één. …cut");
+        assert_eq!(limit(text.clone(), 9, Some("…cut")).unwrap(), "Één. …cut");
+        assert_eq!(limit(text.clone(), 8, Some("…cut")).unwrap(), "Één …cut");
+        assert_eq!(limit("one two three four".into(), 15, Some("…")).unwrap(), "one two …");
+    }
+
+    #[test]
+    fn reports_offset_of_invalid_utf8() {
+        let err = decode(b"caf\xc3\xa9 \xff!".to_vec(), "notes.txt", false).unwrap_err();
+        assert!(err.is::<Binary>());
+        assert_eq!(
+            err.to_string(),
+            "notes.txt looks binary (first invalid byte at offset 6); pass --force-text to send anyway"
+        );
+    }
+
+    #[test]
+    fn tolerates_a_stray_byte_but_not_binary() {
+        let mut text = "word ".repeat(400).into_bytes();
+        text[100] = 0xff;
+        assert_eq!(decode(text.clone(), "stdin", false).unwrap().chars().nth(100), Some('\u{fffd}'));
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        assert!(decode(png.clone(), "stdin", false).unwrap_err().to_string().contains("offset 0"));
+        text[200] = 0;
+        assert!(decode(text, "stdin", false).unwrap_err().to_string().contains("offset 100"));
+        assert_eq!(decode(png, "stdin", true).unwrap(), "\u{fffd}PNG\n\nIHDR");
+    }
+}
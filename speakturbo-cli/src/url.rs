@@ -0,0 +1,134 @@
+//! `--url`: fetch a web page and extract the text worth reading aloud.
+
+use crate::charset;
+use crate::html::{self, Document, Render, Selector};
+use anyhow::{bail, Context, Result};
+use std::io::Read;
+use std::time::Duration;
+
+const MAX_REDIRECTS: u32 = 5;
+// Pages larger than this are refused rather than half-read
+const MAX_PAGE_BYTES: u64 = 10 << 20;
+
+/// The page's article text; with `selector` the text of the matching
+/// elements, with `whole_page` all of it. Plain-text pages are returned as
+/// they are.
+pub fn read(url: &str, selector: Option<&Selector>, whole_page: bool) -> Result<String> {
+    let agent = ureq::AgentBuilder::new()
+        .redirects(MAX_REDIRECTS)
+        .timeout(Duration::from_secs(30))
+        .user_agent(&format!("speakturbo/{}", env!("CARGO_PKG_VERSION")))
+        .build();
+    let response = match agent.get(url).call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(code, _)) => bail!("{} answered HTTP {}", url, code),
+        Err(e) => return Err(e).with_context(|| format!("Cannot fetch {}", url)),
+    };
+
+    let content_type = response.header("content-type").unwrap_or("text/html").to_ascii_lowercase();
+    let mime = content_type.split(';').next().unwrap_or("").trim().to_string();
+    let declared = content_type
+        .split(';')
+        .find_map(|param| param.trim().strip_prefix("charset="))
+        .map(str::to_string);
+
+    let mut body = Vec::new();
+    response.into_reader().take(MAX_PAGE_BYTES + 1).read_to_end(&mut body)?;
+    if body.len() as u64 > MAX_PAGE_BYTES {
+        bail!("{} is larger than {} MB; refusing to read it", url, MAX_PAGE_BYTES >> 20);
+    }
+
+    let is_html = matches!(mime.as_str(), "text/html" | "application/xhtml+xml");
+    if !is_html && mime != "text/plain" {
+        bail!("{} is {}, not a web page", url, mime);
+    }
+    let label = declared.or_else(|| is_html.then(|| charset::sniff_meta(&body)).flatten());
+    let page = charset::decode(&body, label.as_deref())?;
+    if !is_html {
+        return Ok(page);
+    }
+
+    let text = match selector.is_some() || whole_page {
+        true => html::to_speech(&page, selector).with_context(|| format!("Nothing on {} matches the selector", url))?,
+        false => {
+            let doc = Document::parse(&page);
+            match doc.main_content() {
+                Some(main) => doc.text_of(&[main], Render { drop_boilerplate: true, ..Render::default() }),
+                None => doc.text(),
+            }
+        }
+    };
+    if text.trim().is_empty() {
+        bail!("No readable text found at {}", url);
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    /// A web server on a local port answering each path as `answer` says:
+    /// the status line, headers and body. Returns its base URL.
+    fn site(answer: fn(&str) -> (&'static str, &'static str, Vec<u8>)) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let mut request_line = String::new();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                reader.read_line(&mut request_line).unwrap();
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let path = request_line.split_whitespace().nth(1).unwrap_or("/").to_string();
+                let (status, headers, body) = answer(&path);
+                let head = format!("HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n", status, headers, body.len());
+                let _ = stream.write_all(head.as_bytes());
+                let _ = stream.write_all(&body);
+            }
+        });
+        url
+    }
+
+    fn answer(path: &str) -> (&'static str, &'static str, Vec<u8>) {
+        match path {
+            "/notes.txt" => ("200 OK", "Content-Type: text/plain; charset=utf-8\r\n", "Plain notes, read as they are.".into()),
+            "/article" => (
+                "200 OK",
+                "Content-Type: text/html\r\n",
+                b"<html><body><nav>Home | About</nav><article><h1>Title</h1><p>The story itself, told at some length, with commas.</p></article></body></html>".to_vec(),
+            ),
+            "/moved" => ("301 Moved Permanently", "Location: /notes.txt\r\n", Vec::new()),
+            "/loop" => ("302 Found", "Location: /loop\r\n", Vec::new()),
+            "/report.pdf" => ("200 OK", "Content-Type: application/pdf\r\n", b"%PDF-1.4".to_vec()),
+            "/empty" => ("200 OK", "Content-Type: text/html\r\n", b"<html><body><script>x()</script></body></html>".to_vec()),
+            _ => ("404 Not Found", "Content-Type: text/plain\r\n", b"not found".to_vec()),
+        }
+    }
+
+    #[test]
+    fn reads_pages_and_plain_text_following_redirects() {
+        let site = site(answer);
+        assert_eq!(read(&format!("{}/notes.txt", site), None, false).unwrap(), "Plain notes, read as they are.");
+        assert_eq!(read(&format!("{}/moved", site), None, false).unwrap(), "Plain notes, read as they are.");
+        let article = read(&format!("{}/article", site), None, false).unwrap();
+        assert!(article.contains("The story itself, told at some length"), "{}", article);
+        assert!(!article.contains("About"), "{}", article);
+    }
+
+    #[test]
+    fn refuses_what_isnt_a_readable_page() {
+        let site = site(answer);
+        let error = |path: &str| format!("{:#}", read(&format!("{}{}", site, path), None, false).unwrap_err());
+        assert_eq!(error("/report.pdf"), format!("{}/report.pdf is application/pdf, not a web page", site));
+        assert_eq!(error("/gone"), format!("{}/gone answered HTTP 404", site));
+        assert!(error("/loop").starts_with(&format!("Cannot fetch {}/loop", site)), "{}", error("/loop"));
+        assert!(error("/loop").contains("redirect"), "{}", error("/loop"));
+        assert_eq!(error("/empty"), format!("No readable text found at {}/empty", site));
+    }
+}
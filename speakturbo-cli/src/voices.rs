@@ -0,0 +1,150 @@
+//! Matching a --voice name against the daemon's voices, so that a typo
+//! gets a suggestion here rather than an error from the daemon.
+
+/// How far off a name may be and still be suggested (or, with
+/// --fuzzy-voice, taken)
+pub const MAX_DISTANCE: usize = 2;
+
+/// The voice in `known` that `name` means: the same name in any case, or
+/// the only one it's a prefix of. With `fuzzy`, also the only one closest
+/// to it if that's within [`MAX_DISTANCE`] edits.
+pub fn matching<'a>(name: &str, known: &'a [String], fuzzy: bool) -> Result<&'a str, String> {
+    let lower = name.to_lowercase();
+    if let Some(voice) = known.iter().find(|v| v.to_lowercase() == lower) {
+        return Ok(voice);
+    }
+    let prefixed: Vec<&str> = known.iter().filter(|v| v.to_lowercase().starts_with(&lower)).map(String::as_str).collect();
+    match prefixed.as_slice() {
+        [voice] if !lower.is_empty() => return Ok(voice),
+        [_, _, ..] if !lower.is_empty() => {
+            return Err(format!("voice '{}' is ambiguous: it could be {}", name, quoted(&prefixed)));
+        }
+        _ => {}
+    }
+
+    let distances: Vec<usize> = known.iter().map(|v| distance(&lower, &v.to_lowercase())).collect();
+    let best = distances.iter().copied().min().unwrap_or(usize::MAX);
+    let closest: Vec<&str> = match best <= MAX_DISTANCE {
+        true => known.iter().zip(&distances).filter(|(_, d)| **d == best).map(|(v, _)| v.as_str()).collect(),
+        false => Vec::new(),
+    };
+    match (closest.as_slice(), fuzzy) {
+        ([voice], true) => Ok(voice),
+        ([], _) => Err(format!("unknown voice '{}'; the daemon has {}", name, known.join(", "))),
+        ([voice], false) => Err(format!("unknown voice '{}'; did you mean '{}'? (--fuzzy-voice takes it)", name, voice)),
+        (_, _) => Err(format!("unknown voice '{}'; did you mean {}?", name, quoted(&closest))),
+    }
+}
+
+/// `'a', 'b' or 'c'`
+fn quoted(names: &[&str]) -> String {
+    let quoted: Vec<String> = names.iter().map(|n| format!("'{}'", n)).collect();
+    match quoted.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} or {}", rest.join(", "), last),
+        _ => quoted.concat(),
+    }
+}
+
+/// Edits to turn `a` into `b`: insertions, deletions, substitutions and
+/// swaps of neighbouring characters, each counting one.
+pub fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // rows[i][j]: the distance between a[..i] and b[..j]
+    let mut rows = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[i - 1][j] + 1).min(rows[i][j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn known() -> Vec<String> {
+        ["alba", "marius", "javert", "jean", "fantine", "cosette", "eponine", "azelma"].iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn measures_edit_distance() {
+        let cases = [
+            ("alba", "alba", 0),
+            ("abla", "alba", 1),
+            ("alb", "alba", 1),
+            ("albaa", "alba", 1),
+            ("elba", "alba", 1),
+            ("jaen", "jean", 1),
+            ("jvaert", "javert", 1),
+            ("", "jean", 4),
+            ("cosete", "cosette", 1),
+            ("fantin", "fantine", 1),
+            ("kitten", "sitting", 3),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(distance(a, b), expected, "{:?} {:?}", a, b);
+            assert_eq!(distance(b, a), expected, "{:?} {:?}", b, a);
+        }
+    }
+
+    #[test]
+    fn matches_names_strictly() {
+        let known = known();
+        let cases: &[(&str, Result<&str, &str>)] = &[
+            ("alba", Ok("alba")),
+            ("ALBA", Ok("alba")),
+            ("Javert", Ok("javert")),
+            ("mar", Ok("marius")),
+            ("Fan", Ok("fantine")),
+            ("e", Ok("eponine")),
+            ("j", Err("voice 'j' is ambiguous: it could be 'javert' or 'jean'")),
+            ("ja", Ok("javert")),
+            ("a", Err("voice 'a' is ambiguous: it could be 'alba' or 'azelma'")),
+            ("abla", Err("unknown voice 'abla'; did you mean 'alba'? (--fuzzy-voice takes it)")),
+            ("jaen", Err("unknown voice 'jaen'; did you mean 'jean'? (--fuzzy-voice takes it)")),
+            // Cyrillic а: a lookalike is still a typo
+            ("abl\u{430}", Err("unknown voice 'abl\u{430}'; did you mean 'alba'? (--fuzzy-voice takes it)")),
+            (
+                "zzz",
+                Err("unknown voice 'zzz'; the daemon has alba, marius, javert, jean, fantine, cosette, eponine, azelma"),
+            ),
+            ("", Err("unknown voice ''; the daemon has alba, marius, javert, jean, fantine, cosette, eponine, azelma")),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(matching(name, &known, false), expected.map_err(str::to_string), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn takes_the_closest_with_fuzzy() {
+        let known = known();
+        let cases: &[(&str, Result<&str, &str>)] = &[
+            ("abla", Ok("alba")),
+            ("Cosete", Ok("cosette")),
+            ("jvaert", Ok("javert")),
+            ("epnoine", Ok("eponine")),
+            ("mar", Ok("marius")),
+            // Too far, or as close to two voices
+            ("albatross", Err("unknown voice 'albatross'; the daemon has alba, marius, javert, jean, fantine, cosette, eponine, azelma")),
+            ("jan", Ok("jean")),
+            ("jert", Err("unknown voice 'jert'; did you mean 'javert' or 'jean'?")),
+            ("j", Err("voice 'j' is ambiguous: it could be 'javert' or 'jean'")),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(matching(name, &known, true), expected.map_err(str::to_string), "{:?}", name);
+        }
+    }
+}
@@ -0,0 +1,294 @@
+//! Peak-envelope waveform rendering (`--waveform`).
+//!
+//! The envelope is built as samples stream past, in O(width) memory: bins
+//! start at one sample each and are pairwise merged (doubling their span)
+//! whenever there are twice as many as output columns.
+
+use crate::png::Image;
+use crate::SAMPLE_RATE;
+use anyhow::{bail, Context, Result};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+const BACKGROUND: [u8; 3] = [0x11, 0x11, 0x11];
+const AXIS: [u8; 3] = [0x33, 0x33, 0x33];
+const WAVE: [u8; 3] = [0xf9, 0x73, 0x16];
+const TEXT: [u8; 3] = [0xee, 0xee, 0xee];
+
+pub type SharedEnvelope = Arc<Mutex<Envelope>>;
+
+pub struct Envelope {
+    columns: usize,
+    bins: Vec<(i16, i16)>,
+    per_bin: u64,
+    fill: u64,
+    current: (i16, i16),
+    total: u64,
+    odd_byte: Option<u8>,
+}
+
+impl Envelope {
+    pub fn new(columns: usize) -> Self {
+        Self {
+            columns: columns.max(1),
+            bins: Vec::with_capacity(columns * 2),
+            per_bin: 1,
+            fill: 0,
+            current: (0, 0),
+            total: 0,
+            odd_byte: None,
+        }
+    }
+
+    pub fn shared(columns: usize) -> SharedEnvelope {
+        Arc::new(Mutex::new(Self::new(columns)))
+    }
+
+    pub fn push(&mut self, sample: i16) {
+        if self.fill == 0 {
+            self.current = (sample, sample);
+        } else {
+            self.current = (self.current.0.min(sample), self.current.1.max(sample));
+        }
+        self.fill += 1;
+        self.total += 1;
+
+        if self.fill == self.per_bin {
+            self.bins.push(self.current);
+            self.fill = 0;
+            if self.bins.len() == self.columns * 2 {
+                self.bins = self
+                    .bins
+                    .chunks(2)
+                    .map(|p| (p[0].0.min(p[1].0), p[0].1.max(p[1].1)))
+                    .collect();
+                self.per_bin *= 2;
+            }
+        }
+    }
+
+    /// Feed little-endian PCM bytes, carrying an odd trailing byte over.
+    pub fn push_bytes(&mut self, mut bytes: &[u8]) {
+        if let Some(lo) = self.odd_byte.take() {
+            match bytes.split_first() {
+                Some((&hi, rest)) => {
+                    self.push(i16::from_le_bytes([lo, hi]));
+                    bytes = rest;
+                }
+                None => {
+                    self.odd_byte = Some(lo);
+                    return;
+                }
+            }
+        }
+        let mut pairs = bytes.chunks_exact(2);
+        for pair in &mut pairs {
+            self.push(i16::from_le_bytes([pair[0], pair[1]]));
+        }
+        self.odd_byte = pairs.remainder().first().copied();
+    }
+
+    pub fn duration_secs(&self) -> f64 {
+        self.total as f64 / SAMPLE_RATE as f64
+    }
+
+    /// Exactly `columns` (min, max) pairs spanning the whole clip.
+    fn column_peaks(&self) -> Vec<(i16, i16)> {
+        let mut bins = self.bins.clone();
+        if self.fill > 0 {
+            bins.push(self.current);
+        }
+        if bins.is_empty() {
+            return vec![(0, 0); self.columns];
+        }
+
+        let n = bins.len();
+        (0..self.columns)
+            .map(|col| {
+                let lo = col * n / self.columns;
+                let hi = ((col + 1) * n / self.columns).max(lo + 1).min(n);
+                bins[lo..hi]
+                    .iter()
+                    .fold((i16::MAX, i16::MIN), |acc, b| (acc.0.min(b.0), acc.1.max(b.1)))
+            })
+            .collect()
+    }
+
+    pub fn render(&self, height: u32) -> Image {
+        let width = self.columns as u32;
+        let mut image = Image::new(width, height, BACKGROUND);
+        let mid = (height - 1) / 2;
+        for x in 0..width {
+            image.set(x, mid, AXIS);
+        }
+
+        let to_y = |v: i16| -> u32 {
+            let norm = 1.0 - (v as f64 + 0.5) / 32768.0; // 0 (top) .. 2 (bottom)
+            ((norm * (height - 1) as f64 / 2.0).round() as u32).min(height - 1)
+        };
+        for (x, (min, max)) in self.column_peaks().into_iter().enumerate() {
+            for y in to_y(max)..=to_y(min) {
+                image.set(x as u32, y, WAVE);
+            }
+        }
+        image
+    }
+}
+
+/// Writer adapter that taps PCM bytes into an envelope on their way to disk.
+pub struct EnvelopeWriter<W> {
+    inner: W,
+    envelope: SharedEnvelope,
+}
+
+impl<W> EnvelopeWriter<W> {
+    pub fn new(inner: W, envelope: SharedEnvelope) -> Self {
+        Self { inner, envelope }
+    }
+}
+
+impl<W: Write> Write for EnvelopeWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.envelope.lock().unwrap().push_bytes(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Parse a --waveform-size value like "800x200".
+pub fn parse_size(s: &str) -> Result<(u32, u32), String> {
+    let (w, h) = s.split_once('x').ok_or("expected WIDTHxHEIGHT, e.g. 800x200")?;
+    let w: u32 = w.parse().map_err(|_| format!("invalid width: {}", w))?;
+    let h: u32 = h.parse().map_err(|_| format!("invalid height: {}", h))?;
+    if !(16..=8192).contains(&w) || !(16..=8192).contains(&h) {
+        return Err("width and height must be between 16 and 8192".into());
+    }
+    Ok((w, h))
+}
+
+/// Render the envelope with a "voice  12.3s" label and write it as PNG.
+pub fn write_png(envelope: &Envelope, height: u32, voice: &str, path: &Path) -> Result<()> {
+    if envelope.total == 0 {
+        bail!("No audio received; waveform not written");
+    }
+    let mut image = envelope.render(height);
+    let label = format!("{}  {:.1}s", voice.to_lowercase(), envelope.duration_secs());
+    draw_text(&mut image, 4, 4, &label);
+
+    let bytes = image.encode()?;
+    std::fs::File::create(path)
+        .and_then(|mut f| f.write_all(&bytes))
+        .with_context(|| format!("Cannot write {}", path.display()))
+}
+
+// 3x5 bitmap glyphs drawn at 2x, one row per entry, MSB on the left
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'a' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'b' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'c' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'd' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'e' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'f' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'g' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'h' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'i' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'j' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'k' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'l' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'm' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'n' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'o' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'p' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'r' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        's' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        't' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'u' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'v' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'w' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'x' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        _ => [0; 5],
+    }
+}
+
+fn draw_text(image: &mut Image, x0: u32, y0: u32, text: &str) {
+    const SCALE: u32 = 2;
+    let mut x = x0;
+    for c in text.chars() {
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..3 {
+                if bits & (0b100 >> col) != 0 {
+                    for dy in 0..SCALE {
+                        for dx in 0..SCALE {
+                            image.set(x + col * SCALE + dx, y0 + row as u32 * SCALE + dy, TEXT);
+                        }
+                    }
+                }
+            }
+        }
+        x += 4 * SCALE;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn png_has_requested_dimensions() {
+        let mut env = Envelope::new(320);
+        for i in 0..10_000 {
+            env.push(((i as f64 / 20.0).sin() * 20_000.0) as i16);
+        }
+        let png = env.render(90).encode().unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(u32::from_be_bytes(png[16..20].try_into().unwrap()), 320);
+        assert_eq!(u32::from_be_bytes(png[20..24].try_into().unwrap()), 90);
+    }
+
+    #[test]
+    fn silence_renders_a_flat_line() {
+        let mut env = Envelope::new(200);
+        env.push_bytes(&vec![0u8; 48_000]);
+        let image = env.render(60);
+        let mid = 29;
+        for x in 0..200 {
+            for y in 0..60 {
+                let expected = if y == mid { WAVE } else { BACKGROUND };
+                assert_eq!(image.get(x, y), expected, "pixel ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn envelope_memory_stays_bounded() {
+        let mut env = Envelope::new(100);
+        for i in 0..1_000_000u32 {
+            env.push((i % 1000) as i16);
+        }
+        assert!(env.bins.len() < 200);
+        assert_eq!(env.column_peaks().len(), 100);
+    }
+}